@@ -0,0 +1,213 @@
+//! run dora inside another program's tests
+//!
+//! [`DoraServer::builder`] starts a real dora instance with the same plugins as
+//! the `dora` binary, listening on an ephemeral port on localhost with an
+//! in-memory lease database. No config file, network namespace or root is
+//! needed: messages sent to [`DoraServer::v4_addr`] are answered with unicast
+//! back to the sender. The server stops when the [`DoraServer`] is dropped.
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use dora_bin::DoraServer;
+//!
+//! let srv = DoraServer::builder()
+//!     .config_str("networks:\n  127.0.0.0/8:\n    ranges:\n      - start: 127.0.0.100\n        end: 127.0.0.200\n        config:\n          lease_time:\n            default: 3600\n")
+//!     .build()
+//!     .await?;
+//! // send DHCP messages to `srv.v4_addr()`
+//! srv.shutdown().await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{
+    future::Future,
+    net::{Ipv6Addr, SocketAddr, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use config::{wire, DhcpConfig};
+use dora_core::{
+    config::cli::{self, Parser},
+    dhcproto::{v4, v6},
+    tokio::{self, sync::watch, task::JoinHandle},
+    Register, Server,
+};
+use ip_manager::{sqlite::SqliteDb, IpManager};
+use leases::Leases;
+use message_type::MsgType;
+use static_addr::StaticAddr;
+
+pub mod lint;
+pub mod replay;
+pub mod reservations;
+
+/// how often storage is checked for expired leases to publish as events
+const EXPIRY_POLL: Duration = Duration::from_secs(1);
+
+/// builds a [`DoraServer`]
+#[derive(Debug)]
+pub struct DoraServerBuilder {
+    config: Option<Result<DhcpConfig>>,
+    v4_addr: SocketAddr,
+    v6_addr: SocketAddr,
+    database_url: String,
+}
+
+impl Default for DoraServerBuilder {
+    fn default() -> Self {
+        Self {
+            config: None,
+            v4_addr: ([127, 0, 0, 1], 0).into(),
+            v6_addr: (Ipv6Addr::LOCALHOST, 0).into(),
+            database_url: "sqlite::memory:".to_owned(),
+        }
+    }
+}
+
+impl DoraServerBuilder {
+    /// serve `config`
+    pub fn config(mut self, config: DhcpConfig) -> Self {
+        self.config = Some(Ok(config));
+        self
+    }
+
+    /// serve a config built in code, see [`wire::Config`]
+    pub fn wire_config(mut self, config: wire::Config) -> Self {
+        self.config = Some(DhcpConfig::from_wire(config));
+        self
+    }
+
+    /// serve a JSON or YAML config
+    pub fn config_str(mut self, config: impl AsRef<str>) -> Self {
+        self.config = Some(DhcpConfig::parse_str(config));
+        self
+    }
+
+    /// address to listen for v4 on, defaults to an ephemeral port on 127.0.0.1
+    pub fn v4_addr(mut self, addr: impl Into<SocketAddr>) -> Self {
+        self.v4_addr = addr.into();
+        self
+    }
+
+    /// address to listen for v6 on, defaults to an ephemeral port on ::1. Only
+    /// used if the config has a v6 section
+    pub fn v6_addr(mut self, addr: impl Into<SocketAddr>) -> Self {
+        self.v6_addr = addr.into();
+        self
+    }
+
+    /// lease database to use instead of an in-memory one
+    pub fn database_url(mut self, url: impl Into<String>) -> Self {
+        self.database_url = url.into();
+        self
+    }
+
+    /// bind the sockets & start serving. Must be called from a tokio runtime
+    pub async fn build(self) -> Result<DoraServer> {
+        let dhcp_cfg = Arc::new(self.config.context("no config given")??);
+        let mut config = cli::Config::parse_from(["dora"]);
+        config.database_url = self.database_url.clone();
+
+        let v4_soc = UdpSocket::bind(self.v4_addr).context("failed to bind v4 socket")?;
+        let v4_addr = v4_soc.local_addr()?;
+        config.v4_addr = v4_addr;
+        let mut v4: Server<v4::Message> =
+            Server::new(config.clone(), dhcp_cfg.v4().interfaces().to_owned())?;
+        v4.with_socket(v4_soc);
+        MsgType::new(dhcp_cfg.clone())?.register(&mut v4);
+        StaticAddr::new(dhcp_cfg.clone())?.register(&mut v4);
+
+        let store = SqliteDb::new(&self.database_url).await?;
+        let ip_mgr = IpManager::new(store.clone())?;
+        let tasks = vec![tokio::spawn(ip_mgr.clone().watch_expiry(EXPIRY_POLL))];
+        Leases::new(dhcp_cfg.clone(), ip_mgr).register(&mut v4);
+
+        let (shutdown, _) = watch::channel(());
+        let mut servers = vec![tokio::spawn(v4.start(stopped(&shutdown)))];
+
+        let mut v6_addr = None;
+        if dhcp_cfg.has_v6() {
+            let v6_soc = UdpSocket::bind(self.v6_addr).context("failed to bind v6 socket")?;
+            config.v6_addr = v6_soc.local_addr()?;
+            v6_addr = Some(config.v6_addr);
+            let mut v6: Server<v6::Message> =
+                Server::new(config, dhcp_cfg.v6().interfaces().to_owned())?;
+            v6.with_socket(v6_soc);
+            MsgType::new(dhcp_cfg.clone())?.register(&mut v6);
+            servers.push(tokio::spawn(v6.start(stopped(&shutdown))));
+        }
+
+        Ok(DoraServer {
+            v4_addr,
+            v6_addr,
+            store,
+            shutdown: Some(shutdown),
+            servers,
+            tasks,
+        })
+    }
+}
+
+/// resolves when `shutdown` is dropped
+fn stopped(shutdown: &watch::Sender<()>) -> impl Future<Output = Result<()>> {
+    let mut rx = shutdown.subscribe();
+    async move {
+        while rx.changed().await.is_ok() {}
+        Ok(())
+    }
+}
+
+/// a running dora instance, stopped when dropped
+#[derive(Debug)]
+pub struct DoraServer {
+    v4_addr: SocketAddr,
+    v6_addr: Option<SocketAddr>,
+    store: SqliteDb,
+    shutdown: Option<watch::Sender<()>>,
+    servers: Vec<JoinHandle<Result<()>>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl DoraServer {
+    /// start building a server
+    pub fn builder() -> DoraServerBuilder {
+        DoraServerBuilder::default()
+    }
+
+    /// address v4 messages are served on
+    pub fn v4_addr(&self) -> SocketAddr {
+        self.v4_addr
+    }
+
+    /// address v6 messages are served on, if the config has a v6 section
+    pub fn v6_addr(&self) -> Option<SocketAddr> {
+        self.v6_addr
+    }
+
+    /// the lease database, to inspect leases from a test
+    pub fn store(&self) -> &SqliteDb {
+        &self.store
+    }
+
+    /// stop the server & wait for in-flight messages to finish
+    pub async fn shutdown(mut self) -> Result<()> {
+        self.shutdown.take();
+        for server in std::mem::take(&mut self.servers) {
+            server.await.map_err(|err| anyhow!(err))??;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DoraServer {
+    fn drop(&mut self) {
+        for server in &self.servers {
+            server.abort();
+        }
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
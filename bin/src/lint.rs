@@ -0,0 +1,23 @@
+//! `dora lint`, checks a config that loads for problems that only show up
+//! once clients are served
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use config::DhcpConfig;
+
+/// check the config at `path`, listing every problem found
+pub fn run(path: &Path) -> Result<()> {
+    let cfg = DhcpConfig::parse(path)?;
+    let overflows = cfg.v4().reply_overflows();
+    for overflow in &overflows {
+        println!("{overflow}");
+    }
+    if !overflows.is_empty() {
+        bail!(
+            "{} ranges have more options than fit in a reply to clients without a max message size (opt 57)",
+            overflows.len()
+        );
+    }
+    println!("{}: no problems found", path.display());
+    Ok(())
+}
@@ -1,23 +1,41 @@
 #![allow(clippy::cognitive_complexity)]
-use std::sync::Arc;
+use std::{
+    net::{SocketAddr, UdpSocket},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime},
+};
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 
-use config::DhcpConfig;
+use config::{relay, schedule::Schedule, v4::RunAs, v6::OtherConfigSink, wire, DhcpConfig};
 use dora_core::{
     config::{
-        cli::{self, Parser},
+        cli::{self, ConfigSource, DbSync, Parser},
         trace,
     },
     dhcproto::{v4, v6},
-    tokio::{self, runtime::Builder, signal, task::JoinHandle},
+    metrics, systemd,
+    tokio::{
+        self,
+        runtime::Builder,
+        signal,
+        sync::{oneshot, watch},
+        task::JoinHandle,
+    },
     tracing::*,
     Register, Server,
 };
-use external_api::{ExternalApi, Health};
-use ip_manager::{sqlite::SqliteDb, IpManager};
+use external_api::{ExternalApi, Health, Instance};
+use ip_manager::{
+    sqlite::{BatchLimits, Durability, SqliteDb},
+    FakeClock, IpManager, Retention, Storage,
+};
 use leases::Leases;
 use message_type::MsgType;
+use relay_agent::RelayAgent;
 use static_addr::StaticAddr;
 
 #[cfg(not(target_env = "musl"))]
@@ -27,53 +45,415 @@ use jemallocator::Jemalloc;
 #[global_allocator]
 static GLOBAL: Jemalloc = Jemalloc;
 
+/// how often storage is checked for expired leases to publish as events
+const EXPIRY_POLL: Duration = Duration::from_secs(5);
+/// file the v6 server DUID is stored in, next to the lease database
+const DUID_FILE: &str = "server_duid";
+/// exit status after shutting down for a changed `config_url`, EX_TEMPFAIL so
+/// a service manager restarting on failure starts dora again
+const RELOAD_EXIT: i32 = 75;
+
+/// why dora stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    Shutdown,
+    /// the config at `config_url` changed
+    Reload,
+}
+
 fn main() -> Result<()> {
     // parses from cli or environment var
     let config = cli::Config::parse();
-    let trace_config = trace::Config::parse(&config.dora_log)?;
+    let trace_config = trace::Config::parse(&config.dora_log, config.otlp())?;
     debug!(?config);
     debug!(?trace_config);
     if let Err(err) = dotenv::dotenv() {
         debug!(?err, ".env file not loaded");
     }
+    match &config.command {
+        Some(cli::Command::Reservations(cmd)) => {
+            return dora_bin::reservations::run(&config.config_path, cmd);
+        }
+        Some(cli::Command::Lint) => return dora_bin::lint::run(&config.config_path),
+        None => {}
+    }
+    if let Some(dir) = &config.replay {
+        return Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(replay(&config, dir));
+    }
+    // socket activation clears its env vars, so must also happen before the
+    // runtime starts any threads
+    let sockets = systemd::listen_sockets();
+    if !sockets.is_empty() {
+        info!(count = sockets.len(), "using sockets from systemd");
+    }
 
     let mut builder = Builder::new_multi_thread();
     // configure thread name & enable IO/time
     builder.thread_name(&config.thread_name).enable_all();
-    // default num threads will be num logical CPUs
-    // if we have a configured value here, set it
-    if let Some(num) = config.threads {
-        builder.worker_threads(num);
-    }
+    // dropping privileges needs the exact number of workers
+    builder.worker_threads(worker_threads(&config));
     // build the runtime
     let rt = builder.build()?;
 
-    rt.block_on(async move {
-        match dora_core::tokio::spawn(async move { start(config).await }).await {
-            Err(err) => error!(?err, "failed to start server"),
-            Ok(Err(err)) => error!(?err, "exited with error"),
-            Ok(_) => debug!("exiting..."),
+    let exit = rt.block_on(async move {
+        if let Err(err) = trace_config.start_otlp() {
+            error!(?err, "failed to start otlp export, continuing without it");
         }
+        let exit = match dora_core::tokio::spawn(async move { start(config, sockets).await }).await
+        {
+            Err(err) => {
+                error!(?err, "failed to start server");
+                Exit::Shutdown
+            }
+            Ok(Err(err)) => {
+                error!(?err, "exited with error");
+                Exit::Shutdown
+            }
+            Ok(Ok(exit)) => {
+                debug!(?exit, "exiting...");
+                exit
+            }
+        };
+        trace_config.shutdown();
+        exit
     });
+    if exit == Exit::Reload {
+        std::process::exit(RELOAD_EXIT);
+    }
 
     Ok(())
 }
 
-async fn start(config: cli::Config) -> Result<()> {
-    let database_url = config.database_url.clone();
-    info!(?database_url, "using database at path");
+async fn start(config: cli::Config, mut sockets: Vec<UdpSocket>) -> Result<Exit> {
     let dora_id = config.dora_id.clone();
     info!(?dora_id, "using id");
     // setting DORA_ID for other plugins
     std::env::set_var("DORA_ID", &dora_id);
     // start external api for healthchecks
-    let api = ExternalApi::new(config.external_api);
-    debug!("parsing DHCP config");
-    let dhcp_cfg = Arc::new(DhcpConfig::parse(&config.config_path)?);
+    let mut api = ExternalApi::new(config.external_api);
+    let oui = match &config.oui_db {
+        Some(path) => {
+            let oui = oui::OuiDb::load(path)?;
+            info!(vendors = oui.len(), "loaded OUI database");
+            Some(Arc::new(oui))
+        }
+        None => None,
+    };
+
+    if let Some(root) = &config.tftp_root {
+        #[cfg(feature = "tftp")]
+        {
+            let tftp = match take_socket(&mut sockets, config.tftp_addr) {
+                Some(soc) => tftp::Server::from_std(soc, root.clone())?,
+                None => tftp::Server::bind(config.tftp_addr, root.clone())
+                    .await
+                    .context("failed to bind tftp socket")?,
+            };
+            tokio::spawn(tftp.max_transfers(config.tftp_max_transfers).run());
+        }
+        #[cfg(not(feature = "tftp"))]
+        bail!(
+            "dora was built without the `tftp` feature, can't serve {}",
+            root.display()
+        );
+    }
+
+    // set once the config at `config_url` changes, shuts the servers down
+    let (reload, reloading) = watch::channel(false);
+    // `--user` takes precedence over `run_as` in the config
+    let cli_run_as = config.user.clone().map(|user| RunAs {
+        user,
+        group: config.group.clone(),
+    });
+    let workers = worker_threads(&config);
+    let mut run_as = None;
+    // handed to the `run_as` account
+    let mut owned = Vec::new();
+    let mut servers = Vec::new();
+    let mut stores = Vec::new();
+    match &config.instances {
+        Some(path) => {
+            for (name, instance) in config::instances::parse(path)? {
+                info!(?name, "starting instance");
+                let mut config = config.clone();
+                config.config_path = instance.config_path;
+                config.database_url = instance.database_url;
+                config.v4_port = instance.v4_port.or(config.v4_port);
+                config.v6_port = instance.v6_port.or(config.v6_port);
+                config.instance = Some(name.clone());
+                let dhcp_cfg = load_config(&config).await?;
+                if dhcp_cfg.relay().is_some() {
+                    bail!("instance {name} is in relay mode, only a single dora can relay");
+                }
+                match (&run_as, dhcp_cfg.v4().run_as()) {
+                    (Some(prev), Some(new)) if prev != new => {
+                        bail!("instance {name} sets a different `run_as`, dora runs as one account")
+                    }
+                    (None, Some(new)) => run_as = Some(new.clone()),
+                    _ => {}
+                }
+                owned.extend(database_files(&config.database_url));
+                let dhcp = build(config, dhcp_cfg, oui.clone(), &mut sockets).await?;
+                api = api.with_instance(name, dhcp.api);
+                servers.push((dhcp.v4, dhcp.v6));
+                stores.push(dhcp.store);
+            }
+        }
+        None => {
+            debug!("parsing DHCP config");
+            let dhcp_cfg = match &config.config_url {
+                Some(url) => fetch_config(&config, url, reload).await?,
+                None => load_config(&config).await?,
+            };
+            run_as = dhcp_cfg.v4().run_as().cloned();
+            if let Some(relay_cfg) = dhcp_cfg.relay() {
+                let run_as = cli_run_as.or(run_as);
+                return relay(config, relay_cfg.clone(), api, reloading, run_as).await;
+            }
+            owned.extend(database_files(&config.database_url));
+            let dhcp = build(config, dhcp_cfg, oui, &mut sockets).await?;
+            api = api.with_default(dhcp.api);
+            servers.push((dhcp.v4, dhcp.v6));
+            stores.push(dhcp.store);
+        }
+    }
+
+    debug!("changing health to good");
+    api.sender()
+        .send(Health::Good)
+        .await
+        .context("error occurred in changing health status to Good")?;
+
+    for soc in sockets {
+        warn!(addr = ?soc.local_addr(), "socket from systemd doesn't match a listen address, closing it");
+    }
+
+    // if dropped, will stop server
+    let api_guard = api.serve();
+    let mut tasks = Vec::new();
+    let mut bound = Vec::new();
+    for (mut v4, v6) in servers {
+        let (tx, rx) = oneshot::channel();
+        bound.push(rx);
+        v4.notify_bound(tx);
+        tasks.push(flatten(tokio::spawn(
+            v4.start(shutdown_signal(reloading.clone())),
+        )));
+        if let Some(mut v6) = v6 {
+            let (tx, rx) = oneshot::channel();
+            bound.push(rx);
+            v6.notify_bound(tx);
+            tasks.push(flatten(tokio::spawn(
+                v6.start(shutdown_signal(reloading.clone())),
+            )));
+        }
+    }
+    drop_privileges(cli_run_as.or(run_as), workers, bound, &owned).await?;
+    if let Err(err) = systemd::notify("READY=1") {
+        warn!(?err, "failed to notify systemd of readiness");
+    }
+    if let Some(interval) = systemd::watchdog_interval() {
+        tokio::spawn(systemd::watchdog(interval));
+    }
+    let res = futures::future::try_join_all(tasks).await;
+    if let Err(err) = systemd::notify("STOPPING=1") {
+        warn!(?err, "failed to notify systemd of shutdown");
+    }
+    // leases written behind are only on disk once flushed
+    for store in stores {
+        if let Err(err) = store.checkpoint().await {
+            error!(?err, "failed to flush lease database");
+        }
+    }
+    res?;
+    drop(api_guard);
+    Ok(exit(&reloading))
+}
+
+/// the DHCP config to run, from `config_path` or the newest version stored in
+/// the lease database
+async fn load_config(config: &cli::Config) -> Result<DhcpConfig> {
+    if config.config_source == ConfigSource::File {
+        return DhcpConfig::parse(&config.config_path);
+    }
+    let store = SqliteDb::new(&config.database_url).await?;
+    if let Some((version, text)) = store.latest_config().await? {
+        info!(version = version.version, note = ?version.note, "using stored config");
+        return DhcpConfig::parse_str(text)
+            .with_context(|| format!("failed to parse stored config version {}", version.version));
+    }
+    let text = std::fs::read_to_string(&config.config_path)
+        .with_context(|| format!("failed to find config at {}", config.config_path.display()))?;
+    let dhcp_cfg = DhcpConfig::parse_str(&text)?;
+    let version = store.push_config(&text, Some("initial config")).await?;
+    info!(
+        version = version.version,
+        path = %config.config_path.display(),
+        "no stored config, stored the config file as the first version"
+    );
+    Ok(dhcp_cfg)
+}
+
+/// fetch the DHCP config from `url` & watch it, setting `reload` once it holds
+/// a different valid config
+async fn fetch_config(
+    config: &cli::Config,
+    url: &str,
+    reload: watch::Sender<bool>,
+) -> Result<DhcpConfig> {
+    let source = config_source::connect(
+        url,
+        config.config_token.as_deref(),
+        config.config_poll_interval(),
+    )
+    .context("failed to set up config source")?;
+    let text = source
+        .fetch()
+        .await
+        .with_context(|| format!("failed to fetch config from {url}"))?;
+    let dhcp_cfg = DhcpConfig::parse_str(&text)?;
+    info!(?source, "fetched config");
+    let running = dhcp_cfg.clone();
+    tokio::spawn(async move {
+        config_source::changed(&*source, &text, &running).await;
+        info!("config changed, shutting down to restart with it");
+        reload.send_replace(true);
+    });
+    Ok(dhcp_cfg)
+}
+
+/// run as a relay agent forwarding clients to the servers in `relay_cfg`, no
+/// leases are handed out so there's no lease database
+async fn relay(
+    config: cli::Config,
+    relay_cfg: relay::Config,
+    api: ExternalApi,
+    reloading: watch::Receiver<bool>,
+    run_as: Option<RunAs>,
+) -> Result<Exit> {
+    info!(
+        v4_servers = ?relay_cfg.v4_servers(),
+        v6_servers = ?relay_cfg.v6_servers(),
+        "starting in relay mode"
+    );
+    api.sender()
+        .send(Health::Good)
+        .await
+        .context("error occurred in changing health status to Good")?;
+    let api_guard = api.serve();
+    let mut agent = RelayAgent::new(&config, relay_cfg);
+    let (tx, bound) = oneshot::channel();
+    agent.notify_bound(tx);
+    let agent = tokio::spawn(agent.run(shutdown_signal(reloading.clone())));
+    // relaying keeps no lease database
+    drop_privileges(run_as, worker_threads(&config), vec![bound], &[]).await?;
+    if let Err(err) = systemd::notify("READY=1") {
+        warn!(?err, "failed to notify systemd of readiness");
+    }
+    let res = flatten(agent).await;
+    if let Err(err) = systemd::notify("STOPPING=1") {
+        warn!(?err, "failed to notify systemd of shutdown");
+    }
+    drop(api_guard);
+    res.map(|()| exit(&reloading))
+}
+
+/// replay recorded packets against the config, see [`dora_bin::replay`]
+async fn replay(config: &cli::Config, dir: &Path) -> Result<()> {
+    let packets = dora_bin::replay::load(dir)?;
+    info!(packets = packets.len(), "replaying recorded packets");
+    let srv = dora_bin::DoraServer::builder()
+        .config(DhcpConfig::parse(&config.config_path)?)
+        .build()
+        .await?;
+    let replayed = dora_bin::replay::run(&srv, packets, config.replay_subnet).await?;
+    srv.shutdown().await?;
+    for packet in &replayed {
+        println!("{packet}");
+    }
+    let failed = replayed.iter().filter(|p| !p.failed.is_empty()).count();
+    if failed > 0 {
+        bail!(
+            "{failed} of {} replies didn't meet their expectations",
+            replayed.len()
+        );
+    }
+    Ok(())
+}
+
+/// the servers of one dora instance, and what the external api serves for it
+struct Dhcp {
+    v4: Server<v4::Message>,
+    v6: Option<Server<v6::Message>>,
+    api: Instance,
+    /// flushed on shutdown
+    store: SqliteDb,
+}
+
+/// set up the lease database, plugins & servers for `config`
+async fn build(
+    config: cli::Config,
+    mut dhcp_cfg: DhcpConfig,
+    oui: Option<Arc<oui::OuiDb>>,
+    sockets: &mut Vec<UdpSocket>,
+) -> Result<Dhcp> {
+    let database_url = config.database_url.clone();
+    info!(?database_url, "using database at path");
+    let mut api = Instance::new();
+    if let Some(path) = duid_path(&database_url, config.instance.as_deref()) {
+        dhcp_cfg.persist_server_id(path)?;
+    }
+    if dhcp_cfg.has_v6() {
+        api = api.with_server_id(dhcp_cfg.v6().server_id());
+        if let Some(sink) = dhcp_cfg.v6().ra_sync() {
+            // the RA daemon keeps its last copy, not worth failing to start over
+            if let Err(err) = sink.sync(&dhcp_cfg.v6().other_config()) {
+                error!(?err, "failed to share v6 other-config data");
+            }
+        }
+    }
+    let dhcp_cfg = Arc::new(dhcp_cfg);
+    // total addr space for metrics, each instance adds its own
+    metrics::TOTAL_AVAILABLE_ADDRS.add(
+        dhcp_cfg
+            .v4()
+            .networks()
+            .values()
+            .map(|net| net.total_addrs() as i64)
+            .sum(),
+    );
+    for overflow in dhcp_cfg.v4().reply_overflows() {
+        warn!(
+            subnet = %overflow.subnet,
+            range = %overflow.range,
+            dropped = ?overflow.dropped,
+            "range options don't fit in a 576 byte reply, they're dropped for clients without a max message size"
+        );
+    }
+    #[cfg(feature = "tftp")]
+    if let Some(root) = &config.tftp_root {
+        for file in tftp::missing(root, dhcp_cfg.v4().boot_files().iter().map(String::as_str)) {
+            warn!(
+                file,
+                root = %root.display(),
+                "configured boot file isn't in the tftp root"
+            );
+        }
+    }
+    let api = api.with_config(dhcp_cfg.clone());
     // start v4 server
     debug!("starting v4 server");
     let mut v4: Server<v4::Message> =
         Server::new(config.clone(), dhcp_cfg.v4().interfaces().to_owned())?;
+    if let Some(soc) = take_socket(sockets, config.v4_listen_addr()) {
+        v4.with_socket(soc);
+    }
+    for vlan in dhcp_cfg.v4().vlans().iter().filter(|vlan| vlan.unnumbered) {
+        v4.with_raw_interface(vlan.interface.index);
+    }
     debug!("starting v4 plugins");
 
     // perhaps with only one plugin chain we will just register deps here
@@ -82,7 +462,117 @@ async fn start(config: cli::Config) -> Result<()> {
     StaticAddr::new(dhcp_cfg.clone())?.register(&mut v4);
     // leases plugin
 
-    let ip_mgr = IpManager::new(SqliteDb::new(database_url).await?)?;
+    let durability = match config.db_sync {
+        DbSync::Interval => Durability::Wal,
+        DbSync::Commit => Durability::Full,
+        DbSync::Behind => Durability::WriteBehind,
+    };
+    let mut store = SqliteDb::with_durability(database_url, durability).await?;
+    if let Some((size, wait)) = config.db_batch() {
+        debug!(size, ?wait, "batching lease writes");
+        store = store.with_batching(BatchLimits { size, wait });
+    }
+    if let Some(faketime) = config.faketime {
+        warn!(
+            offset = ?faketime.offset,
+            speed = faketime.speed,
+            "lease expiry is measured against a fake clock"
+        );
+        let start = SystemTime::now() + faketime.offset;
+        store = store.with_clock(Arc::new(FakeClock::new(start, faketime.speed)));
+    }
+    let api = api.with_store(store.clone());
+    let mut ip_mgr = IpManager::new(store.clone())?;
+    ip_mgr
+        .recover()
+        .await
+        .context("failed to recover lease database")?;
+    let ping_source = dhcp_cfg.v4().ping_source();
+    if ping_source.address.is_some() || ping_source.interface.is_some() {
+        debug!(?ping_source, "sending ping checks from configured source");
+        ip_mgr = ip_mgr
+            .with_ping_source(ping_source)
+            .context("failed to bind ping check socket")?;
+    }
+    if let Some(oui) = oui {
+        ip_mgr = ip_mgr.with_oui(oui);
+    }
+    let api = api.with_events(ip_mgr.events());
+    tokio::spawn(ip_mgr.clone().watch_expiry(EXPIRY_POLL));
+    if let Some(interval) = config.db_sync_interval() {
+        tokio::spawn(ip_mgr.clone().watch_checkpoint(interval));
+    }
+    if config.scan_in_use {
+        tokio::spawn(
+            ip_mgr
+                .clone()
+                .scan_in_use(dhcp_cfg.clone(), config.scan_rate),
+        );
+    }
+    if let Some(interval) = config.pool_stats() {
+        tokio::spawn(ip_mgr.clone().watch_pool_stats(dhcp_cfg.clone(), interval));
+    }
+    if let Some(interval) = config.network_counts() {
+        let instance = config.instance.clone().unwrap_or_default();
+        ip_mgr
+            .restore_network_counts(&instance)
+            .await
+            .context("failed to restore network message counts")?;
+        tokio::spawn(ip_mgr.clone().watch_network_counts(instance, interval));
+    }
+    let (samples, rollups, audit) = config.retention();
+    tokio::spawn(ip_mgr.clone().watch_retention(Retention {
+        samples,
+        rollups,
+        audit,
+    }));
+    if let Some(window) = &config.db_maintenance_window {
+        let window = maintenance_window(window, config.db_maintenance_timezone.clone())?;
+        tokio::spawn(
+            ip_mgr
+                .clone()
+                .watch_maintenance(window, config.db_vacuum_pages),
+        );
+    }
+    if let Some(url) = &config.event_bus {
+        let publisher = event_bus::connect(url)
+            .await
+            .context("failed to connect to event bus")?;
+        // instances share a broker, the id tells their events apart
+        let server_id = match &config.instance {
+            Some(name) => format!("{}/{name}", config.dora_id),
+            None => config.dora_id.clone(),
+        };
+        tokio::spawn(event_bus::run(
+            publisher,
+            ip_mgr.subscribe(),
+            config.event_topic.clone(),
+            server_id,
+        ));
+    }
+    if let Some(url) = &config.ipam_url {
+        let ipam = ipam_sync::connect(url, config.ipam_token.as_deref())
+            .context("failed to set up ipam sync")?;
+        if config.ipam_export {
+            let (batch, delay) = config.ipam_export();
+            tokio::spawn(ipam_sync::run_export(
+                ipam.clone(),
+                store.clone(),
+                dhcp_cfg.clone(),
+                ipam_sync::ExportLimits {
+                    interval: config.ipam_sync_interval(),
+                    batch,
+                    delay,
+                },
+            ));
+        }
+        tokio::spawn(ipam_sync::run(
+            ipam,
+            ip_mgr.clone(),
+            dhcp_cfg.clone(),
+            config.ipam_sync_interval(),
+        ));
+    }
 
     Leases::new(dhcp_cfg.clone(), ip_mgr).register(&mut v4);
 
@@ -91,36 +581,117 @@ async fn start(config: cli::Config) -> Result<()> {
         info!("starting v6 server");
         let mut v6: Server<v6::Message> =
             Server::new(config.clone(), dhcp_cfg.v6().interfaces().to_owned())?;
+        if let Some(soc) = take_socket(sockets, config.v6_listen_addr()) {
+            v6.with_socket(soc);
+        }
         info!("starting v6 plugins");
         MsgType::new(dhcp_cfg.clone())?.register(&mut v6);
         Some(v6)
     } else {
         None
     };
+    Ok(Dhcp { v4, v6, api, store })
+}
 
-    debug!("changing health to good");
-    api.sender()
-        .send(Health::Good)
-        .await
-        .context("error occurred in changing health status to Good")?;
+/// the file of a sqlite `database_url`, `None` for in-memory databases
+fn database_path(database_url: &str) -> Option<&Path> {
+    let path = database_url
+        .trim_start_matches("sqlite://")
+        .trim_start_matches("sqlite:");
+    let path = path.split('?').next()?;
+    if path.is_empty() || path.contains(":memory:") {
+        return None;
+    }
+    Some(Path::new(path))
+}
 
-    // if dropped, will stop server
-    let api_guard = api.serve();
-    match v6 {
-        Some(v6) => {
-            tokio::try_join!(
-                flatten(tokio::spawn(v4.start(shutdown_signal()))),
-                flatten(tokio::spawn(v6.start(shutdown_signal()))),
-            )?;
-        }
-        None => {
-            tokio::spawn(v4.start(shutdown_signal())).await??;
-        }
+/// the lease database & its WAL files, they're created before dora switches
+/// to its `run_as` account
+fn database_files(database_url: &str) -> Vec<PathBuf> {
+    let path = match database_path(database_url) {
+        Some(path) => path.as_os_str(),
+        None => return Vec::new(),
     };
-    drop(api_guard);
+    ["", "-wal", "-shm"]
+        .iter()
+        .map(|suffix| {
+            let mut file = path.to_owned();
+            file.push(suffix);
+            PathBuf::from(file)
+        })
+        .collect()
+}
+
+/// the DUID file lives in the same directory as the lease database, in-memory
+/// databases don't persist it. Each instance gets its own file
+fn duid_path(database_url: &str, instance: Option<&str>) -> Option<PathBuf> {
+    let path = database_path(database_url)?;
+    Some(match instance {
+        Some(name) => path.with_file_name(format!("{DUID_FILE}.{name}")),
+        None => path.with_file_name(DUID_FILE),
+    })
+}
+
+/// the schedule of `--db-maintenance-window`, "HH:MM-HH:MM" in `timezone`
+fn maintenance_window(window: &str, timezone: Option<String>) -> Result<Schedule> {
+    let (start, end) = window
+        .split_once('-')
+        .context("db_maintenance_window must be HH:MM-HH:MM")?;
+    Schedule::try_from((
+        "db_maintenance_window".to_owned(),
+        wire::Schedule {
+            start: start.trim().to_owned(),
+            end: end.trim().to_owned(),
+            days: Vec::new(),
+            timezone,
+        },
+    ))
+}
+
+/// worker threads of the runtime, the # of logical CPU cores unless configured
+fn worker_threads(config: &cli::Config) -> usize {
+    config
+        .threads
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+}
+
+/// switch to the `run_as` account once every server has bound its sockets,
+/// giving it the `owned` files. `workers` is the runtime's number of worker
+/// threads, see [`dora_core::privs`]. A server that fails to bind drops its
+/// sender, its error is returned once it's joined
+async fn drop_privileges(
+    run_as: Option<RunAs>,
+    workers: usize,
+    bound: Vec<oneshot::Receiver<()>>,
+    owned: &[PathBuf],
+) -> Result<()> {
+    let run_as = match run_as {
+        Some(run_as) => run_as,
+        None => return Ok(()),
+    };
+    if futures::future::try_join_all(bound).await.is_err() {
+        return Ok(());
+    }
+    #[cfg(target_os = "linux")]
+    dora_core::privs::drop_privileges(&run_as.user, run_as.group.as_deref(), owned, workers)
+        .await?;
+    #[cfg(not(target_os = "linux"))]
+    warn!(user = ?run_as.user, "switching user is only supported on linux, ignoring");
     Ok(())
 }
 
+/// remove the socket systemd bound for `addr` from `sockets`. Sockets are
+/// matched on family & port, the service manager may have bound a more
+/// specific address than the one configured
+fn take_socket(sockets: &mut Vec<UdpSocket>, addr: SocketAddr) -> Option<UdpSocket> {
+    let pos = sockets.iter().position(|soc| {
+        soc.local_addr()
+            .map(|local| local.is_ipv4() == addr.is_ipv4() && local.port() == addr.port())
+            .unwrap_or(false)
+    })?;
+    Some(sockets.remove(pos))
+}
+
 async fn flatten<T>(handle: JoinHandle<Result<T, anyhow::Error>>) -> Result<T, anyhow::Error> {
     match handle.await {
         Ok(Ok(result)) => Ok(result),
@@ -129,6 +700,20 @@ async fn flatten<T>(handle: JoinHandle<Result<T, anyhow::Error>>) -> Result<T, a
     }
 }
 
-async fn shutdown_signal() -> Result<()> {
-    signal::ctrl_c().await.map_err(|err| anyhow!(err))
+/// completes on ctrl-c, or once `reloading` is set
+async fn shutdown_signal(mut reloading: watch::Receiver<bool>) -> Result<()> {
+    tokio::select! {
+        res = signal::ctrl_c() => res.map_err(|err| anyhow!(err)),
+        // errors once the sender is dropped, when there's no config to watch
+        Ok(()) = reloading.changed() => Ok(()),
+    }
+}
+
+/// why the servers watching `reloading` stopped
+fn exit(reloading: &watch::Receiver<bool>) -> Exit {
+    if *reloading.borrow() {
+        Exit::Reload
+    } else {
+        Exit::Shutdown
+    }
 }
@@ -0,0 +1,293 @@
+//! replay recorded client packets through the plugins, to check a config change
+//! before it's deployed
+//!
+//! Every `.pcap` & `.json` file in a directory is read in name order. Captures
+//! must be classic pcap with ethernet or raw IP framing, the BOOTREQUESTs sent to
+//! UDP port 67 are kept. JSON files are a list of hex encoded packets, each with
+//! optional expectations about the reply:
+//!
+//! ```json
+//! [
+//!     { "packet": "0101060...", "expect": { "msg_type": "offer", "yiaddr": "192.168.0.10" } },
+//!     { "packet": "0101060...", "expect": { "msg_type": "none" } }
+//! ]
+//! ```
+//!
+//! Packets are sent in order to a [`DoraServer`] with an in-memory lease
+//! database, so a REQUEST is answered with the lease its DISCOVER was offered.
+//! Packets that weren't relayed are answered as if they arrived on the loopback
+//! interface, unless a subnet is given to select their network with option 118.
+use std::{
+    fmt,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use dora_core::{
+    dhcproto::{
+        v4::{DhcpOption, Message, Opcode, OptionCode},
+        Decodable, Decoder, Encodable,
+    },
+    tokio::{net::UdpSocket, time},
+};
+use serde::Deserialize;
+
+use crate::DoraServer;
+
+/// how long to wait for a reply before the packet is considered dropped
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+/// BOOTP/DHCP server port
+const SERVER_PORT: u16 = 67;
+
+/// a recorded packet & what the reply to it should look like
+#[derive(Debug, Clone)]
+pub struct Packet {
+    /// file the packet was read from & its position in the file
+    pub source: String,
+    pub msg: Message,
+    pub expect: Option<Expect>,
+}
+
+/// expectations about the reply to a packet, unset fields aren't checked
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expect {
+    /// message type of the reply, ex. `offer` or `nak`. `none` if the packet
+    /// should be dropped
+    pub msg_type: Option<String>,
+    /// address in the reply
+    pub yiaddr: Option<Ipv4Addr>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonPacket {
+    packet: String,
+    expect: Option<Expect>,
+}
+
+/// a packet & the reply dora sent for it
+#[derive(Debug, Clone)]
+pub struct Replayed {
+    pub packet: Packet,
+    pub reply: Option<Message>,
+    /// expectations the reply didn't meet
+    pub failed: Vec<String>,
+}
+
+impl fmt::Display for Replayed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let req = &self.packet.msg;
+        write!(
+            f,
+            "{}: {} from {}",
+            self.packet.source,
+            msg_type(Some(req)),
+            hex::encode(req.chaddr())
+        )?;
+        match &self.reply {
+            Some(reply) if reply.yiaddr().is_unspecified() => {
+                write!(f, " -> {}", msg_type(Some(reply)))?
+            }
+            Some(reply) => write!(f, " -> {} {}", msg_type(Some(reply)), reply.yiaddr())?,
+            None => write!(f, " -> no reply")?,
+        }
+        for failed in &self.failed {
+            write!(f, "\n    expected {failed}")?;
+        }
+        Ok(())
+    }
+}
+
+/// the packets of every capture & JSON file in `dir`, in file name order
+pub fn load(dir: &Path) -> Result<Vec<Packet>> {
+    let mut paths = std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()?;
+    paths.sort();
+    let mut packets = Vec::new();
+    for path in paths {
+        let name = path.display().to_string();
+        let read = || std::fs::read(&path).with_context(|| format!("failed to read {name}"));
+        let file = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pcap") => pcap_payloads(&read()?)
+                .with_context(|| format!("invalid capture {name}"))?
+                .into_iter()
+                .map(|buf| (buf, None))
+                .collect(),
+            Some("json") => serde_json::from_slice::<Vec<JsonPacket>>(&read()?)
+                .with_context(|| format!("invalid packet file {name}"))?
+                .into_iter()
+                .enumerate()
+                .map(|(i, packet)| {
+                    let buf = hex::decode(&packet.packet)
+                        .with_context(|| format!("{name}#{i}: packet isn't hex"))?;
+                    Ok((buf, packet.expect))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => continue,
+        };
+        for (i, (buf, expect)) in file.into_iter().enumerate() {
+            let msg = Message::decode(&mut Decoder::new(&buf))
+                .with_context(|| format!("{name}#{i}: invalid DHCP message"))?;
+            // captures also hold the replies of the server that was recorded
+            if msg.opcode() != Opcode::BootRequest {
+                continue;
+            }
+            packets.push(Packet {
+                source: format!("{name}#{i}"),
+                msg,
+                expect,
+            });
+        }
+    }
+    Ok(packets)
+}
+
+/// send `packets` to `srv` one at a time, waiting for each reply. `subnet`, if
+/// set, is added as the subnet selection option of packets that weren't relayed
+pub async fn run(
+    srv: &DoraServer,
+    packets: Vec<Packet>,
+    subnet: Option<Ipv4Addr>,
+) -> Result<Vec<Replayed>> {
+    let soc = UdpSocket::bind("127.0.0.1:0").await?;
+    let mut replayed = Vec::with_capacity(packets.len());
+    for packet in packets {
+        let mut msg = packet.msg.clone();
+        if let Some(subnet) = subnet {
+            if msg.giaddr().is_unspecified()
+                && msg.opts().get(OptionCode::SubnetSelection).is_none()
+            {
+                msg.opts_mut().insert(DhcpOption::SubnetSelection(subnet));
+            }
+        }
+        soc.send_to(&msg.to_vec()?, srv.v4_addr()).await?;
+        let reply = recv_reply(&soc, msg.xid()).await?;
+        let failed = packet
+            .expect
+            .as_ref()
+            .map(|expect| check(expect, reply.as_ref()))
+            .unwrap_or_default();
+        replayed.push(Replayed {
+            packet,
+            reply,
+            failed,
+        });
+    }
+    Ok(replayed)
+}
+
+/// the reply with `xid`, `None` if there isn't one before the timeout
+async fn recv_reply(soc: &UdpSocket, xid: u32) -> Result<Option<Message>> {
+    let mut buf = vec![0; 1500];
+    let deadline = time::Instant::now() + REPLY_TIMEOUT;
+    loop {
+        let len = match time::timeout_at(deadline, soc.recv(&mut buf)).await {
+            Ok(len) => len?,
+            Err(_) => return Ok(None),
+        };
+        // a late reply to an earlier packet is skipped
+        match Message::decode(&mut Decoder::new(&buf[..len])) {
+            Ok(reply) if reply.xid() == xid => return Ok(Some(reply)),
+            _ => continue,
+        }
+    }
+}
+
+/// the expectations `reply` doesn't meet
+fn check(expect: &Expect, reply: Option<&Message>) -> Vec<String> {
+    let mut failed = Vec::new();
+    if let Some(want) = &expect.msg_type {
+        let got = msg_type(reply);
+        if !want.eq_ignore_ascii_case(&got) {
+            failed.push(format!("msg_type {want}, got {got}"));
+        }
+    }
+    if let Some(want) = expect.yiaddr {
+        match reply.map(|reply| reply.yiaddr()) {
+            Some(got) if got == want => {}
+            Some(got) => failed.push(format!("yiaddr {want}, got {got}")),
+            None => failed.push(format!("yiaddr {want}, got no reply")),
+        }
+    }
+    failed
+}
+
+/// lowercase message type, `none` if there is no message
+fn msg_type(msg: Option<&Message>) -> String {
+    match msg {
+        Some(msg) => match msg.opts().msg_type() {
+            Some(ty) => format!("{ty:?}").to_lowercase(),
+            None => "bootp".to_owned(),
+        },
+        None => "none".to_owned(),
+    }
+}
+
+/// UDP payloads sent to port 67 in a classic pcap file
+fn pcap_payloads(buf: &[u8]) -> Result<Vec<Vec<u8>>> {
+    if buf.len() < 24 {
+        bail!("missing pcap header");
+    }
+    let magic = [buf[0], buf[1], buf[2], buf[3]];
+    // micro & nanosecond timestamps, the file's byte order decides which way round
+    let big_endian = match magic {
+        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => true,
+        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => false,
+        _ => bail!("not a pcap file, pcapng isn't supported"),
+    };
+    let u32_at = |at: usize| -> Option<u32> {
+        let bytes = <[u8; 4]>::try_from(buf.get(at..at + 4)?).ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let link_type = u32_at(20).context("missing link type")?;
+    let mut payloads = Vec::new();
+    let mut at = 24;
+    while at < buf.len() {
+        let len = u32_at(at + 8).context("truncated record header")? as usize;
+        let frame = buf
+            .get(at + 16..at + 16 + len)
+            .context("truncated record")?;
+        at += 16 + len;
+        let ip = match link_type {
+            // ethernet, skipping any 802.1Q tag
+            1 => match frame.get(12..14) {
+                Some([0x81, 0x00]) if matches!(frame.get(16..18), Some([0x08, 0x00])) => {
+                    &frame[18..]
+                }
+                Some([0x08, 0x00]) => &frame[14..],
+                _ => continue,
+            },
+            // raw IP
+            101 => frame,
+            _ => bail!("unsupported link type {link_type}"),
+        };
+        if let Some(payload) = udp_payload(ip) {
+            payloads.push(payload.to_vec());
+        }
+    }
+    Ok(payloads)
+}
+
+/// payload of an IPv4 UDP datagram sent to the server port
+fn udp_payload(ip: &[u8]) -> Option<&[u8]> {
+    let header = usize::from(ip.first()? & 0x0f) * 4;
+    if ip.first()? >> 4 != 4 || *ip.get(9)? != 17 {
+        return None;
+    }
+    let udp = ip.get(header..)?;
+    let dst_port = u16::from_be_bytes([*udp.get(2)?, *udp.get(3)?]);
+    if dst_port != SERVER_PORT {
+        return None;
+    }
+    let len = usize::from(u16::from_be_bytes([*udp.get(4)?, *udp.get(5)?]));
+    udp.get(8..len)
+}
@@ -0,0 +1,268 @@
+//! `dora reservations`, bulk import & export of v4 MAC reservations as CSV
+//!
+//! Rows are `mac,ip[,lease_time]`, an optional header row starting with `mac`
+//! & lines starting with `#` are skipped. Each row is reserved in the network
+//! whose subnet contains its address, with the default lease time of the
+//! network's first range when `lease_time` (seconds) is empty. Every row is
+//! checked before anything is changed: a bad MAC or address, an address
+//! outside of the configured networks, a MAC or address listed twice, or one
+//! that's already reserved for another client fails the whole import. Rows
+//! matching an existing reservation are left as they are.
+use std::{collections::HashMap, fmt::Write as _, net::Ipv4Addr, path::Path};
+
+use anyhow::{bail, Context, Result};
+use config::{
+    wire::{
+        self,
+        v4::{Condition, NetworkConfig, Options, ReservedIp},
+        MinMax,
+    },
+    DhcpConfig,
+};
+use dora_core::{config::cli, pnet::util::MacAddr};
+use ipnet::Ipv4Net;
+
+/// a reservation read from or written to CSV
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Row {
+    pub mac: MacAddr,
+    pub ip: Ipv4Addr,
+    /// seconds, the network's default if unset
+    pub lease_time: Option<u32>,
+}
+
+/// what an import changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Imported {
+    /// reservations added, by network
+    pub added: Vec<(Ipv4Net, Row)>,
+    /// rows that were already reserved
+    pub unchanged: usize,
+}
+
+/// run a `dora reservations` command on the config at `path`
+pub fn run(path: &Path, cmd: &cli::Reservations) -> Result<()> {
+    let input = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to find config at {}", path.display()))?;
+    // written back in the format it was read in
+    let (mut cfg, json) = match serde_json::from_str::<wire::Config>(&input) {
+        Ok(cfg) => (cfg, true),
+        Err(_) => (serde_yaml::from_str::<wire::Config>(&input)?, false),
+    };
+    match cmd {
+        cli::Reservations::Export { csv } => {
+            let rows = export(&cfg);
+            match csv {
+                Some(csv) => std::fs::write(csv, rows)
+                    .with_context(|| format!("failed to write {}", csv.display()))?,
+                None => print!("{rows}"),
+            }
+        }
+        cli::Reservations::Import { csv, dry_run } => {
+            let rows = std::fs::read_to_string(csv)
+                .with_context(|| format!("failed to read {}", csv.display()))?;
+            let imported = import(&mut cfg, &rows)?;
+            for (net, row) in &imported.added {
+                println!("+ {net} {} {}", row.mac, row.ip);
+            }
+            println!(
+                "{} reservations added, {} already reserved",
+                imported.added.len(),
+                imported.unchanged
+            );
+            if *dry_run || imported.added.is_empty() {
+                return Ok(());
+            }
+            let output = if json {
+                serde_json::to_string_pretty(&cfg)?
+            } else {
+                serde_yaml::to_string(&cfg)?
+            };
+            std::fs::write(path, output)
+                .with_context(|| format!("failed to write config to {}", path.display()))?;
+            println!("wrote {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// parse `csv`, errors name the line they're on
+pub fn parse(csv: &str) -> Result<Vec<(usize, Row)>> {
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in csv.lines().enumerate() {
+        let (n, line) = (i + 1, line.trim());
+        if line.is_empty() || line.starts_with('#') || (i == 0 && line.starts_with("mac")) {
+            continue;
+        }
+        match parse_row(line) {
+            Ok(row) => rows.push((n, row)),
+            Err(err) => errors.push(format!("line {n}: {err}")),
+        }
+    }
+    if !errors.is_empty() {
+        bail!("{} invalid rows\n{}", errors.len(), errors.join("\n"));
+    }
+    Ok(rows)
+}
+
+fn parse_row(line: &str) -> Result<Row> {
+    let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+    let (mac, ip, lease_time) = match fields[..] {
+        [mac, ip] => (mac, ip, ""),
+        [mac, ip, lease_time] => (mac, ip, lease_time),
+        _ => bail!(
+            "expected `mac,ip[,lease_time]`, found {} fields",
+            fields.len()
+        ),
+    };
+    Ok(Row {
+        mac: mac
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{mac:?} isn't a MAC address"))?,
+        ip: ip
+            .parse()
+            .with_context(|| format!("{ip:?} isn't an IPv4 address"))?,
+        lease_time: match lease_time {
+            "" => None,
+            secs => Some(
+                secs.parse()
+                    .with_context(|| format!("{secs:?} isn't a lease time in seconds"))?,
+            ),
+        },
+    })
+}
+
+/// add the reservations in `csv` to `cfg`, nothing is changed if any row is
+/// invalid. The new config is checked like one loaded by the server
+pub fn import(cfg: &mut wire::Config, csv: &str) -> Result<Imported> {
+    let rows = parse(csv)?;
+    let mut imported = Imported::default();
+    let mut errors = Vec::new();
+    let mut seen_macs = HashMap::new();
+    let mut seen_ips = HashMap::new();
+    let mut added = Vec::new();
+    for (n, row) in rows {
+        let found = cfg
+            .networks
+            .iter()
+            .find(|(subnet, _)| subnet.contains(&row.ip));
+        let Some((subnet, net)) = found else {
+            errors.push(format!("line {n}: {} isn't in a configured network", row.ip));
+            continue;
+        };
+        if subnet.prefix_len() < 31 && (row.ip == subnet.network() || row.ip == subnet.broadcast())
+        {
+            errors.push(format!(
+                "line {n}: {} can't be reserved in {subnet}",
+                row.ip
+            ));
+            continue;
+        }
+        if let Some(first) = seen_ips.insert(row.ip, n) {
+            errors.push(format!("line {n}: {} is also on line {first}", row.ip));
+            continue;
+        }
+        if let Some(first) = seen_macs.insert((*subnet, row.mac), n) {
+            errors.push(format!(
+                "line {n}: {} is also on line {first}, for the same network",
+                row.mac
+            ));
+            continue;
+        }
+        match net.reservations.iter().find(|res| res.ip == row.ip) {
+            Some(res) if res.condition == Condition::Mac(row.mac) => {
+                imported.unchanged += 1;
+                continue;
+            }
+            Some(_) => {
+                errors.push(format!(
+                    "line {n}: {} is already reserved for another client",
+                    row.ip
+                ));
+                continue;
+            }
+            None => {}
+        }
+        if let Some(res) = net
+            .reservations
+            .iter()
+            .find(|res| res.condition == Condition::Mac(row.mac))
+        {
+            errors.push(format!(
+                "line {n}: {} already has {} reserved in {subnet}",
+                row.mac, res.ip
+            ));
+            continue;
+        }
+        let lease_time = match row.lease_time.or_else(|| {
+            net.ranges
+                .first()
+                .map(|range| range.config.lease_time.default)
+        }) {
+            Some(lease_time) => lease_time,
+            None => {
+                errors.push(format!(
+                    "line {n}: {subnet} has no range to take a lease time from, add one to the row"
+                ));
+                continue;
+            }
+        };
+        added.push((*subnet, row, lease_time));
+    }
+    if !errors.is_empty() {
+        bail!("{} invalid rows\n{}", errors.len(), errors.join("\n"));
+    }
+    let mut new = cfg.clone();
+    for (subnet, row, lease_time) in &added {
+        let net = new
+            .networks
+            .get_mut(subnet)
+            .expect("network was found above");
+        net.reservations.push(ReservedIp {
+            ip: row.ip,
+            options: Options::default(),
+            condition: Condition::Mac(row.mac),
+            config: NetworkConfig {
+                lease_time: MinMax {
+                    default: *lease_time,
+                    min: None,
+                    max: None,
+                },
+            },
+            class: None,
+            boot: Default::default(),
+        });
+    }
+    DhcpConfig::from_wire(new.clone()).context("the config with the imported reservations")?;
+    *cfg = new;
+    imported.added = added.into_iter().map(|(net, row, _)| (net, row)).collect();
+    Ok(imported)
+}
+
+/// the MAC reservations of every network as CSV with a header row, by address
+pub fn export(cfg: &wire::Config) -> String {
+    let mut rows = cfg
+        .networks
+        .values()
+        .flat_map(|net| &net.reservations)
+        .filter_map(|res| match res.condition {
+            Condition::Mac(mac) => Some(Row {
+                mac,
+                ip: res.ip,
+                lease_time: Some(res.config.lease_time.default),
+            }),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    rows.sort_by_key(|row| row.ip);
+    let mut out = String::from("mac,ip,lease_time\n");
+    for row in rows {
+        let lease_time = row
+            .lease_time
+            .map(|secs| secs.to_string())
+            .unwrap_or_default();
+        let _ = writeln!(out, "{},{},{lease_time}", row.mac, row.ip);
+    }
+    out
+}
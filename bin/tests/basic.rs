@@ -41,6 +41,14 @@ fn test_basic_dhcpv4_unicast() -> Result<()> {
     let resp = client.run(MsgType::Discover(msg_args))?;
 
     assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Offer);
+    // RFC 6842, the client id is returned
+    let client_id = Some(v4::DhcpOption::ClientIdentifier(
+        utils::get_mac().bytes().to_vec(),
+    ));
+    assert_eq!(
+        resp.opts().get(v4::OptionCode::ClientIdentifier),
+        client_id.as_ref()
+    );
 
     // create REQUEST & send
     let msg_args = RequestBuilder::default()
@@ -49,6 +57,10 @@ fn test_basic_dhcpv4_unicast() -> Result<()> {
         .build()?;
     let resp = client.run(MsgType::Request(msg_args))?;
     assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Ack);
+    assert_eq!(
+        resp.opts().get(v4::OptionCode::ClientIdentifier),
+        client_id.as_ref()
+    );
 
     // renew
     let msg_args = RequestBuilder::default()
@@ -285,6 +297,11 @@ fn request_nak() -> Result<()> {
     let resp = client.run(MsgType::Request(msg_args))?;
 
     assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Nak);
+    // RFC 6842, the client id is returned in the NAK
+    assert_eq!(
+        resp.opts().get(v4::OptionCode::ClientIdentifier),
+        Some(&v4::DhcpOption::ClientIdentifier(chaddr.bytes().to_vec()))
+    );
     Ok(())
 }
 
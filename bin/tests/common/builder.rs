@@ -113,6 +113,9 @@ pub struct Request {
     /// supply a mac address for DHCPv4 [default: first avail mac]
     #[builder(default = "utils::get_mac()")]
     chaddr: MacAddress,
+    /// client's current address, set when RENEWING/REBINDING [default: None]
+    #[builder(setter(strip_option), default)]
+    ciaddr: Option<Ipv4Addr>,
     /// address for client [default: None]
     #[builder(setter(strip_option), default)]
     yiaddr: Option<Ipv4Addr>,
@@ -136,7 +139,7 @@ pub struct Request {
 impl Request {
     pub fn build(&self) -> v4::Message {
         let mut msg = v4::Message::new(
-            Ipv4Addr::UNSPECIFIED,
+            self.ciaddr.unwrap_or(Ipv4Addr::UNSPECIFIED),
             self.yiaddr.unwrap_or(Ipv4Addr::UNSPECIFIED),
             Ipv4Addr::UNSPECIFIED,
             self.giaddr.unwrap_or(Ipv4Addr::UNSPECIFIED),
@@ -0,0 +1,369 @@
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use dora_bin::{replay, reservations, DoraServer};
+use dora_core::{
+    dhcproto::{
+        decoder::{Decodable, Decoder},
+        encoder::Encodable,
+        v4,
+    },
+    tokio::{net::UdpSocket, runtime::Runtime, time},
+};
+use ip_manager::{IpManager, Storage};
+
+static CONFIG: &str = "
+networks:
+    127.0.0.0/8:
+        ranges:
+            -
+                start: 127.0.0.100
+                end: 127.0.0.200
+                config:
+                    lease_time:
+                        default: 3600
+";
+
+async fn send(soc: &UdpSocket, srv: &DoraServer, msg: &v4::Message) -> Result<v4::Message> {
+    soc.send_to(&msg.to_vec()?, srv.v4_addr()).await?;
+    let mut buf = vec![0; 1500];
+    let len = time::timeout(Duration::from_secs(3), soc.recv(&mut buf))
+        .await
+        .context("no response from server")??;
+    Ok(v4::Message::decode(&mut Decoder::new(&buf[..len]))?)
+}
+
+fn msg(msg_type: v4::MessageType, opts: &[v4::DhcpOption]) -> v4::Message {
+    client_msg(&[0, 1, 2, 3, 4, 5], msg_type, opts)
+}
+
+fn client_msg(chaddr: &[u8], msg_type: v4::MessageType, opts: &[v4::DhcpOption]) -> v4::Message {
+    let mut msg = v4::Message::new(
+        Ipv4Addr::UNSPECIFIED,
+        Ipv4Addr::UNSPECIFIED,
+        Ipv4Addr::UNSPECIFIED,
+        Ipv4Addr::UNSPECIFIED,
+        chaddr,
+    );
+    msg.opts_mut().insert(v4::DhcpOption::MessageType(msg_type));
+    for opt in opts {
+        msg.opts_mut().insert(opt.clone());
+    }
+    msg
+}
+
+static HINTS_CONFIG: &str = "
+networks:
+    127.0.0.0/8:
+        ranges:
+            -
+                start: 127.0.0.100
+                end: 127.0.0.149
+                config:
+                    lease_time:
+                        default: 3600
+            -
+                start: 127.0.0.150
+                end: 127.0.0.200
+                honor_hints: never
+                config:
+                    lease_time:
+                        default: 3600
+";
+
+/// no root or network namespace needed
+#[test]
+fn embedded_dora() -> Result<()> {
+    Runtime::new()?.block_on(async {
+        let srv = DoraServer::builder().config_str(CONFIG).build().await?;
+        let soc = UdpSocket::bind("127.0.0.1:0").await?;
+
+        let offer = send(&soc, &srv, &msg(v4::MessageType::Discover, &[])).await?;
+        assert_eq!(offer.opts().msg_type(), Some(v4::MessageType::Offer));
+        let ip = offer.yiaddr();
+        assert!((Ipv4Addr::new(127, 0, 0, 100)..=Ipv4Addr::new(127, 0, 0, 200)).contains(&ip));
+
+        let sident = match offer.opts().get(v4::OptionCode::ServerIdentifier) {
+            Some(v4::DhcpOption::ServerIdentifier(id)) => *id,
+            _ => panic!("no server identifier"),
+        };
+        let req = msg(
+            v4::MessageType::Request,
+            &[
+                v4::DhcpOption::ServerIdentifier(sident),
+                v4::DhcpOption::RequestedIpAddress(ip),
+            ],
+        );
+        let ack = send(&soc, &srv, &req).await?;
+        assert_eq!(ack.opts().msg_type(), Some(v4::MessageType::Ack));
+        assert_eq!(ack.yiaddr(), ip);
+        assert!(srv.store().get(IpAddr::V4(ip)).await?.is_some());
+
+        srv.shutdown().await
+    })
+}
+
+#[test]
+fn network_counts() -> Result<()> {
+    Runtime::new()?.block_on(async {
+        let srv = DoraServer::builder().config_str(CONFIG).build().await?;
+        let soc = UdpSocket::bind("127.0.0.1:0").await?;
+        let chaddr = [0, 1, 2, 3, 4, 7];
+        let offer = send(
+            &soc,
+            &srv,
+            &client_msg(&chaddr, v4::MessageType::Discover, &[]),
+        )
+        .await?;
+        let Some(v4::DhcpOption::ServerIdentifier(sident)) =
+            offer.opts().get(v4::OptionCode::ServerIdentifier).cloned()
+        else {
+            panic!("no server identifier");
+        };
+        let req = client_msg(
+            &chaddr,
+            v4::MessageType::Request,
+            &[
+                v4::DhcpOption::ServerIdentifier(sident),
+                v4::DhcpOption::RequestedIpAddress(offer.yiaddr()),
+            ],
+        );
+        let ack = send(&soc, &srv, &req).await?;
+        assert_eq!(ack.opts().msg_type(), Some(v4::MessageType::Ack));
+
+        // other tests ACK on the same network, counters are shared by the process
+        let ip_mgr = IpManager::new(srv.store().clone())?;
+        ip_mgr.save_network_counts("").await?;
+        let counts = srv.store().network_counts().await?;
+        let acks = counts
+            .iter()
+            .find(|count| count.network == Ipv4Addr::new(127, 0, 0, 0) && count.msg_type == "ack")
+            .context("no ACK count saved")?;
+        assert!(acks.count >= 1);
+
+        srv.shutdown().await
+    })
+}
+
+static TEMPLATE_CONFIG: &str = "
+networks:
+    127.0.0.0/8:
+        hostname_template: \"dhcp-{ip_dashed}.guest.example.com\"
+        ranges:
+            -
+                start: 127.0.0.100
+                end: 127.0.0.200
+                config:
+                    lease_time:
+                        default: 3600
+";
+
+#[test]
+fn generated_hostname() -> Result<()> {
+    Runtime::new()?.block_on(async {
+        let srv = DoraServer::builder()
+            .config_str(TEMPLATE_CONFIG)
+            .build()
+            .await?;
+        let soc = UdpSocket::bind("127.0.0.1:0").await?;
+        let offer = send(&soc, &srv, &msg(v4::MessageType::Discover, &[])).await?;
+        let Some(v4::DhcpOption::ServerIdentifier(sident)) =
+            offer.opts().get(v4::OptionCode::ServerIdentifier).cloned()
+        else {
+            panic!("no server identifier");
+        };
+        let ip = offer.yiaddr();
+        let req = msg(
+            v4::MessageType::Request,
+            &[
+                v4::DhcpOption::ServerIdentifier(sident),
+                v4::DhcpOption::RequestedIpAddress(ip),
+            ],
+        );
+        let ack = send(&soc, &srv, &req).await?;
+        assert_eq!(ack.opts().msg_type(), Some(v4::MessageType::Ack));
+
+        let [_, _, _, last] = ip.octets();
+        let hostname = format!("dhcp-127-0-0-{last}.guest.example.com");
+        let entry = srv
+            .store()
+            .get_hostname(&hostname)
+            .await?
+            .context("generated hostname not registered")?;
+        assert_eq!(entry.ip, IpAddr::V4(ip));
+
+        srv.shutdown().await
+    })
+}
+
+/// DISCOVER from `chaddr` asking for `ip`, returns the offered address
+async fn discover(
+    soc: &UdpSocket,
+    srv: &DoraServer,
+    chaddr: &[u8],
+    ip: Ipv4Addr,
+) -> Result<Ipv4Addr> {
+    let req = client_msg(
+        chaddr,
+        v4::MessageType::Discover,
+        &[v4::DhcpOption::RequestedIpAddress(ip)],
+    );
+    let offer = send(soc, srv, &req).await?;
+    assert_eq!(offer.opts().msg_type(), Some(v4::MessageType::Offer));
+    Ok(offer.yiaddr())
+}
+
+#[test]
+fn discover_hints() -> Result<()> {
+    Runtime::new()?.block_on(async {
+        let srv = DoraServer::builder()
+            .config_str(HINTS_CONFIG)
+            .build()
+            .await?;
+        let soc = UdpSocket::bind("127.0.0.1:0").await?;
+        let wanted = Ipv4Addr::new(127, 0, 0, 120);
+        let (a, b) = ([0, 1, 2, 3, 4, 5], [0, 1, 2, 3, 4, 6]);
+
+        // a free address is offered, and offered again to the same client
+        assert_eq!(discover(&soc, &srv, &a, wanted).await?, wanted);
+        assert_eq!(discover(&soc, &srv, &a, wanted).await?, wanted);
+        // another client asking for it gets a different address
+        assert_ne!(discover(&soc, &srv, &b, wanted).await?, wanted);
+
+        // hints in a `never` range are ignored
+        let ignored = Ipv4Addr::new(127, 0, 0, 180);
+        let ip = discover(&soc, &srv, &b, ignored).await?;
+        assert!((Ipv4Addr::new(127, 0, 0, 100)..=Ipv4Addr::new(127, 0, 0, 149)).contains(&ip));
+
+        srv.shutdown().await
+    })
+}
+
+#[test]
+fn offer_dedup() -> Result<()> {
+    Runtime::new()?.block_on(async {
+        let srv = DoraServer::builder()
+            .config_str(format!("offer_dedup_window: 30\n{CONFIG}"))
+            .build()
+            .await?;
+        let soc = UdpSocket::bind("127.0.0.1:0").await?;
+        let (first, second) = (Ipv4Addr::new(127, 0, 0, 110), Ipv4Addr::new(127, 0, 0, 111));
+        let mut req = client_msg(
+            &[0, 1, 2, 3, 4, 8],
+            v4::MessageType::Discover,
+            &[v4::DhcpOption::RequestedIpAddress(first)],
+        );
+        assert_eq!(send(&soc, &srv, &req).await?.yiaddr(), first);
+
+        // a retransmission is sent the same OFFER, whatever it asks for
+        req.opts_mut()
+            .insert(v4::DhcpOption::RequestedIpAddress(second));
+        let offer = send(&soc, &srv, &req).await?;
+        assert_eq!(offer.opts().msg_type(), Some(v4::MessageType::Offer));
+        assert_eq!(offer.yiaddr(), first);
+        // a new DISCOVER is allocated again
+        req.set_xid(req.xid().wrapping_add(1));
+        assert_eq!(send(&soc, &srv, &req).await?.yiaddr(), second);
+
+        srv.shutdown().await
+    })
+}
+
+/// a classic pcap file (raw IP link type) with one UDP datagram to port 67
+fn pcap(payload: &[u8]) -> Vec<u8> {
+    let (udp_len, ip_len) = (8 + payload.len(), 28 + payload.len());
+    // magic, version 2.4, zone, sigfigs, snaplen, link type
+    let mut buf = vec![0xa1, 0xb2, 0xc3, 0xd4, 0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0];
+    buf.extend(65_535_u32.to_be_bytes());
+    buf.extend(101_u32.to_be_bytes());
+    // record: timestamp, captured & original length
+    buf.extend([0; 8]);
+    buf.extend((ip_len as u32).to_be_bytes());
+    buf.extend((ip_len as u32).to_be_bytes());
+    buf.extend([0x45, 0]);
+    buf.extend((ip_len as u16).to_be_bytes());
+    buf.extend([0, 0, 0, 0, 64, 17, 0, 0, 0, 0, 0, 0, 255, 255, 255, 255]);
+    buf.extend(68_u16.to_be_bytes());
+    buf.extend(67_u16.to_be_bytes());
+    buf.extend((udp_len as u16).to_be_bytes());
+    buf.extend([0, 0]);
+    buf.extend(payload);
+    buf
+}
+
+#[test]
+fn replay_expectations() -> Result<()> {
+    Runtime::new()?.block_on(async {
+        let dir = std::env::temp_dir().join(format!("dora-replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let discover = msg(v4::MessageType::Discover, &[]).to_vec()?;
+        let packet = hex::encode(&discover);
+        std::fs::write(
+            dir.join("1.json"),
+            format!(
+                r#"[{{"packet": "{packet}", "expect": {{"msg_type": "offer"}}}},
+                {{"packet": "{packet}", "expect": {{"msg_type": "ack"}}}}]"#
+            ),
+        )?;
+        std::fs::write(dir.join("2.pcap"), pcap(&discover))?;
+        let packets = replay::load(&dir);
+        std::fs::remove_dir_all(&dir)?;
+        let packets = packets?;
+        assert_eq!(packets.len(), 3);
+
+        let srv = DoraServer::builder().config_str(CONFIG).build().await?;
+        let replayed = replay::run(&srv, packets, None).await?;
+        assert!(replayed.iter().all(|packet| packet.reply.is_some()));
+        assert!(replayed[0].failed.is_empty());
+        assert_eq!(replayed[1].failed, ["msg_type ack, got offer"]);
+        // captures have no expectations
+        assert!(replayed[2].failed.is_empty());
+
+        srv.shutdown().await
+    })
+}
+
+#[test]
+fn reservations_csv() -> Result<()> {
+    let mut cfg: config::wire::Config = serde_yaml::from_str(CONFIG)?;
+    let csv =
+        "mac,ip,lease_time\n00:11:22:33:44:55,127.0.0.10,\naa:bb:cc:dd:ee:ff,127.0.0.11,600\n";
+    let imported = reservations::import(&mut cfg, csv)?;
+    assert_eq!(imported.added.len(), 2);
+    let exported = reservations::export(&cfg);
+    // the lease time of the network's range is used when a row has none
+    assert_eq!(
+        exported,
+        "mac,ip,lease_time\n00:11:22:33:44:55,127.0.0.10,3600\naa:bb:cc:dd:ee:ff,127.0.0.11,600\n"
+    );
+    // exported rows import as they are
+    let imported = reservations::import(&mut cfg, &exported)?;
+    assert!(imported.added.is_empty());
+    assert_eq!(imported.unchanged, 2);
+
+    // every invalid row is reported & none are added
+    let invalid = "00:11:22:33:44:55,127.0.0.12\n\
+                   00:11:22:33:44:66,10.0.0.1\n\
+                   00:11:22:33:44:77,127.0.0.11\n\
+                   00:11:22:33:44:88,127.0.0.13\n\
+                   00:11:22:33:44:99,127.0.0.13\n";
+    let err = reservations::import(&mut cfg, invalid)
+        .unwrap_err()
+        .to_string();
+    assert!(err.starts_with("4 invalid rows"), "{err}");
+    assert!(err.contains("line 1: 00:11:22:33:44:55 already has 127.0.0.10 reserved"));
+    assert!(err.contains("line 2: 10.0.0.1 isn't in a configured network"));
+    assert!(err.contains("line 3: 127.0.0.11 is already reserved for another client"));
+    assert!(err.contains("line 5: 127.0.0.13 is also on line 4"));
+    assert_eq!(reservations::export(&cfg), exported);
+
+    let err = reservations::parse("00:11:22:33:44:55\nnot-a-mac,127.0.0.10\n")
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("line 1: expected `mac,ip[,lease_time]`"));
+    assert!(err.contains("line 2: \"not-a-mac\" isn't a MAC address"));
+    Ok(())
+}
@@ -0,0 +1,197 @@
+//! RFC 2131 client state machine scenarios, run with `cargo test --features e2e`.
+//! Each test starts dora in a network namespace with `rfc2131.yaml`, a 5
+//! address pool with 3s leases, and asserts on what's sent back on the wire
+#![cfg(feature = "e2e")]
+// shared with basic.rs, not every helper is used here
+#[allow(dead_code)]
+mod common;
+
+use std::{net::Ipv4Addr, thread, time::Duration};
+
+use anyhow::Result;
+use common::{builder::*, client::Client, env::DhcpServerEnv};
+use dora_core::{dhcproto::v4, tracing};
+use mac_address::MacAddress;
+use tracing_test::traced_test;
+
+use crate::common::utils;
+
+const GIADDR: [u8; 4] = [192, 168, 2, 1];
+
+fn start() -> DhcpServerEnv {
+    DhcpServerEnv::start(
+        "rfc2131.yaml",
+        "rfc2131.db",
+        "dora_test",
+        "dhcpcli",
+        "dhcpsrv",
+        "192.168.2.1",
+    )
+}
+
+fn client() -> Result<Client<v4::Message>> {
+    // use veth_cli created in start()
+    let settings = ClientSettingsBuilder::default()
+        .iface_name("dhcpcli")
+        .target("192.168.2.1".parse::<std::net::IpAddr>().unwrap())
+        .port(9900_u16)
+        .build()?;
+    Ok(Client::<v4::Message>::new(settings))
+}
+
+/// SELECTING -> REQUESTING -> BOUND, returns the leased address & server id
+fn bind(client: &mut Client<v4::Message>, chaddr: MacAddress) -> Result<(Ipv4Addr, Ipv4Addr)> {
+    let msg_args = DiscoverBuilder::default()
+        .giaddr(GIADDR)
+        .chaddr(chaddr)
+        .build()?;
+    let resp = client.run(MsgType::Discover(msg_args))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Offer);
+
+    let sident = utils::get_sident(&resp)?;
+    let msg_args = RequestBuilder::default()
+        .giaddr(GIADDR)
+        .opt_req_addr(resp.yiaddr())
+        .sident(sident)
+        .chaddr(chaddr)
+        .build()?;
+    let resp = client.run(MsgType::Request(msg_args))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Ack);
+    Ok((resp.yiaddr(), sident))
+}
+
+/// INIT-REBOOT (4.3.2): REQUEST with option 50, no server id & no ciaddr.
+/// The server ACKs a client's own lease & NAKs an address it can't give out
+#[test]
+#[traced_test]
+fn init_reboot() -> Result<()> {
+    let _srv = start();
+    let mut client = client()?;
+    let chaddr = utils::rand_mac();
+    let (yiaddr, _) = bind(&mut client, chaddr)?;
+
+    // rebooted client asks for its old address
+    let msg_args = RequestBuilder::default()
+        .giaddr(GIADDR)
+        .opt_req_addr(yiaddr)
+        .chaddr(chaddr)
+        .build()?;
+    let resp = client.run(MsgType::Request(msg_args))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Ack);
+    assert_eq!(resp.yiaddr(), yiaddr);
+
+    // another client claims the same address
+    let msg_args = RequestBuilder::default()
+        .giaddr(GIADDR)
+        .opt_req_addr(yiaddr)
+        .chaddr(utils::rand_mac())
+        .build()?;
+    let resp = client.run(MsgType::Request(msg_args))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Nak);
+    assert!(resp.yiaddr().is_unspecified());
+
+    // client moved from another network, `nak.wrong_network` is set
+    let msg_args = RequestBuilder::default()
+        .giaddr(GIADDR)
+        .opt_req_addr([10, 9, 9, 9])
+        .chaddr(chaddr)
+        .build()?;
+    let resp = client.run(MsgType::Request(msg_args))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Nak);
+    Ok(())
+}
+
+/// RENEWING & REBINDING (4.3.2): REQUEST with ciaddr & no option 50. A
+/// renewing client includes the server id, a rebinding one does not
+#[test]
+#[traced_test]
+fn renew_rebind() -> Result<()> {
+    let _srv = start();
+    let mut client = client()?;
+    let chaddr = utils::rand_mac();
+    let (yiaddr, sident) = bind(&mut client, chaddr)?;
+
+    // T1, RENEWING
+    let msg_args = RequestBuilder::default()
+        .giaddr(GIADDR)
+        .ciaddr(yiaddr)
+        .sident(sident)
+        .chaddr(chaddr)
+        .build()?;
+    let resp = client.run(MsgType::Request(msg_args))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Ack);
+    assert_eq!(resp.yiaddr(), yiaddr);
+
+    // T2, REBINDING
+    let msg_args = RequestBuilder::default()
+        .giaddr(GIADDR)
+        .ciaddr(yiaddr)
+        .chaddr(chaddr)
+        .build()?;
+    let resp = client.run(MsgType::Request(msg_args))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Ack);
+    assert_eq!(resp.yiaddr(), yiaddr);
+    assert!(resp.opts().get(v4::OptionCode::AddressLeaseTime).is_some());
+    Ok(())
+}
+
+/// DECLINE (4.3.3): every declined address goes on probation until the pool
+/// is exhausted, after which DISCOVER gets no OFFER
+#[test]
+#[traced_test]
+fn decline_storm() -> Result<()> {
+    let _srv = start();
+    let mut client = client()?;
+    let mut declined = Vec::new();
+    for _ in 0..5 {
+        let chaddr = utils::rand_mac();
+        let (yiaddr, sident) = bind(&mut client, chaddr)?;
+        assert!(
+            !declined.contains(&yiaddr),
+            "{yiaddr} offered after DECLINE"
+        );
+
+        let msg_args = DeclineBuilder::default()
+            .giaddr(GIADDR)
+            .opt_req_addr(yiaddr)
+            .sident(sident)
+            .chaddr(chaddr)
+            .build()?;
+        // no response to decline
+        assert!(client.run(MsgType::Decline(msg_args)).is_err());
+        declined.push(yiaddr);
+    }
+
+    let msg_args = DiscoverBuilder::default()
+        .giaddr(GIADDR)
+        .chaddr(utils::rand_mac())
+        .build()?;
+    assert!(client.run(MsgType::Discover(msg_args)).is_err());
+    Ok(())
+}
+
+/// lease expiry (4.4.5): the address can't be given to another client while
+/// the lease is held, and can once it has expired
+#[test]
+#[traced_test]
+fn lease_expiry() -> Result<()> {
+    let _srv = start();
+    let mut client = client()?;
+    let (yiaddr, _) = bind(&mut client, utils::rand_mac())?;
+
+    let msg_args = DiscoverBuilder::default()
+        .giaddr(GIADDR)
+        .req_addr(yiaddr)
+        .chaddr(utils::rand_mac())
+        .build()?;
+    let resp = client.run(MsgType::Discover(msg_args.clone()))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Offer);
+    assert_ne!(resp.yiaddr(), yiaddr);
+
+    // wait out the 3s lease & the 1s offer hold
+    thread::sleep(Duration::from_secs(4));
+    let resp = client.run(MsgType::Discover(msg_args))?;
+    assert_eq!(resp.opts().msg_type().unwrap(), v4::MessageType::Offer);
+    assert_eq!(resp.yiaddr(), yiaddr);
+    Ok(())
+}
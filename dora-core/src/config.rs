@@ -14,6 +14,10 @@ pub mod cli {
     pub static DEFAULT_V6_ADDR: &str = "[::]:547"; // default dhcpv6 port is 547
     /// Default external api
     pub static DEFAULT_EXTERNAL_API: &str = "[::]:3333";
+    /// Default tftp addr
+    pub static DEFAULT_TFTP_ADDR: &str = "0.0.0.0:69";
+    /// Default max tftp transfers running at once
+    pub const DEFAULT_TFTP_MAX_TRANSFERS: usize = 64;
     /// Default channel size for mpsc chans
     pub const DEFAULT_CHANNEL_SIZE: usize = 10_000;
     /// Max live messages -- Changing this value will effect memory
@@ -21,6 +25,10 @@ pub mod cli {
     /// used. At some point, the timeout will be hit and setting the live msg count
     /// higher will not affect % of timeouts
     pub const DEFAULT_MAX_LIVE_MSGS: usize = 1_000;
+    /// Default max messages queued per interface waiting for a live msg slot
+    pub const DEFAULT_INGRESS_QUEUE_SIZE: usize = 1_000;
+    /// Default sockets bound per listen address
+    pub const DEFAULT_SOCKET_WORKERS: usize = 1;
     /// Default timeout, we must respond within this window or we will time out
     pub const DEFAULT_TIMEOUT: u64 = 3;
     /// tokio worker thread name
@@ -33,12 +41,41 @@ pub mod cli {
     pub const DEFAULT_DATABASE_URL: &str = "/var/lib/dora/leases.db";
     /// default dora id
     pub const DEFAULT_DORA_ID: &str = "dora_id";
+    /// default topic for lease events
+    pub static DEFAULT_EVENT_TOPIC: &str = "dora.leases";
+    /// default filter for spans exported over OTLP
+    pub const DEFAULT_OTLP_FILTER: &str = "debug";
     /// default log level. Can use this argument or DORA_LOG env var
     pub const DEFAULT_DORA_LOG: &str = "info";
+    /// default seconds between pool utilization samples
+    pub const DEFAULT_POOL_STATS_INTERVAL: u64 = 300;
+    /// default seconds pool utilization samples are kept (7 days)
+    pub const DEFAULT_POOL_STATS_RETENTION: u64 = 604_800;
+    /// default days daily pool utilization rollups are kept
+    pub const DEFAULT_STATS_RETENTION_DAYS: u64 = 365;
+    /// default days hostname conflicts & lease transitions are kept
+    pub const DEFAULT_AUDIT_RETENTION_DAYS: u64 = 90;
+    /// default seconds between saves of the per network message counts
+    pub const DEFAULT_NETWORK_COUNTS_INTERVAL: u64 = 60;
+    /// default seconds between syncs of static addresses from an IPAM
+    pub const DEFAULT_IPAM_SYNC_INTERVAL: u64 = 300;
+    /// default seconds between fetches of an http `config_url`
+    pub const DEFAULT_CONFIG_POLL_INTERVAL: u64 = 30;
+    /// default leases sent to an IPAM per export request
+    pub const DEFAULT_IPAM_EXPORT_BATCH: u64 = 50;
+    /// default milliseconds between export requests to an IPAM
+    pub const DEFAULT_IPAM_EXPORT_DELAY_MS: u64 = 1000;
+    /// default seconds between lease database syncs
+    pub const DEFAULT_DB_SYNC_INTERVAL: u64 = 1;
+    /// default most free pages returned to the filesystem per maintenance run
+    pub const DEFAULT_DB_VACUUM_PAGES: u32 = 10_000;
+    /// default pings per second sent by the startup scan
+    pub const DEFAULT_SCAN_RATE: u32 = 20;
 
     use std::{
-        net::{Ipv6Addr, SocketAddr},
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr},
         path::PathBuf,
+        str::FromStr,
         time::Duration,
     };
 
@@ -58,21 +95,93 @@ pub mod cli {
             default_value = DEFAULT_CONFIG_PATH
         )]
         pub config_path: PathBuf,
+        /// where the DHCP config is loaded from. `database` runs the newest
+        /// config pushed to the external api, `config_path` is stored as the
+        /// first version if none has been pushed
+        #[clap(long, env, value_enum, default_value_t = ConfigSource::File)]
+        pub config_source: ConfigSource,
+        /// fetch the DHCP config from a url instead, ex. "https://cfg.example.com/dora.yaml",
+        /// "etcd+http://etcd:2379/dora/config" or "consul+http://consul:8500/dora/config".
+        /// dora must be built with the matching `config-http`/`config-etcd`/`config-consul`
+        /// feature. When the config changes dora shuts down to be restarted with it
+        #[clap(long, env, value_parser, conflicts_with_all = ["config_source", "instances"])]
+        pub config_url: Option<String>,
+        /// token for `config_url`, sent as a bearer token over http, as the etcd auth
+        /// token or as the consul ACL token
+        #[clap(long, env, value_parser)]
+        pub config_token: Option<String>,
+        /// seconds between fetches of an http `config_url`
+        #[clap(long, env, value_parser = clap::value_parser!(u64).range(1..), default_value_t = DEFAULT_CONFIG_POLL_INTERVAL)]
+        pub config_poll_interval: u64,
         /// the v4 address to listen on
         #[clap(long, env, value_parser, default_value = DEFAULT_V4_ADDR)]
         pub v4_addr: SocketAddr,
         /// the v6 address to listen on
         #[clap(long, env, value_parser, default_value = DEFAULT_V6_ADDR)]
         pub v6_addr: SocketAddr,
+        /// override the port dora listens on for v4, replaces the port in `v4_addr`
+        #[clap(long, env, value_parser)]
+        pub v4_port: Option<u16>,
+        /// override the port dora listens on for v6, replaces the port in `v6_addr`
+        #[clap(long, env, value_parser)]
+        pub v6_port: Option<u16>,
+        /// destination port for v4 replies sent to clients (default 68).
+        /// If dora is on a non-default port & this is not set, replies are
+        /// unicast back to the source ip:port (useful for testing)
+        #[clap(long, env, value_parser)]
+        pub v4_client_port: Option<u16>,
+        /// destination port for v4 replies sent to relays (default 67)
+        #[clap(long, env, value_parser)]
+        pub v4_relay_port: Option<u16>,
+        /// destination port for v6 replies (default 546).
+        /// If dora is on a non-default port & this is not set, replies are
+        /// unicast back to the source ip:port (useful for testing)
+        #[clap(long, env, value_parser)]
+        pub v6_client_port: Option<u16>,
+        /// per-interface v4 client reply port overrides, ex. "eth0=1068,eth1=2068"
+        #[clap(long, env, value_parser, value_delimiter = ',')]
+        pub v4_interface_ports: Vec<InterfacePort>,
+        /// per-interface v6 client reply port overrides, ex. "eth0=1546,eth1=2546"
+        #[clap(long, env, value_parser, value_delimiter = ',')]
+        pub v6_interface_ports: Vec<InterfacePort>,
+        /// serve the boot files in this directory over read-only TFTP. dora must be
+        /// built with the `tftp` feature
+        #[clap(long, env, value_parser)]
+        pub tftp_root: Option<PathBuf>,
+        /// the address TFTP is served on
+        #[clap(long, env, value_parser, default_value = DEFAULT_TFTP_ADDR)]
+        pub tftp_addr: SocketAddr,
+        /// max TFTP transfers running at once, read requests beyond this are refused
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_TFTP_MAX_TRANSFERS)]
+        pub tftp_max_transfers: usize,
         /// the v6 address to listen on
         #[clap(long, env, value_parser, default_value = DEFAULT_EXTERNAL_API)]
         pub external_api: SocketAddr,
         /// default timeout, dora will respond within this window or drop
         #[clap(long, env, value_parser, default_value_t = DEFAULT_TIMEOUT)]
         pub timeout: u64,
-        /// max live messages before new messages will begin to be dropped
+        /// milliseconds a transaction may take before a slow transaction report with
+        /// the time spent in each stage is logged & counted. 0 disables the reports
+        #[clap(long, env, value_parser, default_value_t = 0)]
+        pub latency_budget_ms: u64,
+        /// max live messages (in-flight transactions) processed at once. Messages
+        /// beyond this wait in the ingress queue
         #[clap(long, env, value_parser, default_value_t = DEFAULT_MAX_LIVE_MSGS)]
         pub max_live_msgs: usize,
+        /// max messages queued per interface while waiting for a live message slot.
+        /// When full, the oldest queued message is dropped
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_INGRESS_QUEUE_SIZE)]
+        pub ingress_queue_size: usize,
+        /// sockets bound to each listen address with SO_REUSEPORT, each read on its
+        /// own task. The kernel spreads unicast (ex. relayed) messages across them,
+        /// broadcast & multicast are read by the first. A socket passed by systemd
+        /// needs `ReusePort=yes` for more than one worker
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_SOCKET_WORKERS)]
+        pub socket_workers: usize,
+        /// how DHCP messages are read & sent. `io-uring` needs dora built with the
+        /// `io-uring` feature & linux 5.11 or newer
+        #[clap(long, env, value_enum, default_value_t = IoBackend::Epoll)]
+        pub io_backend: IoBackend,
         /// channel size for various mpsc chans
         #[clap(long, env, value_parser, default_value_t = DEFAULT_CHANNEL_SIZE)]
         pub channel_size: usize,
@@ -92,6 +201,134 @@ pub mod cli {
         /// NOTE: in memory sqlite db connection idle timeout is 5 mins
         #[clap(short, env, value_parser, default_value = DEFAULT_DATABASE_URL)]
         pub database_url: String,
+        /// when lease database commits are synced to disk. `interval` syncs every
+        /// `db_sync_interval` seconds, `commit` syncs each commit before replying,
+        /// `behind` keeps leases in memory & writes them out every
+        /// `db_sync_interval` seconds
+        #[clap(long, env, value_enum, default_value_t = DbSync::Interval)]
+        pub db_sync: DbSync,
+        /// seconds between lease database syncs when `db_sync` is `interval` or `behind`
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_DB_SYNC_INTERVAL)]
+        pub db_sync_interval: u64,
+        /// most lease writes committed in one transaction, writes made while
+        /// a commit is syncing are batched into the next. 0 or 1 commits each
+        /// write on its own
+        #[clap(long, env, value_parser, default_value_t = 0)]
+        pub db_batch_size: usize,
+        /// milliseconds a lease write may wait for others to fill its batch,
+        /// 0 only batches writes that are already queued
+        #[clap(long, env, value_parser, default_value_t = 0)]
+        pub db_batch_wait_ms: u64,
+        /// daily window, "HH:MM-HH:MM", the lease database is vacuumed &
+        /// analyzed in once. Unset disables maintenance
+        #[clap(long, env, value_parser)]
+        pub db_maintenance_window: Option<String>,
+        /// IANA timezone of `db_maintenance_window`, defaults to UTC
+        #[clap(long, env, value_parser)]
+        pub db_maintenance_timezone: Option<String>,
+        /// most free pages returned to the filesystem per maintenance run, 0
+        /// returns all of them
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_DB_VACUUM_PAGES)]
+        pub db_vacuum_pages: u32,
+        /// on start, ping every range address missing from the lease database &
+        /// put the ones in use on probation. Useful after the database was lost
+        #[clap(long, env)]
+        pub scan_in_use: bool,
+        /// pings per second sent by `scan_in_use`
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_SCAN_RATE)]
+        pub scan_rate: u32,
+        /// run as this user once the DHCP sockets are bound, overrides `run_as` in
+        /// the config. No capabilities are kept, open sockets keep working
+        #[clap(long, env, value_parser)]
+        pub user: Option<String>,
+        /// run as this group after startup, defaults to the primary group of `user`
+        #[clap(long, env, value_parser, requires = "user")]
+        pub group: Option<String>,
+        /// publish lease events to a broker, ex. "kafka://host:9092" or "nats://host:4222".
+        /// dora must be built with the matching `kafka`/`nats` feature
+        #[clap(long, env, value_parser)]
+        pub event_bus: Option<String>,
+        /// kafka topic, or NATS subject prefix, lease events are published to
+        #[clap(long, env, value_parser, default_value = DEFAULT_EVENT_TOPIC)]
+        pub event_topic: String,
+        /// keep addresses an IPAM records as statically assigned from being handed out,
+        /// ex. "netbox+https://netbox.example.com" or "phpipam+https://ipam.example.com/api/dora".
+        /// dora must be built with the matching `netbox`/`phpipam` feature
+        #[clap(long, env, value_parser)]
+        pub ipam_url: Option<String>,
+        /// API token for `ipam_url`
+        #[clap(long, env, value_parser)]
+        pub ipam_token: Option<String>,
+        /// seconds between syncs from `ipam_url`
+        #[clap(long, env, value_parser = clap::value_parser!(u64).range(1..), default_value_t = DEFAULT_IPAM_SYNC_INTERVAL)]
+        pub ipam_sync_interval: u64,
+        /// also export active leases to `ipam_url` every `ipam_sync_interval`, as
+        /// DHCP addresses with the client's hostname & MAC
+        #[clap(long, env, value_parser, requires = "ipam_url")]
+        pub ipam_export: bool,
+        /// leases sent to the IPAM per export request
+        #[clap(long, env, value_parser = clap::value_parser!(u64).range(1..), default_value_t = DEFAULT_IPAM_EXPORT_BATCH)]
+        pub ipam_export_batch: u64,
+        /// milliseconds between export requests, to rate limit the IPAM API
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_IPAM_EXPORT_DELAY_MS)]
+        pub ipam_export_delay_ms: u64,
+        /// export tracing spans over OTLP/gRPC to this endpoint, ex. "http://localhost:4317".
+        /// Needs dora built with the `otlp` feature
+        #[clap(long, env, value_parser)]
+        pub otlp_endpoint: Option<String>,
+        /// which spans are exported over OTLP, takes the same arguments as `dora_log`
+        #[clap(long, env, value_parser, default_value = DEFAULT_OTLP_FILTER)]
+        pub otlp_filter: String,
+        /// path to an IEEE OUI registry (oui.csv or oui.txt), used to add the
+        /// hardware vendor to lease events & logs
+        #[clap(long, env, value_parser)]
+        pub oui_db: Option<PathBuf>,
+        /// seconds between pool utilization samples stored in the lease database,
+        /// served on `/stats/pools`. 0 disables sampling
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_POOL_STATS_INTERVAL)]
+        pub pool_stats_interval: u64,
+        /// seconds pool utilization samples are kept, older samples are rolled up
+        /// into daily rollups
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_POOL_STATS_RETENTION)]
+        pub pool_stats_retention: u64,
+        /// days daily pool utilization rollups are kept
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_STATS_RETENTION_DAYS)]
+        pub stats_retention_days: u64,
+        /// days hostname conflicts & lease transitions are kept
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_AUDIT_RETENTION_DAYS)]
+        pub audit_retention_days: u64,
+        /// seconds between saves of the per network ACK, NAK & DECLINE counts to
+        /// the lease database, they're restored on start. 0 disables saving
+        #[clap(long, env, value_parser, default_value_t = DEFAULT_NETWORK_COUNTS_INTERVAL)]
+        pub network_counts_interval: u64,
+        /// path to an instances file, runs one dhcp server per named instance,
+        /// each with its own config & lease database. `config_path` &
+        /// `database_url` are ignored when set
+        #[clap(long, env, value_parser)]
+        pub instances: Option<PathBuf>,
+        /// replay the recorded client packets (`.pcap` & `.json` files) in this
+        /// directory through the plugins with `config_path` & an in-memory lease
+        /// database, print the replies & exit. Exits with an error if a reply
+        /// doesn't meet its expectations
+        #[clap(long, env, value_parser)]
+        pub replay: Option<PathBuf>,
+        /// subnet selection (option 118) added to replayed packets that weren't
+        /// relayed, to pick the network they were recorded on
+        #[clap(long, env, value_parser, requires = "replay")]
+        pub replay_subnet: Option<Ipv4Addr>,
+        /// measure lease expiry & probation against a fake clock, to test them
+        /// without waiting. `+OFFSET` starts it ahead of the system clock (units
+        /// s, m, h or d, ex. `+1d`), `xSPEED` runs it SPEED times as fast, ex.
+        /// `+1hx60`. For development only
+        #[clap(long, env, value_parser)]
+        pub faketime: Option<FakeTime>,
+        /// run a command on `config_path` & exit instead of serving
+        #[clap(subcommand)]
+        pub command: Option<Command>,
+        /// name of the instance this config belongs to, set by dora when
+        /// running with `instances`
+        #[clap(skip)]
+        pub instance: Option<String>,
     }
 
     impl Config {
@@ -100,83 +337,528 @@ pub mod cli {
             Duration::from_secs(self.timeout)
         }
 
+        /// transactions slower than this are reported, if reports are enabled
+        pub fn latency_budget(&self) -> Option<Duration> {
+            if self.latency_budget_ms == 0 {
+                return None;
+            }
+            Some(Duration::from_millis(self.latency_budget_ms))
+        }
+
+        /// pool utilization sampling interval, if sampling is enabled
+        pub fn pool_stats(&self) -> Option<Duration> {
+            if self.pool_stats_interval == 0 {
+                return None;
+            }
+            Some(Duration::from_secs(self.pool_stats_interval))
+        }
+
+        /// interval an http `config_url` is fetched on
+        pub fn config_poll_interval(&self) -> Duration {
+            Duration::from_secs(self.config_poll_interval)
+        }
+
+        /// interval static addresses are synced from an IPAM on
+        pub fn ipam_sync_interval(&self) -> Duration {
+            Duration::from_secs(self.ipam_sync_interval)
+        }
+
+        /// batch size & pause between requests of lease exports to an IPAM
+        pub fn ipam_export(&self) -> (usize, Duration) {
+            (
+                self.ipam_export_batch as usize,
+                Duration::from_millis(self.ipam_export_delay_ms),
+            )
+        }
+
+        /// per network message count save interval, if counts are saved
+        pub fn network_counts(&self) -> Option<Duration> {
+            if self.network_counts_interval == 0 {
+                return None;
+            }
+            Some(Duration::from_secs(self.network_counts_interval))
+        }
+
+        /// how long pool samples, daily rollups & audit data (hostname conflicts,
+        /// lease transitions) are kept
+        pub fn retention(&self) -> (Duration, Duration, Duration) {
+            let days = |days: u64| Duration::from_secs(days.saturating_mul(24 * 60 * 60));
+            (
+                Duration::from_secs(self.pool_stats_retention),
+                days(self.stats_retention_days),
+                days(self.audit_retention_days),
+            )
+        }
+
+        /// most lease writes per batch & how long to wait for a batch to fill,
+        /// `None` if writes aren't batched
+        pub fn db_batch(&self) -> Option<(usize, Duration)> {
+            if self.db_batch_size <= 1 {
+                return None;
+            }
+            Some((
+                self.db_batch_size,
+                Duration::from_millis(self.db_batch_wait_ms),
+            ))
+        }
+
+        /// interval the lease database is synced on, `None` if each commit is synced
+        pub fn db_sync_interval(&self) -> Option<Duration> {
+            match self.db_sync {
+                DbSync::Interval | DbSync::Behind => {
+                    Some(Duration::from_secs(self.db_sync_interval.max(1)))
+                }
+                DbSync::Commit => None,
+            }
+        }
+
+        /// OTLP endpoint & span filter, if export is enabled
+        pub fn otlp(&self) -> Option<(&str, &str)> {
+            self.otlp_endpoint
+                .as_deref()
+                .map(|endpoint| (endpoint, self.otlp_filter.as_str()))
+        }
+
+        /// the v4 address to bind, with `v4_port` applied
+        pub fn v4_listen_addr(&self) -> SocketAddr {
+            let mut addr = self.v4_addr;
+            if let Some(port) = self.v4_port {
+                addr.set_port(port);
+            }
+            addr
+        }
+
+        /// the v6 address to bind, with `v6_port` applied
+        pub fn v6_listen_addr(&self) -> SocketAddr {
+            let mut addr = self.v6_addr;
+            if let Some(port) = self.v6_port {
+                addr.set_port(port);
+            }
+            addr
+        }
+
         /// are we bound to the default dhcpv4 port?
         pub fn is_default_port_v4(&self) -> bool {
-            self.v4_addr.port() == v4::SERVER_PORT
+            self.v4_listen_addr().port() == v4::SERVER_PORT
         }
 
         /// are we bound to the default dhcpv6 port?
         pub fn is_default_port_v6(&self) -> bool {
-            self.v6_addr.port() == v6::SERVER_PORT
+            self.v6_listen_addr().port() == v6::SERVER_PORT
+        }
+
+        fn interface_port(ports: &[InterfacePort], iface: &str) -> Option<u16> {
+            ports
+                .iter()
+                .find(|int| int.name == iface)
+                .map(|int| int.port)
+        }
+
+        /// ports to send v4 replies to for `iface`. `None` means dora is
+        /// running on a non-default port without any reply ports configured, and
+        /// replies should be unicast back to the source ip:port
+        pub fn v4_reply_ports(&self, iface: &str) -> Option<ReplyPorts> {
+            let client =
+                Self::interface_port(&self.v4_interface_ports, iface).or(self.v4_client_port);
+            if client.is_none() && self.v4_relay_port.is_none() && !self.is_default_port_v4() {
+                return None;
+            }
+            Some(ReplyPorts {
+                client: client.unwrap_or(v4::CLIENT_PORT),
+                relay: self.v4_relay_port.unwrap_or(v4::SERVER_PORT),
+            })
+        }
+
+        /// port to send v6 replies to for `iface`. `None` means dora is
+        /// running on a non-default port without a reply port configured, and
+        /// replies should be unicast back to the source ip:port
+        pub fn v6_reply_port(&self, iface: &str) -> Option<u16> {
+            match Self::interface_port(&self.v6_interface_ports, iface).or(self.v6_client_port) {
+                Some(port) => Some(port),
+                None if self.is_default_port_v6() => Some(v6::CLIENT_PORT),
+                None => None,
+            }
+        }
+    }
+
+    /// when lease database commits are synced to disk
+    #[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum DbSync {
+        /// sync on an interval, commits since the last sync can be lost on power loss
+        Interval,
+        /// sync every commit
+        Commit,
+        /// commit in memory & write to disk on an interval, commits since the last
+        /// write are lost on a crash
+        Behind,
+    }
+
+    /// where the DHCP config is loaded from
+    #[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ConfigSource {
+        /// `config_path`
+        File,
+        /// the newest config version stored in the lease database
+        Database,
+    }
+
+    /// socket I/O of the DHCP packet path
+    #[derive(clap::ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum IoBackend {
+        /// `recvmmsg` & `sendmmsg` on sockets polled by the tokio runtime
+        Epoll,
+        /// reads & writes submitted to io_uring rings, each socket gets a receive
+        /// & a send thread
+        IoUring,
+    }
+
+    /// commands run instead of the server
+    #[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+    pub enum Command {
+        /// bulk import & export of the v4 MAC reservations in `config_path`
+        #[clap(subcommand)]
+        Reservations(Reservations),
+        /// check `config_path` for ranges with more options than fit in a
+        /// 576 byte reply, exits with an error if any are found
+        Lint,
+    }
+
+    /// `dora reservations` commands
+    #[derive(clap::Subcommand, Debug, Clone, PartialEq, Eq)]
+    pub enum Reservations {
+        /// add the `mac,ip[,lease_time]` rows of a CSV file to the networks
+        /// containing their addresses & rewrite `config_path`
+        Import {
+            /// CSV file to import
+            #[clap(long, value_parser)]
+            csv: PathBuf,
+            /// check the rows & print what would change without writing the config
+            #[clap(long)]
+            dry_run: bool,
+        },
+        /// write the MAC reservations of every network as `mac,ip,lease_time` rows
+        Export {
+            /// CSV file to write, stdout if unset
+            #[clap(long, value_parser)]
+            csv: Option<PathBuf>,
+        },
+    }
+
+    /// destination ports used when replying
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct ReplyPorts {
+        /// port used for replies sent directly to clients
+        pub client: u16,
+        /// port used for replies sent to a relay (giaddr)
+        pub relay: u16,
+    }
+
+    /// client reply port override for a single interface, parsed from "<iface>=<port>"
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct InterfacePort {
+        /// interface name
+        pub name: String,
+        /// client reply port
+        pub port: u16,
+    }
+
+    impl FromStr for InterfacePort {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (name, port) = s
+                .split_once('=')
+                .ok_or_else(|| format!("expected <iface>=<port>, got {s}"))?;
+            let port = port
+                .parse::<u16>()
+                .map_err(|err| format!("invalid port for interface {name}: {err}"))?;
+            Ok(Self {
+                name: name.to_owned(),
+                port,
+            })
+        }
+    }
+
+    /// a fake clock, parsed from "[+OFFSET][xSPEED]"
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub struct FakeTime {
+        /// how far ahead of the system clock it starts
+        pub offset: Duration,
+        /// how many times as fast as the system clock it runs
+        pub speed: u32,
+    }
+
+    impl FromStr for FakeTime {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (offset, speed) = match s.split_once('x') {
+                Some((offset, speed)) => (offset, Some(speed)),
+                None => (s, None),
+            };
+            let offset = match offset {
+                "" if speed.is_some() => Duration::ZERO,
+                offset => {
+                    let offset = offset
+                        .strip_prefix('+')
+                        .ok_or_else(|| format!("expected [+OFFSET][xSPEED], got {s}"))?;
+                    let (num, unit) = match offset.char_indices().last() {
+                        Some((i, unit @ ('s' | 'm' | 'h' | 'd'))) => (&offset[..i], unit),
+                        _ => (offset, 's'),
+                    };
+                    let num = num
+                        .parse::<u64>()
+                        .map_err(|err| format!("invalid faketime offset {offset}: {err}"))?;
+                    let secs = match unit {
+                        'm' => 60,
+                        'h' => 60 * 60,
+                        'd' => 24 * 60 * 60,
+                        _ => 1,
+                    };
+                    Duration::from_secs(num.saturating_mul(secs))
+                }
+            };
+            let speed = match speed {
+                Some(speed) => speed
+                    .parse::<u32>()
+                    .map_err(|err| format!("invalid faketime speed {speed}: {err}"))?,
+                None => 1,
+            };
+            Ok(Self { offset, speed })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_reply_ports() {
+            // non-default port with no reply ports: unicast to source
+            let cfg = Config::parse_from(["dora", "--v4-port", "9900"]);
+            assert_eq!(cfg.v4_listen_addr().port(), 9900);
+            assert_eq!(cfg.v4_reply_ports("eth0"), None);
+            assert_eq!(cfg.v6_reply_port("eth0"), Some(v6::CLIENT_PORT));
+
+            let cfg = Config::parse_from([
+                "dora",
+                "--v4-port",
+                "6767",
+                "--v4-client-port",
+                "68",
+                "--v4-interface-ports",
+                "eth1=1068",
+                "--v6-interface-ports",
+                "eth2=1546",
+            ]);
+            assert_eq!(
+                cfg.v4_reply_ports("eth0"),
+                Some(ReplyPorts {
+                    client: 68,
+                    relay: v4::SERVER_PORT
+                })
+            );
+            assert_eq!(cfg.v4_reply_ports("eth1").unwrap().client, 1068);
+            // each family has its own overrides
+            assert_eq!(cfg.v4_reply_ports("eth2").unwrap().client, 68);
+            assert_eq!(cfg.v6_reply_port("eth1"), Some(v6::CLIENT_PORT));
+            assert_eq!(cfg.v6_reply_port("eth2"), Some(1546));
+            assert!("eth1".parse::<InterfacePort>().is_err());
+        }
+
+        #[test]
+        fn test_faketime() {
+            let cfg = Config::parse_from(["dora", "--faketime", "+1dx60"]);
+            assert_eq!(
+                cfg.faketime,
+                Some(FakeTime {
+                    offset: Duration::from_secs(86_400),
+                    speed: 60
+                })
+            );
+            assert_eq!(
+                "+90".parse(),
+                Ok(FakeTime {
+                    offset: Duration::from_secs(90),
+                    speed: 1
+                })
+            );
+            assert_eq!(
+                "x0".parse(),
+                Ok(FakeTime {
+                    offset: Duration::ZERO,
+                    speed: 0
+                })
+            );
+            for bad in ["", "1h", "+h", "+1w", "x", "+1hxfast"] {
+                assert!(bad.parse::<FakeTime>().is_err(), "{bad}");
+            }
         }
     }
 }
 
 pub mod trace {
     //! tracing configuration
+    #[cfg(feature = "otlp")]
+    use anyhow::Context;
     use anyhow::Result;
+    #[cfg(feature = "otlp")]
+    use opentelemetry::{
+        sdk::{trace as sdktrace, Resource},
+        KeyValue,
+    };
+    #[cfg(feature = "otlp")]
+    use opentelemetry_otlp::WithExportConfig;
     use tracing_subscriber::{
-        filter::EnvFilter,
+        filter::{EnvFilter, FilterExt},
         fmt::{
             self,
             format::{Format, PrettyFields},
         },
         prelude::__tracing_subscriber_SubscriberExt,
         util::SubscriberInitExt,
+        Layer,
     };
+    #[cfg(feature = "otlp")]
+    use tracing_subscriber::{reload, Registry};
 
-    use std::str;
+    use std::{fmt as stdfmt, str};
 
-    use crate::env::parse_var_with_err;
+    use crate::{env::parse_var_with_err, trace_filter::EnableTraced};
 
     /// log as "json" or "standard" (unstructured)
     static DEFAULT_LOG_FORMAT: &str = "standard";
 
+    #[cfg(feature = "otlp")]
+    type OtlpLayer = tracing_opentelemetry::OpenTelemetryLayer<Registry, sdktrace::Tracer>;
+    #[cfg(feature = "otlp")]
+    type OtlpHandle = reload::Handle<Option<OtlpLayer>, Registry>;
+
     /// Configuration for `tokio` runtime
-    #[derive(Debug)]
     pub struct Config {
         /// formatting to apply to logs
         pub log_frmt: String,
+        #[cfg(feature = "otlp")]
+        otlp: Option<(String, OtlpHandle)>,
+    }
+
+    impl stdfmt::Debug for Config {
+        fn fmt(&self, f: &mut stdfmt::Formatter<'_>) -> stdfmt::Result {
+            let mut f = f.debug_struct("Config");
+            f.field("log_frmt", &self.log_frmt);
+            #[cfg(feature = "otlp")]
+            f.field("otlp", &self.otlp.as_ref().map(|(endpoint, _)| endpoint));
+            f.finish()
+        }
     }
 
     impl Config {
-        /// Make new runtime config
-        pub fn parse(dora_log: &str) -> Result<Self> {
+        /// Make new runtime config. If `otlp` is set to `(endpoint, filter)`, spans
+        /// matching `filter` are exported after [`Config::start_otlp`] is called
+        pub fn parse(dora_log: &str, otlp: Option<(&str, &str)>) -> Result<Self> {
             let log_frmt: String = parse_var_with_err("LOG_FORMAT", DEFAULT_LOG_FORMAT)?;
 
-            // Log level comes from DORA_LOG
+            // Log level comes from DORA_LOG, clients picked by the trace filter
+            // are logged at debug level too
             let filter = EnvFilter::try_new(dora_log)
                 .or_else(|_| EnvFilter::try_new("info"))?
-                .add_directive("hyper=off".parse()?);
+                .add_directive("hyper=off".parse()?)
+                .or(EnableTraced);
+            #[cfg(not(feature = "otlp"))]
+            if otlp.is_some() {
+                anyhow::bail!("dora was built without the `otlp` feature, can't export spans");
+            }
+            #[cfg(not(feature = "otlp"))]
+            let registry = tracing_subscriber::registry();
+            // the exporter needs a runtime, so it's swapped in by `start_otlp`. Its
+            // own filter is separate from the log filter, spans are exported even if
+            // they wouldn't be logged
+            #[cfg(feature = "otlp")]
+            let otlp_filter = match otlp {
+                Some((_, filter)) => EnvFilter::try_new(filter)
+                    .context("invalid otlp filter")?
+                    .add_directive("hyper=off".parse()?)
+                    .add_directive("h2=off".parse()?)
+                    .add_directive("tonic=off".parse()?)
+                    .add_directive("tower=off".parse()?),
+                None => EnvFilter::try_new("off")?,
+            };
+            #[cfg(feature = "otlp")]
+            let (otlp_layer, handle) = reload::Layer::new(None);
+            #[cfg(feature = "otlp")]
+            let registry = tracing_subscriber::registry().with(otlp_layer.with_filter(otlp_filter));
 
             match &log_frmt[..] {
                 "json" => {
-                    tracing_subscriber::registry()
-                        .with(filter)
-                        .with(fmt::layer().json())
+                    registry
+                        .with(fmt::layer().json().with_filter(filter))
                         .init();
                 }
                 "pretty" => {
-                    tracing_subscriber::registry()
-                        .with(filter)
+                    registry
                         .with(
                             fmt::layer()
                                 .event_format(
                                     Format::default().pretty().with_source_location(false),
                                 )
-                                .fmt_fields(PrettyFields::new()),
+                                .fmt_fields(PrettyFields::new())
+                                .with_filter(filter),
                         )
                         .init();
                 }
                 _ => {
-                    tracing_subscriber::registry()
-                        .with(filter)
-                        .with(fmt::layer())
-                        .init();
+                    registry.with(fmt::layer().with_filter(filter)).init();
                 }
             }
 
-            Ok(Self { log_frmt })
+            Ok(Self {
+                log_frmt,
+                #[cfg(feature = "otlp")]
+                otlp: otlp.map(|(endpoint, _)| (endpoint.to_owned(), handle)),
+            })
+        }
+
+        /// start exporting spans over OTLP, if configured. Must be called from
+        /// inside the tokio runtime
+        #[cfg(feature = "otlp")]
+        pub fn start_otlp(&self) -> Result<()> {
+            let (endpoint, handle) = match &self.otlp {
+                Some(otlp) => otlp,
+                None => return Ok(()),
+            };
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(
+                    sdktrace::config()
+                        .with_resource(Resource::new([KeyValue::new("service.name", "dora")])),
+                )
+                .install_batch(opentelemetry::runtime::Tokio)
+                .context("failed to create otlp exporter")?;
+            handle
+                .reload(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+                .context("failed to install otlp layer")?;
+            tracing::info!(?endpoint, "exporting spans over otlp");
+            Ok(())
+        }
+
+        /// start exporting spans over OTLP, without the `otlp` feature `parse`
+        /// already refused an endpoint
+        #[cfg(not(feature = "otlp"))]
+        pub fn start_otlp(&self) -> Result<()> {
+            Ok(())
+        }
+
+        /// flush any spans waiting to be exported
+        pub fn shutdown(&self) {
+            #[cfg(feature = "otlp")]
+            if self.otlp.is_some() {
+                opentelemetry::global::shutdown_tracer_provider();
+            }
         }
     }
 }
@@ -0,0 +1,342 @@
+//! # garbage clients
+//!
+//! Heuristics flagging v4 clients that look spoofed or broken:
+//!
+//! - **xid reuse**: one transaction id (xid) sent from more than `xid_chaddrs`
+//!   different `chaddr`s inside `window`. Clients pick a random xid for every
+//!   transaction, so an xid shared between hardware addresses points to a
+//!   spoofing tool or a cloned image with a fixed seed.
+//! - **MAC rotation**: more than `circuit_chaddrs` different `chaddr`s from one
+//!   relay agent circuit (giaddr & opt 82 circuit id) inside `window`, a single
+//!   port cycling through hardware addresses, usually to exhaust the pool.
+//!
+//! The `chaddr`s past the limit are flagged, the ones seen first are not.
+//! Findings are counted in the `garbage_client_flagged` metric & kept in a
+//! process wide log, the latest [`MAX_FINDINGS`], read by the external api.
+//! With `block` set the flagged message is dropped.
+use std::{
+    collections::HashMap,
+    fmt,
+    net::Ipv4Addr,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use dhcproto::v4::{
+    relay::{RelayCode, RelayInfo},
+    DhcpOption, Message, OptionCode,
+};
+use pnet::util::MacAddr;
+
+use crate::metrics;
+
+/// most findings kept in the log, older ones are dropped
+pub const MAX_FINDINGS: usize = 1_000;
+/// most xids or circuits tracked at once, new ones aren't tracked past it
+const MAX_TRACKED: usize = 100_000;
+
+static FINDINGS: Mutex<Vec<Finding>> = Mutex::new(Vec::new());
+
+/// when clients are flagged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// how long a client is remembered
+    pub window: Duration,
+    /// most `chaddr`s that can send the same xid
+    pub xid_chaddrs: usize,
+    /// most `chaddr`s that can be relayed from the same circuit
+    pub circuit_chaddrs: usize,
+    /// drop messages from flagged clients, instead of only reporting them
+    pub block: bool,
+}
+
+/// a message that tripped a heuristic
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// the heuristic
+    pub kind: FindingKind,
+    /// hardware address the message was sent with
+    pub chaddr: MacAddr,
+    /// different `chaddr`s seen in the window, including this one
+    pub chaddrs: usize,
+    /// was the message dropped
+    pub blocked: bool,
+    /// when the message was received
+    pub at: SystemTime,
+}
+
+/// the heuristic a client tripped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FindingKind {
+    /// the xid was sent from other `chaddr`s
+    XidReuse {
+        /// the shared xid
+        xid: u32,
+    },
+    /// the circuit relayed other `chaddr`s
+    MacRotation {
+        /// the relay
+        giaddr: Ipv4Addr,
+        /// opt 82 circuit id
+        circuit_id: Vec<u8>,
+    },
+}
+
+impl FindingKind {
+    /// name of the heuristic, as used in metrics & the api
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FindingKind::XidReuse { .. } => "xid_reuse",
+            FindingKind::MacRotation { .. } => "mac_rotation",
+        }
+    }
+}
+
+impl fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// tracks recent `chaddr`s by xid & by circuit, one per server
+#[derive(Debug)]
+pub struct Detector {
+    limits: Limits,
+    seen: Mutex<Seen>,
+}
+
+/// the `chaddr`s seen for a key & when each was last seen
+type Sightings = Vec<(MacAddr, Instant)>;
+
+#[derive(Debug)]
+struct Seen {
+    xids: HashMap<u32, Sightings>,
+    /// keyed by giaddr & circuit id
+    circuits: HashMap<(Ipv4Addr, Vec<u8>), Sightings>,
+    /// when expired entries were last dropped
+    swept: Instant,
+}
+
+impl Detector {
+    /// a detector flagging clients past `limits`
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            seen: Mutex::new(Seen {
+                xids: HashMap::new(),
+                circuits: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// the detector's limits
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// record `msg`, returning a finding if it trips a heuristic. Findings are
+    /// added to the log & counted, `blocked` says whether to drop the message
+    pub fn check(&self, msg: &Message) -> Option<Finding> {
+        let finding = self.check_at(msg, Instant::now())?;
+        metrics::GARBAGE_CLIENT_FLAGGED
+            .with_label_values(&[finding.kind.as_str()])
+            .inc();
+        if finding.blocked {
+            metrics::GARBAGE_CLIENT_BLOCKED.inc();
+        }
+        let mut findings = FINDINGS.lock().unwrap_or_else(PoisonError::into_inner);
+        findings.push(finding.clone());
+        if findings.len() > MAX_FINDINGS {
+            let extra = findings.len() - MAX_FINDINGS;
+            findings.drain(..extra);
+        }
+        Some(finding)
+    }
+
+    fn check_at(&self, msg: &Message, now: Instant) -> Option<Finding> {
+        let chaddr = mac(msg.chaddr());
+        let window = self.limits.window;
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        if now.saturating_duration_since(seen.swept) >= window {
+            let live = |entries: &mut Sightings| {
+                entries.retain(|(_, at)| now.saturating_duration_since(*at) < window);
+                !entries.is_empty()
+            };
+            seen.xids.retain(|_, entries| live(entries));
+            seen.circuits.retain(|_, entries| live(entries));
+            seen.swept = now;
+        }
+
+        let xid = msg.xid();
+        let tracked = seen.xids.len() < MAX_TRACKED || seen.xids.contains_key(&xid);
+        let xid_count = tracked
+            .then(|| see(seen.xids.entry(xid).or_default(), chaddr, now, window))
+            .filter(|(pos, _)| *pos >= self.limits.xid_chaddrs);
+        let circuit = circuit_id(msg).map(|id| (msg.giaddr(), id));
+        let circuit_count = circuit.clone().and_then(|key| {
+            let tracked = seen.circuits.len() < MAX_TRACKED || seen.circuits.contains_key(&key);
+            tracked
+                .then(|| see(seen.circuits.entry(key).or_default(), chaddr, now, window))
+                .filter(|(pos, _)| *pos >= self.limits.circuit_chaddrs)
+        });
+
+        let (kind, chaddrs) = match (xid_count, circuit, circuit_count) {
+            (Some((_, chaddrs)), _, _) => (FindingKind::XidReuse { xid }, chaddrs),
+            (None, Some((giaddr, circuit_id)), Some((_, chaddrs))) => {
+                (FindingKind::MacRotation { giaddr, circuit_id }, chaddrs)
+            }
+            _ => return None,
+        };
+        Some(Finding {
+            kind,
+            chaddr,
+            chaddrs,
+            blocked: self.limits.block,
+            at: SystemTime::now(),
+        })
+    }
+}
+
+/// note `chaddr` in `entries`, returning its position by first seen & the
+/// number of `chaddr`s in the window
+fn see(entries: &mut Sightings, chaddr: MacAddr, now: Instant, window: Duration) -> (usize, usize) {
+    entries.retain(|(_, at)| now.saturating_duration_since(*at) < window);
+    let pos = match entries.iter().position(|(mac, _)| *mac == chaddr) {
+        Some(pos) => {
+            entries[pos].1 = now;
+            pos
+        }
+        None => {
+            entries.push((chaddr, now));
+            entries.len() - 1
+        }
+    };
+    (pos, entries.len())
+}
+
+/// the first 6 bytes of `chaddr`
+fn mac(chaddr: &[u8]) -> MacAddr {
+    let mut octets = [0; 6];
+    let len = chaddr.len().min(6);
+    octets[..len].copy_from_slice(&chaddr[..len]);
+    MacAddr::new(
+        octets[0], octets[1], octets[2], octets[3], octets[4], octets[5],
+    )
+}
+
+/// opt 82 circuit id of a relayed message
+fn circuit_id(msg: &Message) -> Option<Vec<u8>> {
+    if msg.giaddr().is_unspecified() {
+        return None;
+    }
+    match msg.opts().get(OptionCode::RelayAgentInformation) {
+        Some(DhcpOption::RelayAgentInformation(info)) => {
+            match info.get(RelayCode::AgentCircuitId) {
+                Some(RelayInfo::AgentCircuitId(id)) => Some(id.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// logged findings, newest first
+pub fn findings() -> Vec<Finding> {
+    FINDINGS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .rev()
+        .cloned()
+        .collect()
+}
+
+/// empty the log
+pub fn clear() {
+    FINDINGS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use dhcproto::v4::relay::RelayAgentInformation;
+
+    use super::*;
+
+    fn msg(xid: u32, chaddr: u8, circuit: Option<&[u8]>) -> Message {
+        let mut msg = Message::default();
+        msg.set_xid(xid)
+            .set_chaddr(&[0xaa, 0xbb, 0xcc, 0xdd, 0xee, chaddr]);
+        if let Some(circuit) = circuit {
+            let mut info = RelayAgentInformation::default();
+            info.insert(RelayInfo::AgentCircuitId(circuit.to_vec()));
+            msg.set_giaddr([10, 0, 0, 1])
+                .opts_mut()
+                .insert(DhcpOption::RelayAgentInformation(info));
+        }
+        msg
+    }
+
+    fn blocking() -> Detector {
+        Detector::new(Limits {
+            window: Duration::from_secs(60),
+            xid_chaddrs: 2,
+            circuit_chaddrs: 3,
+            block: true,
+        })
+    }
+
+    #[test]
+    fn test_xid_reuse() {
+        let detector = blocking();
+        let now = Instant::now();
+        assert!(detector.check_at(&msg(1, 1, None), now).is_none());
+        assert!(detector.check_at(&msg(1, 2, None), now).is_none());
+        // retransmits are fine
+        assert!(detector.check_at(&msg(1, 1, None), now).is_none());
+        let finding = detector.check_at(&msg(1, 3, None), now).unwrap();
+        assert_eq!(finding.kind, FindingKind::XidReuse { xid: 1 });
+        assert_eq!(
+            finding.chaddr,
+            MacAddr::new(0xaa, 0xbb, 0xcc, 0xdd, 0xee, 3)
+        );
+        assert_eq!(finding.chaddrs, 3);
+        assert!(finding.blocked);
+        // the first chaddrs aren't flagged, the xid is forgotten after the window
+        assert!(detector.check_at(&msg(1, 2, None), now).is_none());
+        let later = now + Duration::from_secs(61);
+        assert!(detector.check_at(&msg(1, 3, None), later).is_none());
+    }
+
+    #[test]
+    fn test_mac_rotation() {
+        let detector = blocking();
+        let now = Instant::now();
+        for (xid, chaddr) in [(1, 1), (2, 2), (3, 3)] {
+            assert!(detector
+                .check_at(&msg(xid, chaddr, Some(b"port1")), now)
+                .is_none());
+        }
+        // another circuit on the same relay
+        assert!(detector.check_at(&msg(4, 4, Some(b"port2")), now).is_none());
+        let finding = detector.check_at(&msg(5, 4, Some(b"port1")), now).unwrap();
+        assert_eq!(
+            finding.kind,
+            FindingKind::MacRotation {
+                giaddr: [10, 0, 0, 1].into(),
+                circuit_id: b"port1".to_vec(),
+            }
+        );
+        assert_eq!(finding.chaddrs, 4);
+        // not relayed, not tracked by circuit
+        let detector = blocking();
+        for chaddr in 1..10 {
+            assert!(detector
+                .check_at(&msg(chaddr.into(), chaddr, None), now)
+                .is_none());
+        }
+    }
+}
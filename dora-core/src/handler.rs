@@ -24,6 +24,11 @@ pub trait Plugin<T>: Send + Sync + 'static {
     /// CANCEL-SAFETY: everything in handle must be cancel-safe. A top-level timeout can possibly kill this
     /// method
     async fn handle(&self, ctx: &mut MsgContext<T>) -> Result<Action>;
+
+    /// name used for this plugin's span in the handler chain
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 }
 
 /// A handler that is run after the response is returned. This moves the
@@ -29,10 +29,15 @@ pub use crate::server::Server;
 
 pub mod config;
 pub mod env;
+pub mod garbage;
 pub mod handler;
 pub mod metrics;
 pub mod prelude;
+#[cfg(target_os = "linux")]
+pub mod privs;
 pub mod server;
+pub mod systemd;
+pub mod trace_filter;
 
 /// Register a plugin with the server
 pub trait Register<T> {
@@ -3,10 +3,13 @@
 //! # metrics
 //!
 //! contains statistics for server metrics
-use std::time::Instant;
+use std::{net::Ipv4Addr, time::Instant};
 
 use lazy_static::lazy_static;
-use prometheus::{register_int_counter_vec, register_int_gauge, IntCounterVec, IntGauge};
+use prometheus::{
+    core::Collector, exponential_buckets, register_histogram, register_int_counter,
+    register_int_counter_vec, register_int_gauge, Histogram, IntCounter, IntCounterVec, IntGauge,
+};
 use prometheus_static_metric::make_static_metric;
 
 make_static_metric! {
@@ -87,12 +90,111 @@ lazy_static! {
     )
     .unwrap();
 
+    /// recv'd message types by instance, only counted when running with `instances`
+    pub static ref INSTANCE_RECV_COUNT_VEC: IntCounterVec = register_int_counter_vec!(
+        "instance_recv_type_counts",
+        "Recv Type Counts by instance",
+        &["instance", "message_type"]
+    )
+    .unwrap();
+    /// sent message types by instance, only counted when running with `instances`
+    pub static ref INSTANCE_SENT_COUNT_VEC: IntCounterVec = register_int_counter_vec!(
+        "instance_sent_type_counts",
+        "Sent Type Counts by instance",
+        &["instance", "message_type"]
+    )
+    .unwrap();
+
     /// aggregate count of all recv'd messages types
     pub static ref V6_RECV_TYPE_COUNT: V6RecvStats = V6RecvStats::from(&V6_RECV_COUNT_VEC);
 
     /// aggregate count of all sent messages types
     pub static ref V6_SENT_TYPE_COUNT: V6SentStats = V6SentStats::from(&V6_SENT_COUNT_VEC);
 
+    /// v6 status codes sent, by status
+    pub static ref V6_STATUS_COUNT_VEC: IntCounterVec = register_int_counter_vec!(
+        "v6_status_codes",
+        "V6 Status Code Counts",
+        &["status"]
+    )
+    .unwrap();
+
+    /// ACKs & NAKs sent & DECLINEs recv'd by network. `instance` is empty when
+    /// not running with `instances`. Saved to the lease database & restored on start
+    pub static ref NETWORK_COUNT_VEC: IntCounterVec = register_int_counter_vec!(
+        "network_type_counts",
+        "Message Type Counts by network",
+        &["instance", "network", "message_type"]
+    )
+    .unwrap();
+
+    /// # of msgs dropped because the relay (giaddr) isn't allowed for the network
+    pub static ref RELAY_DROPPED: IntCounter =
+        register_int_counter!("relay_dropped", "count of messages dropped from relays not in allowed_relays").unwrap();
+
+    /// REQUESTs by the client state they were sent from
+    pub static ref REQUEST_STATE_COUNT: IntCounterVec = register_int_counter_vec!(
+        "request_state_counts",
+        "count of REQUESTs by client state",
+        &["state"]
+    )
+    .unwrap();
+
+    /// # of REQUESTs ignored because the client selected another server
+    pub static ref REQUEST_OTHER_SERVER: IntCounter =
+        register_int_counter!("request_other_server", "count of REQUESTs ignored because the client selected another server").unwrap();
+
+    /// # of msgs from clients flagged by a garbage client heuristic
+    pub static ref GARBAGE_CLIENT_FLAGGED: IntCounterVec = register_int_counter_vec!(
+        "garbage_client_flagged",
+        "count of messages from clients flagged by a garbage client heuristic",
+        &["kind"]
+    )
+    .unwrap();
+
+    /// # of msgs dropped from flagged garbage clients
+    pub static ref GARBAGE_CLIENT_BLOCKED: IntCounter =
+        register_int_counter!("garbage_client_blocked", "count of messages dropped from flagged garbage clients").unwrap();
+
+    /// # of OFFERs re-sent for retransmitted DISCOVERs
+    pub static ref OFFER_RESENT: IntCounter =
+        register_int_counter!("offer_resent", "count of cached offers re-sent for retransmitted DISCOVERs").unwrap();
+
+    /// # of OFFERs that expired without a REQUEST
+    pub static ref OFFER_EXPIRED: IntCounter =
+        register_int_counter!("offer_expired", "count of offers that expired before the client sent REQUEST").unwrap();
+
+    /// # of msgs dropped because an interface's ingress queue was full
+    pub static ref INGRESS_DROPPED: IntCounterVec = register_int_counter_vec!(
+        "ingress_dropped",
+        "count of messages dropped from a full ingress queue",
+        &["interface"]
+    )
+    .unwrap();
+
+    /// transactions over the latency budget, by their slowest stage
+    pub static ref SLOW_STAGE_COUNT: IntCounterVec = register_int_counter_vec!(
+        "slow_transaction_stages",
+        "count of transactions over the latency budget by slowest stage",
+        &["stage"]
+    )
+    .unwrap();
+
+    /// lease writes per group commit, only observed with `db_batch_size`
+    pub static ref DB_BATCH_SIZE: Histogram = register_histogram!(
+        "lease_batch_size",
+        "lease writes committed per batch",
+        exponential_buckets(1.0, 2.0, 10).unwrap()
+    )
+    .unwrap();
+
+    /// seconds taken to run & commit a batch of lease writes
+    pub static ref DB_BATCH_COMMIT: Histogram = register_histogram!(
+        "lease_batch_commit_seconds",
+        "time to run & commit a batch of lease writes"
+    )
+    .unwrap();
+
     /// # of in flight msgs
     pub static ref IN_FLIGHT: IntGauge =
         register_int_gauge!("in_flight", "count of currently processing messages").unwrap();
@@ -114,3 +216,34 @@ lazy_static! {
     /// server uptime
     pub static ref UPTIME: IntGauge = register_int_gauge!("uptime", "server uptime (seconds)").unwrap();
 }
+
+/// subnet of the network a v4 message belongs to. Plugins set this as a local
+/// on the `MsgContext` so the message is counted in [`NETWORK_COUNT_VEC`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatchedNetwork(pub Ipv4Addr);
+
+/// the [`NETWORK_COUNT_VEC`] counts of `instance` as (network, message type, count)
+pub fn network_counts(instance: &str) -> Vec<(String, String, u64)> {
+    NETWORK_COUNT_VEC
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .filter_map(|metric| {
+            let label = |name: &str| {
+                metric
+                    .get_label()
+                    .iter()
+                    .find(|label| label.get_name() == name)
+                    .map(|label| label.get_value())
+            };
+            if label("instance")? != instance {
+                return None;
+            }
+            Some((
+                label("network")?.to_owned(),
+                label("message_type")?.to_owned(),
+                metric.get_counter().get_value() as u64,
+            ))
+        })
+        .collect()
+}
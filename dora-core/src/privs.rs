@@ -0,0 +1,210 @@
+//! drop root privileges once dora's sockets are bound, keeping only
+//! [`KEPT_CAPS`] so privileged ports & raw/ICMP sockets can still be opened.
+//!
+//! libc applies setgroups/setgid/setuid to every thread of the process, but
+//! capabilities belong to each thread. The switch is made while every runtime
+//! worker waits on a barrier with PR_SET_KEEPCAPS set, then each worker trims
+//! its own sets to [`KEPT_CAPS`] before running anything else. Threads outside
+//! the workers (the blocking pool, `main`) lose every capability.
+use std::{
+    ffi::CString,
+    io, mem,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Barrier,
+    },
+};
+
+use anyhow::{bail, Context, Result};
+use caps::{CapSet, Capability, CapsHashSet};
+use tracing::{info, warn};
+
+/// capabilities kept after switching user
+pub const KEPT_CAPS: [Capability; 2] = [Capability::CAP_NET_BIND_SERVICE, Capability::CAP_NET_RAW];
+
+/// Switch to `user`/`group`, keeping only [`KEPT_CAPS`]. If `group` is not
+/// provided, the primary group of `user` is used. The `owned` files that
+/// exist, ex. a lease database created as root, are given to `user` first so
+/// it can still write them. `workers` must be the number of worker threads of
+/// the runtime this is called on, or it won't return.
+pub async fn drop_privileges(
+    user: &str,
+    group: Option<&str>,
+    owned: &[PathBuf],
+    workers: usize,
+) -> Result<()> {
+    if unsafe { libc::geteuid() } != 0 {
+        warn!(?user, "not running as root, unable to change user");
+        return Ok(());
+    }
+    let (uid, user_gid) = lookup_user(user)?;
+    let gid = match group {
+        Some(group) => lookup_group(group)?,
+        None => user_gid,
+    };
+
+    for path in owned {
+        chown(path, uid, gid).with_context(|| format!("failed to chown {}", path.display()))?;
+    }
+
+    // a worker blocked on the barrier runs no other task, so each one runs
+    // exactly one of these
+    let barrier = Arc::new(Barrier::new(workers));
+    let switched = Arc::new(AtomicBool::new(false));
+    let tasks = (0..workers)
+        .map(|_| {
+            let barrier = Arc::clone(&barrier);
+            let switched = Arc::clone(&switched);
+            tokio::spawn(async move { on_worker(&barrier, &switched, uid, gid) })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.context("privilege drop panicked")??;
+    }
+
+    info!(?user, ?uid, ?gid, caps = ?KEPT_CAPS, "dropped privileges");
+    Ok(())
+}
+
+/// keep this thread's capabilities across the switch made by one of the
+/// workers, then trim them to [`KEPT_CAPS`]. Every worker waits on both
+/// barriers whatever fails
+fn on_worker(
+    barrier: &Barrier,
+    switched: &AtomicBool,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+) -> Result<()> {
+    let keep = set_keepcaps(true).context("failed to set PR_SET_KEEPCAPS");
+    let switch = barrier.wait().is_leader().then(|| {
+        let res = switch_ids(uid, gid);
+        switched.store(res.is_ok(), Ordering::SeqCst);
+        res
+    });
+    barrier.wait();
+    let kept = if switched.load(Ordering::SeqCst) {
+        keep_only()
+    } else {
+        Ok(())
+    };
+    set_keepcaps(false).context("failed to clear PR_SET_KEEPCAPS")?;
+    keep?;
+    switch.transpose()?;
+    kept
+}
+
+fn set_keepcaps(keep: bool) -> io::Result<()> {
+    if unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, keep as libc::c_ulong, 0, 0, 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn switch_ids(uid: libc::uid_t, gid: libc::gid_t) -> Result<()> {
+    if unsafe { libc::setgroups(1, &gid) } == -1 {
+        return Err(io::Error::last_os_error()).context("failed to set supplementary groups");
+    }
+    if unsafe { libc::setgid(gid) } == -1 {
+        return Err(io::Error::last_os_error()).with_context(|| format!("failed to setgid {gid}"));
+    }
+    if unsafe { libc::setuid(uid) } == -1 {
+        return Err(io::Error::last_os_error()).with_context(|| format!("failed to setuid {uid}"));
+    }
+    Ok(())
+}
+
+/// setuid cleared the effective set & PR_SET_KEEPCAPS kept the permitted one
+fn keep_only() -> Result<()> {
+    let kept = KEPT_CAPS.into_iter().collect::<CapsHashSet>();
+    caps::set(None, CapSet::Permitted, &kept).context("failed to set permitted caps")?;
+    caps::set(None, CapSet::Effective, &kept).context("failed to set effective caps")?;
+    caps::clear(None, CapSet::Inheritable).context("failed to clear inheritable caps")?;
+    let permitted = caps::read(None, CapSet::Permitted).context("failed to read permitted caps")?;
+    if permitted != kept {
+        bail!("capabilities {permitted:?} were kept after switching user");
+    }
+    Ok(())
+}
+
+fn chown(path: &Path, uid: libc::uid_t, gid: libc::gid_t) -> io::Result<()> {
+    let name = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::chown(name.as_ptr(), uid, gid) } == -1 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::NotFound {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+fn lookup_user(user: &str) -> Result<(libc::uid_t, libc::gid_t)> {
+    let name = CString::new(user).context("invalid user name")?;
+    let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buf = [0 as libc::c_char; 4096];
+    let mut found = ptr::null_mut();
+    // the reentrant version, other threads may be running
+    let ret = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret))
+            .with_context(|| format!("failed to look up user {user}"));
+    }
+    if found.is_null() {
+        bail!("user {user} not found");
+    }
+    Ok((pwd.pw_uid, pwd.pw_gid))
+}
+
+fn lookup_group(group: &str) -> Result<libc::gid_t> {
+    let name = CString::new(group).context("invalid group name")?;
+    let mut grp: libc::group = unsafe { mem::zeroed() };
+    let mut buf = [0 as libc::c_char; 4096];
+    let mut found = ptr::null_mut();
+    let ret = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut grp,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::from_raw_os_error(ret))
+            .with_context(|| format!("failed to look up group {group}"));
+    }
+    if found.is_null() {
+        bail!("group {group} not found");
+    }
+    Ok(grp.gr_gid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_root() {
+        assert_eq!(lookup_user("root").unwrap(), (0, 0));
+        assert_eq!(lookup_group("root").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_lookup_unknown() {
+        let err = lookup_user("dora-no-such-user").unwrap_err();
+        assert_eq!(err.to_string(), "user dora-no-such-user not found");
+        let err = lookup_group("dora-no-such-group").unwrap_err();
+        assert_eq!(err.to_string(), "group dora-no-such-group not found");
+        assert!(lookup_user("nul\0byte").is_err());
+    }
+}
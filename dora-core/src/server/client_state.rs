@@ -0,0 +1,205 @@
+//! DHCPv4 client state of a REQUEST
+//!
+//! A client sends REQUEST from several states and the server must treat each
+//! differently, [RFC 2131 section 4.3.2]:
+//!
+//! | state       | server id | requested ip | ciaddr  | sent      |
+//! |-------------|-----------|--------------|---------|-----------|
+//! | SELECTING   | MUST      | MUST         | zero    | broadcast |
+//! | INIT-REBOOT | MUST NOT  | MUST         | zero    | broadcast |
+//! | RENEWING    | MUST NOT  | MUST NOT     | address | unicast   |
+//! | REBINDING   | MUST NOT  | MUST NOT     | address | broadcast |
+//!
+//! [RFC 2131 section 4.3.2]: https://www.rfc-editor.org/rfc/rfc2131#section-4.3.2
+use dhcproto::v4::{DhcpOption, Message, MessageType, OptionCode};
+
+use std::net::Ipv4Addr;
+
+use crate::metrics::REQUEST_STATE_COUNT;
+
+/// the state a client sent a REQUEST from
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ClientState {
+    /// responding to an OFFER from the server in `server_id`
+    Selecting {
+        /// server the client selected
+        server_id: Ipv4Addr,
+        /// address from the OFFER
+        requested_ip: Ipv4Addr,
+    },
+    /// verifying a previously allocated address after a reboot
+    InitReboot {
+        /// previously allocated address
+        requested_ip: Ipv4Addr,
+    },
+    /// extending a lease, unicast to the server that granted it
+    Renewing {
+        /// leased address
+        ciaddr: Ipv4Addr,
+    },
+    /// extending a lease with any server, after T2
+    Rebinding {
+        /// leased address
+        ciaddr: Ipv4Addr,
+    },
+    /// doesn't match any state, ex. no requested IP & no ciaddr
+    Invalid,
+}
+
+impl ClientState {
+    /// determine the state of a REQUEST. `broadcast` is whether the client
+    /// sent it to a broadcast address, relayed messages are always treated as
+    /// broadcast. Returns `None` if `msg` is not a REQUEST
+    pub fn new(msg: &Message, broadcast: bool) -> Option<Self> {
+        if msg.opts().msg_type() != Some(MessageType::Request) {
+            return None;
+        }
+        let ciaddr = msg.ciaddr();
+        let server_id = match msg.opts().get(OptionCode::ServerIdentifier) {
+            Some(DhcpOption::ServerIdentifier(id)) if !id.is_unspecified() => Some(*id),
+            _ => None,
+        };
+        let requested_ip = match msg.opts().get(OptionCode::RequestedIpAddress) {
+            Some(DhcpOption::RequestedIpAddress(ip)) if !ip.is_unspecified() => Some(*ip),
+            _ => None,
+        };
+        // ciaddr is only filled in once the client is BOUND. Some clients
+        // include a server id when renewing anyway, so ciaddr decides
+        Some(if !ciaddr.is_unspecified() {
+            if broadcast || !msg.giaddr().is_unspecified() {
+                ClientState::Rebinding { ciaddr }
+            } else {
+                ClientState::Renewing { ciaddr }
+            }
+        } else {
+            match (server_id, requested_ip) {
+                (Some(server_id), Some(requested_ip)) => ClientState::Selecting {
+                    server_id,
+                    requested_ip,
+                },
+                (None, Some(requested_ip)) => ClientState::InitReboot { requested_ip },
+                _ => ClientState::Invalid,
+            }
+        })
+    }
+
+    /// address the client is asking for
+    pub fn requested_ip(&self) -> Option<Ipv4Addr> {
+        match *self {
+            ClientState::Selecting { requested_ip, .. }
+            | ClientState::InitReboot { requested_ip } => Some(requested_ip),
+            ClientState::Renewing { ciaddr } | ClientState::Rebinding { ciaddr } => Some(ciaddr),
+            ClientState::Invalid => None,
+        }
+    }
+
+    /// is the client selecting an OFFER from a server other than `server_id`?
+    pub fn selected_other(&self, server_id: Ipv4Addr) -> bool {
+        matches!(*self, ClientState::Selecting { server_id: id, .. } if id != server_id)
+    }
+
+    /// name of the state, used as the metrics label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientState::Selecting { .. } => "selecting",
+            ClientState::InitReboot { .. } => "init_reboot",
+            ClientState::Renewing { .. } => "renewing",
+            ClientState::Rebinding { .. } => "rebinding",
+            ClientState::Invalid => "invalid",
+        }
+    }
+
+    /// count the REQUEST in the per-state metrics
+    pub fn inc_metric(&self) {
+        REQUEST_STATE_COUNT
+            .with_label_values(&[self.as_str()])
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(ciaddr: Ipv4Addr, opts: &[DhcpOption]) -> Message {
+        let mut msg = Message::new(
+            ciaddr,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            &[0, 1, 2, 3, 4, 5],
+        );
+        msg.opts_mut()
+            .insert(DhcpOption::MessageType(MessageType::Request));
+        for opt in opts {
+            msg.opts_mut().insert(opt.clone());
+        }
+        msg
+    }
+
+    #[test]
+    fn test_client_state() {
+        let server = Ipv4Addr::new(192, 168, 0, 1);
+        let ip = Ipv4Addr::new(192, 168, 0, 10);
+        let zero = Ipv4Addr::UNSPECIFIED;
+
+        let selecting = request(
+            zero,
+            &[
+                DhcpOption::ServerIdentifier(server),
+                DhcpOption::RequestedIpAddress(ip),
+            ],
+        );
+        let state = ClientState::new(&selecting, true).unwrap();
+        assert_eq!(
+            state,
+            ClientState::Selecting {
+                server_id: server,
+                requested_ip: ip
+            }
+        );
+        assert_eq!(state.requested_ip(), Some(ip));
+        assert!(!state.selected_other(server));
+        assert!(state.selected_other(Ipv4Addr::new(192, 168, 0, 2)));
+
+        let reboot = request(zero, &[DhcpOption::RequestedIpAddress(ip)]);
+        let state = ClientState::new(&reboot, true).unwrap();
+        assert_eq!(state, ClientState::InitReboot { requested_ip: ip });
+        assert!(!state.selected_other(Ipv4Addr::new(192, 168, 0, 2)));
+
+        // ciaddr wins over a server id
+        let renew = request(ip, &[DhcpOption::ServerIdentifier(server)]);
+        assert_eq!(
+            ClientState::new(&renew, false),
+            Some(ClientState::Renewing { ciaddr: ip })
+        );
+        assert_eq!(
+            ClientState::new(&renew, true),
+            Some(ClientState::Rebinding { ciaddr: ip })
+        );
+        // relayed renewals were broadcast by the client
+        let mut relayed = request(ip, &[]);
+        relayed.set_giaddr(Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(
+            ClientState::new(&relayed, false),
+            Some(ClientState::Rebinding { ciaddr: ip })
+        );
+
+        // server id but nothing requested
+        let invalid = request(zero, &[DhcpOption::ServerIdentifier(server)]);
+        let state = ClientState::new(&invalid, true).unwrap();
+        assert_eq!(state, ClientState::Invalid);
+        assert_eq!(state.requested_ip(), None);
+        assert_eq!(
+            ClientState::new(&request(zero, &[]), true),
+            Some(ClientState::Invalid)
+        );
+
+        // not a REQUEST
+        let mut discover = request(zero, &[]);
+        discover
+            .opts_mut()
+            .insert(DhcpOption::MessageType(MessageType::Discover));
+        assert_eq!(ClientState::new(&discover, true), None);
+    }
+}
@@ -2,20 +2,27 @@
 use chrono::{DateTime, Utc};
 use dhcproto::{v4, v6, Decodable, Decoder, Encodable};
 use pnet::ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
-use tracing::{error, trace};
+use tracing::{error, trace, Span};
 use unix_udp_sock::RecvMeta;
 
 use std::{
     fmt,
     io::{self, Error, ErrorKind},
-    net::{Ipv4Addr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     sync::Arc,
     time::Duration,
 };
 
 use crate::{
+    config::cli::ReplyPorts,
     metrics::{RECV_TYPE_COUNT, SENT_TYPE_COUNT, V6_RECV_TYPE_COUNT, V6_SENT_TYPE_COUNT},
-    server::{msg::SerialMsg, typemap::TypeMap, State},
+    server::{
+        client_state::ClientState,
+        msg::{ReplyOptions, SerialMsg},
+        relay::RelayInfo,
+        typemap::TypeMap,
+        State,
+    },
 };
 
 /// Context is what will be passed to the [handler] traits and mutated by
@@ -52,6 +59,8 @@ pub struct MsgContext<T> {
     interface: Option<IpNetwork>,
     /// global unicast address
     global: Option<IpNetwork>,
+    /// span covering the whole request, from decode to post-response
+    span: Span,
 }
 
 impl<T: fmt::Debug> fmt::Debug for MsgContext<T> {
@@ -83,6 +92,16 @@ impl<T> MsgContext<T> {
         self.id
     }
 
+    /// the span covering this request, plugins can record fields on it
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// set the request span
+    pub(crate) fn set_span(&mut self, span: Span) {
+        self.span = span;
+    }
+
     /// Get the `SerialMsg` bytes by shared ref
     pub fn bytes(&self) -> &[u8] {
         self.msg.bytes()
@@ -182,6 +201,13 @@ impl<T> MsgContext<T> {
         self.dst_addr = Some(addr);
     }
 
+    /// Mark this as counting towards `state.live_msgs`, the caller must have
+    /// already incremented the counter. Messages are not live while they wait
+    /// in the ingress queue
+    pub(crate) fn mark_as_live(&mut self) {
+        self.is_live = true;
+    }
+
     /// Decrement the `state.live_msgs` counter and mark this as not live
     /// This gets done before passing the `MsgContext` to the postresponse
     /// plugins.
@@ -201,7 +227,12 @@ impl<T: Encodable + Decodable> MsgContext<T> {
             T::decode(&mut decoder).map_err(|op| io::Error::new(io::ErrorKind::InvalidData, op))?
         };
 
-        Ok(Self {
+        Ok(Self::from_decoded(msg, decoded_msg, meta, state))
+    }
+
+    /// Create a new context where `msg` has already been decoded
+    fn from_decoded(msg: SerialMsg, decoded_msg: T, meta: RecvMeta, state: Arc<State>) -> Self {
+        Self {
             msg,
             src_addr: meta.addr,
             meta,
@@ -212,10 +243,11 @@ impl<T: Encodable + Decodable> MsgContext<T> {
             decoded_resp_msg: None,
             id: state.inc_id(),
             state,
-            is_live: true,
+            is_live: false,
             interface: None,
             global: None,
-        })
+            span: Span::none(),
+        }
     }
 
     /// Decode the currently held binary data in `resp_msg` using [`Decoder`] into a message.
@@ -273,6 +305,47 @@ impl<T: Encodable + Decodable> MsgContext<T> {
     }
 }
 
+/// decode a received datagram into a [`MsgContext`]
+pub(crate) trait FromDatagram: Sized {
+    fn from_datagram(
+        msg: SerialMsg,
+        meta: RecvMeta,
+        state: Arc<State>,
+    ) -> io::Result<MsgContext<Self>>;
+}
+
+impl FromDatagram for v4::Message {
+    fn from_datagram(
+        msg: SerialMsg,
+        meta: RecvMeta,
+        state: Arc<State>,
+    ) -> io::Result<MsgContext<Self>> {
+        MsgContext::new(msg, meta, state)
+    }
+}
+
+impl FromDatagram for v6::Message {
+    /// Relay-Forward messages are unwrapped to the client message, the relay
+    /// layers are stored as a [`RelayInfo`] local
+    fn from_datagram(
+        msg: SerialMsg,
+        meta: RecvMeta,
+        state: Arc<State>,
+    ) -> io::Result<MsgContext<Self>> {
+        let (info, decoded) = match RelayInfo::unwrap(msg.bytes())? {
+            None => return MsgContext::new(msg, meta, state),
+            Some((info, inner)) => (
+                info,
+                v6::Message::from_bytes(inner)
+                    .map_err(|op| io::Error::new(io::ErrorKind::InvalidData, op))?,
+            ),
+        };
+        let mut ctx = MsgContext::from_decoded(msg, decoded, meta, state);
+        ctx.set_local(info);
+        Ok(ctx)
+    }
+}
+
 // v4 specific functions
 impl MsgContext<v4::Message> {
     /// get the interface for the message. this should always be set
@@ -282,6 +355,18 @@ impl MsgContext<v4::Message> {
             _ => None,
         })
     }
+    /// the client state a REQUEST was sent from, `None` for other message types.
+    /// A message is broadcast if it was sent to the limited broadcast address
+    /// or the broadcast address of the interface
+    pub fn client_state(&self) -> Option<ClientState> {
+        let broadcast = match self.meta.dst_ip {
+            Some(IpAddr::V4(ip)) => {
+                ip.is_broadcast() || matches!(self.interface(), Some(int) if int.broadcast() == ip)
+            }
+            _ => false,
+        };
+        ClientState::new(self.decoded_msg(), broadcast)
+    }
     /// determine the response addr based on request. Sets response giaddr
     /// if we are talking to a relay. Injects into ARP cache if response will be
     /// unicast to yiaddr.
@@ -305,9 +390,12 @@ impl MsgContext<v4::Message> {
     //
     // 5. In all cases, when 'giaddr' is zero, the server broadcasts any NAK
     // messages to 0xffffffff.
+    //
+    /// `ports` are the destination ports used for the reply, if `None` the
+    /// response is unicast back to the source ip:port
     pub fn resp_addr(
         &mut self,
-        default_port: bool,
+        ports: Option<ReplyPorts>,
         // device: Option<&str>,
         soc: socket2::SockRef<'_>,
     ) -> SocketAddr {
@@ -324,25 +412,29 @@ impl MsgContext<v4::Message> {
         let yiaddr = self.decoded_resp_msg().map(|msg| msg.yiaddr());
         // TODO: set siaddr (dnsmasq does this)? ciaddr?
 
-        if !default_port {
-            trace!("using non-default port for response");
-            // if we are not on the default v4 port, send the response
-            // back to the source ip:port as unicast.
-            // This is useful for testing
-            self.msg().addr()
-        } else if !giaddr_zero {
+        let ReplyPorts { client, relay } = match ports {
+            Some(ports) => ports,
+            None => {
+                trace!("using non-default port for response");
+                // if we are not on the default v4 port, send the response
+                // back to the source ip:port as unicast.
+                // This is useful for testing
+                return self.msg().addr();
+            }
+        };
+        if !giaddr_zero {
             // relay situation: giaddr nonzero
             // use giaddr
             trace!("responding using giaddr");
             self.decoded_resp_msg
                 .as_mut()
                 .map(|resp| resp.set_giaddr(giaddr));
-            (giaddr, v4::SERVER_PORT).into()
+            (giaddr, relay).into()
         } else if !ciaddr_zero {
             // giaddr zero, ciaddr nonzero
             trace!("responding using ciaddr");
             // use ciaddr
-            (ciaddr, v4::CLIENT_PORT).into()
+            (ciaddr, client).into()
         } else if !broadcast && matches!(yiaddr, Some(ip) if !ip.is_unspecified()) {
             // broadcast false and yiaddr exists
             // INJECT yiaddr IN ARP CACHE:
@@ -359,16 +451,16 @@ impl MsgContext<v4::Message> {
                     "failed to inject into ARP cache-- fall back to broadcast"
                 );
 
-                (Ipv4Addr::BROADCAST, v4::CLIENT_PORT).into()
+                (Ipv4Addr::BROADCAST, client).into()
             } else {
-                (yiaddr, v4::CLIENT_PORT).into()
+                (yiaddr, client).into()
             }
         } else {
             // broadcast set & giaddr/ciaddr zero
             // OR
             // otherwise just broadcast
             trace!("use broadcast addr");
-            (Ipv4Addr::BROADCAST, v4::CLIENT_PORT).into()
+            (Ipv4Addr::BROADCAST, client).into()
         }
     }
 
@@ -587,13 +679,18 @@ impl MsgContext<v4::Message> {
     }
 
     /// looks in `decoded_msg` for `DhcpOption::ParameterRequestList` and provides any options
-    /// in `decoded_resp_msg` that match both in `opts` and in the param req list
+    /// in `decoded_resp_msg` that match both in `opts` and in the param req list. A
+    /// [`ReplyOptions`] local changes which of `opts` are sent.
     ///
     /// Copies over options from request that should be present on response
     /// Also, looks at `interface` and adds subnetmask/broadcast. If provided by `param_opts`
     /// these will be overwritten.
     pub fn populate_opts(&mut self, param_opts: &v4::DhcpOptions) -> Option<()> {
         use dhcproto::v4::{DhcpOption, OptionCode};
+        let filter = self
+            .get_local::<ReplyOptions>()
+            .cloned()
+            .unwrap_or_default();
         // https://datatracker.ietf.org/doc/html/rfc3046#section-2.2
         // copy opt 82 (relay agent) into response
         let resp = self.decoded_resp_msg.as_mut()?;
@@ -605,12 +702,6 @@ impl MsgContext<v4::Message> {
             resp.opts_mut().insert(info.clone());
         }
 
-        // https://datatracker.ietf.org/doc/html/rfc6842#section-3
-        // copy client id
-        if let Some(id) = self.decoded_msg.opts().get(OptionCode::ClientIdentifier) {
-            resp.opts_mut().insert(id.clone());
-        }
-
         // insert router & net mask & broadcast from interface
         // if the config provides these also, they will be overwritten
         if let Some(IpNetwork::V4(interface)) = self.interface {
@@ -622,17 +713,17 @@ impl MsgContext<v4::Message> {
                 .insert(DhcpOption::BroadcastAddr(interface.broadcast()));
         }
 
-        if let Some(DhcpOption::ParameterRequestList(requested)) = self
+        let requested = match self
             .decoded_msg
             .opts()
             .get(OptionCode::ParameterRequestList)
         {
-            // look in the requested list of params
-            for code in requested {
-                // if we have that option, add it to the response
-                if let Some(v) = param_opts.get(*code) {
-                    resp.opts_mut().insert(v.clone());
-                }
+            Some(DhcpOption::ParameterRequestList(requested)) => &requested[..],
+            _ => &[],
+        };
+        for (code, v) in param_opts.iter() {
+            if filter.sends(*code, requested) {
+                resp.opts_mut().insert(v.clone());
             }
         }
         Some(())
@@ -698,18 +789,22 @@ impl MsgContext<v6::Message> {
     }
 
     /// get the response address to send the message to
+    /// if `port` is `None` the response is unicast back to the source ip:port
     pub fn resp_addr(
         &mut self,
-        default_port: bool,
+        port: Option<u16>,
         // soc: socket2::SockRef<'_>,
     ) -> SocketAddr {
-        if !default_port {
-            trace!("using non-default port for response");
-            self.msg().addr()
-        } else {
-            let mut src = self.src_addr();
-            src.set_port(v6::CLIENT_PORT);
-            src
+        match port {
+            None => {
+                trace!("using non-default port for response");
+                self.msg().addr()
+            }
+            Some(port) => {
+                let mut src = self.src_addr();
+                src.set_port(port);
+                src
+            }
         }
     }
 
@@ -939,6 +1034,57 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_reply_options() -> anyhow::Result<()> {
+        let (mut msg, addr, state) = blank_msg()?;
+        msg.opts_mut()
+            .insert(v4::DhcpOption::ParameterRequestList(vec![
+                v4::OptionCode::Router,
+            ]));
+        let mut opts = v4::DhcpOptions::default();
+        opts.insert(v4::DhcpOption::Router(vec![[1, 2, 3, 4].into()]));
+        opts.insert(v4::DhcpOption::DomainNameServer(vec![[1, 2, 3, 4].into()]));
+        opts.insert(v4::DhcpOption::DomainName("example.com".to_owned()));
+        // codes of the options added to the reply with `filter`
+        let reply = |filter: Option<ReplyOptions>| -> anyhow::Result<Vec<v4::OptionCode>> {
+            let meta = RecvMeta {
+                addr,
+                ..RecvMeta::default()
+            };
+            let mut ctx = MsgContext::<v4::Message>::new(
+                SerialMsg::new(Bytes::from(msg.to_vec()?), addr),
+                meta,
+                state.clone(),
+            )?;
+            ctx.decoded_resp_msg = Some(v4::Message::default());
+            if let Some(filter) = filter {
+                ctx.set_local(filter);
+            }
+            ctx.populate_opts(&opts);
+            let mut codes = ctx
+                .decoded_resp_msg()
+                .unwrap()
+                .opts()
+                .iter()
+                .map(|(code, _)| *code)
+                .collect::<Vec<_>>();
+            codes.sort_by_key(|code| u8::from(*code));
+            Ok(codes)
+        };
+        use v4::OptionCode::*;
+        assert_eq!(reply(None)?, [Router]);
+        assert_eq!(reply(Some(ReplyOptions::Requested))?, [Router]);
+        assert_eq!(
+            reply(Some(ReplyOptions::Mandatory(vec![DomainName])))?,
+            [Router, DomainName]
+        );
+        assert_eq!(
+            reply(Some(ReplyOptions::All))?,
+            [Router, DomainNameServer, DomainName]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_relay_agent_resp() -> anyhow::Result<()> {
         let (mut msg, addr, state) = blank_msg()?;
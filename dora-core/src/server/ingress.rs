@@ -0,0 +1,140 @@
+//! Bounded per-interface queues that sit between the UDP socket and the tasks
+//! handling each message.
+//!
+//! Messages are read off the socket as fast as they arrive and pushed onto the
+//! queue for the interface they arrived on. When a queue is full the oldest
+//! message is dropped: under a broadcast storm the oldest messages are the ones
+//! most likely to have timed out on the client already. Queues are drained round
+//! robin, so a storm on one interface doesn't starve the others.
+use tokio::sync::Notify;
+
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Bounded drop-oldest queues keyed by interface index
+#[derive(Debug)]
+pub(crate) struct Ingress<T> {
+    inner: Mutex<Queues<T>>,
+    notify: Notify,
+    /// max messages held per interface
+    capacity: usize,
+}
+
+#[derive(Debug)]
+struct Queues<T> {
+    queues: Vec<(u32, VecDeque<T>)>,
+    /// queue to pop from next
+    next: usize,
+    closed: bool,
+}
+
+impl<T> Ingress<T> {
+    /// Create queues which each hold at most `capacity` messages
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Queues {
+                queues: Vec::new(),
+                next: 0,
+                closed: false,
+            }),
+            notify: Notify::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Push a message received on `ifindex`. If that interface's queue is full
+    /// the oldest message is removed and returned
+    pub(crate) fn push(&self, ifindex: u32, msg: T) -> Option<T> {
+        let dropped = {
+            let mut inner = self.inner.lock().unwrap();
+            let pos = match inner.queues.iter().position(|(idx, _)| *idx == ifindex) {
+                Some(pos) => pos,
+                None => {
+                    inner.queues.push((ifindex, VecDeque::new()));
+                    inner.queues.len() - 1
+                }
+            };
+            let queue = &mut inner.queues[pos].1;
+            let dropped = if queue.len() >= self.capacity {
+                queue.pop_front()
+            } else {
+                None
+            };
+            queue.push_back(msg);
+            dropped
+        };
+        self.notify.notify_one();
+        dropped
+    }
+
+    /// No more messages will be pushed, `pop` returns `None` once the queues
+    /// are empty
+    pub(crate) fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    /// Wait for the next message
+    pub(crate) async fn pop(&self) -> Option<T> {
+        loop {
+            {
+                let mut inner = self.inner.lock().unwrap();
+                if let Some(msg) = inner.try_pop() {
+                    return Some(msg);
+                }
+                if inner.closed {
+                    return None;
+                }
+            }
+            // `notify_one` stores a permit if nothing is waiting, so a push
+            // between releasing the lock & here isn't missed
+            self.notify.notified().await;
+        }
+    }
+}
+
+impl<T> Queues<T> {
+    fn try_pop(&mut self) -> Option<T> {
+        let len = self.queues.len();
+        for i in 0..len {
+            let pos = (self.next + i) % len;
+            if let Some(msg) = self.queues[pos].1.pop_front() {
+                self.next = (pos + 1) % len;
+                return Some(msg);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let ingress = Ingress::new(2);
+        assert_eq!(ingress.push(1, 1), None);
+        assert_eq!(ingress.push(1, 2), None);
+        assert_eq!(ingress.push(1, 3), Some(1));
+        ingress.close();
+        assert_eq!(ingress.pop().await, Some(2));
+        assert_eq!(ingress.pop().await, Some(3));
+        assert_eq!(ingress.pop().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_round_robin() {
+        let ingress = Ingress::new(10);
+        for i in 0..3 {
+            ingress.push(1, i);
+        }
+        ingress.push(2, 10);
+        ingress.push(2, 11);
+        ingress.close();
+        let mut order = Vec::new();
+        while let Some(msg) = ingress.pop().await {
+            order.push(msg);
+        }
+        assert_eq!(order, [0, 10, 1, 11, 2]);
+    }
+}
@@ -56,6 +56,65 @@ pub fn arp_set(
     Ok(())
 }
 
+/// attach a classic BPF filter to `soc` that drops every packet not sent to
+/// this host's link-layer address. Linux delivers broadcast & multicast to all
+/// sockets in a SO_REUSEPORT group, the filter leaves them to one socket
+pub fn unicast_only(soc: SockRef<'_>) -> io::Result<()> {
+    // from linux/filter.h
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JMP_JEQ_K: u16 = 0x15;
+    const BPF_RET_K: u16 = 0x06;
+    const SKF_AD_OFF: i32 = -0x1000;
+    const SKF_AD_PKTTYPE: i32 = 4;
+    const PACKET_HOST: u32 = 0;
+
+    let mut filter = [
+        // A = skb->pkt_type
+        libc::sock_filter {
+            code: BPF_LD_W_ABS,
+            jt: 0,
+            jf: 0,
+            k: (SKF_AD_OFF + SKF_AD_PKTTYPE) as u32,
+        },
+        libc::sock_filter {
+            code: BPF_JMP_JEQ_K,
+            jt: 0,
+            jf: 1,
+            k: PACKET_HOST,
+        },
+        // accept the whole packet
+        libc::sock_filter {
+            code: BPF_RET_K,
+            jt: 0,
+            jf: 0,
+            k: u32::MAX,
+        },
+        libc::sock_filter {
+            code: BPF_RET_K,
+            jt: 0,
+            jf: 0,
+            k: 0,
+        },
+    ];
+    let prog = libc::sock_fprog {
+        len: filter.len() as _,
+        filter: filter.as_mut_ptr(),
+    };
+    let res = unsafe {
+        libc::setsockopt(
+            soc.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ATTACH_FILTER,
+            &prog as *const libc::sock_fprog as *const libc::c_void,
+            std::mem::size_of::<libc::sock_fprog>() as libc::socklen_t,
+        )
+    };
+    if res == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
 /// # Returns
 /// A zeroed out array of size `N` with all the `bytes` copied in.
 ///
@@ -0,0 +1,114 @@
+//! Per-stage timings of a transaction, reported when the transaction takes
+//! longer than the configured latency budget.
+//!
+//! Stages are the plugins in the handler chain, then encoding & sending the
+//! reply. A slow transaction is logged with the time spent in every stage and
+//! counted by its slowest stage in the `slow_transaction_stages` metric.
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+
+use crate::metrics;
+
+/// time spent in each stage of one transaction
+#[derive(Debug)]
+pub(crate) struct Stages {
+    start: Instant,
+    stages: Vec<(&'static str, Duration)>,
+    /// the stage running now & when it started
+    current: Option<(&'static str, Instant)>,
+}
+
+impl Stages {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            stages: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// start timing `stage`, ending the running stage
+    pub(crate) fn begin(&mut self, stage: &'static str) {
+        self.end();
+        self.current = Some((stage, Instant::now()));
+    }
+
+    /// stop timing the running stage
+    pub(crate) fn end(&mut self) {
+        if let Some((stage, start)) = self.current.take() {
+            self.record(stage, start.elapsed());
+        }
+    }
+
+    /// record `elapsed` for `stage`. Plugins are named by their type, the
+    /// module path is dropped
+    fn record(&mut self, stage: &'static str, elapsed: Duration) {
+        let stage = stage.rsplit("::").next().unwrap_or(stage);
+        self.stages.push((stage, elapsed));
+    }
+
+    /// the stage that took the longest
+    fn slowest(&self) -> Option<(&'static str, Duration)> {
+        self.stages
+            .iter()
+            .copied()
+            .max_by_key(|(_, elapsed)| *elapsed)
+    }
+
+    /// log & count the transaction if it took longer than `budget`. A stage
+    /// still running, cut short by the timeout, is ended first
+    pub(crate) fn report(&mut self, budget: Option<Duration>) {
+        self.end();
+        let elapsed = self.start.elapsed();
+        let Some(budget) = budget.filter(|budget| elapsed > *budget) else {
+            return;
+        };
+        // `none` if there were no stages
+        let slowest = self.slowest().map_or("none", |(stage, _)| stage);
+        metrics::SLOW_STAGE_COUNT
+            .with_label_values(&[slowest])
+            .inc();
+        warn!(
+            elapsed_ms = elapsed.as_millis() as u64,
+            budget_ms = budget.as_millis() as u64,
+            slowest,
+            stages = %self,
+            "slow transaction"
+        );
+    }
+}
+
+impl fmt::Display for Stages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (stage, elapsed)) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{stage}={}ms", elapsed.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stages() {
+        let mut stages = Stages::new();
+        stages.record("message_type::MsgType", Duration::from_millis(1));
+        stages.record("leases::Leases", Duration::from_millis(250));
+        stages.record("encode", Duration::from_millis(0));
+        assert_eq!(stages.to_string(), "MsgType=1ms Leases=250ms encode=0ms");
+        assert_eq!(
+            stages.slowest(),
+            Some(("Leases", Duration::from_millis(250)))
+        );
+        assert_eq!(Stages::new().slowest(), None);
+    }
+}
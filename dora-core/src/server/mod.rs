@@ -4,34 +4,56 @@
 //! the handlers/plugins to completion
 use anyhow::{Context, Result};
 use dhcproto::{v4, v6, Decodable, Encodable};
-use pnet::datalink::NetworkInterface;
-use tokio::{sync::mpsc, time};
+use pnet::{datalink::NetworkInterface, ipnetwork::IpNetwork};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time,
+};
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, info, instrument, trace, warn};
-use unix_udp_sock::{Source, Transmit, UdpSocket};
+use tracing::{debug, debug_span, error, info, instrument, trace, warn, Instrument};
+use unix_udp_sock::UdpSocket;
 
 use std::{
     any::{Any, TypeId},
+    collections::HashMap,
     fmt,
     future::Future,
     marker::Send,
+    net::{IpAddr, SocketAddr, SocketAddrV4},
     os::unix::prelude::{FromRawFd, IntoRawFd},
     sync::Arc,
     time::Duration,
 };
 
+pub mod client_state;
 pub mod context;
+pub(crate) mod ingress;
 pub mod ioctl;
+pub(crate) mod latency;
 pub mod msg;
+pub(crate) mod raw;
+pub mod relay;
 pub mod state;
 pub mod typemap;
 pub(crate) mod udp;
+#[cfg(feature = "io-uring")]
+pub(crate) mod uring;
 
 use crate::{
-    config::cli::{Config, ALL_DHCP_RELAY_AGENTS_AND_SERVERS},
+    config::cli::{Config, IoBackend, ALL_DHCP_RELAY_AGENTS_AND_SERVERS},
     handler::*,
-    server::{context::MsgContext, msg::SerialMsg, udp::UdpStream},
+    metrics,
+    server::{
+        context::MsgContext,
+        ingress::Ingress,
+        latency::Stages,
+        msg::{encode_v4_dropped, ReplyEncoding, ReplyRules, SerialMsg},
+        raw::RawSocket,
+        relay::RelayInfo,
+        udp::{Reply, ReplySource, UdpStream},
+    },
+    trace_filter::{self, Traced},
 };
 use topo_sort::DependencyTree;
 
@@ -53,6 +75,12 @@ pub struct Server<T> {
     /// server config
     config: Config,
     interfaces: Vec<NetworkInterface>,
+    /// an already bound socket to listen on instead of binding one
+    socket: Option<std::net::UdpSocket>,
+    /// interfaces replied on with a raw socket
+    raw_interfaces: Vec<u32>,
+    /// completed once every socket is bound
+    bound: Option<oneshot::Sender<()>>,
 }
 
 impl<T> fmt::Debug for Server<T> {
@@ -78,8 +106,34 @@ where
             state,
             config,
             interfaces,
+            socket: None,
+            raw_interfaces: Vec::new(),
+            bound: None,
         })
     }
+
+    /// Listen on an already bound socket, ex. one passed by systemd socket
+    /// activation, instead of binding the configured listen address
+    pub fn with_socket(&mut self, soc: std::net::UdpSocket) -> &mut Self {
+        self.socket = Some(soc);
+        self
+    }
+
+    /// Reply to requests received on the interface at `ifindex` with a raw
+    /// socket, for interfaces without an address of their own. Replies to
+    /// relayed requests still use the UDP socket
+    pub fn with_raw_interface(&mut self, ifindex: u32) -> &mut Self {
+        self.raw_interfaces.push(ifindex);
+        self
+    }
+
+    /// Send on `bound` once every socket the server listens or replies on is
+    /// bound, ex. to drop privileges only after binding
+    pub fn notify_bound(&mut self, bound: oneshot::Sender<()>) -> &mut Self {
+        self.bound = Some(bound);
+        self
+    }
+
     /// Add plugin to the preresolve list of handlers
     pub fn plugin<P, U>(&mut self, plugin: U) -> &mut Self
     where
@@ -119,14 +173,32 @@ where
     fn into_service(self) -> Result<Service<T>> {
         let (shutdown_complete_tx, shutdown_complete_rx) = mpsc::channel(1);
         let cancel = CancellationToken::new();
+        let raw = self
+            .raw_interfaces
+            .into_iter()
+            .map(|ifindex| {
+                let soc = RawSocket::bind(ifindex)
+                    .with_context(|| format!("failed to bind raw socket on {ifindex}"))?;
+                Ok((ifindex, soc))
+            })
+            .collect::<Result<_>>()?;
+        let mtus = self
+            .interfaces
+            .iter()
+            .filter_map(|int| Some((int.index, interface_mtu(&int.name)?)))
+            .collect();
         Ok(Service {
             plugins: Arc::new(ServiceInner {
                 plugins: self.plugins.topological_sort()?,
                 postresponse: self.postresponse,
                 config: self.config,
                 interfaces: self.interfaces,
+                raw,
+                mtus,
             }),
             state: Arc::new(self.state),
+            socket: self.socket,
+            bound: self.bound,
             cancel,
             shutdown_complete_tx,
             shutdown_complete_rx,
@@ -140,9 +212,13 @@ where
 {
     /// if Some(()) - an encoded `MsgContext::decoded_resp_msg` will be sent to client
     /// if None - No response
-    async fn run_handlers(&self, ctx: &mut MsgContext<T>) -> Option<()> {
+    async fn run_handlers(&self, ctx: &mut MsgContext<T>, stages: &mut Stages) -> Option<()> {
         for handler in &*self.plugins {
-            match handler.handle(ctx).await {
+            let span = debug_span!("plugin", name = handler.name());
+            stages.begin(handler.name());
+            let action = handler.handle(ctx).instrument(span).await;
+            stages.end();
+            match action {
                 Ok(Action::Respond) => return Some(()),
                 Ok(Action::NoResponse) => {
                     // remove the resp_msg if we don't plan to send a response
@@ -179,6 +255,10 @@ pub(crate) struct Service<T> {
     pub(crate) plugins: Arc<ServiceInner<T>>,
     /// reference to server state
     pub(crate) state: Arc<State>,
+    /// socket to listen on, if it was bound before the server started
+    pub(crate) socket: Option<std::net::UdpSocket>,
+    /// completed once every socket is bound
+    pub(crate) bound: Option<oneshot::Sender<()>>,
 }
 
 pub(crate) struct ServiceInner<T> {
@@ -189,6 +269,10 @@ pub(crate) struct ServiceInner<T> {
     /// reference to server config
     config: Config,
     interfaces: Vec<NetworkInterface>,
+    /// raw sockets to reply with, by interface index
+    raw: HashMap<u32, RawSocket>,
+    /// MTU of each interface that has one, by interface index
+    mtus: HashMap<u32, usize>,
 }
 
 impl<T> fmt::Debug for Service<T> {
@@ -214,7 +298,8 @@ struct RunInner<T> {
     service: Arc<ServiceInner<T>>,
     /// socket to reply on
     soc: Arc<UdpSocket>,
-    udpstate: Arc<unix_udp_sock::UdpState>,
+    /// replies queued to be sent on `soc`
+    outgoing: mpsc::Sender<Reply>,
 }
 
 impl RunInner<v4::Message> {
@@ -224,6 +309,12 @@ impl RunInner<v4::Message> {
         if let Err(err) = self.ctx.recv_metrics() {
             warn!(?err, "error counting recv metrics--continuing");
         }
+        if let Some(instance) = &self.service.config.instance {
+            let msg_type = v4_msg_type(self.ctx.decoded_msg().opts().msg_type());
+            metrics::INSTANCE_RECV_COUNT_VEC
+                .with_label_values(&[instance, &msg_type])
+                .inc();
+        }
         let timeout = self.service.config.timeout();
         let ifindex = self.ctx.meta().ifindex;
         let source = self.ctx.meta().dst_local_ip;
@@ -235,24 +326,76 @@ impl RunInner<v4::Message> {
             .with_context(|| format!("can't find interface {ifindex}"))?;
         trace!(meta = ?self.ctx.meta(), ?interface, "received datagram");
 
-        let resp = match time::timeout(timeout, self.service.run_handlers(&mut self.ctx)).await {
+        let mut stages = Stages::new();
+        let resp = match time::timeout(
+            timeout,
+            self.service.run_handlers(&mut self.ctx, &mut stages),
+        )
+        .await
+        {
             // WARNING: any use of `?` inside this block will return early and stop post_response from running
             Ok(Some(())) => {
                 let iname = interface.name.as_str();
                 let dst_addr = self.ctx.resp_addr(
-                    self.service.config.is_default_port_v4(),
+                    self.service.config.v4_reply_ports(iname),
                     socket2::SockRef::from(&*self.soc),
                 );
 
+                if let Some(rules) = self.ctx.get_local::<ReplyRules>().cloned() {
+                    let relayed = !self.ctx.decoded_msg().giaddr().is_unspecified();
+                    if let Some(resp) = self.ctx.decoded_resp_msg_mut() {
+                        rules.apply(relayed, resp);
+                    }
+                }
                 if let Some(resp) = self.ctx.decoded_resp_msg() {
                     let msg_type = resp.opts().msg_type();
-                    if let Ok(msg) = SerialMsg::from_msg(resp, dst_addr) {
+                    let mut encoding = self
+                        .ctx
+                        .get_local::<ReplyEncoding>()
+                        .copied()
+                        .unwrap_or_default();
+                    encoding.max_size = [
+                        encoding.max_size,
+                        v4_max_size(
+                            self.ctx.decoded_msg(),
+                            self.service.mtus.get(&ifindex).copied(),
+                        ),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .min();
+                    let prl = match self
+                        .ctx
+                        .decoded_msg()
+                        .opts()
+                        .get(v4::OptionCode::ParameterRequestList)
+                    {
+                        Some(v4::DhcpOption::ParameterRequestList(prl)) => Some(&prl[..]),
+                        _ => None,
+                    };
+                    stages.begin("encode");
+                    let encoded =
+                        debug_span!("encode").in_scope(|| encode_v4_dropped(resp, encoding, prl));
+                    stages.begin("send");
+                    if let Ok((bytes, dropped)) = encoded {
+                        if !dropped.is_empty() {
+                            warn!(
+                                ?dropped,
+                                max_size = ?encoding.max_size,
+                                chaddr = ?resp.chaddr(),
+                                "reply options don't fit in the client's max message size, dropped some"
+                            );
+                        }
+                        let msg = SerialMsg::new(bytes.into(), dst_addr);
                         // https://github.com/imp/dnsmasq/blob/master/src/forward.c#L70
                         // set source IP to the same IP that was used in recv'd destination (ipi_spec_dst)
                         // otherwise use iface idx
-                        let packet_src =
-                            source.map(Source::Ip).unwrap_or(Source::Interface(ifindex));
-                        let transmit = Transmit::new(dst_addr, msg.msg()).src_ip(packet_src);
+                        let packet_src = source
+                            .map(ReplySource::Ip)
+                            .unwrap_or(ReplySource::Interface(ifindex));
+                        if self.ctx.get_local::<Traced>().is_some() {
+                            debug!(bytes = %trace_filter::hex(&msg.msg()), "sending packet");
+                        }
 
                         debug!(
                             opcode = ?resp.opcode(),
@@ -262,9 +405,45 @@ impl RunInner<v4::Message> {
                             source = ?packet_src,
                             %resp,
                         );
+                        // relayed replies go back through the relay on the UDP socket
+                        let raw = self
+                            .service
+                            .raw
+                            .get(&ifindex)
+                            .filter(|_| resp.giaddr().is_unspecified());
+                        let mac = match (dst_addr.ip(), resp.chaddr().get(..6)) {
+                            (IpAddr::V4(ip), Some(chaddr)) if !ip.is_broadcast() => {
+                                let mut mac = [0; 6];
+                                mac.copy_from_slice(chaddr);
+                                mac
+                            }
+                            _ => [0xff; 6],
+                        };
+                        let raw_src = interface.ips.iter().find_map(|net| match net {
+                            IpNetwork::V4(net) => Some(net.ip()),
+                            _ => None,
+                        });
                         self.ctx.set_dst_addr(dst_addr);
-                        if let Err(err) = self.soc.send_msg(&self.udpstate, transmit).await {
-                            error!(?err);
+                        match (raw, raw_src, dst_addr) {
+                            (Some(raw), Some(ip), SocketAddr::V4(dst)) => {
+                                let src = SocketAddrV4::new(
+                                    ip,
+                                    self.service.config.v4_listen_addr().port(),
+                                );
+                                if let Err(err) = raw.send(src, dst, mac, &msg.msg()) {
+                                    error!(?err, ?iname, "failed to send reply on raw socket");
+                                }
+                            }
+                            _ => {
+                                let reply = Reply {
+                                    dst: dst_addr,
+                                    bytes: msg.msg(),
+                                    src: Some(packet_src),
+                                };
+                                if self.outgoing.send(reply).await.is_err() {
+                                    error!("send queue closed, dropping reply");
+                                }
+                            }
                         }
                     }
                 }
@@ -275,9 +454,41 @@ impl RunInner<v4::Message> {
             // drop timeouts
             Err(error) => Err(anyhow::anyhow!(error)),
         };
+        stages.report(self.service.config.latency_budget());
         if let Err(err) = self.ctx.sent_metrics() {
             warn!(?err, "error counting sent metrics");
         }
+        if let (Some(instance), Some(resp)) =
+            (&self.service.config.instance, self.ctx.decoded_resp_msg())
+        {
+            let msg_type = v4_msg_type(resp.opts().msg_type());
+            metrics::INSTANCE_SENT_COUNT_VEC
+                .with_label_values(&[instance, &msg_type])
+                .inc();
+        }
+        if let Some(metrics::MatchedNetwork(network)) =
+            self.ctx.get_local::<metrics::MatchedNetwork>().copied()
+        {
+            let instance = self.service.config.instance.as_deref().unwrap_or_default();
+            let recv = self.ctx.decoded_msg().opts().msg_type();
+            let sent = self
+                .ctx
+                .decoded_resp_msg()
+                .and_then(|resp| resp.opts().msg_type());
+            let counted = [
+                recv.filter(|ty| *ty == v4::MessageType::Decline),
+                sent.filter(|ty| matches!(ty, v4::MessageType::Ack | v4::MessageType::Nak)),
+            ];
+            for msg_type in counted.into_iter().flatten() {
+                metrics::NETWORK_COUNT_VEC
+                    .with_label_values(&[
+                        instance,
+                        &network.to_string(),
+                        &v4_msg_type(Some(msg_type)),
+                    ])
+                    .inc();
+            }
+        }
 
         // run post-response handler, if any
         self.service.run_post_response_handler(self.ctx).await;
@@ -330,6 +541,12 @@ impl RunInner<v6::Message> {
         if let Err(err) = self.ctx.recv_metrics() {
             warn!(?err, "error counting recv metrics--continuing");
         }
+        if let Some(instance) = &self.service.config.instance {
+            let msg_type = v6_msg_type(self.ctx.decoded_msg().msg_type());
+            metrics::INSTANCE_RECV_COUNT_VEC
+                .with_label_values(&[instance, &msg_type])
+                .inc();
+        }
         let timeout = self.service.config.timeout();
         let ifindex = self.ctx.meta().ifindex;
         let interface = self
@@ -340,24 +557,57 @@ impl RunInner<v6::Message> {
             .with_context(|| format!("can't find interface {ifindex}"))?;
         trace!(meta = ?self.ctx.meta(), ?interface, "received datagram");
 
-        let resp = match time::timeout(timeout, self.service.run_handlers(&mut self.ctx)).await {
+        let mut stages = Stages::new();
+        let resp = match time::timeout(
+            timeout,
+            self.service.run_handlers(&mut self.ctx, &mut stages),
+        )
+        .await
+        {
             // WARNING: any use of `?` inside this block will return early and stop post_response from running
             Ok(Some(())) => {
                 let iname = interface.name.as_str();
-                let dst_addr = self.ctx.resp_addr(self.service.config.is_default_port_v6());
+                let relay = self.ctx.get_local::<RelayInfo>().cloned();
+                // Relay-Reply is sent to the relay's server port
+                let port = match relay {
+                    Some(_) => self
+                        .service
+                        .config
+                        .v6_reply_port(iname)
+                        .map(|_| v6::SERVER_PORT),
+                    None => self.service.config.v6_reply_port(iname),
+                };
+                let dst_addr = self.ctx.resp_addr(port);
 
                 if let Some(resp) = self.ctx.decoded_resp_msg() {
                     let msg_type = resp.msg_type();
-                    if let Ok(msg) = SerialMsg::from_msg(resp, dst_addr) {
+                    stages.begin("encode");
+                    let encoded =
+                        debug_span!("encode").in_scope(|| SerialMsg::from_msg(resp, dst_addr));
+                    stages.begin("send");
+                    if let Ok(msg) = encoded {
                         debug!(
                             ?msg_type,
                             ?dst_addr,
                             ?iname,
+                            relayed = relay.is_some(),
                             %resp,
                         );
+                        let bytes = match &relay {
+                            Some(relay) => relay.wrap(msg.bytes()),
+                            None => msg.bytes().to_vec(),
+                        };
+                        if self.ctx.get_local::<Traced>().is_some() {
+                            debug!(bytes = %trace_filter::hex(&bytes), "sending packet");
+                        }
                         self.ctx.set_dst_addr(dst_addr);
-                        if let Err(err) = self.soc.send_to(msg.bytes(), dst_addr).await {
-                            error!(?err);
+                        let reply = Reply {
+                            dst: dst_addr,
+                            bytes: bytes.into(),
+                            src: None,
+                        };
+                        if self.outgoing.send(reply).await.is_err() {
+                            error!("send queue closed, dropping reply");
                         }
                     }
                 }
@@ -368,15 +618,67 @@ impl RunInner<v6::Message> {
             // drop timeouts
             Err(error) => Err(anyhow::anyhow!(error)),
         };
+        stages.report(self.service.config.latency_budget());
         if let Err(err) = self.ctx.sent_metrics() {
             warn!(?err, "error counting sent metrics");
         }
+        if let (Some(instance), Some(resp)) =
+            (&self.service.config.instance, self.ctx.decoded_resp_msg())
+        {
+            let msg_type = v6_msg_type(resp.msg_type());
+            metrics::INSTANCE_SENT_COUNT_VEC
+                .with_label_values(&[instance, &msg_type])
+                .inc();
+        }
         // run post-response handler, if any
         self.service.run_post_response_handler(self.ctx).await;
         resp
     }
 }
 
+/// IP & UDP headers sent in front of a DHCP message
+const IP_UDP_HEADER_LEN: usize = 28;
+/// the smallest max message size (opt 57) a client can send, & the largest
+/// message a DHCP client that doesn't send one must accept, RFC 2131 2
+const MIN_MAX_MSG_SIZE: usize = 576;
+
+/// the most bytes a v4 reply to `req` can take up: the client's max message
+/// size (opt 57) & the interface's MTU, less the IP & UDP headers. BOOTP
+/// clients are only limited by the MTU, `None` if it isn't known
+fn v4_max_size(req: &v4::Message, mtu: Option<usize>) -> Option<usize> {
+    let max_msg_size = match req.opts().get(v4::OptionCode::MaxMessageSize) {
+        Some(v4::DhcpOption::MaxMessageSize(size)) => Some((*size as usize).max(MIN_MAX_MSG_SIZE)),
+        _ if req.opts().msg_type().is_some() => Some(MIN_MAX_MSG_SIZE),
+        _ => None,
+    };
+    [max_msg_size, mtu]
+        .into_iter()
+        .flatten()
+        .min()
+        .map(|size| size.saturating_sub(IP_UDP_HEADER_LEN))
+}
+
+/// MTU of the interface called `name`, read from sysfs
+fn interface_mtu(name: &str) -> Option<usize> {
+    std::fs::read_to_string(format!("/sys/class/net/{name}/mtu"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// message type label for the per-instance metrics
+fn v4_msg_type(msg_type: Option<v4::MessageType>) -> String {
+    msg_type
+        .map(|ty| format!("{ty:?}").to_lowercase())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// message type label for the per-instance metrics
+fn v6_msg_type(msg_type: v6::MessageType) -> String {
+    format!("v6_{msg_type:?}").to_lowercase()
+}
+
 // This is unfortunate,
 // the key problem is that Server/Service is defined over T, and yet
 // they need to call send code to handle broadcast/multicast differently for v4/v6
@@ -453,33 +755,83 @@ macro_rules! impl_server {
         impl Service<$t> {
             // handles listening on UDP and spawning a new task per `MsgContext`
             async fn listen(&mut self) -> Result<()> {
-                let soc = self.create_socket().await?;
-
-                let udp_recv = Arc::new(soc);
-                let udp_send = Arc::clone(&udp_recv);
-                let udp_state = Arc::new(unix_udp_sock::UdpState::new());
-
-                let mut ctx_stream = UdpStream::<$t, _>::new(udp_recv, self.state.clone());
-                while let Some(ctx) = ctx_stream.next().await {
-                    if let Ok(ctx) = ctx {
-                        self.state.inc_live_msgs().await;
-                        let cancel = self.cancel.clone();
-                        let _shutdown_complete = self.shutdown_complete_tx.clone();
-                        let task = RunTask {
-                            inner: RunInner {
-                                ctx,
-                                soc: udp_send.clone(),
-                                service: self.plugins.clone(),
-                                udpstate: udp_state.clone(),
-                            },
-                            cancel,
-                            _shutdown_complete,
-                        };
-                        // TODO: when `JoinSet` is removed from unstable-- add handles
-                        // here.
-                        // Using JoinSet will likely mean that we no longer need `_shutdown_complete`
-                        tokio::spawn(task.run());
-                    }
+                let ingress = Arc::new(Ingress::new(self.plugins.config.ingress_queue_size));
+                let names = Arc::new(
+                    self.plugins
+                        .interfaces
+                        .iter()
+                        .map(|int| (int.index, int.name.clone()))
+                        .collect::<HashMap<_, _>>(),
+                );
+                let mut workers = Vec::new();
+                for worker in 0..self.plugins.config.socket_workers.max(1) {
+                    let soc = Arc::new(self.create_socket(worker).await?);
+                    let (outgoing, rx) = mpsc::channel(self.plugins.config.channel_size);
+                    let mut ctx_stream = self.start_io(&soc, rx)?;
+                    let reader = Arc::clone(&ingress);
+                    let names = Arc::clone(&names);
+                    let cancel = self.cancel.clone();
+                    // read the socket on its own task so the queues keep draining the
+                    // socket while we wait for a free in-flight slot
+                    tokio::spawn(async move {
+                        loop {
+                            let ctx = tokio::select! {
+                                _ = cancel.cancelled() => break,
+                                ctx = ctx_stream.next() => match ctx {
+                                    Some(ctx) => ctx,
+                                    None => break,
+                                },
+                            };
+                            if let Ok(ctx) = ctx {
+                                let ifindex = ctx.meta().ifindex;
+                                if let Some(dropped) = reader.push(ifindex, ctx) {
+                                    let name = names
+                                        .get(&ifindex)
+                                        .cloned()
+                                        .unwrap_or_else(|| ifindex.to_string());
+                                    debug!(
+                                        id = dropped.id(),
+                                        interface = %name,
+                                        "ingress queue full, dropping oldest message"
+                                    );
+                                    metrics::INGRESS_DROPPED.with_label_values(&[&name]).inc();
+                                }
+                            }
+                        }
+                        reader.close();
+                    });
+                    workers.push((soc, outgoing));
+                }
+                if let Some(bound) = self.bound.take() {
+                    // nobody may be waiting
+                    let _ = bound.send(());
+                }
+
+                // replies are spread over the workers' sockets, they're all bound
+                // to the same address
+                let mut next = 0;
+                while let Some(mut ctx) = ingress.pop().await {
+                    let (soc, outgoing) = &workers[next];
+                    next = (next + 1) % workers.len();
+                    let span = ctx.span().clone();
+                    self.state.inc_live_msgs().await;
+                    ctx.mark_as_live();
+                    let cancel = self.cancel.clone();
+                    let _shutdown_complete = self.shutdown_complete_tx.clone();
+                    let task = RunTask {
+                        inner: RunInner {
+                            ctx,
+                            soc: Arc::clone(soc),
+                            service: self.plugins.clone(),
+                            outgoing: outgoing.clone(),
+                        },
+                        cancel,
+                        _shutdown_complete,
+                    };
+                    // TODO: when `JoinSet` is removed from unstable-- add handles
+                    // here.
+                    // Using JoinSet will likely mean that we no longer need `_shutdown_complete`
+                    tokio::spawn(task.run().instrument(span));
                 }
                 Ok(())
             }
@@ -490,21 +842,77 @@ macro_rules! impl_server {
 impl_server!(v4::Message);
 impl_server!(v6::Message);
 
+impl<T> Service<T>
+where
+    T: Decodable + Encodable,
+{
+    /// the stream of messages received on `soc`, replies queued on `replies`
+    /// are sent from it. Uses the configured `--io-backend`
+    fn start_io(
+        &self,
+        soc: &Arc<UdpSocket>,
+        replies: mpsc::Receiver<Reply>,
+    ) -> Result<UdpStream<T, Arc<UdpSocket>>> {
+        match self.plugins.config.io_backend {
+            IoBackend::Epoll => {
+                tokio::spawn(udp::send_batches(udp::try_clone(soc)?, replies));
+                Ok(UdpStream::new(Arc::clone(soc), self.state.clone()))
+            }
+            #[cfg(feature = "io-uring")]
+            IoBackend::IoUring => {
+                let (received, rx) = mpsc::channel(self.plugins.config.channel_size);
+                uring::start(Arc::clone(soc), received, replies)
+                    .context("failed to start io_uring threads")?;
+                Ok(UdpStream::from_ring(rx, self.state.clone()))
+            }
+            #[cfg(not(feature = "io-uring"))]
+            IoBackend::IoUring => {
+                anyhow::bail!("dora was built without the `io-uring` feature, can't use io_uring")
+            }
+        }
+    }
+
+    /// with more than one worker, `socket` joins the SO_REUSEPORT group of the
+    /// listen address & any but the first worker only reads unicast
+    fn share_port(&self, socket: &socket2::Socket, worker: usize) -> Result<()> {
+        if self.plugins.config.socket_workers > 1 {
+            socket
+                .set_reuse_port(true)
+                .context("failed to set_reuse_port")?;
+        }
+        if worker > 0 {
+            ioctl::unicast_only(socket2::SockRef::from(socket))
+                .context("failed to attach unicast filter")?;
+        }
+        Ok(())
+    }
+}
+
 impl Service<v4::Message> {
+    /// the socket of `worker`, see `--socket-workers`. The first takes a socket
+    /// passed by the service manager
     #[instrument(name = "v4", level = "debug", skip_all)]
-    async fn create_socket(&self) -> Result<unix_udp_sock::UdpSocket> {
-        let addr = self.plugins.config.v4_addr;
+    async fn create_socket(&mut self, worker: usize) -> Result<unix_udp_sock::UdpSocket> {
+        let addr = self.plugins.config.v4_listen_addr();
         let interfaces = self.plugins.interfaces.clone();
-        info!(?addr, "binding UDP socket");
-        let soc = if interfaces.len() == 1 {
-            trace!("binding exactly one interface so use SO_BINDTODEVICE");
+        let soc = if let Some(soc) = self.socket.take() {
+            info!(addr = ?soc.local_addr(), "using already bound UDP socket");
+            soc.set_nonblocking(true)
+                .context("failed to set nonblocking mode on socket")?;
+            unix_udp_sock::UdpSocket::from_std(soc)?
+        } else {
+            info!(?addr, worker, "binding UDP socket");
             // to bind to an interface, we must create the socket using libc
             let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None)
                 .context("failed to bind v4 UDP socket")?;
-            // SO_BINDTODEVICE
-            socket
-                .bind_device(Some(interfaces.first().unwrap().name.as_bytes()))
-                .context("failed to find interface")?;
+            if interfaces.len() == 1 {
+                trace!("binding exactly one interface so use SO_BINDTODEVICE");
+                // SO_BINDTODEVICE
+                socket
+                    .bind_device(Some(interfaces.first().unwrap().name.as_bytes()))
+                    .context("failed to find interface")?;
+            }
+            self.share_port(&socket, worker)?;
             socket
                 .set_nonblocking(true)
                 .context("failed to set nonblocking mode on socket")?;
@@ -515,8 +923,6 @@ impl Service<v4::Message> {
             unix_udp_sock::UdpSocket::from_std(unsafe {
                 std::net::UdpSocket::from_raw_fd(socket.into_raw_fd())
             })?
-        } else {
-            unix_udp_sock::UdpSocket::bind(addr).await?
         };
         soc.set_broadcast(true).context("failed to set_broadcast")?;
         Ok(soc)
@@ -524,11 +930,26 @@ impl Service<v4::Message> {
 }
 
 impl Service<v6::Message> {
+    /// the socket of `worker`, see `--socket-workers`. The first takes a socket
+    /// passed by the service manager
     #[instrument(name = "v6", level = "debug", skip_all)]
-    async fn create_socket(&self) -> Result<unix_udp_sock::UdpSocket> {
-        let addr = self.plugins.config.v6_addr;
+    async fn create_socket(&mut self, worker: usize) -> Result<unix_udp_sock::UdpSocket> {
+        let addr = self.plugins.config.v6_listen_addr();
         let interfaces = self.plugins.interfaces.clone();
-        info!(?addr, "binding v6 UDP socket");
+        if let Some(soc) = self.socket.take() {
+            info!(addr = ?soc.local_addr(), "using already bound v6 UDP socket");
+            soc.set_nonblocking(true)
+                .context("failed to set nonblocking mode on socket")?;
+            // the service manager binds the socket but doesn't join multicast
+            let socket = socket2::SockRef::from(&soc);
+            for int in &interfaces {
+                socket
+                    .join_multicast_v6(&ALL_DHCP_RELAY_AGENTS_AND_SERVERS, int.index)
+                    .context("join v6 multicast")?;
+            }
+            return Ok(unix_udp_sock::UdpSocket::from_std(soc)?);
+        }
+        info!(?addr, worker, "binding v6 UDP socket");
         let socket = socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::DGRAM, None)
             .context("failed to bind v6 UDP socket")?;
         socket.set_only_v6(true).context("only ipv6")?;
@@ -539,6 +960,7 @@ impl Service<v6::Message> {
         socket
             .set_reuse_port(true)
             .context("failed to set_reuse_address")?;
+        self.share_port(&socket, worker)?;
         socket
             .set_nonblocking(true)
             .context("failed to set nonblocking mode on socket")?;
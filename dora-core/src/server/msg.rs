@@ -1,6 +1,10 @@
 //! SerialMsg defines raw bytes and an addr
 use bytes::Bytes;
-use dhcproto::{Decodable, Encodable};
+use dhcproto::{
+    error::EncodeResult,
+    v4::{self, OptionCode},
+    Decodable, Encodable,
+};
 
 use std::{io, net::SocketAddr};
 
@@ -32,6 +36,21 @@ impl SerialMsg {
             addr,
         })
     }
+    /// Encode a v4 reply with `encoding`, `prl` is the parameter request list
+    /// of the client's message
+    pub fn from_v4_msg(
+        msg: &v4::Message,
+        encoding: ReplyEncoding,
+        prl: Option<&[OptionCode]>,
+        addr: SocketAddr,
+    ) -> io::Result<Self> {
+        Ok(SerialMsg {
+            message: encode_v4(msg, encoding, prl)
+                .map_err(|op| io::Error::new(io::ErrorKind::InvalidData, op))?
+                .into(),
+            addr,
+        })
+    }
     /// Get a reference to the bytes
     pub fn bytes(&self) -> &[u8] {
         &self.message
@@ -62,3 +81,431 @@ impl SerialMsg {
         T::from_bytes(&self.message).map_err(|op| io::Error::new(io::ErrorKind::InvalidData, op))
     }
 }
+
+/// order options are written in a v4 reply
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OptionOrder {
+    /// ascending option code
+    #[default]
+    Numeric,
+    /// message type first, then options in the order of the client's parameter
+    /// request list, then any others in ascending order
+    RequestList,
+}
+
+/// how a v4 reply is encoded. Plugins set this as a local on the `MsgContext`,
+/// replies are encoded with the default if it isn't set
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct ReplyEncoding {
+    /// the order options are written in
+    pub order: OptionOrder,
+    /// pad the reply after the end option with zeros up to this many bytes
+    pub min_size: Option<usize>,
+    /// the most bytes the reply can take up. Options that don't fit are moved
+    /// into the `file` & `sname` fields with option overload (opt 52) when
+    /// they aren't used for boot info, & the lowest priority options dropped
+    /// if they still don't fit
+    pub max_size: Option<usize>,
+}
+
+/// which configured options are added to a v4 reply. Plugins set this as a
+/// local on the `MsgContext`, only requested options are sent if it isn't set
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum ReplyOptions {
+    /// options in the client's parameter request list (opt 55)
+    #[default]
+    Requested,
+    /// requested options & these, whether they were requested or not
+    Mandatory(Vec<OptionCode>),
+    /// every configured option
+    All,
+}
+
+impl ReplyOptions {
+    /// is a configured option with `code` sent to a client that requested `prl`?
+    pub fn sends(&self, code: OptionCode, prl: &[OptionCode]) -> bool {
+        match self {
+            ReplyOptions::Requested => prl.contains(&code),
+            ReplyOptions::Mandatory(codes) => prl.contains(&code) || codes.contains(&code),
+            ReplyOptions::All => true,
+        }
+    }
+}
+
+/// options stripped or rewritten in a v4 reply depending on where it's sent.
+/// Plugins set this as a local on the `MsgContext`, it's applied just before
+/// the reply is encoded
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReplyRules {
+    /// applied to replies to relayed requests, which are sent to the relay
+    pub relayed: OptionRules,
+    /// applied to replies sent straight to the client
+    pub direct: OptionRules,
+}
+
+/// options removed from & set in a reply
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OptionRules {
+    /// option codes removed
+    pub remove: Vec<OptionCode>,
+    /// options set, replacing any with the same code
+    pub set: Vec<v4::DhcpOption>,
+}
+
+impl ReplyRules {
+    /// apply the rules for a reply to a request with or without a giaddr
+    pub fn apply(&self, relayed: bool, msg: &mut v4::Message) {
+        let rules = if relayed { &self.relayed } else { &self.direct };
+        for code in &rules.remove {
+            msg.opts_mut().remove(*code);
+        }
+        for opt in &rules.set {
+            msg.opts_mut().insert(opt.clone());
+        }
+    }
+}
+
+/// the fixed size header & magic cookie that options follow
+const V4_HEADER_LEN: usize = 240;
+/// where the `sname` & `file` fields are in the header
+const SNAME: std::ops::Range<usize> = 44..108;
+const FILE: std::ops::Range<usize> = 108..236;
+
+/// options that are never dropped to fit a reply in its max size
+const ESSENTIAL_OPTS: &[OptionCode] = &[
+    OptionCode::SubnetMask,
+    OptionCode::Router,
+    OptionCode::AddressLeaseTime,
+    OptionCode::MessageType,
+    OptionCode::ServerIdentifier,
+    OptionCode::Message,
+    OptionCode::Renewal,
+    OptionCode::Rebinding,
+    OptionCode::ClientIdentifier,
+    OptionCode::RelayAgentInformation,
+];
+
+/// encode `msg`, re-writing the options in `encoding.order`, fitting them in
+/// `encoding.max_size` & padding the reply
+pub fn encode_v4(
+    msg: &v4::Message,
+    encoding: ReplyEncoding,
+    prl: Option<&[OptionCode]>,
+) -> EncodeResult<Vec<u8>> {
+    encode_v4_dropped(msg, encoding, prl).map(|(buf, _)| buf)
+}
+
+/// [`encode_v4`], also returning the options dropped to fit `encoding.max_size`
+pub fn encode_v4_dropped(
+    msg: &v4::Message,
+    encoding: ReplyEncoding,
+    prl: Option<&[OptionCode]>,
+) -> EncodeResult<(Vec<u8>, Vec<OptionCode>)> {
+    let mut buf = msg.to_vec()?;
+    let mut dropped = Vec::new();
+    let too_big = encoding.max_size.map_or(false, |max| buf.len() > max);
+    // only the encoder sets option overload, a configured one would have the
+    // client read the boot fields as options
+    let overload = msg.opts().get(OptionCode::OptionOverload).is_some();
+    if (encoding.order == OptionOrder::RequestList || too_big || overload)
+        && buf.len() > V4_HEADER_LEN
+    {
+        let rank = |code: OptionCode| match code {
+            OptionCode::MessageType => 0,
+            code => prl
+                .and_then(|prl| prl.iter().position(|c| *c == code))
+                .map_or(usize::MAX, |pos| pos + 1),
+        };
+        let mut opts = msg
+            .opts()
+            .iter()
+            .filter(|(code, _)| {
+                !matches!(
+                    code,
+                    OptionCode::End | OptionCode::Pad | OptionCode::OptionOverload
+                )
+            })
+            .collect::<Vec<_>>();
+        if encoding.order == OptionOrder::RequestList {
+            opts.sort_by_key(|(code, _)| (rank(**code), u8::from(**code)));
+        } else {
+            opts.sort_by_key(|(code, _)| u8::from(**code));
+        }
+        let mut opts = opts
+            .into_iter()
+            .map(|(code, opt)| opt.to_vec().map(|opt| (*code, opt)))
+            .collect::<EncodeResult<Vec<_>>>()?;
+        buf.truncate(V4_HEADER_LEN);
+        match encoding.max_size {
+            Some(max) if too_big => {
+                // drop the options least likely to be missed until the rest fit
+                while !fit(&mut buf, &opts, max) {
+                    let lowest = opts
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, (code, _))| !ESSENTIAL_OPTS.contains(code))
+                        .max_by_key(|(_, (code, _))| (rank(*code), u8::from(*code)))
+                        .map(|(i, _)| i);
+                    match lowest {
+                        Some(i) => dropped.push(opts.remove(i).0),
+                        // only essential options are left, send them anyway
+                        None => {
+                            for (_, opt) in &opts {
+                                buf.extend(opt);
+                            }
+                            buf.push(u8::from(OptionCode::End));
+                            break;
+                        }
+                    }
+                }
+            }
+            _ => {
+                for (_, opt) in &opts {
+                    buf.extend(opt);
+                }
+                buf.push(u8::from(OptionCode::End));
+            }
+        }
+    }
+    if let Some(min_size) = encoding.min_size {
+        let min_size = encoding.max_size.map_or(min_size, |max| min_size.min(max));
+        if buf.len() < min_size {
+            buf.resize(min_size, u8::from(OptionCode::Pad));
+        }
+    }
+    Ok((buf, dropped))
+}
+
+/// write the encoded `opts` after the header in `buf`, overflowing into the
+/// `file` & then the `sname` field if they're empty, so the reply takes up at
+/// most `max` bytes. A field holding a boot file or server name is never
+/// overloaded. Returns false & leaves `buf` as it was if they don't fit
+fn fit(buf: &mut Vec<u8>, opts: &[(OptionCode, Vec<u8>)], max: usize) -> bool {
+    let opts = opts.iter().map(|(_, opt)| opt).collect::<Vec<_>>();
+    let size = opts.iter().map(|opt| opt.len()).sum::<usize>();
+    // the options field, with room for the end option
+    let room = max.saturating_sub(V4_HEADER_LEN + 1);
+    if size <= room {
+        for opt in opts {
+            buf.extend(opt);
+        }
+        buf.push(u8::from(OptionCode::End));
+        return true;
+    }
+    // option overload takes up 3 bytes of the options field, each field it
+    // fills needs an end option of its own
+    let mut fields = [(room.saturating_sub(3), Vec::new(), 0)]
+        .into_iter()
+        .chain(
+            [(FILE, 1), (SNAME, 2)]
+                .into_iter()
+                .filter(|(range, _)| buf[range.clone()].iter().all(|b| *b == 0))
+                .map(|(range, overload)| (range.len() - 1, Vec::new(), overload)),
+        )
+        .collect::<Vec<_>>();
+    for opt in opts {
+        match fields
+            .iter_mut()
+            .find(|(room, field, _)| field.len() + opt.len() <= *room)
+        {
+            Some((_, field, _)) => field.extend(opt),
+            None => return false,
+        }
+    }
+    let overload = fields
+        .iter()
+        .filter(|(_, field, _)| !field.is_empty())
+        .fold(0, |overload, (_, _, n)| overload | n);
+    let mut fields = fields.into_iter();
+    if let Some((_, options, _)) = fields.next() {
+        buf.extend(options);
+    }
+    buf.extend([u8::from(OptionCode::OptionOverload), 1, overload]);
+    buf.push(u8::from(OptionCode::End));
+    for (_, field, overload) in fields.filter(|(_, field, _)| !field.is_empty()) {
+        let start = if overload == 1 {
+            FILE.start
+        } else {
+            SNAME.start
+        };
+        buf[start..start + field.len()].copy_from_slice(&field);
+        buf[start + field.len()] = u8::from(OptionCode::End);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use dhcproto::v4::{DhcpOption, MessageType};
+
+    use super::*;
+
+    fn reply() -> v4::Message {
+        let mut msg = v4::Message::default();
+        msg.opts_mut()
+            .insert(DhcpOption::MessageType(MessageType::Ack));
+        msg.opts_mut()
+            .insert(DhcpOption::SubnetMask(Ipv4Addr::new(255, 255, 255, 0)));
+        msg.opts_mut()
+            .insert(DhcpOption::Router(vec![Ipv4Addr::new(192, 168, 0, 1)]));
+        msg.opts_mut()
+            .insert(DhcpOption::ServerIdentifier(Ipv4Addr::new(192, 168, 0, 1)));
+        msg
+    }
+
+    /// option codes in the order they were encoded
+    fn codes(buf: &[u8]) -> Vec<u8> {
+        let mut codes = Vec::new();
+        let mut opts = &buf[V4_HEADER_LEN..];
+        while let [code, rest @ ..] = opts {
+            if *code == 255 {
+                break;
+            }
+            codes.push(*code);
+            opts = &rest[1 + rest[0] as usize..];
+        }
+        codes
+    }
+
+    #[test]
+    fn test_request_list_order() {
+        let prl = [OptionCode::Router, OptionCode::SubnetMask];
+        let encoding = ReplyEncoding {
+            order: OptionOrder::RequestList,
+            min_size: None,
+            max_size: None,
+        };
+        let msg = reply();
+        let buf = encode_v4(&msg, encoding, Some(&prl[..])).unwrap();
+        assert_eq!(codes(&buf), [53, 3, 1, 54]);
+        // still decodes to the same message
+        assert_eq!(v4::Message::from_bytes(&buf).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_min_size() {
+        let encoding = ReplyEncoding {
+            order: OptionOrder::Numeric,
+            min_size: Some(300),
+            max_size: None,
+        };
+        let msg = reply();
+        let buf = encode_v4(&msg, encoding, None).unwrap();
+        assert_eq!(buf.len(), 300);
+        // padded with zeros after the end option
+        let end = buf.iter().rposition(|b| *b == 255).unwrap();
+        assert!(end < 300 - 1 && buf[end + 1..].iter().all(|b| *b == 0));
+        assert_eq!(v4::Message::from_bytes(&buf).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_max_size_overload() {
+        let mut msg = reply();
+        msg.opts_mut()
+            .insert(DhcpOption::DomainName("a".repeat(100)));
+        let encoding = ReplyEncoding {
+            max_size: Some(300),
+            ..Default::default()
+        };
+        let buf = encode_v4(&msg, encoding, None).unwrap();
+        assert!(buf.len() <= 300);
+        assert_eq!(codes(&buf), [1, 3, 53, 54, 52]);
+        // overloaded into the file field
+        assert_eq!(&buf[buf.len() - 4..], [52, 1, 1, 255]);
+        assert_eq!(buf[FILE.start], 15);
+        assert_eq!(buf[FILE.start + 102], 255);
+        assert!(buf[SNAME].iter().all(|b| *b == 0));
+        // fits without overload
+        let encoding = ReplyEncoding {
+            max_size: Some(576),
+            ..Default::default()
+        };
+        assert_eq!(
+            encode_v4(&msg, encoding, None).unwrap(),
+            msg.to_vec().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_max_size_trim() {
+        let mut msg = reply();
+        msg.set_sname_str("tftp.example.com");
+        msg.set_fname_str("pxelinux.0");
+        msg.opts_mut()
+            .insert(DhcpOption::DomainName("a".repeat(100)));
+        msg.opts_mut().insert(DhcpOption::Hostname("host".into()));
+        let prl = [OptionCode::Hostname, OptionCode::DomainName];
+        let encoding = ReplyEncoding {
+            max_size: Some(300),
+            ..Default::default()
+        };
+        // no room to overload, the last requested option is dropped
+        let (buf, dropped) = encode_v4_dropped(&msg, encoding, Some(&prl[..])).unwrap();
+        assert!(buf.len() <= 300);
+        assert_eq!(codes(&buf), [1, 3, 12, 53, 54]);
+        assert_eq!(dropped, [OptionCode::DomainName]);
+        // only essential options are left
+        let encoding = ReplyEncoding {
+            max_size: Some(250),
+            ..Default::default()
+        };
+        let (buf, dropped) = encode_v4_dropped(&msg, encoding, Some(&prl[..])).unwrap();
+        assert_eq!(codes(&buf), [1, 3, 53, 54]);
+        assert_eq!(dropped, [OptionCode::DomainName, OptionCode::Hostname]);
+    }
+
+    #[test]
+    fn test_overload_boot_fields() {
+        let mut msg = reply();
+        msg.set_fname_str("pxelinux.0");
+        msg.opts_mut()
+            .insert(DhcpOption::DomainName("a".repeat(50)));
+        let encoding = ReplyEncoding {
+            max_size: Some(300),
+            ..Default::default()
+        };
+        // the boot file is kept, options overflow into sname
+        let buf = encode_v4(&msg, encoding, None).unwrap();
+        assert!(buf.len() <= 300);
+        assert_eq!(&buf[FILE.start..FILE.start + 10], b"pxelinux.0");
+        assert_eq!(&buf[buf.len() - 4..], [52, 1, 2, 255]);
+        assert_eq!(buf[SNAME.start], 15);
+        assert_eq!(buf[SNAME.start + 52], 255);
+        // a configured option overload isn't sent
+        msg.opts_mut().insert(DhcpOption::OptionOverload(3));
+        let buf = encode_v4(&msg, ReplyEncoding::default(), None).unwrap();
+        assert_eq!(codes(&buf), [1, 3, 15, 53, 54]);
+        assert_eq!(&buf[FILE.start..FILE.start + 10], b"pxelinux.0");
+    }
+
+    #[test]
+    fn test_reply_rules() {
+        let rules = ReplyRules {
+            relayed: OptionRules {
+                remove: vec![OptionCode::Router],
+                set: vec![DhcpOption::DomainName("relayed.example.com".into())],
+            },
+            direct: OptionRules {
+                remove: vec![OptionCode::SubnetMask],
+                set: vec![DhcpOption::Router(vec![Ipv4Addr::new(192, 168, 0, 254)])],
+            },
+        };
+        let mut msg = reply();
+        rules.apply(true, &mut msg);
+        assert!(msg.opts().get(OptionCode::Router).is_none());
+        assert!(msg.opts().get(OptionCode::SubnetMask).is_some());
+        assert_eq!(
+            msg.opts().get(OptionCode::DomainName),
+            Some(&DhcpOption::DomainName("relayed.example.com".into()))
+        );
+        let mut msg = reply();
+        rules.apply(false, &mut msg);
+        assert!(msg.opts().get(OptionCode::SubnetMask).is_none());
+        assert_eq!(
+            msg.opts().get(OptionCode::Router),
+            Some(&DhcpOption::Router(vec![Ipv4Addr::new(192, 168, 0, 254)]))
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! sending on interfaces dora has no address on, ex. the VLANs of a trunk port
+//!
+//! Replies are written with their IP & UDP headers to an `AF_PACKET` socket
+//! bound to the interface, so they leave from the address configured for it
+//! instead of one the kernel picks from another interface.
+use std::{
+    io, mem,
+    net::SocketAddrV4,
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd},
+};
+
+/// EtherType of IPv4
+const ETH_P_IP: u16 = 0x0800;
+/// length of an IPv4 header without options
+const IP_HEADER: usize = 20;
+/// length of a UDP header
+const UDP_HEADER: usize = 8;
+const TTL: u8 = 64;
+
+/// an `AF_PACKET` socket bound to one interface
+#[derive(Debug)]
+pub(crate) struct RawSocket {
+    fd: OwnedFd,
+    ifindex: u32,
+}
+
+impl RawSocket {
+    /// open a socket sending on the interface at `ifindex`
+    pub(crate) fn bind(ifindex: u32) -> io::Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_PACKET,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                ETH_P_IP.to_be() as i32,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        // only sent on, receiving nothing keeps the socket's queue empty
+        let addr = link_addr(ifindex, 0, [0; 6]);
+        let res = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { fd, ifindex })
+    }
+
+    /// send `payload` from `src` to `dst` at the link-layer address `mac`
+    pub(crate) fn send(
+        &self,
+        src: SocketAddrV4,
+        dst: SocketAddrV4,
+        mac: [u8; 6],
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let packet = udp_packet(src, dst, payload);
+        let addr = link_addr(self.ifindex, ETH_P_IP, mac);
+        let res = unsafe {
+            libc::sendto(
+                self.fd.as_raw_fd(),
+                packet.as_ptr() as *const libc::c_void,
+                packet.len(),
+                0,
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+fn link_addr(ifindex: u32, protocol: u16, mac: [u8; 6]) -> libc::sockaddr_ll {
+    let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = protocol.to_be();
+    addr.sll_ifindex = ifindex as i32;
+    addr.sll_halen = 6;
+    addr.sll_addr[..6].copy_from_slice(&mac);
+    addr
+}
+
+/// `payload` with UDP & IPv4 headers
+fn udp_packet(src: SocketAddrV4, dst: SocketAddrV4, payload: &[u8]) -> Vec<u8> {
+    let udp_len = (UDP_HEADER + payload.len()) as u16;
+    let total = IP_HEADER as u16 + udp_len;
+    let mut buf = Vec::with_capacity(total as usize);
+    buf.extend_from_slice(&[0x45, 0]);
+    buf.extend_from_slice(&total.to_be_bytes());
+    // id 0 & don't fragment
+    buf.extend_from_slice(&[0, 0, 0x40, 0]);
+    buf.extend_from_slice(&[TTL, libc::IPPROTO_UDP as u8, 0, 0]);
+    buf.extend_from_slice(&src.ip().octets());
+    buf.extend_from_slice(&dst.ip().octets());
+    let sum = checksum(&[&buf]);
+    buf[10..12].copy_from_slice(&sum.to_be_bytes());
+
+    buf.extend_from_slice(&src.port().to_be_bytes());
+    buf.extend_from_slice(&dst.port().to_be_bytes());
+    buf.extend_from_slice(&udp_len.to_be_bytes());
+    buf.extend_from_slice(&[0, 0]);
+    buf.extend_from_slice(payload);
+    let mut pseudo = [0; 12];
+    pseudo[..4].copy_from_slice(&src.ip().octets());
+    pseudo[4..8].copy_from_slice(&dst.ip().octets());
+    pseudo[9] = libc::IPPROTO_UDP as u8;
+    pseudo[10..].copy_from_slice(&udp_len.to_be_bytes());
+    // a zero UDP checksum means there isn't one, RFC 768
+    let sum = match checksum(&[&pseudo, &buf[IP_HEADER..]]) {
+        0 => 0xffff,
+        sum => sum,
+    };
+    buf[IP_HEADER + 6..IP_HEADER + 8].copy_from_slice(&sum.to_be_bytes());
+    buf
+}
+
+/// the internet checksum of `parts`, RFC 1071. Only the last part may have an
+/// odd length
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = parts
+        .iter()
+        .flat_map(|part| part.chunks(2))
+        .map(|word| match word {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]) as u32,
+            [hi] => u16::from_be_bytes([*hi, 0]) as u32,
+            _ => 0,
+        })
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum() {
+        let header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+        assert_eq!(checksum(&[&header]), 0xb861);
+    }
+
+    #[test]
+    fn test_udp_packet() {
+        let src = "10.0.100.1:67".parse().unwrap();
+        let dst = "255.255.255.255:68".parse().unwrap();
+        let packet = udp_packet(src, dst, b"dhcp");
+        assert_eq!(packet.len(), IP_HEADER + UDP_HEADER + 4);
+        assert_eq!(&packet[12..16], &[10, 0, 100, 1]);
+        assert_eq!(&packet[IP_HEADER..IP_HEADER + 4], &[0, 67, 0, 68]);
+        // a packet with valid checksums sums to zero
+        assert_eq!(checksum(&[&packet[..IP_HEADER]]), 0);
+        let mut pseudo = [0; 12];
+        pseudo[..4].copy_from_slice(&[10, 0, 100, 1]);
+        pseudo[4..8].copy_from_slice(&[255; 4]);
+        pseudo[9] = 17;
+        pseudo[11] = (UDP_HEADER + 4) as u8;
+        assert_eq!(checksum(&[&pseudo, &packet[IP_HEADER..]]), 0);
+    }
+}
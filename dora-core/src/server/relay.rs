@@ -0,0 +1,308 @@
+//! DHCPv6 relay encapsulation
+//!
+//! Relay-Forward messages are unwrapped down to the client message before the
+//! plugins run, and the reply is re-encapsulated in matching Relay-Reply
+//! messages before it is sent. When dora runs as a relay agent it does the
+//! opposite, [`RelayLayer::forward`] encapsulates client messages & replies
+//! are unwrapped one layer at a time with [`RelayLayer::unwrap_reply`].
+//!
+//! Per [RFC 8415 section 19.3] the Interface-ID option is always copied back to
+//! the relay. Options listed in an Echo Request option ([RFC 4994]) are echoed
+//! back in the Relay-Reply for the relay that requested them.
+//!
+//! [RFC 8415 section 19.3]: https://www.rfc-editor.org/rfc/rfc8415#section-19.3
+//! [RFC 4994]: https://www.rfc-editor.org/rfc/rfc4994
+use std::{
+    io::{self, Error, ErrorKind},
+    net::Ipv6Addr,
+};
+
+/// Relay-Forward message type
+pub const RELAY_FORW: u8 = 12;
+/// Relay-Reply message type
+pub const RELAY_REPL: u8 = 13;
+/// Relay Message option code
+pub const OPTION_RELAY_MSG: u16 = 9;
+/// Interface-ID option code
+pub const OPTION_INTERFACE_ID: u16 = 18;
+/// Remote-ID option code (RFC 4649)
+pub const OPTION_REMOTE_ID: u16 = 37;
+/// Echo Request option code (RFC 4994)
+pub const OPTION_ERO: u16 = 43;
+/// relay message header, msg-type + hop-count + link-address + peer-address
+const HEADER_LEN: usize = 34;
+/// max relay nesting, RFC 8415 HOP_COUNT_LIMIT
+pub const HOP_COUNT_LIMIT: usize = 8;
+
+/// a single Relay-Forward encapsulation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayLayer {
+    /// hop count
+    pub hop_count: u8,
+    /// link address
+    pub link_addr: Ipv6Addr,
+    /// peer address
+    pub peer_addr: Ipv6Addr,
+    /// relay-supplied options, excluding the Relay Message option
+    pub opts: Vec<(u16, Vec<u8>)>,
+}
+
+impl RelayLayer {
+    /// get the data of a relay-supplied option
+    pub fn get(&self, code: u16) -> Option<&[u8]> {
+        self.opts
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// option codes requested in the ERO option, if any
+    pub fn echo_requested(&self) -> Vec<u16> {
+        self.get(OPTION_ERO)
+            .map(|data| {
+                data.chunks_exact(2)
+                    .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Encapsulate `msg`, a client message or another relay's Relay-Forward,
+    /// in a Relay-Forward carrying this layer's header & options
+    pub fn forward(&self, msg: &[u8]) -> Vec<u8> {
+        encode(RELAY_FORW, self, &self.opts, msg)
+    }
+
+    /// Unwrap the outer layer of a Relay-Reply. Returns `None` if `buf` is not
+    /// a Relay-Reply, otherwise the layer & the message it carries, which is
+    /// another Relay-Reply if there are more relays between us & the client
+    pub fn unwrap_reply(buf: &[u8]) -> io::Result<Option<(Self, &[u8])>> {
+        if buf.first() != Some(&RELAY_REPL) {
+            return Ok(None);
+        }
+        parse_layer(buf).map(Some)
+    }
+
+    /// relay-supplied options that must be included in the Relay-Reply
+    fn echoed_opts(&self) -> impl Iterator<Item = &(u16, Vec<u8>)> {
+        let requested = self.echo_requested();
+        self.opts.iter().filter(move |(code, _)| {
+            *code == OPTION_INTERFACE_ID
+                || (requested.contains(code) && *code != OPTION_ERO && *code != OPTION_RELAY_MSG)
+        })
+    }
+}
+
+/// All relay layers a message passed through, outermost (closest to dora) first
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RelayInfo {
+    layers: Vec<RelayLayer>,
+}
+
+impl RelayInfo {
+    /// Unwrap a Relay-Forward message. Returns `None` if `buf` is not a
+    /// Relay-Forward, otherwise the relay layers & the encapsulated client message
+    pub fn unwrap(mut buf: &[u8]) -> io::Result<Option<(Self, &[u8])>> {
+        if buf.first() != Some(&RELAY_FORW) {
+            return Ok(None);
+        }
+        let mut layers = Vec::new();
+        while buf.first() == Some(&RELAY_FORW) {
+            if layers.len() >= HOP_COUNT_LIMIT {
+                return Err(Error::new(ErrorKind::InvalidData, "too many relay layers"));
+            }
+            let (layer, inner) = parse_layer(buf)?;
+            layers.push(layer);
+            buf = inner;
+        }
+        Ok(Some((Self { layers }, buf)))
+    }
+
+    /// relay layers, outermost first
+    pub fn layers(&self) -> &[RelayLayer] {
+        &self.layers
+    }
+
+    /// the relay closest to the client
+    pub fn innermost(&self) -> Option<&RelayLayer> {
+        self.layers.last()
+    }
+
+    /// Encapsulate `reply` in Relay-Reply messages matching the received
+    /// Relay-Forward layers
+    pub fn wrap(&self, reply: &[u8]) -> Vec<u8> {
+        self.layers
+            .iter()
+            .rev()
+            .fold(reply.to_vec(), |inner, layer| {
+                encode(RELAY_REPL, layer, layer.echoed_opts(), &inner)
+            })
+    }
+}
+
+/// parse the relay message in `buf`, returning its layer & the message it carries
+fn parse_layer(buf: &[u8]) -> io::Result<(RelayLayer, &[u8])> {
+    if buf.len() < HEADER_LEN {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "relay header too short",
+        ));
+    }
+    let hop_count = buf[1];
+    let link_addr = Ipv6Addr::from(<[u8; 16]>::try_from(&buf[2..18]).unwrap());
+    let peer_addr = Ipv6Addr::from(<[u8; 16]>::try_from(&buf[18..34]).unwrap());
+    let mut opts = Vec::new();
+    let mut inner = None;
+    let mut rest = &buf[HEADER_LEN..];
+    while !rest.is_empty() {
+        if rest.len() < 4 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "option too short"));
+        }
+        let code = u16::from_be_bytes([rest[0], rest[1]]);
+        let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        let data = rest
+            .get(4..4 + len)
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "option data too short"))?;
+        if code == OPTION_RELAY_MSG {
+            inner = Some(data);
+        } else {
+            opts.push((code, data.to_vec()));
+        }
+        rest = &rest[4 + len..];
+    }
+    let inner = inner.ok_or_else(|| Error::new(ErrorKind::InvalidData, "relay message missing"))?;
+    let layer = RelayLayer {
+        hop_count,
+        link_addr,
+        peer_addr,
+        opts,
+    };
+    Ok((layer, inner))
+}
+
+/// a relay message of `msg_type` with `layer`'s header, `opts` & `inner` as
+/// its Relay Message option
+fn encode<'a>(
+    msg_type: u8,
+    layer: &RelayLayer,
+    opts: impl IntoIterator<Item = &'a (u16, Vec<u8>)>,
+    inner: &[u8],
+) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + inner.len() + 4);
+    buf.push(msg_type);
+    buf.push(layer.hop_count);
+    buf.extend_from_slice(&layer.link_addr.octets());
+    buf.extend_from_slice(&layer.peer_addr.octets());
+    for (code, data) in opts {
+        write_opt(&mut buf, *code, data);
+    }
+    write_opt(&mut buf, OPTION_RELAY_MSG, inner);
+    buf
+}
+
+fn write_opt(buf: &mut Vec<u8>, code: u16, data: &[u8]) {
+    buf.extend_from_slice(&code.to_be_bytes());
+    buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn relay_forw(hop_count: u8, peer: Ipv6Addr, opts: &[(u16, &[u8])], inner: &[u8]) -> Vec<u8> {
+        let mut buf = vec![RELAY_FORW, hop_count];
+        buf.extend_from_slice(&Ipv6Addr::UNSPECIFIED.octets());
+        buf.extend_from_slice(&peer.octets());
+        for (code, data) in opts {
+            write_opt(&mut buf, *code, data);
+        }
+        write_opt(&mut buf, OPTION_RELAY_MSG, inner);
+        buf
+    }
+
+    #[test]
+    fn test_not_relay() {
+        // INFORMATION-REQUEST
+        assert_eq!(RelayInfo::unwrap(&[11, 1, 2, 3]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_nested_ero() {
+        let client_msg = [11, 1, 2, 3];
+        let client: Ipv6Addr = "fe80::1".parse().unwrap();
+        let relay1: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        // inner relay (closest to client) asks for remote-id (37) to be echoed
+        let inner = relay_forw(
+            0,
+            client,
+            &[
+                (OPTION_INTERFACE_ID, b"eth0"),
+                (37, &[0, 0, 0, 1, 0xaa]),
+                (38, &[0, 0, 0, 1, 0xbb]),
+                (OPTION_ERO, &37_u16.to_be_bytes()),
+            ],
+            &client_msg,
+        );
+        // outer relay asks for subscriber-id (38), which it does not include
+        let outer = relay_forw(1, relay1, &[(OPTION_ERO, &38_u16.to_be_bytes())], &inner);
+
+        let (info, msg) = RelayInfo::unwrap(&outer).unwrap().unwrap();
+        assert_eq!(msg, &client_msg);
+        assert_eq!(info.layers().len(), 2);
+        assert_eq!(info.layers()[0].peer_addr, relay1);
+        assert_eq!(info.innermost().unwrap().peer_addr, client);
+        assert_eq!(info.innermost().unwrap().echo_requested(), vec![37]);
+
+        let reply = [7, 1, 2, 3];
+        let wrapped = info.wrap(&reply);
+        assert_eq!(wrapped[0], RELAY_REPL);
+        // unwrap our own reply to inspect it, flip the msg types so we can reuse unwrap
+        let mut check = wrapped;
+        check[0] = RELAY_FORW;
+        check[HEADER_LEN + 4] = RELAY_FORW;
+        let (replied, msg) = RelayInfo::unwrap(&check).unwrap().unwrap();
+        assert_eq!(msg, &reply);
+        // outer layer: 38 was requested but not supplied, nothing echoed
+        assert!(replied.layers()[0].opts.is_empty());
+        // inner layer: interface-id always echoed, 37 requested, 38 & ERO are not
+        assert_eq!(
+            replied.layers()[1].opts,
+            vec![
+                (OPTION_INTERFACE_ID, b"eth0".to_vec()),
+                (37, vec![0, 0, 0, 1, 0xaa])
+            ]
+        );
+        assert_eq!(replied.layers()[1].hop_count, 0);
+    }
+
+    #[test]
+    fn test_forward() {
+        let client_msg = [1, 1, 2, 3];
+        let layer = RelayLayer {
+            hop_count: 0,
+            link_addr: "2001:db8::1".parse().unwrap(),
+            peer_addr: "fe80::1".parse().unwrap(),
+            opts: vec![(OPTION_INTERFACE_ID, b"eth0".to_vec())],
+        };
+        let forw = layer.forward(&client_msg);
+        let (info, msg) = RelayInfo::unwrap(&forw).unwrap().unwrap();
+        assert_eq!(msg, &client_msg);
+        assert_eq!(info.layers(), &[layer.clone()]);
+
+        // the server's reply comes back with the interface-id
+        let reply = [7, 1, 2, 3];
+        let wrapped = info.wrap(&reply);
+        let (replied, msg) = RelayLayer::unwrap_reply(&wrapped).unwrap().unwrap();
+        assert_eq!(msg, &reply);
+        assert_eq!(replied, layer);
+        assert_eq!(RelayLayer::unwrap_reply(&forw).unwrap(), None);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut buf = relay_forw(0, Ipv6Addr::LOCALHOST, &[], &[11, 1, 2, 3]);
+        buf.truncate(buf.len() - 2);
+        assert!(RelayInfo::unwrap(&buf).is_err());
+    }
+}
@@ -1,16 +1,28 @@
 //! Functions/types for reading incoming message from UDP
+//!
+//! Messages are read with `recvmmsg`, so a burst of broadcasts is drained a batch
+//! per syscall, and replies are queued & sent with `sendmmsg` by [`send_batches`].
+//! Replies go to different clients, so UDP GSO (one buffer, one destination)
+//! doesn't apply. With `--io-backend io-uring` both go through the rings of
+//! [`uring`] instead.
+//!
+//! [`uring`]: crate::server::uring
+use bytes::Bytes;
 use dhcproto::{Decodable, Encodable};
 use futures::ready;
 use pin_project::pin_project;
-// use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
 use tokio_stream::Stream;
-use tokio_util::codec::BytesCodec; // , udp::UdpFramed};
-use unix_udp_sock::{framed::UdpFramed, UdpSocket};
+use tracing::{debug, debug_span, error, field};
+use unix_udp_sock::{RecvMeta, Source, Transmit, UdpSocket, UdpState};
 
 use std::{
     borrow::Borrow,
-    io,
+    collections::VecDeque,
+    fmt,
+    io::{self, IoSliceMut},
     marker::PhantomData,
+    net::{IpAddr, SocketAddr},
     pin::Pin,
     sync::Arc,
     task::{self, Poll},
@@ -18,54 +30,327 @@ use std::{
 
 use crate::{
     handler::{MsgContext, State},
-    server::msg::SerialMsg,
+    server::{context::FromDatagram, msg::SerialMsg},
+    trace_filter::{self, Traceable, Traced},
 };
 
-/// Abstracts reading buffers off of a tokio `net::UdpStream` and converting
+/// most messages read, or sent, per syscall
+pub(crate) const BATCH_SIZE: usize = 32;
+/// read buffer size, DHCP messages are far smaller
+pub(crate) const MAX_DATAGRAM: usize = 16 * 1024;
+
+/// Abstracts reading buffers off of a `UdpSocket` in batches and converting
 /// that raw data into a stream of [`MsgContext`]
 ///
 /// [`MsgContext`]: crate::MsgContext
 #[pin_project]
-#[derive(Debug)]
 pub(crate) struct UdpStream<T, S> {
-    #[pin]
-    stream: UdpFramed<BytesCodec, S>,
+    recv: Recv<S>,
+    /// received messages not decoded yet
+    queue: VecDeque<(Bytes, RecvMeta)>,
     state: Arc<State>,
     _marker: PhantomData<T>,
 }
 
+impl<T, S> fmt::Debug for UdpStream<T, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdpStream")
+            .field("queued", &self.queue.len())
+            .finish()
+    }
+}
+
 impl<T, S> UdpStream<T, S>
 where
     T: Decodable + Encodable,
     S: Borrow<UdpSocket>,
 {
-    /// Create a new stream from a `UdpRecv`r and `State`
-    pub(crate) fn new(stream: S, state: Arc<State>) -> Self {
-        // we just want a stream of bytes, messages will be decoded later
+    /// Create a new stream from a `UdpSocket` and `State`
+    pub(crate) fn new(soc: S, state: Arc<State>) -> Self {
+        UdpStream {
+            recv: Recv::Socket {
+                soc,
+                bufs: vec![vec![0; MAX_DATAGRAM]; BATCH_SIZE],
+            },
+            queue: VecDeque::with_capacity(BATCH_SIZE),
+            state,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Create a new stream of the messages an io_uring thread reads
+    #[cfg(feature = "io-uring")]
+    pub(crate) fn from_ring(rx: mpsc::Receiver<(Bytes, RecvMeta)>, state: Arc<State>) -> Self {
         UdpStream {
-            stream: UdpFramed::new(stream, BytesCodec::new()),
+            recv: Recv::Ring(rx),
+            queue: VecDeque::with_capacity(BATCH_SIZE),
             state,
             _marker: PhantomData,
         }
     }
 }
 
+/// where a [`UdpStream`] gets its messages
+enum Recv<S> {
+    /// `recvmmsg` on the socket
+    Socket {
+        soc: S,
+        /// one buffer per message of a batch
+        bufs: Vec<Vec<u8>>,
+    },
+    /// messages read by an io_uring thread
+    #[cfg(feature = "io-uring")]
+    Ring(mpsc::Receiver<(Bytes, RecvMeta)>),
+}
+
 impl<T, S> Stream for UdpStream<T, S>
 where
-    T: Decodable + Encodable,
+    T: Decodable + Encodable + FromDatagram + Traceable + fmt::Display,
     S: Borrow<UdpSocket>,
 {
     type Item = io::Result<MsgContext<T>>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
         let pin = self.project();
-        match ready!(pin.stream.poll_next(cx)) {
-            Some(res) => {
-                let (buf, meta) = res?;
-                let msg = SerialMsg::new(buf.freeze(), meta.addr);
-                Poll::Ready(Some(Ok(MsgContext::new(msg, meta, Arc::clone(pin.state))?)))
+        while pin.queue.is_empty() {
+            match pin.recv {
+                Recv::Socket { soc, bufs } => {
+                    let soc: &UdpSocket = (*soc).borrow();
+                    let mut meta = [RecvMeta::default(); BATCH_SIZE];
+                    let mut slices = bufs
+                        .iter_mut()
+                        .map(|buf| IoSliceMut::new(buf))
+                        .collect::<Vec<_>>();
+                    let n = ready!(soc.poll_recv_mmsg(cx, &mut slices, &mut meta))?;
+                    for (buf, meta) in bufs.iter().zip(meta).take(n) {
+                        pin.queue
+                            .push_back((Bytes::copy_from_slice(&buf[..meta.len]), meta));
+                    }
+                }
+                #[cfg(feature = "io-uring")]
+                Recv::Ring(rx) => match ready!(rx.poll_recv(cx)) {
+                    Some(recv) => pin.queue.push_back(recv),
+                    None => return Poll::Ready(None),
+                },
+            }
+        }
+        let (buf, meta) = pin.queue.pop_front().expect("a batch was just read");
+        let src = meta.addr;
+        let msg = SerialMsg::new(buf, src);
+        let span = debug_span!("request", %src, id = field::Empty);
+        let mut ctx = span.in_scope(|| {
+            debug_span!("decode").in_scope(|| T::from_datagram(msg, meta, Arc::clone(pin.state)))
+        })?;
+        span.record("id", ctx.id());
+        if trace_filter::is_traced(ctx.decoded_msg()) {
+            // the name is `trace_filter::SPAN`, debug events inside are always logged
+            let traced = debug_span!(parent: &span, "traced", %src, id = ctx.id());
+            traced.in_scope(|| {
+                debug!(
+                    msg = %ctx.decoded_msg(),
+                    bytes = %trace_filter::hex(ctx.bytes()),
+                    "received packet"
+                )
+            });
+            ctx.set_local(Traced);
+            ctx.set_span(traced);
+        } else {
+            ctx.set_span(span);
+        }
+        Poll::Ready(Some(Ok(ctx)))
+    }
+}
+
+/// a reply queued to be sent
+#[derive(Debug, Clone)]
+pub(crate) struct Reply {
+    pub(crate) dst: SocketAddr,
+    pub(crate) bytes: Bytes,
+    pub(crate) src: Option<ReplySource>,
+}
+
+/// what a reply is sent from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ReplySource {
+    /// source address, the one the request was sent to
+    Ip(IpAddr),
+    /// interface index, the one the request arrived on
+    Interface(u32),
+}
+
+impl Reply {
+    fn transmit(self) -> Transmit<Bytes> {
+        let transmit = Transmit::new(self.dst, self.bytes);
+        match self.src {
+            Some(ReplySource::Ip(ip)) => transmit.src_ip(Source::Ip(ip)),
+            Some(ReplySource::Interface(idx)) => transmit.src_ip(Source::Interface(idx)),
+            None => transmit,
+        }
+    }
+}
+
+/// send the replies queued on `rx` from `soc`, everything waiting (up to a batch)
+/// goes out in one `sendmmsg`. `sendmmsg` needs the socket to itself, so it is
+/// given its own handle (see [`try_clone`])
+pub(crate) async fn send_batches(mut soc: UdpSocket, mut rx: mpsc::Receiver<Reply>) {
+    let state = UdpState::new();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some(reply) = rx.recv().await {
+        batch.push(reply.transmit());
+        while batch.len() < BATCH_SIZE {
+            match rx.try_recv() {
+                Ok(reply) => batch.push(reply.transmit()),
+                Err(_) => break,
+            }
+        }
+        let mut sent = 0;
+        while sent < batch.len() {
+            match soc.send_mmsg(&state, &batch[sent..]).await {
+                Ok(n) => sent += n,
+                // the first message failed, the rest may still go out
+                Err(err) => {
+                    error!(?err, "failed to send reply");
+                    sent += 1;
+                }
+            }
+        }
+        batch.clear();
+    }
+}
+
+/// a second handle on `soc`, sharing the same underlying socket
+pub(crate) fn try_clone(soc: &UdpSocket) -> io::Result<UdpSocket> {
+    let dup: std::net::UdpSocket = socket2::SockRef::from(soc).try_clone()?.into();
+    UdpSocket::from_std(dup)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use dhcproto::v4;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::server::ioctl;
+
+    const LOCALHOST: Ipv4Addr = Ipv4Addr::LOCALHOST;
+
+    fn msg(xid: u32) -> Bytes {
+        let msg = v4::Message::new_with_id(
+            xid,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            &[0, 1, 2, 3, 4, 5],
+        );
+        Bytes::from(msg.to_vec().unwrap())
+    }
+
+    fn reply(dst: SocketAddr, xid: u32) -> Reply {
+        Reply {
+            dst,
+            bytes: msg(xid),
+            src: None,
+        }
+    }
+
+    /// xid of the next message on `soc`, `None` if nothing arrives
+    async fn recv_xid(soc: &tokio::net::UdpSocket) -> Option<u32> {
+        let mut buf = [0; 1500];
+        let len = tokio::time::timeout(Duration::from_millis(200), soc.recv(&mut buf))
+            .await
+            .ok()?
+            .unwrap();
+        let msg = v4::Message::decode(&mut dhcproto::Decoder::new(&buf[..len])).unwrap();
+        Some(msg.xid())
+    }
+
+    #[tokio::test]
+    async fn test_recv_batch() {
+        let soc = UdpSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let addr = soc.local_addr().unwrap();
+        let client = tokio::net::UdpSocket::bind((LOCALHOST, 0)).await.unwrap();
+        for xid in 1..=3 {
+            client.send_to(&msg(xid), addr).await.unwrap();
+        }
+
+        let mut stream = UdpStream::<v4::Message, _>::new(soc, Arc::new(State::new(10)));
+        let ctx = stream.next().await.unwrap().unwrap();
+        assert_eq!(ctx.decoded_msg().xid(), 1);
+        // the others came in the same recvmmsg
+        assert_eq!(stream.queue.len(), 2);
+        for xid in 2..=3 {
+            let ctx = stream.next().await.unwrap().unwrap();
+            assert_eq!(ctx.decoded_msg().xid(), xid);
+            assert_eq!(ctx.meta().addr, client.local_addr().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_batch() {
+        let soc = UdpSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let client = tokio::net::UdpSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let dst = client.local_addr().unwrap();
+
+        let (tx, rx) = mpsc::channel(BATCH_SIZE);
+        for xid in 1..=3 {
+            tx.send(reply(dst, xid)).await.unwrap();
+        }
+        drop(tx);
+        // returns once the queue is closed & drained
+        send_batches(soc, rx).await;
+        for xid in 1..=3 {
+            assert_eq!(recv_xid(&client).await, Some(xid));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_partial_batch() {
+        let soc = UdpSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let client = tokio::net::UdpSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let dst = client.local_addr().unwrap();
+
+        // port 0 is rejected by the kernel, sendmmsg stops at it
+        let (tx, rx) = mpsc::channel(BATCH_SIZE);
+        tx.send(reply(dst, 1)).await.unwrap();
+        tx.send(reply((LOCALHOST, 0).into(), 2)).await.unwrap();
+        tx.send(reply(dst, 3)).await.unwrap();
+        drop(tx);
+        send_batches(soc, rx).await;
+        assert_eq!(recv_xid(&client).await, Some(1));
+        assert_eq!(recv_xid(&client).await, Some(3));
+        assert_eq!(recv_xid(&client).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_unicast_only() {
+        let bind = || tokio::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0));
+        let filtered = bind().await.unwrap();
+        ioctl::unicast_only(socket2::SockRef::from(&filtered)).unwrap();
+        let unfiltered = bind().await.unwrap();
+        let client = bind().await.unwrap();
+        client.set_broadcast(true).unwrap();
+
+        for (xid, soc) in [(1, &filtered), (2, &unfiltered)] {
+            let port = soc.local_addr().unwrap().port();
+            // the copy of a broadcast looped back from the interface it went out
+            // on isn't PACKET_HOST. lo hands everything up as PACKET_HOST, so
+            // this needs a default route
+            if client
+                .send_to(&msg(xid), (Ipv4Addr::BROADCAST, port))
+                .await
+                .is_err()
+            {
+                return;
             }
-            None => Poll::Ready(None),
+            client.send_to(&msg(xid), (LOCALHOST, port)).await.unwrap();
         }
+        // the filter only lets the unicast copy through
+        assert_eq!(recv_xid(&filtered).await, Some(1));
+        assert_eq!(recv_xid(&filtered).await, None);
+        assert_eq!(recv_xid(&unfiltered).await, Some(2));
+        assert_eq!(recv_xid(&unfiltered).await, Some(2));
     }
 }
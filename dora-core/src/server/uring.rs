@@ -0,0 +1,398 @@
+//! io_uring packet path, see `--io-backend`
+//!
+//! Sockets are created & configured as usual, only their reads & writes go
+//! through io_uring. Each socket gets two threads with a ring of their own: one
+//! keeps a batch of `RecvMsg` submitted & passes what arrives to the server, the
+//! other submits queued replies as `SendMsg`, a batch per `io_uring_enter`.
+//!
+//! The kernel writes into the buffers of submitted operations until they
+//! complete. Buffers of operations still in flight when a thread exits are
+//! leaked rather than freed under it.
+use std::{
+    io, mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::unix::prelude::{AsRawFd, RawFd},
+    ptr,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use io_uring::{opcode, squeue, types, IoUring};
+use socket2::SockAddr;
+use tokio::sync::mpsc;
+use tracing::{debug, error};
+use unix_udp_sock::{RecvMeta, UdpSocket};
+
+use crate::server::udp::{Reply, ReplySource, BATCH_SIZE, MAX_DATAGRAM};
+
+/// submission queue entries per ring, enough for a batch
+const RING_ENTRIES: u32 = 64;
+/// seconds the receive thread waits before checking if the server stopped
+const RECV_TICK: u64 = 1;
+/// pause before resubmitting receives the kernel had no buffers for
+const RECV_BACKOFF: Duration = Duration::from_millis(10);
+
+/// read `soc` into `received` & send the replies queued on `replies`, each on
+/// its own thread
+pub(crate) fn start(
+    soc: Arc<UdpSocket>,
+    received: mpsc::Sender<(Bytes, RecvMeta)>,
+    replies: mpsc::Receiver<Reply>,
+) -> io::Result<()> {
+    let recv_ring = IoUring::new(RING_ENTRIES)?;
+    let send_ring = IoUring::new(RING_ENTRIES)?;
+    let recv_soc = Arc::clone(&soc);
+    thread::Builder::new()
+        .name("dora-uring-recv".to_owned())
+        .spawn(move || {
+            if let Err(err) = recv_loop(recv_ring, &recv_soc, received) {
+                error!(?err, "io_uring receive thread failed");
+            }
+        })?;
+    thread::Builder::new()
+        .name("dora-uring-send".to_owned())
+        .spawn(move || {
+            if let Err(err) = send_loop(send_ring, &soc, replies) {
+                error!(?err, "io_uring send thread failed");
+            }
+        })?;
+    Ok(())
+}
+
+fn recv_loop(
+    mut ring: IoUring,
+    soc: &UdpSocket,
+    received: mpsc::Sender<(Bytes, RecvMeta)>,
+) -> io::Result<()> {
+    let fd = soc.as_raw_fd();
+    let mut slots = (0..BATCH_SIZE).map(|_| RecvSlot::new()).collect::<Vec<_>>();
+    let res = (|| {
+        for (i, slot) in slots.iter_mut().enumerate() {
+            push(&mut ring, &slot.entry(fd, i))?;
+        }
+        let tick = types::Timespec::new().sec(RECV_TICK);
+        let args = types::SubmitArgs::new().timespec(&tick);
+        while !received.is_closed() {
+            match ring.submitter().submit_with_args(1, &args) {
+                Ok(_) => {}
+                Err(err)
+                    if err.raw_os_error() == Some(libc::ETIME)
+                        || err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+            let done = ring
+                .completion()
+                .map(|cqe| (cqe.user_data() as usize, cqe.result()))
+                .collect::<Vec<_>>();
+            let mut backoff = false;
+            for (i, res) in done {
+                let slot = &mut slots[i];
+                match usize::try_from(res) {
+                    Ok(len) => {
+                        if let Some(meta) = slot.meta(len) {
+                            let buf = Bytes::copy_from_slice(&slot.buf[..len]);
+                            if received.blocking_send((buf, meta)).is_err() {
+                                return Ok(());
+                            }
+                        }
+                    }
+                    Err(_) => {
+                        let err = io::Error::from_raw_os_error(-res);
+                        match -res {
+                            // the socket is gone, every resubmit fails the same way
+                            libc::EBADF | libc::ENOTSOCK => return Err(err),
+                            libc::ENOBUFS | libc::ENOMEM => {
+                                debug!(?err, "io_uring receive out of buffers");
+                                backoff = true;
+                            }
+                            _ => debug!(?err, "io_uring receive failed"),
+                        }
+                    }
+                }
+                push(&mut ring, &slot.entry(fd, i))?;
+            }
+            if backoff {
+                thread::sleep(RECV_BACKOFF);
+            }
+        }
+        Ok(())
+    })();
+    // receives are still submitted
+    mem::forget(slots);
+    res
+}
+
+fn send_loop(
+    mut ring: IoUring,
+    soc: &UdpSocket,
+    mut replies: mpsc::Receiver<Reply>,
+) -> io::Result<()> {
+    let fd = soc.as_raw_fd();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some(reply) = replies.blocking_recv() {
+        batch.push(SendSlot::new(reply));
+        while batch.len() < BATCH_SIZE {
+            match replies.try_recv() {
+                Ok(reply) => batch.push(SendSlot::new(reply)),
+                Err(_) => break,
+            }
+        }
+        for (i, slot) in batch.iter_mut().enumerate() {
+            push(&mut ring, &slot.entry(fd, i))?;
+        }
+        let mut done = 0;
+        while done < batch.len() {
+            match ring.submit_and_wait(batch.len() - done) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    // sends are still submitted
+                    mem::forget(batch);
+                    return Err(err);
+                }
+            }
+            for cqe in ring.completion() {
+                done += 1;
+                if cqe.result() < 0 {
+                    let err = io::Error::from_raw_os_error(-cqe.result());
+                    let dst = batch[cqe.user_data() as usize].reply.dst;
+                    error!(?err, ?dst, "failed to send reply");
+                }
+            }
+        }
+        batch.clear();
+    }
+    Ok(())
+}
+
+/// queue `entry` on the submission ring
+fn push(ring: &mut IoUring, entry: &squeue::Entry) -> io::Result<()> {
+    // SAFETY: the buffers `entry` points to are boxed & live until it completes
+    unsafe { ring.submission().push(entry) }
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "io_uring submission queue full"))
+}
+
+/// control message buffer, aligned for `cmsghdr`
+#[repr(align(8))]
+struct Control([u8; 64]);
+
+/// the buffers of one `RecvMsg`, boxed so the kernel's pointers stay valid
+struct RecvSlot {
+    buf: Vec<u8>,
+    addr: libc::sockaddr_storage,
+    control: Control,
+    iov: libc::iovec,
+    hdr: libc::msghdr,
+}
+
+impl RecvSlot {
+    fn new() -> Box<Self> {
+        Box::new(Self {
+            buf: vec![0; MAX_DATAGRAM],
+            // SAFETY: plain C structs, all zeroes is valid
+            addr: unsafe { mem::zeroed() },
+            control: Control([0; 64]),
+            iov: unsafe { mem::zeroed() },
+            hdr: unsafe { mem::zeroed() },
+        })
+    }
+
+    /// a `RecvMsg` into this slot, `msghdr` lengths are reset as the kernel
+    /// overwrites them
+    fn entry(&mut self, fd: RawFd, i: usize) -> squeue::Entry {
+        self.iov.iov_base = self.buf.as_mut_ptr().cast();
+        self.iov.iov_len = self.buf.len();
+        self.hdr.msg_name = ptr::addr_of_mut!(self.addr).cast();
+        self.hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+        self.hdr.msg_iov = &mut self.iov;
+        self.hdr.msg_iovlen = 1;
+        self.hdr.msg_control = self.control.0.as_mut_ptr().cast();
+        self.hdr.msg_controllen = self.control.0.len() as _;
+        self.hdr.msg_flags = 0;
+        opcode::RecvMsg::new(types::Fd(fd), &mut self.hdr)
+            .build()
+            .user_data(i as u64)
+    }
+
+    /// where a message of `len` bytes came from & the interface it arrived on,
+    /// from IP_PKTINFO/IPV6_PKTINFO
+    fn meta(&self, len: usize) -> Option<RecvMeta> {
+        // SAFETY: the kernel wrote `msg_namelen` bytes of address
+        let addr = unsafe { SockAddr::new(self.addr, self.hdr.msg_namelen) }.as_socket()?;
+        let mut meta = RecvMeta {
+            addr,
+            len,
+            ..RecvMeta::default()
+        };
+        // SAFETY: the kernel wrote `msg_controllen` bytes of control messages
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&self.hdr);
+            while !cmsg.is_null() {
+                match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                    (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                        let info =
+                            ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::in_pktinfo);
+                        meta.ifindex = info.ipi_ifindex as u32;
+                        meta.dst_ip = Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                            info.ipi_addr.s_addr,
+                        ))));
+                        meta.dst_local_ip = Some(IpAddr::V4(Ipv4Addr::from(u32::from_be(
+                            info.ipi_spec_dst.s_addr,
+                        ))));
+                    }
+                    (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                        let info =
+                            ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::in6_pktinfo);
+                        meta.ifindex = info.ipi6_ifindex;
+                        meta.dst_ip = Some(IpAddr::V6(Ipv6Addr::from(info.ipi6_addr.s6_addr)));
+                    }
+                    _ => {}
+                }
+                cmsg = libc::CMSG_NXTHDR(&self.hdr, cmsg);
+            }
+        }
+        Some(meta)
+    }
+}
+
+/// a reply & the buffers of its `SendMsg`, boxed so the kernel's pointers stay
+/// valid
+struct SendSlot {
+    reply: Reply,
+    addr: SockAddr,
+    control: Control,
+    iov: libc::iovec,
+    hdr: libc::msghdr,
+}
+
+impl SendSlot {
+    fn new(reply: Reply) -> Box<Self> {
+        Box::new(Self {
+            addr: SockAddr::from(reply.dst),
+            reply,
+            control: Control([0; 64]),
+            // SAFETY: plain C structs, all zeroes is valid
+            iov: unsafe { mem::zeroed() },
+            hdr: unsafe { mem::zeroed() },
+        })
+    }
+
+    fn entry(&mut self, fd: RawFd, i: usize) -> squeue::Entry {
+        self.iov.iov_base = self.reply.bytes.as_ptr() as *mut _;
+        self.iov.iov_len = self.reply.bytes.len();
+        self.hdr.msg_name = self.addr.as_ptr() as *mut _;
+        self.hdr.msg_namelen = self.addr.len();
+        self.hdr.msg_iov = &mut self.iov;
+        self.hdr.msg_iovlen = 1;
+        if let Some(src) = self.reply.src {
+            self.set_source(src);
+        }
+        opcode::SendMsg::new(types::Fd(fd), &self.hdr)
+            .build()
+            .user_data(i as u64)
+    }
+
+    /// send from `src` with an IP_PKTINFO/IPV6_PKTINFO control message
+    fn set_source(&mut self, src: ReplySource) {
+        self.hdr.msg_control = self.control.0.as_mut_ptr().cast();
+        self.hdr.msg_controllen = self.control.0.len() as _;
+        // SAFETY: `control` fits either pktinfo & is aligned for `cmsghdr`
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&self.hdr);
+            let len = if self.reply.dst.is_ipv4() {
+                let mut info: libc::in_pktinfo = mem::zeroed();
+                match src {
+                    ReplySource::Ip(IpAddr::V4(ip)) => {
+                        info.ipi_spec_dst.s_addr = u32::from_ne_bytes(ip.octets())
+                    }
+                    ReplySource::Interface(idx) => info.ipi_ifindex = idx as _,
+                    ReplySource::Ip(IpAddr::V6(_)) => return self.clear_control(),
+                }
+                (*cmsg).cmsg_level = libc::IPPROTO_IP;
+                (*cmsg).cmsg_type = libc::IP_PKTINFO;
+                ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::in_pktinfo, info);
+                mem::size_of::<libc::in_pktinfo>() as u32
+            } else {
+                let mut info: libc::in6_pktinfo = mem::zeroed();
+                match src {
+                    ReplySource::Ip(IpAddr::V6(ip)) => info.ipi6_addr.s6_addr = ip.octets(),
+                    ReplySource::Interface(idx) => info.ipi6_ifindex = idx,
+                    ReplySource::Ip(IpAddr::V4(_)) => return self.clear_control(),
+                }
+                (*cmsg).cmsg_level = libc::IPPROTO_IPV6;
+                (*cmsg).cmsg_type = libc::IPV6_PKTINFO;
+                ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut libc::in6_pktinfo, info);
+                mem::size_of::<libc::in6_pktinfo>() as u32
+            };
+            (*cmsg).cmsg_len = libc::CMSG_LEN(len) as _;
+            self.hdr.msg_controllen = libc::CMSG_SPACE(len) as _;
+        }
+    }
+
+    /// send without a control message
+    fn clear_control(&mut self) {
+        self.hdr.msg_control = ptr::null_mut();
+        self.hdr.msg_controllen = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let soc = Arc::new(UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap());
+        let addr = soc.local_addr().unwrap();
+        let (received_tx, mut received) = mpsc::channel(BATCH_SIZE);
+        let (replies, replies_rx) = mpsc::channel(BATCH_SIZE);
+        start(soc, received_tx, replies_rx).unwrap();
+
+        let client = tokio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .unwrap();
+        let client_addr = client.local_addr().unwrap();
+        for msg in [&b"one"[..], b"two"] {
+            client.send_to(msg, addr).await.unwrap();
+        }
+        for msg in [&b"one"[..], b"two"] {
+            let (buf, meta) = tokio::time::timeout(Duration::from_secs(1), received.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(buf, msg);
+            assert_eq!(meta.addr, client_addr);
+            assert_eq!(meta.len, msg.len());
+            assert_eq!(meta.dst_ip, Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+            assert_ne!(meta.ifindex, 0);
+        }
+
+        for (msg, src) in [
+            (&b"three"[..], None),
+            (b"four", Some(ReplySource::Ip(Ipv4Addr::LOCALHOST.into()))),
+        ] {
+            replies
+                .send(Reply {
+                    dst: client_addr,
+                    bytes: Bytes::from_static(msg),
+                    src,
+                })
+                .await
+                .unwrap();
+        }
+        let mut buf = [0; 16];
+        for msg in [&b"three"[..], b"four"] {
+            let (len, from) =
+                tokio::time::timeout(Duration::from_secs(1), client.recv_from(&mut buf))
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(&buf[..len], msg);
+            assert_eq!(from, addr);
+        }
+    }
+}
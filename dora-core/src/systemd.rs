@@ -0,0 +1,127 @@
+//! systemd integration: readiness & watchdog notifications (`sd_notify(3)`) and
+//! sockets passed by socket activation (`sd_listen_fds(3)`).
+//!
+//! Everything here is a no-op when dora isn't started by systemd, see
+//! `Type=notify` & `WatchdogSec=` in `systemd.service(5)` and `systemd.socket(5)`.
+use std::{
+    env, io, mem,
+    net::UdpSocket,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{FromRawFd, OwnedFd, RawFd},
+    },
+    process,
+    time::Duration,
+};
+
+use tracing::{debug, warn};
+
+/// first fd passed by socket activation
+const LISTEN_FDS_START: RawFd = 3;
+
+/// send `state` (ex. `READY=1`) to the service manager. Returns `false` if dora
+/// wasn't started with a `NOTIFY_SOCKET`
+pub fn notify(state: &str) -> io::Result<bool> {
+    let path = match env::var_os("NOTIFY_SOCKET") {
+        Some(path) => path,
+        None => return Ok(false),
+    };
+    let path = path.as_bytes();
+    // SAFETY: sockaddr_un is plain data, all zeroes is valid
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    if path.is_empty() || path.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "invalid NOTIFY_SOCKET",
+        ));
+    }
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    for (dst, src) in addr.sun_path.iter_mut().zip(path) {
+        *dst = *src as libc::c_char;
+    }
+    // a leading '@' is an abstract socket, its name starts with a null byte
+    if path[0] == b'@' {
+        addr.sun_path[0] = 0;
+    }
+    let len = mem::size_of::<libc::sa_family_t>() + path.len();
+
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_DGRAM, 0) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd was just created & is owned by nothing else
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let sent = unsafe {
+        libc::sendto(
+            std::os::unix::io::AsRawFd::as_raw_fd(&fd),
+            state.as_ptr().cast(),
+            state.len(),
+            0,
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len as libc::socklen_t,
+        )
+    };
+    if sent == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(true)
+}
+
+/// how often the service manager expects a watchdog ping, if it's enabled for
+/// this process
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != process::id() {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// ping the watchdog at half the `interval` systemd expects. The pings come
+/// from a task on the runtime, so if the runtime hangs systemd will restart
+/// dora. Never returns
+pub async fn watchdog(interval: Duration) {
+    debug!(?interval, "pinging systemd watchdog");
+    let mut ticker = tokio::time::interval(interval / 2);
+    loop {
+        ticker.tick().await;
+        if let Err(err) = notify("WATCHDOG=1") {
+            warn!(?err, "failed to ping systemd watchdog");
+        }
+    }
+}
+
+/// take the UDP sockets passed by socket activation, fds of any other type are
+/// closed. The environment variables are removed so child processes don't
+/// inherit them, which makes this unsafe to call once other threads are running
+pub fn listen_sockets() -> Vec<UdpSocket> {
+    let pid = env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok());
+    let fds = env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<RawFd>().ok());
+    for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(var);
+    }
+    let fds = match (pid, fds) {
+        (Some(pid), Some(fds)) if pid == process::id() => fds,
+        _ => return Vec::new(),
+    };
+    (LISTEN_FDS_START..LISTEN_FDS_START + fds)
+        .filter_map(|fd| {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            // SAFETY: systemd passed ownership of fds starting at LISTEN_FDS_START
+            let soc = unsafe { UdpSocket::from_raw_fd(fd) };
+            match socket2::SockRef::from(&soc).r#type() {
+                Ok(socket2::Type::DGRAM) => Some(soc),
+                ty => {
+                    warn!(fd, ?ty, "ignoring socket from systemd, not a UDP socket");
+                    None
+                }
+            }
+        })
+        .collect()
+}
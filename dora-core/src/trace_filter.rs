@@ -0,0 +1,206 @@
+//! # trace filter
+//!
+//! A filter set at runtime (from the external api) picking a few clients by
+//! MAC, client id or subnet. Their messages are handled in a `traced` span:
+//! debug events inside it are logged whatever `dora_log` is, and the packets
+//! sent & received are dumped in hex. Other clients are logged as usual, so one
+//! device can be followed on a busy server.
+//!
+//! The filter is process wide, it applies to every instance.
+use std::{
+    net::Ipv4Addr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        PoisonError, RwLock,
+    },
+};
+
+use dhcproto::{v4, v6};
+use ipnet::Ipv4Net;
+use pnet::util::MacAddr;
+use tracing::{level_filters::LevelFilter, subscriber::Interest, Level, Metadata, Subscriber};
+use tracing_subscriber::{
+    layer::{Context, Filter},
+    registry::LookupSpan,
+};
+
+/// name of the span traced messages are handled in
+pub(crate) const SPAN: &str = "traced";
+
+/// set while the filter isn't empty, checked before taking the lock
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static FILTER: RwLock<TraceFilter> = RwLock::new(TraceFilter {
+    macs: Vec::new(),
+    client_ids: Vec::new(),
+    subnets: Vec::new(),
+});
+
+/// clients to trace, a message matching any entry is traced
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// v4 `chaddr`, or the link-layer address ending a v6 client's DUID
+    pub macs: Vec<MacAddr>,
+    /// v4 client identifier (opt 61) or v6 client DUID
+    pub client_ids: Vec<Vec<u8>>,
+    /// v4 subnets holding the client's `ciaddr`, requested address (opt 50) or
+    /// relay (`giaddr`)
+    pub subnets: Vec<Ipv4Net>,
+}
+
+impl TraceFilter {
+    /// true if the filter traces nothing
+    pub fn is_empty(&self) -> bool {
+        self.macs.is_empty() && self.client_ids.is_empty() && self.subnets.is_empty()
+    }
+}
+
+/// replace the filter, an empty one stops tracing
+pub fn set(filter: TraceFilter) {
+    let active = !filter.is_empty();
+    *FILTER.write().unwrap_or_else(PoisonError::into_inner) = filter;
+    ACTIVE.store(active, Ordering::Relaxed);
+}
+
+/// the current filter
+pub fn get() -> TraceFilter {
+    FILTER
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// set as a local on the [`MsgContext`] of traced messages
+///
+/// [`MsgContext`]: crate::handler::MsgContext
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Traced;
+
+/// messages that can be matched against a [`TraceFilter`]
+pub(crate) trait Traceable {
+    fn traced_by(&self, filter: &TraceFilter) -> bool;
+}
+
+impl Traceable for v4::Message {
+    fn traced_by(&self, filter: &TraceFilter) -> bool {
+        let chaddr = self.chaddr();
+        let opts = self.opts();
+        let client_id = match opts.get(v4::OptionCode::ClientIdentifier) {
+            Some(v4::DhcpOption::ClientIdentifier(id)) => Some(&id[..]),
+            _ => None,
+        };
+        let requested = match opts.get(v4::OptionCode::RequestedIpAddress) {
+            Some(v4::DhcpOption::RequestedIpAddress(ip)) => Some(*ip),
+            _ => None,
+        };
+        let in_subnet = |ip: Ipv4Addr| {
+            !ip.is_unspecified() && filter.subnets.iter().any(|net| net.contains(&ip))
+        };
+        filter
+            .macs
+            .iter()
+            .any(|mac| chaddr == octets(*mac).as_slice())
+            || client_id.map_or(false, |id| filter.client_ids.iter().any(|c| c == id))
+            || [Some(self.ciaddr()), Some(self.giaddr()), requested]
+                .into_iter()
+                .flatten()
+                .any(in_subnet)
+    }
+}
+
+impl Traceable for v6::Message {
+    fn traced_by(&self, filter: &TraceFilter) -> bool {
+        let Some(v6::DhcpOption::ClientId(duid)) = self.opts().get(v6::OptionCode::ClientId) else {
+            return false;
+        };
+        filter.client_ids.iter().any(|id| id == duid)
+            || filter.macs.iter().any(|mac| duid.ends_with(&octets(*mac)))
+    }
+}
+
+fn octets(mac: MacAddr) -> [u8; 6] {
+    [mac.0, mac.1, mac.2, mac.3, mac.4, mac.5]
+}
+
+/// true if `msg` matches the current filter
+pub(crate) fn is_traced<T: Traceable>(msg: &T) -> bool {
+    ACTIVE.load(Ordering::Relaxed)
+        && msg.traced_by(&FILTER.read().unwrap_or_else(PoisonError::into_inner))
+}
+
+/// bytes as hex, for packet dumps
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// a per-layer [`Filter`] enabling debug events inside `traced` spans. Combine
+/// it with the log filter using [`FilterExt::or`]
+///
+/// [`FilterExt::or`]: tracing_subscriber::filter::FilterExt::or
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EnableTraced;
+
+impl<S> Filter<S> for EnableTraced
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        if !ACTIVE.load(Ordering::Relaxed) || *meta.level() > Level::DEBUG {
+            return false;
+        }
+        if meta.is_span() && meta.name() == SPAN {
+            return true;
+        }
+        cx.lookup_current()
+            .map_or(false, |span| span.scope().any(|span| span.name() == SPAN))
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        if *meta.level() > Level::DEBUG {
+            Interest::never()
+        } else {
+            Interest::sometimes()
+        }
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::DEBUG)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traced_by() {
+        let mut msg = v4::Message::new(
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::new(10, 0, 1, 1),
+            &[0, 1, 2, 3, 4, 5],
+        );
+        msg.opts_mut()
+            .insert(v4::DhcpOption::ClientIdentifier(vec![1, 0, 1, 2, 3, 4, 5]));
+
+        let mut filter = TraceFilter::default();
+        assert!(!msg.traced_by(&filter));
+        filter.macs = vec![MacAddr::new(0, 1, 2, 3, 4, 5)];
+        assert!(msg.traced_by(&filter));
+
+        let filter = TraceFilter {
+            client_ids: vec![vec![1, 0, 1, 2, 3, 4, 5]],
+            ..TraceFilter::default()
+        };
+        assert!(msg.traced_by(&filter));
+
+        // giaddr is in the subnet
+        let mut filter = TraceFilter {
+            subnets: vec!["10.0.1.0/24".parse().unwrap()],
+            ..TraceFilter::default()
+        };
+        assert!(msg.traced_by(&filter));
+        filter.subnets = vec!["10.0.2.0/24".parse().unwrap()];
+        assert!(!msg.traced_by(&filter));
+    }
+}
@@ -0,0 +1,54 @@
+#![cfg(target_os = "linux")]
+//! switching user changes the whole process, so this runs in its own test binary
+use std::net::UdpSocket;
+
+use caps::{CapSet, CapsHashSet};
+use dora_core::{privs, tokio::runtime::Builder};
+
+const WORKERS: usize = 4;
+
+#[test]
+fn test_drop_privileges() {
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("not running as root, skipping");
+        return;
+    }
+    let rt = Builder::new_multi_thread()
+        .worker_threads(WORKERS)
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        privs::drop_privileges("nobody", None, &[], WORKERS)
+            .await
+            .unwrap();
+        let kept = privs::KEPT_CAPS.into_iter().collect::<CapsHashSet>();
+        // whichever worker they run on
+        let tasks = (0..WORKERS * 4)
+            .map(|_| {
+                dora_core::tokio::spawn(async {
+                    (
+                        unsafe { libc::getuid() },
+                        caps::read(None, CapSet::Permitted).unwrap(),
+                        caps::read(None, CapSet::Effective).unwrap(),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            let (uid, permitted, effective) = task.await.unwrap();
+            assert_ne!(uid, 0);
+            assert_eq!(permitted, kept);
+            assert_eq!(effective, kept);
+        }
+        // CAP_NET_BIND_SERVICE, 1021 is reserved for experiments (RFC 4727)
+        dora_core::tokio::spawn(async { UdpSocket::bind("127.0.0.1:1021").map(drop) })
+            .await
+            .unwrap()
+            .unwrap();
+    });
+    assert!(
+        caps::read(None, CapSet::Permitted).unwrap().is_empty(),
+        "a thread outside the runtime keeps nothing"
+    );
+}
@@ -5,6 +5,56 @@
 //!
 //! /health
 //! /ping
+//! /metrics
+//! /metrics-text
+//! /v4/hostnames/conflicts
+//! /v4/leases?q=..&sort=ip&order=asc&limit=500&offset=0
+//! /v4/leases/events (server-sent events)
+//! /v6/server-id
+//! /v6/other-config (DNS servers & domains of each v6 network, for an RA daemon)
+//! /stats/pools?range=24h
+//! /config/diff (POST a config, returns what would change)
+//! /config/versions (GET lists stored configs, POST `?note=..` stores a new version)
+//! /config/versions/:version (GET a stored config)
+//! /config/versions/:version/rollback (POST stores a copy of an old version as the newest)
+//! /v4/forcerenew (POST `{"ip"|"network"|"class": ..}`, sends FORCERENEW)
+//! /v4/sticky (GET lists sticky leases, POST `{"ip": ..}` makes a lease sticky)
+//! /v4/sticky/:ip (DELETE)
+//! /v4/pins (GET lists pins, POST `{"ip": .., "mac"|"client_id": .., "ttl": ..}` holds an address for a client)
+//! /v4/pins/:ip (DELETE)
+//! /v4/static-hosts (manually addressed hosts answered on INFORM)
+//! /db/maintenance (the last lease database vacuum & analyze run)
+//! /trace-filter (GET, PUT `{"macs": [..], "client_ids": [..], "subnets": [..]}` logs
+//!   those clients at debug level & dumps their packets, DELETE stops tracing)
+//! /instances
+//! /instances/:name/v4/hostnames/conflicts
+//! /instances/:name/v4/leases
+//! /instances/:name/v4/leases/events
+//! /instances/:name/v6/server-id
+//! /instances/:name/v6/other-config
+//! /instances/:name/stats/pools?range=24h
+//! /instances/:name/config/diff
+//! /instances/:name/config/versions
+//! /instances/:name/config/versions/:version
+//! /instances/:name/config/versions/:version/rollback
+//! /instances/:name/v4/forcerenew
+//! /instances/:name/v4/sticky
+//! /instances/:name/v4/sticky/:ip
+//! /instances/:name/v4/pins
+//! /instances/:name/v4/pins/:ip
+//! /instances/:name/v4/static-hosts
+//! /instances/:name/db/maintenance
+//!
+//! When dora runs multiple named instances, each instance is served under
+//! `/instances/:name` & the top-level endpoints are unused. `/trace-filter`
+//! applies to every instance
+//!
+//! Configs pushed to `/config/versions` are kept in the lease database. dora
+//! started with `--config-source database` runs the newest version, pushed
+//! configs take effect when it restarts
+//!
+//! With the `ui` feature a dashboard of pool utilization, leases & recent
+//! events is served on `/ui`, `/ui?instance=<name>` shows a named instance
 #![warn(
     missing_debug_implementations,
     missing_docs,
@@ -18,10 +68,16 @@
 
 use anyhow::{bail, Result};
 use axum::{extract::Extension, routing, Router};
-use tokio::{sync::mpsc, task::JoinHandle};
+use tokio::{
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+};
 use tracing::{error, info, trace};
 
-use std::net::SocketAddr;
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+
+use config::DhcpConfig;
+use ip_manager::{sqlite::SqliteDb, LeaseEvent};
 
 pub use crate::models::{Health, State};
 
@@ -40,6 +96,48 @@ impl Drop for ExternalApiGuard {
     }
 }
 
+/// The lease store, events, server id & running config of one dora instance
+#[derive(Debug, Clone, Default)]
+pub struct Instance {
+    store: Option<SqliteDb>,
+    events: Option<broadcast::Sender<LeaseEvent>>,
+    server_id: Option<models::ServerId>,
+    config: Option<Arc<DhcpConfig>>,
+}
+
+impl Instance {
+    /// Create an instance with nothing to serve
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `store` to serve lease related endpoints
+    pub fn with_store(mut self, store: SqliteDb) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Stream lease events from `events`
+    pub fn with_events(mut self, events: broadcast::Sender<LeaseEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Serve the v6 server DUID
+    pub fn with_server_id(mut self, duid: &[u8]) -> Self {
+        self.server_id = Some(models::ServerId {
+            duid: models::hex(duid),
+        });
+        self
+    }
+
+    /// Diff configs posted to the api against `config`
+    pub fn with_config(mut self, config: Arc<DhcpConfig>) -> Self {
+        self.config = Some(config);
+        self
+    }
+}
+
 /// Listens to relevant channels to gather information about
 /// the running system and reports this data in an HTTP API
 #[derive(Debug)]
@@ -48,6 +146,8 @@ pub struct ExternalApi {
     rx: mpsc::Receiver<Health>,
     addr: SocketAddr,
     state: State,
+    default: Instance,
+    instances: BTreeMap<String, Instance>,
 }
 
 impl ExternalApi {
@@ -61,9 +161,47 @@ impl ExternalApi {
             rx,
             addr,
             state,
+            default: Instance::default(),
+            instances: BTreeMap::new(),
         }
     }
 
+    /// Use `store` to serve lease related endpoints
+    pub fn with_store(mut self, store: SqliteDb) -> Self {
+        self.default = self.default.with_store(store);
+        self
+    }
+
+    /// Stream lease events from `events` on `/v4/leases/events`
+    pub fn with_events(mut self, events: broadcast::Sender<LeaseEvent>) -> Self {
+        self.default = self.default.with_events(events);
+        self
+    }
+
+    /// Serve the v6 server DUID on `/v6/server-id`
+    pub fn with_server_id(mut self, duid: &[u8]) -> Self {
+        self.default = self.default.with_server_id(duid);
+        self
+    }
+
+    /// Diff configs posted to `/config/diff` against `config`
+    pub fn with_config(mut self, config: Arc<DhcpConfig>) -> Self {
+        self.default = self.default.with_config(config);
+        self
+    }
+
+    /// Serve `instance` on the top-level endpoints
+    pub fn with_default(mut self, instance: Instance) -> Self {
+        self.default = instance;
+        self
+    }
+
+    /// Serve `instance` under `/instances/:name`
+    pub fn with_instance(mut self, name: impl Into<String>, instance: Instance) -> Self {
+        self.instances.insert(name.into(), instance);
+        self
+    }
+
     /// clone the health sender channel
     pub fn sender(&self) -> mpsc::Sender<Health> {
         self.tx.clone()
@@ -87,18 +225,220 @@ impl ExternalApi {
     }
 
     /// serve the HTTP external api
-    async fn run(state: State, addr: SocketAddr) -> Result<()> {
+    async fn run(
+        state: State,
+        default: Instance,
+        instances: handlers::Instances,
+        addr: SocketAddr,
+    ) -> Result<()> {
         // Provides:
         // /health
         // /ping
         // /metrics
         // /metrics-text
+        // /v4/hostnames/conflicts
+        // /v4/leases
+        // /v4/leases/events
+        // /v4/leases/transitions
+        // /v6/server-id
+        // /v6/other-config
+        // /stats/pools
+        // /config/diff
+        // /config/versions
+        // /config/versions/:version
+        // /config/versions/:version/rollback
+        // /v4/forcerenew
+        // /v4/sticky
+        // /v4/sticky/:ip
+        // /v4/pins
+        // /v4/pins/:ip
+        // /v4/clients/:id/notes
+        // /v4/clients/:id/notes/:key
+        // /v4/static-hosts
+        // /v4/garbage-clients
+        // /db/maintenance
+        // /trace-filter
+        // /instances
+        // /instances/:name/v4/hostnames/conflicts
+        // /instances/:name/v4/leases
+        // /instances/:name/v4/leases/events
+        // /instances/:name/v4/leases/transitions
+        // /instances/:name/v6/server-id
+        // /instances/:name/v6/other-config
+        // /instances/:name/stats/pools
+        // /instances/:name/config/diff
+        // /instances/:name/config/versions
+        // /instances/:name/config/versions/:version
+        // /instances/:name/config/versions/:version/rollback
+        // /instances/:name/v4/forcerenew
+        // /instances/:name/v4/sticky
+        // /instances/:name/v4/sticky/:ip
+        // /instances/:name/v4/pins
+        // /instances/:name/v4/pins/:ip
+        // /instances/:name/v4/clients/:id/notes
+        // /instances/:name/v4/clients/:id/notes/:key
+        // /instances/:name/v4/static-hosts
+        // /instances/:name/db/maintenance
+        // /ui (with the `ui` feature)
         let app = Router::new()
             .route("/health", routing::get(handlers::ok))
             .route("/ping", routing::get(handlers::ping))
             .route("/metrics", routing::get(handlers::metrics))
             .route("/metrics-text", routing::get(handlers::metrics_text))
-            .layer(Extension(state));
+            .route(
+                "/v4/hostnames/conflicts",
+                routing::get(handlers::hostname_conflicts),
+            )
+            .route("/v4/leases", routing::get(handlers::leases))
+            .route("/v4/leases/events", routing::get(handlers::lease_events))
+            .route(
+                "/v4/leases/transitions",
+                routing::get(handlers::lease_transitions),
+            )
+            .route("/v6/server-id", routing::get(handlers::server_id))
+            .route("/v6/other-config", routing::get(handlers::other_config))
+            .route("/stats/pools", routing::get(handlers::pool_stats))
+            .route(
+                "/stats/pools/daily",
+                routing::get(handlers::daily_pool_stats),
+            )
+            .route("/config/diff", routing::post(handlers::config_diff))
+            .route(
+                "/config/versions",
+                routing::get(handlers::config_versions).post(handlers::push_config),
+            )
+            .route(
+                "/config/versions/:version",
+                routing::get(handlers::config_version),
+            )
+            .route(
+                "/config/versions/:version/rollback",
+                routing::post(handlers::rollback_config),
+            )
+            .route("/v4/forcerenew", routing::post(handlers::forcerenew))
+            .route(
+                "/v4/sticky",
+                routing::get(handlers::sticky_leases).post(handlers::make_sticky),
+            )
+            .route("/v4/sticky/:ip", routing::delete(handlers::delete_sticky))
+            .route("/v4/pins", routing::get(handlers::pins).post(handlers::pin))
+            .route("/v4/pins/:ip", routing::delete(handlers::unpin))
+            .route(
+                "/v4/clients/:id/notes",
+                routing::get(handlers::client_notes).put(handlers::set_client_notes),
+            )
+            .route(
+                "/v4/clients/:id/notes/:key",
+                routing::delete(handlers::delete_client_note),
+            )
+            .route("/v4/static-hosts", routing::get(handlers::static_hosts))
+            .route(
+                "/v4/garbage-clients",
+                routing::get(handlers::garbage_clients).delete(handlers::clear_garbage_clients),
+            )
+            .route("/db/maintenance", routing::get(handlers::maintenance))
+            .route(
+                "/trace-filter",
+                routing::get(handlers::trace_filter)
+                    .put(handlers::set_trace_filter)
+                    .delete(handlers::clear_trace_filter),
+            )
+            .route("/instances", routing::get(handlers::instances))
+            .route(
+                "/instances/:name/v4/hostnames/conflicts",
+                routing::get(handlers::instance_hostname_conflicts),
+            )
+            .route(
+                "/instances/:name/v4/leases",
+                routing::get(handlers::instance_leases),
+            )
+            .route(
+                "/instances/:name/v4/leases/events",
+                routing::get(handlers::instance_lease_events),
+            )
+            .route(
+                "/instances/:name/v4/leases/transitions",
+                routing::get(handlers::instance_lease_transitions),
+            )
+            .route(
+                "/instances/:name/v6/server-id",
+                routing::get(handlers::instance_server_id),
+            )
+            .route(
+                "/instances/:name/v6/other-config",
+                routing::get(handlers::instance_other_config),
+            )
+            .route(
+                "/instances/:name/stats/pools",
+                routing::get(handlers::instance_pool_stats),
+            )
+            .route(
+                "/instances/:name/stats/pools/daily",
+                routing::get(handlers::instance_daily_pool_stats),
+            )
+            .route(
+                "/instances/:name/config/diff",
+                routing::post(handlers::instance_config_diff),
+            )
+            .route(
+                "/instances/:name/config/versions",
+                routing::get(handlers::instance_config_versions)
+                    .post(handlers::instance_push_config),
+            )
+            .route(
+                "/instances/:name/config/versions/:version",
+                routing::get(handlers::instance_config_version),
+            )
+            .route(
+                "/instances/:name/config/versions/:version/rollback",
+                routing::post(handlers::instance_rollback_config),
+            )
+            .route(
+                "/instances/:name/v4/forcerenew",
+                routing::post(handlers::instance_forcerenew),
+            )
+            .route(
+                "/instances/:name/v4/sticky",
+                routing::get(handlers::instance_sticky_leases).post(handlers::instance_make_sticky),
+            )
+            .route(
+                "/instances/:name/v4/sticky/:ip",
+                routing::delete(handlers::instance_delete_sticky),
+            )
+            .route(
+                "/instances/:name/v4/pins",
+                routing::get(handlers::instance_pins).post(handlers::instance_pin),
+            )
+            .route(
+                "/instances/:name/v4/pins/:ip",
+                routing::delete(handlers::instance_unpin),
+            )
+            .route(
+                "/instances/:name/v4/clients/:id/notes",
+                routing::get(handlers::instance_client_notes)
+                    .put(handlers::instance_set_client_notes),
+            )
+            .route(
+                "/instances/:name/v4/clients/:id/notes/:key",
+                routing::delete(handlers::instance_delete_client_note),
+            )
+            .route(
+                "/instances/:name/v4/static-hosts",
+                routing::get(handlers::instance_static_hosts),
+            )
+            .route(
+                "/instances/:name/db/maintenance",
+                routing::get(handlers::instance_maintenance),
+            );
+        #[cfg(feature = "ui")]
+        let app = app.route("/ui", routing::get(handlers::ui));
+        let app = app
+            .layer(Extension(state))
+            .layer(Extension(default.store))
+            .layer(Extension(default.events))
+            .layer(Extension(default.server_id))
+            .layer(Extension(default.config))
+            .layer(Extension(instances));
 
         tracing::debug!("external API listening on {}", addr);
 
@@ -112,11 +452,15 @@ impl ExternalApi {
     /// changes
     pub fn start(mut self) -> JoinHandle<()> {
         let state = self.state.clone();
+        let default = self.default.clone();
+        let instances = Arc::new(self.instances.clone());
         let addr = self.addr;
 
         tokio::spawn(async move {
-            if let Err(err) = tokio::try_join!(ExternalApi::run(state, addr), self.listen_status())
-            {
+            if let Err(err) = tokio::try_join!(
+                ExternalApi::run(state, default, instances, addr),
+                self.listen_status()
+            ) {
                 error!(?err, "health task returning, this should not happen")
             }
         })
@@ -131,17 +475,988 @@ impl ExternalApi {
 }
 
 mod handlers {
-    use crate::models::{Health, State};
+    use std::{
+        collections::BTreeMap,
+        net::{IpAddr, Ipv4Addr},
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    use crate::{
+        models::{
+            self, ConfigVersion, DailyPoolStats, ForceRenew, ForceRenewTarget, GarbageClient,
+            Health, HostnameConflict, Lease, LeaseTransition, MaintenanceRun, PinRequest,
+            PoolStats, PushedConfig, ServerId, State, StaticHost, StickyLease, StickyRequest,
+            StoredConfig, TraceFilter,
+        },
+        Instance,
+    };
     use axum::{
         body::Body,
-        extract::Extension,
+        extract::{Extension, Path, Query},
         http::header,
         http::{Response, StatusCode},
-        response::IntoResponse,
+        response::{
+            sse::{Event, KeepAlive, Sse},
+            IntoResponse,
+        },
+        Json,
+    };
+    use config::{v4::diff::ConfigDiff, v6::OtherConfig, DhcpConfig};
+    use dora_core::{
+        garbage,
+        metrics::{START_TIME, UPTIME},
+        trace_filter,
+    };
+    use futures::{stream, Stream};
+    use ip_manager::{
+        sqlite::SqliteDb, IpState, LeaseEvent, LeaseQuery, QueryError, SortKey, Storage,
     };
-    use dora_core::metrics::{START_TIME, UPTIME};
     use prometheus::{Encoder, ProtobufEncoder, TextEncoder};
-    use tracing::error;
+    use serde::Deserialize;
+    use tokio::{
+        net::UdpSocket,
+        sync::broadcast::{self, error::RecvError},
+    };
+    use tracing::{error, info, warn};
+
+    /// default number of hostname conflicts returned
+    const DEFAULT_CONFLICT_LIMIT: u32 = 100;
+    /// default number of leases returned
+    const DEFAULT_LEASE_LIMIT: u32 = 500;
+    /// default number of lease transitions returned
+    const DEFAULT_TRANSITION_LIMIT: u32 = 100;
+    /// default time span of pool samples returned
+    const DEFAULT_STATS_RANGE: Duration = Duration::from_secs(24 * 60 * 60);
+    /// default time span of daily pool rollups returned
+    const DEFAULT_DAILY_STATS_RANGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+    /// default time a lease made sticky through the api is held
+    const DEFAULT_STICKY_HOLD: Duration = Duration::from_secs(config::wire::default_sticky_hold());
+
+    /// named instances, see [`crate::ExternalApi::with_instance`]
+    pub(crate) type Instances = Arc<BTreeMap<String, Instance>>;
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct Limit {
+        limit: Option<u32>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct LeaseSearch {
+        search: Option<String>,
+        /// a lease query, see [`LeaseQuery`]
+        q: Option<String>,
+        sort: Option<String>,
+        /// `asc` or `desc`
+        order: Option<String>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct TransitionSearch {
+        ip: Option<IpAddr>,
+        limit: Option<u32>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct StatsRange {
+        range: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct ConfigNote {
+        note: Option<String>,
+    }
+
+    /// a time span, a number followed by `s`, `m`, `h` or `d` ex. `24h`
+    pub(crate) fn parse_span(span: &str) -> Option<Duration> {
+        let unit = match span.chars().last()? {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return None,
+        };
+        let n = span[..span.len() - 1].parse::<u64>().ok()?;
+        Some(Duration::from_secs(n.checked_mul(unit)?))
+    }
+
+    fn instance<'a>(instances: &'a Instances, name: &str) -> Result<&'a Instance, StatusCode> {
+        instances.get(name).ok_or(StatusCode::NOT_FOUND)
+    }
+
+    pub(crate) async fn instances(Extension(instances): Extension<Instances>) -> Json<Vec<String>> {
+        Json(instances.keys().cloned().collect())
+    }
+
+    pub(crate) async fn instance_hostname_conflicts(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        params: Query<Limit>,
+    ) -> Result<Json<Vec<HostnameConflict>>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        hostname_conflicts(Extension(store), params).await
+    }
+
+    pub(crate) async fn instance_leases(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        params: Query<LeaseSearch>,
+    ) -> Result<Json<Vec<Lease>>, (StatusCode, String)> {
+        let store = instance(&instances, &name)
+            .map_err(|status| (status, String::new()))?
+            .store
+            .clone();
+        leases(Extension(store), params).await
+    }
+
+    pub(crate) async fn instance_lease_transitions(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        params: Query<TransitionSearch>,
+    ) -> Result<Json<Vec<LeaseTransition>>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        lease_transitions(Extension(store), params).await
+    }
+
+    pub(crate) async fn instance_lease_events(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, StatusCode> {
+        let events = instance(&instances, &name)?.events.clone();
+        lease_events(Extension(events)).await
+    }
+
+    pub(crate) async fn instance_server_id(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<ServerId>, StatusCode> {
+        let id = instance(&instances, &name)?.server_id.clone();
+        server_id(Extension(id)).await
+    }
+
+    pub(crate) async fn instance_other_config(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<Vec<OtherConfig>>, StatusCode> {
+        let config = instance(&instances, &name)?.config.clone();
+        other_config(Extension(config)).await
+    }
+
+    pub(crate) async fn instance_pool_stats(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        params: Query<StatsRange>,
+    ) -> Result<Json<Vec<PoolStats>>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        pool_stats(Extension(store), params).await
+    }
+
+    pub(crate) async fn instance_daily_pool_stats(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        params: Query<StatsRange>,
+    ) -> Result<Json<Vec<DailyPoolStats>>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        daily_pool_stats(Extension(store), params).await
+    }
+
+    pub(crate) async fn instance_config_diff(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        body: String,
+    ) -> Result<Json<ConfigDiff>, (StatusCode, String)> {
+        let config = instance(&instances, &name)
+            .map_err(|status| (status, String::new()))?
+            .config
+            .clone();
+        config_diff(Extension(config), body).await
+    }
+
+    pub(crate) async fn instance_config_versions(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<Vec<ConfigVersion>>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        config_versions(Extension(store)).await
+    }
+
+    pub(crate) async fn instance_push_config(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        params: Query<ConfigNote>,
+        body: String,
+    ) -> Result<Json<PushedConfig>, (StatusCode, String)> {
+        let instance = instance(&instances, &name).map_err(|status| (status, String::new()))?;
+        push_config(
+            Extension(instance.store.clone()),
+            Extension(instance.config.clone()),
+            params,
+            body,
+        )
+        .await
+    }
+
+    pub(crate) async fn instance_config_version(
+        Path((name, version)): Path<(String, u64)>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<StoredConfig>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        config_version(Path(version), Extension(store)).await
+    }
+
+    pub(crate) async fn instance_rollback_config(
+        Path((name, version)): Path<(String, u64)>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<PushedConfig>, (StatusCode, String)> {
+        let instance = instance(&instances, &name).map_err(|status| (status, String::new()))?;
+        rollback_config(
+            Path(version),
+            Extension(instance.store.clone()),
+            Extension(instance.config.clone()),
+        )
+        .await
+    }
+
+    pub(crate) async fn instance_forcerenew(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        target: Json<ForceRenewTarget>,
+    ) -> Result<Json<ForceRenew>, (StatusCode, String)> {
+        let store = instance(&instances, &name)
+            .map_err(|status| (status, String::new()))?
+            .store
+            .clone();
+        forcerenew(Extension(store), target).await
+    }
+
+    pub(crate) async fn instance_sticky_leases(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<Vec<StickyLease>>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        sticky_leases(Extension(store)).await
+    }
+
+    pub(crate) async fn instance_make_sticky(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        req: Json<StickyRequest>,
+    ) -> Result<Json<StickyLease>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        make_sticky(Extension(store), req).await
+    }
+
+    pub(crate) async fn instance_delete_sticky(
+        Path((name, ip)): Path<(String, Ipv4Addr)>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<StatusCode, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        delete_sticky(Path(ip), Extension(store)).await
+    }
+
+    pub(crate) async fn instance_pins(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<Vec<StickyLease>>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        pins(Extension(store)).await
+    }
+
+    pub(crate) async fn instance_pin(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+        req: Json<PinRequest>,
+    ) -> Result<Json<StickyLease>, (StatusCode, String)> {
+        let instance = instance(&instances, &name).map_err(|status| (status, String::new()))?;
+        let (store, config) = (instance.store.clone(), instance.config.clone());
+        pin(Extension(store), Extension(config), req).await
+    }
+
+    pub(crate) async fn instance_unpin(
+        Path((name, ip)): Path<(String, Ipv4Addr)>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<StatusCode, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        unpin(Path(ip), Extension(store)).await
+    }
+
+    pub(crate) async fn instance_client_notes(
+        Path((name, id)): Path<(String, String)>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<BTreeMap<String, String>>, (StatusCode, String)> {
+        let store = instance(&instances, &name)
+            .map_err(|status| (status, String::new()))?
+            .store
+            .clone();
+        client_notes(Path(id), Extension(store)).await
+    }
+
+    pub(crate) async fn instance_set_client_notes(
+        Path((name, id)): Path<(String, String)>,
+        Extension(instances): Extension<Instances>,
+        req: Json<BTreeMap<String, Option<String>>>,
+    ) -> Result<Json<BTreeMap<String, String>>, (StatusCode, String)> {
+        let store = instance(&instances, &name)
+            .map_err(|status| (status, String::new()))?
+            .store
+            .clone();
+        set_client_notes(Path(id), Extension(store), req).await
+    }
+
+    pub(crate) async fn instance_delete_client_note(
+        Path((name, id, key)): Path<(String, String, String)>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let store = instance(&instances, &name)
+            .map_err(|status| (status, String::new()))?
+            .store
+            .clone();
+        delete_client_note(Path((id, key)), Extension(store)).await
+    }
+
+    pub(crate) async fn instance_static_hosts(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<Vec<StaticHost>>, StatusCode> {
+        let config = instance(&instances, &name)?.config.clone();
+        static_hosts(Extension(config)).await
+    }
+
+    pub(crate) async fn instance_maintenance(
+        Path(name): Path<String>,
+        Extension(instances): Extension<Instances>,
+    ) -> Result<Json<Option<MaintenanceRun>>, StatusCode> {
+        let store = instance(&instances, &name)?.store.clone();
+        maintenance(Extension(store)).await
+    }
+
+    /// the clients being traced
+    pub(crate) async fn trace_filter() -> Json<TraceFilter> {
+        Json(trace_filter::get().into())
+    }
+
+    /// replace the trace filter, empty lists stop tracing
+    pub(crate) async fn set_trace_filter(
+        Json(req): Json<TraceFilter>,
+    ) -> Result<Json<TraceFilter>, (StatusCode, String)> {
+        let filter = req
+            .to_filter()
+            .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+        info!(?filter, "trace filter set");
+        trace_filter::set(filter);
+        Ok(Json(trace_filter::get().into()))
+    }
+
+    pub(crate) async fn clear_trace_filter() -> StatusCode {
+        trace_filter::set(Default::default());
+        info!("trace filter cleared");
+        StatusCode::NO_CONTENT
+    }
+
+    /// clients flagged by the garbage client heuristics, newest first. The
+    /// log is process wide, it holds the findings of every instance
+    pub(crate) async fn garbage_clients() -> Json<Vec<GarbageClient>> {
+        Json(garbage::findings().into_iter().map(Into::into).collect())
+    }
+
+    pub(crate) async fn clear_garbage_clients() -> StatusCode {
+        garbage::clear();
+        info!("garbage client findings cleared");
+        StatusCode::NO_CONTENT
+    }
+
+    /// the last lease database maintenance run, `null` if there hasn't been one
+    pub(crate) async fn maintenance(
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<Json<Option<MaintenanceRun>>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let run = store.last_maintenance().await.map_err(|err| {
+            error!(?err, "error getting last maintenance run");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(Json(run.map(Into::into)))
+    }
+
+    /// static hosts of every v4 network ordered by ip
+    pub(crate) async fn static_hosts(
+        Extension(config): Extension<Option<Arc<DhcpConfig>>>,
+    ) -> Result<Json<Vec<StaticHost>>, StatusCode> {
+        let config = config.ok_or(StatusCode::NOT_FOUND)?;
+        let mut hosts = config
+            .v4()
+            .networks()
+            .iter()
+            .flat_map(|(subnet, net)| {
+                net.static_hosts().map(|host| {
+                    let mut options = host
+                        .opts()
+                        .iter()
+                        .map(|(code, _)| u8::from(*code))
+                        .collect::<Vec<_>>();
+                    options.sort_unstable();
+                    StaticHost {
+                        ip: IpAddr::V4(host.ip()),
+                        mac: host.mac().to_string(),
+                        network: IpAddr::V4(subnet.network()),
+                        options,
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        hosts.sort_by_key(|host| host.ip);
+        Ok(Json(hosts))
+    }
+
+    /// un-expired sticky leases ordered by ip
+    pub(crate) async fn sticky_leases(
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<Json<Vec<StickyLease>>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let leases = store.sticky_leases().await.map_err(|err| {
+            error!(?err, "error getting sticky leases");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(Json(leases.into_iter().map(Into::into).collect()))
+    }
+
+    /// make the current lease on `ip` sticky for `hold` seconds, default 90 days.
+    /// 404 if the address isn't leased
+    pub(crate) async fn make_sticky(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Json(req): Json<StickyRequest>,
+    ) -> Result<Json<StickyLease>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let db_err = |err| {
+            error!(?err, "error making lease sticky");
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        let lease = store
+            .active_leases()
+            .await
+            .map_err(db_err)?
+            .into_iter()
+            .find(|lease| lease.ip == req.ip && lease.state == IpState::Lease)
+            .ok_or(StatusCode::NOT_FOUND)?;
+        let id = lease.id.ok_or(StatusCode::NOT_FOUND)?;
+        let hold = req.hold.map_or(DEFAULT_STICKY_HOLD, Duration::from_secs);
+        store
+            .set_sticky(req.ip, lease.network, &id, store.now() + hold)
+            .await
+            .map_err(db_err)?;
+        let sticky = store
+            .get_sticky_ip(req.ip)
+            .await
+            .map_err(db_err)?
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        info!(ip = ?req.ip, ?hold, "made lease sticky");
+        Ok(Json(sticky.into()))
+    }
+
+    /// stop holding `ip` for its client, 404 if it wasn't a sticky lease
+    pub(crate) async fn delete_sticky(
+        Path(ip): Path<Ipv4Addr>,
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<StatusCode, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let deleted = store.delete_sticky(ip.into()).await.map_err(|err| {
+            error!(?err, "error deleting sticky lease");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if deleted {
+            info!(?ip, "deleted sticky lease");
+            Ok(StatusCode::OK)
+        } else {
+            Err(StatusCode::NOT_FOUND)
+        }
+    }
+
+    /// un-expired pins ordered by ip
+    pub(crate) async fn pins(
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<Json<Vec<StickyLease>>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let leases = store.sticky_leases().await.map_err(|err| {
+            error!(?err, "error getting pins");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(Json(
+            leases
+                .into_iter()
+                .filter(|lease| lease.pinned)
+                .map(Into::into)
+                .collect(),
+        ))
+    }
+
+    /// hold `ip` for a client for `ttl` seconds, whether or not it's leased. The
+    /// pin is deleted once it expires. 409 if another client is leasing or
+    /// holding the address
+    pub(crate) async fn pin(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Extension(config): Extension<Option<Arc<DhcpConfig>>>,
+        Json(req): Json<PinRequest>,
+    ) -> Result<Json<StickyLease>, (StatusCode, String)> {
+        let (store, config) = store
+            .zip(config)
+            .ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        let id = req
+            .client_id()
+            .map_err(|err| (StatusCode::BAD_REQUEST, err.to_owned()))?;
+        let network = match req.ip {
+            IpAddr::V4(ip) => config.v4().network(ip),
+            IpAddr::V6(_) => None,
+        }
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("no v4 network contains {}", req.ip),
+            )
+        })?;
+        let db_err = |err| {
+            error!(?err, "error pinning address");
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        };
+        let leased = store
+            .active_leases()
+            .await
+            .map_err(db_err)?
+            .into_iter()
+            .any(|lease| lease.ip == req.ip && lease.id.as_deref() != Some(id.as_slice()));
+        let held = matches!(
+            store.get_sticky_ip(req.ip).await.map_err(db_err)?,
+            Some(sticky) if sticky.id != id
+        );
+        if leased || held {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} is in use by another client", req.ip),
+            ));
+        }
+        let ttl = Duration::from_secs(req.ttl);
+        store
+            .set_pin(req.ip, network.subnet().into(), &id, store.now() + ttl)
+            .await
+            .map_err(db_err)?;
+        let pin = store
+            .get_sticky_ip(req.ip)
+            .await
+            .map_err(db_err)?
+            .ok_or((StatusCode::INTERNAL_SERVER_ERROR, String::new()))?;
+        info!(ip = ?req.ip, client_id = %models::hex(&id), ?ttl, "pinned address");
+        Ok(Json(pin.into()))
+    }
+
+    /// delete the pin on `ip` before it expires, 404 if it isn't pinned
+    pub(crate) async fn unpin(
+        Path(ip): Path<Ipv4Addr>,
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<StatusCode, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let db_err = |err| {
+            error!(?err, "error deleting pin");
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        let ip = IpAddr::V4(ip);
+        match store.get_sticky_ip(ip).await.map_err(db_err)? {
+            Some(sticky) if sticky.pinned => {
+                store.delete_sticky(ip).await.map_err(db_err)?;
+                info!(?ip, "deleted pin");
+                Ok(StatusCode::OK)
+            }
+            _ => Err(StatusCode::NOT_FOUND),
+        }
+    }
+
+    /// the notes on a client, by key. `id` is the client id as hex, the MAC of
+    /// a client that doesn't send one
+    pub(crate) async fn client_notes(
+        Path(id): Path<String>,
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<Json<BTreeMap<String, String>>, (StatusCode, String)> {
+        let store = store.ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        let id = models::note_client_id(&id).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+        let notes = store.client_notes(&id).await.map_err(|err| {
+            error!(?err, "error getting client notes");
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        })?;
+        Ok(Json(notes))
+    }
+
+    /// set the notes in the posted object on a client, a `null` value removes
+    /// the note & other notes are left as they are. Returns every note on the
+    /// client
+    pub(crate) async fn set_client_notes(
+        Path(id): Path<String>,
+        Extension(store): Extension<Option<SqliteDb>>,
+        Json(req): Json<BTreeMap<String, Option<String>>>,
+    ) -> Result<Json<BTreeMap<String, String>>, (StatusCode, String)> {
+        let store = store.ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        let id = models::note_client_id(&id).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+        for (key, value) in &req {
+            models::check_note(key, value.as_deref())
+                .map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+        }
+        let db_err = |err| {
+            error!(?err, "error setting client notes");
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        };
+        for (key, value) in &req {
+            store
+                .set_client_note(&id, key, value.as_deref())
+                .await
+                .map_err(db_err)?;
+        }
+        info!(client_id = %models::hex(&id), keys = ?req.keys(), "set client notes");
+        Ok(Json(store.client_notes(&id).await.map_err(db_err)?))
+    }
+
+    /// delete the note `key` on a client, 404 if it isn't set
+    pub(crate) async fn delete_client_note(
+        Path((id, key)): Path<(String, String)>,
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<StatusCode, (StatusCode, String)> {
+        let store = store.ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        let id = models::note_client_id(&id).map_err(|err| (StatusCode::BAD_REQUEST, err))?;
+        let db_err = |err| {
+            error!(?err, "error deleting client note");
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        };
+        let notes = store.client_notes(&id).await.map_err(db_err)?;
+        if !notes.contains_key(&key) {
+            return Err((StatusCode::NOT_FOUND, String::new()));
+        }
+        store
+            .set_client_note(&id, &key, None)
+            .await
+            .map_err(db_err)?;
+        info!(client_id = %models::hex(&id), %key, "deleted client note");
+        Ok(StatusCode::OK)
+    }
+
+    /// send FORCERENEW to every client holding a forcerenew nonce that matches
+    /// the posted `ip`, `network` or `class`. Clients without a nonce can't
+    /// authenticate the message, so they are never sent one
+    pub(crate) async fn forcerenew(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Json(target): Json<ForceRenewTarget>,
+    ) -> Result<Json<ForceRenew>, (StatusCode, String)> {
+        let store = store.ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        target
+            .validate()
+            .map_err(|err| (StatusCode::BAD_REQUEST, err.to_owned()))?;
+        let nonces = store.forcerenew_nonces().await.map_err(|err| {
+            error!(?err, "error getting forcerenew nonces");
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        })?;
+        // sent from an ephemeral port, the server socket belongs to the server
+        let soc = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+            .await
+            .map_err(|err| {
+                error!(?err, "failed to bind forcerenew socket");
+                (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+            })?;
+        let mut result = ForceRenew::default();
+        for nonce in nonces.into_iter().filter(|nonce| target.matches(nonce)) {
+            let (ip, key) = match (nonce.ip, <forcerenew::Nonce>::try_from(&nonce.nonce[..])) {
+                (IpAddr::V4(ip), Ok(key)) => (ip, key),
+                _ => continue,
+            };
+            let client = forcerenew::Target {
+                ip,
+                client_id: (nonce.id != nonce.chaddr).then(|| nonce.id.clone()),
+                chaddr: nonce.chaddr,
+                server_id: nonce.server_id,
+                nonce: key,
+            };
+            match client.send(&soc).await {
+                Ok(()) => result.sent.push(nonce.ip),
+                Err(err) => {
+                    warn!(?err, "failed to send FORCERENEW");
+                    result.failed.push(nonce.ip);
+                }
+            }
+        }
+        info!(
+            sent = result.sent.len(),
+            failed = result.failed.len(),
+            ?target,
+            "sent FORCERENEW"
+        );
+        Ok(Json(result))
+    }
+
+    /// parse the posted config (JSON or YAML) & return what would change if it
+    /// replaced the running config. Nothing is applied
+    pub(crate) async fn config_diff(
+        Extension(config): Extension<Option<Arc<DhcpConfig>>>,
+        body: String,
+    ) -> Result<Json<ConfigDiff>, (StatusCode, String)> {
+        let config = config.ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        let new = DhcpConfig::parse_str(body)
+            .map_err(|err| (StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+        let diff = config.diff(&new);
+        info!(changes = %diff, "diffed posted config against running config");
+        Ok(Json(diff))
+    }
+
+    /// stored config versions, newest first
+    pub(crate) async fn config_versions(
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<Json<Vec<ConfigVersion>>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let versions = store.config_versions().await.map_err(|err| {
+            error!(?err, "error getting config versions");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(Json(versions.into_iter().map(Into::into).collect()))
+    }
+
+    /// the stored config `version`
+    pub(crate) async fn config_version(
+        Path(version): Path<u64>,
+        Extension(store): Extension<Option<SqliteDb>>,
+    ) -> Result<Json<StoredConfig>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let (version, config) = store
+            .config_version(version)
+            .await
+            .map_err(|err| {
+                error!(?err, "error getting config version");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .ok_or(StatusCode::NOT_FOUND)?;
+        Ok(Json(StoredConfig {
+            version: version.into(),
+            config,
+        }))
+    }
+
+    /// validate the posted config (JSON or YAML) & store it as the newest
+    /// version, returning it with what changed against the running config. It
+    /// takes effect when dora is restarted with `--config-source database`
+    pub(crate) async fn push_config(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Extension(config): Extension<Option<Arc<DhcpConfig>>>,
+        Query(params): Query<ConfigNote>,
+        body: String,
+    ) -> Result<Json<PushedConfig>, (StatusCode, String)> {
+        let store = store.ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        store_config(&store, config.as_deref(), &body, params.note.as_deref()).await
+    }
+
+    /// store a copy of config `version` as the newest version
+    pub(crate) async fn rollback_config(
+        Path(version): Path<u64>,
+        Extension(store): Extension<Option<SqliteDb>>,
+        Extension(config): Extension<Option<Arc<DhcpConfig>>>,
+    ) -> Result<Json<PushedConfig>, (StatusCode, String)> {
+        let store = store.ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        let (_, old) = store
+            .config_version(version)
+            .await
+            .map_err(|err| {
+                error!(?err, "error getting config version");
+                (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+            })?
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("no config version {version}"),
+                )
+            })?;
+        let note = format!("rollback to {version}");
+        store_config(&store, config.as_deref(), &old, Some(&note)).await
+    }
+
+    /// parse `body` & store it as a new version. Stored configs were valid
+    /// when pushed, but are checked again on rollback in case dora changed
+    async fn store_config(
+        store: &SqliteDb,
+        running: Option<&DhcpConfig>,
+        body: &str,
+        note: Option<&str>,
+    ) -> Result<Json<PushedConfig>, (StatusCode, String)> {
+        let new = DhcpConfig::parse_str(body)
+            .map_err(|err| (StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+        let version = store.push_config(body, note).await.map_err(|err| {
+            error!(?err, "error storing config");
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        })?;
+        let diff = running.map(|running| running.diff(&new));
+        info!(version = version.version, ?note, "stored config version");
+        Ok(Json(PushedConfig {
+            version: version.into(),
+            diff,
+        }))
+    }
+
+    /// utilization samples for each range over `range`, default 24h
+    pub(crate) async fn pool_stats(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Query(params): Query<StatsRange>,
+    ) -> Result<Json<Vec<PoolStats>>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let span = match params.range {
+            Some(range) => parse_span(&range).ok_or(StatusCode::BAD_REQUEST)?,
+            None => DEFAULT_STATS_RANGE,
+        };
+        let since = store
+            .now()
+            .checked_sub(span)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let samples = store.pool_samples(since).await.map_err(|err| {
+            error!(?err, "error getting pool samples");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(Json(models::pool_stats(samples)))
+    }
+
+    /// daily rollups for each range over `range`, default 30d
+    pub(crate) async fn daily_pool_stats(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Query(params): Query<StatsRange>,
+    ) -> Result<Json<Vec<DailyPoolStats>>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let span = match params.range {
+            Some(range) => parse_span(&range).ok_or(StatusCode::BAD_REQUEST)?,
+            None => DEFAULT_DAILY_STATS_RANGE,
+        };
+        let since = store
+            .now()
+            .checked_sub(span)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let rollups = store.pool_rollups(since).await.map_err(|err| {
+            error!(?err, "error getting daily pool rollups");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(Json(models::daily_pool_stats(rollups)))
+    }
+
+    /// un-expired leases, offers & probated addresses. `q` is a [`LeaseQuery`]
+    /// run in the database, sorted by `sort` & paged with `limit`/`offset`.
+    /// `search` matches part of the ip, client id or hostname of every lease
+    pub(crate) async fn leases(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Query(params): Query<LeaseSearch>,
+    ) -> Result<Json<Vec<Lease>>, (StatusCode, String)> {
+        let store = store.ok_or((StatusCode::NOT_FOUND, String::new()))?;
+        let internal = |err: <SqliteDb as Storage>::Error| {
+            error!(?err, "error getting leases");
+            (StatusCode::INTERNAL_SERVER_ERROR, String::new())
+        };
+        let limit = params.limit.unwrap_or(DEFAULT_LEASE_LIMIT);
+        if let Some(search) = &params.search {
+            let leases = store.active_leases().await.map_err(internal)?;
+            return Ok(Json(
+                leases
+                    .into_iter()
+                    .map(Lease::from)
+                    .filter(|lease| lease.matches(search))
+                    .skip(params.offset.unwrap_or_default() as usize)
+                    .take(limit as usize)
+                    .collect(),
+            ));
+        }
+        let bad_request = |err: QueryError| (StatusCode::BAD_REQUEST, err.to_string());
+        let sort = match &params.sort {
+            Some(sort) => sort.parse::<SortKey>().map_err(bad_request)?,
+            None => SortKey::default(),
+        };
+        let desc = match params.order.as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(order) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("order must be asc or desc, got {order:?}"),
+                ))
+            }
+        };
+        let query = LeaseQuery::new(params.q.as_deref().unwrap_or_default())
+            .map_err(bad_request)?
+            .sort(sort, desc)
+            .page(limit, params.offset.unwrap_or_default());
+        let leases = store.search_leases(&query).await.map_err(internal)?;
+        Ok(Json(leases.into_iter().map(Lease::from).collect()))
+    }
+
+    /// the embedded dashboard
+    #[cfg(feature = "ui")]
+    pub(crate) async fn ui() -> axum::response::Html<&'static str> {
+        axum::response::Html(include_str!("../ui/index.html"))
+    }
+
+    pub(crate) async fn hostname_conflicts(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Query(params): Query<Limit>,
+    ) -> Result<Json<Vec<HostnameConflict>>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let conflicts = store
+            .hostname_conflicts(params.limit.unwrap_or(DEFAULT_CONFLICT_LIMIT))
+            .await
+            .map_err(|err| {
+                error!(?err, "error getting hostname conflicts");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(Json(conflicts.into_iter().map(Into::into).collect()))
+    }
+
+    /// the lease transition log, newest first, of one address if `ip` is set
+    pub(crate) async fn lease_transitions(
+        Extension(store): Extension<Option<SqliteDb>>,
+        Query(params): Query<TransitionSearch>,
+    ) -> Result<Json<Vec<LeaseTransition>>, StatusCode> {
+        let store = store.ok_or(StatusCode::NOT_FOUND)?;
+        let transitions = store
+            .lease_transitions(params.ip, params.limit.unwrap_or(DEFAULT_TRANSITION_LIMIT))
+            .await
+            .map_err(|err| {
+                error!(?err, "error getting lease transitions");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(Json(transitions.into_iter().map(Into::into).collect()))
+    }
+
+    /// stream lease events as they happen. A subscriber that falls behind
+    /// receives a `lagged` event with the number of events it missed
+    pub(crate) async fn lease_events(
+        Extension(events): Extension<Option<broadcast::Sender<LeaseEvent>>>,
+    ) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, StatusCode> {
+        let rx = events.ok_or(StatusCode::NOT_FOUND)?.subscribe();
+        let stream = stream::unfold(rx, |mut rx| async move {
+            let event = match rx.recv().await {
+                Ok(event) => {
+                    let event = models::LeaseEvent::from(event);
+                    Event::default().event(&event.event).json_data(&event)
+                }
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(?missed, "lease event subscriber lagged");
+                    Ok(Event::default().event("lagged").data(missed.to_string()))
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            Some((event, rx))
+        });
+        Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+    }
+
+    pub(crate) async fn server_id(
+        Extension(server_id): Extension<Option<ServerId>>,
+    ) -> Result<Json<ServerId>, StatusCode> {
+        server_id.map(Json).ok_or(StatusCode::NOT_FOUND)
+    }
+
+    /// DNS servers & domains of each v6 network, what an RA daemon should advertise
+    pub(crate) async fn other_config(
+        Extension(config): Extension<Option<Arc<DhcpConfig>>>,
+    ) -> Result<Json<Vec<OtherConfig>>, StatusCode> {
+        match config {
+            Some(config) if config.has_v6() => Ok(Json(config.v6().other_config())),
+            _ => Err(StatusCode::NOT_FOUND),
+        }
+    }
 
     pub(crate) async fn ok(
         Extension(state): Extension<State>,
@@ -197,9 +1512,20 @@ mod handlers {
 
 /// Various models for API responses
 pub mod models {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use client_classification::relay;
+    use config::v4::diff::ConfigDiff;
+    use dora_core::{garbage, pnet::util::MacAddr, trace_filter};
+    use ip_manager::{HostnamePolicy, IpState};
+    use ipnet::{IpNet, Ipv4Net};
     use parking_lot::Mutex;
     use serde::{Deserialize, Serialize};
-    use std::{fmt, sync::Arc};
+    use std::{
+        collections::BTreeMap,
+        fmt,
+        net::{IpAddr, Ipv4Addr},
+        sync::Arc,
+    };
 
     /// The overall health of the system
     pub type State = Arc<Mutex<Health>>;
@@ -229,48 +1555,1497 @@ pub mod models {
     pub(crate) fn blank_health() -> State {
         Arc::new(Mutex::new(Health::Bad))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
+    /// a client claimed a hostname held by another client
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct HostnameConflict {
+        /// the hostname the client asked for
+        pub hostname: String,
+        /// client id as hex
+        pub client_id: String,
+        /// client id of the existing holder as hex
+        pub existing_client_id: String,
+        /// conflict policy applied
+        pub policy: HostnamePolicy,
+        /// the hostname registered for the client, if any
+        pub assigned: Option<String>,
+        /// rfc3339 time of the conflict
+        pub created_at: String,
+    }
 
-    use super::*;
-    #[tokio::test]
-    async fn test_health() -> anyhow::Result<()> {
-        let api = ExternalApi::new("0.0.0.0:8889".parse().unwrap());
-        let _handle = api.serve();
-        // wait for server to come up
-        tokio::time::sleep(Duration::from_secs(1)).await;
-        let r = reqwest::get("http://0.0.0.0:8889/health")
-            .await?
-            .error_for_status();
-        // initial health state will be BAD i.e. 500
-        match r {
-            Ok(_) => {}
-            Err(err) => {
-                assert_eq!(
-                    err.status(),
-                    Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR)
-                );
+    impl From<ip_manager::HostnameConflict> for HostnameConflict {
+        fn from(conflict: ip_manager::HostnameConflict) -> Self {
+            Self {
+                hostname: conflict.hostname,
+                client_id: hex(&conflict.id),
+                existing_client_id: hex(&conflict.existing_id),
+                policy: conflict.policy,
+                assigned: conflict.assigned,
+                created_at: DateTime::<Utc>::from(conflict.created_at)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
             }
         }
-        Ok(())
     }
-    // very simple test for existence of metrics endpoint
-    #[tokio::test]
-    async fn test_metrics() -> anyhow::Result<()> {
-        let api = ExternalApi::new("0.0.0.0:8888".parse().unwrap());
-        let _handle = api.serve();
-        // wait for server to come up
-        tokio::time::sleep(Duration::from_secs(1)).await;
-        let bytes = reqwest::get("http://0.0.0.0:8888/metrics")
-            .await?
-            .error_for_status()?
-            .bytes()
-            .await;
-        assert!(bytes.is_ok());
 
+    /// a lease state change
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct LeaseEvent {
+        /// one of `offered`, `leased`, `released`, `probated`, `expired`, `offer_expired`
+        pub event: String,
+        /// the address
+        pub ip: IpAddr,
+        /// client id as hex
+        pub client_id: Option<String>,
+        /// rfc3339 expiry of the lease or probation period
+        pub expires_at: String,
+        /// rfc3339 time of the event
+        pub timestamp: String,
+        /// hardware vendor of the client, only set when an OUI database is loaded
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub vendor: Option<String>,
+    }
+
+    impl From<ip_manager::LeaseEvent> for LeaseEvent {
+        fn from(event: ip_manager::LeaseEvent) -> Self {
+            Self {
+                event: event.kind.as_str().to_owned(),
+                ip: event.ip,
+                client_id: event.id.as_deref().map(hex),
+                expires_at: DateTime::<Utc>::from(event.expires_at)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                timestamp: DateTime::<Utc>::from(event.at)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                vendor: event.vendor,
+            }
+        }
+    }
+
+    /// a change of state of an entry in the lease database
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct LeaseTransition {
+        /// position in the log, increases with every transition
+        pub id: u64,
+        /// the address
+        pub ip: IpAddr,
+        /// client id as hex, of the client the entry is for afterwards
+        pub client_id: Option<String>,
+        /// the state before, unset for a new entry
+        pub from: Option<String>,
+        /// one of `offered`, `active`, `expired`, `released`, `declined`,
+        /// `reserved`, `reclaimed`
+        pub to: String,
+        /// rfc3339 time of the transition
+        pub at: String,
+    }
+
+    impl From<ip_manager::LeaseTransition> for LeaseTransition {
+        fn from(transition: ip_manager::LeaseTransition) -> Self {
+            Self {
+                id: transition.id,
+                ip: transition.ip,
+                client_id: transition.client_id.as_deref().map(hex),
+                from: transition.from.map(|state| state.to_string()),
+                to: transition.to.to_string(),
+                at: DateTime::<Utc>::from(transition.at).to_rfc3339_opts(SecondsFormat::Secs, true),
+            }
+        }
+    }
+
+    /// an un-expired entry in the lease database
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct Lease {
+        /// the address
+        pub ip: IpAddr,
+        /// client id as hex
+        pub client_id: Option<String>,
+        /// subnet of the network the address belongs to
+        pub network: IpAddr,
+        /// one of `leased`, `offered`, `probated`
+        pub state: String,
+        /// the stored state, one of `active`, `offered`, `declined`, `reserved`
+        pub lease_state: String,
+        /// rfc3339 expiry of the lease, offer or probation period
+        pub expires_at: String,
+        /// hostname registered by the client
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub hostname: Option<String>,
+        /// relay agent information (option 82) sub-options the lease was made
+        /// with, as hex by sub-option name ex. `circuit_id`, `vendor_specific.3561`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub relay_info: Option<BTreeMap<String, String>>,
+        /// DHCP fingerprint of the client the lease was made for
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub fingerprint: Option<Fingerprint>,
+        /// notes attached to the client, by key
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        pub notes: BTreeMap<String, String>,
+    }
+
+    /// a client's DHCP fingerprint & the device it was identified as
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct Fingerprint {
+        /// parameter request list (option 55) codes, comma separated
+        pub fingerprint: String,
+        /// vendor class identifier (option 60)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub vendor_class: Option<String>,
+        /// device category ex. `printer`, unset if the fingerprint isn't known
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub category: Option<String>,
+        /// the matched device, unset if the fingerprint isn't known or names none
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub device: Option<String>,
+    }
+
+    impl From<ip_manager::LeaseFingerprint> for Fingerprint {
+        fn from(fp: ip_manager::LeaseFingerprint) -> Self {
+            Self {
+                fingerprint: fp.fingerprint,
+                vendor_class: fp.vendor_class,
+                category: fp.category,
+                device: fp.device,
+            }
+        }
+    }
+
+    impl Lease {
+        /// does `search` match part of the ip, client id, hostname or a note
+        /// value? Case is ignored, as are `:` & `-` separators in client ids
+        pub(crate) fn matches(&self, search: &str) -> bool {
+            let search = search.trim().to_lowercase();
+            if search.is_empty() || self.ip.to_string().contains(&search) {
+                return true;
+            }
+            let id_search = search.replace([':', '-'], "");
+            let id_match = !id_search.is_empty()
+                && self
+                    .client_id
+                    .as_ref()
+                    .map_or(false, |id| id.contains(&id_search));
+            id_match
+                || self
+                    .hostname
+                    .as_ref()
+                    .map_or(false, |name| name.to_lowercase().contains(&search))
+                || self
+                    .notes
+                    .values()
+                    .any(|value| value.to_lowercase().contains(&search))
+        }
+    }
+
+    impl From<ip_manager::ActiveLease> for Lease {
+        fn from(lease: ip_manager::ActiveLease) -> Self {
+            Self {
+                ip: lease.ip,
+                client_id: lease.id.as_deref().map(hex),
+                network: lease.network,
+                state: match lease.state {
+                    IpState::Lease => "leased",
+                    IpState::Probate => "probated",
+                    IpState::Clear => "offered",
+                }
+                .to_owned(),
+                lease_state: lease.lease_state.to_string(),
+                expires_at: DateTime::<Utc>::from(lease.expires_at)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                hostname: lease.hostname,
+                relay_info: lease.relay_info.and_then(|info| relay_info(&info)),
+                fingerprint: lease.fingerprint.map(Fingerprint::from),
+                notes: lease.notes,
+            }
+        }
+    }
+
+    /// decoded relay agent information, `None` if it is malformed
+    pub(crate) fn relay_info(buf: &[u8]) -> Option<BTreeMap<String, String>> {
+        relay::decode(buf).ok().map(|opts| {
+            opts.into_iter()
+                .map(|(name, data)| (name, hex(&data)))
+                .collect()
+        })
+    }
+
+    /// a lease held for its client as a reservation, even when it isn't leased
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct StickyLease {
+        /// the address
+        pub ip: IpAddr,
+        /// client id as hex
+        pub client_id: String,
+        /// subnet of the network the address belongs to
+        pub network: IpAddr,
+        /// times the client renewed the address
+        pub renewals: u32,
+        /// rfc3339 time the address stops being held for the client
+        pub expires_at: String,
+        /// pinned through the api, renewals don't extend it
+        pub pinned: bool,
+    }
+
+    impl From<ip_manager::StickyLease> for StickyLease {
+        fn from(lease: ip_manager::StickyLease) -> Self {
+            Self {
+                ip: lease.ip,
+                client_id: hex(&lease.id),
+                network: lease.network,
+                renewals: lease.renewals,
+                expires_at: DateTime::<Utc>::from(lease.expires_at)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                pinned: lease.pinned,
+            }
+        }
+    }
+
+    /// a lease database maintenance run
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct MaintenanceRun {
+        /// rfc3339 time the run started
+        pub started_at: String,
+        /// how long the run took
+        pub elapsed_ms: u64,
+        /// pages returned to the filesystem
+        pub reclaimed_pages: u64,
+        /// database size after the run, in pages
+        pub page_count: u64,
+        /// free pages left in the database after the run
+        pub free_pages: u64,
+        /// the run converted the database to incremental vacuum with a full vacuum
+        pub full_vacuum: bool,
+    }
+
+    impl From<ip_manager::Maintenance> for MaintenanceRun {
+        fn from(run: ip_manager::Maintenance) -> Self {
+            Self {
+                started_at: DateTime::<Utc>::from(run.started_at)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                elapsed_ms: run.elapsed.as_millis() as u64,
+                reclaimed_pages: run.reclaimed_pages,
+                page_count: run.page_count,
+                free_pages: run.free_pages,
+                full_vacuum: run.full_vacuum,
+            }
+        }
+    }
+
+    /// a stored config version
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct ConfigVersion {
+        /// increases with every push
+        pub version: u64,
+        /// rfc3339 time the config was stored
+        pub created_at: String,
+        /// why the config was pushed, ex. `rollback to 3`
+        pub note: Option<String>,
+    }
+
+    impl From<ip_manager::ConfigVersion> for ConfigVersion {
+        fn from(version: ip_manager::ConfigVersion) -> Self {
+            Self {
+                version: version.version,
+                created_at: DateTime::<Utc>::from(version.created_at)
+                    .to_rfc3339_opts(SecondsFormat::Secs, true),
+                note: version.note,
+            }
+        }
+    }
+
+    /// a stored config version with its config
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct StoredConfig {
+        /// the version it was stored as
+        #[serde(flatten)]
+        pub version: ConfigVersion,
+        /// the config as it was pushed
+        pub config: String,
+    }
+
+    /// a config stored by a push or rollback
+    #[derive(Serialize, Debug, PartialEq, Clone, Eq)]
+    pub struct PushedConfig {
+        /// the version it was stored as
+        #[serde(flatten)]
+        pub version: ConfigVersion,
+        /// what changes from the running config when it's applied, `None` if
+        /// dora isn't running a DHCP config
+        pub diff: Option<ConfigDiff>,
+    }
+
+    /// a manually addressed host from a network's `static_hosts`
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct StaticHost {
+        /// the host's address
+        pub ip: IpAddr,
+        /// hardware address, `aa:bb:cc:dd:ee:ff`
+        pub mac: String,
+        /// subnet of the network the host is in
+        pub network: IpAddr,
+        /// codes of the options it is sent in reply to INFORM
+        pub options: Vec<u8>,
+    }
+
+    /// clients whose messages are logged at debug level & dumped in hex, whatever
+    /// the log level. A message matching any entry is traced
+    #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, Eq)]
+    #[serde(default)]
+    pub struct TraceFilter {
+        /// hardware addresses, `aa:bb:cc:dd:ee:ff`
+        pub macs: Vec<String>,
+        /// v4 client ids (option 61) or v6 DUIDs as hex, `:` & `-` separators are ignored
+        pub client_ids: Vec<String>,
+        /// v4 subnets holding the client's address or its relay, ex. `192.168.0.0/24`
+        pub subnets: Vec<Ipv4Net>,
+    }
+
+    impl TraceFilter {
+        pub(crate) fn to_filter(&self) -> Result<trace_filter::TraceFilter, String> {
+            let macs = self
+                .macs
+                .iter()
+                .map(|mac| match unhex(mac).as_deref() {
+                    Some(&[a, b, c, d, e, f]) => Ok(MacAddr::new(a, b, c, d, e, f)),
+                    _ => Err(format!(
+                        "`{mac}` must be 6 hex bytes, ex. `aa:bb:cc:dd:ee:ff`"
+                    )),
+                })
+                .collect::<Result<_, _>>()?;
+            let client_ids = self
+                .client_ids
+                .iter()
+                .map(|id| {
+                    unhex(id)
+                        .filter(|id| !id.is_empty())
+                        .ok_or_else(|| format!("client id `{id}` must be hex"))
+                })
+                .collect::<Result<_, _>>()?;
+            Ok(trace_filter::TraceFilter {
+                macs,
+                client_ids,
+                subnets: self.subnets.clone(),
+            })
+        }
+    }
+
+    impl From<trace_filter::TraceFilter> for TraceFilter {
+        fn from(filter: trace_filter::TraceFilter) -> Self {
+            Self {
+                macs: filter.macs.iter().map(ToString::to_string).collect(),
+                client_ids: filter.client_ids.iter().map(|id| hex(id)).collect(),
+                subnets: filter.subnets,
+            }
+        }
+    }
+
+    /// a message from a client flagged by a garbage client heuristic
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct GarbageClient {
+        /// `xid_reuse` or `mac_rotation`
+        pub kind: String,
+        /// hardware address the message was sent with
+        pub chaddr: String,
+        /// different chaddrs seen with the xid or on the circuit, including this one
+        pub chaddrs: usize,
+        /// the shared xid, for `xid_reuse`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub xid: Option<u32>,
+        /// the relay, for `mac_rotation`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub giaddr: Option<Ipv4Addr>,
+        /// opt 82 circuit id as hex, for `mac_rotation`
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub circuit_id: Option<String>,
+        /// was the message dropped
+        pub blocked: bool,
+        /// rfc3339 time the message was received
+        pub at: String,
+    }
+
+    impl From<garbage::Finding> for GarbageClient {
+        fn from(finding: garbage::Finding) -> Self {
+            let (xid, giaddr, circuit_id) = match &finding.kind {
+                garbage::FindingKind::XidReuse { xid } => (Some(*xid), None, None),
+                garbage::FindingKind::MacRotation { giaddr, circuit_id } => {
+                    (None, Some(*giaddr), Some(hex(circuit_id)))
+                }
+            };
+            Self {
+                kind: finding.kind.to_string(),
+                chaddr: finding.chaddr.to_string(),
+                chaddrs: finding.chaddrs,
+                xid,
+                giaddr,
+                circuit_id,
+                blocked: finding.blocked,
+                at: DateTime::<Utc>::from(finding.at).to_rfc3339_opts(SecondsFormat::Secs, true),
+            }
+        }
+    }
+
+    /// make the current lease on `ip` sticky
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct StickyRequest {
+        /// a leased address
+        pub ip: IpAddr,
+        /// seconds to hold the address for the client (default: 90 days)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub hold: Option<u64>,
+    }
+
+    /// hold `ip` for a client for `ttl` seconds, exactly one of `mac` or
+    /// `client_id` must be set
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct PinRequest {
+        /// the address to hold
+        pub ip: IpAddr,
+        /// hardware address of a client that doesn't send a client id (option 61)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub mac: Option<String>,
+        /// client id as hex, `:` & `-` separators are ignored
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub client_id: Option<String>,
+        /// seconds to hold the address for
+        pub ttl: u64,
+    }
+
+    impl PinRequest {
+        /// the client id the address is held for
+        pub(crate) fn client_id(&self) -> Result<Vec<u8>, &'static str> {
+            match (&self.mac, &self.client_id) {
+                (Some(mac), None) => unhex(mac)
+                    .filter(|mac| mac.len() == 6)
+                    .ok_or("`mac` must be 6 hex bytes, ex. `aa:bb:cc:dd:ee:ff`"),
+                (None, Some(id)) => unhex(id)
+                    .filter(|id| !id.is_empty())
+                    .ok_or("`client_id` must be hex"),
+                _ => Err("exactly one of `mac` or `client_id` must be set"),
+            }
+        }
+    }
+
+    /// longest note key accepted, in bytes
+    pub const MAX_NOTE_KEY: usize = 64;
+    /// longest note value accepted, in bytes
+    pub const MAX_NOTE_VALUE: usize = 1024;
+
+    /// the client id in a notes path, hex with optional `:` & `-` separators
+    pub(crate) fn note_client_id(id: &str) -> Result<Vec<u8>, String> {
+        unhex(id)
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| format!("{id:?} isn't a hex client id or MAC"))
+    }
+
+    /// can `key` be stored with `value`?
+    pub(crate) fn check_note(key: &str, value: Option<&str>) -> Result<(), String> {
+        if key.is_empty() || key.len() > MAX_NOTE_KEY {
+            return Err(format!(
+                "note keys must be 1 to {MAX_NOTE_KEY} bytes, got {key:?}"
+            ));
+        }
+        match value {
+            Some(value) if value.len() > MAX_NOTE_VALUE => Err(format!(
+                "note {key:?} is longer than {MAX_NOTE_VALUE} bytes"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// which clients to send FORCERENEW to, exactly one field must be set
+    #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, Eq)]
+    #[serde(default)]
+    pub struct ForceRenewTarget {
+        /// the client leasing this address
+        pub ip: Option<IpAddr>,
+        /// clients leasing from this network, its subnet ex. `192.168.0.0/24`
+        pub network: Option<IpNet>,
+        /// clients that matched this class when their lease was made
+        pub class: Option<String>,
+    }
+
+    impl ForceRenewTarget {
+        pub(crate) fn validate(&self) -> Result<(), &'static str> {
+            let set = [
+                self.ip.is_some(),
+                self.network.is_some(),
+                self.class.is_some(),
+            ];
+            match set.iter().filter(|set| **set).count() {
+                1 => Ok(()),
+                _ => Err("exactly one of `ip`, `network` or `class` must be set"),
+            }
+        }
+
+        pub(crate) fn matches(&self, nonce: &ip_manager::ForceRenewNonce) -> bool {
+            if let Some(ip) = self.ip {
+                return nonce.ip == ip;
+            }
+            if let Some(net) = self.network {
+                return net.trunc().addr() == nonce.network;
+            }
+            if let Some(class) = &self.class {
+                return nonce.classes.contains(class);
+            }
+            false
+        }
+    }
+
+    /// clients a FORCERENEW was sent to
+    #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone, Eq)]
+    pub struct ForceRenew {
+        /// addresses of the clients FORCERENEW was sent to
+        pub sent: Vec<IpAddr>,
+        /// addresses a FORCERENEW couldn't be sent to
+        pub failed: Vec<IpAddr>,
+    }
+
+    /// utilization samples of a range, oldest first
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    pub struct PoolStats {
+        /// subnet of the network the range belongs to
+        pub network: IpAddr,
+        /// first address of the range
+        pub start: IpAddr,
+        /// last address of the range
+        pub end: IpAddr,
+        /// samples in the requested time span
+        pub samples: Vec<PoolStatsSample>,
+    }
+
+    /// the utilization of a range at `timestamp`
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    pub struct PoolStatsSample {
+        /// rfc3339 time of the sample
+        pub timestamp: String,
+        /// allocatable addresses in the range
+        pub total: u64,
+        /// leased addresses
+        pub leased: u64,
+        /// offered addresses waiting for REQUEST
+        pub reserved: u64,
+        /// addresses on probation
+        pub probated: u64,
+        /// fraction of `total` that is leased, reserved or probated
+        pub utilization: f64,
+    }
+
+    /// group samples by range, keeping the order they were taken in
+    pub(crate) fn pool_stats(samples: Vec<ip_manager::PoolSample>) -> Vec<PoolStats> {
+        let mut pools = BTreeMap::<_, PoolStats>::new();
+        for sample in samples {
+            let (start, end) = (*sample.range.start(), *sample.range.end());
+            let used = sample.counts.leased + sample.counts.reserved + sample.counts.probated;
+            pools
+                .entry((sample.network, start, end))
+                .or_insert_with(|| PoolStats {
+                    network: sample.network,
+                    start,
+                    end,
+                    samples: Vec::new(),
+                })
+                .samples
+                .push(PoolStatsSample {
+                    timestamp: DateTime::<Utc>::from(sample.sampled_at)
+                        .to_rfc3339_opts(SecondsFormat::Secs, true),
+                    total: sample.total,
+                    leased: sample.counts.leased,
+                    reserved: sample.counts.reserved,
+                    probated: sample.counts.probated,
+                    utilization: if sample.total == 0 {
+                        0.
+                    } else {
+                        used as f64 / sample.total as f64
+                    },
+                });
+        }
+        pools.into_values().collect()
+    }
+
+    /// daily utilization rollups of a range
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    pub struct DailyPoolStats {
+        /// subnet of the network the range belongs to
+        pub network: IpAddr,
+        /// first address of the range
+        pub start: IpAddr,
+        /// last address of the range
+        pub end: IpAddr,
+        /// rollups in the requested time span
+        pub days: Vec<PoolStatsDay>,
+    }
+
+    /// the utilization of a range over a UTC day
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    pub struct PoolStatsDay {
+        /// the day, ex. `2023-11-14`
+        pub date: String,
+        /// number of samples rolled up
+        pub samples: u64,
+        /// allocatable addresses in the range
+        pub total: u64,
+        /// average leased addresses
+        pub leased_avg: f64,
+        /// most leased addresses in a sample
+        pub leased_max: u64,
+        /// average fraction of `total` that is leased, reserved or probated
+        pub utilization_avg: f64,
+        /// highest fraction of `total` that is leased, reserved or probated
+        pub utilization_max: f64,
+    }
+
+    /// group rollups by range, oldest day first
+    pub(crate) fn daily_pool_stats(
+        rollups: Vec<ip_manager::DailyPoolStats>,
+    ) -> Vec<DailyPoolStats> {
+        let mut pools = BTreeMap::<_, DailyPoolStats>::new();
+        for rollup in rollups {
+            let (start, end) = (*rollup.range.start(), *rollup.range.end());
+            let fraction = |used: f64| {
+                if rollup.total == 0 {
+                    0.
+                } else {
+                    used / rollup.total as f64
+                }
+            };
+            let day = PoolStatsDay {
+                date: DateTime::<Utc>::from(rollup.day)
+                    .format("%Y-%m-%d")
+                    .to_string(),
+                samples: rollup.samples,
+                total: rollup.total,
+                leased_avg: rollup.leased_avg,
+                leased_max: rollup.leased_max,
+                utilization_avg: fraction(rollup.used_avg),
+                utilization_max: fraction(rollup.used_max as f64),
+            };
+            pools
+                .entry((rollup.network, start, end))
+                .or_insert_with(|| DailyPoolStats {
+                    network: rollup.network,
+                    start,
+                    end,
+                    days: Vec::new(),
+                })
+                .days
+                .push(day);
+        }
+        pools.into_values().collect()
+    }
+
+    /// the v6 server id
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Eq)]
+    pub struct ServerId {
+        /// DUID as hex
+        pub duid: String,
+    }
+
+    pub(crate) fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// bytes of a hex string, ignoring `:` & `-` separators
+    pub(crate) fn unhex(s: &str) -> Option<Vec<u8>> {
+        let digits = s.replace([':', '-'], "");
+        if digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        (0..digits.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::IpAddr,
+        time::{Duration, SystemTime},
+    };
+
+    use ip_manager::{DailyPoolStats, LeaseQuery, LeaseState, PoolSample, RangeCounts, Storage};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_span() {
+        assert_eq!(
+            handlers::parse_span("24h"),
+            Some(Duration::from_secs(86_400))
+        );
+        assert_eq!(
+            handlers::parse_span("30m"),
+            Some(Duration::from_secs(1_800))
+        );
+        assert_eq!(
+            handlers::parse_span("7d"),
+            Some(Duration::from_secs(604_800))
+        );
+        assert_eq!(handlers::parse_span("10s"), Some(Duration::from_secs(10)));
+        assert_eq!(handlers::parse_span("h"), None);
+        assert_eq!(handlers::parse_span("24"), None);
+        assert_eq!(handlers::parse_span(""), None);
+    }
+
+    #[test]
+    fn test_lease_search() {
+        let lease = models::Lease {
+            ip: "192.168.0.10".parse().unwrap(),
+            client_id: Some("01aabbccddeeff".to_owned()),
+            network: "192.168.0.0".parse().unwrap(),
+            state: "leased".to_owned(),
+            lease_state: "active".to_owned(),
+            expires_at: "2023-11-14T23:13:20Z".to_owned(),
+            hostname: Some("Printer".to_owned()),
+            relay_info: None,
+            fingerprint: None,
+            notes: BTreeMap::from([("owner".to_owned(), "Alice".to_owned())]),
+        };
+        assert!(lease.matches(""));
+        assert!(lease.matches("192.168.0.1"));
+        assert!(lease.matches("AA:BB:CC"));
+        assert!(lease.matches("dd-ee-ff"));
+        assert!(lease.matches("print"));
+        assert!(lease.matches("alice"));
+        assert!(!lease.matches("10.0.0"));
+        assert!(!lease.matches("laptop"));
+    }
+
+    #[test]
+    fn test_lease_relay_info() {
+        let mut data = vec![1, 4, b'e', b't', b'h', b'0', 6, 2, 0xab, 0xcd, 9, 7];
+        data.extend(3561_u32.to_be_bytes());
+        data.extend([2, 1, 2]);
+        let info = models::relay_info(&data).unwrap();
+        assert_eq!(info["circuit_id"], "65746830");
+        assert_eq!(info["subscriber_id"], "abcd");
+        assert_eq!(info["vendor_specific.3561"], "0102");
+        // truncated
+        assert_eq!(models::relay_info(&[1, 4, b'e']), None);
+    }
+
+    #[test]
+    fn test_sticky_lease() {
+        let lease = models::StickyLease::from(ip_manager::StickyLease {
+            ip: "192.168.0.10".parse().unwrap(),
+            id: vec![1, 0xaa, 0xbb],
+            network: "192.168.0.0".parse().unwrap(),
+            renewals: 3,
+            expires_at: SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_003_600),
+            pinned: false,
+        });
+        assert_eq!(lease.client_id, "01aabb");
+        assert_eq!(lease.expires_at, "2023-11-14T23:13:20Z");
+        let req: models::StickyRequest = serde_json::from_str(r#"{"ip": "192.168.0.10"}"#).unwrap();
+        assert_eq!(req.hold, None);
+    }
+
+    #[test]
+    fn test_pin_request() {
+        let req: models::PinRequest = serde_json::from_str(
+            r#"{"ip": "192.168.0.10", "mac": "AA:bb:cc:dd:ee:0f", "ttl": 7200}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            req.client_id(),
+            Ok(vec![0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0x0f])
+        );
+        let req: models::PinRequest =
+            serde_json::from_str(r#"{"ip": "192.168.0.10", "client_id": "01-aa-bb", "ttl": 60}"#)
+                .unwrap();
+        assert_eq!(req.client_id(), Ok(vec![1, 0xaa, 0xbb]));
+        for bad in [
+            r#"{"ip": "192.168.0.10", "ttl": 60}"#,
+            r#"{"ip": "192.168.0.10", "mac": "aa:bb", "ttl": 60}"#,
+            r#"{"ip": "192.168.0.10", "client_id": "+a", "ttl": 60}"#,
+            r#"{"ip": "192.168.0.10", "mac": "aa:bb:cc:dd:ee:ff", "client_id": "01", "ttl": 60}"#,
+        ] {
+            let req: models::PinRequest = serde_json::from_str(bad).unwrap();
+            assert!(req.client_id().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_forcerenew_target() {
+        let nonce = ip_manager::ForceRenewNonce {
+            ip: "192.168.0.10".parse().unwrap(),
+            id: vec![1, 2, 3],
+            chaddr: vec![1, 2, 3],
+            network: "192.168.0.0".parse().unwrap(),
+            server_id: "192.168.0.1".parse().unwrap(),
+            classes: vec!["printers".to_owned()],
+            nonce: vec![0; 16],
+        };
+        let target = |json: &str| serde_json::from_str::<models::ForceRenewTarget>(json).unwrap();
+        assert!(target(r#"{"ip": "192.168.0.10"}"#).matches(&nonce));
+        assert!(!target(r#"{"ip": "192.168.0.11"}"#).matches(&nonce));
+        assert!(target(r#"{"network": "192.168.0.1/24"}"#).matches(&nonce));
+        assert!(!target(r#"{"network": "10.0.0.0/8"}"#).matches(&nonce));
+        assert!(target(r#"{"class": "printers"}"#).matches(&nonce));
+        assert!(!target(r#"{"class": "phones"}"#).matches(&nonce));
+        assert!(target("{}").validate().is_err());
+        assert!(target(r#"{"ip": "192.168.0.10", "class": "printers"}"#)
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_pool_stats() {
+        let sample = |start: [u8; 4], leased, at| PoolSample {
+            network: [192, 168, 0, 0].into(),
+            range: start.into()..=[192, 168, 0, 255].into(),
+            total: 100,
+            counts: RangeCounts {
+                leased,
+                reserved: 5,
+                probated: 0,
+            },
+            sampled_at: SystemTime::UNIX_EPOCH + Duration::from_secs(at),
+        };
+        let pools = models::pool_stats(vec![
+            sample([192, 168, 0, 100], 10, 0),
+            sample([192, 168, 0, 10], 20, 0),
+            sample([192, 168, 0, 100], 45, 300),
+        ]);
+        assert_eq!(pools.len(), 2);
+        let pool = pools
+            .iter()
+            .find(|pool| pool.start == IpAddr::from([192, 168, 0, 100]))
+            .unwrap();
+        assert_eq!(pool.samples.len(), 2);
+        assert_eq!(pool.samples[0].timestamp, "1970-01-01T00:00:00Z");
+        assert_eq!(pool.samples[1].leased, 45);
+        assert_eq!(pool.samples[1].utilization, 0.5);
+    }
+    #[test]
+    fn test_daily_pool_stats() {
+        let rollup = |day: u64| DailyPoolStats {
+            network: [192, 168, 0, 0].into(),
+            range: [192, 168, 0, 100].into()..=[192, 168, 0, 199].into(),
+            day: SystemTime::UNIX_EPOCH + Duration::from_secs(day * 86_400),
+            samples: 288,
+            total: 100,
+            leased_avg: 40.5,
+            leased_max: 60,
+            used_avg: 50.,
+            used_max: 75,
+        };
+        let pools = models::daily_pool_stats(vec![rollup(19_675), rollup(19_676)]);
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].days.len(), 2);
+        assert_eq!(pools[0].days[0].date, "2023-11-14");
+        assert_eq!(pools[0].days[1].date, "2023-11-15");
+        assert_eq!(pools[0].days[0].utilization_avg, 0.5);
+        assert_eq!(pools[0].days[0].utilization_max, 0.75);
+    }
+
+    #[tokio::test]
+    async fn test_health() -> anyhow::Result<()> {
+        let api = ExternalApi::new("0.0.0.0:8889".parse().unwrap());
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let r = reqwest::get("http://0.0.0.0:8889/health")
+            .await?
+            .error_for_status();
+        // initial health state will be BAD i.e. 500
+        match r {
+            Ok(_) => {}
+            Err(err) => {
+                assert_eq!(
+                    err.status(),
+                    Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR)
+                );
+            }
+        }
+        Ok(())
+    }
+    // very simple test for existence of metrics endpoint
+    #[tokio::test]
+    async fn test_metrics() -> anyhow::Result<()> {
+        let api = ExternalApi::new("0.0.0.0:8888".parse().unwrap());
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let bytes = reqwest::get("http://0.0.0.0:8888/metrics")
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await;
+        assert!(bytes.is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_instances() -> anyhow::Result<()> {
+        let api = ExternalApi::new("0.0.0.0:8887".parse().unwrap())
+            .with_instance("lab1", Instance::new().with_server_id(&[0, 1]))
+            .with_instance("lab2", Instance::new());
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let names = reqwest::get("http://0.0.0.0:8887/instances")
+            .await?
+            .json::<Vec<String>>()
+            .await?;
+        assert_eq!(names, ["lab1", "lab2"]);
+        let id = reqwest::get("http://0.0.0.0:8887/instances/lab1/v6/server-id")
+            .await?
+            .json::<models::ServerId>()
+            .await?;
+        assert_eq!(id.duid, "0001");
+        let r = reqwest::get("http://0.0.0.0:8887/instances/lab2/v6/server-id").await?;
+        assert_eq!(r.status(), reqwest::StatusCode::NOT_FOUND);
+        let r = reqwest::get("http://0.0.0.0:8887/instances/nope/v6/server-id").await?;
+        assert_eq!(r.status(), reqwest::StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_config_diff() -> anyhow::Result<()> {
+        let yaml = "networks:\n    192.168.0.1/24:\n        ranges:\n            -\n                start: 192.168.0.10\n                end: 192.168.0.20\n                config:\n                    lease_time:\n                        default: 3600\n";
+        let running = Arc::new(DhcpConfig::parse_str(yaml)?);
+        let api = ExternalApi::new("0.0.0.0:8886".parse().unwrap()).with_config(running);
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let client = reqwest::Client::new();
+        let diff = client
+            .post("http://0.0.0.0:8886/config/diff")
+            .body(yaml.replace("192.168.0.20", "192.168.0.30"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?;
+        assert_eq!(
+            diff["networks_changed"][0]["ranges_added"][0],
+            serde_json::json!({ "start": "192.168.0.10", "end": "192.168.0.30" })
+        );
+        assert_eq!(
+            diff["networks_changed"][0]["ranges_removed"][0]["end"],
+            "192.168.0.20"
+        );
+        let r = client
+            .post("http://0.0.0.0:8886/config/diff")
+            .body("networks: [")
+            .send()
+            .await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_config_versions() -> anyhow::Result<()> {
+        let yaml = "networks:\n    192.168.0.1/24:\n        ranges:\n            -\n                start: 192.168.0.10\n                end: 192.168.0.20\n                config:\n                    lease_time:\n                        default: 3600\n";
+        let store = SqliteDb::memory().await?;
+        let api = ExternalApi::new("0.0.0.0:8894".parse().unwrap())
+            .with_store(store.clone())
+            .with_config(Arc::new(DhcpConfig::parse_str(yaml)?));
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let client = reqwest::Client::new();
+        let url = "http://0.0.0.0:8894/config/versions";
+        let pushed = client
+            .post(format!("{url}?note=wider"))
+            .body(yaml.replace("192.168.0.20", "192.168.0.30"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?;
+        assert_eq!(pushed["version"], 1);
+        assert_eq!(pushed["note"], "wider");
+        assert_eq!(
+            pushed["diff"]["networks_changed"][0]["ranges_added"][0]["end"],
+            "192.168.0.30"
+        );
+        // invalid configs aren't stored
+        let r = client.post(url).body("networks: [").send().await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        client
+            .post(url)
+            .body(yaml)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let rollback = client
+            .post(format!("{url}/1/rollback"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?;
+        assert_eq!(rollback["version"], 3);
+        assert_eq!(rollback["note"], "rollback to 1");
+        let versions = reqwest::get(url)
+            .await?
+            .json::<Vec<models::ConfigVersion>>()
+            .await?;
+        assert_eq!(
+            versions.iter().map(|v| v.version).collect::<Vec<_>>(),
+            [3, 2, 1]
+        );
+        let stored = reqwest::get(format!("{url}/3"))
+            .await?
+            .json::<models::StoredConfig>()
+            .await?;
+        assert_eq!(stored.config, yaml.replace("192.168.0.20", "192.168.0.30"));
+        let (latest, _) = store.latest_config().await?.unwrap();
+        assert_eq!(latest.version, 3);
+        let r = client.post(format!("{url}/9/rollback")).send().await?;
+        assert_eq!(r.status(), reqwest::StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lease_query() {
+        assert!(LeaseQuery::new("").is_ok());
+        assert!(LeaseQuery::new(
+            r#"state=active AND network=10.1.0.0/16 and hostname ~ "printer" AND client_id!=aa:bb"#
+        )
+        .is_ok());
+        assert!(LeaseQuery::new("ip=10.0.0.1 AND ip!=10.0.0.0/24 AND hostname!~lab").is_ok());
+        assert!(LeaseQuery::new("state=gone").is_err());
+        assert!(LeaseQuery::new("ip~10.0").is_err());
+        assert!(LeaseQuery::new("client_id=zz").is_err());
+        assert!(LeaseQuery::new("mac=aa").is_err());
+        assert!(LeaseQuery::new("state=leased AND").is_err());
+        assert!(LeaseQuery::new(r#"hostname="printer"#).is_err());
+        assert!(LeaseQuery::new("hostname printer").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lease_query_api() -> anyhow::Result<()> {
+        let store = SqliteDb::memory().await?;
+        let expires = SystemTime::now() + Duration::from_secs(3600);
+        for (ip, host, state) in [
+            ([10, 1, 0, 10], "printer-1", LeaseState::Active),
+            ([10, 1, 0, 11], "laptop", LeaseState::Active),
+            ([10, 1, 1, 12], "Printer-2", LeaseState::Active),
+            ([10, 2, 0, 13], "printer-3", LeaseState::Active),
+            ([10, 1, 0, 14], "printer-4", LeaseState::Declined),
+        ] {
+            let network = IpAddr::from([ip[0], ip[1], ip[2], 0]);
+            let id = [1, ip[3]];
+            let ip = IpAddr::from(ip);
+            store.insert(ip, network, &id, expires, state).await?;
+            store.set_hostname(host, ip, &id, expires).await?;
+        }
+        let api = ExternalApi::new("0.0.0.0:8885".parse().unwrap()).with_store(store);
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let get = |params: &'static str| async move {
+            reqwest::Client::new()
+                .get("http://0.0.0.0:8885/v4/leases")
+                .query(&[("q", params)])
+                .send()
+                .await
+        };
+        let ips = |leases: Vec<models::Lease>| {
+            leases
+                .into_iter()
+                .map(|lease| lease.ip.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let leases = get(r#"state=active AND network=10.1.0.0/16 AND hostname~"printer""#)
+            .await?
+            .json::<Vec<models::Lease>>()
+            .await?;
+        assert_eq!(ips(leases), ["10.1.0.10", "10.1.1.12"]);
+        let leases = get("client_id=01:0b").await?.json().await?;
+        assert_eq!(ips(leases), ["10.1.0.11"]);
+        let leases = get("hostname!~printer").await?.json().await?;
+        assert_eq!(ips(leases), ["10.1.0.11"]);
+
+        // sorted & paged
+        let leases =
+            reqwest::get("http://0.0.0.0:8885/v4/leases?sort=ip&order=desc&limit=2&offset=1")
+                .await?
+                .json()
+                .await?;
+        assert_eq!(ips(leases), ["10.1.1.12", "10.1.0.14"]);
+
+        let r = get("state=gone").await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        let r = reqwest::get("http://0.0.0.0:8885/v4/leases?sort=mac").await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lease_transitions() -> anyhow::Result<()> {
+        let store = SqliteDb::memory().await?;
+        let (ip, network, id) = (
+            IpAddr::from([192, 168, 0, 11]),
+            IpAddr::from([192, 168, 0, 0]),
+            [1, 2],
+        );
+        let expires = SystemTime::now() + Duration::from_secs(3600);
+        store
+            .insert(ip, network, &id, expires, LeaseState::Offered)
+            .await?;
+        store
+            .update_unexpired(ip, LeaseState::Active, &id, expires, Some(&id[..]))
+            .await?;
+        // an active lease can't be reclaimed, nothing changes
+        assert!(store
+            .update_ip(ip, LeaseState::Reclaimed, None, expires)
+            .await?
+            .is_none());
+        assert!(store.release_ip(ip, &id).await?.is_some());
+        // released already
+        assert!(store.release_ip(ip, &id).await?.is_none());
+        store
+            .insert(
+                IpAddr::from([192, 168, 0, 12]),
+                network,
+                &[3, 4],
+                SystemTime::now() - Duration::from_secs(1),
+                LeaseState::Offered,
+            )
+            .await?;
+        assert_eq!(store.expire_leases(SystemTime::now()).await?, 1);
+
+        let api = ExternalApi::new("0.0.0.0:8895".parse().unwrap()).with_store(store);
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let states = |transitions: Vec<models::LeaseTransition>| {
+            transitions
+                .into_iter()
+                .map(|t| (t.ip.to_string(), t.from, t.to))
+                .collect::<Vec<_>>()
+        };
+        let transitions = reqwest::get("http://0.0.0.0:8895/v4/leases/transitions?ip=192.168.0.11")
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let ip = || "192.168.0.11".to_owned();
+        assert_eq!(
+            states(transitions),
+            [
+                (ip(), Some("active".to_owned()), "released".to_owned()),
+                (ip(), Some("offered".to_owned()), "active".to_owned()),
+                (ip(), None, "offered".to_owned()),
+            ]
+        );
+        let transitions = reqwest::get("http://0.0.0.0:8895/v4/leases/transitions?limit=1")
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        assert_eq!(
+            states(transitions),
+            [(
+                "192.168.0.12".to_owned(),
+                Some("offered".to_owned()),
+                "expired".to_owned()
+            )]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_static_hosts() -> anyhow::Result<()> {
+        let yaml = "networks:\n    192.168.0.1/24:\n        ranges:\n            -\n                start: 192.168.0.10\n                end: 192.168.0.20\n                config:\n                    lease_time:\n                        default: 3600\n        static_hosts:\n            -\n                ip: 192.168.0.250\n                mac: aa:bb:cc:dd:ee:ff\n                options:\n                    values:\n                        3:\n                            type: ip\n                            value: 192.168.0.1\n";
+        let api = ExternalApi::new("0.0.0.0:8891".parse().unwrap())
+            .with_config(Arc::new(DhcpConfig::parse_str(yaml)?));
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let hosts = reqwest::get("http://0.0.0.0:8891/v4/static-hosts")
+            .await?
+            .error_for_status()?
+            .json::<Vec<models::StaticHost>>()
+            .await?;
+        assert_eq!(
+            hosts,
+            [models::StaticHost {
+                ip: IpAddr::from([192, 168, 0, 250]),
+                mac: "aa:bb:cc:dd:ee:ff".to_owned(),
+                network: IpAddr::from([192, 168, 0, 0]),
+                options: vec![3],
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pins() -> anyhow::Result<()> {
+        let yaml = "networks:\n    192.168.0.1/24:\n        ranges:\n            -\n                start: 192.168.0.10\n                end: 192.168.0.20\n                config:\n                    lease_time:\n                        default: 3600\n";
+        let store = SqliteDb::memory().await?;
+        let (ip, network) = (
+            IpAddr::from([192, 168, 0, 11]),
+            IpAddr::from([192, 168, 0, 0]),
+        );
+        let expires = SystemTime::now() + Duration::from_secs(3600);
+        store
+            .insert(ip, network, &[1, 2], expires, LeaseState::Active)
+            .await?;
+        let api = ExternalApi::new("0.0.0.0:8890".parse().unwrap())
+            .with_store(store)
+            .with_config(Arc::new(DhcpConfig::parse_str(yaml)?));
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let client = reqwest::Client::new();
+        let pin = |body: &'static str| {
+            client
+                .post("http://0.0.0.0:8890/v4/pins")
+                .header("content-type", "application/json")
+                .body(body)
+                .send()
+        };
+
+        let r = pin(r#"{"ip": "192.168.0.12", "mac": "aa:bb:cc:dd:ee:ff", "ttl": 7200}"#).await?;
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let lease = r.json::<models::StickyLease>().await?;
+        assert_eq!(lease.client_id, "aabbccddeeff");
+        assert!(lease.pinned);
+        // leased by another client, or outside every network
+        let r = pin(r#"{"ip": "192.168.0.11", "mac": "aa:bb:cc:dd:ee:ff", "ttl": 60}"#).await?;
+        assert_eq!(r.status(), reqwest::StatusCode::CONFLICT);
+        let r = pin(r#"{"ip": "10.0.0.1", "mac": "aa:bb:cc:dd:ee:ff", "ttl": 60}"#).await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+
+        let pins = reqwest::get("http://0.0.0.0:8890/v4/pins")
+            .await?
+            .json::<Vec<models::StickyLease>>()
+            .await?;
+        assert_eq!(pins, [lease]);
+        let unpin = || {
+            client
+                .delete("http://0.0.0.0:8890/v4/pins/192.168.0.12")
+                .send()
+        };
+        assert_eq!(unpin().await?.status(), reqwest::StatusCode::OK);
+        assert_eq!(unpin().await?.status(), reqwest::StatusCode::NOT_FOUND);
+        let r = client
+            .delete("http://0.0.0.0:8890/v4/pins/2001:db8::1")
+            .send()
+            .await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        // sticky leases are v4 only
+        let r = client
+            .delete("http://0.0.0.0:8890/v4/sticky/2001:db8::1")
+            .send()
+            .await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_client_notes() -> anyhow::Result<()> {
+        let store = SqliteDb::memory().await?;
+        let expires = SystemTime::now() + Duration::from_secs(3600);
+        store
+            .insert(
+                IpAddr::from([192, 168, 0, 11]),
+                IpAddr::from([192, 168, 0, 0]),
+                &[0xaa, 0xbb],
+                expires,
+                LeaseState::Active,
+            )
+            .await?;
+        let api = ExternalApi::new("0.0.0.0:8896".parse().unwrap()).with_store(store);
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let client = reqwest::Client::new();
+        let put = |body: &'static str| {
+            client
+                .put("http://0.0.0.0:8896/v4/clients/aa:bb/notes")
+                .header("content-type", "application/json")
+                .body(body)
+                .send()
+        };
+
+        let r = put(r#"{"owner": "alice", "ticket": "NET-1234"}"#).await?;
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let r = put(r#"{"ticket": null, "rack": "b2"}"#).await?;
+        let notes = r.json::<BTreeMap<String, String>>().await?;
+        let expected = BTreeMap::from([
+            ("owner".to_owned(), "alice".to_owned()),
+            ("rack".to_owned(), "b2".to_owned()),
+        ]);
+        assert_eq!(notes, expected);
+        assert_eq!(
+            put(r#"{"": "empty key"}"#).await?.status(),
+            reqwest::StatusCode::BAD_REQUEST
+        );
+        // returned with the client's leases
+        let leases = reqwest::get("http://0.0.0.0:8896/v4/leases?q=client_id=aabb")
+            .await?
+            .json::<Vec<models::Lease>>()
+            .await?;
+        assert_eq!(leases.len(), 1);
+        assert_eq!(leases[0].notes, expected);
+
+        let delete = || {
+            client
+                .delete("http://0.0.0.0:8896/v4/clients/aabb/notes/rack")
+                .send()
+        };
+        assert_eq!(delete().await?.status(), reqwest::StatusCode::OK);
+        assert_eq!(delete().await?.status(), reqwest::StatusCode::NOT_FOUND);
+        let notes = reqwest::get("http://0.0.0.0:8896/v4/clients/aabb/notes")
+            .await?
+            .json::<BTreeMap<String, String>>()
+            .await?;
+        assert_eq!(notes.keys().collect::<Vec<_>>(), ["owner"]);
+        let r = reqwest::get("http://0.0.0.0:8896/v4/clients/zz/notes").await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_garbage_clients() -> anyhow::Result<()> {
+        use dora_core::{
+            dhcproto::v4::Message,
+            garbage::{Detector, Limits},
+        };
+        let detector = Detector::new(Limits {
+            window: Duration::from_secs(60),
+            xid_chaddrs: 1,
+            circuit_chaddrs: 8,
+            block: false,
+        });
+        let mut msg = Message::default();
+        msg.set_xid(0xdead_beef).set_chaddr(&[0, 1, 2, 3, 4, 5]);
+        assert!(detector.check(&msg).is_none());
+        msg.set_chaddr(&[0, 1, 2, 3, 4, 6]);
+        assert!(detector.check(&msg).is_some());
+
+        let api = ExternalApi::new("0.0.0.0:8897".parse().unwrap());
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let found = reqwest::get("http://0.0.0.0:8897/v4/garbage-clients")
+            .await?
+            .json::<Vec<models::GarbageClient>>()
+            .await?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].kind, "xid_reuse");
+        assert_eq!(found[0].chaddr, "00:01:02:03:04:06");
+        assert_eq!(found[0].xid, Some(0xdead_beef));
+        assert_eq!(found[0].chaddrs, 2);
+        assert!(!found[0].blocked);
+        let r = reqwest::Client::new()
+            .delete("http://0.0.0.0:8897/v4/garbage-clients")
+            .send()
+            .await?;
+        assert_eq!(r.status(), reqwest::StatusCode::NO_CONTENT);
+        let found = reqwest::get("http://0.0.0.0:8897/v4/garbage-clients")
+            .await?
+            .json::<Vec<models::GarbageClient>>()
+            .await?;
+        assert!(found.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fake_clock() -> anyhow::Result<()> {
+        use ip_manager::FakeClock;
+        let clock = Arc::new(FakeClock::frozen(SystemTime::now()));
+        let store = SqliteDb::memory().await?.with_clock(clock.clone());
+        let expires = store.now() + Duration::from_secs(3600);
+        store
+            .insert(
+                IpAddr::from([192, 168, 0, 12]),
+                IpAddr::from([192, 168, 0, 0]),
+                &[0xaa, 0xbb],
+                expires,
+                LeaseState::Active,
+            )
+            .await?;
+        let api = ExternalApi::new("0.0.0.0:8898".parse().unwrap()).with_store(store);
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let leases = || async {
+            reqwest::get("http://0.0.0.0:8898/v4/leases")
+                .await?
+                .json::<Vec<models::Lease>>()
+                .await
+        };
+        assert_eq!(leases().await?.len(), 1);
+        // expired once the clock passes the hour, without waiting for it
+        clock.advance(Duration::from_secs(3599));
+        assert_eq!(leases().await?.len(), 1);
+        clock.advance(Duration::from_secs(1));
+        assert!(leases().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_trace_filter() -> anyhow::Result<()> {
+        let api = ExternalApi::new("0.0.0.0:8892".parse().unwrap());
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let client = reqwest::Client::new();
+        let url = "http://0.0.0.0:8892/trace-filter";
+        let put = |body: &'static str| {
+            client
+                .put(url)
+                .header("content-type", "application/json")
+                .body(body)
+                .send()
+        };
+
+        let r = put(r#"{"macs": ["AA:BB:CC:DD:EE:FF"], "subnets": ["10.0.1.0/24"]}"#).await?;
+        assert_eq!(r.status(), reqwest::StatusCode::OK);
+        let filter = models::TraceFilter {
+            macs: vec!["aa:bb:cc:dd:ee:ff".to_owned()],
+            client_ids: vec![],
+            subnets: vec!["10.0.1.0/24".parse()?],
+        };
+        assert_eq!(r.json::<models::TraceFilter>().await?, filter);
+        let r = reqwest::get(url)
+            .await?
+            .json::<models::TraceFilter>()
+            .await?;
+        assert_eq!(r, filter);
+        // not a mac, the filter is left as is
+        let r = put(r#"{"macs": ["aa:bb"]}"#).await?;
+        assert_eq!(r.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert!(!dora_core::trace_filter::get().is_empty());
+
+        let r = client.delete(url).send().await?;
+        assert_eq!(r.status(), reqwest::StatusCode::NO_CONTENT);
+        assert!(dora_core::trace_filter::get().is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_maintenance() -> anyhow::Result<()> {
+        let store = SqliteDb::memory().await?;
+        let api = ExternalApi::new("0.0.0.0:8893".parse().unwrap()).with_store(store.clone());
+        let _handle = api.serve();
+        // wait for server to come up
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let last_run = || async {
+            reqwest::get("http://0.0.0.0:8893/db/maintenance")
+                .await?
+                .error_for_status()?
+                .json::<Option<models::MaintenanceRun>>()
+                .await
+        };
+        assert_eq!(last_run().await?, None);
+
+        // the first run converts the database to incremental vacuum
+        let run = store.maintain(0).await?;
+        assert!(run.full_vacuum);
+        assert_eq!(last_run().await?, Some(run.into()));
+        let run = store.maintain(0).await?;
+        assert!(!run.full_vacuum);
+        assert_eq!(run.free_pages, 0);
+        assert_eq!(last_run().await?, Some(run.into()));
         Ok(())
     }
 }
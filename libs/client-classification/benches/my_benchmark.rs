@@ -31,6 +31,7 @@ fn criterion_benchmark(c: &mut Criterion) {
                     opts,
                     msg: &v4::Message::default(),
                     deps: HashSet::new(),
+                    fingerprints: None,
                 };
                 client_classification::eval(
                     &client_classification::ast::build_ast(tokens).unwrap(),
@@ -63,6 +64,7 @@ fn criterion_benchmark(c: &mut Criterion) {
                     opts,
                     msg: &v4::Message::default(),
                     deps: HashSet::new(),
+                    fingerprints: None,
                 };
                 client_classification::eval(&ast, &args).unwrap()
             })
@@ -21,7 +21,13 @@ pub enum Expr {
     Bool(bool),
     Option(u8),
     Member(String),
+    // true if the user class option contains this class
+    UserClass(String),
     Relay(u8),
+    // option 82 vendor-specific sub-option data for an enterprise number
+    RelayVendor(u32),
+    // option 125 data for an enterprise number
+    Vendor(u32),
     Mac(),
     Hlen(),
     HType(),
@@ -31,6 +37,12 @@ pub enum Expr {
     SiAddr(),
     MsgType(),
     TransId(),
+    // client machine identifier (option 97), as a hyphenated uuid
+    Uuid(),
+    // parameter request list (option 55) codes, comma separated
+    Fingerprint(),
+    // category of the device the fingerprint db finds for the client
+    Category(),
     // operation (expr, start, len) where len of None means 'all'
     Substring(Box<Expr>, isize, Option<isize>),
     Concat(Box<Expr>, Box<Expr>),
@@ -114,11 +126,17 @@ fn parse_expr(pairs: Pairs<Rule>, pratt: &PrattParser<Rule>) -> ParseResult<Expr
                 Rule::pkt_siaddr => Expr::SiAddr(),
                 Rule::pkt_msgtype => Expr::MsgType(),
                 Rule::pkt_transid => Expr::TransId(),
+                Rule::pkt_uuid => Expr::Uuid(),
+                Rule::pkt_fingerprint => Expr::Fingerprint(),
+                Rule::pkt_category => Expr::Category(),
                 Rule::ip => Expr::Ip(primary.as_str().parse()?),
                 Rule::string => Expr::String(parse_string(primary)),
                 Rule::option => Expr::Option(parse_num(primary)?),
                 Rule::relay => Expr::Relay(parse_num(primary)?),
+                Rule::relay_vendor => Expr::RelayVendor(parse_num(primary)?),
+                Rule::vendor => Expr::Vendor(parse_num(primary)?),
                 Rule::member => Expr::Member(parse_string_inner(primary)),
+                Rule::user_class => Expr::UserClass(parse_string_inner(primary)),
                 // trim off '0x'. hex decode?
                 Rule::hex => Expr::Hex(primary.as_str()[2..].to_string()),
                 Rule::substring => {
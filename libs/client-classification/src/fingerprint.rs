@@ -0,0 +1,229 @@
+//! DHCP fingerprints
+//!
+//! Clients ask for options in an order set by their DHCP client software, so the
+//! parameter request list (option 55) together with the vendor class (option 60)
+//! tells device types apart, the way fingerbank does. [`FingerprintDb`] maps
+//! fingerprints to a device & its category (ex. `printer`, `camera`, `phone`).
+//!
+//! A database has one entry per line, `#` starts a comment:
+//!
+//! ```text
+//! # parameter request list | vendor class | category | device
+//! 1,3,6,15,31,33,43,44,46,47,119,121,249,252 | MSFT 5.0 | computer | Windows
+//! * | Polycom | phone | Polycom phone
+//! ```
+//!
+//! The parameter request list is the option codes in the order the client sent
+//! them, `*` matches any list. The vendor class is matched as a substring & can
+//! be empty to match any client. When several entries match, one for the exact
+//! list wins over `*`, then one with a vendor class over one without, then the
+//! first one listed.
+use std::str;
+
+use dhcproto::v4;
+use thiserror::Error;
+
+/// the database built into dora, a few common devices. Load a fuller one for
+/// anything but a small network
+pub const BUILTIN: &str = include_str!("fingerprints.txt");
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum FingerprintErr {
+    #[error("fingerprint db line {0}: expected `list | vendor class | category | device`")]
+    Fields(usize),
+    #[error("fingerprint db line {0}: bad parameter request list {1:?}")]
+    List(usize, String),
+    #[error("fingerprint db line {0}: category is empty")]
+    Category(usize),
+}
+
+/// what a client sent that identifies its DHCP client software
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// parameter request list (option 55) codes, comma separated, ex. `1,3,6,15`
+    pub prl: String,
+    /// vendor class identifier (option 60)
+    pub vendor_class: Option<String>,
+}
+
+impl Fingerprint {
+    /// the fingerprint of `msg`, `None` if it has no parameter request list
+    pub fn new(msg: &v4::Message) -> Option<Self> {
+        let prl = match msg.opts().get(v4::OptionCode::ParameterRequestList)? {
+            v4::DhcpOption::ParameterRequestList(codes) => codes
+                .iter()
+                .map(|code| u8::from(*code).to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => return None,
+        };
+        let vendor_class = match msg.opts().get(v4::OptionCode::ClassIdentifier) {
+            Some(v4::DhcpOption::ClassIdentifier(id)) => {
+                Some(String::from_utf8_lossy(id).into_owned())
+            }
+            _ => None,
+        };
+        Some(Self { prl, vendor_class })
+    }
+}
+
+/// a known kind of device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Device {
+    /// lowercase, ex. `printer`
+    pub category: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    /// `None` matches any list
+    prl: Option<String>,
+    vendor_class: Option<String>,
+    device: Device,
+}
+
+/// devices by fingerprint
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FingerprintDb {
+    entries: Vec<Entry>,
+}
+
+impl FingerprintDb {
+    /// the built-in database
+    pub fn builtin() -> Self {
+        Self::parse(BUILTIN).expect("built-in fingerprint db is valid")
+    }
+
+    /// parse a database, see the module docs for its format
+    pub fn parse(db: &str) -> Result<Self, FingerprintErr> {
+        let mut entries = Vec::new();
+        for (i, line) in db.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields = line.split('|').map(str::trim).collect::<Vec<_>>();
+            let [prl, vendor_class, category, name] = fields[..] else {
+                return Err(FingerprintErr::Fields(i + 1));
+            };
+            let prl = match prl {
+                "*" => None,
+                _ if prl.split(',').all(|code| code.trim().parse::<u8>().is_ok()) => {
+                    Some(prl.split(',').map(str::trim).collect::<Vec<_>>().join(","))
+                }
+                _ => return Err(FingerprintErr::List(i + 1, prl.to_owned())),
+            };
+            if category.is_empty() {
+                return Err(FingerprintErr::Category(i + 1));
+            }
+            entries.push(Entry {
+                prl,
+                vendor_class: Some(vendor_class)
+                    .filter(|class| !class.is_empty())
+                    .map(str::to_owned),
+                device: Device {
+                    category: category.to_lowercase(),
+                    name: name.to_owned(),
+                },
+            });
+        }
+        Ok(Self { entries })
+    }
+
+    /// add the entries of `other`, entries already in the database are
+    /// listed first
+    pub fn extend(&mut self, other: FingerprintDb) {
+        self.entries.extend(other.entries);
+    }
+
+    /// number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// is the database empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// the device `fingerprint` belongs to
+    pub fn lookup(&self, fingerprint: &Fingerprint) -> Option<&Device> {
+        self.entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .prl
+                    .as_ref()
+                    .map_or(true, |prl| *prl == fingerprint.prl)
+                    && entry.vendor_class.as_ref().map_or(true, |class| {
+                        fingerprint
+                            .vendor_class
+                            .as_ref()
+                            .map_or(false, |vendor| vendor.contains(class.as_str()))
+                    })
+            })
+            // max_by_key returns the last max, keep the first listed instead
+            .rev()
+            .max_by_key(|entry| (entry.prl.is_some(), entry.vendor_class.is_some()))
+            .map(|entry| &entry.device)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(prl: &str, vendor_class: Option<&str>) -> Fingerprint {
+        Fingerprint {
+            prl: prl.to_owned(),
+            vendor_class: vendor_class.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let db = FingerprintDb::parse(
+            "# comment\n\
+             1, 3, 6 | | computer | Linux\n\
+             1,3,6 | Acme | Printer | Acme printer # trailing comment\n\
+             * | Acme | camera | Acme camera\n",
+        )
+        .unwrap();
+        assert_eq!(db.len(), 3);
+        let name = |fp: &Fingerprint| db.lookup(fp).map(|device| device.name.as_str());
+        assert_eq!(name(&fingerprint("1,3,6", None)), Some("Linux"));
+        let printer = db.lookup(&fingerprint("1,3,6", Some("Acme Corp"))).unwrap();
+        assert_eq!(printer.category, "printer");
+        assert_eq!(name(&fingerprint("1,3", Some("Acme"))), Some("Acme camera"));
+        assert_eq!(name(&fingerprint("1,3", None)), None);
+
+        assert_eq!(
+            FingerprintDb::parse("1,3 | | computer"),
+            Err(FingerprintErr::Fields(1))
+        );
+        assert_eq!(
+            FingerprintDb::parse("1,300 | | computer | x"),
+            Err(FingerprintErr::List(1, "1,300".to_owned()))
+        );
+        assert!(!FingerprintDb::builtin().is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut msg = v4::Message::default();
+        assert_eq!(Fingerprint::new(&msg), None);
+        msg.opts_mut()
+            .insert(v4::DhcpOption::ParameterRequestList(vec![
+                v4::OptionCode::SubnetMask,
+                v4::OptionCode::Router,
+                v4::OptionCode::DomainNameServer,
+            ]));
+        msg.opts_mut()
+            .insert(v4::DhcpOption::ClassIdentifier(b"MSFT 5.0".to_vec()));
+        assert_eq!(
+            Fingerprint::new(&msg),
+            Some(fingerprint("1,3,6", Some("MSFT 5.0")))
+        );
+    }
+}
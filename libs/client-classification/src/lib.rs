@@ -7,10 +7,20 @@ use dhcproto::{v4, Decoder};
 use thiserror::Error;
 
 pub mod ast;
+pub mod fingerprint;
+pub mod relay;
 pub use ast::{Expr, ParseErr, ParseResult};
+pub use fingerprint::{Fingerprint, FingerprintDb};
 
 pub type EvalResult<T> = Result<T, EvalErr>;
 
+/// V-I Vendor-Specific Information option code
+const VENDOR_OPTS: u8 = 125;
+/// User Class option code
+const USER_CLASS: u8 = 77;
+/// Client Machine Identifier option code, RFC 4578
+const CLIENT_UUID: u8 = 97;
+
 #[derive(Error, Debug)]
 pub enum EvalErr {
     #[error("expected bool: got {0}")]
@@ -83,6 +93,62 @@ fn parse_sub_opts(buf: &[u8], sub_code: u8) -> Result<Option<Vec<u8>>, EvalErr>
     Ok(None)
 }
 
+/// get the sub-option data for `enterprise` from V-I vendor-specific information
+/// (option 125), or the vendor-specific sub-option of option 82 which uses the
+/// same encoding. Multiple entries for the same enterprise are concatenated
+fn parse_vendor_opts(buf: &[u8], enterprise: u32) -> Result<Option<Vec<u8>>, EvalErr> {
+    let mut d = Decoder::new(buf);
+    let mut found: Option<Vec<u8>> = None;
+    while let Ok(num) = d.read_u32() {
+        let len = d.read_u8()?;
+        let slice = d.read_slice(len as usize)?;
+        if num == enterprise {
+            found.get_or_insert_with(Vec::new).extend_from_slice(slice);
+        }
+    }
+    Ok(found)
+}
+
+/// split User Class (option 77) data into its instances. RFC 3004 encodes each
+/// instance as `[len][data]`, some clients send a single class without a length
+/// prefix, in which case the whole buffer is returned as one instance
+pub fn parse_user_classes(buf: &[u8]) -> Vec<&[u8]> {
+    let mut classes = Vec::new();
+    let mut rest = buf;
+    while let Some((&len, tail)) = rest.split_first() {
+        match tail.get(..len as usize) {
+            Some(class) if len != 0 => {
+                classes.push(class);
+                rest = &tail[len as usize..];
+            }
+            // lengths don't add up, not RFC 3004 encoded
+            _ => return vec![buf],
+        }
+    }
+    classes
+}
+
+/// the machine uuid in Client Machine Identifier (option 97) data, a type of 0
+/// followed by 16 bytes. `None` for any other type or length
+pub fn client_uuid(data: &[u8]) -> Option<String> {
+    match data {
+        [0, uuid @ ..] => Some(format_uuid(uuid.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// `uuid` as lowercase hex in 8-4-4-4-12 groups
+pub fn format_uuid(uuid: &[u8; 16]) -> String {
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex::encode(&uuid[..4]),
+        hex::encode(&uuid[4..6]),
+        hex::encode(&uuid[6..8]),
+        hex::encode(&uuid[8..10]),
+        hex::encode(&uuid[10..])
+    )
+}
+
 /// get all the `member` classes used in the expression
 pub fn get_class_dependencies(expr: &Expr) -> Vec<String> {
     use Expr::*;
@@ -122,6 +188,8 @@ pub struct Args<'a> {
     pub opts: HashMap<v4::OptionCode, v4::UnknownOption>,
     pub msg: &'a v4::Message,
     pub deps: HashSet<String>,
+    /// looked up by `pkt4.category`, which is empty without one
+    pub fingerprints: Option<&'a FingerprintDb>,
 }
 
 /// evaluate the AST, using values from this DHCP message
@@ -141,6 +209,25 @@ pub fn eval(expr: &Expr, args: &Args) -> Result<Val, EvalErr> {
             Some(v) => Val::Bytes(v?),
             None => Val::Empty,
         },
+        RelayVendor(n) => match args
+            .opts
+            .get(&v4::OptionCode::RelayAgentInformation)
+            .and_then(|info| parse_sub_opts(info.data(), relay::VENDOR_SPECIFIC).transpose())
+        {
+            Some(v) => match parse_vendor_opts(&v?, *n)? {
+                Some(v) => Val::Bytes(v),
+                None => Val::Empty,
+            },
+            None => Val::Empty,
+        },
+        Vendor(n) => match args
+            .opts
+            .get(&VENDOR_OPTS.into())
+            .and_then(|info| parse_vendor_opts(info.data(), *n).transpose())
+        {
+            Some(v) => Val::Bytes(v?),
+            None => Val::Empty,
+        },
         Option(o) => match args.opts.get(&(*o).into()) {
             Some(v) => Val::Bytes(v.data().to_owned()),
             None => Val::Empty,
@@ -158,6 +245,26 @@ pub fn eval(expr: &Expr, args: &Args) -> Result<Val, EvalErr> {
             None => Val::Empty,
         },
         TransId() => Val::Int(args.msg.xid()),
+        Uuid() => match args
+            .opts
+            .get(&CLIENT_UUID.into())
+            .and_then(|opt| client_uuid(opt.data()))
+        {
+            Some(uuid) => Val::String(uuid),
+            None => Val::Empty,
+        },
+        Fingerprint() => match fingerprint::Fingerprint::new(args.msg) {
+            Some(fingerprint) => Val::String(fingerprint.prl),
+            None => Val::Empty,
+        },
+        Category() => match args
+            .fingerprints
+            .zip(fingerprint::Fingerprint::new(args.msg))
+            .and_then(|(db, fingerprint)| db.lookup(&fingerprint))
+        {
+            Some(device) => Val::String(device.category.clone()),
+            None => Val::Empty,
+        },
         Ip(ip) => Val::Int(u32::from_be_bytes(ip.octets())),
         // prefix
         Not(rhs) => Val::Bool(!is_bool(eval(rhs, args)?)?),
@@ -209,6 +316,16 @@ pub fn eval(expr: &Expr, args: &Args) -> Result<Val, EvalErr> {
             (a, _b) => return Err(EvalErr::ExpectedString(a)),
         },
         Member(s) => Val::Bool(args.deps.contains(s)),
+        UserClass(class) => Val::Bool(
+            args.opts
+                .get(&USER_CLASS.into())
+                .map(|opt| {
+                    parse_user_classes(opt.data())
+                        .into_iter()
+                        .any(|c| c == class.as_bytes())
+                })
+                .unwrap_or(false),
+        ),
     })
 }
 
@@ -284,6 +401,7 @@ mod tests {
             opts: HashMap::new(),
             msg: &v4::Message::default(),
             deps: HashSet::new(),
+            fingerprints: None,
         };
 
         let val = eval(&dbg!(build_ast(tokens).unwrap()), &args).unwrap();
@@ -297,6 +415,7 @@ mod tests {
             opts: HashMap::new(),
             msg: &v4::Message::default(),
             deps: HashSet::new(),
+            fingerprints: None,
         };
         let val = eval(&build_ast(tokens).unwrap(), &args).unwrap();
         assert_eq!(val, Val::Bool(true));
@@ -319,6 +438,7 @@ mod tests {
             opts,
             msg: &v4::Message::default(),
             deps: HashSet::new(),
+            fingerprints: None,
         };
         let val = eval(&tokens, &args).unwrap();
         assert_eq!(val, Val::Bool(true));
@@ -332,7 +452,7 @@ mod tests {
         let sub_opt = "foo".as_bytes();
         data.push(sub_opt.len() as u8);
         data.extend(sub_opt);
-        data.extend(&[
+        data.extend([
             23, 3, 1, 2, 3, // two
             45, 0, // three
             123, 10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10,
@@ -347,6 +467,7 @@ mod tests {
             opts,
             msg: &v4::Message::default(),
             deps: HashSet::new(),
+            fingerprints: None,
         };
 
         let expr = ast::parse("relay4[12].exists").unwrap();
@@ -358,6 +479,144 @@ mod tests {
         assert_eq!(val, Val::Bool(true));
     }
 
+    #[test]
+    fn test_relay_vendor_opts() {
+        let mut opts = HashMap::new();
+        // subscriber id, then vendor-specific for enterprise 3561 with sub-opt 1
+        let mut data: Vec<u8> = vec![6, 3, b'a', b'b', b'c', 9, 10];
+        data.extend(3561_u32.to_be_bytes());
+        data.extend([5, 1, 3, b'f', b'o', b'o']);
+
+        opts.insert(
+            v4::OptionCode::RelayAgentInformation,
+            UnknownOption::new(v4::OptionCode::RelayAgentInformation, data),
+        );
+        let args = Args {
+            chaddr: "001122334455".to_owned(),
+            opts,
+            msg: &v4::Message::default(),
+            deps: HashSet::new(),
+            fingerprints: None,
+        };
+
+        let expr = ast::parse("relay4[6].hex == 'abc'").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+
+        let expr =
+            ast::parse("relay4_vendor[3561].exists and not relay4_vendor[9].exists").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+
+        let expr = ast::parse("relay4_vendor[3561].option[1].hex == 'foo'").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+    }
+
+    #[test]
+    fn test_vendor_opts() {
+        let mut opts = HashMap::new();
+        let mut data: Vec<u8> = Vec::new();
+        // enterprise 4491, sub-opts 1 & 2
+        data.extend(4491_u32.to_be_bytes());
+        data.extend([8, 1, 3, b'f', b'o', b'o', 2, 1, 9]);
+        // enterprise 9, sub-opt 1
+        data.extend(9_u32.to_be_bytes());
+        data.extend([3, 1, 1, 7]);
+
+        opts.insert(125.into(), UnknownOption::new(125.into(), data));
+        let args = Args {
+            chaddr: "001122334455".to_owned(),
+            opts,
+            msg: &v4::Message::default(),
+            deps: HashSet::new(),
+            fingerprints: None,
+        };
+
+        let expr = ast::parse("vendor[4491].exists and not vendor[1234].exists").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+
+        let expr = ast::parse("vendor[4491].option[1].hex == 'foo'").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+
+        let expr = ast::parse("vendor[4491].option[3].exists").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(false));
+    }
+
+    #[test]
+    fn test_user_class() {
+        assert_eq!(
+            parse_user_classes(&[3, b'f', b'o', b'o', 2, b'a', b'b']),
+            vec![&b"foo"[..], &b"ab"[..]]
+        );
+        // not length prefixed
+        assert_eq!(parse_user_classes(b"MSFT 5.0"), vec![&b"MSFT 5.0"[..]]);
+
+        let mut opts = HashMap::new();
+        opts.insert(
+            77.into(),
+            UnknownOption::new(77.into(), vec![3, b'f', b'o', b'o', 3, b'b', b'a', b'r']),
+        );
+        let args = Args {
+            chaddr: "001122334455".to_owned(),
+            opts,
+            msg: &v4::Message::default(),
+            deps: HashSet::new(),
+            fingerprints: None,
+        };
+        let expr = ast::parse("user_class('bar') and not user_class('baz')").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+    }
+
+    #[test]
+    fn test_uuid() {
+        let mut data = vec![0];
+        data.extend(hex::decode("4c4c454400423510805ab4c04f4e3032").unwrap());
+        assert_eq!(
+            client_uuid(&data).as_deref(),
+            Some("4c4c4544-0042-3510-805a-b4c04f4e3032")
+        );
+        assert_eq!(client_uuid(&data[..16]), None);
+        assert_eq!(client_uuid(&[1; 17]), None);
+
+        let mut opts = HashMap::new();
+        opts.insert(97.into(), UnknownOption::new(97.into(), data));
+        let args = Args {
+            chaddr: "001122334455".to_owned(),
+            opts,
+            msg: &v4::Message::default(),
+            deps: HashSet::new(),
+            fingerprints: None,
+        };
+        let expr = ast::parse("pkt4.uuid == '4C4C4544-0042-3510-805A-B4C04F4E3032'").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+        let expr = ast::parse("pkt4.uuid.exists").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut msg = v4::Message::default();
+        msg.opts_mut()
+            .insert(v4::DhcpOption::ParameterRequestList(vec![
+                v4::OptionCode::SubnetMask,
+                v4::OptionCode::Router,
+            ]));
+        msg.opts_mut()
+            .insert(v4::DhcpOption::ClassIdentifier(b"Acme".to_vec()));
+        let db = FingerprintDb::parse("* | Acme | Printer | Acme printer").unwrap();
+        let mut args = Args {
+            chaddr: "001122334455".to_owned(),
+            opts: HashMap::new(),
+            msg: &msg,
+            deps: HashSet::new(),
+            fingerprints: None,
+        };
+        let expr = ast::parse("pkt4.fingerprint == '1,3'").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+        let expr = ast::parse("pkt4.category == 'printer'").unwrap();
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(false));
+        args.fingerprints = Some(&db);
+        assert_eq!(eval(&expr, &args).unwrap(), Val::Bool(true));
+    }
+
     #[test]
     fn test_sub_opts_postfix() {
         let mut opts = HashMap::new();
@@ -366,7 +625,7 @@ mod tests {
         let sub_opt = "foo".as_bytes();
         data.push(sub_opt.len() as u8);
         data.extend(sub_opt);
-        data.extend(&[
+        data.extend([
             23, 3, 1, 2, 3, // two
             45, 0, // three
             123, 10, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10,
@@ -381,6 +640,7 @@ mod tests {
             opts,
             msg: &v4::Message::default(),
             deps: HashSet::new(),
+            fingerprints: None,
         };
         // test that we can address sub options through the sub-opt postfix
         let expr = ast::parse("option[82].option[12] == 'foo'").unwrap();
@@ -450,6 +710,7 @@ mod tests {
             opts: options,
             msg: &msg,
             deps: HashSet::new(),
+            fingerprints: None,
         };
 
         let expr = ast::parse("pkt4.hlen == 6").unwrap();
@@ -510,6 +771,7 @@ mod tests {
                 .into_iter()
                 .map(|s| s.to_owned())
                 .collect(),
+            fingerprints: None,
         };
         let val = eval(&expr, &args).unwrap();
         assert_eq!(val, Val::Bool(true));
@@ -523,6 +785,7 @@ mod tests {
                 .into_iter()
                 .map(|s| s.to_owned())
                 .collect(),
+            fingerprints: None,
         };
         let val = eval(&expr, &args).unwrap();
         assert_eq!(val, Val::Bool(true));
@@ -536,6 +799,7 @@ mod tests {
                 .into_iter()
                 .map(|s| s.to_owned())
                 .collect(),
+            fingerprints: None,
         };
         let val = eval(&expr, &args).unwrap();
         assert_eq!(val, Val::Bool(false));
@@ -563,6 +827,7 @@ mod tests {
             opts: HashMap::new(),
             msg: &v4::Message::default(),
             deps: HashSet::new(),
+            fingerprints: None,
         };
 
         let expr = ast::parse("concat('foo', 'bar')").unwrap();
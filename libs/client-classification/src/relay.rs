@@ -0,0 +1,159 @@
+//! decoding of relay agent information (option 82) sub-options
+//!
+//! <https://www.iana.org/assignments/bootp-dhcp-parameters/bootp-dhcp-parameters.xhtml#relay-agent-sub-options>
+use dhcproto::Decoder;
+
+use crate::EvalErr;
+
+/// agent circuit id, RFC 3046
+pub const CIRCUIT_ID: u8 = 1;
+/// agent remote id, RFC 3046
+pub const REMOTE_ID: u8 = 2;
+/// DOCSIS device class, RFC 3256
+pub const DOCSIS_DEVICE_CLASS: u8 = 4;
+/// link selection, RFC 3527
+pub const LINK_SELECTION: u8 = 5;
+/// subscriber id, RFC 3993
+pub const SUBSCRIBER_ID: u8 = 6;
+/// RADIUS attributes, RFC 4014
+pub const RADIUS_ATTRIBUTES: u8 = 7;
+/// authentication, RFC 4030
+pub const AUTHENTICATION: u8 = 8;
+/// vendor-specific information, RFC 4243. Keyed by enterprise number like
+/// option 125
+pub const VENDOR_SPECIFIC: u8 = 9;
+/// relay agent flags, RFC 5010
+pub const FLAGS: u8 = 10;
+/// server identifier override, RFC 5107
+pub const SERVER_ID_OVERRIDE: u8 = 11;
+/// relay agent identifier, RFC 6925
+pub const RELAY_ID: u8 = 12;
+/// access technology type, RFC 7839
+pub const ACCESS_TECHNOLOGY_TYPE: u8 = 13;
+/// access network name, RFC 7839
+pub const ACCESS_NETWORK_NAME: u8 = 14;
+/// access point name, RFC 7839
+pub const ACCESS_POINT_NAME: u8 = 15;
+/// access point BSSID, RFC 7839
+pub const ACCESS_POINT_BSSID: u8 = 16;
+/// operator id, RFC 7839
+pub const OPERATOR_ID: u8 = 17;
+/// operator realm, RFC 7839
+pub const OPERATOR_REALM: u8 = 18;
+/// relay source port, RFC 8357
+pub const RELAY_SOURCE_PORT: u8 = 19;
+/// virtual subnet selection, RFC 6607
+pub const VIRTUAL_SUBNET_SELECTION: u8 = 151;
+/// virtual subnet selection control, RFC 6607
+pub const VIRTUAL_SUBNET_SELECTION_CONTROL: u8 = 152;
+
+/// name of a standard relay agent sub-option
+pub fn sub_opt_name(code: u8) -> Option<&'static str> {
+    Some(match code {
+        CIRCUIT_ID => "circuit_id",
+        REMOTE_ID => "remote_id",
+        DOCSIS_DEVICE_CLASS => "docsis_device_class",
+        LINK_SELECTION => "link_selection",
+        SUBSCRIBER_ID => "subscriber_id",
+        RADIUS_ATTRIBUTES => "radius_attributes",
+        AUTHENTICATION => "authentication",
+        VENDOR_SPECIFIC => "vendor_specific",
+        FLAGS => "flags",
+        SERVER_ID_OVERRIDE => "server_id_override",
+        RELAY_ID => "relay_id",
+        ACCESS_TECHNOLOGY_TYPE => "access_technology_type",
+        ACCESS_NETWORK_NAME => "access_network_name",
+        ACCESS_POINT_NAME => "access_point_name",
+        ACCESS_POINT_BSSID => "access_point_bssid",
+        OPERATOR_ID => "operator_id",
+        OPERATOR_REALM => "operator_realm",
+        RELAY_SOURCE_PORT => "relay_source_port",
+        VIRTUAL_SUBNET_SELECTION => "virtual_subnet_selection",
+        VIRTUAL_SUBNET_SELECTION_CONTROL => "virtual_subnet_selection_control",
+        _ => return None,
+    })
+}
+
+/// split relay agent information (option 82) data into its sub-options, in
+/// the order they were sent
+pub fn sub_opts(buf: &[u8]) -> Result<Vec<(u8, &[u8])>, EvalErr> {
+    let mut d = Decoder::new(buf);
+    let mut opts = Vec::new();
+    while let Ok(code) = d.read_u8() {
+        let len = d.read_u8()?;
+        opts.push((code, d.read_slice(len as usize)?));
+    }
+    Ok(opts)
+}
+
+/// split vendor-specific sub-option (9) data into its `(enterprise, data)`
+/// entries. Multiple entries for the same enterprise are concatenated
+pub fn vendor_opts(buf: &[u8]) -> Result<Vec<(u32, Vec<u8>)>, EvalErr> {
+    let mut d = Decoder::new(buf);
+    let mut opts: Vec<(u32, Vec<u8>)> = Vec::new();
+    while let Ok(num) = d.read_u32() {
+        let len = d.read_u8()?;
+        let slice = d.read_slice(len as usize)?;
+        match opts.iter_mut().find(|(n, _)| *n == num) {
+            Some((_, data)) => data.extend_from_slice(slice),
+            None => opts.push((num, slice.to_vec())),
+        }
+    }
+    Ok(opts)
+}
+
+/// decode option 82 data to `(name, data)` pairs. Sub-options without a
+/// standard name are named by their code, vendor-specific entries are split
+/// out as `vendor_specific.<enterprise>`
+pub fn decode(buf: &[u8]) -> Result<Vec<(String, Vec<u8>)>, EvalErr> {
+    let mut decoded = Vec::new();
+    for (code, data) in sub_opts(buf)? {
+        if code == VENDOR_SPECIFIC {
+            for (num, data) in vendor_opts(data)? {
+                decoded.push((format!("vendor_specific.{num}"), data));
+            }
+            continue;
+        }
+        let name = match sub_opt_name(code) {
+            Some(name) => name.to_owned(),
+            None => code.to_string(),
+        };
+        decoded.push((name, data.to_vec()));
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let mut data = vec![CIRCUIT_ID, 4, b'e', b't', b'h', b'0'];
+        data.extend([SUBSCRIBER_ID, 3, b'a', b'b', b'c']);
+        // enterprise 3561 twice, enterprise 9 once
+        data.extend([VENDOR_SPECIFIC, 19]);
+        data.extend(3561_u32.to_be_bytes());
+        data.extend([2, 1, 2]);
+        data.extend(9_u32.to_be_bytes());
+        data.extend([1, 7]);
+        data.extend(3561_u32.to_be_bytes());
+        data.extend([1, 3]);
+        data.extend([VIRTUAL_SUBNET_SELECTION, 2, 255, 0]);
+        data.extend([200, 1, 1]);
+
+        assert_eq!(
+            decode(&data).unwrap(),
+            vec![
+                ("circuit_id".to_owned(), b"eth0".to_vec()),
+                ("subscriber_id".to_owned(), b"abc".to_vec()),
+                ("vendor_specific.3561".to_owned(), vec![1, 2, 3]),
+                ("vendor_specific.9".to_owned(), vec![7]),
+                ("virtual_subnet_selection".to_owned(), vec![255, 0]),
+                ("200".to_owned(), vec![1]),
+            ]
+        );
+        // truncated sub-option
+        assert!(decode(&[CIRCUIT_ID, 4, b'e']).is_err());
+    }
+}
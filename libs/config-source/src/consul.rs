@@ -0,0 +1,74 @@
+//! a Consul KV key, watched with blocking queries
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use reqwest::{header, Client, Response, StatusCode};
+
+use crate::Source;
+
+/// longest a blocking query waits for the key to change
+const WAIT: &str = "5m";
+/// header holding the raft index of the key
+const INDEX: &str = "X-Consul-Index";
+
+#[derive(Debug)]
+pub(crate) struct Consul {
+    client: Client,
+    url: String,
+    /// `X-Consul-Index` of the last read, 0 before the first
+    index: AtomicU64,
+}
+
+impl Consul {
+    pub(crate) fn new(base: &str, key: &str, token: Option<&str>) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        if let Some(token) = token {
+            let mut value = header::HeaderValue::from_str(token).context("invalid consul token")?;
+            value.set_sensitive(true);
+            headers.insert("X-Consul-Token", value);
+        }
+        Ok(Self {
+            client: Client::builder().default_headers(headers).build()?,
+            url: format!("{base}/v1/kv/{key}"),
+            index: AtomicU64::new(0),
+        })
+    }
+
+    /// the value of the key in `resp`, storing its index
+    async fn value(&self, resp: Response) -> Result<String> {
+        if resp.status() == StatusCode::NOT_FOUND {
+            bail!("{} doesn't exist", self.url);
+        }
+        let resp = resp.error_for_status()?;
+        let index = resp
+            .headers()
+            .get(INDEX)
+            .and_then(|index| index.to_str().ok()?.parse::<u64>().ok())
+            .unwrap_or(0);
+        // an index that goes backwards must be reset, a blocking query with it
+        // would return straight away
+        let last = self.index.load(Ordering::Relaxed);
+        self.index
+            .store(if index < last { 0 } else { index }, Ordering::Relaxed);
+        Ok(resp.text().await?)
+    }
+}
+
+#[async_trait]
+impl Source for Consul {
+    async fn fetch(&self) -> Result<String> {
+        let resp = self.client.get(format!("{}?raw", self.url)).send().await?;
+        self.value(resp).await
+    }
+
+    async fn next(&self) -> Result<String> {
+        let index = self.index.load(Ordering::Relaxed);
+        let resp = self
+            .client
+            .get(format!("{}?raw&index={index}&wait={WAIT}", self.url))
+            .send()
+            .await?;
+        self.value(resp).await
+    }
+}
@@ -0,0 +1,110 @@
+//! an etcd key, read & watched through the v3 JSON gateway
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{header, Client};
+use serde_json::{json, Value};
+
+use crate::Source;
+
+#[derive(Debug)]
+pub(crate) struct Etcd {
+    client: Client,
+    base: String,
+    /// base64 of the key, as the gateway takes it
+    key: String,
+    /// `mod_revision` of the last read
+    revision: AtomicI64,
+}
+
+impl Etcd {
+    pub(crate) fn new(base: &str, key: &str, token: Option<&str>) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        if let Some(token) = token {
+            let mut value = header::HeaderValue::from_str(token).context("invalid etcd token")?;
+            value.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, value);
+        }
+        Ok(Self {
+            client: Client::builder().default_headers(headers).build()?,
+            base: base.to_owned(),
+            key: STANDARD.encode(key),
+            revision: AtomicI64::new(0),
+        })
+    }
+
+    /// the value of `kv`, storing its revision
+    fn value(&self, kv: &Value) -> Result<String> {
+        if let Some(revision) = kv.get("mod_revision").and_then(int) {
+            self.revision.store(revision, Ordering::Relaxed);
+        }
+        let value = kv.get("value").and_then(Value::as_str).unwrap_or_default();
+        Ok(String::from_utf8(STANDARD.decode(value)?)?)
+    }
+}
+
+/// an int64, which the gateway encodes as a string
+fn int(value: &Value) -> Option<i64> {
+    value
+        .as_str()
+        .and_then(|value| value.parse().ok())
+        .or_else(|| value.as_i64())
+}
+
+#[async_trait]
+impl Source for Etcd {
+    async fn fetch(&self) -> Result<String> {
+        let resp = self
+            .client
+            .post(format!("{}/v3/kv/range", self.base))
+            .json(&json!({ "key": self.key }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        let kv = resp
+            .get("kvs")
+            .and_then(|kvs| kvs.get(0))
+            .context("config key doesn't exist")?;
+        self.value(kv)
+    }
+
+    async fn next(&self) -> Result<String> {
+        let start = self.revision.load(Ordering::Relaxed) + 1;
+        let mut resp = self
+            .client
+            .post(format!("{}/v3/watch", self.base))
+            .json(&json!({ "create_request": { "key": self.key, "start_revision": start } }))
+            .send()
+            .await?
+            .error_for_status()?;
+        // the gateway streams a JSON object per line, the first confirms the
+        // watch was created
+        let mut buf = Vec::new();
+        while let Some(chunk) = resp.chunk().await? {
+            buf.extend_from_slice(&chunk);
+            while let Some(end) = buf.iter().position(|&b| b == b'\n') {
+                let line = buf.drain(..=end).collect::<Vec<_>>();
+                let msg = serde_json::from_slice::<Value>(&line)?;
+                let Some(result) = msg.get("result") else {
+                    bail!("etcd watch failed: {msg}");
+                };
+                // the revisions since the last read were compacted away
+                if result.get("compact_revision").and_then(int).unwrap_or(0) > 0 {
+                    return self.fetch().await;
+                }
+                let events = result.get("events").and_then(Value::as_array);
+                if let Some(event) = events.and_then(|events| events.last()) {
+                    if event.get("type").and_then(Value::as_str) == Some("DELETE") {
+                        bail!("config key was deleted");
+                    }
+                    return self.value(event.get("kv").context("watch event has no kv")?);
+                }
+            }
+        }
+        bail!("etcd closed the watch")
+    }
+}
@@ -0,0 +1,67 @@
+//! a config served over HTTP, polled with `If-None-Match`
+use std::{sync::Mutex, time::Duration};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{header, Client, StatusCode};
+
+use crate::Source;
+
+#[derive(Debug)]
+pub(crate) struct Http {
+    client: Client,
+    url: String,
+    poll: Duration,
+    /// the `ETag` & body of the last response
+    last: Mutex<Option<(header::HeaderValue, String)>>,
+}
+
+impl Http {
+    pub(crate) fn new(url: &str, token: Option<&str>, poll: Duration) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        if let Some(token) = token {
+            let mut value = header::HeaderValue::from_str(&format!("Bearer {token}"))
+                .context("invalid config token")?;
+            value.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, value);
+        }
+        Ok(Self {
+            client: Client::builder().default_headers(headers).build()?,
+            url: url.to_owned(),
+            poll,
+            last: Mutex::new(None),
+        })
+    }
+}
+
+#[async_trait]
+impl Source for Http {
+    async fn fetch(&self) -> Result<String> {
+        let etag = self
+            .last
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|(etag, _)| etag.clone());
+        let mut req = self.client.get(&self.url);
+        if let Some(etag) = etag {
+            req = req.header(header::IF_NONE_MATCH, etag);
+        }
+        let resp = req.send().await?;
+        if resp.status() == StatusCode::NOT_MODIFIED {
+            if let Some((_, body)) = &*self.last.lock().unwrap() {
+                return Ok(body.clone());
+            }
+        }
+        let resp = resp.error_for_status()?;
+        let etag = resp.headers().get(header::ETAG).cloned();
+        let body = resp.text().await?;
+        *self.last.lock().unwrap() = etag.map(|etag| (etag, body.clone()));
+        Ok(body)
+    }
+
+    async fn next(&self) -> Result<String> {
+        tokio::time::sleep(self.poll).await;
+        self.fetch().await
+    }
+}
@@ -0,0 +1,158 @@
+//! # config-source
+//!
+//! Fetch dora's config from somewhere other than a file, for fleets where
+//! baking a file into every image is awkward. Sources are enabled with cargo
+//! features: `http`, `etcd` and `consul`. The source is chosen from the url,
+//! a plain `https://cfg.example.com/dora.yaml` is fetched with a GET, while
+//! `etcd+http://etcd:2379/dora/config` & `consul+http://consul:8500/dora/config`
+//! read the key `dora/config` from the etcd v3 JSON gateway or the Consul KV
+//! store.
+//!
+//! [`changed`] waits for the source to hold a different, valid config. etcd
+//! keys are watched & Consul keys read with blocking queries, so changes are
+//! seen as soon as they're made. HTTP urls are polled, with `If-None-Match`
+//! when the server sends an `ETag`.
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    unreachable_pub,
+    non_snake_case,
+    non_upper_case_globals
+)]
+#![allow(clippy::cognitive_complexity)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use config::DhcpConfig;
+use tracing::{error, info};
+
+#[cfg(feature = "consul")]
+mod consul;
+#[cfg(feature = "etcd")]
+mod etcd;
+#[cfg(feature = "http")]
+mod http;
+
+/// wait after a failed fetch before trying again
+pub const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// somewhere a config is kept
+#[async_trait]
+pub trait Source: Send + Sync + std::fmt::Debug {
+    /// the config the source holds now
+    async fn fetch(&self) -> Result<String>;
+    /// wait for the next version of the config & return it. It may be the same
+    /// as the last one, ex. when a poll interval or blocking query runs out
+    async fn next(&self) -> Result<String>;
+}
+
+/// create a client for the config at `url`, authenticating with `token`. HTTP
+/// urls are fetched every `poll`
+pub fn connect(url: &str, token: Option<&str>, poll: Duration) -> Result<Arc<dyn Source>> {
+    // only read by the sources that were built
+    let _ = (token, poll);
+    match url.split_once('+') {
+        #[cfg(feature = "etcd")]
+        Some(("etcd", base)) => {
+            let (base, key) = split_key(base)?;
+            Ok(Arc::new(etcd::Etcd::new(base, key, token)?))
+        }
+        #[cfg(feature = "consul")]
+        Some(("consul", base)) => {
+            let (base, key) = split_key(base)?;
+            Ok(Arc::new(consul::Consul::new(base, key, token)?))
+        }
+        #[cfg(not(feature = "etcd"))]
+        Some(("etcd", _)) => {
+            bail!("dora was built without the `config-etcd` feature, can't read {url}")
+        }
+        #[cfg(not(feature = "consul"))]
+        Some(("consul", _)) => {
+            bail!("dora was built without the `config-consul` feature, can't read {url}")
+        }
+        #[cfg(feature = "http")]
+        _ if is_http(url) => Ok(Arc::new(http::Http::new(url, token, poll)?)),
+        #[cfg(not(feature = "http"))]
+        _ if is_http(url) => {
+            bail!("dora was built without the `config-http` feature, can't fetch {url}")
+        }
+        _ => bail!("unsupported config url {url}"),
+    }
+}
+
+fn is_http(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+/// split `http://host:port/some/key` into the base url & the key
+#[cfg_attr(not(any(feature = "etcd", feature = "consul")), allow(dead_code))]
+fn split_key(url: &str) -> Result<(&str, &str)> {
+    let Some((_, rest)) = url.split_once("://") else {
+        bail!("{url} isn't an http or https url");
+    };
+    let at = url.len() - rest.len() + rest.find('/').unwrap_or(rest.len());
+    let (base, key) = (&url[..at], url[at..].trim_start_matches('/'));
+    if key.is_empty() {
+        bail!("{url} has no key, ex. {base}/dora/config");
+    }
+    Ok((base, key))
+}
+
+/// wait for `source` to hold a valid config other than `current`, the text of
+/// `running`. Fetch errors & invalid configs are logged, dora keeps running
+/// `running` until they're fixed
+pub async fn changed(source: &dyn Source, current: &str, running: &DhcpConfig) {
+    // invalid configs are only logged the first time they're seen
+    let mut invalid = None;
+    loop {
+        let text = match source.next().await {
+            Ok(text) => text,
+            Err(err) => {
+                error!(?err, ?source, "failed to fetch config");
+                tokio::time::sleep(RETRY_DELAY).await;
+                continue;
+            }
+        };
+        if text == current || invalid.as_ref() == Some(&text) {
+            continue;
+        }
+        match DhcpConfig::parse_str(&text) {
+            Ok(new) => {
+                info!(changes = %running.diff(&new), ?source, "config changed");
+                return;
+            }
+            Err(err) => {
+                error!(
+                    ?err,
+                    ?source,
+                    "fetched config is invalid, keeping the running config"
+                );
+                invalid = Some(text);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_key() {
+        assert_eq!(
+            split_key("http://etcd:2379/dora/config").unwrap(),
+            ("http://etcd:2379", "dora/config")
+        );
+        assert_eq!(
+            split_key("https://consul.example.com//dora").unwrap(),
+            ("https://consul.example.com", "dora")
+        );
+        assert!(split_key("http://etcd:2379/").is_err());
+        assert!(split_key("http://etcd:2379").is_err());
+        assert!(split_key("etcd:2379/dora").is_err());
+    }
+}
@@ -0,0 +1,438 @@
+//! build a config in code, for tools that generate configs from Rust
+//!
+//! The builders fill in the same [`wire`] types a config file is parsed into,
+//! and [`ConfigBuilder::build`] checks what the config file format can't
+//! express: ranges & reservations must be inside their network, ranges can't
+//! overlap and lease times must be ordered `min <= default <= max`.
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use config::builder::{ConfigBuilder, NetworkBuilder, RangeBuilder};
+//! use dora_core::dhcproto::v4::DhcpOption;
+//!
+//! let cfg = ConfigBuilder::new()
+//!     .option(DhcpOption::DomainNameServer(vec![[1, 1, 1, 1].into()]))
+//!     .network(
+//!         NetworkBuilder::new("192.168.0.0/24".parse()?)
+//!             .option(DhcpOption::Router(vec![[192, 168, 0, 1].into()]))
+//!             .range(RangeBuilder::new([192, 168, 0, 100], [192, 168, 0, 200], 3600)),
+//!     )
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, ensure, Context, Result};
+use dora_core::{
+    dhcproto::v4::{DhcpOption, OptionCode},
+    pnet::util::MacAddr,
+};
+use ipnet::Ipv4Net;
+
+use crate::{
+    wire::{
+        self,
+        v4::{Condition, HintPolicy, IpRange, Net, NetworkConfig, Options, ReservedIp},
+        MinMax,
+    },
+    DhcpConfig,
+};
+
+/// builds a [`DhcpConfig`]
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    cfg: wire::Config,
+    networks: Vec<NetworkBuilder>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// only serve on these interfaces, by default all interfaces with an IPv4 are used
+    pub fn interfaces<I, S>(mut self, interfaces: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cfg.interfaces = Some(interfaces.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// global option, inherited by every network
+    pub fn option(mut self, opt: DhcpOption) -> Self {
+        set_opt(self.cfg.options.get_or_insert_with(Options::default), opt);
+        self
+    }
+
+    /// only answer clients with a reservation for their chaddr
+    pub fn chaddr_only(mut self, chaddr_only: bool) -> Self {
+        self.cfg.chaddr_only = chaddr_only;
+        self
+    }
+
+    /// rewrites applied to client identifiers before they're used to find leases
+    pub fn client_id_normalize(mut self, normalize: wire::ClientIdNormalize) -> Self {
+        self.cfg.client_id_normalize = Some(normalize);
+        self
+    }
+
+    /// answer BOOTP clients
+    pub fn bootp_enable(mut self, bootp_enable: bool) -> Self {
+        self.cfg.bootp_enable = bootp_enable;
+        self
+    }
+
+    pub fn network(mut self, network: NetworkBuilder) -> Self {
+        self.networks.push(network);
+        self
+    }
+
+    /// validate & return the wire config, ex. to serialize it to a file
+    pub fn into_wire(self) -> Result<wire::Config> {
+        let mut cfg = self.cfg;
+        for network in self.networks {
+            let subnet = network.subnet;
+            let net = network
+                .into_wire()
+                .with_context(|| format!("network {subnet}"))?;
+            if cfg.networks.insert(subnet, net).is_some() {
+                bail!("network {subnet} is configured twice");
+            }
+        }
+        Ok(cfg)
+    }
+
+    /// validate & build the config
+    pub fn build(self) -> Result<DhcpConfig> {
+        DhcpConfig::from_wire(self.into_wire()?)
+    }
+}
+
+/// builds a network for [`ConfigBuilder::network`]
+#[derive(Debug, Clone)]
+pub struct NetworkBuilder {
+    subnet: Ipv4Net,
+    net: Net,
+    ranges: Vec<RangeBuilder>,
+    reservations: Vec<ReservationBuilder>,
+}
+
+impl NetworkBuilder {
+    pub fn new(subnet: Ipv4Net) -> Self {
+        Self {
+            subnet,
+            net: Net::default(),
+            ranges: Vec::new(),
+            reservations: Vec::new(),
+        }
+    }
+
+    /// server id used when talking with clients on this network
+    pub fn server_id(mut self, server_id: Ipv4Addr) -> Self {
+        self.net.server_id = Some(server_id);
+        self
+    }
+
+    /// option inherited by all ranges & reservations in this network
+    pub fn option(mut self, opt: DhcpOption) -> Self {
+        set_opt(self.net.options.get_or_insert_with(Options::default), opt);
+        self
+    }
+
+    /// don't inherit the global option `code`
+    pub fn remove(mut self, code: OptionCode) -> Self {
+        remove_opt(self.net.options.get_or_insert_with(Options::default), code);
+        self
+    }
+
+    /// send an ICMP echo request before offering an address
+    pub fn ping_check(mut self, ping_check: bool) -> Self {
+        self.net.ping_check = ping_check;
+        self
+    }
+
+    /// seconds a declined or in use address isn't handed out
+    pub fn probation_period(mut self, secs: u64) -> Self {
+        self.net.probation_period = secs;
+        self
+    }
+
+    /// NAK REQUESTs that can't be satisfied (default: true)
+    pub fn authoritative(mut self, authoritative: bool) -> Self {
+        self.net.authoritative = authoritative;
+        self
+    }
+
+    pub fn range(mut self, range: RangeBuilder) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    pub fn reservation(mut self, reservation: ReservationBuilder) -> Self {
+        self.reservations.push(reservation);
+        self
+    }
+
+    fn into_wire(self) -> Result<Net> {
+        let Self {
+            subnet,
+            mut net,
+            ranges,
+            reservations,
+        } = self;
+        for range in ranges {
+            let addrs = range.range.range.clone();
+            ensure!(
+                addrs.start() <= addrs.end(),
+                "range {addrs:?}: start is after end"
+            );
+            ensure!(
+                subnet.contains(addrs.start()) && subnet.contains(addrs.end()),
+                "range {addrs:?} is outside of the network"
+            );
+            if let Some(other) = net.ranges.iter().find(|other| {
+                other.range.start() <= addrs.end() && addrs.start() <= other.range.end()
+            }) {
+                bail!("range {addrs:?} overlaps range {:?}", other.range);
+            }
+            check_lease(&range.range.config.lease_time)
+                .with_context(|| format!("range {addrs:?}"))?;
+            net.ranges.push(range.range);
+        }
+        for res in reservations {
+            let ip = res.res.ip;
+            ensure!(
+                subnet.contains(&ip),
+                "reservation {ip} is outside of the network"
+            );
+            check_lease(&res.res.config.lease_time).with_context(|| format!("reservation {ip}"))?;
+            net.reservations.push(res.res);
+        }
+        Ok(net)
+    }
+}
+
+/// builds a range for [`NetworkBuilder::range`]
+#[derive(Debug, Clone)]
+pub struct RangeBuilder {
+    range: IpRange,
+}
+
+impl RangeBuilder {
+    /// addresses `start..=end` leased for `lease_time` seconds by default
+    pub fn new(start: impl Into<Ipv4Addr>, end: impl Into<Ipv4Addr>, lease_time: u32) -> Self {
+        Self {
+            range: IpRange {
+                range: start.into()..=end.into(),
+                options: Options::default(),
+                config: NetworkConfig {
+                    lease_time: MinMax {
+                        default: lease_time,
+                        min: None,
+                        max: None,
+                    },
+                },
+                except: Vec::new(),
+                reserve_first_n: None,
+                reserve_last_n: None,
+                class: None,
+                vendor_class: None,
+                schedule: Vec::new(),
+                ping_check: None,
+                ping_timeout_ms: None,
+                ping_retries: None,
+                honor_hints: HintPolicy::default(),
+                boot: wire::v4::Boot::default(),
+            },
+        }
+    }
+
+    /// bounds for the lease time a client can request
+    pub fn lease_bounds(mut self, min: u32, max: u32) -> Self {
+        self.range.config.lease_time.min = Some(min);
+        self.range.config.lease_time.max = Some(max);
+        self
+    }
+
+    pub fn option(mut self, opt: DhcpOption) -> Self {
+        set_opt(&mut self.range.options, opt);
+        self
+    }
+
+    /// don't inherit the network option `code`
+    pub fn remove(mut self, code: OptionCode) -> Self {
+        remove_opt(&mut self.range.options, code);
+        self
+    }
+
+    /// never hand out `ip`
+    pub fn except(mut self, ip: Ipv4Addr) -> Self {
+        self.range.except.push(wire::v4::Exclusion::Ip(ip));
+        self
+    }
+
+    /// only clients in the client class `class` get addresses from this range
+    pub fn class(mut self, class: impl Into<String>) -> Self {
+        self.range.class = Some(class.into());
+        self
+    }
+
+    /// when a client's requested address in this range is offered
+    pub fn honor_hints(mut self, policy: HintPolicy) -> Self {
+        self.range.honor_hints = policy;
+        self
+    }
+}
+
+/// builds a reservation for [`NetworkBuilder::reservation`]
+#[derive(Debug, Clone)]
+pub struct ReservationBuilder {
+    res: ReservedIp,
+}
+
+impl ReservationBuilder {
+    /// reserve `ip` for the client with chaddr `mac`, leased for `lease_time` seconds
+    pub fn mac(ip: impl Into<Ipv4Addr>, mac: MacAddr, lease_time: u32) -> Self {
+        Self::new(ip.into(), Condition::Mac(mac), lease_time)
+    }
+
+    /// reserve `ip` for clients sending `opt`
+    pub fn matching(ip: impl Into<Ipv4Addr>, opt: DhcpOption, lease_time: u32) -> Self {
+        let mut options = Options::default();
+        set_opt(&mut options, opt);
+        Self::new(ip.into(), Condition::Options(options), lease_time)
+    }
+
+    fn new(ip: Ipv4Addr, condition: Condition, lease_time: u32) -> Self {
+        Self {
+            res: ReservedIp {
+                ip,
+                options: Options::default(),
+                condition,
+                config: NetworkConfig {
+                    lease_time: MinMax {
+                        default: lease_time,
+                        min: None,
+                        max: None,
+                    },
+                },
+                class: None,
+                boot: wire::v4::Boot::default(),
+            },
+        }
+    }
+
+    pub fn option(mut self, opt: DhcpOption) -> Self {
+        set_opt(&mut self.res.options, opt);
+        self
+    }
+
+    /// don't inherit the network option `code`
+    pub fn remove(mut self, code: OptionCode) -> Self {
+        remove_opt(&mut self.res.options, code);
+        self
+    }
+}
+
+fn set_opt(opts: &mut Options, opt: DhcpOption) {
+    let code = u8::from(OptionCode::from(&opt));
+    opts.remove.retain(|c| *c != code);
+    opts.values.0.insert(opt);
+}
+
+fn remove_opt(opts: &mut Options, code: OptionCode) {
+    opts.values.0.remove(code);
+    let code = u8::from(code);
+    if !opts.remove.contains(&code) {
+        opts.remove.push(code);
+    }
+}
+
+fn check_lease(lease: &MinMax) -> Result<()> {
+    let MinMax { default, min, max } = *lease;
+    ensure!(
+        min.unwrap_or(default) <= default && default <= max.unwrap_or(default),
+        "lease_time must be min <= default <= max, got {min:?} <= {default} <= {max:?}"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net() -> NetworkBuilder {
+        NetworkBuilder::new("192.168.0.0/24".parse().unwrap())
+    }
+
+    #[test]
+    fn test_builder() {
+        let cfg = ConfigBuilder::new()
+            .option(DhcpOption::DomainNameServer(vec![[1, 1, 1, 1].into()]))
+            .network(
+                net()
+                    .option(DhcpOption::Router(vec![[192, 168, 0, 1].into()]))
+                    .range(
+                        RangeBuilder::new([192, 168, 0, 100], [192, 168, 0, 200], 3600)
+                            .lease_bounds(1200, 7200)
+                            .remove(OptionCode::DomainNameServer),
+                    )
+                    .reservation(ReservationBuilder::mac(
+                        [192, 168, 0, 10],
+                        MacAddr::new(0, 1, 2, 3, 4, 5),
+                        3600,
+                    )),
+            );
+        let wire = cfg.clone().into_wire().unwrap();
+        // the same config a file would be parsed into
+        let yaml = serde_yaml::to_string(&wire).unwrap();
+        assert_eq!(serde_yaml::from_str::<wire::Config>(&yaml).unwrap(), wire);
+
+        let cfg = cfg.build().unwrap();
+        let net = cfg.v4().network([192, 168, 0, 1]).unwrap();
+        let range = net.range([192, 168, 0, 150], None).unwrap();
+        assert_eq!(
+            range.opts().get(OptionCode::Router),
+            Some(&DhcpOption::Router(vec![[192, 168, 0, 1].into()]))
+        );
+        assert!(range.opts().get(OptionCode::DomainNameServer).is_none());
+        assert_eq!(range.lease().get_min().as_secs(), 1200);
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let build = |net: NetworkBuilder| ConfigBuilder::new().network(net).into_wire();
+        // outside of the network
+        assert!(build(net().range(RangeBuilder::new([10, 0, 0, 1], [10, 0, 0, 9], 60))).is_err());
+        // backwards
+        assert!(
+            build(net().range(RangeBuilder::new([192, 168, 0, 9], [192, 168, 0, 1], 60))).is_err()
+        );
+        // overlapping
+        assert!(build(
+            net()
+                .range(RangeBuilder::new([192, 168, 0, 1], [192, 168, 0, 9], 60))
+                .range(RangeBuilder::new([192, 168, 0, 5], [192, 168, 0, 20], 60))
+        )
+        .is_err());
+        // default lease longer than max
+        assert!(build(net().range(
+            RangeBuilder::new([192, 168, 0, 1], [192, 168, 0, 9], 600).lease_bounds(60, 300)
+        ))
+        .is_err());
+        assert!(build(net().reservation(ReservationBuilder::mac(
+            [10, 0, 0, 1],
+            MacAddr::zero(),
+            60
+        )))
+        .is_err());
+        assert!(ConfigBuilder::new()
+            .network(net())
+            .network(net())
+            .into_wire()
+            .is_err());
+    }
+}
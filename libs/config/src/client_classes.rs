@@ -3,7 +3,7 @@
 use std::collections::{HashMap, HashSet};
 
 use anyhow::{Context, Result};
-use client_classification::{ast, Args, Expr, Val};
+use client_classification::{ast, Args, Expr, FingerprintDb, Val};
 use dora_core::dhcproto::{
     self,
     v4::{self, OptionCode, UnknownOption},
@@ -12,9 +12,9 @@ use dora_core::dhcproto::{
 use topo_sort::DependencyTree;
 use tracing::{error, trace};
 
-use crate::wire;
+use crate::wire::{self, v4::Boot};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Eq)]
 pub struct ClientClasses {
     /// list of classes, order is topologically sorted based on use of `member` dependencies in the expression
     pub(crate) classes: HashMap<String, ClientClass>,
@@ -22,6 +22,14 @@ pub struct ClientClasses {
     pub(crate) topo_order: Vec<String>,
 }
 
+// classes that don't depend on each other can be sorted in any order, so
+// `topo_order` isn't compared
+impl PartialEq for ClientClasses {
+    fn eq(&self, other: &Self) -> bool {
+        self.classes == other.classes && self.original_order == other.original_order
+    }
+}
+
 impl ClientClasses {
     pub fn find(&self, name: &str) -> Option<&ClientClass> {
         self.classes.get(name)
@@ -34,6 +42,7 @@ pub struct ClientClass {
     // TODO: client classes assertion won't work with sub-options right now
     pub(crate) assert: Expr,
     pub(crate) options: v4::DhcpOptions,
+    pub(crate) boot: Boot,
 }
 
 impl TryFrom<wire::client_classes::ClientClasses> for ClientClasses {
@@ -56,6 +65,7 @@ impl TryFrom<wire::client_classes::ClientClasses> for ClientClasses {
                     name: class.name,
                     assert,
                     options: class.options.get(),
+                    boot: class.boot,
                 },
             );
         }
@@ -69,14 +79,20 @@ impl TryFrom<wire::client_classes::ClientClasses> for ClientClasses {
 }
 
 impl ClientClasses {
-    /// evaluate all client classes, returning a list of classes that match
-    pub fn eval(&self, req: &dhcproto::v4::Message) -> Result<Vec<String>> {
+    /// evaluate all client classes, returning a list of classes that match.
+    /// `pkt4.category` is looked up in `fingerprints`
+    pub fn eval(
+        &self,
+        req: &dhcproto::v4::Message,
+        fingerprints: &FingerprintDb,
+    ) -> Result<Vec<String>> {
         let (chaddr, opts) = to_unknown_opts(req)?;
         let mut args = Args {
             chaddr,
             deps: HashSet::new(),
             msg: req,
             opts,
+            fingerprints: Some(fingerprints),
         };
         for name in &self.topo_order {
             // this should never fail
@@ -103,6 +119,15 @@ impl ClientClasses {
                 merge_opts(&class.options, ret)
             })
     }
+    /// boot header fields of the matched classes, each field is taken from the
+    /// first class in the client_classes list that sets it
+    pub fn boot(&self, matched_classes: Option<&[String]>) -> Boot {
+        self.original_order
+            .iter()
+            .filter(|name| matched_classes.map(|m| m.contains(name)).unwrap_or(false))
+            .filter_map(|name| self.find(name))
+            .fold(Boot::default(), |ret, class| ret.or(&class.boot))
+    }
 }
 
 impl ClientClass {
@@ -183,6 +208,7 @@ mod tests {
                             opts.insert(v4::DhcpOption::AddressLeaseTime(10));
                             opts
                         },
+                        boot: Boot::default(),
                     },
                 ),
                 (
@@ -197,6 +223,7 @@ mod tests {
                             opts.insert(v4::DhcpOption::TimeOffset(50));
                             opts
                         },
+                        boot: Boot::default(),
                     },
                 ),
                 (
@@ -210,6 +237,7 @@ mod tests {
                             opts.insert(v4::DhcpOption::ArpCacheTimeout(1));
                             opts
                         },
+                        boot: Boot::default(),
                     },
                 ),
             ]
@@ -0,0 +1,108 @@
+//! hostnames generated for clients that don't send one, from a network's
+//! `hostname_template`
+//!
+//! A template is a hostname with placeholders, ex.
+//! `dhcp-{ip_dashed}.guest.example.com`:
+//!
+//! - `{ip_dashed}` the leased address with dashes, `192-168-0-10`
+//! - `{ip_last}` the last octet of the leased address, `10`
+//! - `{mac}` the client's hardware address in hex, `001122aabbcc`
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, ensure, Result};
+
+/// a parsed `hostname_template`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostnameTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    IpDashed,
+    IpLast,
+    Mac,
+}
+
+impl HostnameTemplate {
+    /// parse a template, the text around placeholders may only hold letters,
+    /// digits, `-` & `.`
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while !rest.is_empty() {
+            let Some(start) = rest.find('{') else {
+                parts.push(literal(rest)?);
+                break;
+            };
+            if start > 0 {
+                parts.push(literal(&rest[..start])?);
+            }
+            let Some(len) = rest[start..].find('}') else {
+                bail!("unclosed placeholder in hostname template {template:?}");
+            };
+            parts.push(match &rest[start + 1..start + len] {
+                "ip_dashed" => Part::IpDashed,
+                "ip_last" => Part::IpLast,
+                "mac" => Part::Mac,
+                other => bail!("unknown placeholder {{{other}}} in hostname template"),
+            });
+            rest = &rest[start + len + 1..];
+        }
+        ensure!(
+            parts.iter().any(|part| !matches!(part, Part::Literal(_))),
+            "hostname template {template:?} has no placeholders, clients would share a name"
+        );
+        Ok(Self { parts })
+    }
+
+    /// the hostname of the client with hardware address `chaddr` leasing `ip`
+    pub fn render(&self, ip: Ipv4Addr, chaddr: &[u8]) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(s) => s.clone(),
+                Part::IpDashed => ip.to_string().replace('.', "-"),
+                Part::IpLast => ip.octets()[3].to_string(),
+                Part::Mac => chaddr.iter().map(|b| format!("{b:02x}")).collect(),
+            })
+            .collect()
+    }
+}
+
+/// lowercased text between placeholders
+fn literal(s: &str) -> Result<Part> {
+    ensure!(
+        s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.'),
+        "hostname template text {s:?} may only hold letters, digits, `-` & `.`"
+    );
+    Ok(Part::Literal(s.to_ascii_lowercase()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let ip = Ipv4Addr::new(192, 168, 0, 10);
+        let chaddr = [0x00, 0x11, 0x22, 0xaa, 0xbb, 0xcc];
+        let template = HostnameTemplate::parse("dhcp-{ip_dashed}.Guest.example.com").unwrap();
+        assert_eq!(
+            template.render(ip, &chaddr),
+            "dhcp-192-168-0-10.guest.example.com"
+        );
+        let template = HostnameTemplate::parse("{mac}-{ip_last}").unwrap();
+        assert_eq!(template.render(ip, &chaddr), "001122aabbcc-10");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(HostnameTemplate::parse("guest.example.com").is_err());
+        assert!(HostnameTemplate::parse("dhcp-{ip}.example.com").is_err());
+        assert!(HostnameTemplate::parse("dhcp-{ip_dashed.example.com").is_err());
+        assert!(HostnameTemplate::parse("dhcp_{ip_dashed}").is_err());
+    }
+}
@@ -0,0 +1,128 @@
+//! named server instances, each with its own config & lease database, run
+//! side by side in one dora process
+use std::{collections::BTreeMap, path::Path, path::PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// an instance of dora, see [`parse`]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Instance {
+    /// path to the instance's dhcp config
+    pub config_path: PathBuf,
+    /// lease database, must not be shared with another instance
+    pub database_url: String,
+    /// override the v4 listen port
+    pub v4_port: Option<u16>,
+    /// override the v6 listen port
+    pub v6_port: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+struct Instances {
+    instances: BTreeMap<String, Instance>,
+}
+
+/// parse the instances file at `path`, json or yaml. Ex.
+///
+/// ```yaml
+/// instances:
+///     lab1:
+///         config_path: /etc/dora/lab1.yaml
+///         database_url: /var/lib/dora/lab1.db
+/// ```
+pub fn parse<P: AsRef<Path>>(path: P) -> Result<BTreeMap<String, Instance>> {
+    let path = path.as_ref();
+    let input = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to find instances at {}", path.display()))?;
+    let instances = match serde_json::from_str::<Instances>(&input) {
+        Ok(instances) => instances,
+        Err(_) => serde_yaml::from_str::<Instances>(&input)
+            .with_context(|| format!("failed to parse instances at {}", path.display()))?,
+    }
+    .instances;
+    validate(&instances)?;
+    Ok(instances)
+}
+
+fn validate(instances: &BTreeMap<String, Instance>) -> Result<()> {
+    if instances.is_empty() {
+        bail!("at least one instance must be configured");
+    }
+    let mut databases = BTreeMap::new();
+    for (name, instance) in instances {
+        // names are used in api paths & metric labels
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("instance name {name:?} may only contain letters, numbers, `-` and `_`");
+        }
+        if let Some(other) = databases.insert(&instance.database_url, name) {
+            bail!(
+                "instances {other} and {name} share database {}",
+                instance.database_url
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instances(yaml: &str) -> Result<BTreeMap<String, Instance>> {
+        let instances = serde_yaml::from_str::<Instances>(yaml)?.instances;
+        validate(&instances)?;
+        Ok(instances)
+    }
+
+    #[test]
+    fn test_instances() {
+        let instances = instances(
+            r#"
+instances:
+    lab1:
+        config_path: /etc/dora/lab1.yaml
+        database_url: /var/lib/dora/lab1.db
+    lab2:
+        config_path: /etc/dora/lab2.yaml
+        database_url: /var/lib/dora/lab2.db
+        v4_port: 6767
+"#,
+        )
+        .unwrap();
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances["lab2"].v4_port, Some(6767));
+        assert_eq!(instances["lab1"].v4_port, None);
+    }
+
+    #[test]
+    fn test_invalid_instances() {
+        // shared database
+        assert!(instances(
+            r#"
+instances:
+    lab1:
+        config_path: /etc/dora/lab1.yaml
+        database_url: /var/lib/dora/leases.db
+    lab2:
+        config_path: /etc/dora/lab2.yaml
+        database_url: /var/lib/dora/leases.db
+"#,
+        )
+        .is_err());
+        // bad name
+        assert!(instances(
+            r#"
+instances:
+    lab/1:
+        config_path: /etc/dora/lab1.yaml
+        database_url: /var/lib/dora/lab1.db
+"#,
+        )
+        .is_err());
+    }
+}
@@ -1,13 +1,21 @@
+pub mod builder;
 pub mod client_classes;
+pub mod hostname;
+pub mod instances;
+pub mod profiles;
+pub mod relay;
+pub mod schedule;
+pub mod system_dns;
+pub mod topology;
 pub mod v4;
 pub mod v6;
+pub mod vlan;
 pub mod wire;
 
 use std::{env, path::Path, time::Duration};
 
 use anyhow::{bail, Context, Result};
 use dora_core::pnet::{
-    self,
     datalink::NetworkInterface,
     ipnetwork::{IpNetwork, Ipv4Network},
 };
@@ -29,6 +37,10 @@ impl DhcpConfig {
     pub fn v6(&self) -> &v6::Config {
         self.v4.v6().unwrap() // v6 existence checked before starting plugins
     }
+    /// the relay agent config, `None` unless dora runs in `relay` mode
+    pub fn relay(&self) -> Option<&relay::Config> {
+        self.v4.relay()
+    }
 }
 
 /// server instance config
@@ -55,19 +67,41 @@ impl DhcpConfig {
     /// attempts to decode the config first as JSON, then YAML, finally erroring if neither work
     pub fn parse<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
-        let config = v4::Config::new(
+        Self::parse_str(
             std::fs::read_to_string(path)
                 .with_context(|| format!("failed to find config at {}", &path.display()))?,
-        )?;
+        )
+    }
+    /// decode a config from JSON or YAML text, see [`DhcpConfig::parse`]
+    pub fn parse_str<S: AsRef<str>>(input: S) -> Result<Self> {
+        let config = v4::Config::new(input)?;
         debug!(?config);
 
         Ok(Self { v4: config })
     }
+    /// build a config in code instead of parsing it
+    pub fn from_wire(cfg: wire::Config) -> Result<Self> {
+        Ok(Self {
+            v4: v4::Config::try_from(cfg)?,
+        })
+    }
+    /// what changes if `new` replaces this config
+    pub fn diff(&self, new: &DhcpConfig) -> v4::diff::ConfigDiff {
+        self.v4.diff(&new.v4)
+    }
+    /// load the v6 server DUID from `path` or persist the generated one there,
+    /// see [`v6::Config::persist_server_id`]. Does nothing if v6 isn't configured
+    pub fn persist_server_id<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        match self.v4.v6_mut() {
+            Some(v6) => v6.persist_server_id(path),
+            None => Ok(()),
+        }
+    }
 }
 
 /// find the first up non-loopback interface, if a name is provided it must also match
 pub fn backup_ivp4_interface(interface: Option<&str>) -> Result<Ipv4Network> {
-    let interface = pnet::datalink::interfaces().into_iter().find(|e| {
+    let interface = interfaces::list().into_iter().find(|e| {
         e.is_up()
             && !e.is_loopback()
             && !e.ips.is_empty()
@@ -95,7 +129,7 @@ pub fn backup_ivp4_interface(interface: Option<&str>) -> Result<Ipv4Network> {
 /// - interfaces matching the list supplied that are 'up' and have an IPv4
 /// - OR any 'up' interfaces that also have an IPv4
 pub fn v4_find_interfaces(interfaces: Option<Vec<String>>) -> Result<Vec<NetworkInterface>> {
-    let found_interfaces = pnet::datalink::interfaces()
+    let found_interfaces = interfaces::list()
         .into_iter()
         .filter(|e| e.is_up() && !e.ips.is_empty() && e.ips.iter().any(|i| i.is_ipv4()))
         .collect::<Vec<_>>();
@@ -106,7 +140,7 @@ pub fn v4_find_interfaces(interfaces: Option<Vec<String>>) -> Result<Vec<Network
 /// - interfaces matching the list supplied that are 'up' and have an IPv6
 /// - OR any 'up' interfaces that also have an IPv6
 pub fn v6_find_interfaces(interfaces: Option<Vec<String>>) -> Result<Vec<NetworkInterface>> {
-    let found_interfaces = pnet::datalink::interfaces()
+    let found_interfaces = interfaces::list()
         .into_iter()
         .filter(|e| e.is_up() && !e.ips.is_empty() && e.ips.iter().any(|i| i.is_ipv6()))
         .collect::<Vec<_>>();
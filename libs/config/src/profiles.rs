@@ -0,0 +1,160 @@
+//! named option profiles, an `options` block lists them in `profiles` instead of
+//! repeating the same values
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use dora_core::dhcproto::v4::OptionCode;
+
+use crate::wire::v4::Options;
+
+/// option profiles with the profiles they include expanded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profiles(HashMap<String, Options>);
+
+impl Profiles {
+    /// expand each profile's includes, a profile can't include itself
+    pub fn new(profiles: HashMap<String, Options>) -> Result<Self> {
+        let mut resolved = HashMap::new();
+        for name in profiles.keys() {
+            resolve(name, &profiles, &mut resolved, &mut Vec::new())?;
+        }
+        Ok(Self(resolved))
+    }
+
+    /// get an expanded profile
+    pub fn get(&self, name: &str) -> Option<&Options> {
+        self.0.get(name)
+    }
+
+    /// apply the profiles referenced by `opts` in order, then `opts` itself
+    pub fn apply(&self, opts: Options) -> Result<Options> {
+        if opts.profiles.is_empty() {
+            return Ok(opts);
+        }
+        let profiles = opts
+            .profiles
+            .iter()
+            .map(|name| {
+                self.get(name)
+                    .with_context(|| format!("unknown option profile {name}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(flatten(profiles.into_iter().chain([&opts])))
+    }
+}
+
+fn resolve(
+    name: &str,
+    profiles: &HashMap<String, Options>,
+    resolved: &mut HashMap<String, Options>,
+    path: &mut Vec<String>,
+) -> Result<()> {
+    if resolved.contains_key(name) {
+        return Ok(());
+    }
+    if path.iter().any(|n| n == name) {
+        bail!(
+            "option profile {name} includes itself: {} -> {name}",
+            path.join(" -> ")
+        );
+    }
+    let profile = profiles.get(name).with_context(|| match path.last() {
+        Some(parent) => format!("option profile {parent} includes unknown profile {name}"),
+        None => format!("unknown option profile {name}"),
+    })?;
+    path.push(name.to_owned());
+    for include in &profile.profiles {
+        resolve(include, profiles, resolved, path)?;
+    }
+    path.pop();
+    let flat = flatten(
+        profile
+            .profiles
+            .iter()
+            .map(|include| &resolved[include])
+            .chain([profile]),
+    );
+    resolved.insert(name.to_owned(), flat);
+    Ok(())
+}
+
+/// combine `layers` into a single options block, each layer is applied on top
+/// of the previous ones the same way a child level inherits from its parent
+fn flatten<'a>(layers: impl IntoIterator<Item = &'a Options>) -> Options {
+    let mut flat = Options::default();
+    for layer in layers {
+        for (code, opt) in layer.values.0.iter() {
+            flat.values.0.insert(opt.clone());
+            flat.remove.retain(|c| OptionCode::from(*c) != *code);
+        }
+        for code in &layer.remove {
+            flat.values.0.remove(OptionCode::from(*code));
+            if !flat.remove.contains(code) {
+                flat.remove.push(*code);
+            }
+        }
+    }
+    flat
+}
+
+#[cfg(test)]
+mod tests {
+    use dora_core::dhcproto::v4::DhcpOption;
+
+    use super::*;
+
+    fn profile(yaml: &str) -> Options {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_profiles() {
+        let profiles = Profiles::new(HashMap::from([
+            (
+                "dns".to_owned(),
+                profile("values:\n  6:\n    type: ip_list\n    value: [1.1.1.1]\n  15:\n    type: str\n    value: corp.local\n"),
+            ),
+            (
+                "voip".to_owned(),
+                profile("profiles: [dns]\nvalues:\n  15:\n    type: str\n    value: voip.local\nremove: [6]\n"),
+            ),
+        ]))
+        .unwrap();
+        let voip = profiles.get("voip").unwrap();
+        assert_eq!(
+            voip.values.0.get(OptionCode::DomainName),
+            Some(&DhcpOption::DomainName("voip.local".to_owned()))
+        );
+        assert!(voip.values.0.get(OptionCode::DomainNameServer).is_none());
+        assert_eq!(voip.remove, vec![6]);
+
+        // own values win, a value set again is no longer removed
+        let opts = profiles
+            .apply(profile(
+                "profiles: [voip]\nvalues:\n  6:\n    type: ip_list\n    value: [8.8.8.8]\n",
+            ))
+            .unwrap();
+        assert_eq!(
+            opts.values.0.get(OptionCode::DomainNameServer),
+            Some(&DhcpOption::DomainNameServer(vec![[8, 8, 8, 8].into()]))
+        );
+        assert!(opts.remove.is_empty());
+
+        assert!(profiles.apply(profile("profiles: [nope]\n")).is_err());
+    }
+
+    #[test]
+    fn test_profile_cycle() {
+        let err = Profiles::new(HashMap::from([
+            ("a".to_owned(), profile("profiles: [b]\n")),
+            ("b".to_owned(), profile("profiles: [a]\n")),
+        ]))
+        .unwrap_err();
+        assert!(err.to_string().contains("includes itself"));
+        assert!(Profiles::new(HashMap::from([(
+            "a".to_owned(),
+            profile("profiles: [c]\n")
+        )]))
+        .is_err());
+    }
+}
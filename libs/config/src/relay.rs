@@ -0,0 +1,139 @@
+//! relay agent config
+//!
+//! With `mode: relay` dora doesn't hand out leases. Client messages received on
+//! its interfaces are forwarded to the upstream `servers`, so a branch router can
+//! run dora next to its clients & leave leasing to a central dora. v4 messages
+//! get giaddr & relay agent information (opt 82), v6 messages are encapsulated
+//! in Relay-Forward with the Interface-ID & Remote-ID options.
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{bail, Context, Result};
+use dora_core::{
+    dhcproto::{v4, v6},
+    pnet::datalink::NetworkInterface,
+};
+
+use crate::wire;
+
+/// relay agent config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// interfaces v4 clients are relayed from
+    v4_interfaces: Vec<NetworkInterface>,
+    /// interfaces v6 clients are relayed from
+    v6_interfaces: Vec<NetworkInterface>,
+    v4_servers: Vec<SocketAddr>,
+    v6_servers: Vec<SocketAddr>,
+    interface_id: bool,
+    remote_id: Option<Vec<u8>>,
+    enterprise_number: u32,
+}
+
+impl Config {
+    /// relay from `interfaces`, or every interface that's up if `None`
+    pub fn new(relay: wire::Relay, interfaces: Option<Vec<String>>) -> Result<Self> {
+        let mut v4_servers = Vec::new();
+        let mut v6_servers = Vec::new();
+        for server in &relay.servers {
+            match parse_server(server)? {
+                addr @ SocketAddr::V4(_) => v4_servers.push(addr),
+                addr @ SocketAddr::V6(_) => v6_servers.push(addr),
+            }
+        }
+        if v4_servers.is_empty() && v6_servers.is_empty() {
+            bail!("relay mode needs at least one upstream server in `relay.servers`");
+        }
+        let v4_interfaces = if v4_servers.is_empty() {
+            Vec::new()
+        } else {
+            crate::v4_find_interfaces(interfaces.clone())?
+        };
+        let v6_interfaces = if v6_servers.is_empty() {
+            Vec::new()
+        } else {
+            crate::v6_find_interfaces(interfaces)?
+        };
+        Ok(Self {
+            v4_interfaces,
+            v6_interfaces,
+            v4_servers,
+            v6_servers,
+            interface_id: relay.interface_id,
+            remote_id: relay.remote_id.map(String::into_bytes),
+            enterprise_number: relay.enterprise_number,
+        })
+    }
+
+    /// interfaces v4 clients are relayed from, empty if there are no v4 servers
+    pub fn v4_interfaces(&self) -> &[NetworkInterface] {
+        &self.v4_interfaces
+    }
+
+    /// interfaces v6 clients are relayed from, empty if there are no v6 servers
+    pub fn v6_interfaces(&self) -> &[NetworkInterface] {
+        &self.v6_interfaces
+    }
+
+    /// upstream v4 servers
+    pub fn v4_servers(&self) -> &[SocketAddr] {
+        &self.v4_servers
+    }
+
+    /// upstream v6 servers
+    pub fn v6_servers(&self) -> &[SocketAddr] {
+        &self.v6_servers
+    }
+
+    /// add the receiving interface's name as the circuit id & Interface-ID
+    pub fn interface_id(&self) -> bool {
+        self.interface_id
+    }
+
+    /// remote id added to relayed messages
+    pub fn remote_id(&self) -> Option<&[u8]> {
+        self.remote_id.as_deref()
+    }
+
+    /// enterprise number of the v6 Remote-ID option
+    pub fn enterprise_number(&self) -> u32 {
+        self.enterprise_number
+    }
+}
+
+/// `ip` or `ip:port`, v6 addresses with a port are written `[ip]:port`. The
+/// port defaults to the server port of the address family
+fn parse_server(server: &str) -> Result<SocketAddr> {
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let ip = server
+        .parse::<IpAddr>()
+        .with_context(|| format!("invalid relay server {server:?}, expected ip or ip:port"))?;
+    let port = match ip {
+        IpAddr::V4(_) => v4::SERVER_PORT,
+        IpAddr::V6(_) => v6::SERVER_PORT,
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_server() {
+        assert_eq!(
+            parse_server("10.0.0.5").unwrap(),
+            "10.0.0.5:67".parse().unwrap()
+        );
+        assert_eq!(
+            parse_server("10.0.0.5:6767").unwrap(),
+            "10.0.0.5:6767".parse().unwrap()
+        );
+        assert_eq!(
+            parse_server("2001:db8::5").unwrap(),
+            "[2001:db8::5]:547".parse().unwrap()
+        );
+        assert!(parse_server("dhcp.example.com").is_err());
+    }
+}
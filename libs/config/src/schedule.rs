@@ -0,0 +1,142 @@
+//! time-of-day schedules, used to change range config inside of a time window
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use dora_core::{
+    chrono::{DateTime, Datelike, NaiveTime, Timelike, Utc, Weekday},
+    chrono_tz::Tz,
+};
+
+use crate::wire;
+
+/// a recurring time window, evaluated in `tz`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    name: String,
+    start: NaiveTime,
+    end: NaiveTime,
+    /// days the window starts on. Empty means every day
+    days: Vec<Weekday>,
+    tz: Tz,
+}
+
+impl Schedule {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// is `now` inside of this window? If `end` is before `start` the window
+    /// wraps past midnight
+    pub fn contains(&self, now: DateTime<Utc>) -> bool {
+        let now = now.with_timezone(&self.tz);
+        let (day, time) = (now.weekday(), now.time());
+        // ignore sub-minute precision, windows are configured in HH:MM
+        let time = time.with_second(0).and_then(|t| t.with_nanosecond(0));
+        let time = match time {
+            Some(time) => time,
+            None => return false,
+        };
+        let on_day = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        if self.start < self.end {
+            on_day(day) && time >= self.start && time < self.end
+        } else {
+            (on_day(day) && time >= self.start) || (on_day(day.pred()) && time < self.end)
+        }
+    }
+}
+
+impl TryFrom<(String, wire::Schedule)> for Schedule {
+    type Error = anyhow::Error;
+
+    fn try_from((name, schedule): (String, wire::Schedule)) -> Result<Self> {
+        let parse_time = |s: &str| {
+            NaiveTime::parse_from_str(s, "%H:%M")
+                .with_context(|| format!("schedule {name}: expected HH:MM, got {s}"))
+        };
+        let days = schedule
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .ok()
+                    .with_context(|| format!("schedule {name}: invalid day {day}"))
+            })
+            .collect::<Result<_>>()?;
+        let tz = match schedule.timezone {
+            Some(tz) => tz
+                .parse::<Tz>()
+                .map_err(|err| anyhow::anyhow!(err))
+                .with_context(|| format!("schedule {name}: invalid timezone"))?,
+            None => Tz::UTC,
+        };
+        Ok(Self {
+            start: parse_time(&schedule.start)?,
+            end: parse_time(&schedule.end)?,
+            days,
+            tz,
+            name,
+        })
+    }
+}
+
+/// parse all schedules by name
+pub fn parse(schedules: HashMap<String, wire::Schedule>) -> Result<HashMap<String, Schedule>> {
+    schedules
+        .into_iter()
+        .map(|(name, schedule)| Ok((name.clone(), Schedule::try_from((name, schedule))?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use dora_core::chrono::TimeZone;
+
+    use super::*;
+
+    fn schedule(start: &str, end: &str, days: &[&str]) -> Schedule {
+        Schedule::try_from((
+            "test".to_owned(),
+            wire::Schedule {
+                start: start.to_owned(),
+                end: end.to_owned(),
+                days: days.iter().map(|d| d.to_string()).collect(),
+                timezone: None,
+            },
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_window() {
+        let s = schedule("09:00", "17:00", &[]);
+        assert!(s.contains(Utc.with_ymd_and_hms(2023, 3, 1, 9, 0, 0).unwrap()));
+        assert!(s.contains(Utc.with_ymd_and_hms(2023, 3, 1, 16, 59, 59).unwrap()));
+        assert!(!s.contains(Utc.with_ymd_and_hms(2023, 3, 1, 17, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_overnight_days() {
+        // 2023-03-03 is a friday
+        let s = schedule("22:00", "06:00", &["fri"]);
+        assert!(s.contains(Utc.with_ymd_and_hms(2023, 3, 3, 23, 0, 0).unwrap()));
+        // saturday morning is still in friday's window
+        assert!(s.contains(Utc.with_ymd_and_hms(2023, 3, 4, 5, 0, 0).unwrap()));
+        // saturday night is not
+        assert!(!s.contains(Utc.with_ymd_and_hms(2023, 3, 4, 23, 0, 0).unwrap()));
+        assert!(!s.contains(Utc.with_ymd_and_hms(2023, 3, 3, 12, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(Schedule::try_from((
+            "bad".to_owned(),
+            wire::Schedule {
+                start: "25:00".to_owned(),
+                end: "06:00".to_owned(),
+                days: vec![],
+                timezone: None,
+            },
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,106 @@
+//! DNS & NTP servers read from the host, for networks with `inherit_system_dns`
+//!
+//! Name servers come from systemd-resolved's upstream list when it's running,
+//! otherwise `/etc/resolv.conf`. NTP servers are the `server`/`pool` lines of
+//! chrony's config that are IPv4 addresses, hostnames can't be sent in option 42.
+//! Loopback addresses are skipped since they're only reachable from the host.
+use std::{fs, io, net::Ipv4Addr};
+
+use dora_core::dhcproto::v4::DhcpOption;
+use tracing::{debug, warn};
+
+/// upstream servers of systemd-resolved, `/etc/resolv.conf` points at its stub
+const RESOLVED_CONF: &str = "/run/systemd/resolve/resolv.conf";
+const RESOLV_CONF: &str = "/etc/resolv.conf";
+const CHRONY_CONFS: [&str; 2] = ["/etc/chrony/chrony.conf", "/etc/chrony.conf"];
+
+/// options 6 (DNS) & 42 (NTP) with the host's servers, either is left out if
+/// the host has none
+pub fn system_opts() -> Vec<DhcpOption> {
+    let mut opts = Vec::new();
+    let dns = read_first(&[RESOLVED_CONF, RESOLV_CONF])
+        .map(|conf| name_servers(&conf))
+        .unwrap_or_default();
+    if dns.is_empty() {
+        warn!("inherit_system_dns is set but no name servers were found on the host");
+    } else {
+        debug!(?dns, "using the host's name servers");
+        opts.push(DhcpOption::DomainNameServer(dns));
+    }
+    let ntp = read_first(&CHRONY_CONFS)
+        .map(|conf| ntp_servers(&conf))
+        .unwrap_or_default();
+    if !ntp.is_empty() {
+        debug!(?ntp, "using the host's NTP servers");
+        opts.push(DhcpOption::NTPServers(ntp));
+    }
+    opts
+}
+
+/// contents of the first of `paths` that can be read
+fn read_first(paths: &[&str]) -> Option<String> {
+    paths
+        .iter()
+        .find_map(|path| match fs::read_to_string(path) {
+            Ok(conf) => Some(conf),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                warn!(?err, path, "failed to read host config");
+                None
+            }
+        })
+}
+
+/// `nameserver` entries of a resolv.conf
+fn name_servers(conf: &str) -> Vec<Ipv4Addr> {
+    addrs(conf, &["nameserver"])
+}
+
+/// `server` & `pool` entries of a chrony config
+fn ntp_servers(conf: &str) -> Vec<Ipv4Addr> {
+    addrs(conf, &["server", "pool"])
+}
+
+/// non-loopback IPv4 addresses following one of `keys`, in order & without
+/// duplicates
+fn addrs(conf: &str, keys: &[&str]) -> Vec<Ipv4Addr> {
+    let mut ips = Vec::new();
+    for line in conf.lines() {
+        let mut words = line.split_whitespace();
+        let (Some(key), Some(value)) = (words.next(), words.next()) else {
+            continue;
+        };
+        if !keys.contains(&key) {
+            continue;
+        }
+        if let Ok(ip) = value.parse::<Ipv4Addr>() {
+            if !ip.is_loopback() && !ips.contains(&ip) {
+                ips.push(ip);
+            }
+        }
+    }
+    ips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_servers() {
+        let conf = "# generated\nnameserver 127.0.0.53\nnameserver 192.168.0.1\nnameserver ::1\nnameserver 1.1.1.1\nsearch lan\nnameserver 1.1.1.1\n";
+        assert_eq!(
+            name_servers(conf),
+            vec![Ipv4Addr::new(192, 168, 0, 1), Ipv4Addr::new(1, 1, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_ntp_servers() {
+        let conf = "pool 2.debian.pool.ntp.org iburst\nserver 10.0.0.1 iburst\n#server 10.0.0.2\npool 10.0.0.3\nkeyfile /etc/chrony/chrony.keys\n";
+        assert_eq!(
+            ntp_servers(conf),
+            vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 3)]
+        );
+    }
+}
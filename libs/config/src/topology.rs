@@ -0,0 +1,145 @@
+//! router & static route options generated from the `topology` routes
+//!
+//! A route applies to every network whose subnet contains its gateway. For each
+//! network dora generates:
+//!
+//! - option 3 (router), the gateways of default (`0.0.0.0/0`) routes
+//! - option 121 (classless static routes, RFC 3442), every route. Clients that
+//!   accept 121 ignore option 3, so the default route is included
+//! - option 33 (static routes), routes to classful networks or hosts. A default
+//!   route can't be sent in 33
+use anyhow::{ensure, Result};
+use dora_core::dhcproto::{v4::DhcpOption, Decodable, Decoder};
+use ipnet::Ipv4Net;
+
+use crate::wire;
+
+/// static route option code, RFC 2132
+const STATIC_ROUTE: u8 = 33;
+/// classless static route option code, RFC 3442
+const CLASSLESS_STATIC_ROUTE: u8 = 121;
+
+/// options generated for the network `subnet`, in code order. Empty if no route's
+/// gateway is on the subnet
+pub fn route_opts(routes: &[wire::Route], subnet: Ipv4Net) -> Result<Vec<DhcpOption>> {
+    let routes = routes
+        .iter()
+        .filter(|route| subnet.contains(&route.gateway))
+        .map(|route| (route.prefix.trunc(), route))
+        .collect::<Vec<_>>();
+    let mut opts = Vec::new();
+    let routers = routes
+        .iter()
+        .filter(|(prefix, _)| prefix.prefix_len() == 0)
+        .map(|(_, route)| route.gateway)
+        .collect::<Vec<_>>();
+    if !routers.is_empty() {
+        opts.push(DhcpOption::Router(routers));
+    }
+
+    let classful = routes
+        .iter()
+        .filter(|(prefix, _)| prefix.prefix_len() != 0 && is_classful(prefix))
+        .flat_map(|(prefix, route)| {
+            prefix
+                .network()
+                .octets()
+                .into_iter()
+                .chain(route.gateway.octets())
+        })
+        .collect::<Vec<_>>();
+    if !classful.is_empty() {
+        opts.push(decode(STATIC_ROUTE, &classful, subnet)?);
+    }
+
+    let mut classless = Vec::new();
+    for (prefix, route) in &routes {
+        // width, the significant octets of the destination, then the router
+        let width = prefix.prefix_len();
+        classless.push(width);
+        classless.extend(&prefix.network().octets()[..(usize::from(width) + 7) / 8]);
+        classless.extend(route.gateway.octets());
+    }
+    if !classless.is_empty() {
+        opts.push(decode(CLASSLESS_STATIC_ROUTE, &classless, subnet)?);
+    }
+    Ok(opts)
+}
+
+/// `net` is a whole class A, B or C network, or a host
+fn is_classful(net: &Ipv4Net) -> bool {
+    let natural = match net.network().octets()[0] {
+        0..=127 => 8,
+        128..=191 => 16,
+        192..=223 => 24,
+        _ => return false,
+    };
+    net.prefix_len() == natural || net.prefix_len() == 32
+}
+
+/// decode the option from its payload, so it has the same type as an option from
+/// an `options` block
+fn decode(code: u8, data: &[u8], subnet: Ipv4Net) -> Result<DhcpOption> {
+    ensure!(
+        data.len() <= usize::from(u8::MAX),
+        "network {subnet}: too many topology routes for option {code}"
+    );
+    let mut buf = vec![code, data.len() as u8];
+    buf.extend(data);
+    Ok(DhcpOption::decode(&mut Decoder::new(&buf))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use dora_core::dhcproto::Encodable;
+
+    use super::*;
+
+    fn route(prefix: &str, gateway: &str) -> wire::Route {
+        wire::Route {
+            prefix: prefix.parse().unwrap(),
+            gateway: gateway.parse().unwrap(),
+        }
+    }
+
+    fn payload(opt: &DhcpOption) -> Vec<u8> {
+        opt.to_vec().unwrap()[2..].to_vec()
+    }
+
+    #[test]
+    fn test_route_opts() {
+        let routes = [
+            route("0.0.0.0/0", "192.168.0.1"),
+            route("10.0.0.0/8", "192.168.0.254"),
+            route("172.16.5.0/24", "192.168.0.254"),
+            // not on the subnet
+            route("0.0.0.0/0", "192.168.1.1"),
+        ];
+        let opts = route_opts(&routes, "192.168.0.1/24".parse().unwrap()).unwrap();
+        assert_eq!(opts.len(), 3);
+        assert_eq!(opts[0], DhcpOption::Router(vec![[192, 168, 0, 1].into()]));
+        // 172.16.5.0/24 isn't a class B network
+        assert_eq!(payload(&opts[1]), [10, 0, 0, 0, 192, 168, 0, 254]);
+        assert_eq!(
+            payload(&opts[2]),
+            [
+                0, 192, 168, 0, 1, //
+                8, 10, 192, 168, 0, 254, //
+                24, 172, 16, 5, 192, 168, 0, 254,
+            ]
+        );
+
+        // no gateways on the subnet
+        assert!(route_opts(&routes, "10.1.0.0/16".parse().unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_too_many_routes() {
+        let routes = (0..=50)
+            .map(|i| route(&format!("10.{i}.0.0/16"), "192.168.0.1"))
+            .collect::<Vec<_>>();
+        assert!(route_opts(&routes, "192.168.0.1/24".parse().unwrap()).is_err());
+    }
+}
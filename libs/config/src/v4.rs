@@ -1,28 +1,57 @@
 use std::{
-    collections::{HashMap, HashSet},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     net::Ipv4Addr,
     ops::RangeInclusive,
     time::Duration,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use dora_core::{
+    chrono::{DateTime, Utc},
     dhcproto::{
         self,
         v4::{DhcpOption, DhcpOptions, Message, OptionCode},
+        Encodable,
     },
+    garbage,
     pnet::{
         datalink::NetworkInterface,
         ipnetwork::{IpNetwork, Ipv4Network},
         util::MacAddr,
     },
+    server::msg::{OptionOrder, OptionRules, ReplyEncoding, ReplyOptions, ReplyRules},
 };
-use ipnet::{Ipv4AddrRange, Ipv4Net};
+use ipnet::Ipv4Net;
 use tracing::debug;
 
-use crate::{client_classes::ClientClasses, wire, LeaseTime};
+use crate::{
+    client_classes::ClientClasses, hostname::HostnameTemplate, profiles::Profiles,
+    schedule::Schedule, wire, LeaseTime,
+};
+
+pub mod diff;
+pub mod overflow;
+
+pub use crate::wire::{
+    v4::{Boot, HintPolicy, HostnamePolicy, NakPolicy},
+    ClientIdNormalize, NodeSpecific, PingSource, RunAs,
+};
+pub use client_classification::fingerprint::{Device, Fingerprint, FingerprintDb};
 
 pub const DEFAULT_LEASE_TIME: Duration = Duration::from_secs(86_400);
+/// vendor class identifier option code, RFC 2132
+const VENDOR_CLASS: u8 = 60;
+/// user class option code, RFC 3004
+const USER_CLASS: u8 = 77;
+/// bootfile name option code, RFC 2132
+const BOOTFILE_NAME: u8 = 67;
+/// client machine identifier option code, RFC 4578
+const CLIENT_UUID: u8 = 97;
+/// iPXE's encapsulated options, only sent by iPXE
+const IPXE_ENCAP: u8 = 175;
+/// replies aren't padded past an ethernet MTU
+const MAX_REPLY_SIZE: usize = 1_500;
 
 /// server config for dhcpv4
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -31,19 +60,59 @@ pub struct Config {
     /// are up & ipv4
     interfaces: Vec<NetworkInterface>,
     chaddr_only: bool,
+    /// rewrites applied to opt 61 before it's used as the lease key
+    client_id_normalize: ClientIdNormalize,
     bootp_enable: bool,
+    /// copy the client identifier into replies
+    echo_client_id: bool,
+    /// release a client's leases on other networks when it moves
+    release_on_move: bool,
+    /// re-send the cached OFFER to retransmitted DISCOVERs for this long
+    offer_dedup_window: Duration,
+    /// limits of the garbage client heuristics, `None` if they're off
+    garbage_clients: Option<garbage::Limits>,
     /// used to make a selection on which network or subnet to use
     networks: HashMap<Ipv4Net, Network>,
+    /// giaddr blocks mapped to a network subnet, longest prefix first
+    relay_map: Vec<(Ipv4Net, Ipv4Net)>,
+    /// VLANs of trunk interfaces, their subinterfaces are in `interfaces`
+    vlans: Vec<crate::vlan::Vlan>,
+    /// where ping check echo requests are sent from
+    ping_source: PingSource,
+    /// account to switch to once sockets are bound
+    run_as: Option<RunAs>,
     v6: Option<crate::v6::Config>,
     client_classes: Option<ClientClasses>,
+    /// `vendor_class` strings set on ranges, matched against option 60
+    vendor_classes: HashSet<String>,
+    /// devices by DHCP fingerprint, the `fingerprint_db` file's entries then
+    /// the built-in ones
+    fingerprints: FingerprintDb,
+    /// set in `relay` mode, dora relays to other servers instead of leasing
+    relay: Option<crate::relay::Config>,
 }
 
 impl TryFrom<wire::Config> for Config {
     type Error = anyhow::Error;
     fn try_from(cfg: wire::Config) -> Result<Self> {
-        let interfaces = crate::v4_find_interfaces(cfg.interfaces.clone())?;
+        let mut interfaces = crate::v4_find_interfaces(cfg.interfaces.clone())?;
 
         debug!(?interfaces, "using v4 interfaces");
+        let profiles = Profiles::new(cfg.option_profiles)?;
+        // global options are the root of the inheritance hierarchy
+        let global_opts = inherit_opts(
+            &DhcpOptions::new(),
+            profiles
+                .apply(cfg.options.unwrap_or_default())
+                .context("global options")?,
+        );
+        let schedules = crate::schedule::parse(cfg.schedules)?;
+        // only read the host's config if a network asks for it
+        let system_opts = if cfg.networks.values().any(|net| net.inherit_system_dns) {
+            crate::system_dns::system_opts()
+        } else {
+            Vec::new()
+        };
         // transform wire::Config into a more optimized format
         let networks = cfg
             .networks
@@ -52,16 +121,123 @@ impl TryFrom<wire::Config> for Config {
                 let wire::v4::Net {
                     ranges,
                     reservations,
+                    static_hosts,
                     ping_check,
                     probation_period,
+                    offer_hold,
                     authoritative,
                     server_id,
                     ping_timeout_ms,
+                    ping_retries,
+                    next_server,
                     server_name,
                     file_name,
+                    ipxe_file_name,
+                    options,
+                    hostname_conflict,
+                    hostname_template,
+                    allowed_relays,
+                    nak,
+                    reply,
+                    forcerenew_nonce,
+                    sticky,
+                    inherit_system_dns,
                 } = net;
+                if let Some(min_size) = reply.min_size {
+                    anyhow::ensure!(
+                        usize::from(min_size) <= MAX_REPLY_SIZE,
+                        "network {subnet}: reply.min_size {min_size} is larger than {MAX_REPLY_SIZE}"
+                    );
+                }
+                anyhow::ensure!(
+                    reply.mandatory.is_empty()
+                        || reply.options == wire::v4::OptionFilter::Mandatory,
+                    "network {subnet}: reply.mandatory is only used with `options: mandatory`"
+                );
+                let reply_rules = ReplyRules {
+                    relayed: option_rules(&reply.relayed)
+                        .with_context(|| format!("network {subnet}: reply.relayed"))?,
+                    direct: option_rules(&reply.direct)
+                        .with_context(|| format!("network {subnet}: reply.direct"))?,
+                };
+                let hostname_template = hostname_template
+                    .as_deref()
+                    .map(HostnameTemplate::parse)
+                    .transpose()
+                    .with_context(|| format!("network {subnet}"))?;
 
-                let ranges = ranges.into_iter().map(|range| range.into()).collect();
+                let options = profiles
+                    .apply(options.unwrap_or_default())
+                    .with_context(|| format!("network {subnet}"))?;
+                let mut net_opts = if inherit_system_dns {
+                    // the host's servers replace global ones, the network's own options win
+                    let mut parent = global_opts.clone();
+                    for opt in &system_opts {
+                        parent.insert(opt.clone());
+                    }
+                    inherit_opts(&parent, options)
+                } else {
+                    inherit_opts(&global_opts, options)
+                };
+                // configured options win over ones generated from the topology
+                for opt in crate::topology::route_opts(&cfg.topology, subnet)? {
+                    if net_opts.get(OptionCode::from(&opt)).is_none() {
+                        net_opts.insert(opt);
+                    }
+                }
+                let ping = PingCheck {
+                    enabled: ping_check,
+                    timeout: Duration::from_millis(ping_timeout_ms),
+                    retries: ping_retries,
+                };
+                let ranges = ranges
+                    .into_iter()
+                    .map(|mut range| {
+                        let context = || format!("network {subnet}: range {:?}", range.range);
+                        range.options =
+                            inherited(&net_opts, profiles.apply(range.options).with_context(context)?);
+                        for scheduled in &mut range.schedule {
+                            scheduled.options = profiles
+                                .apply(std::mem::take(&mut scheduled.options))
+                                .with_context(context)?;
+                        }
+                        NetRange::from_wire(range, &schedules, ping)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let reservations = reservations
+                    .into_iter()
+                    .map(|mut res| {
+                        let options = profiles
+                            .apply(res.options)
+                            .with_context(|| format!("network {subnet}: reservation {}", res.ip))?;
+                        res.options = inherited(&net_opts, options);
+                        Ok(res)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let static_hosts = static_hosts
+                    .into_iter()
+                    .map(|host| {
+                        let context = || format!("network {subnet}: static host {}", host.ip);
+                        anyhow::ensure!(
+                            subnet.contains(&host.ip),
+                            "{}: address is outside of the network",
+                            context()
+                        );
+                        let pooled = ranges.iter().any(|r: &NetRange| r.contains(&host.ip));
+                        anyhow::ensure!(
+                            !pooled,
+                            "{}: address is inside a range, static hosts don't take pool addresses",
+                            context()
+                        );
+                        let options = profiles.apply(host.options).with_context(context)?;
+                        let host = StaticHost {
+                            ip: host.ip,
+                            mac: host.mac,
+                            opts: inherit_opts(&net_opts, options),
+                        };
+                        Ok((host.ip, host))
+                    })
+                    .collect::<Result<_>>()?;
                 let reserved_macs = reservations
                     .iter()
                     .filter_map(|res| match &res.condition {
@@ -69,6 +245,24 @@ impl TryFrom<wire::Config> for Config {
                         _ => None,
                     })
                     .collect();
+                let reserved_user_classes = reservations
+                    .iter()
+                    .filter_map(|res| match &res.condition {
+                        wire::v4::Condition::UserClass(class) => {
+                            Some((class.as_bytes().to_vec(), res.into()))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                let mut reserved_uuids = HashMap::new();
+                for res in &reservations {
+                    if let wire::v4::Condition::Uuid(uuid) = &res.condition {
+                        let uuid = parse_uuid(uuid).with_context(|| {
+                            format!("network {subnet}: reservation {}", res.ip)
+                        })?;
+                        reserved_uuids.insert(uuid, res.into());
+                    }
+                }
                 let reserved_opts = reservations
                     .iter()
                     .filter_map(|res| {
@@ -88,26 +282,137 @@ impl TryFrom<wire::Config> for Config {
                 let network = Network {
                     server_id,
                     subnet,
-                    ping_check,
+                    ping,
                     probation_period: Duration::from_secs(probation_period),
+                    offer_hold: Duration::from_secs(offer_hold),
                     ranges,
                     reserved_macs,
                     reserved_opts,
+                    reserved_user_classes,
+                    reserved_uuids,
+                    static_hosts,
                     authoritative,
-                    ping_timeout_ms: Duration::from_millis(ping_timeout_ms),
-                    server_name,
-                    file_name,
+                    boot: Boot {
+                        next_server,
+                        server_name,
+                        file_name,
+                        ipxe_file_name,
+                    },
+                    hostname_conflict,
+                    hostname_template,
+                    allowed_relays: allowed_relays.map(|relays| relays.into_iter().collect()),
+                    nak,
+                    reply: ReplyEncoding {
+                        order: match reply.option_order {
+                            wire::v4::OptionOrder::Numeric => OptionOrder::Numeric,
+                            wire::v4::OptionOrder::RequestList => OptionOrder::RequestList,
+                        },
+                        min_size: reply.min_size.map(usize::from),
+                        max_size: None,
+                    },
+                    reply_opts: match reply.options {
+                        wire::v4::OptionFilter::Requested => ReplyOptions::Requested,
+                        wire::v4::OptionFilter::Mandatory => ReplyOptions::Mandatory(
+                            reply.mandatory.into_iter().map(OptionCode::from).collect(),
+                        ),
+                        wire::v4::OptionFilter::All => ReplyOptions::All,
+                    },
+                    reply_rules,
+                    forcerenew_nonce,
+                    sticky: sticky.map(|sticky| Sticky {
+                        after_renewals: sticky.after_renewals,
+                        hold: Duration::from_secs(sticky.hold),
+                    }),
                 };
-                // set total addr space for metrics
-                dora_core::metrics::TOTAL_AVAILABLE_ADDRS.set(network.total_addrs() as i64);
-                (subnet, network)
+                Ok((subnet, network))
             })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let mut relay_map = cfg
+            .relay_map
+            .into_iter()
+            .map(|map| {
+                let subnet = networks
+                    .keys()
+                    .find(|subnet: &&Ipv4Net| subnet.trunc() == map.network.trunc())
+                    .with_context(|| {
+                        format!(
+                            "relay_map: giaddr {} maps to {}, which is not a configured network",
+                            map.giaddr, map.network
+                        )
+                    })?;
+                Ok((map.giaddr.trunc(), *subnet))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        relay_map.sort_by(|(a, _), (b, _)| b.prefix_len().cmp(&a.prefix_len()));
+        let vlans = crate::vlan::resolve(cfg.vlans)?;
+        for vlan in &vlans {
+            if !networks.keys().any(|net| net.contains(&vlan.address.ip())) {
+                bail!(
+                    "vlans: VLAN {} address {} isn't in a configured network",
+                    vlan.id,
+                    vlan.address
+                );
+            }
+            // unnumbered subinterfaces aren't found by `v4_find_interfaces`
+            interfaces.retain(|int| int.index != vlan.interface.index);
+            interfaces.push(vlan.interface.clone());
+        }
+        let mut fingerprints = match &cfg.fingerprint_db {
+            Some(path) => {
+                let context = || format!("failed to load fingerprint db at {}", path.display());
+                let db = std::fs::read_to_string(path).with_context(context)?;
+                FingerprintDb::parse(&db).with_context(context)?
+            }
+            None => FingerprintDb::default(),
+        };
+        fingerprints.extend(FingerprintDb::builtin());
+        let vendor_classes = networks
+            .values()
+            .flat_map(|net| &net.ranges)
+            .filter_map(|range| range.vendor_class.clone())
             .collect();
 
+        let garbage_clients = cfg
+            .garbage_clients
+            .map(|garbage| {
+                if garbage.window == 0 || garbage.xid_chaddrs == 0 || garbage.circuit_chaddrs == 0 {
+                    bail!("garbage_clients `window`, `xid_chaddrs` & `circuit_chaddrs` can't be 0");
+                }
+                Ok(garbage::Limits {
+                    window: Duration::from_secs(garbage.window),
+                    xid_chaddrs: garbage.xid_chaddrs,
+                    circuit_chaddrs: garbage.circuit_chaddrs,
+                    block: garbage.block,
+                })
+            })
+            .transpose()?;
+
+        let relay = match (cfg.mode, cfg.relay) {
+            (wire::Mode::Relay, Some(relay)) => Some(
+                crate::relay::Config::new(relay, cfg.interfaces.clone())
+                    .context("unable to parse relay config")?,
+            ),
+            (wire::Mode::Relay, None) => {
+                bail!("`mode: relay` needs a `relay` block with the upstream servers")
+            }
+            (wire::Mode::Server, Some(_)) => bail!("`relay` is only used with `mode: relay`"),
+            (wire::Mode::Server, None) => None,
+        };
+
         Ok(Self {
             interfaces,
             networks,
+            relay_map,
+            vlans,
+            ping_source: cfg.ping_source.unwrap_or_default(),
+            run_as: cfg.run_as,
             chaddr_only: cfg.chaddr_only,
+            client_id_normalize: cfg.client_id_normalize.unwrap_or_default(),
+            echo_client_id: cfg.echo_client_id,
+            release_on_move: cfg.release_on_move,
+            offer_dedup_window: Duration::from_secs(cfg.offer_dedup_window),
+            garbage_clients,
             bootp_enable: cfg.bootp_enable,
             v6: cfg
                 .v6
@@ -119,29 +424,96 @@ impl TryFrom<wire::Config> for Config {
                 .map(ClientClasses::try_from)
                 .transpose()
                 .context("unable to parse client_classes config")?,
+            vendor_classes,
+            fingerprints,
+            relay,
         })
     }
 }
 
 impl Config {
+    /// what changes if `new` replaces this config
+    pub fn diff(&self, new: &Config) -> diff::ConfigDiff {
+        diff::ConfigDiff::new(self, new)
+    }
+    /// source address/interface for ping checks
+    pub fn ping_source(&self) -> &PingSource {
+        &self.ping_source
+    }
+    /// the account dora switches to once its sockets are bound
+    pub fn run_as(&self) -> Option<&RunAs> {
+        self.run_as.as_ref()
+    }
     pub fn v6(&self) -> Option<&crate::v6::Config> {
         self.v6.as_ref()
     }
-    /// eval all client classes, return names of classes that evaluate to true
+    pub fn v6_mut(&mut self) -> Option<&mut crate::v6::Config> {
+        self.v6.as_mut()
+    }
+    /// the relay agent config, `None` unless dora runs in `relay` mode
+    pub fn relay(&self) -> Option<&crate::relay::Config> {
+        self.relay.as_ref()
+    }
+    /// the VLANs of trunk interfaces served
+    pub fn vlans(&self) -> &[crate::vlan::Vlan] {
+        &self.vlans
+    }
+    /// eval all client classes, return names of classes that evaluate to true.
+    /// The classes of range `vendor_class` strings found in option 60 are added,
+    /// see [`vendor_class_name`]
     pub fn eval_client_classes(&self, req: &dhcproto::v4::Message) -> Option<Result<Vec<String>>> {
-        self.client_classes
-            .as_ref()
-            .map(|classes| classes.eval(req))
+        if self.vendor_classes.is_empty() {
+            return self
+                .client_classes
+                .as_ref()
+                .map(|classes| classes.eval(req, &self.fingerprints));
+        }
+        let mut matched = match &self.client_classes {
+            Some(classes) => match classes.eval(req, &self.fingerprints) {
+                Ok(matched) => matched,
+                Err(err) => return Some(Err(err)),
+            },
+            None => Vec::new(),
+        };
+        matched.extend(self.vendor_classes(req.opts()));
+        Some(Ok(matched))
+    }
+    /// class names of the range `vendor_class` strings the vendor class
+    /// identifier (option 60) in `opts` contains
+    fn vendor_classes<'a>(&'a self, opts: &DhcpOptions) -> impl Iterator<Item = String> + 'a {
+        // [code][len][data]
+        let buf = opts
+            .get(OptionCode::from(VENDOR_CLASS))
+            .and_then(|opt| opt.to_vec().ok())
+            .unwrap_or_default();
+        let id = buf.get(2..).unwrap_or_default().to_vec();
+        self.vendor_classes
+            .iter()
+            .filter(move |vendor| {
+                id.windows(vendor.len())
+                    .any(|window| window == vendor.as_bytes())
+            })
+            .map(|vendor| vendor_class_name(vendor))
     }
     pub fn classes(&self) -> Option<&ClientClasses> {
         self.client_classes.as_ref()
     }
+    /// the fingerprint of `req` & the device it belongs to, if it's known
+    pub fn fingerprint(
+        &self,
+        req: &dhcproto::v4::Message,
+    ) -> Option<(Fingerprint, Option<&Device>)> {
+        let fingerprint = Fingerprint::new(req)?;
+        let device = self.fingerprints.lookup(&fingerprint);
+        Some((fingerprint, device))
+    }
     /// Returns:
     ///     - `server_id` of `Network` belonging to `ip`
     ///     - OR interface at index `iface`
     pub fn server_id(&self, iface: u32, ip: Ipv4Addr) -> Option<Ipv4Addr> {
         self.network(ip)
             .and_then(|net| net.server_id)
+            .or_else(|| self.attached_interface(iface, ip).map(|i| i.ip()))
             .or_else(|| self.get_interface(iface).map(|i| i.ip()))
     }
 
@@ -162,6 +534,26 @@ impl Config {
         })
     }
 
+    /// every v4 address on the interface at `iface_index`, the primary address first
+    pub fn get_interface_ips(&self, iface_index: u32) -> Option<Vec<Ipv4Network>> {
+        self.find_interface(iface_index).map(|int| {
+            int.ips
+                .iter()
+                .filter_map(|ip| match ip {
+                    IpNetwork::V4(ip) => Some(*ip),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    /// the address of the interface at `iface_index` on the same subnet as `ip`.
+    /// Clients on a secondary subnet of the interface are served from the
+    /// interface's address on that subnet
+    pub fn attached_interface(&self, iface_index: u32, ip: Ipv4Addr) -> Option<Ipv4Network> {
+        attached(&self.get_interface_ips(iface_index)?, ip)
+    }
+
     /// find the interface at the index `iface_index`
     fn find_interface(&self, iface_index: u32) -> Option<&NetworkInterface> {
         self.interfaces.iter().find(|e| e.index == iface_index)
@@ -177,23 +569,49 @@ impl Config {
         self.bootp_enable
     }
 
-    /// If opt 61 (client id) exists return that, otherwise return `chaddr` from the message
-    /// header.
-    pub fn client_id<'a>(&self, msg: &'a Message) -> &'a [u8] {
+    /// Whether opt 61 (client id) is returned in replies (RFC 6842)
+    pub fn echo_client_id(&self) -> bool {
+        self.echo_client_id
+    }
+
+    /// Whether a client's leases on other networks are released when it sends
+    /// DISCOVER on a new network
+    pub fn release_on_move(&self) -> bool {
+        self.release_on_move
+    }
+
+    /// How long a retransmitted DISCOVER, with the xid of one already answered,
+    /// is sent the same OFFER instead of allocating again. Zero if disabled
+    pub fn offer_dedup_window(&self) -> Duration {
+        self.offer_dedup_window
+    }
+
+    /// Limits of the garbage client heuristics, `None` if they're off
+    pub fn garbage_clients(&self) -> Option<garbage::Limits> {
+        self.garbage_clients
+    }
+
+    /// If opt 61 (client id) exists return that with `client_id_normalize` applied, otherwise
+    /// return `chaddr` from the message header.
+    pub fn client_id<'a>(&self, msg: &'a Message) -> Cow<'a, [u8]> {
         if self.chaddr_only {
-            msg.chaddr()
+            Cow::Borrowed(msg.chaddr())
         } else if let Some(DhcpOption::ClientIdentifier(id)) =
             msg.opts().get(OptionCode::ClientIdentifier)
         {
-            id
+            normalize_client_id(&self.client_id_normalize, id)
         } else {
-            msg.chaddr()
+            Cow::Borrowed(msg.chaddr())
         }
     }
 
-    /// get a `Network` with a subnet that contains the given IP
+    /// get a `Network` with a subnet that contains the given IP. If the IP is in
+    /// the relay map, the mapped network is returned instead
     pub fn network<I: Into<Ipv4Addr>>(&self, subnet: I) -> Option<&Network> {
         let contains = subnet.into();
+        if let Some(network) = self.mapped_network(contains) {
+            return Some(network);
+        }
         self.networks.iter().find_map(|(network_subnet, network)| {
             if network_subnet.contains(&contains) {
                 Some(network)
@@ -203,6 +621,14 @@ impl Config {
         })
     }
 
+    /// the network `giaddr` is mapped to in the relay map, longest match wins
+    pub fn mapped_network(&self, giaddr: Ipv4Addr) -> Option<&Network> {
+        self.relay_map
+            .iter()
+            .find(|(giaddrs, _)| giaddrs.contains(&giaddr))
+            .and_then(|(_, subnet)| self.networks.get(subnet))
+    }
+
     /// given a list of matched classes and a range
     /// return all options merged for parameter request list
     pub fn collect_opts(
@@ -217,7 +643,69 @@ impl Config {
             .as_ref()
             // range opts
             .map(|classes| merge_opts(opts.clone(), classes.collect_opts(matched_classes)))
-            .unwrap_or(opts.clone())
+            .unwrap_or_else(|| opts.clone())
+    }
+
+    /// boot header fields for a range or reservation's `boot`, with unset fields
+    /// filled from the matched classes. Network level fields are already set by
+    /// the time this is applied. iPXE clients (see [`is_ipxe`]) get `ipxe_file_name`
+    pub fn boot(
+        &self,
+        boot: &Boot,
+        matched_classes: Option<&[String]>,
+        opts: &DhcpOptions,
+    ) -> Boot {
+        let boot = match &self.client_classes {
+            Some(classes) => boot.clone().or(&classes.boot(matched_classes)),
+            None => boot.clone(),
+        };
+        if is_ipxe(opts) {
+            boot.ipxe()
+        } else {
+            boot
+        }
+    }
+
+    /// boot file names set anywhere in the config, in boot headers or option 67
+    pub fn boot_files(&self) -> BTreeSet<String> {
+        let mut boots = Vec::new();
+        let mut opts = Vec::new();
+        for net in self.networks.values() {
+            boots.push(&net.boot);
+            for range in &net.ranges {
+                boots.push(&range.boot);
+                opts.push(&range.opts);
+            }
+            let reserved = net
+                .reserved_macs
+                .values()
+                .chain(net.reserved_opts.values().map(|(_, res)| res))
+                .chain(net.reserved_user_classes.values())
+                .chain(net.reserved_uuids.values());
+            for res in reserved {
+                boots.push(&res.boot);
+                opts.push(&res.opts);
+            }
+        }
+        if let Some(classes) = &self.client_classes {
+            for class in classes.classes.values() {
+                boots.push(&class.boot);
+                opts.push(&class.options);
+            }
+        }
+        let bootfile_opts = opts.into_iter().filter_map(|opts| {
+            // [code][len][name]
+            let buf = opts.get(OptionCode::from(BOOTFILE_NAME))?.to_vec().ok()?;
+            let name = std::str::from_utf8(buf.get(2..)?).ok()?;
+            Some(name.trim_end_matches('\0').to_owned())
+        });
+        boots
+            .into_iter()
+            .flat_map(|boot| [boot.file_name.clone(), boot.ipxe_file_name.clone()])
+            .flatten()
+            .chain(bootfile_opts)
+            .filter(|name| !name.is_empty())
+            .collect()
     }
 
     /// get a `NetRange` within a subnet that contains the given IP & any matching client classes
@@ -233,6 +721,10 @@ impl Config {
     }
 
     /// get the first `Network`
+    /// all networks by subnet
+    pub fn networks(&self) -> &HashMap<Ipv4Net, Network> {
+        &self.networks
+    }
     pub fn get_first(&self) -> Option<(&Ipv4Net, &Network)> {
         self.networks.iter().next()
     }
@@ -269,25 +761,100 @@ pub struct Network {
     /// Reserved addresses based on opt
     /// Currently only support matching on a single option
     reserved_opts: HashMap<OptionCode, (DhcpOption, Reserved)>,
-    /// Will send an ICMP echo request to an IP before OFFER
-    /// Should this be a global configuration?
-    ping_check: bool,
-    ping_timeout_ms: Duration,
+    /// Reserved addresses based on an instance of the user class option
+    reserved_user_classes: HashMap<Vec<u8>, Reserved>,
+    /// Reserved addresses based on the machine uuid (option 97), hyphenated
+    reserved_uuids: HashMap<String, Reserved>,
+    /// manually addressed hosts answered on INFORM, by address
+    static_hosts: BTreeMap<Ipv4Addr, StaticHost>,
+    /// Will send an ICMP echo request to an IP before OFFER,
+    /// ranges may override this
+    ping: PingCheck,
     /// how long a DECLINE or ping check will be put on probation for
     probation_period: Duration,
+    /// how long an OFFER holds the address before it can be given to another client
+    offer_hold: Duration,
     /// with authoritative == true then dora will always try to respond
     /// to REQUEST/INFORM
     authoritative: bool,
-    server_name: Option<String>,
-    file_name: Option<String>,
+    /// siaddr, sname & file header fields
+    boot: Boot,
+    /// if set, client hostnames are tracked & conflicts resolved with this policy
+    hostname_conflict: Option<HostnamePolicy>,
+    /// if set, clients that don't send a hostname are registered with one from it
+    hostname_template: Option<HostnameTemplate>,
+    /// if set, the giaddrs relays are allowed to use for this network
+    allowed_relays: Option<HashSet<Ipv4Addr>>,
+    /// which failed REQUESTs are NAK'd
+    nak: NakPolicy,
+    /// option order & padding of replies
+    reply: ReplyEncoding,
+    /// which configured options are sent in replies
+    reply_opts: ReplyOptions,
+    /// options stripped or rewritten in replies, by where they're sent
+    reply_rules: ReplyRules,
+    /// send FORCERENEW nonces in ACKs
+    forcerenew_nonce: bool,
+    /// if set, leases are promoted to reservations after enough renewals
+    sticky: Option<Sticky>,
 }
 
 impl Network {
+    /// can a relay with this `giaddr` act for this network?
+    pub fn relay_allowed(&self, giaddr: Ipv4Addr) -> bool {
+        self.allowed_relays
+            .as_ref()
+            .map_or(true, |relays| relays.contains(&giaddr))
+    }
+    /// when to NAK a REQUEST
+    pub fn nak(&self) -> &NakPolicy {
+        &self.nak
+    }
+    /// how replies on this network are encoded
+    pub fn reply_encoding(&self) -> ReplyEncoding {
+        self.reply
+    }
+    /// which configured options are sent in replies on this network
+    pub fn reply_options(&self) -> &ReplyOptions {
+        &self.reply_opts
+    }
+    /// options stripped or rewritten in replies to relayed & direct requests
+    pub fn reply_rules(&self) -> &ReplyRules {
+        &self.reply_rules
+    }
+    /// are FORCERENEW nonces (RFC 6704) sent to capable clients?
+    pub fn forcerenew_nonce(&self) -> bool {
+        self.forcerenew_nonce
+    }
+    /// sticky lease settings, `None` if leases are never promoted by renewals
+    pub fn sticky(&self) -> Option<Sticky> {
+        self.sticky
+    }
+    /// is `ip` inside of this network's subnet?
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        self.subnet.contains(&ip)
+    }
+    /// the hostname conflict policy, `None` if hostnames are not tracked
+    pub fn hostname_conflict(&self) -> Option<HostnamePolicy> {
+        self.hostname_conflict
+    }
+    /// template of hostnames for clients without one, `None` if none are generated
+    pub fn hostname_template(&self) -> Option<&HostnameTemplate> {
+        self.hostname_template.as_ref()
+    }
+    /// next server (siaddr), `None` means the server id is used
+    pub fn next_server(&self) -> Option<Ipv4Addr> {
+        self.boot.next_server
+    }
     pub fn server_name(&self) -> Option<&str> {
-        self.server_name.as_deref()
+        self.boot.server_name.as_deref()
     }
     pub fn file_name(&self) -> Option<&str> {
-        self.file_name.as_deref()
+        self.boot.file_name.as_deref()
+    }
+    /// file sent to iPXE clients, `None` if they are sent `file_name`
+    pub fn ipxe_file_name(&self) -> Option<&str> {
+        self.boot.ipxe_file_name.as_deref()
     }
     pub fn subnet(&self) -> Ipv4Addr {
         self.subnet.network()
@@ -325,6 +892,35 @@ impl Network {
             _ => None,
         }
     }
+    /// find a reservation matching one of the user classes (option 77) in `opts`
+    pub fn get_reserved_user_class(&self, opts: &DhcpOptions) -> Option<&Reserved> {
+        if self.reserved_user_classes.is_empty() {
+            return None;
+        }
+        let buf = opts.get(OptionCode::from(USER_CLASS))?.to_vec().ok()?;
+        // [code][len][data]
+        client_classification::parse_user_classes(buf.get(2..)?)
+            .into_iter()
+            .find_map(|class| self.reserved_user_classes.get(class))
+    }
+    /// the static host configured with `ip` & hardware address `mac`
+    pub fn static_host(&self, ip: Ipv4Addr, mac: MacAddr) -> Option<&StaticHost> {
+        self.static_hosts.get(&ip).filter(|host| host.mac == mac)
+    }
+    /// all static hosts, ordered by address
+    pub fn static_hosts(&self) -> impl Iterator<Item = &StaticHost> {
+        self.static_hosts.values()
+    }
+    /// find a reservation matching the machine uuid (option 97) in `opts`
+    pub fn get_reserved_uuid(&self, opts: &DhcpOptions) -> Option<&Reserved> {
+        if self.reserved_uuids.is_empty() {
+            return None;
+        }
+        let buf = opts.get(OptionCode::from(CLIENT_UUID))?.to_vec().ok()?;
+        // [code][len][data]
+        let uuid = client_classification::client_uuid(buf.get(2..)?)?;
+        self.reserved_uuids.get(&uuid)
+    }
     /// Given some `opts`, search to see if there is a match with a reservation
     /// client classes must also match
     pub fn search_reserved_opt(
@@ -332,6 +928,16 @@ impl Network {
         opts: &DhcpOptions,
         classes: Option<&[String]>,
     ) -> Option<&Reserved> {
+        if let Some(res) = self.get_reserved_uuid(opts) {
+            if res.match_class(classes) {
+                return Some(res);
+            }
+        }
+        if let Some(res) = self.get_reserved_user_class(opts) {
+            if res.match_class(classes) {
+                return Some(res);
+            }
+        }
         for (_, opt) in opts.iter() {
             if let Some(res) = self.get_reserved_opt(opt) {
                 if res.match_class(classes) {
@@ -354,23 +960,85 @@ impl Network {
         // if classes exist, look for a range that contains `ip` and matching class
         self.ranges.iter().find(|r| r.contains_class(&ip, classes))
     }
-    /// is ping check enabled for this range? should we ping an IP before offering?
+    /// is ping check enabled for this network? should we ping an IP before offering?
+    /// Ranges may override this, see [`NetRange::ping`]
     pub fn ping_check(&self) -> bool {
-        self.ping_check
+        self.ping.enabled
     }
     /// get the ping timeout
     pub fn ping_timeout(&self) -> Duration {
-        self.ping_timeout_ms
+        self.ping.timeout
+    }
+    /// the network's ping check settings
+    pub fn ping(&self) -> PingCheck {
+        self.ping
     }
     /// Returns the configured probation period for decline's received on this network
     pub fn probation_period(&self) -> Duration {
         self.probation_period
     }
+    /// how long an OFFER reserves the address while waiting for REQUEST
+    pub fn offer_hold(&self) -> Duration {
+        self.offer_hold
+    }
     pub fn total_addrs(&self) -> usize {
         self.ranges.iter().map(|range| range.total_addrs()).sum()
     }
 }
 
+/// ping check settings for a network or range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PingCheck {
+    enabled: bool,
+    timeout: Duration,
+    /// extra echo requests sent after the first times out
+    retries: u32,
+}
+
+impl Default for PingCheck {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout: Duration::from_millis(wire::default_ping_to()),
+            retries: 0,
+        }
+    }
+}
+
+impl PingCheck {
+    /// should we ping an IP before offering?
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+    /// how long to wait for each echo reply
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    /// echo requests sent after the first times out
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+}
+
+/// sticky lease settings for a network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sticky {
+    after_renewals: Option<u32>,
+    hold: Duration,
+}
+
+impl Sticky {
+    /// renewals of the same address before a lease is promoted, `None` if
+    /// leases are only promoted through the external api
+    pub fn after_renewals(&self) -> Option<u32> {
+        self.after_renewals
+    }
+    /// how long the address is held after the client's last lease
+    pub fn hold(&self) -> Duration {
+        self.hold
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct NetRange {
     addrs: RangeInclusive<Ipv4Addr>,
@@ -379,8 +1047,37 @@ pub struct NetRange {
     /// a lease time
     lease: LeaseTime,
     opts: DhcpOptions,
-    exclude: HashSet<Ipv4Addr>,
+    exclude: Exclusions,
     class: Option<String>,
+    /// option 60 substring matched by the range's generated class
+    vendor_class: Option<String>,
+    /// config used while a schedule is active, first match wins
+    scheduled: Vec<Scheduled>,
+    /// network ping settings with the range's overrides applied
+    ping: PingCheck,
+    /// boot header fields, on top of the network's
+    boot: Boot,
+    /// when a requested address in this range is offered
+    hints: HintPolicy,
+}
+
+/// range config that applies while `schedule` is active
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheduled {
+    schedule: Schedule,
+    lease: Option<LeaseTime>,
+    /// range options with the scheduled options applied
+    opts: DhcpOptions,
+    closed: bool,
+}
+
+impl Scheduled {
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+    pub fn closed(&self) -> bool {
+        self.closed
+    }
 }
 
 impl NetRange {
@@ -389,10 +1086,45 @@ impl NetRange {
             addrs,
             lease,
             opts: DhcpOptions::default(),
-            exclude: HashSet::default(),
+            exclude: Exclusions::default(),
             class: None,
+            vendor_class: None,
+            scheduled: Vec::new(),
+            ping: PingCheck::default(),
+            boot: Boot::default(),
+            hints: HintPolicy::default(),
         }
     }
+    /// ping check settings for addresses in this range
+    pub fn ping(&self) -> PingCheck {
+        self.ping
+    }
+    /// when a DISCOVER's requested address in this range is offered
+    pub fn hints(&self) -> HintPolicy {
+        self.hints
+    }
+    /// boot header fields set on this range
+    pub fn boot(&self) -> &Boot {
+        &self.boot
+    }
+    /// the scheduled config active at `now`, if any
+    pub fn scheduled(&self, now: DateTime<Utc>) -> Option<&Scheduled> {
+        self.scheduled.iter().find(|s| s.schedule.contains(now))
+    }
+    /// lease time at `now`, taking schedules into account
+    pub fn lease_at(&self, now: DateTime<Utc>) -> LeaseTime {
+        self.scheduled(now)
+            .and_then(|s| s.lease)
+            .unwrap_or(self.lease)
+    }
+    /// options at `now`, taking schedules into account
+    pub fn opts_at(&self, now: DateTime<Utc>) -> &DhcpOptions {
+        self.scheduled(now).map(|s| &s.opts).unwrap_or(&self.opts)
+    }
+    /// is the range closed to new allocations at `now`
+    pub fn is_closed_at(&self, now: DateTime<Utc>) -> bool {
+        self.scheduled(now).map(|s| s.closed).unwrap_or(false)
+    }
     /// get the range of IPs this range offers
     pub fn addrs(&self) -> RangeInclusive<Ipv4Addr> {
         self.addrs.clone()
@@ -437,13 +1169,10 @@ impl NetRange {
     }
     /// return an iterator over the range
     pub fn iter(&self) -> NetRangeIter<'_> {
-        NetRangeIter {
-            exclusions: &self.exclude,
-            iter: Ipv4AddrRange::new(self.start(), self.end()),
-        }
+        NetRangeIter::new(self.addrs(), &self.exclude)
     }
-    /// returns a set of excluded ipv4 addrs
-    pub fn exclusions(&self) -> &HashSet<Ipv4Addr> {
+    /// returns the excluded ipv4 addrs
+    pub fn exclusions(&self) -> &Exclusions {
         &self.exclude
     }
     /// count the total number of addresses that could possibly be
@@ -457,32 +1186,140 @@ impl NetRange {
     }
 }
 
+/// addresses excluded from a range, kept as sorted, non-overlapping blocks
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Exclusions(Vec<RangeInclusive<u32>>);
+
+impl Exclusions {
+    /// exclude every address in `blocks`, they may overlap
+    pub fn new<I>(blocks: I) -> Self
+    where
+        I: IntoIterator<Item = RangeInclusive<Ipv4Addr>>,
+    {
+        let mut blocks = blocks
+            .into_iter()
+            .map(|block| u32::from(*block.start())..=u32::from(*block.end()))
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<_>>();
+        blocks.sort_unstable_by_key(|block| *block.start());
+        let mut merged: Vec<RangeInclusive<u32>> = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            match merged.last_mut() {
+                // overlapping or adjacent
+                Some(last) if *block.start() <= last.end().saturating_add(1) => {
+                    *last = *last.start()..=*last.end().max(block.end());
+                }
+                _ => merged.push(block),
+            }
+        }
+        Self(merged)
+    }
+    /// these exclusions & `ips`
+    pub fn with<I>(&self, ips: I) -> Self
+    where
+        I: IntoIterator<Item = Ipv4Addr>,
+    {
+        Self::new(self.blocks().chain(ips.into_iter().map(|ip| ip..=ip)))
+    }
+    /// the excluded blocks, in order
+    pub fn blocks(&self) -> impl Iterator<Item = RangeInclusive<Ipv4Addr>> + '_ {
+        self.0
+            .iter()
+            .map(|block| Ipv4Addr::from(*block.start())..=Ipv4Addr::from(*block.end()))
+    }
+    /// is `ip` excluded
+    pub fn contains(&self, ip: &Ipv4Addr) -> bool {
+        self.block(u32::from(*ip)).is_some()
+    }
+    /// number of excluded addresses
+    pub fn len(&self) -> usize {
+        self.0
+            .iter()
+            .map(|block| (block.end() - block.start()) as usize + 1)
+            .sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    /// number of excluded addresses between `start` & `end`
+    fn count(&self, start: u32, end: u32) -> usize {
+        self.0
+            .iter()
+            .filter(|block| *block.start() <= end && *block.end() >= start)
+            .map(|block| (*block.end().min(&end) - *block.start().max(&start)) as usize + 1)
+            .sum()
+    }
+    /// the excluded block holding `ip`
+    fn block(&self, ip: u32) -> Option<&RangeInclusive<u32>> {
+        let i = self.0.partition_point(|block| *block.end() < ip);
+        self.0.get(i).filter(|block| *block.start() <= ip)
+    }
+}
+
+impl FromIterator<Ipv4Addr> for Exclusions {
+    fn from_iter<I: IntoIterator<Item = Ipv4Addr>>(ips: I) -> Self {
+        Self::new(ips.into_iter().map(|ip| ip..=ip))
+    }
+}
+
+/// the addresses of a range that aren't excluded, in order
 #[derive(Debug)]
 pub struct NetRangeIter<'a> {
-    exclusions: &'a HashSet<Ipv4Addr>,
-    iter: Ipv4AddrRange,
+    exclusions: &'a Exclusions,
+    /// `None` once the range is done
+    next: Option<u32>,
+    end: u32,
 }
 
 impl<'a> NetRangeIter<'a> {
-    pub fn new(iter: Ipv4AddrRange, exclusions: &'a HashSet<Ipv4Addr>) -> Self {
-        Self { iter, exclusions }
+    pub fn new(addrs: RangeInclusive<Ipv4Addr>, exclusions: &'a Exclusions) -> Self {
+        let (start, end) = (u32::from(*addrs.start()), u32::from(*addrs.end()));
+        Self {
+            exclusions,
+            next: (start <= end).then_some(start),
+            end,
+        }
     }
 }
 
 impl<'a> Iterator for NetRangeIter<'a> {
     type Item = Ipv4Addr;
 
-    // skips any IPs in exclusions
+    // skips over excluded blocks
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let next = self.iter.next()?;
-            if !self.exclusions.contains(&next) {
-                return Some(next);
-            }
+        let mut next = self.next?;
+        if let Some(block) = self.exclusions.block(next) {
+            next = block.end().checked_add(1).filter(|ip| *ip <= self.end)?;
         }
+        self.next = next.checked_add(1).filter(|ip| *ip <= self.end);
+        Some(Ipv4Addr::from(next))
     }
     fn count(self) -> usize {
-        self.iter.count() - self.exclusions.len()
+        match self.next {
+            Some(next) => (self.end - next) as usize + 1 - self.exclusions.count(next, self.end),
+            None => 0,
+        }
+    }
+}
+
+/// a manually addressed host, it only sends INFORM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticHost {
+    ip: Ipv4Addr,
+    mac: MacAddr,
+    /// the host's options on top of the network's
+    opts: DhcpOptions,
+}
+
+impl StaticHost {
+    pub fn ip(&self) -> Ipv4Addr {
+        self.ip
+    }
+    pub fn mac(&self) -> MacAddr {
+        self.mac
+    }
+    pub fn opts(&self) -> &DhcpOptions {
+        &self.opts
     }
 }
 
@@ -496,6 +1333,7 @@ pub struct Reserved {
     lease: LeaseTime,
     opts: DhcpOptions,
     class: Option<String>,
+    boot: Boot,
 }
 
 impl Reserved {
@@ -516,6 +1354,10 @@ impl Reserved {
     pub fn class(&self) -> Option<&str> {
         self.class.as_deref()
     }
+    /// boot header fields set on this reservation
+    pub fn boot(&self) -> &Boot {
+        &self.boot
+    }
     /// given a list of matched classes, determine if this reservation has a match
     ///         if reservation has no class, this expression is always true
     /// if reservation has a class, it must match an entry in the list
@@ -532,20 +1374,163 @@ impl Reserved {
     }
 }
 
-impl From<wire::v4::IpRange> for NetRange {
-    fn from(range: wire::v4::IpRange) -> Self {
+impl NetRange {
+    /// convert from the wire format, looking up any referenced schedules
+    fn from_wire(
+        range: wire::v4::IpRange,
+        schedules: &HashMap<String, Schedule>,
+        network_ping: PingCheck,
+    ) -> Result<Self> {
+        let ping = PingCheck {
+            enabled: range.ping_check.unwrap_or(network_ping.enabled),
+            timeout: range
+                .ping_timeout_ms
+                .map_or(network_ping.timeout, Duration::from_millis),
+            retries: range.ping_retries.unwrap_or(network_ping.retries),
+        };
+        anyhow::ensure!(
+            range.vendor_class.as_deref() != Some(""),
+            "range {:?}: `vendor_class` is empty",
+            range.range
+        );
+        let exclude = exclusions(&range);
+        let class = match (range.class, &range.vendor_class) {
+            (Some(_), Some(_)) => anyhow::bail!(
+                "range {:?} sets both `class` & `vendor_class`, only one can be used",
+                range.range
+            ),
+            (class, None) => class,
+            (None, Some(vendor)) => Some(vendor_class_name(vendor)),
+        };
         let lease = range.config.lease_time.into();
         let opts = range.options.get();
-        NetRange {
+        let scheduled = range
+            .schedule
+            .into_iter()
+            .map(|cfg| {
+                let schedule = schedules
+                    .get(&cfg.name)
+                    .with_context(|| format!("range references unknown schedule {}", cfg.name))?
+                    .clone();
+                Ok(Scheduled {
+                    schedule,
+                    lease: cfg.lease_time.map(LeaseTime::from),
+                    opts: inherit_opts(&opts, cfg.options),
+                    closed: cfg.closed,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(NetRange {
             addrs: range.range,
             opts,
             lease,
-            exclude: range.except.into_iter().collect(),
-            class: range.class,
+            exclude,
+            class,
+            vendor_class: range.vendor_class,
+            scheduled,
+            ping,
+            boot: range.boot,
+            hints: range.honor_hints,
+        })
+    }
+}
+
+/// the client class a range's `vendor_class` generates, clients whose vendor
+/// class identifier (option 60) contains `vendor` are in it
+pub fn vendor_class_name(vendor: &str) -> String {
+    format!("vendor_class:{vendor}")
+}
+
+/// whether the client is running iPXE, it sends option 175 or the `iPXE` user class
+pub fn is_ipxe(opts: &DhcpOptions) -> bool {
+    if opts.get(OptionCode::from(IPXE_ENCAP)).is_some() {
+        return true;
+    }
+    // [code][len][data]
+    let Some(buf) = opts
+        .get(OptionCode::from(USER_CLASS))
+        .and_then(|opt| opt.to_vec().ok())
+    else {
+        return false;
+    };
+    buf.get(2..).map_or(false, |data| {
+        client_classification::parse_user_classes(data).contains(&&b"iPXE"[..])
+    })
+}
+
+/// a reservation's `uuid`, with or without hyphens, in the form option 97 is matched in
+fn parse_uuid(uuid: &str) -> Result<String> {
+    let bytes = hex::decode(uuid.replace('-', "")).context("uuid isn't hex")?;
+    let bytes = <[u8; 16]>::try_from(bytes)
+        .ok()
+        .context("uuid isn't 16 bytes")?;
+    Ok(client_classification::format_uuid(&bytes))
+}
+
+/// `id` with the rewrites in `norm` applied
+fn normalize_client_id<'a>(norm: &ClientIdNormalize, id: &'a [u8]) -> Cow<'a, [u8]> {
+    match id.split_first() {
+        // RFC 4361 node-specific id, [255][iaid: 4][duid]
+        Some((&255, rest)) if rest.len() > 4 => {
+            let duid = &rest[4..];
+            let keyed_by_duid = || Cow::Owned([&[255][..], duid].concat());
+            match norm.node_specific {
+                NodeSpecific::Keep => Cow::Borrowed(id),
+                NodeSpecific::Duid => keyed_by_duid(),
+                NodeSpecific::LinkLayer => duid_link_layer(duid)
+                    .map(Cow::Borrowed)
+                    .unwrap_or_else(keyed_by_duid),
+            }
+        }
+        Some((&0, name)) if norm.case_fold => {
+            Cow::Owned([&[0][..], name.to_ascii_lowercase().as_slice()].concat())
         }
+        Some((&(1..=254), hw)) if norm.strip_hw_type && !hw.is_empty() => Cow::Borrowed(hw),
+        _ => Cow::Borrowed(id),
     }
 }
 
+/// the link-layer address of a DUID-LLT (type 1) or DUID-LL (type 3)
+fn duid_link_layer(duid: &[u8]) -> Option<&[u8]> {
+    match duid {
+        [0, 1, _, _, _, _, _, _, ll @ ..] | [0, 3, _, _, ll @ ..] if !ll.is_empty() => Some(ll),
+        _ => None,
+    }
+}
+
+/// the first of `ips` whose subnet contains `ip`
+fn attached(ips: &[Ipv4Network], ip: Ipv4Addr) -> Option<Ipv4Network> {
+    ips.iter().find(|int| int.contains(ip)).copied()
+}
+
+/// `except` & `reserve_first_n`/`reserve_last_n` as excluded blocks, only
+/// addresses inside of the range are kept
+fn exclusions(range: &wire::v4::IpRange) -> Exclusions {
+    let (start, end) = (
+        u32::from(*range.range.start()),
+        u32::from(*range.range.end()),
+    );
+    let mut blocks = Vec::new();
+    for except in &range.except {
+        let (first, last) = match except {
+            wire::v4::Exclusion::Ip(ip) => (*ip, *ip),
+            wire::v4::Exclusion::Net(net) => (net.network(), net.broadcast()),
+        };
+        blocks.push(u32::from(first).max(start)..=u32::from(last).min(end));
+    }
+    if let Some(n) = range.reserve_first_n.filter(|n| *n > 0) {
+        blocks.push(start..=start.saturating_add(n - 1).min(end));
+    }
+    if let Some(n) = range.reserve_last_n.filter(|n| *n > 0) {
+        blocks.push(end.saturating_sub(n - 1).max(start)..=end);
+    }
+    Exclusions::new(
+        blocks
+            .into_iter()
+            .map(|block| Ipv4Addr::from(*block.start())..=Ipv4Addr::from(*block.end())),
+    )
+}
+
 impl From<&wire::v4::ReservedIp> for Reserved {
     fn from(res: &wire::v4::ReservedIp) -> Self {
         let lease = res.config.lease_time.into();
@@ -554,10 +1539,44 @@ impl From<&wire::v4::ReservedIp> for Reserved {
             ip: res.ip,
             opts: res.options.as_ref().clone(),
             class: res.class.clone(),
+            boot: res.boot.clone(),
         }
     }
 }
 
+/// apply `child` on top of the `parent` options. Values in `child` override
+/// the parent, and any codes in `child.remove` are dropped from the result
+fn inherit_opts(parent: &DhcpOptions, child: wire::v4::Options) -> DhcpOptions {
+    let mut opts = parent.clone();
+    for (_, opt) in child.values.0.into_iter() {
+        opts.insert(opt);
+    }
+    for code in child.remove {
+        opts.remove(OptionCode::from(code));
+    }
+    opts
+}
+
+fn inherited(parent: &DhcpOptions, child: wire::v4::Options) -> wire::v4::Options {
+    wire::v4::Options {
+        profiles: Vec::new(),
+        values: wire::v4::Opts(inherit_opts(parent, child)),
+        remove: Vec::new(),
+    }
+}
+
+/// `rules` with option codes converted, the message type can't be changed
+fn option_rules(rules: &wire::v4::OptionRules) -> Result<OptionRules> {
+    let msg_type = u8::from(OptionCode::MessageType);
+    if rules.remove.contains(&msg_type) || rules.set.0.get(OptionCode::MessageType).is_some() {
+        bail!("the message type (option {msg_type}) can't be removed or set");
+    }
+    Ok(OptionRules {
+        remove: rules.remove.iter().copied().map(OptionCode::from).collect(),
+        set: rules.set.0.iter().map(|(_, opt)| opt.clone()).collect(),
+    })
+}
+
 /// merge `b` into `a`, favoring `a` where there are duplicates
 fn merge_opts(mut a: DhcpOptions, b: Option<DhcpOptions>) -> DhcpOptions {
     match b {
@@ -576,11 +1595,20 @@ fn merge_opts(mut a: DhcpOptions, b: Option<DhcpOptions>) -> DhcpOptions {
 #[cfg(test)]
 mod tests {
     use dora_core::dhcproto::v4;
+    use ipnet::Ipv4AddrRange;
 
     use super::*;
 
     pub static SAMPLE_YAML: &str = include_str!("../sample/config.yaml");
     pub static CIRC_YAML: &str = include_str!("../sample/circular_deps.yaml");
+    pub static INHERIT_YAML: &str = include_str!("../sample/inherit_opts.yaml");
+    pub static PROFILES_YAML: &str = include_str!("../sample/option_profiles.yaml");
+    pub static SCHEDULE_YAML: &str = include_str!("../sample/schedule.yaml");
+    pub static EXCLUSIONS_YAML: &str = include_str!("../sample/exclusions.yaml");
+    pub static RELAY_MAP_YAML: &str = include_str!("../sample/relay_map.yaml");
+    pub static PING_YAML: &str = include_str!("../sample/ping.yaml");
+    pub static BOOT_YAML: &str = include_str!("../sample/boot.yaml");
+    pub static SECONDARY_YAML: &str = include_str!("../sample/secondary_subnets.yaml");
 
     // test we can decode from wire
     #[test]
@@ -597,6 +1625,599 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_inherit_opts() {
+        let cfg = Config::new(INHERIT_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        // range: network overrides global, option 6 explicitly removed
+        let opts = net.ranges()[0].opts();
+        assert_eq!(
+            opts.get(v4::OptionCode::DomainName),
+            Some(&v4::DhcpOption::DomainName("net.local".to_owned()))
+        );
+        assert_eq!(
+            opts.get(v4::OptionCode::Router),
+            Some(&v4::DhcpOption::Router(vec![Ipv4Addr::new(192, 168, 0, 1)]))
+        );
+        assert!(opts.get(v4::OptionCode::SubnetMask).is_some());
+        assert!(opts.get(v4::OptionCode::DomainNameServer).is_none());
+        // reservation has no options of its own, inherits everything
+        let res = net
+            .get_reserved_mac(MacAddr::new(0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff), None)
+            .unwrap();
+        assert_eq!(
+            res.opts().get(v4::OptionCode::DomainNameServer),
+            Some(&v4::DhcpOption::DomainNameServer(vec![Ipv4Addr::new(
+                1, 1, 1, 1
+            )]))
+        );
+    }
+
+    #[test]
+    fn test_option_profiles() {
+        let cfg = Config::new(PROFILES_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        // range: voip includes dns, network options are still inherited
+        let opts = net.ranges()[0].opts();
+        assert_eq!(
+            opts.get(v4::OptionCode::DomainName),
+            Some(&v4::DhcpOption::DomainName("voip.local".to_owned()))
+        );
+        assert_eq!(
+            opts.get(v4::OptionCode::DomainNameServer),
+            Some(&v4::DhcpOption::DomainNameServer(vec![Ipv4Addr::new(
+                1, 1, 1, 1
+            )]))
+        );
+        assert!(opts.get(v4::OptionCode::from(66)).is_some());
+        assert!(opts.get(v4::OptionCode::SubnetMask).is_some());
+        assert!(opts.get(v4::OptionCode::Router).is_some());
+        let res = net
+            .get_reserved_mac(MacAddr::new(0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff), None)
+            .unwrap();
+        assert_eq!(
+            res.opts().get(v4::OptionCode::DomainName),
+            Some(&v4::DhcpOption::DomainName("corp.local".to_owned()))
+        );
+        assert!(res.opts().get(v4::OptionCode::from(66)).is_none());
+        // referencing an unknown profile is an error
+        assert!(Config::new(PROFILES_YAML.replace("- dns", "- nope")).is_err());
+    }
+
+    #[test]
+    fn test_schedule() {
+        use dora_core::chrono::TimeZone;
+
+        let cfg = Config::new(SCHEDULE_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        let (day, night) = (
+            Utc.with_ymd_and_hms(2023, 3, 1, 12, 0, 0).unwrap(),
+            Utc.with_ymd_and_hms(2023, 3, 1, 23, 0, 0).unwrap(),
+        );
+        let range = &net.ranges()[0];
+        assert_eq!(range.lease_at(day).default, Duration::from_secs(3600));
+        assert_eq!(range.lease_at(night).default, Duration::from_secs(900));
+        assert!(range
+            .opts_at(day)
+            .get(v4::OptionCode::DomainNameServer)
+            .is_none());
+        // scheduled opts are applied on top of range opts
+        let opts = range.opts_at(night);
+        assert!(opts.get(v4::OptionCode::DomainNameServer).is_some());
+        assert!(opts.get(v4::OptionCode::Router).is_some());
+
+        let closed = &net.ranges()[1];
+        assert!(!closed.is_closed_at(day));
+        assert!(closed.is_closed_at(night));
+    }
+
+    #[test]
+    fn test_relay_map() {
+        let cfg = Config::new(RELAY_MAP_YAML).unwrap();
+        // single giaddr
+        let net = cfg.network([10, 0, 0, 1]).unwrap();
+        assert_eq!(net.subnet(), Ipv4Addr::new(192, 168, 1, 0));
+        // longest match wins over the /16
+        let net = cfg.network([10, 1, 2, 3]).unwrap();
+        assert_eq!(net.subnet(), Ipv4Addr::new(192, 168, 1, 0));
+        let net = cfg.network([10, 1, 0, 1]).unwrap();
+        assert_eq!(net.subnet(), Ipv4Addr::new(192, 168, 0, 0));
+        // giaddr inside of a subnet still works
+        let net = cfg.network([192, 168, 1, 1]).unwrap();
+        assert_eq!(net.subnet(), Ipv4Addr::new(192, 168, 1, 0));
+        assert!(cfg.network([10, 2, 0, 1]).is_none());
+    }
+
+    #[test]
+    fn test_relay_map_unknown_network() {
+        let yaml = RELAY_MAP_YAML.replace("network: 192.168.0.0/24", "network: 172.16.0.0/24");
+        assert!(Config::new(yaml).is_err());
+    }
+
+    #[test]
+    fn test_wire_default() {
+        assert_eq!(
+            serde_yaml::from_str::<wire::Config>("{}").unwrap(),
+            wire::Config::default()
+        );
+    }
+
+    #[test]
+    fn test_secondary_subnets() {
+        let cfg = Config::new(SECONDARY_YAML).unwrap();
+        // one interface with an address on each subnet
+        let ips: Vec<Ipv4Network> = vec![
+            "192.168.0.1/24".parse().unwrap(),
+            "10.0.0.1/24".parse().unwrap(),
+        ];
+        // renewing from each subnet selects that subnet's address & network
+        for (ciaddr, router) in [
+            (
+                Ipv4Addr::new(192, 168, 0, 150),
+                Ipv4Addr::new(192, 168, 0, 1),
+            ),
+            (Ipv4Addr::new(10, 0, 0, 150), Ipv4Addr::new(10, 0, 0, 1)),
+        ] {
+            let int = attached(&ips, ciaddr).unwrap();
+            assert_eq!(int.ip(), router);
+            let net = cfg.network(int.ip()).unwrap();
+            assert_eq!(net.subnet(), int.network());
+            assert_eq!(
+                net.range(ciaddr, None)
+                    .unwrap()
+                    .opts()
+                    .get(v4::OptionCode::Router),
+                Some(&v4::DhcpOption::Router(vec![router]))
+            );
+        }
+        // not on any of the interface's subnets
+        assert!(attached(&ips, Ipv4Addr::new(172, 16, 0, 10)).is_none());
+    }
+
+    #[test]
+    fn test_range_ping() {
+        let cfg = Config::new(PING_YAML).unwrap();
+        assert_eq!(
+            cfg.run_as(),
+            Some(&RunAs {
+                user: "dora".to_owned(),
+                group: None,
+            })
+        );
+        assert_eq!(
+            cfg.ping_source(),
+            &PingSource {
+                address: Some(Ipv4Addr::new(192, 168, 0, 1)),
+                interface: Some("eth0".to_owned()),
+            }
+        );
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        let ranges = net.ranges();
+        // inherited from the network
+        assert_eq!(ranges[0].ping(), net.ping());
+        assert!(ranges[0].ping().enabled());
+        assert_eq!(ranges[0].ping().timeout(), Duration::from_millis(200));
+        assert_eq!(ranges[0].ping().retries(), 1);
+        // disabled for this range only
+        assert!(!ranges[1].ping().enabled());
+        assert_eq!(ranges[1].ping().timeout(), Duration::from_millis(200));
+        // timeout & retries overridden
+        assert!(ranges[2].ping().enabled());
+        assert_eq!(ranges[2].ping().timeout(), Duration::from_millis(1000));
+        assert_eq!(ranges[2].ping().retries(), 3);
+    }
+
+    #[test]
+    fn test_reply_encoding() {
+        let reply = |min_size: u16| {
+            EXCLUSIONS_YAML.replace(
+                "server_id: 192.168.0.1",
+                &format!(
+                    "server_id: 192.168.0.1\n        reply:\n            option_order: request_list\n            min_size: {min_size}"
+                ),
+            )
+        };
+        let cfg = Config::new(reply(300)).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(
+            net.reply_encoding(),
+            ReplyEncoding {
+                order: OptionOrder::RequestList,
+                min_size: Some(300),
+                max_size: None,
+            }
+        );
+        assert!(Config::new(reply(2_000)).is_err());
+        // defaults to dhcproto's encoding
+        let cfg = Config::new(EXCLUSIONS_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(net.reply_encoding(), ReplyEncoding::default());
+    }
+
+    #[test]
+    fn test_reply_options() {
+        let reply = |reply: &str| {
+            Config::new(EXCLUSIONS_YAML.replace(
+                "server_id: 192.168.0.1",
+                &format!("server_id: 192.168.0.1\n        reply:\n{reply}"),
+            ))
+        };
+        let cfg = reply("            options: mandatory\n            mandatory: [3, 6]").unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(
+            net.reply_options(),
+            &ReplyOptions::Mandatory(vec![OptionCode::Router, OptionCode::DomainNameServer])
+        );
+        let cfg = reply("            options: all").unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(net.reply_options(), &ReplyOptions::All);
+        // the mandatory list does nothing without `options: mandatory`
+        assert!(reply("            mandatory: [3]").is_err());
+        let cfg = Config::new(EXCLUSIONS_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(net.reply_options(), &ReplyOptions::Requested);
+    }
+
+    #[test]
+    fn test_reply_rules() {
+        let reply = |reply: &str| {
+            Config::new(EXCLUSIONS_YAML.replace(
+                "server_id: 192.168.0.1",
+                &format!("server_id: 192.168.0.1\n        reply:\n{reply}"),
+            ))
+        };
+        let cfg = reply(
+            "            relayed:
+                remove: [82]
+            direct:
+                remove: [3]
+                set:
+                    15:
+                        type: str
+                        value: local.example.com",
+        )
+        .unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(
+            net.reply_rules(),
+            &ReplyRules {
+                relayed: OptionRules {
+                    remove: vec![OptionCode::RelayAgentInformation],
+                    set: vec![],
+                },
+                direct: OptionRules {
+                    remove: vec![OptionCode::Router],
+                    set: vec![DhcpOption::DomainName("local.example.com".into())],
+                },
+            }
+        );
+        // every reply needs its message type
+        assert!(reply("            direct:\n                remove: [53]").is_err());
+        let cfg = Config::new(EXCLUSIONS_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(net.reply_rules(), &ReplyRules::default());
+    }
+
+    #[test]
+    fn test_boot() {
+        let cfg = Config::new(BOOT_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(net.next_server(), Some(Ipv4Addr::new(192, 168, 0, 2)));
+        assert_eq!(net.server_name(), Some("tftp.example.org"));
+        assert_eq!(net.file_name(), Some("pxelinux.0"));
+        assert_eq!(net.ipxe_file_name(), Some("http://192.168.0.2/boot.ipxe"));
+        let ranges = net.ranges();
+        let opts = DhcpOptions::default();
+        // nothing set on the range or a class, network values stay in the header
+        assert_eq!(cfg.boot(ranges[0].boot(), None, &opts), Boot::default());
+
+        let classes = ["ipxe".to_owned(), "uefi".to_owned()];
+        // fields are taken from the first class that sets them
+        assert_eq!(
+            cfg.boot(ranges[0].boot(), Some(&classes), &opts),
+            Boot {
+                next_server: Some(Ipv4Addr::new(192, 168, 0, 6)),
+                server_name: Some("uefi.example.org".to_owned()),
+                file_name: Some("boot.ipxe".to_owned()),
+                ipxe_file_name: None,
+            }
+        );
+        // range values win over classes
+        let boot = cfg.boot(ranges[1].boot(), Some(&classes[1..]), &opts);
+        assert_eq!(boot.next_server, Some(Ipv4Addr::new(192, 168, 0, 3)));
+        assert_eq!(boot.file_name.as_deref(), Some("ipxe.efi"));
+
+        let res = net
+            .get_reserved_mac(MacAddr::new(0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff), None)
+            .unwrap();
+        let boot = cfg.boot(res.boot(), None, &opts);
+        assert_eq!(boot.file_name.as_deref(), Some("reserved.efi"));
+        assert_eq!(boot.next_server, None);
+
+        let mut msg = v4::Message::default();
+        boot.apply(&mut msg);
+        assert_eq!(msg.fname().unwrap(), b"reserved.efi");
+
+        // once the ROM has chainloaded iPXE it's sent the script instead
+        let mut ipxe_opts = DhcpOptions::new();
+        ipxe_opts.insert(DhcpOption::Unknown(v4::UnknownOption::new(
+            77.into(),
+            b"iPXE".to_vec(),
+        )));
+        let boot = cfg.boot(res.boot(), None, &ipxe_opts);
+        assert_eq!(boot.file_name.as_deref(), Some("reserved.ipxe"));
+        // no `ipxe_file_name` set, `file_name` is kept
+        let boot = cfg.boot(ranges[1].boot(), Some(&classes[1..]), &ipxe_opts);
+        assert_eq!(boot.file_name.as_deref(), Some("ipxe.efi"));
+    }
+
+    #[test]
+    fn test_static_hosts() {
+        let hosts = |ip: &str| {
+            format!(
+                "        static_hosts:\n            -\n                ip: {ip}\n                mac: aa:bb:cc:dd:ee:ff\n                options:\n                    values:\n                        3:\n                            type: ip\n                            value: 192.168.0.254\n"
+            )
+        };
+        let cfg = Config::new(format!("{BOOT_YAML}{}", hosts("192.168.0.250"))).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        let mac = MacAddr::new(0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff);
+        let host = net.static_host([192, 168, 0, 250].into(), mac).unwrap();
+        assert_eq!(
+            host.opts().get(OptionCode::Router),
+            Some(&DhcpOption::Router(vec![Ipv4Addr::new(192, 168, 0, 254)]))
+        );
+        // the mac must match too
+        assert!(net
+            .static_host([192, 168, 0, 250].into(), MacAddr::zero())
+            .is_none());
+        assert_eq!(net.static_hosts().count(), 1);
+
+        // inside a range, or outside of the network
+        assert!(Config::new(format!("{BOOT_YAML}{}", hosts("192.168.0.20"))).is_err());
+        assert!(Config::new(format!("{BOOT_YAML}{}", hosts("10.0.0.1"))).is_err());
+    }
+
+    #[test]
+    fn test_reserved_uuid() {
+        let res = Reserved {
+            ip: [192, 168, 0, 130].into(),
+            lease: LeaseTime {
+                default: Duration::from_secs(5),
+                min: Duration::from_secs(3),
+                max: Duration::from_secs(10),
+            },
+            opts: DhcpOptions::default(),
+            class: None,
+            boot: Boot::default(),
+        };
+        let uuid = parse_uuid("4C4C4544004235108052B4C04F4E3032").unwrap();
+        assert_eq!(uuid, "4c4c4544-0042-3510-8052-b4c04f4e3032");
+        assert!(parse_uuid("4c4c4544-0042-3510-8052").is_err());
+        let mut reserved_uuids = HashMap::new();
+        reserved_uuids.insert(uuid, res);
+        let net = Network {
+            subnet: "192.168.0.0/24".parse().unwrap(),
+            reserved_uuids,
+            ..Default::default()
+        };
+        let mut data = vec![0];
+        data.extend(hex::decode("4c4c4544004235108052b4c04f4e3032").unwrap());
+        let mut opts = DhcpOptions::new();
+        opts.insert(DhcpOption::ClientMachineIdentifier(data));
+        let res = net.search_reserved_opt(&opts, None).unwrap();
+        assert_eq!(res.ip, Ipv4Addr::new(192, 168, 0, 130));
+
+        let mut opts = DhcpOptions::new();
+        opts.insert(DhcpOption::ClientMachineIdentifier(vec![0; 17]));
+        assert!(net.search_reserved_opt(&opts, None).is_none());
+    }
+
+    #[test]
+    fn test_is_ipxe() {
+        let mut opts = DhcpOptions::new();
+        assert!(!is_ipxe(&opts));
+        // RFC 3004 encoded user classes
+        opts.insert(DhcpOption::Unknown(v4::UnknownOption::new(
+            77.into(),
+            b"\x04gPXE\x04iPXE".to_vec(),
+        )));
+        assert!(is_ipxe(&opts));
+        opts.insert(DhcpOption::Unknown(v4::UnknownOption::new(
+            77.into(),
+            b"PXEClient".to_vec(),
+        )));
+        assert!(!is_ipxe(&opts));
+        opts.insert(DhcpOption::Unknown(v4::UnknownOption::new(
+            175.into(),
+            vec![19, 1, 1],
+        )));
+        assert!(is_ipxe(&opts));
+    }
+
+    #[test]
+    fn test_boot_files() {
+        let cfg = Config::new(BOOT_YAML).unwrap();
+        assert_eq!(
+            cfg.boot_files().into_iter().collect::<Vec<_>>(),
+            [
+                "boot.ipxe",
+                "http://192.168.0.2/boot.ipxe",
+                "ipxe.efi",
+                "pxelinux.0",
+                "reserved.efi",
+                "reserved.ipxe"
+            ]
+        );
+        let yaml = BOOT_YAML.replace(
+            "                boot:\n                    next_server: 192.168.0.3",
+            "                options:\n                    values:\n                        67:\n                            type: str\n                            value: grubx64.efi",
+        );
+        let cfg = Config::new(yaml).unwrap();
+        assert!(cfg.boot_files().contains("grubx64.efi"));
+    }
+
+    #[test]
+    fn test_topology() {
+        let yaml = format!(
+            "topology:\n    - prefix: 0.0.0.0/0\n      gateway: 192.168.0.254\n    - prefix: 10.0.0.0/8\n      gateway: 192.168.0.254\n{SCHEDULE_YAML}"
+        );
+        let cfg = Config::new(yaml).unwrap();
+        let range = &cfg.network([192, 168, 0, 1]).unwrap().ranges()[0];
+        // the range's router option wins over the generated one
+        assert_eq!(
+            range.opts().get(OptionCode::Router),
+            Some(&DhcpOption::Router(vec![Ipv4Addr::new(192, 168, 0, 1)]))
+        );
+        assert!(range.opts().get(OptionCode::from(121)).is_some());
+        assert!(range.opts().get(OptionCode::from(33)).is_some());
+    }
+
+    #[test]
+    fn test_forcerenew_nonce() {
+        let cfg = Config::new(EXCLUSIONS_YAML).unwrap();
+        assert!(!cfg.network([192, 168, 0, 1]).unwrap().forcerenew_nonce());
+        let yaml = EXCLUSIONS_YAML.replace(
+            "server_id: 192.168.0.1",
+            "server_id: 192.168.0.1\n        forcerenew_nonce: true",
+        );
+        let cfg = Config::new(yaml).unwrap();
+        assert!(cfg.network([192, 168, 0, 1]).unwrap().forcerenew_nonce());
+    }
+
+    #[test]
+    fn test_hostname_template() {
+        let template = |template: &str| {
+            Config::new(EXCLUSIONS_YAML.replace(
+                "server_id: 192.168.0.1",
+                &format!("server_id: 192.168.0.1\n        hostname_template: \"{template}\""),
+            ))
+        };
+        let cfg = template("dhcp-{ip_dashed}.guest.example.com").unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert_eq!(
+            net.hostname_template()
+                .unwrap()
+                .render(Ipv4Addr::new(192, 168, 0, 10), &[0; 6]),
+            "dhcp-192-168-0-10.guest.example.com"
+        );
+        assert!(template("dhcp-{hostname}").is_err());
+        let cfg = Config::new(EXCLUSIONS_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        assert!(net.hostname_template().is_none());
+    }
+
+    #[test]
+    fn test_sticky() {
+        let cfg = Config::new(EXCLUSIONS_YAML).unwrap();
+        assert_eq!(cfg.network([192, 168, 0, 1]).unwrap().sticky(), None);
+        let yaml = EXCLUSIONS_YAML.replace(
+            "server_id: 192.168.0.1",
+            "server_id: 192.168.0.1\n        sticky:\n            after_renewals: 3",
+        );
+        let cfg = Config::new(yaml).unwrap();
+        let sticky = cfg.network([192, 168, 0, 1]).unwrap().sticky().unwrap();
+        assert_eq!(sticky.after_renewals(), Some(3));
+        assert_eq!(sticky.hold(), Duration::from_secs(7_776_000));
+    }
+
+    #[test]
+    fn test_echo_client_id() {
+        assert!(Config::new(EXCLUSIONS_YAML).unwrap().echo_client_id());
+        let yaml = format!("echo_client_id: false\n{EXCLUSIONS_YAML}");
+        assert!(!Config::new(yaml).unwrap().echo_client_id());
+    }
+
+    #[test]
+    fn test_relay_mode() {
+        assert!(Config::new(EXCLUSIONS_YAML).unwrap().relay().is_none());
+        let yaml = "
+mode: relay
+relay:
+    servers: [10.0.0.5, 10.0.0.6:6767, \"2001:db8::5\"]
+    remote_id: branch-12
+";
+        let cfg = Config::new(yaml).unwrap();
+        let relay = cfg.relay().unwrap();
+        assert_eq!(
+            relay.v4_servers(),
+            [
+                "10.0.0.5:67".parse().unwrap(),
+                "10.0.0.6:6767".parse().unwrap()
+            ]
+        );
+        assert_eq!(relay.v6_servers(), ["[2001:db8::5]:547".parse().unwrap()]);
+        assert!(relay.interface_id());
+        assert_eq!(relay.remote_id(), Some(&b"branch-12"[..]));
+
+        assert!(Config::new("mode: relay").is_err());
+        assert!(Config::new("mode: relay\nrelay:\n    servers: []").is_err());
+        let yaml = format!("relay:\n    servers: [10.0.0.5]\n{EXCLUSIONS_YAML}");
+        assert!(Config::new(yaml).is_err());
+    }
+
+    #[test]
+    fn test_release_on_move() {
+        assert!(!Config::new(EXCLUSIONS_YAML).unwrap().release_on_move());
+        let yaml = format!("release_on_move: true\n{EXCLUSIONS_YAML}");
+        assert!(Config::new(yaml).unwrap().release_on_move());
+    }
+
+    #[test]
+    fn test_offer_dedup_window() {
+        let cfg = Config::new(EXCLUSIONS_YAML).unwrap();
+        assert_eq!(cfg.offer_dedup_window(), Duration::ZERO);
+        let yaml = format!("offer_dedup_window: 10s\n{EXCLUSIONS_YAML}");
+        let cfg = Config::new(yaml).unwrap();
+        assert_eq!(cfg.offer_dedup_window(), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_garbage_clients() {
+        assert_eq!(
+            Config::new(EXCLUSIONS_YAML).unwrap().garbage_clients(),
+            None
+        );
+        let yaml = format!("garbage_clients:\n    block: true\n{EXCLUSIONS_YAML}");
+        assert_eq!(
+            Config::new(yaml).unwrap().garbage_clients(),
+            Some(garbage::Limits {
+                window: Duration::from_secs(60),
+                xid_chaddrs: 2,
+                circuit_chaddrs: 8,
+                block: true,
+            })
+        );
+        let yaml =
+            format!("garbage_clients:\n    window: 5m\n    xid_chaddrs: 1\n{EXCLUSIONS_YAML}");
+        let limits = Config::new(yaml).unwrap().garbage_clients().unwrap();
+        assert_eq!(limits.window, Duration::from_secs(300));
+        assert_eq!(limits.xid_chaddrs, 1);
+        assert!(!limits.block);
+        let yaml = format!("garbage_clients:\n    circuit_chaddrs: 0\n{EXCLUSIONS_YAML}");
+        assert!(Config::new(yaml).is_err());
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let cfg = Config::new(EXCLUSIONS_YAML).unwrap();
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        let range = &net.ranges()[0];
+        let excluded = range.exclusions();
+        // reserve_first_n: 2 & reserve_last_n: 1
+        assert!(excluded.contains(&Ipv4Addr::new(192, 168, 0, 0)));
+        assert!(excluded.contains(&Ipv4Addr::new(192, 168, 0, 1)));
+        assert!(excluded.contains(&Ipv4Addr::new(192, 168, 0, 255)));
+        // 192.168.0.16/28
+        assert!(excluded.contains(&Ipv4Addr::new(192, 168, 0, 16)));
+        assert!(excluded.contains(&Ipv4Addr::new(192, 168, 0, 31)));
+        assert!(!excluded.contains(&Ipv4Addr::new(192, 168, 0, 32)));
+        assert!(excluded.contains(&Ipv4Addr::new(192, 168, 0, 100)));
+        // 10.0.0.0/8 is outside the range
+        assert_eq!(excluded.len(), 2 + 1 + 16 + 1);
+        assert_eq!(range.total_addrs(), 256 - excluded.len());
+        assert_eq!(range.iter().next(), Some(Ipv4Addr::new(192, 168, 0, 2)));
+    }
+
     #[test]
     fn test_circular() {
         let cfg = Config::new(CIRC_YAML);
@@ -616,8 +2237,13 @@ mod tests {
                 max: Duration::from_secs(10),
             },
             opts: DhcpOptions::new(),
-            exclude: HashSet::new(),
+            exclude: Exclusions::default(),
             class: Some("foo".to_owned()),
+            vendor_class: None,
+            scheduled: Vec::new(),
+            ping: PingCheck::default(),
+            boot: Boot::default(),
+            hints: HintPolicy::default(),
         };
         // class matches
         assert!(range.match_class(Some(&["foo".to_owned()])));
@@ -634,8 +2260,13 @@ mod tests {
                 max: Duration::from_secs(10),
             },
             opts: DhcpOptions::new(),
-            exclude: HashSet::new(),
+            exclude: Exclusions::default(),
             class: None,
+            vendor_class: None,
+            scheduled: Vec::new(),
+            ping: PingCheck::default(),
+            boot: Boot::default(),
+            hints: HintPolicy::default(),
         };
         // no classes to match -> true
         assert!(range.match_class(None));
@@ -683,6 +2314,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_vendor_class() {
+        let yaml = "networks:\n    192.168.0.1/24:\n        ranges:\n            -\n                start: 192.168.0.10\n                end: 192.168.0.50\n                vendor_class: Cisco\n                config:\n                    lease_time:\n                        default: 3600\n            -\n                start: 192.168.0.100\n                end: 192.168.0.200\n                config:\n                    lease_time:\n                        default: 3600\n";
+        let cfg = Config::new(yaml).unwrap();
+        let mut msg = v4::Message::default();
+        // no client classes, but vendor classes are still evaluated
+        assert!(cfg.eval_client_classes(&msg).unwrap().unwrap().is_empty());
+        msg.opts_mut().insert(DhcpOption::ClassIdentifier(
+            b"Cisco Systems, Inc. IP Phone CP-8841".to_vec(),
+        ));
+        let matched = cfg.eval_client_classes(&msg).unwrap().unwrap();
+        assert_eq!(matched, [vendor_class_name("Cisco")]);
+        let net = cfg.network([192, 168, 0, 1]).unwrap();
+        let range = net.ranges_with_class(Some(&matched)).next().unwrap();
+        assert_eq!(range.start(), Ipv4Addr::new(192, 168, 0, 10));
+        // other clients only get the unclassed range
+        let range = net.ranges_with_class(Some(&[])).next().unwrap();
+        assert_eq!(range.start(), Ipv4Addr::new(192, 168, 0, 100));
+
+        let both = yaml.replace(
+            "vendor_class: Cisco",
+            "vendor_class: Cisco\n                class: phones",
+        );
+        assert!(Config::new(both).is_err());
+        assert!(Config::new(yaml.replace("vendor_class: Cisco", "vendor_class: \"\"")).is_err());
+    }
+
     #[test]
     fn test_range_lease_time() {
         let range = NetRange::new(
@@ -720,14 +2378,21 @@ mod tests {
                 min: Duration::from_secs(3),
                 max: Duration::from_secs(10),
             },
-            exclude: HashSet::from([
+            exclude: [
                 [192, 168, 0, 1].into(),
                 [192, 168, 0, 2].into(),
                 [192, 168, 0, 3].into(),
                 [192, 168, 0, 4].into(),
-            ]),
+            ]
+            .into_iter()
+            .collect(),
             opts: DhcpOptions::default(),
             class: None,
+            vendor_class: None,
+            scheduled: Vec::new(),
+            ping: PingCheck::default(),
+            boot: Boot::default(),
+            hints: HintPolicy::default(),
         };
         // excluded causes us to skip 1-4
         assert!(range.iter().eq(Ipv4AddrRange::new(
@@ -737,6 +2402,62 @@ mod tests {
         assert_eq!(range.total_addrs(), 100 - 4);
     }
 
+    #[test]
+    fn test_exclusion_blocks() {
+        let ip = |n: u8| Ipv4Addr::new(10, 0, 0, n);
+        let exclusions = Exclusions::new([
+            ip(20)..=ip(29),
+            ip(5)..=ip(9),
+            ip(25)..=ip(40),
+            ip(10)..=ip(10),
+        ]);
+        // overlapping & adjacent blocks are merged
+        assert_eq!(
+            exclusions.blocks().collect::<Vec<_>>(),
+            [ip(5)..=ip(10), ip(20)..=ip(40)]
+        );
+        assert_eq!(exclusions.len(), 6 + 21);
+        assert!(exclusions.contains(&ip(5)));
+        assert!(exclusions.contains(&ip(30)));
+        assert!(!exclusions.contains(&ip(4)));
+        assert!(!exclusions.contains(&ip(11)));
+        assert!(!exclusions.contains(&ip(41)));
+
+        let mut iter = NetRangeIter::new(ip(1)..=ip(50), &exclusions);
+        assert!(iter.by_ref().take(5).eq((1..=4).chain(11..=11).map(ip)));
+        assert_eq!(iter.count(), 8 + 10);
+        assert_eq!(
+            NetRangeIter::new(ip(1)..=ip(50), &exclusions).count(),
+            50 - 27
+        );
+        // a range ending in an excluded block
+        assert!(NetRangeIter::new(ip(18)..=ip(30), &exclusions).eq([ip(18), ip(19)]));
+        assert_eq!(NetRangeIter::new(ip(18)..=ip(30), &exclusions).count(), 2);
+        assert_eq!(NetRangeIter::new(ip(20)..=ip(30), &exclusions).next(), None);
+
+        let held = exclusions.with([ip(11), ip(50)]);
+        assert_eq!(
+            held.blocks().collect::<Vec<_>>(),
+            [ip(5)..=ip(11), ip(20)..=ip(40), ip(50)..=ip(50)]
+        );
+    }
+
+    #[test]
+    fn test_total_addrs_large() {
+        // counted without walking the range
+        let mut range = NetRange::new(
+            Ipv4Addr::new(10, 0, 0, 0)..=Ipv4Addr::new(10, 255, 255, 255),
+            LeaseTime {
+                default: Duration::from_secs(5),
+                min: Duration::from_secs(3),
+                max: Duration::from_secs(10),
+            },
+        );
+        range.exclude =
+            Exclusions::new([Ipv4Addr::new(10, 1, 0, 0)..=Ipv4Addr::new(10, 1, 255, 255)]);
+        assert_eq!(range.total_addrs(), (1 << 24) - (1 << 16));
+    }
+
     #[test]
     fn test_big_range() {
         let range = NetRange::new(
@@ -751,6 +2472,96 @@ mod tests {
         assert_eq!(range.total_addrs(), 256 * 4);
     }
 
+    #[test]
+    fn test_relay_allowed() {
+        let net = Network::default();
+        assert!(net.relay_allowed([10, 0, 0, 1].into()));
+        let net = Network {
+            allowed_relays: Some([Ipv4Addr::new(10, 0, 0, 1)].into_iter().collect()),
+            ..Default::default()
+        };
+        assert!(net.relay_allowed([10, 0, 0, 1].into()));
+        assert!(!net.relay_allowed([10, 0, 0, 2].into()));
+    }
+
+    #[test]
+    fn test_normalize_client_id() {
+        let mac = [0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff];
+        let off = ClientIdNormalize::default();
+        let hw_id = [&[1][..], &mac].concat();
+        assert_eq!(normalize_client_id(&off, &hw_id), &hw_id[..]);
+
+        let norm = ClientIdNormalize {
+            strip_hw_type: true,
+            case_fold: true,
+            node_specific: NodeSpecific::Duid,
+        };
+        assert_eq!(normalize_client_id(&norm, &hw_id), &mac[..]);
+        assert_eq!(
+            normalize_client_id(&norm, b"\0Printer-1"),
+            &b"\0printer-1"[..]
+        );
+        // [255][iaid][DUID-LL: type 3, htype 1, mac]
+        let duid = [&[0, 3, 0, 1][..], &mac].concat();
+        let node_id = |iaid: u8| [&[255, 0, 0, 0, iaid][..], &duid].concat();
+        assert_eq!(
+            normalize_client_id(&norm, &node_id(1)),
+            normalize_client_id(&norm, &node_id(2))
+        );
+        assert_eq!(
+            normalize_client_id(&norm, &node_id(1)),
+            [&[255][..], &duid].concat()
+        );
+
+        let norm = ClientIdNormalize {
+            node_specific: NodeSpecific::LinkLayer,
+            ..norm
+        };
+        assert_eq!(normalize_client_id(&norm, &node_id(1)), &mac[..]);
+        // DUID-EN has no link-layer address, keyed by the DUID
+        let en = [255, 0, 0, 0, 1, 0, 2, 0, 0, 0, 9, 1, 2];
+        assert_eq!(
+            normalize_client_id(&norm, &en),
+            &[255, 0, 2, 0, 0, 0, 9, 1, 2][..]
+        );
+    }
+
+    #[test]
+    fn test_reserved_user_class() {
+        let res = Reserved {
+            ip: [192, 168, 0, 120].into(),
+            lease: LeaseTime {
+                default: Duration::from_secs(5),
+                min: Duration::from_secs(3),
+                max: Duration::from_secs(10),
+            },
+            opts: DhcpOptions::default(),
+            class: None,
+            boot: Boot::default(),
+        };
+        let mut reserved_user_classes = HashMap::new();
+        reserved_user_classes.insert(b"iptv".to_vec(), res);
+        let net = Network {
+            subnet: "192.168.0.0/24".parse().unwrap(),
+            reserved_user_classes,
+            ..Default::default()
+        };
+        let mut opts = DhcpOptions::new();
+        opts.insert(DhcpOption::Unknown(v4::UnknownOption::new(
+            77.into(),
+            vec![3, b'f', b'o', b'o', 4, b'i', b'p', b't', b'v'],
+        )));
+        let res = net.search_reserved_opt(&opts, None).unwrap();
+        assert_eq!(res.ip, Ipv4Addr::new(192, 168, 0, 120));
+
+        let mut opts = DhcpOptions::new();
+        opts.insert(DhcpOption::Unknown(v4::UnknownOption::new(
+            77.into(),
+            vec![3, b'f', b'o', b'o'],
+        )));
+        assert!(net.search_reserved_opt(&opts, None).is_none());
+    }
+
     #[test]
     fn test_reserved_opt() {
         let res = Reserved {
@@ -762,6 +2573,7 @@ mod tests {
             },
             opts: DhcpOptions::default(),
             class: None,
+            boot: Boot::default(),
         };
         // another value just to make sure we select the right one
         let mut another = res.clone();
@@ -0,0 +1,439 @@
+//! differences between two v4 configs, used to show what a new config changes
+//! before it's applied
+use std::{collections::BTreeMap, fmt, net::Ipv4Addr};
+
+use dora_core::dhcproto::v4::DhcpOptions;
+use ipnet::Ipv4Net;
+use serde::Serialize;
+
+use super::{Config, NetRange, Network, Reserved};
+
+/// everything that changed between two configs. Settings are named by their config key
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConfigDiff {
+    pub networks_added: Vec<Ipv4Net>,
+    pub networks_removed: Vec<Ipv4Net>,
+    pub networks_changed: Vec<NetworkDiff>,
+    /// top-level settings that changed
+    pub settings_changed: Vec<&'static str>,
+}
+
+/// changes inside of a network present in both configs
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct NetworkDiff {
+    pub subnet: Ipv4Net,
+    pub ranges_added: Vec<RangeId>,
+    pub ranges_removed: Vec<RangeId>,
+    pub ranges_changed: Vec<RangeDiff>,
+    pub reservations_added: Vec<Ipv4Addr>,
+    pub reservations_removed: Vec<Ipv4Addr>,
+    pub reservations_changed: Vec<Ipv4Addr>,
+    /// network level settings that changed
+    pub settings_changed: Vec<&'static str>,
+}
+
+/// a range is identified by its start & end
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct RangeId {
+    pub start: Ipv4Addr,
+    pub end: Ipv4Addr,
+}
+
+/// changes to a range present in both configs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RangeDiff {
+    #[serde(flatten)]
+    pub range: RangeId,
+    pub options: OptionDiff,
+    /// range settings that changed
+    pub settings_changed: Vec<&'static str>,
+}
+
+/// option codes added, removed or given a new value
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct OptionDiff {
+    pub added: Vec<u8>,
+    pub removed: Vec<u8>,
+    pub changed: Vec<u8>,
+}
+
+impl ConfigDiff {
+    /// the changes needed to go from `old` to `new`
+    pub fn new(old: &Config, new: &Config) -> Self {
+        let mut diff = ConfigDiff::default();
+        for (subnet, net) in &new.networks {
+            match old.networks.get(subnet) {
+                None => diff.networks_added.push(*subnet),
+                Some(old_net) if old_net != net => diff
+                    .networks_changed
+                    .push(NetworkDiff::new(*subnet, old_net, net)),
+                _ => {}
+            }
+        }
+        diff.networks_removed = old
+            .networks
+            .keys()
+            .filter(|subnet| !new.networks.contains_key(subnet))
+            .copied()
+            .collect();
+        diff.networks_added.sort();
+        diff.networks_removed.sort();
+        diff.networks_changed.sort_by_key(|net| net.subnet);
+
+        let mut changed = Changed::default();
+        changed.check("interfaces", &old.interfaces, &new.interfaces);
+        changed.check("chaddr_only", &old.chaddr_only, &new.chaddr_only);
+        changed.check(
+            "client_id_normalize",
+            &old.client_id_normalize,
+            &new.client_id_normalize,
+        );
+        changed.check("bootp_enable", &old.bootp_enable, &new.bootp_enable);
+        changed.check("echo_client_id", &old.echo_client_id, &new.echo_client_id);
+        changed.check(
+            "release_on_move",
+            &old.release_on_move,
+            &new.release_on_move,
+        );
+        changed.check(
+            "offer_dedup_window",
+            &old.offer_dedup_window,
+            &new.offer_dedup_window,
+        );
+        changed.check(
+            "garbage_clients",
+            &old.garbage_clients,
+            &new.garbage_clients,
+        );
+        changed.check("relay_map", &old.relay_map, &new.relay_map);
+        changed.check("vlans", &old.vlans, &new.vlans);
+        changed.check("ping_source", &old.ping_source, &new.ping_source);
+        changed.check("run_as", &old.run_as, &new.run_as);
+        // the v6 server id is generated on every parse, don't count it as a change
+        let v6_changed = match (&old.v6, &new.v6) {
+            (Some(old), Some(new)) => !old.same_settings(new),
+            (old, new) => old.is_some() != new.is_some(),
+        };
+        if v6_changed {
+            changed.0.push("v6");
+        }
+        changed.check("client_classes", &old.client_classes, &new.client_classes);
+        changed.check("relay", &old.relay, &new.relay);
+        diff.settings_changed = changed.0;
+        diff
+    }
+
+    /// true if the configs are equivalent
+    pub fn is_empty(&self) -> bool {
+        self.networks_added.is_empty()
+            && self.networks_removed.is_empty()
+            && self.networks_changed.is_empty()
+            && self.settings_changed.is_empty()
+    }
+}
+
+impl NetworkDiff {
+    fn new(subnet: Ipv4Net, old: &Network, new: &Network) -> Self {
+        let old_ranges = ranges(old);
+        let new_ranges = ranges(new);
+        let mut diff = NetworkDiff {
+            subnet,
+            ..Default::default()
+        };
+        for (id, range) in &new_ranges {
+            match old_ranges.get(id) {
+                None => diff.ranges_added.push(*id),
+                Some(old_range) if old_range != range => diff
+                    .ranges_changed
+                    .push(RangeDiff::new(*id, old_range, range)),
+                _ => {}
+            }
+        }
+        diff.ranges_removed = old_ranges
+            .keys()
+            .filter(|id| !new_ranges.contains_key(id))
+            .copied()
+            .collect();
+
+        let old_res = reservations(old);
+        let new_res = reservations(new);
+        for (ip, res) in &new_res {
+            match old_res.get(ip) {
+                None => diff.reservations_added.push(*ip),
+                Some(old_res) if !same_items(old_res, res) => diff.reservations_changed.push(*ip),
+                _ => {}
+            }
+        }
+        diff.reservations_removed = old_res
+            .keys()
+            .filter(|ip| !new_res.contains_key(ip))
+            .copied()
+            .collect();
+
+        let mut changed = Changed::default();
+        changed.check("server_id", &old.server_id, &new.server_id);
+        changed.check("ping_check", &old.ping, &new.ping);
+        changed.check(
+            "probation_period",
+            &old.probation_period,
+            &new.probation_period,
+        );
+        changed.check("offer_hold", &old.offer_hold, &new.offer_hold);
+        changed.check("authoritative", &old.authoritative, &new.authoritative);
+        changed.check("boot", &old.boot, &new.boot);
+        changed.check("static_hosts", &old.static_hosts, &new.static_hosts);
+        changed.check(
+            "hostname_conflict",
+            &old.hostname_conflict,
+            &new.hostname_conflict,
+        );
+        changed.check(
+            "hostname_template",
+            &old.hostname_template,
+            &new.hostname_template,
+        );
+        changed.check("allowed_relays", &old.allowed_relays, &new.allowed_relays);
+        changed.check("nak", &old.nak, &new.nak);
+        changed.check(
+            "reply",
+            &(&old.reply, &old.reply_rules),
+            &(&new.reply, &new.reply_rules),
+        );
+        changed.check(
+            "forcerenew_nonce",
+            &old.forcerenew_nonce,
+            &new.forcerenew_nonce,
+        );
+        changed.check("sticky", &old.sticky, &new.sticky);
+        diff.settings_changed = changed.0;
+        diff
+    }
+}
+
+impl RangeDiff {
+    fn new(range: RangeId, old: &NetRange, new: &NetRange) -> Self {
+        let mut changed = Changed::default();
+        changed.check("lease_time", &old.lease, &new.lease);
+        changed.check("except", &old.exclude, &new.exclude);
+        changed.check("class", &old.class, &new.class);
+        changed.check("schedule", &old.scheduled, &new.scheduled);
+        changed.check("ping_check", &old.ping, &new.ping);
+        changed.check("boot", &old.boot, &new.boot);
+        changed.check("honor_hints", &old.hints, &new.hints);
+        RangeDiff {
+            range,
+            options: OptionDiff::new(&old.opts, &new.opts),
+            settings_changed: changed.0,
+        }
+    }
+}
+
+impl OptionDiff {
+    fn new(old: &DhcpOptions, new: &DhcpOptions) -> Self {
+        let mut diff = OptionDiff::default();
+        for (code, opt) in new.iter() {
+            match old.get(*code) {
+                None => diff.added.push(u8::from(*code)),
+                Some(old_opt) if old_opt != opt => diff.changed.push(u8::from(*code)),
+                _ => {}
+            }
+        }
+        diff.removed = old
+            .iter()
+            .filter(|(code, _)| new.get(**code).is_none())
+            .map(|(code, _)| u8::from(*code))
+            .collect();
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.changed.sort_unstable();
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// names of the settings that differ
+#[derive(Default)]
+struct Changed(Vec<&'static str>);
+
+impl Changed {
+    fn check<T: PartialEq>(&mut self, name: &'static str, old: &T, new: &T) {
+        if old != new {
+            self.0.push(name);
+        }
+    }
+}
+
+fn ranges(net: &Network) -> BTreeMap<RangeId, &NetRange> {
+    net.ranges
+        .iter()
+        .map(|range| {
+            let id = RangeId {
+                start: range.start(),
+                end: range.end(),
+            };
+            (id, range)
+        })
+        .collect()
+}
+
+/// all reservations by ip, more than one reservation can use the same ip
+fn reservations(net: &Network) -> BTreeMap<Ipv4Addr, Vec<&Reserved>> {
+    let mut all: BTreeMap<Ipv4Addr, Vec<&Reserved>> = BTreeMap::new();
+    let reserved = net
+        .reserved_macs
+        .values()
+        .chain(net.reserved_opts.values().map(|(_, res)| res))
+        .chain(net.reserved_user_classes.values())
+        .chain(net.reserved_uuids.values());
+    for res in reserved {
+        all.entry(res.ip).or_default().push(res);
+    }
+    all
+}
+
+/// equal ignoring order, reservations are stored in hashmaps
+fn same_items(a: &[&Reserved], b: &[&Reserved]) -> bool {
+    a.len() == b.len() && a.iter().all(|res| b.contains(res))
+}
+
+impl fmt::Display for RangeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+        let mut lines = Vec::new();
+        lines.extend(self.networks_added.iter().map(|n| format!("+ network {n}")));
+        lines.extend(
+            self.networks_removed
+                .iter()
+                .map(|n| format!("- network {n}")),
+        );
+        for net in &self.networks_changed {
+            let subnet = net.subnet;
+            lines.extend(
+                net.ranges_added
+                    .iter()
+                    .map(|r| format!("+ network {subnet} range {r}")),
+            );
+            lines.extend(
+                net.ranges_removed
+                    .iter()
+                    .map(|r| format!("- network {subnet} range {r}")),
+            );
+            for range in &net.ranges_changed {
+                let mut changes = range
+                    .settings_changed
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>();
+                if !range.options.is_empty() {
+                    changes.push(format!(
+                        "options +{:?} -{:?} ~{:?}",
+                        range.options.added, range.options.removed, range.options.changed
+                    ));
+                }
+                lines.push(format!(
+                    "~ network {subnet} range {}: {}",
+                    range.range,
+                    changes.join(", ")
+                ));
+            }
+            lines.extend(
+                net.reservations_added
+                    .iter()
+                    .map(|ip| format!("+ network {subnet} reservation {ip}")),
+            );
+            lines.extend(
+                net.reservations_removed
+                    .iter()
+                    .map(|ip| format!("- network {subnet} reservation {ip}")),
+            );
+            lines.extend(
+                net.reservations_changed
+                    .iter()
+                    .map(|ip| format!("~ network {subnet} reservation {ip}")),
+            );
+            if !net.settings_changed.is_empty() {
+                lines.push(format!(
+                    "~ network {subnet}: {}",
+                    net.settings_changed.join(", ")
+                ));
+            }
+        }
+        if !self.settings_changed.is_empty() {
+            lines.push(format!("~ {}", self.settings_changed.join(", ")));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static BOOT_YAML: &str = include_str!("../../sample/boot.yaml");
+
+    #[test]
+    fn test_no_changes() {
+        let cfg = Config::new(BOOT_YAML).unwrap();
+        let diff = cfg.diff(&Config::new(BOOT_YAML).unwrap());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no changes");
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Config::new(BOOT_YAML).unwrap();
+        let yaml = BOOT_YAML
+            // network setting
+            .replace("file_name: \"pxelinux.0\"", "file_name: \"grub.efi\"")
+            // range option
+            .replace(
+                "                boot:\n                    next_server: 192.168.0.3\n",
+                "                boot:\n                    next_server: 192.168.0.3\n                options:\n                    values:\n                        6:\n                            type: ip_list\n                            value:\n                                - 1.1.1.1\n",
+            )
+            // reservation moved to a new ip
+            .replace("ip: 192.168.0.200", "ip: 192.168.0.201")
+            + "    10.0.0.1/24:\n        ranges:\n            -\n                start: 10.0.0.10\n                end: 10.0.0.20\n                config:\n                    lease_time:\n                        default: 60\n";
+        let new = Config::new(yaml).unwrap();
+        let diff = old.diff(&new);
+
+        assert_eq!(diff.networks_added, vec!["10.0.0.1/24".parse().unwrap()]);
+        assert!(diff.networks_removed.is_empty());
+        assert!(diff.settings_changed.is_empty());
+        let net = &diff.networks_changed[0];
+        assert_eq!(net.subnet, "192.168.0.1/24".parse().unwrap());
+        assert_eq!(net.settings_changed, vec!["boot"]);
+        assert!(net.ranges_added.is_empty());
+        let range = &net.ranges_changed[0];
+        assert_eq!(range.range.start, Ipv4Addr::new(192, 168, 0, 100));
+        assert_eq!(range.options.added, vec![6]);
+        assert!(range.settings_changed.is_empty());
+        assert_eq!(
+            net.reservations_added,
+            vec![Ipv4Addr::new(192, 168, 0, 201)]
+        );
+        assert_eq!(
+            net.reservations_removed,
+            vec![Ipv4Addr::new(192, 168, 0, 200)]
+        );
+        assert!(diff.to_string().contains("+ network 10.0.0.1/24"));
+
+        // and back again
+        let diff = new.diff(&old);
+        assert_eq!(diff.networks_removed, vec!["10.0.0.1/24".parse().unwrap()]);
+        assert_eq!(
+            diff.networks_changed[0].ranges_changed[0].options.removed,
+            vec![6]
+        );
+    }
+}
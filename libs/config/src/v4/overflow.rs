@@ -0,0 +1,130 @@
+//! ranges whose options can't all fit in the smallest reply a DHCP client has
+//! to accept, 576 bytes for a client that doesn't send a max message size
+//! (opt 57). Replies that don't fit have options dropped when they're sent
+use std::{fmt, net::Ipv4Addr};
+
+use dora_core::{
+    dhcproto::v4::{DhcpOption, Message, MessageType},
+    server::msg::encode_v4_dropped,
+};
+use ipnet::Ipv4Net;
+use serde::Serialize;
+
+use super::{diff::RangeId, Config, NetRange, Network};
+
+/// the most bytes a reply to a client without a max message size can take
+/// up, 576 less the IP & UDP headers
+pub const MIN_REPLY_SIZE: usize = 548;
+
+/// a range with more options than fit in a minimal reply
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Overflow {
+    pub subnet: Ipv4Net,
+    #[serde(flatten)]
+    pub range: RangeId,
+    /// option codes dropped from a reply to a client that requests them all
+    pub dropped: Vec<u8>,
+}
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} range {}: options {:?} don't fit in a 576 byte reply",
+            self.subnet, self.range, self.dropped
+        )
+    }
+}
+
+impl Config {
+    /// ranges whose options can't all be sent in a reply to a client that
+    /// doesn't send a max message size, by subnet
+    pub fn reply_overflows(&self) -> Vec<Overflow> {
+        let mut overflows = self
+            .networks
+            .iter()
+            .flat_map(|(subnet, net)| {
+                net.ranges.iter().filter_map(move |range| {
+                    let dropped = dropped(net, range);
+                    (!dropped.is_empty()).then(|| Overflow {
+                        subnet: *subnet,
+                        range: RangeId {
+                            start: range.start(),
+                            end: range.end(),
+                        },
+                        dropped,
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        overflows.sort_by_key(|overflow| (overflow.subnet, overflow.range));
+        overflows
+    }
+}
+
+/// options dropped from the largest ACK `range` can send
+fn dropped(net: &Network, range: &NetRange) -> Vec<u8> {
+    let mut msg = Message::default();
+    let boot = range.boot.clone().or(&net.boot);
+    if let Some(sname) = &boot.server_name {
+        msg.set_sname_str(sname);
+    }
+    if let Some(fname) = &boot.file_name {
+        msg.set_fname_str(fname);
+    }
+    // sent in every ACK, the values don't change their size
+    for opt in [
+        DhcpOption::MessageType(MessageType::Ack),
+        DhcpOption::ServerIdentifier(Ipv4Addr::UNSPECIFIED),
+        DhcpOption::AddressLeaseTime(0),
+        DhcpOption::Renewal(0),
+        DhcpOption::Rebinding(0),
+        DhcpOption::SubnetMask(Ipv4Addr::UNSPECIFIED),
+        DhcpOption::Router(vec![Ipv4Addr::UNSPECIFIED]),
+        DhcpOption::BroadcastAddr(Ipv4Addr::UNSPECIFIED),
+    ] {
+        msg.opts_mut().insert(opt);
+    }
+    for (_, opt) in range.opts().iter() {
+        msg.opts_mut().insert(opt.clone());
+    }
+    let mut encoding = net.reply_encoding();
+    encoding.max_size = Some(MIN_REPLY_SIZE);
+    match encode_v4_dropped(&msg, encoding, None) {
+        Ok((_, dropped)) => dropped.into_iter().map(u8::from).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static LONG_OPTS: &str = include_str!("../../sample/long_opts.yaml");
+    static EXCLUSIONS_YAML: &str = include_str!("../../sample/exclusions.yaml");
+
+    #[test]
+    fn test_reply_overflows() {
+        let cfg = Config::new(LONG_OPTS).unwrap();
+        let overflows = cfg.reply_overflows();
+        assert_eq!(
+            overflows,
+            [Overflow {
+                subnet: "192.168.1.100/30".parse().unwrap(),
+                range: RangeId {
+                    start: [192, 168, 1, 100].into(),
+                    end: [192, 168, 1, 103].into(),
+                },
+                dropped: vec![43],
+            }]
+        );
+        assert_eq!(
+            overflows[0].to_string(),
+            "192.168.1.100/30 range 192.168.1.100-192.168.1.103: options [43] don't fit in a 576 byte reply"
+        );
+        assert!(Config::new(EXCLUSIONS_YAML)
+            .unwrap()
+            .reply_overflows()
+            .is_empty());
+    }
+}
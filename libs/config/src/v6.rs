@@ -1,6 +1,9 @@
 use std::{
     collections::HashMap,
+    io::ErrorKind,
     net::Ipv6Addr,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
     time::{Duration, SystemTime},
 };
 
@@ -9,16 +12,27 @@ use dora_core::{
     anyhow::Result,
     dhcproto::{
         v4::HType,
-        v6::{duid::Duid, DhcpOptions},
+        v6::{duid::Duid, DhcpOption, DhcpOptions, OptionCode},
+        Encodable,
     },
     pnet::ipnetwork::{IpNetwork, Ipv6Network},
-    pnet::{self, datalink::NetworkInterface},
+    pnet::{datalink::NetworkInterface, util::MacAddr},
 };
 use ipnet::Ipv6Net;
-use tracing::debug;
+use serde::Serialize;
+use tracing::{debug, info};
+use trust_dns_proto::{
+    rr,
+    serialize::binary::{BinDecodable, BinDecoder},
+};
 
 use crate::{wire, LeaseTime};
 
+pub use crate::wire::v6::{DuidType, StatusMessages, StatusPolicy};
+
+/// domain search list option code, RFC 3646
+const DOMAIN_LIST: u16 = 24;
+
 // const DEFAULT_VALID: Duration = Duration::from_secs(12 * 24 * 60 * 60); // 12 days
 // const DEFAULT_PREFERRED: Duration = Duration::from_secs(8 * 24 * 60 * 60); // 8 days
 
@@ -33,13 +47,101 @@ pub struct Config {
     /// used to make a selection on which network or subnet to use
     networks: HashMap<Ipv6Net, Network>,
     server_id: Duid,
+    /// file the other-config data is shared through with an RA daemon
+    ra_sync: Option<PathBuf>,
+}
+
+/// the DNS servers & search domains a v6 network hands out, for an RA daemon to
+/// advertise in RDNSS & DNSSL (RFC 8106) so both stay in sync
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OtherConfig {
+    pub network: Ipv6Net,
+    pub dns_servers: Vec<Ipv6Addr>,
+    pub domains: Vec<String>,
+}
+
+/// receives the other-config data when dora loads its config
+pub trait OtherConfigSink {
+    fn sync(&self, data: &[OtherConfig]) -> Result<()>;
+}
+
+/// writes the other-config data as JSON, replacing `path` so readers never see
+/// a partial file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonFileSink {
+    path: PathBuf,
+}
+
+impl JsonFileSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl OtherConfigSink for JsonFileSink {
+    fn sync(&self, data: &[OtherConfig]) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(data)?)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to replace {}", self.path.display()))?;
+        Ok(())
+    }
 }
 
 impl Config {
+    /// equal ignoring the server id, which is generated each time a config is parsed
+    pub(crate) fn same_settings(&self, other: &Config) -> bool {
+        self.interfaces == other.interfaces
+            && self.opts == other.opts
+            && self.networks == other.networks
+            && self.ra_sync == other.ra_sync
+    }
+    /// DNS servers & domains of every network, ordered by subnet
+    pub fn other_config(&self) -> Vec<OtherConfig> {
+        let mut data = self
+            .networks
+            .iter()
+            .map(|(subnet, network)| other_config(*subnet, network.opts()))
+            .collect::<Vec<_>>();
+        data.sort_by_key(|cfg| cfg.network);
+        data
+    }
+    /// where the other-config data is shared with an RA daemon, if configured
+    pub fn ra_sync(&self) -> Option<JsonFileSink> {
+        self.ra_sync.as_ref().map(JsonFileSink::new)
+    }
     /// return server id as a slice of bytes
     pub fn server_id(&self) -> &[u8] {
         self.server_id.as_ref()
     }
+    /// read the server DUID from `path`, so it is stable across restarts. If `path`
+    /// doesn't exist, the generated DUID is written to it
+    pub fn persist_server_id<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(duid) => {
+                let duid = hex::decode(duid.trim())
+                    .with_context(|| format!("invalid server DUID in {}", path.display()))?;
+                if duid.is_empty() {
+                    bail!("empty server DUID in {}", path.display());
+                }
+                self.server_id = Duid::from(duid);
+                info!(path = %path.display(), duid = %hex::encode(&self.server_id), "loaded server DUID");
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                std::fs::write(path, hex::encode(&self.server_id) + "\n").with_context(|| {
+                    format!("failed to write server DUID to {}", path.display())
+                })?;
+                info!(path = %path.display(), duid = %hex::encode(&self.server_id), "generated server DUID");
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read server DUID from {}", path.display()))
+            }
+        }
+        Ok(())
+    }
     /// return the optional explicitly bound interfaces if there are any
     pub fn interfaces(&self) -> &[NetworkInterface] {
         self.interfaces.as_slice()
@@ -96,6 +198,26 @@ impl Config {
         })
     }
 
+    /// get the `Network` of a message relayed from a link with `link_addr`, the
+    /// network whose prefix contains it. Messages that weren't relayed, or with
+    /// an unspecified link-address, use the network of `iface_index`
+    pub fn get_network_by_link(
+        &self,
+        iface_index: u32,
+        link_addr: Option<Ipv6Addr>,
+    ) -> Option<&Network> {
+        match link_addr.filter(|addr| !addr.is_unspecified()) {
+            Some(addr) => self
+                .networks
+                .iter()
+                .filter(|(subnet, _)| subnet.contains(&addr))
+                .max_by_key(|(subnet, _)| subnet.prefix_len())
+                .map(|(_, network)| network)
+                .or_else(|| self.get_network(iface_index)),
+            None => self.get_network(iface_index),
+        }
+    }
+
     /// gets options (which have been already merged with global opts) for the network of `iface_index` or the global options
     pub fn get_opts(&self, iface_index: u32) -> Option<&DhcpOptions> {
         self.get_network(iface_index)
@@ -109,6 +231,38 @@ impl Config {
     }
 }
 
+/// the DNS servers (23) & domain search list (24) in `opts`
+fn other_config(network: Ipv6Net, opts: &DhcpOptions) -> OtherConfig {
+    let dns_servers = match opts.get(OptionCode::DomainNameServers) {
+        Some(DhcpOption::DomainNameServers(ips)) => ips.clone(),
+        _ => Vec::new(),
+    };
+    let domains = opts
+        .get(OptionCode::from(DOMAIN_LIST))
+        .and_then(|opt| opt.to_vec().ok())
+        // [code: u16][len: u16][names...]
+        .map(|buf| domain_names(buf.get(4..).unwrap_or_default()))
+        .unwrap_or_default();
+    OtherConfig {
+        network,
+        dns_servers,
+        domains,
+    }
+}
+
+/// DNS encoded names in `buf`, stopping at the first that can't be read
+fn domain_names(buf: &[u8]) -> Vec<String> {
+    let mut decoder = BinDecoder::new(buf);
+    let mut names = Vec::new();
+    while !decoder.is_empty() {
+        match rr::Name::read(&mut decoder) {
+            Ok(name) => names.push(name.to_string()),
+            Err(_) => break,
+        }
+    }
+    names
+}
+
 /// merge `b` into `a`, favoring `a` where there are duplicates
 fn merge_opts(a: &DhcpOptions, b: DhcpOptions) -> DhcpOptions {
     let mut opts = a.clone();
@@ -124,8 +278,9 @@ fn merge_opts(a: &DhcpOptions, b: DhcpOptions) -> DhcpOptions {
 pub struct Network {
     interfaces: Option<Vec<NetworkInterface>>,
     subnet: Ipv6Net,
-    valid: LeaseTime,
-    preferred: LeaseTime,
+    /// `None` for an options only network
+    valid: Option<LeaseTime>,
+    preferred: Option<LeaseTime>,
     options: DhcpOptions,
     ping_check: bool,
     /// default ping timeout in ms
@@ -134,12 +289,46 @@ pub struct Network {
     probation_period: Duration,
     /// Whether we are authoritative for this network (default: true)
     authoritative: bool,
+    /// address pools
+    ranges: Vec<NetRange>,
+    /// addresses reserved for single clients
+    reservations: Reservations,
+    /// status codes returned for this network
+    status: StatusPolicy,
+    /// only Information-Request is answered
+    options_only: bool,
 }
 
 impl Network {
+    /// the address pools of this network
+    pub fn ranges(&self) -> &[NetRange] {
+        &self.ranges
+    }
+    /// get the range containing `ip`, excluded addresses are never matched
+    pub fn range(&self, ip: Ipv6Addr) -> Option<&NetRange> {
+        self.ranges.iter().find(|range| range.contains(&ip))
+    }
+    /// the reservation of the client with `duid`, relayed with `interface_id`
+    /// if any. A match on the whole DUID wins over its link-layer address, which
+    /// wins over the interface-id
+    pub fn get_reserved(&self, duid: &[u8], interface_id: Option<&[u8]>) -> Option<&Reserved> {
+        self.reservations.get(duid, interface_id)
+    }
     pub fn subnet(&self) -> Ipv6Addr {
         self.subnet.network()
     }
+    /// is `ip` inside of this network's subnet?
+    pub fn contains(&self, ip: &Ipv6Addr) -> bool {
+        self.subnet.contains(ip)
+    }
+    /// which status codes are returned for this network
+    pub fn status(&self) -> &StatusPolicy {
+        &self.status
+    }
+    /// is only stateless service (Information-Request) provided?
+    pub fn options_only(&self) -> bool {
+        self.options_only
+    }
     pub fn authoritative(&self) -> bool {
         self.authoritative
     }
@@ -161,6 +350,165 @@ impl Network {
     }
 }
 
+/// an address pool in a v6 network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetRange {
+    range: RangeInclusive<Ipv6Addr>,
+    valid: LeaseTime,
+    preferred: LeaseTime,
+    options: DhcpOptions,
+    /// addresses are stored as /128s
+    exclusions: Vec<Ipv6Net>,
+}
+
+impl NetRange {
+    fn from_wire(subnet: Ipv6Net, range: wire::v6::IpRange) -> Result<Self> {
+        let (start, end) = (*range.range.start(), *range.range.end());
+        if start > end || !subnet.contains(&start) || !subnet.contains(&end) {
+            bail!("range {start}-{end} must be inside of network {subnet}");
+        }
+        let exclusions = range
+            .except
+            .into_iter()
+            .map(|exclusion| {
+                let net = match exclusion {
+                    wire::v6::Exclusion::Ip(ip) => Ipv6Net::from(ip),
+                    wire::v6::Exclusion::Net(net) => net.trunc(),
+                };
+                if !range.range.contains(&net.network()) || !range.range.contains(&net.broadcast())
+                {
+                    bail!("exclusion {net} must be inside of range {start}-{end}");
+                }
+                Ok(net)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            range: range.range,
+            valid: range.config.lease_time.into(),
+            preferred: range.config.preferred_time.into(),
+            options: range.options.get(),
+            exclusions,
+        })
+    }
+    pub fn start(&self) -> Ipv6Addr {
+        *self.range.start()
+    }
+    pub fn end(&self) -> Ipv6Addr {
+        *self.range.end()
+    }
+    pub fn valid(&self) -> &LeaseTime {
+        &self.valid
+    }
+    pub fn preferred(&self) -> &LeaseTime {
+        &self.preferred
+    }
+    pub fn opts(&self) -> &DhcpOptions {
+        &self.options
+    }
+    pub fn exclusions(&self) -> &[Ipv6Net] {
+        &self.exclusions
+    }
+    /// is `ip` excluded from being leased?
+    pub fn is_excluded(&self, ip: &Ipv6Addr) -> bool {
+        self.exclusions.iter().any(|net| net.contains(ip))
+    }
+    /// is `ip` in the range & not excluded?
+    pub fn contains(&self, ip: &Ipv6Addr) -> bool {
+        self.range.contains(ip) && !self.is_excluded(ip)
+    }
+}
+
+/// an address reserved for one client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reserved {
+    ip: Ipv6Addr,
+    valid: LeaseTime,
+    preferred: LeaseTime,
+    options: DhcpOptions,
+}
+
+impl Reserved {
+    pub fn ip(&self) -> Ipv6Addr {
+        self.ip
+    }
+    pub fn valid(&self) -> &LeaseTime {
+        &self.valid
+    }
+    pub fn preferred(&self) -> &LeaseTime {
+        &self.preferred
+    }
+    pub fn opts(&self) -> &DhcpOptions {
+        &self.options
+    }
+}
+
+/// a network's reservations, by what they match on
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Reservations {
+    duids: HashMap<Vec<u8>, Reserved>,
+    macs: HashMap<MacAddr, Reserved>,
+    interface_ids: HashMap<Vec<u8>, Reserved>,
+}
+
+impl Reservations {
+    fn from_wire(subnet: Ipv6Net, reservations: Vec<wire::v6::ReservedIp>) -> Result<Self> {
+        let mut this = Self::default();
+        for res in reservations {
+            if !subnet.contains(&res.ip) {
+                bail!("reservation {} must be inside of network {subnet}", res.ip);
+            }
+            let reserved = Reserved {
+                ip: res.ip,
+                valid: res.config.lease_time.into(),
+                preferred: res.config.preferred_time.into(),
+                options: res.options.get(),
+            };
+            match res.condition {
+                wire::v6::Condition::Duid(duid) => {
+                    let duid = hex::decode(duid.replace(':', ""))
+                        .with_context(|| format!("reservation {}: invalid DUID", res.ip))?;
+                    this.duids.insert(duid, reserved);
+                }
+                wire::v6::Condition::DuidLl(mac) => {
+                    this.macs.insert(mac, reserved);
+                }
+                wire::v6::Condition::InterfaceId(id) => {
+                    this.interface_ids.insert(id.into_bytes(), reserved);
+                }
+            }
+        }
+        Ok(this)
+    }
+
+    fn get(&self, duid: &[u8], interface_id: Option<&[u8]>) -> Option<&Reserved> {
+        self.duids
+            .get(duid)
+            .or_else(|| duid_mac(duid).and_then(|mac| self.macs.get(&mac)))
+            .or_else(|| interface_id.and_then(|id| self.interface_ids.get(id)))
+    }
+}
+
+/// ethernet address of a DUID-LLT or DUID-LL, https://www.rfc-editor.org/rfc/rfc8415#section-11.2
+fn duid_mac(duid: &[u8]) -> Option<MacAddr> {
+    match *duid {
+        // type, hardware type, (time), address
+        [0, 1, 0, 1, _, _, _, _, a, b, c, d, e, f] | [0, 3, 0, 1, a, b, c, d, e, f] => {
+            Some(MacAddr::new(a, b, c, d, e, f))
+        }
+        _ => None,
+    }
+}
+
+/// DUID-UUID from a random (version 4) UUID, https://www.rfc-editor.org/rfc/rfc6355
+fn duid_uuid(mut uuid: [u8; 16]) -> Duid {
+    // set version 4 & the RFC 4122 variant
+    uuid[6] = (uuid[6] & 0x0f) | 0x40;
+    uuid[8] = (uuid[8] & 0x3f) | 0x80;
+    let mut duid = vec![0, 4];
+    duid.extend_from_slice(&uuid);
+    Duid::from(duid)
+}
+
 // TODO: replace with is_unicast_global from std when released
 pub const fn is_unicast_global(ip: &Ipv6Addr) -> bool {
     !(ip.is_multicast()
@@ -186,27 +534,30 @@ impl TryFrom<wire::v6::Config> for Config {
             .first()
             .context("must find at least one v6 interface")?;
 
-        // find a link local ipv6 address, then convert that into a Duid
-        let server_id = int
-            .ips
-            .iter()
-            .find_map(|ip| match ip {
-                IpNetwork::V6(ip) if is_unicast_link_local(&ip.ip()) => Some(*ip),
-                _ => None,
-            })
-            .context("unable to find a link local ip")
-            .and_then(|link_local| {
-                // https://www.rfc-editor.org/rfc/rfc8415#section-11.2
-                Ok(Duid::link_layer_time(
-                    // TODO: hardcoded eth type right now
-                    HType::Eth,
-                    SystemTime::now()
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .context("unable to get system time")?
-                        .as_secs() as u32,
-                    link_local.ip(),
-                ))
-            })?;
+        let server_id = match cfg.server_duid {
+            // find a link local ipv6 address, then convert that into a Duid
+            DuidType::Llt => int
+                .ips
+                .iter()
+                .find_map(|ip| match ip {
+                    IpNetwork::V6(ip) if is_unicast_link_local(&ip.ip()) => Some(*ip),
+                    _ => None,
+                })
+                .context("unable to find a link local ip")
+                .and_then(|link_local| {
+                    // https://www.rfc-editor.org/rfc/rfc8415#section-11.2
+                    Ok(Duid::link_layer_time(
+                        // TODO: hardcoded eth type right now
+                        HType::Eth,
+                        SystemTime::now()
+                            .duration_since(SystemTime::UNIX_EPOCH)
+                            .context("unable to get system time")?
+                            .as_secs() as u32,
+                        link_local.ip(),
+                    ))
+                })?,
+            DuidType::Uuid => duid_uuid(rand::random()),
+        };
 
         let global_opts = cfg.options;
         debug!(?interfaces, ?server_id, "v6 interfaces that will be used");
@@ -220,15 +571,25 @@ impl TryFrom<wire::v6::Config> for Config {
                     authoritative,
                     ping_timeout_ms,
                     config,
+                    options_only,
                     options,
                     interfaces: net_interfaces,
+                    ranges,
+                    reservations,
+                    status,
                 } = net;
+                if options_only && !(ranges.is_empty() && reservations.is_empty()) {
+                    bail!("options_only network {subnet} can't have ranges or reservations");
+                }
+                if !options_only && config.is_none() {
+                    bail!("network {subnet} needs a `config` with lease times unless it's options_only");
+                }
 
                 // If any interfaces are explicitly set for the network,
                 // find them. If the interface can't be found return an error.
                 let net_interfaces = net_interfaces
                     .map(|net_interfaces| {
-                        let found_interfaces = pnet::datalink::interfaces()
+                        let found_interfaces = interfaces::list()
                             .into_iter()
                             .filter(|e| {
                                 e.is_up() && !e.ips.is_empty() && e.ips.iter().any(|i| i.is_ipv6())
@@ -250,7 +611,15 @@ impl TryFrom<wire::v6::Config> for Config {
                     })
                     .transpose()?;
 
-                let (valid, preferred) = (config.lease_time.into(), config.preferred_time.into());
+                let valid = config.as_ref().map(|config| config.lease_time.into());
+                let preferred = config.map(|config| config.preferred_time.into());
+                let ranges = ranges
+                    .into_iter()
+                    .map(|range| NetRange::from_wire(subnet, range))
+                    .collect::<Result<_>>()
+                    .with_context(|| format!("invalid range in network {subnet}"))?;
+                let reservations = Reservations::from_wire(subnet, reservations)
+                    .with_context(|| format!("invalid reservation in network {subnet}"))?;
 
                 let network = Network {
                     interfaces: net_interfaces,
@@ -261,6 +630,10 @@ impl TryFrom<wire::v6::Config> for Config {
                     probation_period: Duration::from_secs(probation_period),
                     authoritative,
                     ping_timeout_ms: Duration::from_millis(ping_timeout_ms),
+                    ranges,
+                    reservations,
+                    status,
+                    options_only,
                     // merge global with network opts OR just return network options if no global exist
                     options: match &global_opts {
                         Some(a) => merge_opts(a.as_ref(), options.get()),
@@ -276,6 +649,170 @@ impl TryFrom<wire::v6::Config> for Config {
             networks,
             opts: global_opts.map(|o| o.get()),
             server_id,
+            ra_sync: cfg.ra_sync.map(|ra| ra.path),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(except: &str) -> Result<NetRange> {
+        let range = serde_yaml::from_str::<wire::v6::IpRange>(&format!(
+            r#"
+start: "2001:db8:1::100"
+end: "2001:db8:1::1ff"
+config:
+    lease_time:
+        default: 3600
+    preferred_time:
+        default: 3600
+except: {except}
+"#
+        ))?;
+        NetRange::from_wire("2001:db8:1::/64".parse()?, range)
+    }
+
+    #[test]
+    fn test_duid_uuid() {
+        let duid = duid_uuid([0xff; 16]);
+        let duid: &[u8] = duid.as_ref();
+        assert_eq!(duid.len(), 18);
+        assert_eq!(&duid[..2], &[0, 4]);
+        assert_eq!(duid[2 + 6] >> 4, 4);
+        assert_eq!(duid[2 + 8] >> 6, 0b10);
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let range = range(r#"["2001:db8:1::100", "2001:db8:1::180/123"]"#).unwrap();
+        assert!(!range.contains(&"2001:db8:1::100".parse().unwrap()));
+        assert!(range.contains(&"2001:db8:1::101".parse().unwrap()));
+        assert!(!range.contains(&"2001:db8:1::18f".parse().unwrap()));
+        assert!(!range.contains(&"2001:db8:1::19f".parse().unwrap()));
+        assert!(range.contains(&"2001:db8:1::1a0".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_status_policy() {
+        let policy = serde_yaml::from_str::<StatusPolicy>(
+            r#"
+use_multicast: false
+messages:
+    no_addrs_avail: pool is full
+"#,
+        )
+        .unwrap();
+        assert!(!policy.use_multicast);
+        // unset fields keep their defaults
+        assert!(policy.not_on_link);
+        assert_eq!(
+            policy.messages.no_addrs_avail.as_deref(),
+            Some("pool is full")
+        );
+        assert_eq!(policy.messages.no_binding, None);
+    }
+
+    #[test]
+    fn test_other_config() {
+        let opts = serde_yaml::from_str::<wire::v6::Options>(
+            r#"
+values:
+    23:
+        type: ip_list
+        value: ["2001:db8::53", "2001:db8::54"]
+    24:
+        type: domain_list
+        value: ["lan.example.com.", "example.com."]
+"#,
+        )
+        .unwrap()
+        .get();
+        let data = other_config("2001:db8::/64".parse().unwrap(), &opts);
+        assert_eq!(
+            data.dns_servers,
+            vec![
+                "2001:db8::53".parse::<Ipv6Addr>().unwrap(),
+                "2001:db8::54".parse().unwrap()
+            ]
+        );
+        assert_eq!(data.domains, vec!["lan.example.com.", "example.com."]);
+
+        let data = other_config("2001:db8::/64".parse().unwrap(), &DhcpOptions::new());
+        assert!(data.dns_servers.is_empty() && data.domains.is_empty());
+    }
+
+    #[test]
+    fn test_options_only() {
+        let net = serde_yaml::from_str::<wire::v6::Net>(
+            r#"
+options_only: true
+options:
+    values:
+        23:
+            type: ip_list
+            value: ["2001:db8::53"]
+"#,
+        )
+        .unwrap();
+        assert!(net.options_only);
+        // no lease times needed
+        assert_eq!(net.config, None);
+        assert!(net.ranges.is_empty() && net.reservations.is_empty());
+    }
+
+    #[test]
+    fn test_reservations() {
+        let reservations = serde_yaml::from_str::<Vec<wire::v6::ReservedIp>>(
+            r#"
+- ip: "2001:db8:1::10"
+  match:
+      duid: "00:01:00:01:2b:3c:4d:5e:00:11:22:33:44:55"
+  config: &config
+      lease_time:
+          default: 3600
+      preferred_time:
+          default: 3600
+- ip: "2001:db8:1::11"
+  match:
+      duid_ll: "00:11:22:33:44:66"
+  config: *config
+- ip: "2001:db8:1::12"
+  match:
+      interface_id: "eth0/1"
+  config: *config
+"#,
+        )
+        .unwrap();
+        let subnet = "2001:db8:1::/64".parse().unwrap();
+        let res = Reservations::from_wire(subnet, reservations.clone()).unwrap();
+        let ip = |duid: &[u8], id: &str| {
+            let id = Some(id.as_bytes()).filter(|id| !id.is_empty());
+            res.get(duid, id).map(|res| res.ip().to_string())
+        };
+
+        let llt = [
+            0, 1, 0, 1, 0x2b, 0x3c, 0x4d, 0x5e, 0, 0x11, 0x22, 0x33, 0x44,
+        ];
+        let duid = [&llt[..], &[0x55][..]].concat();
+        assert_eq!(ip(&duid, "eth0/1").unwrap(), "2001:db8:1::10");
+        // a new DUID-LLT or a DUID-LL with the reserved address
+        let duid = [&llt[..], &[0x66][..]].concat();
+        assert_eq!(ip(&duid, "").unwrap(), "2001:db8:1::11");
+        let duid = [0, 3, 0, 1, 0, 0x11, 0x22, 0x33, 0x44, 0x66];
+        assert_eq!(ip(&duid, "eth0/1").unwrap(), "2001:db8:1::11");
+        assert_eq!(ip(&[0, 4, 1, 2], "eth0/1").unwrap(), "2001:db8:1::12");
+        assert_eq!(ip(&[0, 4, 1, 2], "eth0/2"), None);
+
+        let outside = "2001:db8:2::/64".parse().unwrap();
+        assert!(Reservations::from_wire(outside, reservations).is_err());
+    }
+
+    #[test]
+    fn test_exclusion_outside_range() {
+        assert!(range(r#"["2001:db8:1::1"]"#).is_err());
+        assert!(range(r#"["2001:db8:1::100/120"]"#).is_ok());
+        assert!(range(r#"["2001:db8:1::100/119"]"#).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! VLANs of a trunk port
+//!
+//! Each entry of `vlans` gives dora an address on a VLAN tagged on a trunk
+//! interface. Clients are served on the kernel's subinterface for the VLAN,
+//! whatever it's named, from the network containing that address. The
+//! subinterface needs no address of its own: if it has none, it's used as if
+//! it had the configured one & replies are written to a raw socket bound to it,
+//! so they're sent from that address.
+use std::collections::HashSet;
+
+use anyhow::{bail, Context, Result};
+use dora_core::pnet::{
+    datalink::NetworkInterface,
+    ipnetwork::{IpNetwork, Ipv4Network},
+};
+
+use crate::wire;
+
+/// where linux lists its VLAN subinterfaces
+const PROC_VLANS: &str = "/proc/net/vlan/config";
+
+/// a VLAN dora serves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vlan {
+    /// the VLAN ID
+    pub id: u16,
+    /// the trunk interface the VLAN is tagged on
+    pub parent: String,
+    /// the subinterface for the VLAN
+    pub interface: NetworkInterface,
+    /// dora's address on the VLAN
+    pub address: Ipv4Network,
+    /// the subinterface has no v4 address, replies are sent on a raw socket
+    pub unnumbered: bool,
+}
+
+/// find the subinterface of each of `vlans`
+pub fn resolve(vlans: Vec<wire::Vlan>) -> Result<Vec<Vlan>> {
+    if vlans.is_empty() {
+        return Ok(Vec::new());
+    }
+    let proc = std::fs::read_to_string(PROC_VLANS)
+        .with_context(|| format!("failed to read {PROC_VLANS}, is the 8021q module loaded?"))?;
+    let subinterfaces = parse_proc(&proc);
+    let interfaces = interfaces::list();
+    let mut seen = HashSet::new();
+    vlans
+        .into_iter()
+        .map(|vlan| {
+            let (id, parent) = (vlan.id, &vlan.interface);
+            if !(1..=4094).contains(&id) {
+                bail!("vlans: {id} isn't a VLAN ID, they're 1-4094");
+            }
+            if !seen.insert((parent.clone(), id)) {
+                bail!("vlans: VLAN {id} on {parent} is listed twice");
+            }
+            let name = subinterfaces
+                .iter()
+                .find(|(_, vid, link)| *vid == id && link == parent)
+                .map(|(name, _, _)| name)
+                .with_context(|| {
+                    format!(
+                        "vlans: {parent} has no subinterface for VLAN {id}, create one with \
+                         `ip link add link {parent} name {parent}.{id} type vlan id {id}`"
+                    )
+                })?;
+            let mut interface = interfaces
+                .iter()
+                .find(|int| &int.name == name && int.is_up())
+                .cloned()
+                .with_context(|| format!("vlans: subinterface {name} isn't up"))?;
+            let address = Ipv4Network::new(vlan.address.addr(), vlan.address.prefix_len())?;
+            let unnumbered = !interface.ips.iter().any(IpNetwork::is_ipv4);
+            if unnumbered {
+                interface.ips.push(IpNetwork::V4(address));
+            }
+            Ok(Vlan {
+                id: vlan.id,
+                parent: vlan.interface,
+                interface,
+                address,
+                unnumbered,
+            })
+        })
+        .collect()
+}
+
+/// the `(name, VLAN ID, parent)` of each subinterface in `/proc/net/vlan/config`
+fn parse_proc(text: &str) -> Vec<(String, u16, String)> {
+    text.lines()
+        // a header & the name type
+        .skip(2)
+        .filter_map(|line| {
+            let mut fields = line.split('|').map(str::trim);
+            Some((
+                fields.next()?.to_owned(),
+                fields.next()?.parse().ok()?,
+                fields.next()?.to_owned(),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc() {
+        let proc = "VLAN Dev name	 | VLAN ID\nName-Type: VLAN_NAME_TYPE_RAW_PLUS_VID_NO_PAD\neth0.100       | 100  | eth0\nguests         | 200  | bond0\n";
+        assert_eq!(
+            parse_proc(proc),
+            [
+                ("eth0.100".to_owned(), 100, "eth0".to_owned()),
+                ("guests".to_owned(), 200, "bond0".to_owned()),
+            ]
+        );
+        assert!(parse_proc("VLAN Dev name	 | VLAN ID\n").is_empty());
+    }
+}
@@ -2,7 +2,7 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::wire::v4::Options;
+use crate::wire::v4::{Boot, Options};
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct ClientClasses {
@@ -13,5 +13,10 @@ pub struct ClientClasses {
 pub struct ClientClass {
     pub(crate) name: String,
     pub(crate) assert: String,
+    /// a class may only set `boot`
+    #[serde(default)]
     pub(crate) options: Options,
+    /// boot header fields for clients in this class
+    #[serde(default, skip_serializing_if = "Boot::is_empty")]
+    pub(crate) boot: Boot,
 }
@@ -0,0 +1,197 @@
+//! durations written as a number in the field's unit, or a string with units
+//! like `"45m"`, `"12h"`, `"7d"` or `"1h30m"`
+//!
+//! Fields keep their integer type & unit, so `lease_time: { default: "12h" }`
+//! is read as `43200` & `ping_timeout_ms: "1s"` as `1000`.
+use std::{fmt, marker::PhantomData, time::Duration};
+
+use serde::{de, Deserialize, Deserializer};
+
+/// parse a duration string, a sequence of numbers each followed by `ms`, `s`,
+/// `m`, `h` or `d`
+pub(crate) fn parse(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err("empty duration".to_owned());
+    }
+    let mut total = Duration::ZERO;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let n = rest[..digits]
+            .parse::<u64>()
+            .map_err(|_| format!("invalid duration {s:?}, expected ex. \"12h\" or \"1h30m\""))?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let part = match &rest[..unit] {
+            "ms" => Duration::from_millis(n),
+            "s" => Duration::from_secs(n),
+            "m" => Duration::from_secs(n.saturating_mul(60)),
+            "h" => Duration::from_secs(n.saturating_mul(60 * 60)),
+            "d" => Duration::from_secs(n.saturating_mul(24 * 60 * 60)),
+            "" => return Err(format!("duration {s:?} is missing a unit")),
+            other => return Err(format!("unknown unit {other:?} in duration {s:?}")),
+        };
+        total = total.saturating_add(part);
+        rest = &rest[unit..];
+    }
+    Ok(total)
+}
+
+/// whole seconds, from a number of seconds or a duration string
+pub(crate) fn secs<'de, D, T>(de: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    convert(Secs::deserialize(de)?.0)
+}
+
+/// optional [`secs`], needs `#[serde(default)]` for the field to be left out
+pub(crate) fn opt_secs<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    Option::<Secs>::deserialize(de)?
+        .map(|Secs(n)| convert(n))
+        .transpose()
+}
+
+/// milliseconds, from a number of milliseconds or a duration string
+pub(crate) fn millis<'de, D, T>(de: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    convert(Millis::deserialize(de)?.0)
+}
+
+/// optional [`millis`], needs `#[serde(default)]` for the field to be left out
+pub(crate) fn opt_millis<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<u64>,
+{
+    Option::<Millis>::deserialize(de)?
+        .map(|Millis(n)| convert(n))
+        .transpose()
+}
+
+fn convert<T: TryFrom<u64>, E: de::Error>(n: u64) -> Result<T, E> {
+    T::try_from(n).map_err(|_| E::custom(format!("duration {n} is too long")))
+}
+
+struct Secs(u64);
+struct Millis(u64);
+
+impl<'de> Deserialize<'de> for Secs {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        de.deserialize_any(UnitVisitor::<Secs>(PhantomData))
+            .map(Secs)
+    }
+}
+
+impl<'de> Deserialize<'de> for Millis {
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        de.deserialize_any(UnitVisitor::<Millis>(PhantomData))
+            .map(Millis)
+    }
+}
+
+/// the unit a plain number is in
+trait Unit {
+    const NAME: &'static str;
+    /// `None` if `duration` isn't a whole number of the unit
+    fn from_duration(duration: Duration) -> Option<u64>;
+}
+
+impl Unit for Secs {
+    const NAME: &'static str = "seconds";
+    fn from_duration(duration: Duration) -> Option<u64> {
+        (duration.subsec_nanos() == 0).then_some(duration.as_secs())
+    }
+}
+
+impl Unit for Millis {
+    const NAME: &'static str = "milliseconds";
+    fn from_duration(duration: Duration) -> Option<u64> {
+        (duration.subsec_nanos() % 1_000_000 == 0)
+            .then(|| u64::try_from(duration.as_millis()).ok())
+            .flatten()
+    }
+}
+
+struct UnitVisitor<U>(PhantomData<U>);
+
+impl<'de, U: Unit> de::Visitor<'de> for UnitVisitor<U> {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a number of {} or a duration like \"12h\"", U::NAME)
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<u64, E> {
+        Ok(n)
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<u64, E> {
+        u64::try_from(n).map_err(|_| E::custom("duration can't be negative"))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<u64, E> {
+        let duration = parse(s).map_err(E::custom)?;
+        U::from_duration(duration)
+            .ok_or_else(|| E::custom(format!("{s:?} isn't a whole number of {}", U::NAME)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("45m"), Ok(Duration::from_secs(2_700)));
+        assert_eq!(parse("12h"), Ok(Duration::from_secs(43_200)));
+        assert_eq!(parse("7d"), Ok(Duration::from_secs(604_800)));
+        assert_eq!(parse("1h30m"), Ok(Duration::from_secs(5_400)));
+        assert_eq!(parse("250ms"), Ok(Duration::from_millis(250)));
+        assert!(parse("").is_err());
+        assert!(parse("12").is_err());
+        assert!(parse("12y").is_err());
+        assert!(parse("h").is_err());
+    }
+
+    #[test]
+    fn test_deserialize() {
+        #[derive(Deserialize, Debug)]
+        struct Times {
+            #[serde(deserialize_with = "secs")]
+            lease: u32,
+            #[serde(default, deserialize_with = "opt_secs")]
+            max: Option<u32>,
+            #[serde(deserialize_with = "millis")]
+            timeout: u64,
+        }
+        let times: Times = serde_yaml::from_str("{ lease: 12h, timeout: 1s }").unwrap();
+        assert_eq!(
+            (times.lease, times.max, times.timeout),
+            (43_200, None, 1_000)
+        );
+        let times: Times =
+            serde_yaml::from_str("{ lease: 3600, max: \"1d\", timeout: 500 }").unwrap();
+        assert_eq!(
+            (times.lease, times.max, times.timeout),
+            (3_600, Some(86_400), 500)
+        );
+        // seconds can't hold a fraction, u32 can't hold 200 years
+        assert!(serde_yaml::from_str::<Times>("{ lease: 500ms, timeout: 1 }").is_err());
+        assert!(serde_yaml::from_str::<Times>("{ lease: 73000d, timeout: 1 }").is_err());
+        assert!(serde_yaml::from_str::<Times>("{ lease: -1, timeout: 1 }").is_err());
+    }
+}
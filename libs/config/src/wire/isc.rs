@@ -0,0 +1,163 @@
+//! option values written the way isc-dhcp's dhcpd.conf writes them, so they can
+//! be copied over as is. A value is a comma separated list of:
+//!
+//! - addresses, `10.0.0.1, 10.0.0.2`
+//! - quoted strings, with `\"`, `\\`, `\n`, `\r`, `\t`, `\xHH` & octal `\NNN` escapes
+//! - colon separated hex, `01:00:5e:0a`
+//! - `true`/`on` & `false`/`off`
+//!
+//! A trailing `;` is ignored. Plain integers & hostnames aren't accepted, their
+//! encoding depends on the option's definition in dhcpd
+use std::{net::Ipv4Addr, str::Chars};
+
+use anyhow::{bail, Context, Result};
+
+const UNTERMINATED: &str = "unterminated string in ISC option value";
+
+/// one item of a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Item {
+    Ip(Ipv4Addr),
+    Text(Vec<u8>),
+    Bytes(Vec<u8>),
+    Bool(bool),
+}
+
+impl Item {
+    /// the item as it's encoded in the option
+    pub(crate) fn into_bytes(self) -> Vec<u8> {
+        match self {
+            Item::Ip(ip) => ip.octets().to_vec(),
+            Item::Text(bytes) | Item::Bytes(bytes) => bytes,
+            Item::Bool(b) => vec![b.into()],
+        }
+    }
+}
+
+/// parse a dhcpd.conf option value
+pub(crate) fn parse(value: &str) -> Result<Vec<Item>> {
+    let value = value.trim();
+    let value = value.strip_suffix(';').unwrap_or(value);
+    let mut items = Vec::new();
+    let mut chars = value.chars();
+    loop {
+        let item = match skip_space(&mut chars) {
+            Some('"') => Item::Text(quoted(&mut chars)?),
+            Some(c) => {
+                let word = format!("{c}{}", take_while(&mut chars, usize::MAX, |c| c != ','));
+                unquoted(word.trim())?
+            }
+            None => bail!("empty item in ISC option value {value:?}"),
+        };
+        items.push(item);
+        match skip_space(&mut chars) {
+            Some(',') => {}
+            None => return Ok(items),
+            Some(c) => bail!("expected `,` in ISC option value {value:?}, found {c:?}"),
+        }
+    }
+}
+
+fn skip_space(chars: &mut Chars<'_>) -> Option<char> {
+    chars.find(|c| !c.is_whitespace())
+}
+
+/// the rest of a quoted string, after its opening quote
+fn quoted(chars: &mut Chars<'_>) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    loop {
+        match chars.next().context(UNTERMINATED)? {
+            '"' => return Ok(bytes),
+            '\\' => bytes.push(escape(chars)?),
+            c => bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+fn escape(chars: &mut Chars<'_>) -> Result<u8> {
+    Ok(match chars.next().context(UNTERMINATED)? {
+        '"' => b'"',
+        '\\' => b'\\',
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'x' => {
+            let hex = take_while(chars, 2, |c| c.is_ascii_hexdigit());
+            u8::from_str_radix(&hex, 16).with_context(|| format!("bad escape \\x{hex}"))?
+        }
+        c @ '0'..='7' => {
+            let octal = format!("{c}{}", take_while(chars, 2, |c| c.is_digit(8)));
+            u8::from_str_radix(&octal, 8).with_context(|| format!("bad escape \\{octal}"))?
+        }
+        c => bail!("unknown escape \\{c} in ISC option value"),
+    })
+}
+
+/// up to `n` of the next chars matching `pred`
+fn take_while(chars: &mut Chars<'_>, n: usize, pred: impl Fn(char) -> bool) -> String {
+    let mut taken = String::new();
+    for _ in 0..n {
+        match chars.clone().next() {
+            Some(c) if pred(c) => {
+                taken.push(c);
+                chars.next();
+            }
+            _ => break,
+        }
+    }
+    taken
+}
+
+fn unquoted(word: &str) -> Result<Item> {
+    Ok(match word {
+        "true" | "on" => Item::Bool(true),
+        "false" | "off" => Item::Bool(false),
+        _ if word.contains(':') => Item::Bytes(
+            word.split(':')
+                .map(|octet| match octet.len() {
+                    1 | 2 => u8::from_str_radix(octet, 16).ok(),
+                    _ => None,
+                })
+                .collect::<Option<_>>()
+                .with_context(|| format!("bad hex {word:?} in ISC option value"))?,
+        ),
+        _ => match word.parse() {
+            Ok(ip) => Item::Ip(ip),
+            Err(_) => bail!(
+                "can't encode {word:?} from an ISC option value, integers & names \
+                 depend on the option's definition in dhcpd, use a typed value"
+            ),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse("10.0.0.1, 10.0.0.2;").unwrap(),
+            [
+                Item::Ip(Ipv4Addr::new(10, 0, 0, 1)),
+                Item::Ip(Ipv4Addr::new(10, 0, 0, 2))
+            ]
+        );
+        assert_eq!(
+            parse(r#""a, \"b\"\x41\101\n""#).unwrap(),
+            [Item::Text(b"a, \"b\"AA\n".to_vec())]
+        );
+        assert_eq!(
+            parse("1:0:5e:a, on").unwrap(),
+            [Item::Bytes(vec![1, 0, 0x5e, 0xa]), Item::Bool(true)]
+        );
+        assert!(parse("").is_err());
+        assert!(parse("10.0.0.1,").is_err());
+        assert!(parse(r#""unterminated"#).is_err());
+        assert!(parse(r#""a" "b""#).is_err());
+        assert!(parse("1:100").is_err());
+        assert!(parse("600").is_err());
+        assert!(parse("ns1.example.com").is_err());
+    }
+}
@@ -1,32 +1,281 @@
-use std::{collections::HashMap, time::Duration};
+use std::{collections::HashMap, net::Ipv4Addr, path::PathBuf, time::Duration};
 
 use ipnet::Ipv4Net;
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 
 use crate::{wire::client_classes::ClientClasses, LeaseTime};
 
 pub mod client_classes;
+mod duration;
+mod isc;
+mod opt_rules;
 pub mod v4;
 pub mod v6;
 
 /// top-level config type
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Config {
+    /// serve leases, or relay client messages to other servers
+    #[serde(default)]
+    pub mode: Mode,
+    /// upstream servers & the options added in `relay` mode
+    pub relay: Option<Relay>,
     pub interfaces: Option<Vec<String>>,
     #[serde(default = "default_chaddr_only")]
     pub chaddr_only: bool,
     #[serde(default = "default_bootp_enable")]
     pub bootp_enable: bool,
+    /// return the client identifier (opt 61) in replies, RFC 6842
+    #[serde(default = "default_echo_client_id")]
+    pub echo_client_id: bool,
+    /// when a client shows up on a different network, release its leases on
+    /// the networks it left
+    #[serde(default)]
+    pub release_on_move: bool,
+    /// seconds a retransmitted DISCOVER (same client & xid) is answered with
+    /// the OFFER already sent, 0 disables it
+    #[serde(default, deserialize_with = "duration::secs")]
+    pub offer_dedup_window: u64,
+    /// flag clients reusing xids across chaddrs or rotating chaddrs on one
+    /// relay circuit, & optionally drop their messages
+    pub garbage_clients: Option<GarbageClients>,
     #[serde(default)]
     pub networks: HashMap<Ipv4Net, v4::Net>,
     pub v6: Option<v6::Config>,
     pub client_classes: Option<ClientClasses>,
+    /// global v4 options, inherited by every network
+    pub options: Option<v4::Options>,
+    /// named options, referenced by `profiles` in any v4 options block
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub option_profiles: HashMap<String, v4::Options>,
+    /// named time windows, referenced by ranges to change their config
+    #[serde(default)]
+    pub schedules: HashMap<String, Schedule>,
+    /// select the network for relayed packets by giaddr, instead of the network
+    /// containing giaddr
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relay_map: Vec<RelayMap>,
+    /// VLANs of trunk interfaces to serve, with dora's address on each
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub vlans: Vec<Vlan>,
+    /// where ping check echo requests are sent from
+    pub ping_source: Option<PingSource>,
+    /// the account dora switches to once its sockets are bound, `--user`
+    /// takes precedence
+    pub run_as: Option<RunAs>,
+    /// how client identifiers (opt 61) are normalized before they're used to find leases
+    pub client_id_normalize: Option<ClientIdNormalize>,
+    /// routes used to generate router & static route options for each network
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub topology: Vec<Route>,
+    /// DHCP fingerprints file, its entries are used before the built-in ones
+    pub fingerprint_db: Option<PathBuf>,
+}
+
+/// the same defaults as an empty config file, for building a config in code
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::default(),
+            relay: None,
+            interfaces: None,
+            chaddr_only: default_chaddr_only(),
+            bootp_enable: default_bootp_enable(),
+            echo_client_id: default_echo_client_id(),
+            release_on_move: false,
+            offer_dedup_window: 0,
+            garbage_clients: None,
+            networks: HashMap::new(),
+            v6: None,
+            client_classes: None,
+            options: None,
+            option_profiles: HashMap::new(),
+            schedules: HashMap::new(),
+            relay_map: Vec::new(),
+            vlans: Vec::new(),
+            ping_source: None,
+            run_as: None,
+            client_id_normalize: None,
+            topology: Vec::new(),
+            fingerprint_db: None,
+        }
+    }
+}
+
+/// what dora runs as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// a DHCP server
+    #[default]
+    Server,
+    /// a relay agent forwarding to the servers in `relay`
+    Relay,
+}
+
+/// relay agent settings, used with `mode: relay`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Relay {
+    /// upstream servers, an address or `address:port`. v4 messages are relayed
+    /// to the v4 ones & v6 messages to the v6 ones
+    pub servers: Vec<String>,
+    /// add the receiving interface's name, as the circuit id (opt 82 sub-option 1)
+    /// & the v6 Interface-ID option
+    #[serde(default = "default_interface_id")]
+    pub interface_id: bool,
+    /// added as the remote id (opt 82 sub-option 2) & the v6 Remote-ID option
+    pub remote_id: Option<String>,
+    /// enterprise number of the v6 Remote-ID option
+    #[serde(default)]
+    pub enterprise_number: u32,
+}
+
+pub const fn default_interface_id() -> bool {
+    true
+}
+
+/// limits of the garbage client heuristics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GarbageClients {
+    /// seconds a client is remembered
+    #[serde(
+        default = "default_garbage_window",
+        deserialize_with = "duration::secs"
+    )]
+    pub window: u64,
+    /// most chaddrs that can send the same xid
+    #[serde(default = "default_xid_chaddrs")]
+    pub xid_chaddrs: usize,
+    /// most chaddrs that can be relayed from the same opt 82 circuit
+    #[serde(default = "default_circuit_chaddrs")]
+    pub circuit_chaddrs: usize,
+    /// drop messages from flagged clients, instead of only reporting them
+    #[serde(default)]
+    pub block: bool,
+}
+
+pub const fn default_garbage_window() -> u64 {
+    60
+}
+
+pub const fn default_xid_chaddrs() -> usize {
+    2
+}
+
+pub const fn default_circuit_chaddrs() -> usize {
+    8
+}
+
+/// `prefix` is reachable through `gateway`, the route is sent to networks containing `gateway`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Route {
+    /// destination, `0.0.0.0/0` is the default route
+    #[serde(deserialize_with = "ip_or_net")]
+    pub prefix: Ipv4Net,
+    pub gateway: Ipv4Addr,
+}
+
+/// source of ICMP echo requests, by default the kernel picks one from the routing table
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct PingSource {
+    /// source address of echo requests
+    pub address: Option<Ipv4Addr>,
+    /// interface to send echo requests on
+    pub interface: Option<String>,
+}
+
+/// account dora runs as after binding its sockets, linux only
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RunAs {
+    /// user name
+    pub user: String,
+    /// group name, defaults to the primary group of `user`
+    pub group: Option<String>,
+}
+
+/// rewrites applied to client identifiers, so the same device gets the same lease key
+/// when its OS is reinstalled or it changes how it builds the id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct ClientIdNormalize {
+    /// drop the hardware type byte from `[htype][hw addr]` ids, so they match `chaddr`
+    #[serde(default)]
+    pub strip_hw_type: bool,
+    /// lowercase non-hardware (type 0) ids
+    #[serde(default)]
+    pub case_fold: bool,
+    /// how RFC 4361 node-specific ids (`[255][iaid][duid]`) are keyed
+    #[serde(default)]
+    pub node_specific: NodeSpecific,
+}
+
+/// lease key for RFC 4361 node-specific client ids
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeSpecific {
+    /// the whole id
+    #[default]
+    Keep,
+    /// the DUID, ignoring the IAID
+    Duid,
+    /// the link-layer address of a DUID-LLT or DUID-LL, otherwise the DUID
+    LinkLayer,
+}
+
+/// relayed packets with a giaddr in `giaddr` use `network`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RelayMap {
+    /// a giaddr or a CIDR block of giaddrs
+    #[serde(deserialize_with = "ip_or_net")]
+    pub giaddr: Ipv4Net,
+    /// subnet of a network in `networks`
+    pub network: Ipv4Net,
+}
+
+/// a VLAN tagged on a trunk interface, served from the network containing `address`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Vlan {
+    /// the trunk interface, ex. `eth0`
+    pub interface: String,
+    /// the VLAN ID
+    pub id: u16,
+    /// dora's address on the VLAN with its prefix length, ex. `10.0.100.1/24`.
+    /// The subinterface doesn't need to have it
+    pub address: Ipv4Net,
+}
+
+/// an address is treated as a /32
+fn ip_or_net<'de, D>(de: D) -> Result<Ipv4Net, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(de)?;
+    s.parse::<Ipv4Net>()
+        .or_else(|_| s.parse::<Ipv4Addr>().map(Ipv4Net::from))
+        .map_err(de::Error::custom)
+}
+
+/// a recurring time window
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Schedule {
+    /// start time "HH:MM"
+    pub start: String,
+    /// end time "HH:MM", if before `start` the window ends the next day
+    pub end: String,
+    /// days the window starts on ex. ["mon", "tue"], empty means every day
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// IANA timezone name, defaults to UTC
+    pub timezone: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub struct MinMax {
+    /// seconds, or a duration like "12h"
+    #[serde(deserialize_with = "duration::secs")]
     pub default: u32,
+    #[serde(default, deserialize_with = "duration::opt_secs")]
     pub min: Option<u32>,
+    #[serde(default, deserialize_with = "duration::opt_secs")]
     pub max: Option<u32>,
 }
 
@@ -42,6 +291,14 @@ pub const fn default_probation() -> u64 {
     86_400
 }
 
+pub const fn default_offer_hold() -> u64 {
+    60
+}
+
+pub const fn default_sticky_hold() -> u64 {
+    7_776_000
+}
+
 pub const fn default_chaddr_only() -> bool {
     false
 }
@@ -50,6 +307,10 @@ pub const fn default_bootp_enable() -> bool {
     false
 }
 
+pub const fn default_echo_client_id() -> bool {
+    true
+}
+
 impl From<MinMax> for LeaseTime {
     fn from(lease_time: MinMax) -> Self {
         let default = Duration::from_secs(lease_time.default as u64);
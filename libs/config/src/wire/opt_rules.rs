@@ -0,0 +1,148 @@
+//! length & value constraints for v4 option payloads (RFC 2132, RFC 3442, ..).
+//! Options are checked when the config is loaded so a bad payload is a config
+//! error instead of a malformed packet. Codes without a rule aren't checked
+use anyhow::{bail, Result};
+
+/// what a valid payload looks like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rule {
+    /// exactly `n` bytes
+    Len(usize),
+    /// a non-empty list of `n` byte items
+    Multiple(usize),
+    /// at least `n` bytes
+    Min(usize),
+    /// one byte, `0` or `1`
+    Bool,
+    /// one byte, one of the listed values
+    OneOf(&'static [u8]),
+    /// a `u16` no less than the value
+    MinU16(u16),
+}
+
+/// DHCP message types, RFC 2132, RFC 4388 & RFC 6926
+const MSG_TYPES: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8, 10, 11, 12, 13, 14, 15, 16, 17, 18];
+
+fn rule(code: u8) -> Option<(&'static str, Rule)> {
+    use Rule::*;
+    Some(match code {
+        1 => ("subnet mask", Len(4)),
+        2 => ("time offset", Len(4)),
+        3 => ("router", Multiple(4)),
+        4..=11 => ("server list", Multiple(4)),
+        12 => ("hostname", Min(1)),
+        13 => ("boot file size", Len(2)),
+        14 | 17 | 18 => ("path", Min(1)),
+        15 => ("domain name", Min(1)),
+        16 => ("swap server", Len(4)),
+        19 | 20 | 27 | 29 | 30 | 31 | 34 | 36 | 39 => ("flag", Bool),
+        21 => ("policy filter", Multiple(8)),
+        22 => ("max datagram reassembly size", MinU16(576)),
+        23 | 37 => ("ttl", Len(1)),
+        24 | 35 | 38 => ("timeout", Len(4)),
+        25 => ("path MTU plateau table", Multiple(2)),
+        26 => ("interface MTU", MinU16(68)),
+        28 | 32 => ("address", Len(4)),
+        33 => ("static route", Multiple(8)),
+        40 | 47 | 64 | 66 | 67 => ("name", Min(1)),
+        41 | 42 | 44 | 45 | 48 | 49 | 65 | 69..=76 | 150 => ("server list", Multiple(4)),
+        46 => ("NetBIOS node type", OneOf(&[1, 2, 4, 8])),
+        50 => ("requested address", Len(4)),
+        51 => ("lease time", Len(4)),
+        52 => ("option overload", OneOf(&[1, 2, 3])),
+        53 => ("message type", OneOf(MSG_TYPES)),
+        54 => ("server identifier", Len(4)),
+        55 => ("parameter request list", Min(1)),
+        56 | 60 => ("message", Min(1)),
+        57 => ("max message size", MinU16(576)),
+        58 => ("renewal time", Len(4)),
+        59 => ("rebinding time", Len(4)),
+        61 => ("client identifier", Min(2)),
+        116 => ("auto-configure", OneOf(&[0, 1])),
+        118 => ("subnet selection", Len(4)),
+        121 => ("classless static route", Min(5)),
+        _ => return None,
+    })
+}
+
+/// check the payload of option `code`
+pub(crate) fn check(code: u8, data: &[u8]) -> Result<()> {
+    if code == 0 || code == 255 {
+        bail!("option {code} is reserved (pad/end) and can't be configured");
+    }
+    let (name, rule) = match rule(code) {
+        Some(rule) => rule,
+        None => return Ok(()),
+    };
+    let len = data.len();
+    match rule {
+        Rule::Len(n) if len != n => {
+            bail!("option {code} ({name}) must be {n} bytes, got {len}")
+        }
+        Rule::Multiple(n) if len == 0 || len % n != 0 => {
+            bail!("option {code} ({name}) must be a non-empty multiple of {n} bytes, got {len}")
+        }
+        Rule::Min(n) if len < n => {
+            bail!("option {code} ({name}) must be at least {n} bytes, got {len}")
+        }
+        Rule::Bool if len != 1 || data[0] > 1 => {
+            bail!("option {code} ({name}) must be a bool (0 or 1), got {data:?}")
+        }
+        Rule::OneOf(values) if len != 1 || !values.contains(&data[0]) => {
+            bail!("option {code} ({name}) must be one of {values:?}, got {data:?}")
+        }
+        Rule::MinU16(min) => match <[u8; 2]>::try_from(data) {
+            Ok(n) if u16::from_be_bytes(n) >= min => Ok(()),
+            Ok(n) => bail!(
+                "option {code} ({name}) must be at least {min}, got {}",
+                u16::from_be_bytes(n)
+            ),
+            Err(_) => bail!("option {code} ({name}) must be 2 bytes, got {len}"),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// the payload of an encoded option, joining the chunks of a long option (RFC 3396)
+pub(crate) fn payload(mut buf: &[u8]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    while let [_code, len, rest @ ..] = buf {
+        let len = *len as usize;
+        if rest.len() < len {
+            bail!("option is truncated");
+        }
+        data.extend_from_slice(&rest[..len]);
+        buf = &rest[len..];
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rules() {
+        assert!(check(1, &[255, 255, 255, 0]).is_ok());
+        assert!(check(1, &[255, 255, 255]).is_err());
+        assert!(check(3, &[192, 168, 0, 1, 192, 168, 0, 2]).is_ok());
+        assert!(check(3, &[192, 168, 0, 1, 192]).is_err());
+        assert!(check(3, &[]).is_err());
+        assert!(check(46, &[8]).is_ok());
+        assert!(check(46, &[3]).is_err());
+        assert!(check(53, &[5]).is_ok());
+        assert!(check(53, &[9]).is_err());
+        assert!(check(19, &[2]).is_err());
+        assert!(check(26, &1500_u16.to_be_bytes()).is_ok());
+        assert!(check(26, &60_u16.to_be_bytes()).is_err());
+        assert!(check(255, &[]).is_err());
+        // no rule
+        assert!(check(253, &[1, 2, 3]).is_ok());
+    }
+
+    #[test]
+    fn test_payload() {
+        assert_eq!(payload(&[3, 2, 1, 2, 3, 1, 3]).unwrap(), vec![1, 2, 3]);
+        assert!(payload(&[3, 4, 1, 2]).is_err());
+    }
+}
@@ -5,12 +5,140 @@
 //! Reservations are supported based on `chaddr`, or `options`. Currently, only a single
 //! options may be specified for a match. There is no AND/OR logic for matching on options.
 //!
+//! `uuid` matches the machine uuid a PXE client sends in option 97, ex.
+//! `4c4c4544-0042-3510-8052-b4c04f4e3032` (hyphens are optional), so bare-metal
+//! machines can be reserved an address before their NIC MACs are known. Classes can
+//! match it with `pkt4.uuid`.
+//!
+//! ## Static hosts
+//!
+//! `static_hosts` lists machines that are addressed by hand (`ip`, `mac` & `options`)
+//! but still ask for their options with INFORM. They are answered with the network's
+//! options & their own, and listed by the api, without taking addresses from a range.
+//! A static host's `ip` must be in the network and outside of every range.
+//!
 //! ## Parameter request options
 //!
 //! Both reservations & ranges can include an options map, if an incoming dhcp msg gets
 //! an IP from that reservation or range, it will also use the corresponding `options`
 //! to respond to any parameter request list values.
 //!
+//! ## Option inheritance
+//!
+//! Options can be defined globally (top-level `options`), per-network, and per
+//! range/reservation. Options are inherited from the parent level, with the child
+//! overriding any duplicate values. A `remove` list of option codes can be provided
+//! to drop an inherited option at that level.
+//!
+//! ## Option profiles
+//!
+//! `option_profiles` defines named options blocks. Any `options` block (global,
+//! network, range, reservation or schedule) can list them in `profiles` instead of
+//! repeating the values. Profiles are applied in order before the block's own
+//! `values` & `remove`, and a profile can include other profiles the same way.
+//!
+//! ## Vendor templates
+//!
+//! Option 43 can be written as `type: vendor` with a named `template` instead of a
+//! hex blob or hand built sub-options. `unifi`, `aruba` & `mitel` take a single
+//! `controller_ip`, `cisco-ap` takes a list of them, and `mitel` an optional `vlan`.
+//! Each expands to the encoding the vendor's devices expect.
+//!
+//! ## ISC option syntax
+//!
+//! Any option can be written as `type: isc` with its value as it appears in an
+//! isc-dhcp `dhcpd.conf`, i.e. `10.0.0.1, 10.0.0.2`, `"pxelinux.0"` or `01:02:0a`,
+//! so existing snippets can be copied over. Items are encoded in order: addresses as
+//! 4 bytes, quoted strings (with `\"`, `\xHH` & octal escapes) as their bytes,
+//! colon separated hex as is & `true`/`false` as a byte. Quoted names for the domain
+//! search list (119) are DNS encoded. Plain integers & hostnames are rejected, their
+//! size depends on the option in dhcpd, use a typed value for them.
+//!
+//! ## Hostname conflicts
+//!
+//! When `hostname_conflict` is set on a network, dora tracks the hostname each client
+//! claims (option 81 client FQDN, falling back to option 12 hostname). If a client
+//! claims a name already held by another client, the policy decides the outcome:
+//! `reject` won't register the name for the new client, `suffix` assigns the first
+//! free name from `name-2`, `name-3`..., and `replace` moves the name to the new client.
+//!
+//! ## Generated hostnames
+//!
+//! `hostname_template` on a network registers a hostname for clients that don't send
+//! one, i.e. `dhcp-{ip_dashed}.guest.example.com`. `{ip_dashed}` is the leased address
+//! with dashes, `{ip_last}` its last octet & `{mac}` the client's hardware address in
+//! hex. If another client holds the name, the first free one of `dhcp-192-168-0-10-2`,
+//! `-3`... is registered, the domain is kept.
+//!
+//! ## Exclusions
+//!
+//! `except` on a range takes addresses or CIDR blocks that will never be handed out.
+//! `reserve_first_n`/`reserve_last_n` exclude that many addresses from the start/end
+//! of the range, i.e. `reserve_first_n: 1` on a range starting at the network address.
+//!
+//! ## Address hints
+//!
+//! A client can ask for an address in DISCOVER with the requested IP option (50).
+//! `honor_hints` on the range holding that address decides if it's offered:
+//! `if-free` (the default) offers it when no other client holds it, unless the client
+//! has a sticky lease, `always` also prefers it over the client's sticky lease, and
+//! `never` ignores it so addresses are always handed out in order.
+//!
+//! ## Schedules
+//!
+//! `schedules` defines named time windows. A range can list `schedule` entries
+//! referencing them to change its lease time, options, or close the range to new
+//! allocations while the window is active. Schedules are evaluated at allocation time.
+//!
+//! ## NAK policy
+//!
+//! An authoritative network NAKs REQUESTs it can't satisfy. `nak` controls which
+//! cases get a NAK & which are silently dropped: `unknown_client` (no lease for the
+//! client or the address is held by someone else), `expired_lease` (the client's lease
+//! has expired) and `wrong_network` (the requested address is outside of the network).
+//! `message` is sent in option 56 with every NAK to help troubleshooting on the client.
+//! Non-authoritative networks never NAK.
+//!
+//! ## Relay allowlist
+//!
+//! `allowed_relays` restricts which relay agents can act for a network. A packet with a
+//! `giaddr` selecting the network that is not in the list is dropped, so a rogue relay
+//! can't claim to be on another segment & exhaust its pools. When unset, any relay is
+//! accepted. Packets without a `giaddr` are not affected.
+//!
+//! ## Secondary subnets
+//!
+//! An interface can have addresses on several subnets, each with its own entry in
+//! `networks`. A client that isn't relayed is served from the network matching the
+//! subnet selection option (118) or the address it's renewing/requesting, when one of
+//! the interface's addresses is on that subnet. Otherwise the interface's first
+//! address picks the network. The server id, router & broadcast address follow the
+//! selected subnet.
+//!
+//! ## Forcerenew
+//!
+//! `forcerenew_nonce` on a network sends a random nonce (RFC 6704) in the ACK to
+//! clients that list HMAC-MD5 in option 145. The nonce is stored with the lease &
+//! used to authenticate FORCERENEW messages sent from the external api, so clients
+//! pick up config changes (ex. new DNS servers) without waiting for T1.
+//!
+//! ## System DNS
+//!
+//! `inherit_system_dns` on a network sends the host's name servers in option 6 & its
+//! NTP servers in option 42, read from resolv.conf (systemd-resolved's upstream list
+//! when it's running) & chrony's config each time the config is loaded. This suits edge
+//! routers whose upstream DNS changes. They replace global options 6 & 42, options set
+//! on the network or its ranges still win. Loopback addresses & NTP hostnames are
+//! skipped.
+//!
+//! ## Sticky leases
+//!
+//! `sticky` on a network promotes a client's dynamic lease to a reservation stored in
+//! the lease database after `after_renewals` renewals of the same address. The address
+//! isn't given to anyone else for `hold` seconds after the client's last lease, and the
+//! client keeps it even if the network's ranges are changed so it's no longer in one.
+//! Leases can also be made sticky with `POST /v4/sticky` on the external api.
+//!
 //! ## Ping check
 //!
 //! `ping_check` set to true will ping before assigning an IP
@@ -22,6 +150,61 @@
 //! (meaning the address is in use), dora will not attempt to lease the IP inside of
 //! the probation period.
 //!
+//! ## Offer hold
+//!
+//! `offer_hold` is how long (seconds, default 60) an address is held for a client after
+//! an OFFER. If no REQUEST arrives inside of that time the address goes back to the pool,
+//! and the expired offer is counted in the `offer_expired` metric.
+//!
+//! A client that doesn't hear the OFFER retransmits its DISCOVER with the same xid.
+//! Setting the top level `offer_dedup_window` (seconds, default 0 which disables it)
+//! answers those retransmissions with the OFFER already sent, while the address is
+//! still held, instead of running allocation & ping checks again.
+//!
+//! ## Boot server
+//!
+//! `next_server` (siaddr), `server_name` (sname) and `file_name` (file) can be set on
+//! a network, and overridden with a `boot` block on a range, a reservation, or a client
+//! class. Ranges & reservations take precedence over classes, which take precedence over
+//! the network. Options 66 & 67 are set with `options` as usual.
+//!
+//! ## iPXE chainloading
+//!
+//! A PXE ROM that loads iPXE from `file_name` is followed by a second DHCP exchange,
+//! this time from iPXE. Sending it the same file would load iPXE again, in a loop.
+//! Clients that send option 175 or the `iPXE` user class (opt 77) are sent
+//! `ipxe_file_name` instead, usually a script url like `http://10.0.0.1/boot.ipxe`.
+//! `ipxe_file_name` can be set anywhere `file_name` can, if no level sets one iPXE
+//! clients are sent `file_name` as before.
+//!
+//! ## Vendor class ranges
+//!
+//! A range's `vendor_class` only gives its addresses to clients whose vendor class
+//! identifier (option 60) contains that string, ex. `vendor_class: "Cisco Systems"`
+//! for a range of VoIP phones. The match is case sensitive. It works like a `class`
+//! without having to define a client class, so a range can set one or the other.
+//!
+//! ## Fingerprints
+//!
+//! Clients are fingerprinted by their parameter request list (option 55) & vendor
+//! class (option 60). Known fingerprints identify a device & its category, ex.
+//! `printer`, `camera` or `phone`, which client classes can match with
+//! `pkt4.category == 'printer'`. A few common devices are built in, the top level
+//! `fingerprint_db` loads more from a file & its entries are used first.
+//!
+//! ## VLANs
+//!
+//! `vlans` serves the VLANs of a trunk interface, each one from the network containing
+//! its `address`. The 802.1Q subinterfaces must exist & be up, they're found by trunk
+//! & VLAN ID. A subinterface without a v4 address is unnumbered, `address` is used for
+//! it & replies are sent on a raw socket so they come from that address.
+//!
+//! ## Relay mode
+//!
+//! With the top level `mode: relay` dora is a relay agent instead of a server. Clients
+//! are forwarded to the `relay.servers`, v4 with giaddr & opt 82, v6 encapsulated in
+//! Relay-Forward with the Interface-ID & Remote-ID options. `networks` isn't used.
+//!
 //! ## Chaddr Only
 //!
 //! Normally, client id is determined by (opt 60) client identifier, if it is
@@ -29,6 +212,15 @@
 //! the server to only look at the `chaddr` field. Setting `chaddr_only` to true
 //! will do that.
 //!
+//! ## Client id normalization
+//!
+//! `client_id_normalize` rewrites opt 61 before it's used to find a client's lease, so
+//! a device keeps its lease across an OS reinstall. `strip_hw_type` turns
+//! `[htype][hw addr]` ids into the bare address, matching clients keyed by `chaddr`.
+//! `case_fold` lowercases type 0 (non-hardware) ids. `node_specific` keys RFC 4361
+//! ids by the whole id (`keep`), the DUID without the IAID (`duid`), or the MAC from a
+//! DUID-LLT/DUID-LL (`link_layer`).
+//!
 //! ## BOOTP enable
 //!
 //! Enable BOOTP for dora, only support for RFC1497.
@@ -51,6 +243,7 @@ use dora_core::{
     },
     pnet::util::MacAddr,
 };
+use ipnet::Ipv4Net;
 use serde::{de, Deserialize, Deserializer, Serialize};
 use tracing::warn;
 use trust_dns_proto::{
@@ -58,7 +251,7 @@ use trust_dns_proto::{
     serialize::binary::{BinEncodable, BinEncoder},
 };
 
-use crate::wire::MinMax;
+use crate::wire::{isc, opt_rules, MinMax};
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Net {
@@ -67,21 +260,293 @@ pub struct Net {
     pub ranges: Vec<IpRange>,
     #[serde(default)]
     pub reservations: Vec<ReservedIp>,
+    /// manually addressed hosts, sent options in reply to INFORM
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub static_hosts: Vec<StaticHost>,
     /// ping check is an optional value, when turned on an ICMP echo request will be sent
     /// before OFFER for this network
     #[serde(default)]
     pub ping_check: bool,
     /// default ping timeout in ms
-    #[serde(default = "super::default_ping_to")]
+    #[serde(
+        default = "super::default_ping_to",
+        deserialize_with = "super::duration::millis"
+    )]
     pub ping_timeout_ms: u64,
+    /// pings sent after the first times out before the address is considered free
+    #[serde(default)]
+    pub ping_retries: u32,
     /// probation period in seconds
-    #[serde(default = "super::default_probation")]
+    #[serde(
+        default = "super::default_probation",
+        deserialize_with = "super::duration::secs"
+    )]
     pub probation_period: u64,
+    /// seconds an OFFER holds the address for the client's REQUEST
+    #[serde(
+        default = "super::default_offer_hold",
+        deserialize_with = "super::duration::secs"
+    )]
+    pub offer_hold: u64,
     /// Whether we are authoritative for this network (default: true)
     #[serde(default = "super::default_authoritative")]
     pub authoritative: bool,
+    /// siaddr sent to clients, the next server to use in bootstrap (default: server_id)
+    pub next_server: Option<Ipv4Addr>,
     pub server_name: Option<String>,
     pub file_name: Option<String>,
+    /// file sent to clients already running iPXE, in place of `file_name`
+    pub ipxe_file_name: Option<String>,
+    /// options inherited by all ranges & reservations in this network
+    pub options: Option<Options>,
+    /// track client hostnames & resolve conflicts with this policy
+    pub hostname_conflict: Option<HostnamePolicy>,
+    /// hostname registered for clients that don't send one, ex.
+    /// `dhcp-{ip_dashed}.guest.example.com`
+    pub hostname_template: Option<String>,
+    /// if set, only relays with a giaddr in this list are accepted for this network
+    pub allowed_relays: Option<Vec<Ipv4Addr>>,
+    /// when to NAK a REQUEST
+    #[serde(default)]
+    pub nak: NakPolicy,
+    /// option order & padding of replies, for clients that need a specific layout
+    #[serde(default)]
+    pub reply: ReplyFormat,
+    /// send a FORCERENEW nonce (RFC 6704) in ACKs to clients that support it
+    #[serde(default)]
+    pub forcerenew_nonce: bool,
+    /// promote stable clients' leases to reservations
+    pub sticky: Option<StickyLeases>,
+    /// fill options 6 & 42 from the host's resolv.conf & chrony config when the
+    /// config is loaded
+    #[serde(default)]
+    pub inherit_system_dns: bool,
+}
+
+/// the same defaults as an empty network in a config file
+impl Default for Net {
+    fn default() -> Self {
+        Self {
+            server_id: None,
+            ranges: Vec::new(),
+            reservations: Vec::new(),
+            static_hosts: Vec::new(),
+            ping_check: false,
+            ping_timeout_ms: super::default_ping_to(),
+            ping_retries: 0,
+            probation_period: super::default_probation(),
+            offer_hold: super::default_offer_hold(),
+            authoritative: super::default_authoritative(),
+            next_server: None,
+            server_name: None,
+            file_name: None,
+            ipxe_file_name: None,
+            options: None,
+            hostname_conflict: None,
+            hostname_template: None,
+            allowed_relays: None,
+            nak: NakPolicy::default(),
+            reply: ReplyFormat::default(),
+            forcerenew_nonce: false,
+            sticky: None,
+            inherit_system_dns: false,
+        }
+    }
+}
+
+/// BOOTP header fields used to direct a client's network boot, i.e. to a TFTP server.
+/// Unset fields are inherited from the level above
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Boot {
+    /// siaddr
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_server: Option<Ipv4Addr>,
+    /// sname
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server_name: Option<String>,
+    /// file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_name: Option<String>,
+    /// file sent to clients already running iPXE, in place of `file_name`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipxe_file_name: Option<String>,
+}
+
+impl Boot {
+    pub fn is_empty(&self) -> bool {
+        self.next_server.is_none()
+            && self.server_name.is_none()
+            && self.file_name.is_none()
+            && self.ipxe_file_name.is_none()
+    }
+
+    /// fill any unset fields from `other`
+    pub fn or(self, other: &Boot) -> Boot {
+        Boot {
+            next_server: self.next_server.or(other.next_server),
+            server_name: self.server_name.or_else(|| other.server_name.clone()),
+            file_name: self.file_name.or_else(|| other.file_name.clone()),
+            ipxe_file_name: self.ipxe_file_name.or_else(|| other.ipxe_file_name.clone()),
+        }
+    }
+
+    /// the fields sent to an iPXE client, `ipxe_file_name` replaces `file_name`
+    /// so the chainloaded iPXE isn't sent its own image again
+    pub fn ipxe(self) -> Boot {
+        Boot {
+            file_name: self.ipxe_file_name.or(self.file_name),
+            ipxe_file_name: None,
+            ..self
+        }
+    }
+
+    /// set the configured header fields on `msg`, unset fields are left as is
+    pub fn apply(&self, msg: &mut v4::Message) {
+        if let Some(ip) = self.next_server {
+            msg.set_siaddr(ip);
+        }
+        if let Some(sname) = &self.server_name {
+            msg.set_sname_str(sname);
+        }
+        if let Some(fname) = &self.file_name {
+            msg.set_fname_str(fname);
+        }
+    }
+}
+
+/// how replies are encoded
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ReplyFormat {
+    /// order options are written in (default: numeric)
+    pub option_order: OptionOrder,
+    /// pad replies with zeros up to this many bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_size: Option<u16>,
+    /// which configured options are sent (default: requested)
+    pub options: OptionFilter,
+    /// option codes sent with `options: mandatory` whether they were requested or not
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub mandatory: Vec<u8>,
+    /// options stripped or rewritten in replies to relayed requests
+    #[serde(skip_serializing_if = "OptionRules::is_empty")]
+    pub relayed: OptionRules,
+    /// options stripped or rewritten in replies sent straight to the client
+    #[serde(skip_serializing_if = "OptionRules::is_empty")]
+    pub direct: OptionRules,
+}
+
+/// options removed from & set in replies, after the configured options are added
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct OptionRules {
+    /// option codes removed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<u8>,
+    /// options set, replacing any the reply already has
+    pub set: Opts,
+}
+
+impl OptionRules {
+    pub fn is_empty(&self) -> bool {
+        self.remove.is_empty() && self.set.0.iter().next().is_none()
+    }
+}
+
+/// which configured options are sent in replies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionFilter {
+    /// only options in the client's parameter request list (opt 55)
+    #[default]
+    Requested,
+    /// requested options & the `mandatory` list
+    Mandatory,
+    /// every configured option, for clients that don't send a parameter request list
+    All,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionOrder {
+    /// ascending option code
+    #[default]
+    Numeric,
+    /// message type, then the order of the client's parameter request list (opt 55)
+    RequestList,
+}
+
+/// which failed REQUESTs an authoritative network will NAK, the rest get no response
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NakPolicy {
+    /// no lease is held by the client, or the address belongs to another client (default: true)
+    pub unknown_client: bool,
+    /// the client's lease has expired (default: true)
+    pub expired_lease: bool,
+    /// the requested address isn't in the network's subnet (default: false)
+    pub wrong_network: bool,
+    /// sent in option 56 (message) with the NAK
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Default for NakPolicy {
+    fn default() -> Self {
+        Self {
+            unknown_client: true,
+            expired_lease: true,
+            wrong_network: false,
+            message: None,
+        }
+    }
+}
+
+/// when a dynamic lease becomes a reservation held in the lease database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StickyLeases {
+    /// renewals of the same address before the lease is made sticky, unset only
+    /// promotes leases through the external api
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_renewals: Option<u32>,
+    /// seconds the address is held after the client's last lease (default: 90 days)
+    #[serde(
+        default = "super::default_sticky_hold",
+        deserialize_with = "super::duration::secs"
+    )]
+    pub hold: u64,
+}
+
+/// what to do when a client claims a hostname held by another client
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostnamePolicy {
+    /// don't register the hostname for the new client
+    Reject,
+    /// register the first free `<name>-N`, starting at 2
+    Suffix,
+    /// the new client takes over the hostname
+    Replace,
+}
+
+/// when to offer the address a client asks for in DISCOVER
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HintPolicy {
+    /// offer it if no other client holds it, even over the client's sticky lease
+    Always,
+    /// offer it if no other client holds it, after the client's sticky lease
+    #[default]
+    IfFree,
+    /// ignore it & offer the next free address
+    Never,
+}
+
+impl HintPolicy {
+    fn is_default(&self) -> bool {
+        *self == HintPolicy::default()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
@@ -89,11 +554,66 @@ pub struct IpRange {
     // RangeInclusive includes `start`/`end` so flatten will parse those fields
     #[serde(flatten)]
     pub range: RangeInclusive<Ipv4Addr>,
+    #[serde(default)]
     pub options: Options,
     pub config: NetworkConfig,
     #[serde(default)]
-    pub except: Vec<Ipv4Addr>,
+    pub except: Vec<Exclusion>,
+    /// exclude this many addresses from the start of the range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserve_first_n: Option<u32>,
+    /// exclude this many addresses from the end of the range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reserve_last_n: Option<u32>,
     pub class: Option<String>,
+    /// only clients whose vendor class identifier (option 60) contains this
+    /// string get addresses from this range, in place of a `class`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor_class: Option<String>,
+    /// config changes applied while a schedule is active, first match wins
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<ScheduledConfig>,
+    /// override the network's `ping_check` for this range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ping_check: Option<bool>,
+    /// override the network's `ping_timeout_ms` for this range
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "super::duration::opt_millis"
+    )]
+    pub ping_timeout_ms: Option<u64>,
+    /// override the network's `ping_retries` for this range
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ping_retries: Option<u32>,
+    /// when a DISCOVER's requested address (option 50) in this range is offered
+    #[serde(default, skip_serializing_if = "HintPolicy::is_default")]
+    pub honor_hints: HintPolicy,
+    /// boot header fields for clients in this range
+    #[serde(default, skip_serializing_if = "Boot::is_empty")]
+    pub boot: Boot,
+}
+
+/// an address or CIDR block excluded from a range
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Exclusion {
+    Ip(Ipv4Addr),
+    Net(Ipv4Net),
+}
+
+/// range config used inside of a schedule's time window
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScheduledConfig {
+    /// name of a schedule in `schedules`
+    pub name: String,
+    pub lease_time: Option<MinMax>,
+    /// options applied on top of the range options
+    #[serde(default)]
+    pub options: Options,
+    /// when true, no new addresses are allocated from the range
+    #[serde(default)]
+    pub closed: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
@@ -101,9 +621,16 @@ pub struct NetworkConfig {
     pub lease_time: MinMax,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
 pub struct Options {
+    /// names of `option_profiles` applied before `values`, in order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profiles: Vec<String>,
+    #[serde(default)]
     pub values: Opts,
+    /// option codes that should not be inherited from the parent level
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub remove: Vec<u8>,
 }
 
 impl Options {
@@ -127,11 +654,24 @@ impl From<Options> for DhcpOptions {
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct ReservedIp {
     pub ip: Ipv4Addr,
+    #[serde(default)]
     pub options: Options,
     #[serde(rename = "match")]
     pub condition: Condition,
     pub config: NetworkConfig,
     pub class: Option<String>,
+    /// boot header fields for this reservation
+    #[serde(default, skip_serializing_if = "Boot::is_empty")]
+    pub boot: Boot,
+}
+
+/// a host with a manually configured address outside of any range
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct StaticHost {
+    pub ip: Ipv4Addr,
+    pub mac: MacAddr,
+    #[serde(default)]
+    pub options: Options,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
@@ -140,9 +680,13 @@ pub enum Condition {
     #[serde(rename = "chaddr")]
     Mac(MacAddr),
     Options(Options),
+    /// matches if any instance in the client's user class option (77) is equal
+    UserClass(String),
+    /// matches the machine uuid in the client machine identifier option (97)
+    Uuid(String),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct Opts(pub DhcpOptions);
 
 /// this type is only used as an intermediate representation
@@ -163,6 +707,58 @@ enum Opt {
     B64(String),
     Hex(String),
     SubOption(HashMap<u8, Opt>),
+    /// V-I vendor-specific information (option 125), sub-options keyed by
+    /// enterprise number
+    VendorSubOption(HashMap<u32, HashMap<u8, Opt>>),
+    /// user class (option 77), RFC 3004 encoded as a list of `[len][class]`
+    UserClass(Vec<String>),
+    /// vendor specific info (option 43) built from a named template
+    Vendor(VendorTemplate),
+    /// a value in isc-dhcp's dhcpd.conf syntax, see [`isc`]
+    Isc(String),
+}
+
+/// option 43 encodings for common vendors' APs & phones, so the controller
+/// address doesn't have to be written as a hex blob
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "template", rename_all = "kebab-case", deny_unknown_fields)]
+enum VendorTemplate {
+    /// UniFi devices, sub-option 1 is the controller address
+    Unifi { controller_ip: Ipv4Addr },
+    /// Aruba APs, the controller address as text
+    Aruba { controller_ip: Ipv4Addr },
+    /// Cisco lightweight APs, sub-option 241 is the list of WLC addresses
+    CiscoAp { controller_ip: Vec<Ipv4Addr> },
+    /// Mitel IP phones, a `;` separated list of `key=value` settings
+    Mitel {
+        controller_ip: Ipv4Addr,
+        vlan: Option<u16>,
+    },
+}
+
+impl VendorTemplate {
+    /// the option this template stands for
+    fn expand(self) -> Opt {
+        match self {
+            VendorTemplate::Unifi { controller_ip } => {
+                Opt::SubOption(HashMap::from([(1, Opt::Ip(controller_ip))]))
+            }
+            VendorTemplate::Aruba { controller_ip } => Opt::Str(controller_ip.to_string()),
+            VendorTemplate::CiscoAp { controller_ip } => {
+                Opt::SubOption(HashMap::from([(241, Opt::IpList(controller_ip))]))
+            }
+            VendorTemplate::Mitel {
+                controller_ip,
+                vlan,
+            } => {
+                let mut s = format!("id:ipphone.mitel.com;call_srv={controller_ip};");
+                if let Some(vlan) = vlan {
+                    s.push_str(&format!("vlan={vlan};"));
+                }
+                Opt::Str(s)
+            }
+        }
+    }
 }
 
 impl<'de> serde::Deserialize<'de> for Opts {
@@ -174,13 +770,17 @@ impl<'de> serde::Deserialize<'de> for Opts {
         let map: HashMap<u8, Opt> = Deserialize::deserialize(de)?;
         // we'll encode the map to buf so we can use DhcpOptions::decode
         let mut buf = vec![];
-        let mut enc = Encoder::new(&mut buf);
         for (code, opt) in map {
-            write_opt(&mut enc, code, opt).map_err(de::Error::custom)?;
+            let mut opt_buf = vec![];
+            write_opt(&mut Encoder::new(&mut opt_buf), code, opt).map_err(de::Error::custom)?;
+            // reject payloads that would be malformed on the wire
+            opt_rules::payload(&opt_buf)
+                .and_then(|data| opt_rules::check(code, &data))
+                .map_err(de::Error::custom)?;
+            buf.extend(opt_buf);
         }
         // write `End` so DhcpOptions can decode
-        enc.write_u8(OptionCode::End.into())
-            .map_err(de::Error::custom)?;
+        buf.push(OptionCode::End.into());
 
         // buffer now has binary data for DhcpOptions -- decode it
         let opts = DhcpOptions::decode(&mut Decoder::new(&buf)).map_err(de::Error::custom)?;
@@ -260,6 +860,58 @@ fn write_opt(enc: &mut Encoder<'_>, code: u8, opt: Opt) -> anyhow::Result<()> {
 
             v4::encode_long_opt_bytes(OptionCode::from(code), &sub_buf, enc)?;
         }
+        Opt::VendorSubOption(vendors) => {
+            // [enterprise: u32][data-len: u8][sub-opts...] for each enterprise
+            let mut buf = vec![];
+            let mut vendor_enc = Encoder::new(&mut buf);
+            for (enterprise, sub_opts) in vendors {
+                let mut sub_buf = vec![];
+                let mut sub_enc = Encoder::new(&mut sub_buf);
+                for (sub_code, sub_opt) in sub_opts {
+                    write_opt(&mut sub_enc, sub_code, sub_opt)?;
+                }
+                let len = u8::try_from(sub_buf.len()).map_err(|_| {
+                    anyhow::anyhow!("vendor {enterprise} sub-options exceed 255 bytes")
+                })?;
+                vendor_enc.write_u32(enterprise)?;
+                vendor_enc.write_u8(len)?;
+                vendor_enc.write_slice(&sub_buf)?;
+            }
+            v4::encode_long_opt_bytes(OptionCode::from(code), &buf, enc)?;
+        }
+        Opt::UserClass(classes) => {
+            let mut buf = vec![];
+            for class in classes {
+                let len = u8::try_from(class.len())
+                    .map_err(|_| anyhow::anyhow!("user class {class} exceeds 255 bytes"))?;
+                buf.push(len);
+                buf.extend_from_slice(class.as_bytes());
+            }
+            v4::encode_long_opt_bytes(OptionCode::from(code), &buf, enc)?;
+        }
+        Opt::Vendor(template) => {
+            if code != u8::from(OptionCode::VendorExtensions) {
+                anyhow::bail!("vendor templates are only valid for option 43, not {code}");
+            }
+            write_opt(enc, code, template.expand())?;
+        }
+        Opt::Isc(value) => {
+            let items = isc::parse(&value)?;
+            let is_text = |item: &isc::Item| matches!(item, isc::Item::Text(_));
+            // dhcpd writes the domain search list (119) as strings, it's sent DNS encoded
+            let opt = if code == 119 && items.iter().all(is_text) {
+                Opt::DomainList(
+                    items
+                        .into_iter()
+                        .map(|item| String::from_utf8(item.into_bytes()))
+                        .collect::<Result<_, _>>()?,
+                )
+            } else {
+                let bytes = items.into_iter().flat_map(isc::Item::into_bytes);
+                Opt::Hex(hex::encode(bytes.collect::<Vec<_>>()))
+            };
+            write_opt(enc, code, opt)?;
+        }
     }
     Ok(())
 }
@@ -332,6 +984,24 @@ fn to_opt(code: &OptionCode, opt: &DhcpOption) -> Option<(u8, Opt)> {
         BootFileSize(num) | MaxDatagramSize(num) | InterfaceMtu(num) | MaxMessageSize(num) => {
             Some(((*code).into(), Opt::U16(*num)))
         }
+        Unknown(opt) if u8::from(*code) == 125 => {
+            let opt = match vendor_sub_opts(opt.data()) {
+                Some(vendors) => Opt::VendorSubOption(
+                    vendors
+                        .into_iter()
+                        .map(|(enterprise, sub_opts)| {
+                            let sub_opts = sub_opts
+                                .into_iter()
+                                .map(|(sub_code, data)| (sub_code, Opt::Hex(hex::encode(data))))
+                                .collect();
+                            (enterprise, sub_opts)
+                        })
+                        .collect(),
+                ),
+                None => Opt::Hex(hex::encode(opt.data())),
+            };
+            Some(((*code).into(), opt))
+        }
         Unknown(opt) => Some(((*code).into(), Opt::Hex(hex::encode(opt.data())))),
         _ => {
             // the data includes the code & len, let's slice that off
@@ -354,14 +1024,51 @@ fn to_opt(code: &OptionCode, opt: &DhcpOption) -> Option<(u8, Opt)> {
     }
 }
 
+/// split V-I vendor-specific info (option 125) back into its
+/// `[enterprise: u32][data-len: u8][sub-opts...]` groups, `None` if it's
+/// malformed
+fn vendor_sub_opts(data: &[u8]) -> Option<HashMap<u32, HashMap<u8, Vec<u8>>>> {
+    let mut vendors = HashMap::new();
+    let mut i = 0;
+    while i < data.len() {
+        let enterprise = u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?);
+        let len = *data.get(i + 4)? as usize;
+        let sub_data = data.get(i + 5..i + 5 + len)?;
+        let sub_opts: &mut HashMap<u8, Vec<u8>> = vendors.entry(enterprise).or_default();
+        let mut j = 0;
+        while j < sub_data.len() {
+            let sub_code = sub_data[j];
+            let sub_len = *sub_data.get(j + 1)? as usize;
+            sub_opts.insert(sub_code, sub_data.get(j + 2..j + 2 + sub_len)?.to_vec());
+            j += 2 + sub_len;
+        }
+        i += 5 + len;
+    }
+    Some(vendors)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use ipnet::Ipv4Net;
 
     pub static SAMPLE_YAML: &str = include_str!("../../sample/config.yaml");
     pub static LONG_OPTS: &str = include_str!("../../sample/long_opts.yaml");
 
+    #[test]
+    fn test_nak_policy() {
+        let policy: NakPolicy = serde_yaml::from_str("message: wrong network").unwrap();
+        assert_eq!(
+            policy,
+            NakPolicy {
+                message: Some("wrong network".to_owned()),
+                ..NakPolicy::default()
+            }
+        );
+        let policy: NakPolicy =
+            serde_yaml::from_str("{ unknown_client: false, wrong_network: true }").unwrap();
+        assert!(!policy.unknown_client && policy.expired_lease && policy.wrong_network);
+    }
+
     // test we can encode/decode sample
     #[test]
     fn test_sample() {
@@ -372,6 +1079,26 @@ mod tests {
         println!("{s}");
     }
 
+    #[test]
+    fn test_net_default() {
+        assert_eq!(serde_yaml::from_str::<Net>("{}").unwrap(), Net::default());
+    }
+
+    #[test]
+    fn test_hint_policy() {
+        let range: IpRange = serde_yaml::from_str(
+            "{ start: 10.0.0.1, end: 10.0.0.9, config: { lease_time: { default: 60 } } }",
+        )
+        .unwrap();
+        assert_eq!(range.honor_hints, HintPolicy::IfFree);
+        let range: IpRange = serde_yaml::from_str(
+            "{ start: 10.0.0.1, end: 10.0.0.9, config: { lease_time: { default: 60 } }, honor_hints: never }",
+        )
+        .unwrap();
+        assert_eq!(range.honor_hints, HintPolicy::Never);
+        assert!(serde_yaml::from_str::<HintPolicy>("if_free").is_err());
+    }
+
     #[test]
     fn test_long_opts() {
         let cfg: crate::wire::Config = serde_yaml::from_str(LONG_OPTS).unwrap();
@@ -390,4 +1117,163 @@ mod tests {
         println!("{vendor:?}");
         // TODO: add test for sub-opts in vendor extensions
     }
+
+    #[test]
+    fn test_vendor_sub_opts() {
+        let opts: Opts = serde_yaml::from_str(
+            r#"
+            125:
+                type: vendor_sub_option
+                value:
+                    4491:
+                        1:
+                            type: str
+                            value: foo
+                        2:
+                            type: u8
+                            value: 9
+            "#,
+        )
+        .unwrap();
+        let opt = opts.0.get(OptionCode::from(125)).unwrap().to_vec().unwrap();
+        // [code][len][enterprise][data-len][sub-opts]
+        assert_eq!(&opt[..2], &[125, 13]);
+        assert_eq!(&opt[2..6], &4491_u32.to_be_bytes());
+        assert_eq!(opt[6], 8);
+        let sub_opts = &opt[7..];
+        assert!(sub_opts.windows(5).any(|w| w == [1, 3, b'f', b'o', b'o']));
+        assert!(sub_opts.windows(3).any(|w| w == [2, 1, 9]));
+    }
+
+    #[test]
+    fn test_vendor_sub_opts_round_trip() {
+        let opts: Opts = serde_yaml::from_str(
+            r#"
+            125:
+                type: vendor_sub_option
+                value:
+                    4491:
+                        1:
+                            type: str
+                            value: foo
+                        2:
+                            type: u8
+                            value: 9
+                    3561:
+                        3:
+                            type: ip
+                            value: 10.0.0.1
+            "#,
+        )
+        .unwrap();
+        let yaml = serde_yaml::to_string(&opts).unwrap();
+        assert!(yaml.contains("vendor_sub_option"));
+        let round_trip: Opts = serde_yaml::from_str(&yaml).unwrap();
+
+        // sub-options are written in map order, compare what they decode to
+        let decode = |opts: &Opts| match opts.0.get(OptionCode::from(125)).unwrap() {
+            DhcpOption::Unknown(opt) => vendor_sub_opts(opt.data()).unwrap(),
+            opt => panic!("unexpected {opt:?}"),
+        };
+        let vendors = decode(&round_trip);
+        assert_eq!(vendors, decode(&opts));
+        assert_eq!(vendors[&4491][&1], b"foo");
+        assert_eq!(vendors[&4491][&2], [9]);
+        assert_eq!(vendors[&3561][&3], [10, 0, 0, 1]);
+
+        // anything that isn't enterprise groups is left as hex
+        assert!(vendor_sub_opts(&[0, 0, 0x11, 0x8b, 5, 1, 3]).is_none());
+    }
+
+    #[test]
+    fn test_vendor_templates() {
+        let opts: Opts = serde_yaml::from_str(
+            r#"
+            43:
+                type: vendor
+                value:
+                    template: unifi
+                    controller_ip: 10.0.0.5
+            "#,
+        )
+        .unwrap();
+        let opt = opts.0.get(OptionCode::from(43)).unwrap().to_vec().unwrap();
+        assert_eq!(opt, [43, 6, 1, 4, 10, 0, 0, 5]);
+
+        let opts: Opts = serde_yaml::from_str(
+            r#"
+            43:
+                type: vendor
+                value:
+                    template: cisco-ap
+                    controller_ip: [10.0.0.5, 10.0.0.6]
+            "#,
+        )
+        .unwrap();
+        let opt = opts.0.get(OptionCode::from(43)).unwrap().to_vec().unwrap();
+        assert_eq!(opt, [43, 10, 241, 8, 10, 0, 0, 5, 10, 0, 0, 6]);
+
+        let opts: Opts = serde_yaml::from_str(
+            "43: { type: vendor, value: { template: aruba, controller_ip: 10.0.0.5 } }",
+        )
+        .unwrap();
+        let opt = opts.0.get(OptionCode::from(43)).unwrap().to_vec().unwrap();
+        assert_eq!(&opt[2..], b"10.0.0.5");
+
+        let opts: Opts = serde_yaml::from_str(
+            "43: { type: vendor, value: { template: mitel, controller_ip: 10.0.0.5, vlan: 20 } }",
+        )
+        .unwrap();
+        let opt = opts.0.get(OptionCode::from(43)).unwrap().to_vec().unwrap();
+        assert_eq!(
+            &opt[2..],
+            b"id:ipphone.mitel.com;call_srv=10.0.0.5;vlan=20;"
+        );
+
+        // templates only encode option 43
+        assert!(serde_yaml::from_str::<Opts>(
+            "125: { type: vendor, value: { template: unifi, controller_ip: 10.0.0.5 } }"
+        )
+        .is_err());
+        // unknown fields are likely typos
+        assert!(serde_yaml::from_str::<Opts>(
+            "43: { type: vendor, value: { template: unifi, controller: 10.0.0.5 } }"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_isc_opts() {
+        let opts: Opts = serde_yaml::from_str(
+            r#"
+            3: { type: isc, value: "10.0.0.1, 10.0.0.2;" }
+            67: { type: isc, value: '"pxelinux.0"' }
+            119: { type: isc, value: '"example.com", "corp.example.com"' }
+            43: { type: isc, value: "01:04:0a:00:00:05" }
+            "#,
+        )
+        .unwrap();
+        let opt = |code: u8| opts.0.get(code.into()).unwrap().to_vec().unwrap();
+        assert_eq!(opt(3), [3, 8, 10, 0, 0, 1, 10, 0, 0, 2]);
+        assert_eq!(&opt(67)[2..], b"pxelinux.0");
+        assert_eq!(&opt(119)[2..15], b"\x07example\x03com\x00");
+        assert_eq!(opt(43), [43, 6, 1, 4, 10, 0, 0, 5]);
+
+        // the router option is a list of addresses
+        assert!(serde_yaml::from_str::<Opts>(r#"3: { type: isc, value: '"router"' }"#).is_err());
+        assert!(serde_yaml::from_str::<Opts>("2: { type: isc, value: '3600' }").is_err());
+    }
+
+    #[test]
+    fn test_invalid_opts() {
+        // NetBIOS node type must be 1, 2, 4 or 8
+        let err = serde_yaml::from_str::<Opts>("46: { type: u8, value: 3 }").unwrap_err();
+        assert!(err.to_string().contains("option 46"), "{err}");
+        // subnet mask is a single address
+        assert!(serde_yaml::from_str::<Opts>(
+            "1: { type: ip_list, value: [255.255.255.0, 255.255.0.0] }"
+        )
+        .is_err());
+        assert!(serde_yaml::from_str::<Opts>("46: { type: u8, value: 8 }").is_ok());
+    }
 }
@@ -1,13 +1,20 @@
 use base64::Engine;
-use dora_core::dhcproto::{
-    v6::{DhcpOption, DhcpOptions, OptionCode},
-    Decodable, Decoder, Encodable, Encoder,
+use dora_core::{
+    dhcproto::{
+        v6::{DhcpOption, DhcpOptions, OptionCode},
+        Decodable, Decoder, Encodable, Encoder,
+    },
+    pnet::util::MacAddr,
 };
 use ipnet::Ipv6Net;
 use serde::{de, Deserialize, Deserializer, Serialize};
 use tracing::warn;
+use trust_dns_proto::{
+    rr,
+    serialize::binary::{BinEncodable, BinEncoder},
+};
 
-use std::{collections::HashMap, net::Ipv6Addr, ops::RangeInclusive};
+use std::{collections::HashMap, net::Ipv6Addr, ops::RangeInclusive, path::PathBuf};
 
 use crate::wire::MinMax;
 
@@ -19,11 +26,42 @@ pub struct Config {
     // TODO: better defaults than blank? pull information from the system
     #[serde(default)]
     pub options: Option<Options>,
+    /// type of DUID generated for the server id on first start
+    #[serde(default)]
+    pub server_duid: DuidType,
+    /// share the DNS servers & domains dora serves with a router advertisement daemon
+    pub ra_sync: Option<RaSync>,
+}
+
+/// where the other-config data (DNS servers & search domains) of each network is
+/// written for an RA daemon, each time the config is loaded
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RaSync {
+    /// JSON file replaced with the data
+    pub path: PathBuf,
+}
+
+/// DUID types dora can generate for its server id
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DuidType {
+    /// DUID-LLT, link-layer address plus time
+    #[default]
+    Llt,
+    /// DUID-UUID (RFC 6355), a random UUID
+    Uuid,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Net {
-    pub config: NetworkConfig,
+    /// lease times, required unless the network is `options_only`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<NetworkConfig>,
+    /// stateless service only (default: false), Information-Request is
+    /// answered with the network's options & all other messages are ignored.
+    /// The network can't have ranges or reservations
+    #[serde(default)]
+    pub options_only: bool,
     #[serde(default)]
     pub options: Options,
     pub interfaces: Option<Vec<String>>,
@@ -33,14 +71,68 @@ pub struct Net {
     #[serde(default)]
     pub ping_check: bool,
     /// default ping timeout in ms
-    #[serde(default = "super::default_ping_to")]
+    #[serde(
+        default = "super::default_ping_to",
+        deserialize_with = "super::duration::millis"
+    )]
     pub ping_timeout_ms: u64,
     /// probation period in seconds
-    #[serde(default = "super::default_probation")]
+    #[serde(
+        default = "super::default_probation",
+        deserialize_with = "super::duration::secs"
+    )]
     pub probation_period: u64,
     /// Whether we are authoritative for this network (default: true)
     #[serde(default = "super::default_authoritative")]
     pub authoritative: bool,
+    /// address pools, must be inside of the network's subnet
+    #[serde(default)]
+    pub ranges: Vec<IpRange>,
+    /// addresses reserved for a single client, must be inside of the network's subnet
+    #[serde(default)]
+    pub reservations: Vec<ReservedIp>,
+    /// which status codes are returned for this network
+    #[serde(default)]
+    pub status: StatusPolicy,
+}
+
+/// when the network replies with a status code instead of dropping the
+/// message, see https://www.rfc-editor.org/rfc/rfc8415#section-21.13
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StatusPolicy {
+    /// Request, Renew, Release & Decline sent to our unicast address get
+    /// UseMulticast (default: true), otherwise they are processed
+    pub use_multicast: bool,
+    /// Request & Confirm with addresses outside of the network get NotOnLink
+    /// (default: true), otherwise Confirm is dropped & Request gets NoAddrsAvail
+    pub not_on_link: bool,
+    /// status message text sent with each code, replaces the default text
+    pub messages: StatusMessages,
+}
+
+impl Default for StatusPolicy {
+    fn default() -> Self {
+        Self {
+            use_multicast: true,
+            not_on_link: true,
+            messages: StatusMessages::default(),
+        }
+    }
+}
+
+/// status message overrides, by status code
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct StatusMessages {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_addrs_avail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub no_binding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_on_link: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_multicast: Option<String>,
 }
 
 // TODO allow configuring server id
@@ -63,10 +155,43 @@ pub struct IpRange {
     // RangeInclusive includes `start`/`end` so flatten will parse those fields
     #[serde(flatten)]
     pub range: RangeInclusive<Ipv6Addr>,
+    #[serde(default)]
     pub options: Options,
     pub config: NetworkConfig,
+    /// addresses & sub-prefixes that will never be leased, must be inside of the range
+    #[serde(default, alias = "exclude")]
+    pub except: Vec<Exclusion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ReservedIp {
+    pub ip: Ipv6Addr,
     #[serde(default)]
-    pub except: Vec<Ipv6Addr>,
+    pub options: Options,
+    #[serde(rename = "match")]
+    pub condition: Condition,
+    pub config: NetworkConfig,
+}
+
+/// how a client is matched to a reservation
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// the client's whole DUID (option 1) as hex
+    Duid(String),
+    /// link-layer address of a DUID-LL or DUID-LLT, so the reservation survives
+    /// the client regenerating a DUID-LLT
+    DuidLl(MacAddr),
+    /// interface-id (option 18) added by the relay the message came through
+    InterfaceId(String),
+}
+
+/// an address or sub-prefix excluded from a range
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Exclusion {
+    Ip(Ipv6Addr),
+    Net(Ipv6Net),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
@@ -103,6 +228,8 @@ pub struct Opts(pub DhcpOptions);
 enum Opt {
     Ip(Ipv6Addr),
     IpList(Vec<Ipv6Addr>),
+    /// list of domains in DNS format, ex. the domain search list (24)
+    DomainList(Vec<String>),
     U8(u8),
     U32(u32),
     U16(u16),
@@ -144,6 +271,18 @@ fn write_opt(enc: &mut Encoder<'_>, code: u16, opt: Opt) -> anyhow::Result<()> {
                 enc.write_u128(ip.into())?;
             }
         }
+        Opt::DomainList(list) => {
+            let mut buf = Vec::new();
+            let mut name_encoder = BinEncoder::new(&mut buf);
+            // RFC 3646, names in v6 options aren't compressed
+            name_encoder.set_canonical_names(true);
+            for name in list {
+                let name = name.parse::<rr::Name>()?;
+                name.emit(&mut name_encoder)?;
+            }
+            enc.write_u16(buf.len() as u16)?;
+            enc.write_slice(&buf)?;
+        }
         Opt::U8(n) => {
             enc.write_u16(1)?;
             enc.write_u8(n)?;
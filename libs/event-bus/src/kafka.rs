@@ -0,0 +1,53 @@
+//! kafka publisher
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rdkafka::{
+    producer::{FutureProducer, FutureRecord},
+    ClientConfig,
+};
+
+use crate::{EventRecord, Publisher};
+
+/// how long a message can wait in the producer queue
+const QUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) struct Kafka {
+    producer: FutureProducer,
+}
+
+impl std::fmt::Debug for Kafka {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Kafka").finish_non_exhaustive()
+    }
+}
+
+impl Kafka {
+    /// `brokers` is a comma separated list of `host:port`
+    pub(crate) fn new(brokers: &str) -> Result<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", "5000")
+            .create()
+            .context("failed to create kafka producer")?;
+        Ok(Self { producer })
+    }
+}
+
+#[async_trait]
+impl Publisher for Kafka {
+    async fn publish(&self, topic: &str, record: &EventRecord) -> Result<()> {
+        let payload = serde_json::to_vec(record)?;
+        let key = record.ip.to_string();
+        self.producer
+            .send(
+                FutureRecord::to(topic).key(&key).payload(&payload),
+                QUEUE_TIMEOUT,
+            )
+            .await
+            .map_err(|(err, _)| err)
+            .context("failed to send to kafka")?;
+        Ok(())
+    }
+}
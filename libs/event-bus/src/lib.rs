@@ -0,0 +1,177 @@
+//! # event-bus
+//!
+//! Publish lease events to a message broker. Brokers are enabled with cargo
+//! features: `kafka` and `nats`. The broker is chosen from the url scheme,
+//! `kafka://host:9092,host2:9092` or `nats://host:4222`.
+//!
+//! Kafka messages are sent to `topic`, keyed by ip. NATS messages are sent to
+//! `<topic>.<event>`, i.e. `dora.leases.expired`, so subscribers can filter by
+//! event type.
+//!
+//! Every message is an [`EventRecord`] encoded as JSON. Fields are only ever
+//! added to the record, a breaking change will increment [`SCHEMA_VERSION`].
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    unreachable_pub,
+    non_snake_case,
+    non_upper_case_globals
+)]
+#![allow(clippy::cognitive_complexity)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+use std::net::IpAddr;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use ip_manager::LeaseEvent;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, warn};
+
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "nats")]
+mod nats;
+
+/// version of the [`EventRecord`] schema
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// the JSON payload published for each lease event
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EventRecord {
+    /// [`SCHEMA_VERSION`]
+    pub version: u32,
+    /// id of the dora instance that published the event
+    pub server_id: String,
+    /// one of `offered`, `leased`, `released`, `probated`, `expired`, `offer_expired`
+    pub event: String,
+    /// the address
+    pub ip: IpAddr,
+    /// client id as hex
+    pub client_id: Option<String>,
+    /// rfc3339 expiry of the lease or probation period
+    pub expires_at: String,
+    /// rfc3339 time of the event
+    pub timestamp: String,
+    /// hardware vendor of the client, only set when an OUI database is loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+}
+
+impl EventRecord {
+    /// create a record for `event` published by `server_id`
+    pub fn new(server_id: &str, event: &LeaseEvent) -> Self {
+        Self {
+            version: SCHEMA_VERSION,
+            server_id: server_id.to_owned(),
+            event: event.kind.as_str().to_owned(),
+            ip: event.ip,
+            client_id: event
+                .id
+                .as_ref()
+                .map(|id| id.iter().map(|b| format!("{b:02x}")).collect()),
+            expires_at: DateTime::<Utc>::from(event.expires_at)
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            timestamp: DateTime::<Utc>::from(event.at).to_rfc3339_opts(SecondsFormat::Secs, true),
+            vendor: event.vendor.clone(),
+        }
+    }
+}
+
+/// a connection to a message broker
+#[async_trait]
+pub trait Publisher: Send + Sync + std::fmt::Debug {
+    /// publish `record` under `topic`
+    async fn publish(&self, topic: &str, record: &EventRecord) -> Result<()>;
+}
+
+/// connect to the broker at `url`
+pub async fn connect(url: &str) -> Result<Box<dyn Publisher>> {
+    let (scheme, addr) = url
+        .split_once("://")
+        .ok_or_else(|| anyhow::anyhow!("event bus url must be kafka://.. or nats://.."))?;
+    match scheme {
+        #[cfg(feature = "kafka")]
+        "kafka" => Ok(Box::new(kafka::Kafka::new(addr)?)),
+        #[cfg(feature = "nats")]
+        "nats" => Ok(Box::new(nats::Nats::connect(url).await?)),
+        #[cfg(not(feature = "kafka"))]
+        "kafka" => {
+            anyhow::bail!("dora was built without the `kafka` feature, can't connect to {addr}")
+        }
+        #[cfg(not(feature = "nats"))]
+        "nats" => {
+            anyhow::bail!("dora was built without the `nats` feature, can't connect to {addr}")
+        }
+        _ => anyhow::bail!("unsupported event bus scheme {scheme}"),
+    }
+}
+
+/// publish events from `rx` until the channel closes. Publish errors are
+/// logged & the event is dropped, a subscriber that lags behind skips the
+/// missed events
+pub async fn run(
+    publisher: Box<dyn Publisher>,
+    mut rx: broadcast::Receiver<LeaseEvent>,
+    topic: String,
+    server_id: String,
+) {
+    info!(?publisher, ?topic, "publishing lease events");
+    loop {
+        match rx.recv().await {
+            Ok(event) => {
+                let record = EventRecord::new(&server_id, &event);
+                if let Err(err) = publisher.publish(&topic, &record).await {
+                    error!(?err, ?record, "failed to publish lease event");
+                } else {
+                    debug!(?record, "published lease event");
+                }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                warn!(?missed, "event bus publisher lagged, dropped events");
+            }
+            Err(RecvError::Closed) => {
+                info!("lease event channel closed, stopping publisher");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use ip_manager::LeaseEventKind;
+
+    use super::*;
+
+    #[test]
+    fn test_record_schema() {
+        let at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let event = LeaseEvent {
+            kind: LeaseEventKind::Leased,
+            ip: "192.168.0.10".parse().unwrap(),
+            id: Some(vec![0xde, 0xad]),
+            expires_at: at + Duration::from_secs(3600),
+            at,
+            vendor: None,
+        };
+        let json = serde_json::to_value(EventRecord::new("dora_id", &event)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "version": 1,
+                "server_id": "dora_id",
+                "event": "leased",
+                "ip": "192.168.0.10",
+                "client_id": "dead",
+                "expires_at": "2023-11-14T23:13:20Z",
+                "timestamp": "2023-11-14T22:13:20Z",
+            })
+        );
+    }
+}
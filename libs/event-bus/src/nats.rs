@@ -0,0 +1,31 @@
+//! NATS publisher
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+
+use crate::{EventRecord, Publisher};
+
+#[derive(Debug)]
+pub(crate) struct Nats {
+    client: async_nats::Client,
+}
+
+impl Nats {
+    pub(crate) async fn connect(url: &str) -> Result<Self> {
+        let client = async_nats::connect(url)
+            .await
+            .with_context(|| format!("failed to connect to nats at {url}"))?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait]
+impl Publisher for Nats {
+    async fn publish(&self, topic: &str, record: &EventRecord) -> Result<()> {
+        let payload = serde_json::to_vec(record)?;
+        self.client
+            .publish(format!("{topic}.{}", record.event), payload.into())
+            .await
+            .context("failed to publish to nats")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,234 @@
+//! # forcerenew
+//!
+//! FORCERENEW (RFC 3203) with nonce authentication (RFC 6704). A client that
+//! supports it lists HMAC-MD5 in option 145 (forcerenew nonce capable), the
+//! server then sends a random nonce in the authentication option (90) of the
+//! ACK. The client only accepts a FORCERENEW carrying an HMAC-MD5 of the whole
+//! message keyed with that nonce.
+//!
+//! <https://www.rfc-editor.org/rfc/rfc6704>
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    unreachable_pub,
+    non_snake_case,
+    non_upper_case_globals
+)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use dora_core::{
+    dhcproto::{
+        v4::{DhcpOption, Message, MessageType, Opcode, OptionCode, UnknownOption},
+        Encodable,
+    },
+    tokio::net::UdpSocket,
+};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+
+/// forcerenew nonce capable option code, RFC 6704
+pub const FORCERENEW_NONCE_CAPABLE: u8 = 145;
+/// authentication option code, RFC 3118
+pub const AUTHENTICATION: u8 = 90;
+/// length of the nonce & of an HMAC-MD5 digest
+pub const NONCE_LEN: usize = 16;
+/// FORCERENEW is sent to the client port
+pub const CLIENT_PORT: u16 = 68;
+
+/// forcerenew nonce authentication protocol
+const PROTOCOL: u8 = 3;
+/// the only algorithm defined for the protocol
+const HMAC_MD5: u8 = 1;
+/// replay detection method: monotonically increasing counter
+const RDM_COUNTER: u8 = 0;
+/// authentication info type in an ACK
+const NONCE_VALUE: u8 = 1;
+/// authentication info type in a FORCERENEW
+const HMAC_MD5_DIGEST: u8 = 2;
+/// protocol, algorithm, RDM & replay detection come before the auth info type
+const AUTH_HEADER_LEN: usize = 11;
+/// fixed header + magic cookie, options start after
+const OPTIONS_START: usize = 240;
+
+type HmacMd5 = Hmac<md5::Md5>;
+
+/// the key a client uses to authenticate FORCERENEW messages
+pub type Nonce = [u8; NONCE_LEN];
+
+/// does `msg` list HMAC-MD5 in option 145?
+pub fn capable(msg: &Message) -> bool {
+    msg.opts()
+        .get(OptionCode::from(FORCERENEW_NONCE_CAPABLE))
+        .and_then(|opt| opt.to_vec().ok())
+        // [code][len][algorithms]
+        .map_or(false, |buf| {
+            buf.get(2..).map_or(false, |algs| algs.contains(&HMAC_MD5))
+        })
+}
+
+/// a new random nonce
+pub fn new_nonce() -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    nonce
+}
+
+/// replay detection value, clients drop messages that don't increase it. Time
+/// based so it keeps increasing across restarts
+pub fn replay_counter() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+/// authentication option sending `nonce` to the client, added to the ACK
+pub fn nonce_option(nonce: &Nonce) -> DhcpOption {
+    auth_option(NONCE_VALUE, nonce)
+}
+
+fn auth_option(info_type: u8, info: &[u8; NONCE_LEN]) -> DhcpOption {
+    let mut data = Vec::with_capacity(AUTH_HEADER_LEN + 1 + NONCE_LEN);
+    data.extend([PROTOCOL, HMAC_MD5, RDM_COUNTER]);
+    data.extend(replay_counter().to_be_bytes());
+    data.push(info_type);
+    data.extend(info);
+    DhcpOption::Unknown(UnknownOption::new(OptionCode::from(AUTHENTICATION), data))
+}
+
+/// a client to send FORCERENEW to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// the client's leased address
+    pub ip: Ipv4Addr,
+    /// hardware address the lease was made with
+    pub chaddr: Vec<u8>,
+    /// option 61 of the client, if it sent one
+    pub client_id: Option<Vec<u8>>,
+    /// server identifier the client got in its ACK
+    pub server_id: Ipv4Addr,
+    /// the nonce sent to the client in its last ACK
+    pub nonce: Nonce,
+}
+
+impl Target {
+    /// encode a FORCERENEW for the client, authenticated with its nonce
+    pub fn message(&self) -> Result<Vec<u8>> {
+        let mut msg = Message::new(
+            self.ip,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            &self.chaddr,
+        );
+        msg.set_opcode(Opcode::BootReply).set_xid(rand::random());
+        let opts = msg.opts_mut();
+        opts.insert(DhcpOption::MessageType(MessageType::ForceRenew));
+        opts.insert(DhcpOption::ServerIdentifier(self.server_id));
+        if let Some(id) = &self.client_id {
+            opts.insert(DhcpOption::ClientIdentifier(id.clone()));
+        }
+        // the digest is calculated over the message with the digest zeroed
+        opts.insert(auth_option(HMAC_MD5_DIGEST, &[0; NONCE_LEN]));
+        let mut buf = msg.to_vec()?;
+        let offset = digest_offset(&buf).context("authentication option not encoded")?;
+        let digest = sign(&self.nonce, &buf)?;
+        buf[offset..offset + NONCE_LEN].copy_from_slice(&digest);
+        Ok(buf)
+    }
+
+    /// send a FORCERENEW to the client
+    pub async fn send(&self, soc: &UdpSocket) -> Result<()> {
+        let buf = self.message()?;
+        soc.send_to(&buf, SocketAddr::from((self.ip, CLIENT_PORT)))
+            .await
+            .with_context(|| format!("failed to send FORCERENEW to {}", self.ip))?;
+        Ok(())
+    }
+}
+
+/// HMAC-MD5 of `buf` keyed with `nonce`
+fn sign(nonce: &Nonce, buf: &[u8]) -> Result<[u8; NONCE_LEN]> {
+    let mut mac = HmacMd5::new_from_slice(nonce).context("invalid HMAC key")?;
+    mac.update(buf);
+    Ok(mac.finalize().into_bytes().into())
+}
+
+/// position of the digest in an encoded message's authentication option
+fn digest_offset(buf: &[u8]) -> Option<usize> {
+    let mut i = OPTIONS_START;
+    loop {
+        match *buf.get(i)? {
+            // pad
+            0 => i += 1,
+            // end
+            255 => return None,
+            code => {
+                let len = *buf.get(i + 1)? as usize;
+                if code == AUTHENTICATION && len == AUTH_HEADER_LEN + 1 + NONCE_LEN {
+                    return Some(i + 2 + AUTH_HEADER_LEN + 1);
+                }
+                i += 2 + len;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use dora_core::dhcproto::{Decodable, Decoder};
+
+    #[test]
+    fn test_capable() {
+        let mut msg = Message::default();
+        assert!(!capable(&msg));
+        msg.opts_mut()
+            .insert(DhcpOption::Unknown(UnknownOption::new(
+                OptionCode::from(FORCERENEW_NONCE_CAPABLE),
+                vec![HMAC_MD5],
+            )));
+        assert!(capable(&msg));
+    }
+
+    #[test]
+    fn test_nonce_option() {
+        let nonce = new_nonce();
+        let buf = nonce_option(&nonce).to_vec().unwrap();
+        assert_eq!(
+            &buf[..5],
+            &[AUTHENTICATION, 28, PROTOCOL, HMAC_MD5, RDM_COUNTER]
+        );
+        assert_eq!(buf[13], NONCE_VALUE);
+        assert_eq!(&buf[14..], &nonce);
+    }
+
+    #[test]
+    fn test_message() {
+        let target = Target {
+            ip: Ipv4Addr::new(192, 168, 0, 10),
+            chaddr: vec![0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff],
+            client_id: None,
+            server_id: Ipv4Addr::new(192, 168, 0, 1),
+            nonce: new_nonce(),
+        };
+        let mut buf = target.message().unwrap();
+        let msg = Message::decode(&mut Decoder::new(&buf)).unwrap();
+        assert_eq!(msg.opts().msg_type(), Some(MessageType::ForceRenew));
+        assert_eq!(msg.ciaddr(), target.ip);
+        assert_eq!(msg.chaddr(), &target.chaddr[..]);
+        // client side check: zero the digest & compare
+        let offset = digest_offset(&buf).unwrap();
+        let digest = buf[offset..offset + NONCE_LEN].to_vec();
+        buf[offset..offset + NONCE_LEN].fill(0);
+        assert_eq!(sign(&target.nonce, &buf).unwrap().to_vec(), digest);
+        assert_ne!(sign(&new_nonce(), &buf).unwrap().to_vec(), digest);
+    }
+}
@@ -5,6 +5,7 @@ mod socket;
 
 pub use crate::errors::Error;
 pub use crate::icmp::{Decode, EchoReply, EchoRequest, Encode, Icmpv4, Icmpv6, ICMP_HEADER_SIZE};
+pub use crate::socket::Source;
 use crate::{icmp::Proto, socket::Socket};
 
 use parking_lot::Mutex;
@@ -164,7 +165,12 @@ macro_rules! impl_icmp {
     ($t:ty) => {
         impl Listener<$t> {
             pub fn new() -> errors::Result<Listener<$t>> {
-                let soc = Arc::new(IcmpEcho::<$t>::new()?);
+                Self::bind(&Source::default())
+            }
+
+            /// create a listener that sends echo requests from `source`
+            pub fn bind(source: &Source) -> errors::Result<Listener<$t>> {
+                let soc = Arc::new(IcmpEcho::<$t>::bind(source)?);
                 // when notify_shutdown is dropped, all pingers will shutdown
                 let (notify_shutdown, _) = broadcast::channel(1);
 
@@ -226,20 +232,25 @@ macro_rules! impl_icmp {
 impl IcmpEcho<Icmpv4> {
     /// create a new ICMPv4 socket
     pub fn new() -> io::Result<Self> {
-        let (inner, decode_header) = match Socket::new(Domain::IPV4, Type::DGRAM, Protocol::ICMPV4)
-        {
-            Ok(s) => (s, false),
-            Err(err) => {
-                error!(
-                    ?err,
-                    "error building DGRAM socket, check ping_group_range. trying RAW socket"
-                );
-                (
-                    Socket::new(Domain::IPV4, Type::RAW, Protocol::ICMPV4)?,
-                    true,
-                )
-            }
-        };
+        Self::bind(&Source::default())
+    }
+
+    /// create a new ICMPv4 socket sending from `source`
+    pub fn bind(source: &Source) -> io::Result<Self> {
+        let (inner, decode_header) =
+            match Socket::bind(Domain::IPV4, Type::DGRAM, Protocol::ICMPV4, source) {
+                Ok(s) => (s, false),
+                Err(err) => {
+                    error!(
+                        ?err,
+                        "error building DGRAM socket, check ping_group_range. trying RAW socket"
+                    );
+                    (
+                        Socket::bind(Domain::IPV4, Type::RAW, Protocol::ICMPV4, source)?,
+                        true,
+                    )
+                }
+            };
         debug!("created new icmpv4 socket");
         Ok(Self {
             inner,
@@ -252,20 +263,25 @@ impl IcmpEcho<Icmpv4> {
 impl IcmpEcho<Icmpv6> {
     /// create a new ICMPv6 socket
     pub fn new() -> io::Result<Self> {
-        let (inner, decode_header) = match Socket::new(Domain::IPV6, Type::DGRAM, Protocol::ICMPV6)
-        {
-            Ok(s) => (s, false),
-            Err(err) => {
-                warn!(
-                    ?err,
-                    "error building DGRAM socket, check ping_group_range. trying RAW socket"
-                );
-                (
-                    Socket::new(Domain::IPV6, Type::RAW, Protocol::ICMPV6)?,
-                    true,
-                )
-            }
-        };
+        Self::bind(&Source::default())
+    }
+
+    /// create a new ICMPv6 socket sending from `source`
+    pub fn bind(source: &Source) -> io::Result<Self> {
+        let (inner, decode_header) =
+            match Socket::bind(Domain::IPV6, Type::DGRAM, Protocol::ICMPV6, source) {
+                Ok(s) => (s, false),
+                Err(err) => {
+                    warn!(
+                        ?err,
+                        "error building DGRAM socket, check ping_group_range. trying RAW socket"
+                    );
+                    (
+                        Socket::bind(Domain::IPV6, Type::RAW, Protocol::ICMPV6, source)?,
+                        true,
+                    )
+                }
+            };
         debug!("created new icmpv6 socket");
         Ok(Self {
             inner,
@@ -1,25 +1,59 @@
+#[cfg(unix)]
 use std::{
     io,
+    net::SocketAddr,
     os::unix::io::{FromRawFd, IntoRawFd},
 };
 
+#[cfg(unix)]
 use socket2::{Domain, Protocol, Type};
-use std::net::SocketAddr;
+use std::net::IpAddr;
+#[cfg(unix)]
 use tokio::net::UdpSocket;
 
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use windows::Socket;
+
+/// where echo requests are sent from, by default the kernel picks the
+/// interface & address from the routing table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Source {
+    /// source address of echo requests
+    pub addr: Option<IpAddr>,
+    /// interface echo requests are sent on (SO_BINDTODEVICE)
+    pub interface: Option<String>,
+}
+
+#[cfg(unix)]
 pub struct Socket {
     pub(crate) socket: UdpSocket,
 }
 
+#[cfg(unix)]
 impl Socket {
-    pub fn new(domain: Domain, type_: Type, protocol: Protocol) -> io::Result<Self> {
+    /// create a socket sending from `source`
+    pub fn bind(
+        domain: Domain,
+        type_: Type,
+        protocol: Protocol,
+        source: &Source,
+    ) -> io::Result<Self> {
         let socket = socket2::Socket::new(domain, type_, Some(protocol))?;
         socket.set_nonblocking(true)?;
-        #[cfg(windows)]
-        let socket = UdpSocket::from_std(unsafe {
-            std::net::UdpSocket::from_raw_socket(socket.into_raw_socket())
-        })?;
-        #[cfg(unix)]
+        if let Some(iface) = &source.interface {
+            #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+            socket.bind_device(Some(iface.as_bytes()))?;
+            #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("can't bind ICMP socket to {iface}, unsupported on this platform"),
+            ));
+        }
+        if let Some(ip) = source.addr {
+            socket.bind(&SocketAddr::new(ip, 0).into())?;
+        }
         let socket =
             UdpSocket::from_std(unsafe { std::net::UdpSocket::from_raw_fd(socket.into_raw_fd()) })?;
 
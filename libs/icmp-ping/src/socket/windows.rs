@@ -0,0 +1,255 @@
+//! Windows has no unprivileged ICMP socket, echo requests go through the
+//! ICMP helper API in iphlpapi (`IcmpSendEcho2Ex`/`Icmp6SendEcho2`) instead.
+//! Those calls block until the reply or their timeout, so each request runs
+//! on the blocking pool and its reply is rebuilt into an ICMP echo reply &
+//! handed to `recv`, the same as a DGRAM socket would on linux.
+use std::{
+    ffi::c_void,
+    io, mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    ptr,
+    sync::Arc,
+};
+
+use socket2::{Domain, Protocol, Type};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, trace};
+use windows_sys::Win32::{
+    NetworkManagement::IpHelper::{
+        Icmp6CreateFile, Icmp6SendEcho2, IcmpCloseHandle, IcmpCreateFile, IcmpHandle,
+        IcmpSendEcho2Ex, ICMPV6_ECHO_REPLY_LH, ICMP_ECHO_REPLY, IP_SUCCESS,
+    },
+    Networking::WinSock::{AF_INET6, IN6_ADDR, IN6_ADDR_0, SOCKADDR_IN6, SOCKADDR_IN6_0},
+};
+
+use super::Source;
+use crate::ICMP_HEADER_SIZE;
+
+/// how long the blocking pool waits on a reply, `Pinger` gives up on its
+/// own timeout well before this
+const REPLY_TIMEOUT_MS: u32 = 5_000;
+const INVALID_HANDLE: IcmpHandle = -1;
+/// echo reply type for ICMPv4 & ICMPv6
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REPLY_V6: u8 = 129;
+
+/// an ICMP handle from `IcmpCreateFile`/`Icmp6CreateFile`, closed on drop
+#[derive(Debug)]
+struct Handle(IcmpHandle);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { IcmpCloseHandle(self.0) };
+    }
+}
+
+type Reply = (Vec<u8>, SocketAddr);
+
+pub struct Socket {
+    handle: Arc<Handle>,
+    source: Option<IpAddr>,
+    tx: mpsc::UnboundedSender<Reply>,
+    rx: Mutex<mpsc::UnboundedReceiver<Reply>>,
+}
+
+impl Socket {
+    /// create an ICMP handle sending from `source`, the socket type is
+    /// ignored-- every handle behaves like a DGRAM socket
+    pub fn bind(
+        domain: Domain,
+        _type: Type,
+        _protocol: Protocol,
+        source: &Source,
+    ) -> io::Result<Self> {
+        if let Some(iface) = &source.interface {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("can't bind ICMP socket to {iface}, unsupported on this platform"),
+            ));
+        }
+        let handle = if domain == Domain::IPV6 {
+            unsafe { Icmp6CreateFile() }
+        } else {
+            unsafe { IcmpCreateFile() }
+        };
+        if handle == INVALID_HANDLE {
+            return Err(io::Error::last_os_error());
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        Ok(Self {
+            handle: Arc::new(Handle(handle)),
+            source: source.addr,
+            tx,
+            rx: Mutex::new(rx),
+        })
+    }
+
+    /// `buf` is an encoded echo request, only its payload goes on the wire,
+    /// windows picks the identifier & sequence number itself
+    pub async fn send_to(&self, buf: &[u8], target: &SocketAddr) -> io::Result<usize> {
+        if buf.len() < ICMP_HEADER_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "echo request shorter than the ICMP header",
+            ));
+        }
+        let request = buf.to_vec();
+        let handle = self.handle.clone();
+        let (source, target) = (self.source, target.ip());
+        let tx = self.tx.clone();
+        tokio::task::spawn_blocking(move || {
+            match echo(&handle, source, target, &request[ICMP_HEADER_SIZE..]) {
+                Ok(Some((data, from))) => {
+                    let ty = if from.is_ipv6() {
+                        ECHO_REPLY_V6
+                    } else {
+                        ECHO_REPLY_V4
+                    };
+                    // checksum is left zeroed, nothing checks it after the kernel
+                    let mut reply = vec![ty, 0, 0, 0];
+                    reply.extend_from_slice(&request[4..ICMP_HEADER_SIZE]);
+                    reply.extend_from_slice(&data);
+                    // only fails once the socket is dropped
+                    let _ = tx.send((reply, SocketAddr::new(from, 0)));
+                }
+                Ok(None) => trace!(?target, "no echo reply"),
+                Err(err) => debug!(?err, ?target, "failed to send echo request"),
+            }
+        });
+        Ok(buf.len())
+    }
+
+    pub async fn recv(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let (reply, addr) = self
+            .rx
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        let n = reply.len().min(buf.len());
+        buf[..n].copy_from_slice(&reply[..n]);
+        Ok((n, addr))
+    }
+}
+
+/// send one echo request & wait for its reply, returns the echoed data and
+/// the address it came from or `None` if nothing came back in time
+fn echo(
+    handle: &Handle,
+    source: Option<IpAddr>,
+    target: IpAddr,
+    data: &[u8],
+) -> io::Result<Option<(Vec<u8>, IpAddr)>> {
+    // room for the reply struct, the echoed data, an ICMP error & the
+    // IO_STATUS_BLOCK Icmp6SendEcho2 wants at the end
+    let mut buf = vec![0_u8; mem::size_of::<ICMP_ECHO_REPLY>() + data.len() + 64];
+    let size = u16::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "echo payload too large"))?;
+    match target {
+        IpAddr::V4(dst) => {
+            let src = match source {
+                Some(IpAddr::V4(src)) => src,
+                _ => Ipv4Addr::UNSPECIFIED,
+            };
+            let n = unsafe {
+                IcmpSendEcho2Ex(
+                    handle.0,
+                    0,
+                    None,
+                    ptr::null(),
+                    u32::from_ne_bytes(src.octets()),
+                    u32::from_ne_bytes(dst.octets()),
+                    data.as_ptr() as *const c_void,
+                    size,
+                    ptr::null(),
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.len() as u32,
+                    REPLY_TIMEOUT_MS,
+                )
+            };
+            if n == 0 {
+                return none_or_err();
+            }
+            let reply = unsafe { ptr::read_unaligned(buf.as_ptr() as *const ICMP_ECHO_REPLY) };
+            if reply.Status != IP_SUCCESS {
+                return Ok(None);
+            }
+            // Data points back into `buf`
+            let echoed = unsafe {
+                std::slice::from_raw_parts(reply.Data as *const u8, reply.DataSize as usize)
+            };
+            let from = Ipv4Addr::from(reply.Address.to_ne_bytes());
+            Ok(Some((echoed.to_vec(), from.into())))
+        }
+        IpAddr::V6(dst) => {
+            let src = match source {
+                Some(IpAddr::V6(src)) => src,
+                _ => Ipv6Addr::UNSPECIFIED,
+            };
+            let (src, dst) = (
+                sockaddr_in6(&SocketAddrV6::new(src, 0, 0, 0)),
+                sockaddr_in6(&SocketAddrV6::new(dst, 0, 0, 0)),
+            );
+            let n = unsafe {
+                Icmp6SendEcho2(
+                    handle.0,
+                    0,
+                    None,
+                    ptr::null(),
+                    &src,
+                    &dst,
+                    data.as_ptr() as *const c_void,
+                    size,
+                    ptr::null(),
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.len() as u32,
+                    REPLY_TIMEOUT_MS,
+                )
+            };
+            if n == 0 {
+                return none_or_err();
+            }
+            let reply = unsafe { ptr::read_unaligned(buf.as_ptr() as *const ICMPV6_ECHO_REPLY_LH) };
+            if reply.Status != IP_SUCCESS {
+                return Ok(None);
+            }
+            // the echoed data directly follows the reply struct
+            let start = mem::size_of::<ICMPV6_ECHO_REPLY_LH>();
+            let echoed = buf[start..start + data.len()].to_vec();
+            let mut octets = [0; 16];
+            for (chunk, word) in octets.chunks_mut(2).zip(reply.Address.sin6_addr) {
+                // words are in network order already
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            Ok(Some((echoed, Ipv6Addr::from(octets).into())))
+        }
+    }
+}
+
+/// a send that got no reply sets a status in the last error, anything
+/// else is a real failure
+fn none_or_err<T>() -> io::Result<Option<T>> {
+    let err = io::Error::last_os_error();
+    match err.raw_os_error().map(|code| code as u32) {
+        // IP_REQ_TIMED_OUT & the other IP_* statuses
+        Some(code) if (11000..=11050).contains(&code) => Ok(None),
+        _ => Err(err),
+    }
+}
+
+fn sockaddr_in6(addr: &SocketAddrV6) -> SOCKADDR_IN6 {
+    SOCKADDR_IN6 {
+        sin6_family: AF_INET6,
+        sin6_port: addr.port().to_be(),
+        sin6_flowinfo: addr.flowinfo(),
+        sin6_addr: IN6_ADDR {
+            u: IN6_ADDR_0 {
+                Byte: addr.ip().octets(),
+            },
+        },
+        Anonymous: SOCKADDR_IN6_0 {
+            sin6_scope_id: addr.scope_id(),
+        },
+    }
+}
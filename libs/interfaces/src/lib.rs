@@ -0,0 +1,45 @@
+//! # interfaces
+//!
+//! List the network interfaces on this host. On unix this is
+//! `pnet::datalink::interfaces`, windows builds the same list from
+//! `GetAdaptersAddresses` so interface discovery doesn't need Npcap.
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    unreachable_pub,
+    non_snake_case,
+    non_upper_case_globals
+)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+use pnet::datalink::NetworkInterface;
+
+#[cfg(windows)]
+mod windows;
+
+/// every interface on the host, up or not
+#[cfg(not(windows))]
+pub fn list() -> Vec<NetworkInterface> {
+    pnet::datalink::interfaces()
+}
+
+/// every interface on the host, up or not. Interfaces are named by their
+/// friendly name (`Ethernet`, `Wi-Fi`), the same name `netsh` & the network
+/// settings use
+#[cfg(windows)]
+pub fn list() -> Vec<NetworkInterface> {
+    windows::interfaces()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_loopback() {
+        let loopback = list().into_iter().find(|int| int.is_loopback()).unwrap();
+        assert!(loopback.is_up());
+        assert!(loopback.ips.iter().any(|ip| ip.ip().is_loopback()));
+    }
+}
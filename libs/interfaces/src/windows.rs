@@ -0,0 +1,134 @@
+use std::{
+    mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    ptr,
+};
+
+use pnet::{datalink::NetworkInterface, ipnetwork::IpNetwork, util::MacAddr};
+use windows_sys::Win32::{
+    Foundation::{ERROR_BUFFER_OVERFLOW, ERROR_SUCCESS},
+    NetworkManagement::{
+        IpHelper::{
+            GetAdaptersAddresses, GAA_FLAG_SKIP_ANYCAST, GAA_FLAG_SKIP_DNS_SERVER,
+            GAA_FLAG_SKIP_MULTICAST, IF_TYPE_PPP, IF_TYPE_SOFTWARE_LOOPBACK,
+            IP_ADAPTER_ADDRESSES_LH, IP_ADAPTER_NO_MULTICAST,
+        },
+        Ndis::IfOperStatusUp,
+    },
+    Networking::WinSock::{
+        AF_INET, AF_INET6, AF_UNSPEC, SOCKADDR_IN, SOCKADDR_IN6, SOCKET_ADDRESS,
+    },
+};
+
+// the flag bits pnet checks in `is_up` & friends on windows
+const IFF_UP: u32 = 0x1;
+const IFF_BROADCAST: u32 = 0x2;
+const IFF_LOOPBACK: u32 = 0x4;
+const IFF_POINTOPOINT: u32 = 0x8;
+const IFF_MULTICAST: u32 = 0x10;
+
+/// 15KB is the size microsoft recommends starting from
+const INITIAL_BUF_SIZE: u32 = 15 * 1024;
+
+pub(crate) fn interfaces() -> Vec<NetworkInterface> {
+    let mut size = INITIAL_BUF_SIZE;
+    // u64s so the adapter structs are aligned
+    let mut buf: Vec<u64>;
+    loop {
+        buf = vec![0; size as usize / mem::size_of::<u64>() + 1];
+        let ret = unsafe {
+            GetAdaptersAddresses(
+                AF_UNSPEC as u32,
+                GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER,
+                ptr::null(),
+                buf.as_mut_ptr() as *mut IP_ADAPTER_ADDRESSES_LH,
+                &mut size,
+            )
+        };
+        match ret {
+            ERROR_SUCCESS => break,
+            // `size` now holds what's needed, adapters may be added in between
+            ERROR_BUFFER_OVERFLOW => continue,
+            // ERROR_NO_DATA when there are no adapters at all
+            _ => return Vec::new(),
+        }
+    }
+
+    let mut interfaces = Vec::new();
+    let mut adapter = buf.as_ptr() as *const IP_ADAPTER_ADDRESSES_LH;
+    while let Some(a) = unsafe { adapter.as_ref() } {
+        interfaces.push(interface(a));
+        adapter = a.Next;
+    }
+    interfaces
+}
+
+fn interface(a: &IP_ADAPTER_ADDRESSES_LH) -> NetworkInterface {
+    let mac = match a.PhysicalAddressLength {
+        6 => {
+            let m = &a.PhysicalAddress;
+            Some(MacAddr::new(m[0], m[1], m[2], m[3], m[4], m[5]))
+        }
+        _ => None,
+    };
+
+    let mut ips = Vec::new();
+    let mut unicast = a.FirstUnicastAddress;
+    while let Some(u) = unsafe { unicast.as_ref() } {
+        if let Some(ip) = unsafe { socket_addr(&u.Address) } {
+            if let Ok(net) = IpNetwork::new(ip, u.OnLinkPrefixLength) {
+                ips.push(net);
+            }
+        }
+        unicast = u.Next;
+    }
+
+    let mut flags = 0;
+    if a.OperStatus == IfOperStatusUp {
+        flags |= IFF_UP;
+    }
+    match a.IfType {
+        IF_TYPE_SOFTWARE_LOOPBACK => flags |= IFF_LOOPBACK,
+        IF_TYPE_PPP => flags |= IFF_POINTOPOINT,
+        _ => flags |= IFF_BROADCAST,
+    }
+    if unsafe { a.Anonymous2.Flags } & IP_ADAPTER_NO_MULTICAST == 0 {
+        flags |= IFF_MULTICAST;
+    }
+
+    NetworkInterface {
+        name: unsafe { wide(a.FriendlyName) },
+        description: unsafe { wide(a.Description) },
+        index: unsafe { a.Anonymous1.Anonymous.IfIndex },
+        mac,
+        ips,
+        flags,
+    }
+}
+
+/// # Safety
+/// `addr` must come from `GetAdaptersAddresses`
+unsafe fn socket_addr(addr: &SOCKET_ADDRESS) -> Option<IpAddr> {
+    let sa = addr.lpSockaddr.as_ref()?;
+    match sa.sa_family {
+        AF_INET => {
+            let sin = &*(addr.lpSockaddr as *const SOCKADDR_IN);
+            Some(Ipv4Addr::from(sin.sin_addr.S_un.S_addr.to_ne_bytes()).into())
+        }
+        AF_INET6 => {
+            let sin6 = &*(addr.lpSockaddr as *const SOCKADDR_IN6);
+            Some(Ipv6Addr::from(sin6.sin6_addr.u.Byte).into())
+        }
+        _ => None,
+    }
+}
+
+/// # Safety
+/// `s` must be null or a nul terminated UTF-16 string
+unsafe fn wide(s: *const u16) -> String {
+    if s.is_null() {
+        return String::new();
+    }
+    let len = (0..).take_while(|&i| *s.add(i) != 0).count();
+    String::from_utf16_lossy(std::slice::from_raw_parts(s, len))
+}
@@ -0,0 +1,94 @@
+//! the time lease expiry & probation are measured against
+//!
+//! Every expiry the lease store writes or compares is taken from its
+//! [`Clock`], the system clock unless the store is given another one (see
+//! [`SqliteDb::with_clock`]). A [`FakeClock`] can be started ahead of the
+//! system clock, run faster than it or be advanced by hand, so leases &
+//! probations run out without waiting for them.
+//!
+//! [`SqliteDb::with_clock`]: crate::sqlite::SqliteDb::with_clock
+use std::{
+    fmt,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// a source of the current time
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// the current time
+    fn now(&self) -> SystemTime;
+}
+
+/// the system clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// a clock that starts at a given time & runs `speed` times as fast as the
+/// system clock, 0 stops it. It only moves forward
+#[derive(Debug)]
+pub struct FakeClock {
+    inner: Mutex<Fake>,
+}
+
+#[derive(Debug)]
+struct Fake {
+    /// the time at `since`
+    base: SystemTime,
+    since: Instant,
+    speed: u32,
+}
+
+impl Fake {
+    fn now(&self) -> SystemTime {
+        self.base + self.since.elapsed() * self.speed
+    }
+}
+
+impl FakeClock {
+    /// a clock reading `start` now & running `speed` times as fast as the
+    /// system clock
+    pub fn new(start: SystemTime, speed: u32) -> Self {
+        Self {
+            inner: Mutex::new(Fake {
+                base: start,
+                since: Instant::now(),
+                speed,
+            }),
+        }
+    }
+
+    /// a clock stopped at `at`, it only moves when advanced
+    pub fn frozen(at: SystemTime) -> Self {
+        Self::new(at, 0)
+    }
+
+    /// move the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        let mut fake = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        fake.base += by;
+    }
+
+    /// move the clock forward to `at`, times before the current one are ignored
+    pub fn set(&self, at: SystemTime) {
+        let mut fake = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if at > fake.now() {
+            fake.base = at;
+            fake.since = Instant::now();
+        }
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        self.inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .now()
+    }
+}
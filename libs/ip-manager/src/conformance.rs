@@ -0,0 +1,967 @@
+//! # conformance
+//!
+//! Checks that a [`Storage`] backend behaves the way [`IpManager`] relies on:
+//! allocating addresses in a range, moving entries between [`LeaseState`]s &
+//! logging it, expiry measured against [`Storage::now`], probation, sticky
+//! leases & pins, hostnames, lease searches, pool stats, the data kept with a
+//! lease (FORCERENEW nonces, relay info, fingerprints & notes), stored config
+//! versions & the server state kept across restarts. Backends built outside of dora run it from their own tests:
+//!
+//! ```ignore
+//! #[tokio::test]
+//! async fn conformance() -> Result<(), MyError> {
+//!     ip_manager::conformance::run(MyStore::empty).await
+//! }
+//! ```
+//!
+//! `new` is called for every check & must return an empty store. A check that
+//! fails panics with its name & what was expected, errors from the store are
+//! returned. Only IPv4 addresses are used. Times are compared to the second,
+//! a backend can store them with no more precision than that.
+//!
+//! [`IpManager`]: crate::IpManager
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    net::{IpAddr, Ipv4Addr},
+    ops::RangeInclusive,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    ClientInfo, DailyPoolStats, Exclusions, ForceRenewNonce, HostnameConflict, HostnamePolicy,
+    IpState, LeaseFingerprint, LeaseQuery, LeaseState, NetworkCount, PoolSample, RangeCounts,
+    SortKey, State, StickyLease, Storage,
+};
+
+const MINUTE: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const A: &[u8] = &[0xaa, 0, 0, 0, 0, 1];
+const B: &[u8] = &[0xbb, 0, 0, 0, 0, 2];
+const C: &[u8] = &[0xcc, 0, 0, 0, 0, 3];
+
+/// run every check against stores made by `new`
+pub async fn run<S, F, Fut>(new: F) -> Result<(), S::Error>
+where
+    S: Storage,
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<S, S::Error>>,
+{
+    allocate(&new().await?).await?;
+    ranges(&new().await?).await?;
+    lease(&new().await?).await?;
+    transitions(&new().await?).await?;
+    probation(&new().await?).await?;
+    expiry(&new().await?).await?;
+    sticky(&new().await?).await?;
+    pins(&new().await?).await?;
+    hostnames(&new().await?).await?;
+    search(&new().await?).await?;
+    pool_stats(&new().await?).await?;
+    forcerenew(&new().await?).await?;
+    relay_info(&new().await?).await?;
+    fingerprints(&new().await?).await?;
+    notes(&new().await?).await?;
+    config_versions(&new().await?).await?;
+    server_state(&new().await?).await?;
+    Ok(())
+}
+
+fn ip(last: u8) -> IpAddr {
+    IpAddr::V4(Ipv4Addr::new(192, 168, 0, last))
+}
+
+fn network() -> IpAddr {
+    ip(0)
+}
+
+fn range() -> RangeInclusive<IpAddr> {
+    ip(10)..=ip(20)
+}
+
+/// `store.now()` to the second
+fn now<S: Storage>(store: &S) -> SystemTime {
+    let secs = store
+        .now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn info(ip: IpAddr, id: Option<&[u8]>, expires_at: SystemTime) -> ClientInfo {
+    ClientInfo::new(ip, id.map(<[u8]>::to_vec), network(), expires_at)
+}
+
+/// offers are made from the start of a range, past exclusions
+async fn allocate<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let expires_at = now(store) + MINUTE;
+    assert_eq!(
+        store
+            .next_expired(range(), network(), A, expires_at)
+            .await?,
+        None,
+        "allocate: an empty store has no expired entries"
+    );
+    let first = store
+        .insert_max_in_range(range(), &Exclusions::default(), network(), A, expires_at)
+        .await?;
+    assert_eq!(
+        first,
+        Some(ip(10)),
+        "allocate: an empty range is offered from its start"
+    );
+    let excluded = [Ipv4Addr::new(192, 168, 0, 11)]
+        .into_iter()
+        .collect::<Exclusions>();
+    let second = store
+        .insert_max_in_range(range(), &excluded, network(), B, expires_at)
+        .await?;
+    assert_eq!(
+        second,
+        Some(ip(12)),
+        "allocate: the next offer skips exclusions"
+    );
+    assert_eq!(
+        store.get(ip(10)).await?,
+        Some(State::Reserved(info(ip(10), Some(A), expires_at))),
+        "allocate: an offer is stored reserved"
+    );
+    assert_eq!(
+        store.get_id(A).await?,
+        Some(ip(10)),
+        "allocate: an offer is found by client"
+    );
+    assert_eq!(
+        store
+            .next_expired(range(), network(), A, expires_at + MINUTE)
+            .await?,
+        Some(ip(10)),
+        "allocate: a client is offered its own address again"
+    );
+    assert_eq!(
+        store.get(ip(10)).await?.map(State::into),
+        Some(info(ip(10), Some(A), expires_at + MINUTE)),
+        "allocate: a new offer replaces the expiry"
+    );
+    Ok(())
+}
+
+/// offers come from the lowest expired entry of a range, then past its highest
+async fn ranges<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    let none = Exclusions::default();
+    store
+        .insert(ip(9), network(), A, now - MINUTE, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(12), network(), A, now - MINUTE, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(14), network(), A, now + HOUR, LeaseState::Active)
+        .await?;
+    assert_eq!(
+        store
+            .next_expired(range(), network(), B, now + MINUTE)
+            .await?,
+        Some(ip(12)),
+        "ranges: the lowest expired entry in the range is offered"
+    );
+    assert_eq!(
+        store
+            .next_expired(range(), network(), C, now + MINUTE)
+            .await?,
+        None,
+        "ranges: an offered entry isn't offered again"
+    );
+    assert_eq!(
+        store
+            .insert_max_in_range(range(), &none, network(), C, now + MINUTE)
+            .await?,
+        Some(ip(15)),
+        "ranges: the address after the highest entry is offered"
+    );
+    store
+        .insert(ip(20), network(), A, now + HOUR, LeaseState::Active)
+        .await?;
+    assert_eq!(
+        store
+            .insert_max_in_range(range(), &none, network(), B, now + MINUTE)
+            .await?,
+        None,
+        "ranges: nothing is offered past the end of the range"
+    );
+    Ok(())
+}
+
+/// offers are leased & released by the client they were made for
+async fn lease<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now + MINUTE, LeaseState::Offered)
+        .await?;
+    assert_eq!(
+        store
+            .update_unexpired(ip(10), LeaseState::Active, B, now + HOUR, Some(B))
+            .await?,
+        None,
+        "lease: another client's offer isn't leased"
+    );
+    assert_eq!(
+        store
+            .update_unexpired(ip(10), LeaseState::Active, A, now + HOUR, Some(A))
+            .await?,
+        Some(ip(10)),
+        "lease: an offer is leased"
+    );
+    assert_eq!(
+        store.get(ip(10)).await?,
+        Some(State::Leased(info(ip(10), Some(A), now + HOUR))),
+        "lease: a lease is stored leased"
+    );
+    assert_eq!(
+        store.count(IpState::Lease).await?,
+        1,
+        "lease: leases are counted"
+    );
+    assert_eq!(
+        store.get_all_id(A).await?,
+        [info(ip(10), Some(A), now + HOUR)],
+        "lease: leases are found by client"
+    );
+    let active = store.active_leases().await?;
+    assert_eq!(
+        active
+            .iter()
+            .map(|lease| (lease.ip, lease.state, lease.lease_state))
+            .collect::<Vec<_>>(),
+        [(ip(10), IpState::Lease, LeaseState::Active)],
+        "lease: leases are listed"
+    );
+
+    assert_eq!(
+        store.release_ip(ip(10), A).await?,
+        Some(info(ip(10), Some(A), now + HOUR)),
+        "lease: a release returns the lease as it was"
+    );
+    assert_eq!(
+        store.release_ip(ip(10), A).await?,
+        None,
+        "lease: a released lease isn't released again"
+    );
+    assert_eq!(
+        store.get_id(A).await?,
+        None,
+        "lease: a released lease isn't found by client"
+    );
+    let transitions = store
+        .lease_transitions(Some(ip(10)), 10)
+        .await?
+        .into_iter()
+        .map(|transition| (transition.from, transition.to))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        transitions,
+        [
+            (Some(LeaseState::Active), LeaseState::Released),
+            (Some(LeaseState::Offered), LeaseState::Active),
+            (None, LeaseState::Offered),
+        ],
+        "lease: transitions are logged newest first"
+    );
+    Ok(())
+}
+
+/// every change of state is logged with the client & when it happened
+async fn transitions<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now + MINUTE, LeaseState::Offered)
+        .await?;
+    store
+        .update_unexpired(ip(10), LeaseState::Active, A, now + HOUR, Some(A))
+        .await?;
+    store
+        .insert(ip(11), network(), B, now + MINUTE, LeaseState::Offered)
+        .await?;
+    let logged = store.lease_transitions(None, 10).await?;
+    assert_eq!(
+        logged
+            .iter()
+            .map(|transition| (
+                transition.ip,
+                transition.client_id.as_deref(),
+                transition.to
+            ))
+            .collect::<Vec<_>>(),
+        [
+            (ip(11), Some(B), LeaseState::Offered),
+            (ip(10), Some(A), LeaseState::Active),
+            (ip(10), Some(A), LeaseState::Offered),
+        ],
+        "transitions: every address is logged newest first"
+    );
+    assert!(
+        logged.windows(2).all(|pair| pair[0].id > pair[1].id),
+        "transitions: positions increase"
+    );
+    assert!(
+        logged
+            .iter()
+            .all(|transition| transition.at >= now - MINUTE && transition.at <= now + MINUTE),
+        "transitions: logged at store.now()"
+    );
+    assert_eq!(
+        store.lease_transitions(None, 1).await?.len(),
+        1,
+        "transitions: limited"
+    );
+    assert_eq!(
+        store.delete_lease_transitions(now - HOUR).await?,
+        0,
+        "transitions: newer transitions aren't deleted"
+    );
+    assert_eq!(
+        store.delete_lease_transitions(now + HOUR).await?,
+        3,
+        "transitions: older transitions are deleted"
+    );
+    assert!(
+        store.lease_transitions(None, 10).await?.is_empty(),
+        "transitions: deleted transitions aren't listed"
+    );
+    Ok(())
+}
+
+/// declined addresses are held without a client until probation ends
+async fn probation<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now + HOUR, LeaseState::Active)
+        .await?;
+    assert_eq!(
+        store
+            .update_unexpired(ip(10), LeaseState::Declined, A, now + HOUR, None)
+            .await?,
+        Some(ip(10)),
+        "probation: a lease is declined"
+    );
+    assert!(
+        matches!(store.get(ip(10)).await?, Some(State::Probated(_))),
+        "probation: a declined address is stored probated"
+    );
+    assert_eq!(
+        store.count(IpState::Probate).await?,
+        1,
+        "probation: probations are counted"
+    );
+    assert_eq!(
+        store.count(IpState::Lease).await?,
+        0,
+        "probation: probations aren't counted as leases"
+    );
+    assert_eq!(
+        store
+            .next_expired(range(), network(), B, now + MINUTE)
+            .await?,
+        None,
+        "probation: an address on probation isn't offered"
+    );
+    assert_eq!(
+        store
+            .update_ip(ip(10), LeaseState::Reserved, None, now + 2 * HOUR)
+            .await?,
+        Some(State::Probated(info(ip(10), None, now + 2 * HOUR))),
+        "probation: a probation is extended whatever client it's for"
+    );
+    assert_eq!(
+        store
+            .update_ip(ip(99), LeaseState::Reserved, None, now + HOUR)
+            .await?,
+        None,
+        "probation: an address without an entry isn't updated"
+    );
+    Ok(())
+}
+
+/// entries past their expiry are gone from lookups & can be offered again
+async fn expiry<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now - MINUTE, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(11), network(), B, now - MINUTE, LeaseState::Declined)
+        .await?;
+    assert!(
+        store.active_leases().await?.is_empty(),
+        "expiry: expired entries aren't listed"
+    );
+    assert_eq!(
+        store.get_id(A).await?,
+        None,
+        "expiry: an expired lease isn't found by client"
+    );
+    assert_eq!(
+        store.expired_between(now - HOUR, now).await?,
+        [info(ip(10), Some(A), now - MINUTE)],
+        "expiry: expired leases are found by expiry"
+    );
+    assert!(
+        store
+            .expired_offers_between(now - HOUR, now)
+            .await?
+            .is_empty(),
+        "expiry: expired leases aren't expired offers"
+    );
+    assert_eq!(
+        store.expire_leases(now).await?,
+        2,
+        "expiry: expired leases & probations are moved"
+    );
+    assert_eq!(
+        store.expire_leases(now).await?,
+        0,
+        "expiry: expired entries are only moved once"
+    );
+    let last = |ip: IpAddr| async move {
+        Ok::<_, S::Error>(
+            store
+                .lease_transitions(Some(ip), 1)
+                .await?
+                .pop()
+                .map(|transition| transition.to),
+        )
+    };
+    assert_eq!(
+        last(ip(10)).await?,
+        Some(LeaseState::Expired),
+        "expiry: a lease expires"
+    );
+    assert_eq!(
+        last(ip(11)).await?,
+        Some(LeaseState::Reclaimed),
+        "expiry: a probation is reclaimed"
+    );
+    assert_eq!(
+        store
+            .next_expired(range(), network(), B, now + HOUR)
+            .await?,
+        Some(ip(10)),
+        "expiry: an expired address is offered to another client"
+    );
+    Ok(())
+}
+
+/// sticky leases hold an address for their client
+async fn sticky<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now - MINUTE, LeaseState::Active)
+        .await?;
+    store.set_sticky(ip(10), network(), A, now + HOUR).await?;
+    let held = StickyLease {
+        ip: ip(10),
+        id: A.to_vec(),
+        network: network(),
+        renewals: 0,
+        expires_at: now + HOUR,
+        pinned: false,
+    };
+    assert_eq!(
+        store.get_sticky(A, network()).await?,
+        Some(held.clone()),
+        "sticky: found by client"
+    );
+    assert_eq!(
+        store.get_sticky_ip(ip(10)).await?,
+        Some(held.clone()),
+        "sticky: found by address"
+    );
+    assert_eq!(store.sticky_leases().await?, [held], "sticky: listed");
+    assert_eq!(
+        store
+            .next_expired(range(), network(), B, now + HOUR)
+            .await?,
+        None,
+        "sticky: an expired address held for another client isn't offered"
+    );
+    assert_eq!(
+        store
+            .next_expired(range(), network(), A, now + HOUR)
+            .await?,
+        Some(ip(10)),
+        "sticky: an expired address is offered to the client it's held for"
+    );
+    assert!(store.delete_sticky(ip(10)).await?, "sticky: deleted");
+    assert!(
+        !store.delete_sticky(ip(10)).await?,
+        "sticky: only deleted once"
+    );
+    Ok(())
+}
+
+/// pins hold an address until they expire, whatever the client does
+async fn pins<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now - MINUTE, LeaseState::Active)
+        .await?;
+    store.set_pin(ip(10), network(), A, now + HOUR).await?;
+    assert_eq!(
+        store.get_sticky_ip(ip(10)).await?.map(|held| held.pinned),
+        Some(true),
+        "pins: found pinned"
+    );
+    assert_eq!(
+        store
+            .next_expired(range(), network(), B, now + HOUR)
+            .await?,
+        None,
+        "pins: an expired address pinned for another client isn't offered"
+    );
+    assert_eq!(
+        store
+            .renew_sticky(ip(10), network(), A, Some(1), now + 2 * HOUR)
+            .await?,
+        Some(now + HOUR),
+        "pins: a renewal doesn't extend a pin"
+    );
+    store.set_pin(ip(11), network(), B, now - MINUTE).await?;
+    store.set_sticky(ip(12), network(), B, now - MINUTE).await?;
+    assert_eq!(
+        store.delete_expired_pins().await?,
+        1,
+        "pins: only expired pins are deleted"
+    );
+    assert!(
+        store.get_sticky_ip(ip(10)).await?.is_some(),
+        "pins: an un-expired pin is kept"
+    );
+    Ok(())
+}
+
+/// a client holds one hostname & a conflict is recorded once
+async fn hostnames<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store.set_hostname("one", ip(10), A, now + HOUR).await?;
+    let entry = store.get_hostname("one").await?;
+    assert_eq!(
+        entry.map(|entry| (entry.id, entry.ip, entry.expires_at)),
+        Some((A.to_vec(), ip(10), now + HOUR)),
+        "hostnames: found by name"
+    );
+    store.set_hostname("two", ip(10), A, now + HOUR).await?;
+    assert_eq!(
+        store.get_hostname("one").await?,
+        None,
+        "hostnames: a new hostname replaces the client's old one"
+    );
+    store.set_hostname("three", ip(11), B, now - MINUTE).await?;
+    assert_eq!(
+        store.get_hostname("three").await?,
+        None,
+        "hostnames: an expired hostname isn't found"
+    );
+    let conflict = |assigned: &str| HostnameConflict {
+        hostname: "two".to_owned(),
+        id: B.to_vec(),
+        existing_id: A.to_vec(),
+        policy: HostnamePolicy::Suffix,
+        assigned: Some(assigned.to_owned()),
+        created_at: now,
+    };
+    assert!(
+        store.insert_hostname_conflict(&conflict("two-2")).await?,
+        "hostnames: a new conflict is recorded"
+    );
+    assert!(
+        !store
+            .insert_hostname_conflict(&HostnameConflict {
+                created_at: now + MINUTE,
+                ..conflict("two-3")
+            })
+            .await?,
+        "hostnames: a repeated conflict isn't recorded again"
+    );
+    assert_eq!(
+        store.hostname_conflicts(10).await?,
+        [conflict("two-3")],
+        "hostnames: a repeated conflict updates the first"
+    );
+    Ok(())
+}
+
+/// searches return the un-expired leases matching every condition, in order
+async fn search<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now + HOUR, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(11), network(), B, now + MINUTE, LeaseState::Offered)
+        .await?;
+    store
+        .insert(ip(12), network(), C, now - MINUTE, LeaseState::Active)
+        .await?;
+    store.set_hostname("printer", ip(10), A, now + HOUR).await?;
+    let found = |query: LeaseQuery| async move {
+        Ok::<_, S::Error>(
+            store
+                .search_leases(&query)
+                .await?
+                .into_iter()
+                .map(|lease| lease.ip)
+                .collect::<Vec<_>>(),
+        )
+    };
+    let parse = |query: &str| LeaseQuery::new(query).expect("search: query parses");
+    assert_eq!(
+        found(parse("")).await?,
+        [ip(10), ip(11)],
+        "search: an empty query finds the un-expired leases"
+    );
+    assert_eq!(
+        found(parse("state=leased")).await?,
+        [ip(10)],
+        "search: by state"
+    );
+    assert_eq!(
+        found(parse("ip=192.168.0.8/29 AND ip!=192.168.0.10")).await?,
+        [ip(11)],
+        "search: by address, every condition matches"
+    );
+    assert_eq!(
+        found(parse("client_id=bb:00:00:00:00:02")).await?,
+        [ip(11)],
+        "search: by client id"
+    );
+    assert_eq!(
+        found(parse("hostname~PRINT")).await?,
+        [ip(10)],
+        "search: by part of a hostname, ignoring case"
+    );
+    assert_eq!(
+        found(parse("").sort(SortKey::ExpiresAt, false)).await?,
+        [ip(11), ip(10)],
+        "search: sorted"
+    );
+    assert_eq!(
+        found(parse("").sort(SortKey::Ip, true).page(1, 1)).await?,
+        [ip(10)],
+        "search: paged"
+    );
+    let leases = store.search_leases(&parse("state=leased")).await?;
+    assert_eq!(
+        leases
+            .iter()
+            .map(|lease| lease.hostname.as_deref())
+            .collect::<Vec<_>>(),
+        [Some("printer")],
+        "search: leases are returned with their hostname"
+    );
+    Ok(())
+}
+
+/// ranges are counted by state & samples are rolled up by day
+async fn pool_stats<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now + HOUR, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(11), network(), B, now + HOUR, LeaseState::Offered)
+        .await?;
+    store
+        .insert(ip(12), network(), C, now + HOUR, LeaseState::Declined)
+        .await?;
+    store
+        .insert(ip(13), network(), A, now - MINUTE, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(30), network(), B, now + HOUR, LeaseState::Active)
+        .await?;
+    let counts = RangeCounts {
+        leased: 1,
+        reserved: 1,
+        probated: 1,
+    };
+    assert_eq!(
+        store.range_counts(range()).await?,
+        counts,
+        "pool_stats: un-expired entries in the range are counted by state"
+    );
+
+    let day = SystemTime::UNIX_EPOCH + 10 * 24 * HOUR;
+    let sample = |leased, sampled_at| PoolSample {
+        network: network(),
+        range: range(),
+        total: 11,
+        counts: RangeCounts { leased, ..counts },
+        sampled_at,
+    };
+    let samples = [sample(1, day + HOUR), sample(3, day + 2 * HOUR)];
+    store.insert_pool_samples(&samples).await?;
+    assert_eq!(
+        store.pool_samples(day).await?,
+        samples,
+        "pool_stats: samples are listed oldest first"
+    );
+    assert_eq!(
+        store.pool_samples(day + 2 * HOUR).await?,
+        samples[1..],
+        "pool_stats: samples are listed since a time"
+    );
+    assert_eq!(
+        store.rollup_pool_samples(day + 3 * HOUR).await?,
+        2,
+        "pool_stats: samples are rolled up"
+    );
+    assert!(
+        store.pool_samples(day).await?.is_empty(),
+        "pool_stats: rolled up samples are deleted"
+    );
+    assert_eq!(
+        store.pool_rollups(day).await?,
+        [DailyPoolStats {
+            network: network(),
+            range: range(),
+            day,
+            samples: 2,
+            total: 11,
+            leased_avg: 2.0,
+            leased_max: 3,
+            used_avg: 4.0,
+            used_max: 5,
+        }],
+        "pool_stats: samples are rolled up by day"
+    );
+    assert_eq!(
+        store.delete_pool_rollups(day + 24 * HOUR).await?,
+        1,
+        "pool_stats: rollups are deleted"
+    );
+    assert!(
+        store.pool_rollups(day).await?.is_empty(),
+        "pool_stats: deleted rollups aren't listed"
+    );
+    Ok(())
+}
+
+/// a nonce is kept for each address while its client holds the lease
+async fn forcerenew<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now + HOUR, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(11), network(), B, now + HOUR, LeaseState::Offered)
+        .await?;
+    let nonce = |ip: IpAddr, id: &[u8], nonce: &[u8]| ForceRenewNonce {
+        ip,
+        id: id.to_vec(),
+        chaddr: id.to_vec(),
+        network: network(),
+        server_id: Ipv4Addr::new(192, 168, 0, 1),
+        classes: vec!["one".to_owned(), "two".to_owned()],
+        nonce: nonce.to_vec(),
+    };
+    store
+        .set_forcerenew_nonce(&nonce(ip(10), A, &[1; 16]))
+        .await?;
+    store
+        .set_forcerenew_nonce(&nonce(ip(10), A, &[2; 16]))
+        .await?;
+    store
+        .set_forcerenew_nonce(&nonce(ip(11), B, &[3; 16]))
+        .await?;
+    assert_eq!(
+        store.forcerenew_nonces().await?,
+        [nonce(ip(10), A, &[2; 16])],
+        "forcerenew: the last nonce of a lease is listed, not an offer's"
+    );
+    store.release_ip(ip(10), A).await?;
+    assert!(
+        store.forcerenew_nonces().await?.is_empty(),
+        "forcerenew: a released lease's nonce isn't listed"
+    );
+    Ok(())
+}
+
+/// relay info is listed with the lease of the client it was stored for
+async fn relay_info<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now + HOUR, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(11), network(), B, now + HOUR, LeaseState::Active)
+        .await?;
+    store.set_relay_info(ip(10), A, Some(&[1, 2, 3])).await?;
+    store.set_relay_info(ip(11), A, Some(&[4, 5, 6])).await?;
+    let listed = || async {
+        Ok::<_, S::Error>(
+            store
+                .active_leases()
+                .await?
+                .into_iter()
+                .map(|lease| lease.relay_info)
+                .collect::<Vec<_>>(),
+        )
+    };
+    assert_eq!(
+        listed().await?,
+        [Some(vec![1, 2, 3]), None],
+        "relay_info: listed with the client's lease only"
+    );
+    store.set_relay_info(ip(10), A, None).await?;
+    assert_eq!(listed().await?, [None, None], "relay_info: removed");
+    Ok(())
+}
+
+/// fingerprints are listed with the lease of the client they were stored for
+async fn fingerprints<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    store
+        .insert(ip(10), network(), A, now + HOUR, LeaseState::Active)
+        .await?;
+    store
+        .insert(ip(11), network(), B, now + HOUR, LeaseState::Active)
+        .await?;
+    let known = LeaseFingerprint {
+        fingerprint: "1,3,6,15".to_owned(),
+        vendor_class: Some("MSFT 5.0".to_owned()),
+        category: Some("Windows".to_owned()),
+        device: Some("Windows 10".to_owned()),
+    };
+    store.set_fingerprint(ip(10), A, Some(&known)).await?;
+    store.set_fingerprint(ip(11), A, Some(&known)).await?;
+    let listed = || async {
+        Ok::<_, S::Error>(
+            store
+                .active_leases()
+                .await?
+                .into_iter()
+                .map(|lease| lease.fingerprint)
+                .collect::<Vec<_>>(),
+        )
+    };
+    assert_eq!(
+        listed().await?,
+        [Some(known), None],
+        "fingerprints: listed with the client's lease only"
+    );
+    store.set_fingerprint(ip(10), A, None).await?;
+    assert_eq!(listed().await?, [None, None], "fingerprints: removed");
+    Ok(())
+}
+
+/// notes are kept by client & key
+async fn notes<S: Storage>(store: &S) -> Result<(), S::Error> {
+    store.set_client_note(A, "owner", Some("alice")).await?;
+    store.set_client_note(A, "rack", Some("b2")).await?;
+    store.set_client_note(A, "rack", Some("c3")).await?;
+    store.set_client_note(B, "owner", Some("bob")).await?;
+    assert_eq!(
+        store.client_notes(A).await?,
+        BTreeMap::from([
+            ("owner".to_owned(), "alice".to_owned()),
+            ("rack".to_owned(), "c3".to_owned()),
+        ]),
+        "notes: set & replaced by key"
+    );
+    store.set_client_note(A, "owner", None).await?;
+    store.set_client_note(A, "rack", None).await?;
+    assert!(store.client_notes(A).await?.is_empty(), "notes: removed");
+    assert_eq!(
+        store.client_notes(B).await?.len(),
+        1,
+        "notes: kept per client"
+    );
+    store
+        .insert_max_in_range(
+            range(),
+            &Exclusions::default(),
+            network(),
+            B,
+            now(store) + MINUTE,
+        )
+        .await?;
+    let leases = store.active_leases().await?;
+    assert_eq!(
+        leases.iter().map(|lease| &lease.notes).collect::<Vec<_>>(),
+        [&BTreeMap::from([("owner".to_owned(), "bob".to_owned())])],
+        "notes: listed with the client's lease"
+    );
+    Ok(())
+}
+
+/// config versions increase & are read back as pushed
+async fn config_versions<S: Storage>(store: &S) -> Result<(), S::Error> {
+    assert_eq!(
+        store.latest_config().await?,
+        None,
+        "config_versions: an empty store has no config"
+    );
+    let first = store.push_config("first", None).await?;
+    let second = store.push_config("second", Some("rollback")).await?;
+    assert!(
+        first.created_at >= now(store) - MINUTE && first.created_at <= now(store),
+        "config_versions: created at store.now()"
+    );
+    assert!(
+        second.version > first.version,
+        "config_versions: versions increase"
+    );
+    assert_eq!(
+        second.note.as_deref(),
+        Some("rollback"),
+        "config_versions: the note is kept"
+    );
+    assert_eq!(
+        store.latest_config().await?,
+        Some((second.clone(), "second".to_owned())),
+        "config_versions: the latest is the last pushed"
+    );
+    assert_eq!(
+        store.config_version(first.version).await?,
+        Some((first.clone(), "first".to_owned())),
+        "config_versions: found by version"
+    );
+    assert_eq!(
+        store.config_versions().await?,
+        [second, first],
+        "config_versions: listed newest first"
+    );
+    Ok(())
+}
+
+/// state kept across restarts
+async fn server_state<S: Storage>(store: &S) -> Result<(), S::Error> {
+    let now = now(store);
+    assert_eq!(
+        store.expiry_checkpoint().await?,
+        None,
+        "server_state: an empty store has no expiry checkpoint"
+    );
+    store.set_expiry_checkpoint(now).await?;
+    assert_eq!(
+        store.expiry_checkpoint().await?,
+        Some(now),
+        "server_state: the expiry checkpoint is kept"
+    );
+    let count = |msg_type: &str, count| NetworkCount {
+        network: network(),
+        msg_type: msg_type.to_owned(),
+        count,
+    };
+    store
+        .save_network_counts(&[count("ack", 3), count("nak", 1)])
+        .await?;
+    store.save_network_counts(&[count("ack", 5)]).await?;
+    assert_eq!(
+        store.network_counts().await?,
+        [count("ack", 5), count("nak", 1)],
+        "server_state: message counts are replaced by network & type"
+    );
+    Ok(())
+}
@@ -0,0 +1,210 @@
+//! lease state-change events
+//!
+//! `IpManager` publishes a [`LeaseEvent`] on a broadcast channel whenever it
+//! changes the state of an address. Expiry has no corresponding DHCP message, so
+//! expired leases & offers are found by polling storage with [`IpManager::watch_expiry`].
+//! Expired offers are counted in the `offer_expired` metric even with no subscribers.
+//! The time polling has reached is stored, so a restart resumes where it left off.
+//! Once published, expired entries are moved to their [`LeaseState`] after expiry.
+//!
+//! [`IpManager::watch_expiry`]: crate::IpManager::watch_expiry
+//! [`LeaseState`]: crate::LeaseState
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use dora_core::metrics;
+use tokio::sync::broadcast;
+use tracing::{debug, error, trace};
+
+use crate::{ClientInfo, IpManager, Storage};
+
+/// number of events buffered per subscriber before it starts missing events
+pub const EVENT_BUFFER: usize = 1_024;
+
+/// what happened to the address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaseEventKind {
+    /// address reserved for a client after DISCOVER
+    Offered,
+    /// address leased after REQUEST
+    Leased,
+    /// client released the address
+    Released,
+    /// address put on probation, after DECLINE or a successful ping check
+    Probated,
+    /// lease expired without being renewed
+    Expired,
+    /// an OFFER's hold ran out before the client sent REQUEST
+    OfferExpired,
+}
+
+impl LeaseEventKind {
+    /// event name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeaseEventKind::Offered => "offered",
+            LeaseEventKind::Leased => "leased",
+            LeaseEventKind::Released => "released",
+            LeaseEventKind::Probated => "probated",
+            LeaseEventKind::Expired => "expired",
+            LeaseEventKind::OfferExpired => "offer_expired",
+        }
+    }
+}
+
+/// a lease state change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseEvent {
+    pub kind: LeaseEventKind,
+    pub ip: IpAddr,
+    pub id: Option<Vec<u8>>,
+    /// expiry of the lease, or of the probation period
+    pub expires_at: SystemTime,
+    /// when the event occurred
+    pub at: SystemTime,
+    /// hardware vendor of the client, from the OUI database
+    pub vendor: Option<String>,
+}
+
+impl LeaseEvent {
+    pub fn new(
+        kind: LeaseEventKind,
+        ip: IpAddr,
+        id: Option<&[u8]>,
+        expires_at: SystemTime,
+    ) -> Self {
+        Self {
+            kind,
+            ip,
+            id: id.map(|id| id.to_vec()),
+            expires_at,
+            at: SystemTime::now(),
+            vendor: None,
+        }
+    }
+}
+
+impl From<ClientInfo> for LeaseEvent {
+    fn from(info: ClientInfo) -> Self {
+        Self {
+            kind: LeaseEventKind::Expired,
+            at: info.expires_at,
+            ip: info.ip,
+            id: info.id,
+            expires_at: info.expires_at,
+            vendor: None,
+        }
+    }
+}
+
+impl<T> IpManager<T>
+where
+    T: Storage,
+{
+    /// subscribe to lease events
+    pub fn subscribe(&self) -> broadcast::Receiver<LeaseEvent> {
+        self.events.subscribe()
+    }
+
+    /// the sender half of the event channel, subscribers can be created from it
+    pub fn events(&self) -> broadcast::Sender<LeaseEvent> {
+        self.events.clone()
+    }
+
+    pub(crate) fn publish(
+        &self,
+        kind: LeaseEventKind,
+        ip: IpAddr,
+        id: Option<&[u8]>,
+        expires_at: SystemTime,
+    ) {
+        // nobody listening is not an error
+        if self.events.receiver_count() > 0 {
+            let _ = self.events.send(LeaseEvent {
+                vendor: self.event_vendor(id),
+                at: self.now(),
+                ..LeaseEvent::new(kind, ip, id, expires_at)
+            });
+        }
+    }
+
+    fn event_vendor(&self, id: Option<&[u8]>) -> Option<String> {
+        id.and_then(|id| self.vendor(id)).map(ToOwned::to_owned)
+    }
+
+    /// poll storage every `interval`, publishing an `Expired` event for each
+    /// lease & an `OfferExpired` event for each offer that expired since the last
+    /// poll, then move the expired entries to `Expired` or `Reclaimed`. Polling
+    /// resumes from the checkpoint stored by the last run, so expiries while
+    /// dora was down aren't missed. Expired pins are deleted on each poll.
+    /// Never returns
+    pub async fn watch_expiry(self, interval: Duration) {
+        let mut last = match self.store.expiry_checkpoint().await {
+            Ok(checkpoint) => checkpoint.unwrap_or_else(|| self.now()),
+            Err(err) => {
+                error!(?err, "failed to get expiry checkpoint");
+                self.now()
+            }
+        };
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let now = self.now();
+            match self.expired_between(last, now).await {
+                Ok(()) => {
+                    last = now;
+                    if let Err(err) = self.store.set_expiry_checkpoint(now).await {
+                        error!(?err, "failed to store expiry checkpoint");
+                    }
+                    match self.store.expire_leases(now).await {
+                        Ok(0) => {}
+                        Ok(expired) => trace!(expired, "moved expired entries"),
+                        Err(err) => error!(?err, "failed to move expired entries"),
+                    }
+                }
+                // `last` isn't advanced, the next poll will pick these up
+                Err(err) => error!(?err, "failed to get expired leases"),
+            }
+            match self.store.delete_expired_pins().await {
+                Ok(0) => {}
+                Ok(pins) => debug!(pins, "deleted expired pins"),
+                Err(err) => error!(?err, "failed to delete expired pins"),
+            }
+        }
+    }
+
+    async fn expired_between(&self, start: SystemTime, end: SystemTime) -> Result<(), T::Error> {
+        let offers = self.store.expired_offers_between(start, end).await?;
+        // leases are only needed for events, skip the query if nobody is listening
+        let subscribed = self.events.receiver_count() > 0;
+        let expired = if subscribed {
+            self.store.expired_between(start, end).await?
+        } else {
+            Vec::new()
+        };
+        metrics::OFFER_EXPIRED.inc_by(offers.len() as u64);
+        if subscribed {
+            trace!(
+                leases = expired.len(),
+                offers = offers.len(),
+                "publishing expired leases"
+            );
+            for info in offers {
+                let _ = self.events.send(LeaseEvent {
+                    kind: LeaseEventKind::OfferExpired,
+                    vendor: self.event_vendor(info.id.as_deref()),
+                    ..info.into()
+                });
+            }
+            for info in expired {
+                let _ = self.events.send(LeaseEvent {
+                    vendor: self.event_vendor(info.id.as_deref()),
+                    ..info.into()
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -10,21 +10,62 @@
 //! `IpManager` then uses those methods to do the job of reserving/leasing ips while maintaining
 //! a nicer interface for the plugin to interact with.
 //!
+//! ## Backends
+//!
+//! [`Storage`] is a stable public API, backends can be written outside of
+//! dora (see [`sqlite::SqliteDb`] for the one it ships with). A backend must:
+//!
+//! - measure expiry against [`Storage::now`], an entry with `expires_at` at or
+//!   before it is expired & every lookup of un-expired entries skips it
+//! - only move an entry along the transitions [`LeaseState::can_transition`]
+//!   allows, reading its state as of its expiry ([`LeaseState::at`]), & log
+//!   every transition for [`Storage::lease_transitions`]
+//! - answer [`Storage::search_leases`] from the parsed [`LeaseQuery`]: its
+//!   [`LeaseQuery::filters`], sort & page
+//! - make each method atomic, a method that fails changes nothing
+//! - keep times to at least the second
+//!
+//! [`conformance::run`] checks a backend does what [`IpManager`] relies on.
+//! The database housekeeping methods ([`Storage::integrity_check`],
+//! [`Storage::checkpoint`], [`Storage::maintain`] &
+//! [`Storage::last_maintenance`]) have default implementations that do
+//! nothing, a backend only overrides the ones its database needs.
+//!
 //! [`Storage`]: ip_manager::Storage
 //! [`IpManager`]: ip_manager::IpManager
-use config::v4::{NetRange, Network};
-use icmp_ping::{Icmpv4, Listener, PingReply};
+pub use config::v4::{Exclusions, HostnamePolicy};
+use config::v4::{NetRange, Network, PingCheck, PingSource};
+use icmp_ping::{Icmpv4, Listener, PingReply, Source};
+use oui::OuiDb;
 
-use async_trait::async_trait;
+pub use async_trait::async_trait;
 use chrono::DateTime;
 use chrono::{offset::Utc, SecondsFormat};
 use thiserror::Error;
-use tracing::{debug, error, info, trace, warn};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, instrument, trace, warn};
 
+pub mod clock;
+pub mod conformance;
+pub mod events;
+pub mod maintenance;
+pub mod query;
+pub mod recovery;
+pub mod scan;
 pub mod sqlite;
+pub mod state;
+pub mod stats;
+
+pub use clock::{Clock, FakeClock, SystemClock};
+pub use events::{LeaseEvent, LeaseEventKind};
+pub use maintenance::Maintenance;
+pub use query::{Cond, Filter, IpCol, LeaseQuery, QueryError, SortKey, StateFilter};
+pub use recovery::{Recovered, RecoveryError};
+pub use state::{LeaseState, LeaseTransition, UnknownState};
+pub use stats::{DailyPoolStats, NetworkCount, PoolSample, RangeCounts, Retention};
 
 use std::{
-    collections::HashSet,
+    collections::BTreeMap,
     net::{IpAddr, Ipv4Addr},
     ops::RangeInclusive,
     sync::{
@@ -36,7 +77,7 @@ use std::{
 
 pub type ClientId = Option<Vec<u8>>;
 
-#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ClientInfo {
     ip: IpAddr,
     id: ClientId,
@@ -44,6 +85,127 @@ pub struct ClientInfo {
     expires_at: SystemTime,
 }
 
+impl ClientInfo {
+    pub fn new(ip: IpAddr, id: ClientId, network: IpAddr, expires_at: SystemTime) -> Self {
+        Self {
+            ip,
+            id,
+            network,
+            expires_at,
+        }
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// client the entry is for, `None` for an address on probation
+    pub fn id(&self) -> Option<&[u8]> {
+        self.id.as_deref()
+    }
+
+    /// subnet of the network the address belongs to
+    pub fn network(&self) -> IpAddr {
+        self.network
+    }
+
+    pub fn expires_at(&self) -> SystemTime {
+        self.expires_at
+    }
+}
+
+/// a hostname claimed by a client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostnameEntry {
+    pub hostname: String,
+    pub id: Vec<u8>,
+    pub ip: IpAddr,
+    pub expires_at: SystemTime,
+}
+
+/// an un-expired entry in the lease table, used for listing leases
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveLease {
+    pub ip: IpAddr,
+    pub id: Option<Vec<u8>>,
+    pub network: IpAddr,
+    /// `Clear` is an offered address waiting for REQUEST
+    pub state: IpState,
+    /// the stored state, `state` is derived from it
+    pub lease_state: LeaseState,
+    pub expires_at: SystemTime,
+    /// hostname registered by the client, if hostnames are tracked
+    pub hostname: Option<String>,
+    /// relay agent information (option 82) data the lease was made with
+    pub relay_info: Option<Vec<u8>>,
+    /// DHCP fingerprint of the client the lease was made for
+    pub fingerprint: Option<LeaseFingerprint>,
+    /// notes attached to the client, by key
+    pub notes: BTreeMap<String, String>,
+}
+
+/// the DHCP fingerprint a lease was made with & the device it identified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseFingerprint {
+    /// parameter request list codes, comma separated
+    pub fingerprint: String,
+    pub vendor_class: Option<String>,
+    /// device category, `None` if the fingerprint isn't known
+    pub category: Option<String>,
+    pub device: Option<String>,
+}
+
+/// a client claimed a hostname already held by another client
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostnameConflict {
+    /// the hostname the client asked for
+    pub hostname: String,
+    pub id: Vec<u8>,
+    pub existing_id: Vec<u8>,
+    pub policy: HostnamePolicy,
+    /// the hostname that was registered for the client, if any
+    pub assigned: Option<String>,
+    pub created_at: SystemTime,
+}
+
+/// the FORCERENEW nonce (RFC 6704) sent to a client in the ACK for `ip`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForceRenewNonce {
+    pub ip: IpAddr,
+    pub id: Vec<u8>,
+    pub chaddr: Vec<u8>,
+    pub network: IpAddr,
+    /// server identifier sent in the ACK
+    pub server_id: Ipv4Addr,
+    /// client classes matched when the lease was made
+    pub classes: Vec<String>,
+    pub nonce: Vec<u8>,
+}
+
+/// a lease promoted to a reservation, the address is held for the client
+/// until `expires_at` even when it's not leased
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StickyLease {
+    pub ip: IpAddr,
+    pub id: Vec<u8>,
+    pub network: IpAddr,
+    /// times the client renewed the address
+    pub renewals: u32,
+    pub expires_at: SystemTime,
+    /// pinned through the api, renewals don't extend it
+    pub pinned: bool,
+}
+
+/// a stored config version, see [`Storage::push_config`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigVersion {
+    /// increases with every push, versions are never reused
+    pub version: u64,
+    pub created_at: SystemTime,
+    /// why the config was pushed, ex. `rollback to 3`
+    pub note: Option<String>,
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum IpState {
     Lease,
@@ -64,32 +226,48 @@ impl From<IpState> for (bool, bool) {
     }
 }
 
+/// persistence of leases & the server state kept with them, see the
+/// [crate docs](crate#backends) for what a backend must do. Implemented with
+/// [`async_trait`], which is re-exported
 #[async_trait]
 pub trait Storage: Send + Sync + 'static {
     // send/sync/static required for async trait bounds
     type Error: std::error::Error + Send + Sync + 'static;
+    /// the current time, expiry is measured against it
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
     /// updates if expired & ip matches or if ip & id match
     async fn update_expired(
         &self,
         ip: IpAddr,
-        state: IpState,
+        state: LeaseState,
         id: &[u8],
         expires_at: SystemTime,
     ) -> Result<bool, Self::Error>;
+    /// add an entry for an address that has none
     async fn insert(
         &self,
         ip: IpAddr,
         network: IpAddr,
         id: &[u8],
         expires_at: SystemTime,
-        state: Option<IpState>,
+        state: LeaseState,
     ) -> Result<(), Self::Error>;
 
+    /// the entry for `ip`, expired or not
     async fn get(&self, ip: IpAddr) -> Result<Option<State>, Self::Error>;
+    /// an address with an un-expired entry for `id`
     async fn get_id(&self, id: &[u8]) -> Result<Option<IpAddr>, Self::Error>;
+    /// every un-expired lease & offer for `id`, on any network
+    async fn get_all_id(&self, id: &[u8]) -> Result<Vec<ClientInfo>, Self::Error>;
+    /// mark the un-expired offer or lease of `id` for `ip` released
     async fn release_ip(&self, ip: IpAddr, id: &[u8]) -> Result<Option<ClientInfo>, Self::Error>;
+    /// remove the entry for `ip`
     async fn delete(&self, ip: IpAddr) -> Result<(), Self::Error>;
 
+    /// offer the entry of `id` in `range` (unless it's on probation) or the
+    /// lowest expired one, skipping sticky leases held for other clients
     async fn next_expired(
         &self,
         range: RangeInclusive<IpAddr>,
@@ -98,32 +276,221 @@ pub trait Storage: Send + Sync + 'static {
         expires_at: SystemTime,
     ) -> Result<Option<IpAddr>, Self::Error>;
 
+    /// offer the address after the highest entry in `range`, or its start if
+    /// it has none, skipping `exclusions` & sticky leases held for other clients
     async fn insert_max_in_range(
         &self,
         range: RangeInclusive<IpAddr>,
         // TODO not ipv4
-        exclusions: &HashSet<Ipv4Addr>,
+        exclusions: &Exclusions,
         network: IpAddr,
         id: &[u8],
         expires_at: SystemTime,
     ) -> Result<Option<IpAddr>, Self::Error>;
-    /// updates if not expired & id & ip match
+    /// updates if not expired & id & ip match, `new_id` is the client the
+    /// entry is for afterwards
     async fn update_unexpired(
         &self,
         ip: IpAddr,
-        state: IpState,
+        state: LeaseState,
         id: &[u8],
         expires_at: SystemTime,
         new_id: Option<&[u8]>,
     ) -> Result<Option<IpAddr>, Self::Error>;
+    /// updates the entry for `ip` whatever client it's for
     async fn update_ip(
         &self,
         ip: IpAddr,
-        state: IpState,
+        state: LeaseState,
         id: Option<&[u8]>,
         expires_at: SystemTime,
     ) -> Result<Option<State>, Self::Error>;
+    /// count the un-expired entries in `state`
     async fn count(&self, state: IpState) -> Result<usize, Self::Error>;
+
+    /// get the un-expired owner of `hostname`
+    async fn get_hostname(&self, hostname: &str) -> Result<Option<HostnameEntry>, Self::Error>;
+    /// set `hostname` for `id`, any other hostname `id` holds is removed
+    async fn set_hostname(
+        &self,
+        hostname: &str,
+        ip: IpAddr,
+        id: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<(), Self::Error>;
+    /// record a conflict, once per hostname & client. Returns `false` if it
+    /// was recorded already, its existing client, policy & assigned hostname
+    /// are updated but `created_at` is kept
+    async fn insert_hostname_conflict(
+        &self,
+        conflict: &HostnameConflict,
+    ) -> Result<bool, Self::Error>;
+    /// most recent hostname conflicts, newest first
+    async fn hostname_conflicts(&self, limit: u32) -> Result<Vec<HostnameConflict>, Self::Error>;
+    /// leases that expired after `start`, up to & including `end`
+    async fn expired_between(
+        &self,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Result<Vec<ClientInfo>, Self::Error>;
+    /// offers (reserved, never leased) that expired after `start`, up to & including `end`
+    async fn expired_offers_between(
+        &self,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Result<Vec<ClientInfo>, Self::Error>;
+    /// move offers & leases that expired by `now` to `Expired` & probations
+    /// that ended to `Reclaimed`, returning the number of entries moved
+    async fn expire_leases(&self, now: SystemTime) -> Result<u64, Self::Error>;
+    /// the transition log, newest first, only of `ip` if set
+    async fn lease_transitions(
+        &self,
+        ip: Option<IpAddr>,
+        limit: u32,
+    ) -> Result<Vec<LeaseTransition>, Self::Error>;
+    /// delete transitions logged before `before`
+    async fn delete_lease_transitions(&self, before: SystemTime) -> Result<u64, Self::Error>;
+    /// count the un-expired entries in `range` by state
+    async fn range_counts(&self, range: RangeInclusive<IpAddr>)
+        -> Result<RangeCounts, Self::Error>;
+    async fn insert_pool_samples(&self, samples: &[PoolSample]) -> Result<(), Self::Error>;
+    /// samples taken at or after `since`, oldest first
+    async fn pool_samples(&self, since: SystemTime) -> Result<Vec<PoolSample>, Self::Error>;
+    /// roll up samples taken before `before` into daily rollups & delete them,
+    /// returning the number of samples rolled up
+    async fn rollup_pool_samples(&self, before: SystemTime) -> Result<u64, Self::Error>;
+    /// daily rollups for days starting at or after `since`, oldest first
+    async fn pool_rollups(&self, since: SystemTime) -> Result<Vec<DailyPoolStats>, Self::Error>;
+    /// delete rollups for days starting before `before`, returning the number deleted
+    async fn delete_pool_rollups(&self, before: SystemTime) -> Result<u64, Self::Error>;
+    /// delete hostname conflicts logged before `before`, returning the number deleted
+    async fn delete_hostname_conflicts(&self, before: SystemTime) -> Result<u64, Self::Error>;
+    /// store cumulative message counts, replacing the saved count of each
+    async fn save_network_counts(&self, counts: &[NetworkCount]) -> Result<(), Self::Error>;
+    /// all saved message counts
+    async fn network_counts(&self) -> Result<Vec<NetworkCount>, Self::Error>;
+    /// all un-expired entries, ordered by ip
+    async fn active_leases(&self) -> Result<Vec<ActiveLease>, Self::Error>;
+    /// the un-expired entries matching every filter of `query`, sorted & paged
+    /// as it asks
+    async fn search_leases(&self, query: &LeaseQuery) -> Result<Vec<ActiveLease>, Self::Error>;
+    /// store the FORCERENEW nonce sent with the lease on `nonce.ip`, replacing
+    /// any earlier one
+    async fn set_forcerenew_nonce(&self, nonce: &ForceRenewNonce) -> Result<(), Self::Error>;
+    /// nonces of un-expired leases still held by the client they were sent to
+    async fn forcerenew_nonces(&self) -> Result<Vec<ForceRenewNonce>, Self::Error>;
+    /// count a lease of `ip` by `id`, starting over if the address was held by
+    /// another client. The lease is made sticky until `hold_until` once it has
+    /// been renewed `after_renewals` times, or if it's already sticky. Returns
+    /// the expiry of the sticky lease
+    async fn renew_sticky(
+        &self,
+        ip: IpAddr,
+        network: IpAddr,
+        id: &[u8],
+        after_renewals: Option<u32>,
+        hold_until: SystemTime,
+    ) -> Result<Option<SystemTime>, Self::Error>;
+    /// make the lease of `ip` by `id` sticky until `expires_at`
+    async fn set_sticky(
+        &self,
+        ip: IpAddr,
+        network: IpAddr,
+        id: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<(), Self::Error>;
+    /// hold `ip` for `id` until `expires_at`, whether or not it's leased. Renewals
+    /// don't extend a pin, it's deleted by [`Storage::delete_expired_pins`]
+    async fn set_pin(
+        &self,
+        ip: IpAddr,
+        network: IpAddr,
+        id: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<(), Self::Error>;
+    /// delete pins that have expired, returning how many there were
+    async fn delete_expired_pins(&self) -> Result<u64, Self::Error>;
+    /// the un-expired sticky lease of `id` on `network`
+    async fn get_sticky(
+        &self,
+        id: &[u8],
+        network: IpAddr,
+    ) -> Result<Option<StickyLease>, Self::Error>;
+    /// the un-expired sticky lease on `ip`
+    async fn get_sticky_ip(&self, ip: IpAddr) -> Result<Option<StickyLease>, Self::Error>;
+    /// delete the sticky lease on `ip`, returning whether there was one
+    async fn delete_sticky(&self, ip: IpAddr) -> Result<bool, Self::Error>;
+    /// all un-expired sticky leases, ordered by ip
+    async fn sticky_leases(&self) -> Result<Vec<StickyLease>, Self::Error>;
+    /// store the relay agent information `id` leased `ip` with, `None` removes it
+    async fn set_relay_info(
+        &self,
+        ip: IpAddr,
+        id: &[u8],
+        info: Option<&[u8]>,
+    ) -> Result<(), Self::Error>;
+    /// store the fingerprint `id` leased `ip` with, `None` removes it
+    async fn set_fingerprint(
+        &self,
+        ip: IpAddr,
+        id: &[u8],
+        fingerprint: Option<&LeaseFingerprint>,
+    ) -> Result<(), Self::Error>;
+    /// set the note `key` on client `id`, `None` removes it. Notes are kept
+    /// whether or not the client holds a lease
+    async fn set_client_note(
+        &self,
+        id: &[u8],
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<(), Self::Error>;
+    /// the notes on client `id`, by key
+    async fn client_notes(&self, id: &[u8]) -> Result<BTreeMap<String, String>, Self::Error>;
+    /// problems found checking the database structure, empty if there are none
+    async fn integrity_check(&self) -> Result<Vec<String>, Self::Error> {
+        Ok(Vec::new())
+    }
+    /// delete per-lease data for addresses no longer held by the client it
+    /// was stored for, returning the number of rows deleted
+    async fn delete_orphans(&self) -> Result<u64, Self::Error>;
+    /// the time expired leases were last published up to
+    async fn expiry_checkpoint(&self) -> Result<Option<SystemTime>, Self::Error>;
+    async fn set_expiry_checkpoint(&self, at: SystemTime) -> Result<(), Self::Error>;
+    /// sync committed changes to the database file
+    async fn checkpoint(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// return up to `max_pages` free pages to the filesystem (0 for all of them)
+    /// & refresh query planner statistics, storing the result
+    async fn maintain(&self, _max_pages: u32) -> Result<Maintenance, Self::Error> {
+        Ok(Maintenance {
+            started_at: self.now(),
+            elapsed: Duration::ZERO,
+            reclaimed_pages: 0,
+            page_count: 0,
+            free_pages: 0,
+            full_vacuum: false,
+        })
+    }
+    /// the result of the last [`Storage::maintain`] run
+    async fn last_maintenance(&self) -> Result<Option<Maintenance>, Self::Error> {
+        Ok(None)
+    }
+    /// store `config` as a new version, returning it
+    async fn push_config(
+        &self,
+        config: &str,
+        note: Option<&str>,
+    ) -> Result<ConfigVersion, Self::Error>;
+    /// every stored config version without its config, newest first
+    async fn config_versions(&self) -> Result<Vec<ConfigVersion>, Self::Error>;
+    /// the stored config `version`
+    async fn config_version(
+        &self,
+        version: u64,
+    ) -> Result<Option<(ConfigVersion, String)>, Self::Error>;
+    /// the newest stored config version
+    async fn latest_config(&self) -> Result<Option<(ConfigVersion, String)>, Self::Error>;
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -157,6 +524,8 @@ pub struct IpManager<T> {
     store: T,
     icmpv4: Arc<IcmpInner>,
     ping_cache: moka::future::Cache<IpAddr, Option<PingReply>>,
+    events: broadcast::Sender<LeaseEvent>,
+    oui: Option<Arc<OuiDb>>,
 }
 
 impl<T: Clone> Clone for IpManager<T> {
@@ -165,6 +534,8 @@ impl<T: Clone> Clone for IpManager<T> {
             store: self.store.clone(),
             icmpv4: self.icmpv4.clone(),
             ping_cache: self.ping_cache.clone(),
+            events: self.events.clone(),
+            oui: self.oui.clone(),
         }
     }
 }
@@ -179,30 +550,43 @@ where
     T: Storage,
 {
     /// Check to see if the address is in use.
-    /// If `Network` has `ping_check` set to `true`, we will test to see if the IP is already
-    /// being used by another client
+    /// If the range has `ping_check` set to `true`, we will test to see if the IP is already
+    /// being used by another client. A ping is sent, then `retries` more as each times out
+    #[instrument(name = "ping", level = "debug", skip(self, timeout))]
     async fn addr_in_use(
         &self,
         ip: IpAddr,
         timeout: Duration,
+        retries: u32,
     ) -> Result<PingReply, icmp_ping::Error> {
-        let seq_cnt = self.icmpv4.seq_cnt.fetch_add(1, Ordering::Relaxed);
-        // send a single ping
-        self.icmpv4
-            .listener
-            .pinger(ip)
-            .timeout(timeout)
-            .ping(seq_cnt)
-            .await
-        // ping succeeded, meaning addr is in use
-    }
-
-    /// returns Ok(()) if ping failed or ping == false
+        let mut attempt = 0;
+        loop {
+            let seq_cnt = self.icmpv4.seq_cnt.fetch_add(1, Ordering::Relaxed);
+            match self
+                .icmpv4
+                .listener
+                .pinger(ip)
+                .timeout(timeout)
+                .ping(seq_cnt)
+                .await
+            {
+                // ping succeeded, meaning addr is in use
+                Ok(reply) => return Ok(reply),
+                Err(err) if attempt >= retries => return Err(err),
+                Err(err) => {
+                    trace!(?err, attempt, "no ping reply, retrying");
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// returns Ok(()) if ping failed or ping is disabled
     /// returns Err if ping succeeded
-    pub async fn ping_check(&self, ip: IpAddr, network: &Network) -> Result<(), IpError<T::Error>> {
-        if network.ping_check() {
+    pub async fn ping_check(&self, ip: IpAddr, ping: PingCheck) -> Result<(), IpError<T::Error>> {
+        if ping.enabled() {
             let fut = async {
-                match self.addr_in_use(ip, network.ping_timeout()).await {
+                match self.addr_in_use(ip, ping.timeout(), ping.retries()).await {
                     Ok(reply) => {
                         // ping succeeded
                         if let Err(err) = self.store.delete(ip).await {
@@ -240,9 +624,43 @@ where
                 .time_to_live(Duration::from_secs(120))
                 .initial_capacity(1_000)
                 .build(),
+            events: broadcast::channel(events::EVENT_BUFFER).0,
+            oui: None,
         })
     }
 
+    /// send ping checks from `source` instead of the address & interface
+    /// chosen by the routing table
+    pub fn with_ping_source(mut self, source: &PingSource) -> Result<Self, icmp_ping::Error> {
+        let source = Source {
+            addr: source.address.map(IpAddr::V4),
+            interface: source.interface.clone(),
+        };
+        self.icmpv4 = Arc::new(IcmpInner {
+            seq_cnt: AtomicU16::new(1),
+            listener: Listener::<Icmpv4>::bind(&source)?,
+        });
+        Ok(self)
+    }
+
+    /// the current time of the store's clock, lease expiry & probation are
+    /// measured against it
+    pub fn now(&self) -> SystemTime {
+        self.store.now()
+    }
+
+    /// use `oui` to add the hardware vendor to lease events
+    pub fn with_oui(mut self, oui: Arc<OuiDb>) -> Self {
+        self.oui = Some(oui);
+        self
+    }
+
+    /// hardware vendor of the client, if the client id is a MAC address & an
+    /// OUI database is loaded
+    pub fn vendor(&self, id: &[u8]) -> Option<&str> {
+        self.oui.as_ref()?.lookup_client_id(id)
+    }
+
     /// get the first available IP in a range with a given id/expiry/network
     pub async fn reserve_first(
         &self,
@@ -301,11 +719,14 @@ where
                 IpAddr::V4(ipv4) => {
                     if range.contains(&ipv4) {
                         // ping_check will delete the expired entry if it's in use
-                        match self.ping_check(ip, network).await {
-                            Ok(()) => return Ok(ip),
+                        match self.ping_check(ip, range.ping()).await {
+                            Ok(()) => {
+                                self.publish(LeaseEventKind::Offered, ip, Some(id), expires_at);
+                                return Ok(ip);
+                            }
                             // ping success so insert probated IP
                             Err(err) => {
-                                let probation_time = SystemTime::now() + network.probation_period();
+                                let probation_time = self.now() + network.probation_period();
                                 info!(
                                     ?err,
                                     probation_time = %DateTime::<Utc>::from(probation_time).to_rfc3339_opts(SecondsFormat::Secs, true),
@@ -314,13 +735,19 @@ where
                                 // update regardless of expiry/id because something is using the IP
                                 if let Err(err) = self
                                     .store
-                                    .update_ip(ip, IpState::Probate, None, probation_time)
+                                    .update_ip(ip, LeaseState::Declined, None, probation_time)
                                     .await
                                 {
                                     error!(?err, "failed to probate IP on ping success");
                                     // not returning error because we must give client an IP
                                 } else {
                                     debug!("IP put on probation, trying next");
+                                    self.publish(
+                                        LeaseEventKind::Probated,
+                                        ip,
+                                        None,
+                                        probation_time,
+                                    );
                                 }
                                 continue;
                             }
@@ -342,7 +769,7 @@ where
     }
 
     /// tries to take an ip for an id that's set to expire at some future time.
-    /// If `ping` is set, will send a ping to the IP, returning an error if in use
+    /// If `ping` is enabled, will send a ping to the IP, returning an error if in use
     /// Returns
     ///     `Err` if ip/id are already present or ping succeeded
     ///     `Ok(())` allocated IP successfully
@@ -352,13 +779,17 @@ where
         subnet: IpAddr,
         id: &[u8],
         expires_at: SystemTime,
-        network: &Network,
+        ping: PingCheck,
     ) -> Result<(), IpError<T::Error>> {
+        if self.held_for_other(ip, id).await? {
+            debug!(?ip, "IP is a sticky lease of another client");
+            return Err(IpError::AddrInUse(ip));
+        }
         // TODO: there may be a way to remove this .get also
         if self.store.get(ip).await?.is_some() {
             return if self
                 .store
-                .update_expired(ip, IpState::Clear, id, expires_at)
+                .update_expired(ip, LeaseState::Offered, id, expires_at)
                 .await?
             {
                 debug!(
@@ -366,6 +797,7 @@ where
                     ?id,
                     "set reserved, found ip/id for this client or expired"
                 );
+                self.publish(LeaseEventKind::Offered, ip, Some(id), expires_at);
                 Ok(())
             } else {
                 debug!("IP not updated, couldn't find ip/id or in use");
@@ -373,9 +805,12 @@ where
             };
         };
         // if the entry doesn't exist yet & ping fails, insert it
-        self.store.insert(ip, subnet, id, expires_at, None).await?;
+        self.store
+            .insert(ip, subnet, id, expires_at, LeaseState::Offered)
+            .await?;
         // not marking for probation because request IP can be sent at any time
-        self.ping_check(ip, network).await?;
+        self.ping_check(ip, ping).await?;
+        self.publish(LeaseEventKind::Offered, ip, Some(id), expires_at);
 
         Ok(())
     }
@@ -396,6 +831,16 @@ where
             }
         }
     }
+    /// is there a lease (or reservation) for this ip/id that has expired?
+    pub async fn lease_expired(&self, ip: IpAddr, id: &[u8]) -> Result<bool, IpError<T::Error>> {
+        Ok(match self.store.get(ip).await? {
+            Some(state) => {
+                let info = state.as_ref();
+                info.id.as_deref() == Some(id) && info.expires_at <= self.now()
+            }
+            None => false,
+        })
+    }
     /// Sets a reserved ip/id combo to leased state. If no un-expired ip/id pair
     /// found, then if we're authoritative we will just try to insert the IP, and
     /// if not we return.
@@ -409,9 +854,13 @@ where
         expires_at: SystemTime,
         network: &Network,
     ) -> Result<(), IpError<T::Error>> {
+        if self.held_for_other(ip, id).await? {
+            debug!(?ip, "IP is a sticky lease of another client");
+            return Err(IpError::AddrInUse(ip));
+        }
         match self
             .store
-            .update_unexpired(ip, IpState::Lease, id, expires_at, Some(id))
+            .update_unexpired(ip, LeaseState::Active, id, expires_at, Some(id))
             .await?
         {
             Some(ip) => {
@@ -420,6 +869,7 @@ where
                     ?id,
                     "found ip for id-- updating expiry and setting leased"
                 );
+                self.publish(LeaseEventKind::Leased, ip, Some(id), expires_at);
                 Ok(())
             }
             None if network.authoritative() => {
@@ -437,12 +887,13 @@ where
                         network.subnet().into(),
                         id,
                         expires_at,
-                        Some(IpState::Lease),
+                        LeaseState::Active,
                     )
                     .await
                 {
                     Ok(()) => {
                         trace!("inserted new IP");
+                        self.publish(LeaseEventKind::Leased, ip, Some(id), expires_at);
                         Ok(())
                     }
                     Err(err) => {
@@ -461,9 +912,10 @@ where
         }
     }
 
-    /// release the requested ip if the (ip, id) pair matches
+    /// release the requested ip if the (ip, id) pair matches. The entry is kept,
+    /// marked released
     /// Returns
-    ///     Ok(None) if ip did not exist in storage
+    ///     Ok(None) if ip did not exist in storage or isn't offered/leased to id
     ///     Ok(Some(info)) the existing client info
     ///     Err(_) for database error
     pub async fn release_ip(
@@ -471,8 +923,37 @@ where
         ip: IpAddr,
         id: &[u8],
     ) -> Result<Option<ClientInfo>, IpError<T::Error>> {
-        // TODO: this deletes the entry, but we don't really need to
-        Ok(self.store.release_ip(ip, id).await?)
+        let info = self.store.release_ip(ip, id).await?;
+        if info.is_some() {
+            self.publish(LeaseEventKind::Released, ip, Some(id), self.now());
+        }
+        Ok(info)
+    }
+
+    /// release the un-expired leases & offers `id` holds on networks other than
+    /// `network`, returning the released ips
+    pub async fn release_elsewhere(
+        &self,
+        id: &[u8],
+        network: IpAddr,
+    ) -> Result<Vec<IpAddr>, IpError<T::Error>> {
+        let mut released = Vec::new();
+        for info in self.store.get_all_id(id).await? {
+            if info.network == network {
+                continue;
+            }
+            if self.release_ip(info.ip, id).await?.is_some() {
+                info!(
+                    ip = ?info.ip,
+                    old_network = ?info.network,
+                    ?network,
+                    ?id,
+                    "client moved networks, released lease"
+                );
+                released.push(info.ip);
+            }
+        }
+        Ok(released)
     }
 
     /// Will mark IP for probation if it is un-expired and ip/id match
@@ -486,7 +967,7 @@ where
     ) -> Result<(), IpError<T::Error>> {
         match self
             .store
-            .update_unexpired(ip, IpState::Probate, id, expires_at, None)
+            .update_unexpired(ip, LeaseState::Declined, id, expires_at, None)
             .await?
         {
             Some(ip) => {
@@ -495,6 +976,7 @@ where
                     ?id,
                     "found ip for id-- updating expiry and set PROBATION"
                 );
+                self.publish(LeaseEventKind::Probated, ip, Some(id), expires_at);
                 Ok(())
             }
             None => {
@@ -509,6 +991,186 @@ where
     }
 }
 
+/// max `-N` suffix tried before giving up on a hostname
+const MAX_HOSTNAME_SUFFIX: usize = 100;
+
+impl<T> IpManager<T>
+where
+    T: Storage,
+{
+    /// claim `hostname` for `id`. If another client holds the name, `policy`
+    /// decides the outcome & the conflict is recorded.
+    /// Returns
+    ///     Ok(Some(name)) the hostname registered for `id`
+    ///     Ok(None) the hostname was rejected
+    pub async fn claim_hostname(
+        &self,
+        hostname: &str,
+        ip: IpAddr,
+        id: &[u8],
+        expires_at: SystemTime,
+        policy: HostnamePolicy,
+    ) -> Result<Option<String>, IpError<T::Error>> {
+        let existing = match self.store.get_hostname(hostname).await? {
+            Some(existing) if existing.id != id => existing,
+            // free or already held by this client
+            _ => {
+                self.store
+                    .set_hostname(hostname, ip, id, expires_at)
+                    .await?;
+                return Ok(Some(hostname.to_owned()));
+            }
+        };
+        let assigned = match policy {
+            HostnamePolicy::Reject => None,
+            HostnamePolicy::Replace => Some(hostname.to_owned()),
+            HostnamePolicy::Suffix => self.free_suffix(hostname, id).await?,
+        };
+        if let Some(name) = &assigned {
+            self.store.set_hostname(name, ip, id, expires_at).await?;
+        }
+        let conflict = HostnameConflict {
+            hostname: hostname.to_owned(),
+            id: id.to_vec(),
+            existing_id: existing.id,
+            policy,
+            assigned,
+            created_at: self.now(),
+        };
+        // the client asks for the same name on every renewal
+        if self.store.insert_hostname_conflict(&conflict).await? {
+            info!(
+                ?hostname,
+                ?id,
+                existing_id = ?conflict.existing_id,
+                ?policy,
+                assigned = ?conflict.assigned,
+                "hostname conflict"
+            );
+        }
+        Ok(conflict.assigned)
+    }
+
+    /// claim a hostname generated for `id`, numbering it if another client holds
+    /// `hostname`. Not a conflict, since the client didn't ask for the name.
+    /// Returns the hostname registered for `id`, `None` if every numbered name
+    /// is taken
+    pub async fn claim_generated_hostname(
+        &self,
+        hostname: &str,
+        ip: IpAddr,
+        id: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<Option<String>, IpError<T::Error>> {
+        let assigned = match self.store.get_hostname(hostname).await? {
+            Some(existing) if existing.id != id => self.free_suffix(hostname, id).await?,
+            _ => Some(hostname.to_owned()),
+        };
+        if let Some(name) = &assigned {
+            self.store.set_hostname(name, ip, id, expires_at).await?;
+        }
+        Ok(assigned)
+    }
+
+    /// the first of `name-2`, `name-3`... that's free or held by `id`. The
+    /// number goes on the first label, the domain is kept
+    async fn free_suffix(&self, hostname: &str, id: &[u8]) -> Result<Option<String>, T::Error> {
+        let (label, domain) = match hostname.find('.') {
+            Some(dot) => hostname.split_at(dot),
+            None => (hostname, ""),
+        };
+        for n in 2..=MAX_HOSTNAME_SUFFIX {
+            let name = format!("{label}-{n}{domain}");
+            match self.store.get_hostname(&name).await? {
+                Some(entry) if entry.id != id => continue,
+                _ => return Ok(Some(name)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// most recent hostname conflicts, newest first
+    pub async fn hostname_conflicts(
+        &self,
+        limit: u32,
+    ) -> Result<Vec<HostnameConflict>, IpError<T::Error>> {
+        Ok(self.store.hostname_conflicts(limit).await?)
+    }
+
+    /// remember the FORCERENEW nonce sent to a client, so a FORCERENEW can be
+    /// authenticated later
+    pub async fn set_forcerenew_nonce(
+        &self,
+        nonce: &ForceRenewNonce,
+    ) -> Result<(), IpError<T::Error>> {
+        Ok(self.store.set_forcerenew_nonce(nonce).await?)
+    }
+
+    /// the un-expired sticky lease of `id` on `network`
+    pub async fn sticky(
+        &self,
+        id: &[u8],
+        network: IpAddr,
+    ) -> Result<Option<StickyLease>, IpError<T::Error>> {
+        Ok(self.store.get_sticky(id, network).await?)
+    }
+
+    /// count a lease of `ip` towards making it sticky, if `network` has sticky
+    /// leases enabled. A sticky lease is held for another `hold` from now.
+    /// Returns the expiry of the sticky lease
+    pub async fn renew_sticky(
+        &self,
+        ip: IpAddr,
+        id: &[u8],
+        network: &Network,
+    ) -> Result<Option<SystemTime>, IpError<T::Error>> {
+        let sticky = match network.sticky() {
+            Some(sticky) => sticky,
+            None => return Ok(None),
+        };
+        Ok(self
+            .store
+            .renew_sticky(
+                ip,
+                network.subnet().into(),
+                id,
+                sticky.after_renewals(),
+                self.now() + sticky.hold(),
+            )
+            .await?)
+    }
+
+    /// is `ip` held as a sticky lease for a client other than `id`?
+    async fn held_for_other(&self, ip: IpAddr, id: &[u8]) -> Result<bool, IpError<T::Error>> {
+        Ok(matches!(
+            self.store.get_sticky_ip(ip).await?,
+            Some(sticky) if sticky.id != id
+        ))
+    }
+
+    /// remember the relay agent information (option 82) a lease was made with,
+    /// `None` if the request wasn't relayed with any
+    pub async fn set_relay_info(
+        &self,
+        ip: IpAddr,
+        id: &[u8],
+        info: Option<&[u8]>,
+    ) -> Result<(), IpError<T::Error>> {
+        Ok(self.store.set_relay_info(ip, id, info).await?)
+    }
+
+    /// remember the DHCP fingerprint a lease was made with, `None` if the
+    /// client sent no parameter request list
+    pub async fn set_fingerprint(
+        &self,
+        ip: IpAddr,
+        id: &[u8],
+        fingerprint: Option<&LeaseFingerprint>,
+    ) -> Result<(), IpError<T::Error>> {
+        Ok(self.store.set_fingerprint(ip, id, fingerprint).await?)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum IpError<E> {
     #[error("ip is leased {0:?}")]
@@ -0,0 +1,73 @@
+//! lease database maintenance
+//!
+//! Deleting expired rows leaves free pages in the database file. SQLite reuses
+//! them, but never gives them back to the filesystem, and its query planner
+//! statistics go stale as the tables grow & shrink.
+//!
+//! [`IpManager::watch_maintenance`] runs [`Storage::maintain`] once each time a
+//! quiet window opens, ex. overnight. It returns up to a number of free pages to
+//! the filesystem with an incremental vacuum & refreshes the planner statistics
+//! with `ANALYZE`. The first run on a database created without incremental
+//! vacuum enabled converts it with a full `VACUUM`, which rewrites the file &
+//! blocks writes while it runs. The result of the last run is kept in the
+//! database.
+use std::time::{Duration, SystemTime};
+
+use chrono::Utc;
+use config::schedule::Schedule;
+use tracing::{error, info};
+
+use crate::{IpManager, Storage};
+
+/// how often [`IpManager::watch_maintenance`] checks whether its window is open
+pub const MAINTENANCE_POLL: Duration = Duration::from_secs(60);
+
+/// what a run of [`Storage::maintain`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Maintenance {
+    pub started_at: SystemTime,
+    pub elapsed: Duration,
+    /// pages returned to the filesystem
+    pub reclaimed_pages: u64,
+    /// size of the database after the run, in pages
+    pub page_count: u64,
+    /// free pages left in the database after the run
+    pub free_pages: u64,
+    /// the run converted the database to incremental vacuum with a full `VACUUM`
+    pub full_vacuum: bool,
+}
+
+impl<T> IpManager<T>
+where
+    T: Storage,
+{
+    /// run [`Storage::maintain`] once each time `window` opens, reclaiming at
+    /// most `max_pages` free pages per run (0 reclaims all of them). Never returns
+    pub async fn watch_maintenance(self, window: Schedule, max_pages: u32) {
+        let mut ticker = tokio::time::interval(MAINTENANCE_POLL);
+        // set once the current window has been run in, cleared when it closes
+        let mut done = false;
+        loop {
+            ticker.tick().await;
+            if !window.contains(Utc::now()) {
+                done = false;
+                continue;
+            }
+            if done {
+                continue;
+            }
+            done = true;
+            match self.store.maintain(max_pages).await {
+                Ok(run) => info!(
+                    reclaimed_pages = run.reclaimed_pages,
+                    free_pages = run.free_pages,
+                    page_count = run.page_count,
+                    full_vacuum = run.full_vacuum,
+                    elapsed = ?run.elapsed,
+                    "lease database maintenance done"
+                ),
+                Err(err) => error!(?err, "failed lease database maintenance"),
+            }
+        }
+    }
+}
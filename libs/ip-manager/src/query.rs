@@ -0,0 +1,449 @@
+//! lease search queries
+//!
+//! A query is a list of conditions joined by `AND`, ex.
+//! `state=leased AND network=10.1.0.0/16 AND hostname~"printer"`. Each
+//! condition is `<field> <op> <value>`, values with spaces are quoted:
+//!
+//! | field       | ops               | value                                                            |
+//! |-------------|-------------------|------------------------------------------------------------------|
+//! | `ip`        | `=` `!=`          | an address or CIDR block                                         |
+//! | `network`   | `=` `!=`          | a network's subnet address or CIDR block                         |
+//! | `state`     | `=` `!=`          | `active`/`leased`, `offered`, `declined`, `reserved`, `probated` |
+//! | `client_id` | `=` `!=` `~` `!~` | hex, `:` & `-` separators are ignored                            |
+//! | `hostname`  | `=` `!=` `~` `!~` | case is ignored                                                  |
+//!
+//! `~` matches part of the value, `probated` is `declined` or `reserved`.
+//! A backend reads the parsed conditions, sort & page from [`LeaseQuery`]
+//! to return only the requested page of leases, the sqlite backend turns
+//! them into SQL.
+use std::{fmt, net::Ipv4Addr, str::FromStr};
+
+use ipnet::Ipv4Net;
+use thiserror::Error;
+
+/// a query string that couldn't be parsed
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid lease query: {0}")]
+pub struct QueryError(String);
+
+/// lease field results can be ordered by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    #[default]
+    Ip,
+    Network,
+    ExpiresAt,
+    Hostname,
+}
+
+impl FromStr for SortKey {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "ip" => SortKey::Ip,
+            "network" => SortKey::Network,
+            "expires_at" => SortKey::ExpiresAt,
+            "hostname" => SortKey::Hostname,
+            _ => return Err(QueryError(format!("can't sort by {s:?}"))),
+        })
+    }
+}
+
+/// a filtered, sorted page of un-expired leases
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseQuery {
+    filters: Vec<Filter>,
+    sort: SortKey,
+    desc: bool,
+    limit: u32,
+    offset: u32,
+}
+
+impl LeaseQuery {
+    /// parse `query`, an empty query matches every lease. Returns the first
+    /// 500 results ordered by ip unless [`LeaseQuery::sort`] &
+    /// [`LeaseQuery::page`] are used
+    pub fn new(query: &str) -> Result<Self, QueryError> {
+        let tokens = tokenize(query)?;
+        let filters = if tokens.is_empty() {
+            Vec::new()
+        } else {
+            tokens
+                .split(|tok| matches!(tok, Token::Word(w) if w.eq_ignore_ascii_case("and")))
+                .map(Filter::parse)
+                .collect::<Result<_, _>>()?
+        };
+        Ok(Self {
+            filters,
+            sort: SortKey::default(),
+            desc: false,
+            limit: 500,
+            offset: 0,
+        })
+    }
+
+    /// order results by `key`, highest first if `desc`. Ties are ordered by ip
+    pub fn sort(mut self, key: SortKey, desc: bool) -> Self {
+        self.sort = key;
+        self.desc = desc;
+        self
+    }
+
+    /// return at most `limit` results, skipping the first `offset`
+    pub fn page(mut self, limit: u32, offset: u32) -> Self {
+        self.limit = limit;
+        self.offset = offset;
+        self
+    }
+
+    /// the conditions a lease must match, all of them
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    /// the field results are ordered by, ties are ordered by ip
+    pub fn sort_key(&self) -> SortKey {
+        self.sort
+    }
+
+    /// are results ordered highest first?
+    pub fn descending(&self) -> bool {
+        self.desc
+    }
+
+    /// most results returned
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// results skipped before the first returned
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+}
+
+/// one condition of a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    negate: bool,
+    cond: Cond,
+}
+
+impl Filter {
+    /// does a lease match when `cond` doesn't? A lease without the field
+    /// (no client id or hostname) matches neither way
+    pub fn negated(&self) -> bool {
+        self.negate
+    }
+
+    pub fn cond(&self) -> &Cond {
+        &self.cond
+    }
+}
+
+/// what a [`Filter`] compares
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cond {
+    /// `col` is between `start` & `end`, inclusive
+    Block {
+        col: IpCol,
+        start: Ipv4Addr,
+        end: Ipv4Addr,
+    },
+    State(StateFilter),
+    /// lowercase hex without separators, equal to or contained in the
+    /// client id's hex
+    ClientId {
+        hex: String,
+        contains: bool,
+    },
+    /// equal to or contained in the hostname, case is ignored
+    Hostname {
+        name: String,
+        contains: bool,
+    },
+}
+
+/// address field a [`Cond::Block`] applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpCol {
+    Ip,
+    Network,
+}
+
+/// the [`LeaseState`](crate::LeaseState)s a [`Cond::State`] matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateFilter {
+    /// active
+    Leased,
+    Offered,
+    /// declined or reserved
+    Probated,
+    Declined,
+    Reserved,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Contains,
+    NotContains,
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Contains => "~",
+            Op::NotContains => "!~",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Op(Op),
+}
+
+impl Filter {
+    fn parse(term: &[Token]) -> Result<Self, QueryError> {
+        let (field, op, value) = match term {
+            [Token::Word(field), Token::Op(op), Token::Word(value) | Token::Quoted(value)] => {
+                (field.as_str(), *op, value.as_str())
+            }
+            [] => return Err(QueryError("empty condition around AND".to_owned())),
+            _ => {
+                return Err(QueryError(
+                    "expected a condition like `field=value`".to_owned(),
+                ))
+            }
+        };
+        let negate = matches!(op, Op::Ne | Op::NotContains);
+        let contains = matches!(op, Op::Contains | Op::NotContains);
+        let no_contains = || {
+            if contains {
+                Err(QueryError(format!("{field} doesn't support {op}")))
+            } else {
+                Ok(())
+            }
+        };
+        let cond = match field {
+            "ip" | "network" => {
+                no_contains()?;
+                let col = if field == "ip" {
+                    IpCol::Ip
+                } else {
+                    IpCol::Network
+                };
+                let (start, end) = block(value)?;
+                Cond::Block { col, start, end }
+            }
+            "state" => {
+                no_contains()?;
+                Cond::State(match value {
+                    "active" | "leased" => StateFilter::Leased,
+                    "offered" => StateFilter::Offered,
+                    "probated" => StateFilter::Probated,
+                    "declined" => StateFilter::Declined,
+                    "reserved" => StateFilter::Reserved,
+                    _ => return Err(QueryError(format!("unknown state {value:?}"))),
+                })
+            }
+            "client_id" => {
+                let hex = value.replace([':', '-'], "").to_lowercase();
+                if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(QueryError(format!("client_id {value:?} isn't hex")));
+                }
+                Cond::ClientId { hex, contains }
+            }
+            "hostname" => Cond::Hostname {
+                name: value.to_owned(),
+                contains,
+            },
+            _ => return Err(QueryError(format!("unknown field {field:?}"))),
+        };
+        Ok(Filter { negate, cond })
+    }
+}
+
+/// first & last address of an address or CIDR block
+fn block(value: &str) -> Result<(Ipv4Addr, Ipv4Addr), QueryError> {
+    if let Ok(ip) = value.parse::<Ipv4Addr>() {
+        return Ok((ip, ip));
+    }
+    let net = value
+        .parse::<Ipv4Net>()
+        .map_err(|_| QueryError(format!("{value:?} isn't an IPv4 address or CIDR block")))?;
+    Ok((net.network(), net.broadcast()))
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => s.extend(chars.next()),
+                        Some(c) => s.push(c),
+                        None => return Err(QueryError("unterminated quote".to_owned())),
+                    }
+                }
+                tokens.push(Token::Quoted(s));
+            }
+            '=' | '~' => {
+                chars.next();
+                tokens.push(Token::Op(if c == '=' { Op::Eq } else { Op::Contains }));
+            }
+            '!' => {
+                chars.next();
+                tokens.push(Token::Op(match chars.next() {
+                    Some('=') => Op::Ne,
+                    Some('~') => Op::NotContains,
+                    _ => return Err(QueryError("expected != or !~".to_owned())),
+                }));
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '"' | '=' | '~' | '!') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(s));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+
+    use super::*;
+    use crate::{sqlite::SqliteDb, LeaseState, Storage};
+
+    #[test]
+    fn test_parse() {
+        let query =
+            LeaseQuery::new(r#"state=leased AND network=10.1.0.0/16 and hostname~"my printer""#)
+                .unwrap();
+        assert_eq!(
+            query.filters,
+            [
+                Filter {
+                    negate: false,
+                    cond: Cond::State(StateFilter::Leased),
+                },
+                Filter {
+                    negate: false,
+                    cond: Cond::Block {
+                        col: IpCol::Network,
+                        start: Ipv4Addr::new(10, 1, 0, 0),
+                        end: Ipv4Addr::new(10, 1, 255, 255),
+                    },
+                },
+                Filter {
+                    negate: false,
+                    cond: Cond::Hostname {
+                        name: "my printer".to_owned(),
+                        contains: true,
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            LeaseQuery::new("client_id!~AA:bb-01").unwrap().filters,
+            [Filter {
+                negate: true,
+                cond: Cond::ClientId {
+                    hex: "aabb01".to_owned(),
+                    contains: true,
+                },
+            }]
+        );
+        assert!(LeaseQuery::new("").unwrap().filters.is_empty());
+        assert_eq!(
+            (
+                query.sort_key(),
+                query.descending(),
+                query.limit(),
+                query.offset()
+            ),
+            (SortKey::Ip, false, 500, 0)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for query in [
+            "ip~10.0.0.1",
+            "ip=10.0.0",
+            "state=gone",
+            "client_id=xyz",
+            "colour=red",
+            "hostname=a AND",
+            "hostname",
+            r#"hostname="a"#,
+            "hostname!a",
+        ] {
+            assert!(LeaseQuery::new(query).is_err(), "{query} parsed");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_null() -> Result<(), sqlx::Error> {
+        let store = SqliteDb::memory().await?;
+        let expires = store.now() + std::time::Duration::from_secs(3600);
+        let network = IpAddr::from([10, 0, 0, 0]);
+        let printer = IpAddr::from([10, 0, 0, 10]);
+        let nameless = IpAddr::from([10, 0, 0, 11]);
+        store
+            .insert(printer, network, &[1], expires, LeaseState::Active)
+            .await?;
+        store
+            .insert(nameless, network, &[2], expires, LeaseState::Active)
+            .await?;
+        store
+            .set_hostname("printer", printer, &[1], expires)
+            .await?;
+
+        let search = |query: &str| {
+            let query = LeaseQuery::new(query).unwrap();
+            let store = &store;
+            async move {
+                Ok::<_, sqlx::Error>(
+                    store
+                        .search_leases(&query)
+                        .await?
+                        .into_iter()
+                        .map(|lease| lease.ip)
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        assert_eq!(search("hostname=printer").await?, [printer]);
+        assert_eq!(search("hostname~print").await?, [printer]);
+        // a lease without a hostname matches neither the filter nor its negation
+        assert!(search("hostname!=printer").await?.is_empty());
+        assert!(search("hostname!~print").await?.is_empty());
+        assert_eq!(search("hostname!=scanner").await?, [printer]);
+        assert_eq!(search("client_id!=01").await?, [nameless]);
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+//! crash recovery
+//!
+//! [`IpManager::recover`] runs before dora starts serving. It checks the
+//! lease database for corruption & deletes per-lease data (FORCERENEW nonces,
+//! relay agent information) left behind for addresses that changed hands.
+//!
+//! Expired leases are published from a checkpoint stored in the database, so
+//! [`IpManager::watch_expiry`] picks up leases that expired while dora was
+//! down instead of starting over from the time it restarted.
+//!
+//! With [`Durability::Wal`] commits aren't synced as they're made,
+//! [`IpManager::watch_checkpoint`] syncs them on an interval. That interval
+//! bounds how many commits can be lost on power loss.
+//!
+//! With [`Durability::WriteBehind`] leases are committed to an in-memory copy
+//! of the database & replies never wait on the disk. The copy is written to the
+//! file on the same interval, and should be once more on a clean shutdown. A
+//! crash loses every lease made since the last write: clients keep addresses
+//! dora no longer knows about & may offer to someone else, ping checks before
+//! offering catch most of these.
+//!
+//! [`IpManager::watch_expiry`]: crate::IpManager::watch_expiry
+//! [`Durability::Wal`]: crate::sqlite::Durability::Wal
+//! [`Durability::WriteBehind`]: crate::sqlite::Durability::WriteBehind
+use std::time::{Duration, SystemTime};
+
+use thiserror::Error;
+use tracing::{debug, error, info};
+
+use crate::{IpManager, Storage};
+
+/// what [`IpManager::recover`] found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recovered {
+    /// per-lease rows deleted because their address changed hands
+    pub orphans: u64,
+    /// time expired leases were last published up to
+    pub expiry_checkpoint: Option<SystemTime>,
+}
+
+#[derive(Error, Debug)]
+pub enum RecoveryError<E> {
+    #[error("lease database failed its integrity check: {0:?}")]
+    Corrupt(Vec<String>),
+    #[error("database error")]
+    DbError(#[from] E),
+}
+
+impl<T> IpManager<T>
+where
+    T: Storage,
+{
+    /// check the lease database & reconcile it after an unclean shutdown
+    pub async fn recover(&self) -> Result<Recovered, RecoveryError<T::Error>> {
+        let problems = self.store.integrity_check().await?;
+        if !problems.is_empty() {
+            return Err(RecoveryError::Corrupt(problems));
+        }
+        let recovered = Recovered {
+            orphans: self.store.delete_orphans().await?,
+            expiry_checkpoint: self.store.expiry_checkpoint().await?,
+        };
+        if recovered.orphans > 0 {
+            info!(orphans = recovered.orphans, "deleted orphaned lease data");
+        }
+        debug!(?recovered, "lease database recovered");
+        Ok(recovered)
+    }
+
+    /// sync committed changes to the database file every `interval`. Never returns
+    ///
+    /// with [`Durability::WriteBehind`] this is what writes leases to the file,
+    /// so `interval` is how many seconds of leases a crash can lose
+    ///
+    /// [`Durability::WriteBehind`]: crate::sqlite::Durability::WriteBehind
+    pub async fn watch_checkpoint(self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.store.checkpoint().await {
+                error!(?err, "failed to checkpoint lease database");
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! addresses in use outside of dora
+//!
+//! When the lease database is lost, clients keep using their addresses until
+//! their leases run out. [`IpManager::scan_in_use`] pings every address in the
+//! configured ranges that has no un-expired entry in the database, and puts the
+//! ones that reply on probation so they aren't handed out again. Pings are rate
+//! limited so the sweep doesn't flood the network.
+//!
+//! Addresses assigned statically are put on probation with
+//! [`IpManager::probate_static`], which is repeated before the probation ends
+//! for as long as the address is assigned.
+use std::{
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use config::DhcpConfig;
+use tracing::{debug, error, info, warn};
+
+use crate::{IpManager, LeaseEventKind, LeaseState, State, Storage};
+
+impl<T> IpManager<T>
+where
+    T: Storage,
+{
+    /// put `ip`, assigned statically outside of dora, on probation until
+    /// `until`. A probation ending sooner is extended, an address leased or
+    /// offered to a client is left alone. Returns `true` if the address wasn't
+    /// on probation already
+    pub async fn probate_static(
+        &self,
+        ip: IpAddr,
+        network: IpAddr,
+        until: SystemTime,
+    ) -> Result<bool, T::Error> {
+        let now = self.now();
+        match self.store.get(ip).await? {
+            Some(State::Probated(info)) if info.expires_at > now => {
+                if info.expires_at < until {
+                    self.store
+                        .update_ip(ip, LeaseState::Reserved, None, until)
+                        .await?;
+                }
+                Ok(false)
+            }
+            Some(state) if state.as_ref().expires_at > now => {
+                warn!(
+                    ?ip,
+                    ?state,
+                    "statically assigned address is in use by a dhcp client"
+                );
+                Ok(false)
+            }
+            Some(_) => {
+                self.store
+                    .update_ip(ip, LeaseState::Reserved, None, until)
+                    .await?;
+                debug!(?ip, "statically assigned address, marking IP on probation");
+                self.publish(LeaseEventKind::Probated, ip, None, until);
+                Ok(true)
+            }
+            None => {
+                self.store
+                    .insert(ip, network, &[], until, LeaseState::Reserved)
+                    .await?;
+                debug!(?ip, "statically assigned address, marking IP on probation");
+                self.publish(LeaseEventKind::Probated, ip, None, until);
+                Ok(true)
+            }
+        }
+    }
+}
+
+impl<T> IpManager<T>
+where
+    T: Storage + Clone,
+{
+    /// ping the addresses of every v4 range in `cfg` that aren't in the lease
+    /// database, at most `rate` per second, and probate the ones in use.
+    /// Returns the number of addresses probated
+    pub async fn scan_in_use(self, cfg: Arc<DhcpConfig>, rate: u32) -> usize {
+        let mut ticker = tokio::time::interval(Duration::from_secs(1) / rate.max(1));
+        let mut pings = Vec::new();
+        for network in cfg.v4().networks().values() {
+            let (subnet, probation) = (IpAddr::from(network.subnet()), network.probation_period());
+            for range in network.ranges() {
+                let ping = range.ping();
+                for ip in range.iter() {
+                    let ip = IpAddr::V4(ip);
+                    if self.is_known(ip).await {
+                        continue;
+                    }
+                    ticker.tick().await;
+                    let mgr = self.clone();
+                    pings.push(tokio::spawn(async move {
+                        match mgr.addr_in_use(ip, ping.timeout(), ping.retries()).await {
+                            Ok(_) => mgr.probate_in_use(ip, subnet, probation).await,
+                            Err(_) => false,
+                        }
+                    }));
+                }
+            }
+        }
+        let scanned = pings.len();
+        let mut probated = 0;
+        for ping in pings {
+            if let Ok(true) = ping.await {
+                probated += 1;
+            }
+        }
+        info!(scanned, probated, "finished scanning for addresses in use");
+        probated
+    }
+
+    /// does `ip` have an un-expired entry in the lease database? Lookup
+    /// errors count as known, so the address isn't touched
+    async fn is_known(&self, ip: IpAddr) -> bool {
+        match self.store.get(ip).await {
+            Ok(Some(state)) => state.as_ref().expires_at > self.now(),
+            Ok(None) => false,
+            Err(err) => {
+                error!(?err, ?ip, "failed to look up address");
+                true
+            }
+        }
+    }
+
+    async fn probate_in_use(&self, ip: IpAddr, network: IpAddr, probation: Duration) -> bool {
+        // the address may have been given out while the ping was in flight
+        if self.is_known(ip).await {
+            return false;
+        }
+        let expires_at = self.now() + probation;
+        let res = match self.store.get(ip).await {
+            Ok(Some(_)) => self
+                .store
+                .update_ip(ip, LeaseState::Declined, None, expires_at)
+                .await
+                .map(|_| ()),
+            Ok(None) => {
+                self.store
+                    .insert(ip, network, &[], expires_at, LeaseState::Declined)
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        match res {
+            Ok(()) => {
+                debug!(?ip, "address in use, marking IP on probation");
+                self.publish(LeaseEventKind::Probated, ip, None, expires_at);
+                true
+            }
+            Err(err) => {
+                error!(?err, ?ip, "failed to probate address in use");
+                false
+            }
+        }
+    }
+}
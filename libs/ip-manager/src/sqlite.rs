@@ -1,56 +1,235 @@
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashMap, HashSet},
     net::{IpAddr, Ipv4Addr},
     ops::RangeInclusive,
+    path::PathBuf,
     str::FromStr,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
 use async_trait::async_trait;
+use config::v4::{Exclusions, HostnamePolicy, NetRangeIter};
 use sqlx::{
-    sqlite::{SqliteConnectOptions, SqlitePool},
-    ConnectOptions, Sqlite,
+    sqlite::{
+        SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions, SqliteSynchronous,
+    },
+    ConnectOptions, Row, Sqlite, SqliteConnection,
 };
+use tokio::sync::Mutex;
 use tracing::debug;
 
-use crate::{ClientInfo, IpState, State, Storage};
+use crate::{
+    clock::{Clock, SystemClock},
+    ActiveLease, ClientInfo, ConfigVersion, DailyPoolStats, ForceRenewNonce, HostnameConflict,
+    HostnameEntry, IpState, LeaseFingerprint, LeaseQuery, LeaseState, LeaseTransition, Maintenance,
+    NetworkCount, PoolSample, RangeCounts, State, StickyLease, Storage,
+};
+
+mod batch;
+mod search;
+
+pub use batch::BatchLimits;
+
+/// un-expired entries with their hostname, relay info & fingerprint, `?1` is
+/// the current time. Filters are appended for a search, `active_leases` checks
+/// the same query at compile time
+const ACTIVE_LEASES: &str = r#"SELECT l.ip, l.client_id, l.network, l.state, l.expires_at,
+        h.hostname, r.data AS relay_info, f.fingerprint, f.vendor_class, f.category, f.device
+    FROM leases l LEFT JOIN hostnames h
+        ON h.ip = l.ip AND h.client_id = l.client_id AND h.expires_at > ?1
+    LEFT JOIN relay_info r ON r.ip = l.ip AND r.client_id = l.client_id
+    LEFT JOIN fingerprints f ON f.ip = l.ip AND f.client_id = l.client_id
+    WHERE l.expires_at > ?1"#;
+
+/// server_state key of the expiry checkpoint
+const EXPIRY_CHECKPOINT: &str = "expiry_checkpoint";
+
+/// how commits to the lease database are made durable
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// commits are written to the WAL without waiting for it to sync, it is
+    /// synced by checkpoints (see [`IpManager::watch_checkpoint`]). A power loss
+    /// can lose commits made since the last checkpoint
+    ///
+    /// [`IpManager::watch_checkpoint`]: crate::IpManager::watch_checkpoint
+    #[default]
+    Wal,
+    /// every commit is synced before it returns
+    Full,
+    /// commits are made to an in-memory copy of the database, checkpoints write
+    /// the copy to the file. Nothing waits on the disk, but a crash or power
+    /// loss loses every commit made since the last checkpoint
+    WriteBehind,
+}
 
 #[derive(Debug)]
 pub struct SqliteDb {
     inner: SqlitePool,
+    /// the file an in-memory copy is written behind to
+    behind: Option<Arc<Behind>>,
+    /// lease writes are group committed when set
+    batch: Option<batch::Batcher>,
+    /// the time expiry is measured against
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Debug)]
+struct Behind {
+    path: PathBuf,
+    /// one checkpoint writes the file at a time
+    lock: Mutex<()>,
 }
 
 impl Clone for SqliteDb {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            behind: self.behind.clone(),
+            batch: self.batch.clone(),
+            clock: self.clock.clone(),
         }
     }
 }
 
 impl SqliteDb {
+    /// an in-memory database, gone once the last clone is dropped
+    pub async fn memory() -> Result<Self, sqlx::Error> {
+        Self::new("sqlite::memory:").await
+    }
+
     pub async fn new(uri: impl AsRef<str>) -> Result<Self, sqlx::Error> {
-        // in memory sqlite will clear the db after all conns close,
-        // to keep it alive for testing-- we can make sure conns _never_ close.
-        //
-        // let inner = if uri.as_ref().contains("memory") {
-        //     sqlx::sqlite::SqlitePoolOptions::new()
-        //         .idle_timeout(None)
-        //         .max_lifetime(None)
-        //         .connect(uri.as_ref())
-        //         .await?
-        // } else {
-        let mut opts = SqliteConnectOptions::from_str(uri.as_ref())?
-            .synchronous(sqlx::sqlite::SqliteSynchronous::Normal)
-            .create_if_missing(true);
-        // make sqlite log queries at trace level so we don't get a bloated log on `info`
-        opts.log_statements(tracing::log::LevelFilter::Trace);
-
-        let inner = SqlitePool::connect_with(opts).await?;
-        // };
-        sqlx::migrate!("../../migrations").run(&inner).await?;
-        Ok(Self { inner })
+        Self::with_durability(uri, Durability::default()).await
+    }
+
+    /// open the database at `uri`, committing with `durability`
+    pub async fn with_durability(
+        uri: impl AsRef<str>,
+        durability: Durability,
+    ) -> Result<Self, sqlx::Error> {
+        let synchronous = match durability {
+            Durability::Wal | Durability::WriteBehind => SqliteSynchronous::Normal,
+            Durability::Full => SqliteSynchronous::Full,
+        };
+        let inner = connect(uri.as_ref(), synchronous).await?;
+        let db = Self {
+            inner,
+            behind: None,
+            batch: None,
+            clock: Arc::new(SystemClock),
+        };
+        match durability {
+            Durability::WriteBehind if !uri.as_ref().contains(":memory:") => {
+                db.write_behind(uri.as_ref()).await
+            }
+            _ => Ok(db),
+        }
+    }
+
+    /// load the file opened as `self` into an in-memory copy that is written
+    /// back to the file by [`Storage::checkpoint`]
+    async fn write_behind(self, uri: &str) -> Result<Self, sqlx::Error> {
+        let path = db_path(uri).ok_or_else(|| {
+            sqlx::Error::Configuration(format!("no database file in {uri:?}").into())
+        })?;
+        // fold the WAL into the file so attaching it sees every commit
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.inner)
+            .await?;
+        self.inner.close().await;
+
+        // not `Self::memory`, that would make these `async fn`s recursive
+        let inner = connect("sqlite::memory:", SqliteSynchronous::Normal).await?;
+        sqlx::query("ATTACH DATABASE ?1 AS disk")
+            .bind(path.to_string_lossy().into_owned())
+            .execute(&inner)
+            .await?;
+        // both were migrated by this build, so their tables have the same columns
+        let tables = sqlx::query(
+            r#"SELECT name FROM disk.sqlite_master
+            WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations'"#,
+        )
+        .fetch_all(&inner)
+        .await?;
+        for table in tables {
+            let table = table.try_get::<String, _>("name")?;
+            sqlx::query(&format!(
+                "INSERT INTO main.{table} SELECT * FROM disk.{table}"
+            ))
+            .execute(&inner)
+            .await?;
+        }
+        sqlx::query("DETACH DATABASE disk").execute(&inner).await?;
+        debug!(?path, "lease database loaded for write-behind");
+        Ok(Self {
+            inner,
+            behind: Some(Arc::new(Behind {
+                path,
+                lock: Mutex::new(()),
+            })),
+            batch: None,
+            clock: self.clock,
+        })
+    }
+
+    /// commit lease writes made while clients are being answered in batches
+    /// within `limits`, so they share syncs to disk. Every write still only
+    /// returns once it's committed
+    pub fn with_batching(mut self, limits: BatchLimits) -> Self {
+        self.batch = Some(batch::Batcher::spawn(self.inner.clone(), limits));
+        self
+    }
+
+    /// measure expiry against `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+/// open & migrate the database at `uri`
+async fn connect(uri: &str, synchronous: SqliteSynchronous) -> Result<SqlitePool, sqlx::Error> {
+    let mut opts = SqliteConnectOptions::from_str(uri)?
+        .journal_mode(SqliteJournalMode::Wal)
+        .synchronous(synchronous)
+        .create_if_missing(true);
+    // make sqlite log queries at trace level so we don't get a bloated log on `info`
+    opts.log_statements(tracing::log::LevelFilter::Trace);
+
+    // in memory sqlite gives every connection its own db & clears it when
+    // the connection closes, so use exactly one connection that never closes
+    let inner = if uri.contains(":memory:") {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(opts.journal_mode(SqliteJournalMode::Memory))
+            .await?
+    } else {
+        SqlitePool::connect_with(opts).await?
+    };
+    sqlx::migrate!("../../migrations").run(&inner).await?;
+    Ok(inner)
+}
+
+/// the file a sqlite uri points to, `None` for in-memory databases
+fn db_path(uri: &str) -> Option<PathBuf> {
+    let path = uri
+        .trim_start_matches("sqlite://")
+        .trim_start_matches("sqlite:");
+    let path = path.split('?').next()?;
+    if path.is_empty() || path.contains(":memory:") {
+        return None;
     }
+    Some(PathBuf::from(path))
+}
+
+/// the value of an integer pragma, ex. `page_count`
+async fn pragma(conn: &mut SqliteConnection, name: &str) -> Result<u64, sqlx::Error> {
+    let row = sqlx::query(&format!("PRAGMA {name}"))
+        .fetch_one(conn)
+        .await?;
+    Ok(row.try_get::<i64, _>(0)? as u64)
 }
 
 #[async_trait]
@@ -58,8 +237,12 @@ impl Storage for SqliteDb {
     // TODO: consider alternate error type
     type Error = sqlx::Error;
 
+    fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
     /// find the next expired IP in the range, or where client_id matches,
-    /// and update it to leased = false with the new client_id & expiry
+    /// and offer it with the new client_id & expiry
     async fn next_expired(
         &self,
         range: RangeInclusive<IpAddr>,
@@ -71,18 +254,20 @@ impl Storage for SqliteDb {
             (IpAddr::V4(start), IpAddr::V4(end), IpAddr::V4(_network)) => {
                 let start_ip = u32::from(start) as i64;
                 let end_ip = u32::from(end) as i64;
-                let now = util::systime_epoch(SystemTime::now());
+                let now = util::systime_epoch(self.now());
 
-                Ok(util::update_next_expired(
-                    &self.inner,
+                let mut trans = self.inner.begin().await?;
+                let ip = util::update_next_expired(
+                    &mut trans,
                     now,
                     id,
                     start_ip,
                     end_ip,
                     util::systime_epoch(expires_at),
-                    false,
                 )
-                .await?)
+                .await?;
+                trans.commit().await?;
+                Ok(ip)
             }
             _ => {
                 panic!("ipv6 not yet implemented");
@@ -95,7 +280,7 @@ impl Storage for SqliteDb {
         &self,
         range: RangeInclusive<IpAddr>,
         // TODO should not mix Ip and Ipv4 in args
-        exclusions: &HashSet<Ipv4Addr>,
+        exclusions: &Exclusions,
         network: IpAddr,
         id: &[u8],
         expires_at: SystemTime,
@@ -113,6 +298,22 @@ impl Storage for SqliteDb {
                 debug!("no expired entries, finding start of range");
                 // TRANSACTION START
                 let mut conn = self.inner.begin().await?;
+                // addresses held as sticky leases for other clients are skipped too
+                let held_ips = util::sticky_held(
+                    &mut conn,
+                    &id,
+                    start_ip,
+                    end_ip,
+                    util::systime_epoch(self.now()),
+                )
+                .await?;
+                let held;
+                let exclusions = if held_ips.is_empty() {
+                    exclusions
+                } else {
+                    held = exclusions.with(held_ips);
+                    &held
+                };
                 // we only use this IP to find what the next available should be
                 let ip = match util::max_in_range(&mut conn, start_ip, end_ip).await? {
                     Some(State::Leased(cur) | State::Reserved(cur) | State::Probated(cur)) => {
@@ -124,7 +325,9 @@ impl Storage for SqliteDb {
                     None => {
                         debug!(start = ?range.start(), "using start of range");
                         // no IPs in range, so it must be empty
-                        Some(*range.start())
+                        NetRangeIter::new(start..=end, exclusions)
+                            .next()
+                            .map(IpAddr::from)
                     }
                 };
                 if let Some(IpAddr::V4(v4_ip)) = ip {
@@ -134,7 +337,8 @@ impl Storage for SqliteDb {
                         u32::from(network) as i64,
                         &id,
                         util::systime_epoch(expires_at),
-                        None,
+                        util::systime_epoch(self.now()),
+                        LeaseState::Offered,
                     )
                     .await?;
                     // TRANSACTION COMMIT
@@ -156,23 +360,25 @@ impl Storage for SqliteDb {
     async fn update_expired(
         &self,
         ip: IpAddr,
-        state: IpState,
+        state: LeaseState,
         id: &[u8],
         expires_at: SystemTime,
     ) -> Result<bool, Self::Error> {
-        let (lease, probation) = state.into();
         match ip {
-            IpAddr::V4(ip) => Ok(util::update_expired(
-                &self.inner,
-                u32::from(ip) as i64,
-                id,
-                util::systime_epoch(expires_at),
-                util::systime_epoch(SystemTime::now()),
-                lease,
-                probation,
-            )
-            .await?
-            .is_some()),
+            IpAddr::V4(ip) => {
+                let mut trans = self.inner.begin().await?;
+                let ip = util::update_expired(
+                    &mut trans,
+                    u32::from(ip) as i64,
+                    id,
+                    util::systime_epoch(expires_at),
+                    util::systime_epoch(self.now()),
+                    state,
+                )
+                .await?;
+                trans.commit().await?;
+                Ok(ip.is_some())
+            }
             _ => {
                 panic!("ipv6 not yet implemented");
             }
@@ -182,25 +388,38 @@ impl Storage for SqliteDb {
     async fn update_unexpired(
         &self,
         ip: IpAddr,
-        state: IpState,
+        state: LeaseState,
         id: &[u8],
         expires_at: SystemTime,
         new_id: Option<&[u8]>,
     ) -> Result<Option<IpAddr>, Self::Error> {
-        let (lease, probation) = state.into();
         match ip {
             IpAddr::V4(ip) => {
-                util::update_unexpired(
-                    &self.inner,
-                    u32::from(ip) as i64,
-                    id,
-                    util::systime_epoch(expires_at),
-                    util::systime_epoch(SystemTime::now()),
-                    lease,
-                    probation,
-                    new_id,
-                )
-                .await
+                let ip = u32::from(ip) as i64;
+                let expires_at = util::systime_epoch(expires_at);
+                let now = util::systime_epoch(self.now());
+                match &self.batch {
+                    Some(batch) => {
+                        let op = batch::Op::UpdateUnexpired {
+                            ip,
+                            id: id.to_vec(),
+                            expires_at,
+                            now,
+                            state,
+                            new_id: new_id.map(<[u8]>::to_vec),
+                        };
+                        batch.write(op).await
+                    }
+                    None => {
+                        let mut trans = self.inner.begin().await?;
+                        let ip = util::update_unexpired(
+                            &mut trans, ip, id, expires_at, now, state, new_id,
+                        )
+                        .await?;
+                        trans.commit().await?;
+                        Ok(ip)
+                    }
+                }
             }
             _ => {
                 panic!("ipv6 not yet implemented");
@@ -211,22 +430,24 @@ impl Storage for SqliteDb {
     async fn update_ip(
         &self,
         ip: IpAddr,
-        state: IpState,
+        state: LeaseState,
         id: Option<&[u8]>,
         expires_at: SystemTime,
     ) -> Result<Option<State>, Self::Error> {
-        let (lease, probation) = state.into();
         match ip {
             IpAddr::V4(ip) => {
-                util::update_ip(
-                    &self.inner,
+                let mut trans = self.inner.begin().await?;
+                let state = util::update_ip(
+                    &mut trans,
                     u32::from(ip) as i64,
                     id,
                     util::systime_epoch(expires_at),
-                    lease,
-                    probation,
+                    util::systime_epoch(self.now()),
+                    state,
                 )
-                .await
+                .await?;
+                trans.commit().await?;
+                Ok(state)
             }
             _ => {
                 panic!("ipv6 not yet implemented");
@@ -240,15 +461,32 @@ impl Storage for SqliteDb {
         network: IpAddr,
         id: &[u8],
         expires_at: SystemTime,
-        state: Option<IpState>,
+        state: LeaseState,
     ) -> Result<(), Self::Error> {
         match (ip, network) {
             (IpAddr::V4(ip), IpAddr::V4(network)) => {
                 let ip = u32::from(ip) as i64;
                 let network = u32::from(network) as i64;
                 let expires_at = util::systime_epoch(expires_at);
-                let state = state.map(|s| s.into());
-                util::insert(&self.inner, ip, network, id, expires_at, state).await
+                let now = util::systime_epoch(self.now());
+                match &self.batch {
+                    Some(batch) => {
+                        let op = batch::Op::Insert {
+                            ip,
+                            network,
+                            id: id.to_vec(),
+                            expires_at,
+                            now,
+                            state,
+                        };
+                        batch.write(op).await.map(|_| ())
+                    }
+                    None => {
+                        let mut trans = self.inner.begin().await?;
+                        util::insert(&mut trans, ip, network, id, expires_at, now, state).await?;
+                        trans.commit().await
+                    }
+                }
             }
             _ => {
                 panic!("ipv6 not yet implemented");
@@ -269,14 +507,34 @@ impl Storage for SqliteDb {
     }
 
     async fn get_id(&self, id: &[u8]) -> Result<Option<IpAddr>, Self::Error> {
-        util::find_by_id(&self.inner, id, util::systime_epoch(SystemTime::now())).await
+        util::find_by_id(&self.inner, id, util::systime_epoch(self.now())).await
+    }
+
+    async fn get_all_id(&self, id: &[u8]) -> Result<Vec<ClientInfo>, Self::Error> {
+        let now = util::systime_epoch(self.now());
+        Ok(sqlx::query_as!(
+            util::Entry,
+            r#"SELECT ip, client_id, expires_at, network, state
+            FROM leases WHERE client_id = ?1 AND expires_at > ?2 AND probation = 0"#,
+            id,
+            now
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .iter()
+        .map(util::client_info)
+        .collect())
     }
 
     async fn release_ip(&self, ip: IpAddr, id: &[u8]) -> Result<Option<ClientInfo>, Self::Error> {
         match ip {
             IpAddr::V4(ip) => {
                 let ip = u32::from(ip) as i64;
-                util::release_ip(&self.inner, ip, id).await
+                let mut trans = self.inner.begin().await?;
+                let info =
+                    util::release_ip(&mut trans, ip, id, util::systime_epoch(self.now())).await?;
+                trans.commit().await?;
+                Ok(info)
             }
             IpAddr::V6(_ip) => {
                 panic!("ipv6 not yet implemented");
@@ -304,300 +562,1382 @@ impl Storage for SqliteDb {
             &self.inner,
             lease,
             probation,
-            util::systime_epoch(SystemTime::now()),
+            util::systime_epoch(self.now()),
         )
         .await
     }
-}
 
-mod util {
-    use std::net::Ipv4Addr;
+    async fn get_hostname(&self, hostname: &str) -> Result<Option<HostnameEntry>, Self::Error> {
+        let now = util::systime_epoch(self.now());
+        Ok(sqlx::query!(
+            "SELECT hostname, client_id, ip, expires_at FROM hostnames WHERE hostname = ?1 AND expires_at > ?2",
+            hostname,
+            now
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(|row| HostnameEntry {
+            hostname: row.hostname,
+            id: row.client_id,
+            ip: IpAddr::V4(Ipv4Addr::from(row.ip as u32)),
+            expires_at: util::to_systime(row.expires_at),
+        }))
+    }
 
-    use config::v4::NetRangeIter;
+    async fn set_hostname(
+        &self,
+        hostname: &str,
+        ip: IpAddr,
+        id: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<(), Self::Error> {
+        let ip = match ip {
+            IpAddr::V4(ip) => u32::from(ip) as i64,
+            IpAddr::V6(_ip) => {
+                panic!("ipv6 not yet implemented");
+            }
+        };
+        let mut trans = self.inner.begin().await?;
+        // a client only holds a single hostname
+        sqlx::query!(
+            "DELETE FROM hostnames WHERE client_id = ?1 AND hostname != ?2",
+            id,
+            hostname
+        )
+        .execute(&mut trans)
+        .await?;
+        let expires_at = util::systime_epoch(expires_at);
+        sqlx::query!(
+            r#"INSERT INTO hostnames (hostname, client_id, ip, expires_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(hostname) DO UPDATE SET
+                client_id = excluded.client_id, ip = excluded.ip, expires_at = excluded.expires_at"#,
+            hostname,
+            id,
+            ip,
+            expires_at
+        )
+        .execute(&mut trans)
+        .await?;
+        trans.commit().await
+    }
 
-    use crate::State;
+    async fn insert_hostname_conflict(
+        &self,
+        conflict: &HostnameConflict,
+    ) -> Result<bool, Self::Error> {
+        let policy = util::policy_str(conflict.policy);
+        let created_at = util::systime_epoch(conflict.created_at);
+        let mut conn = self.inner.begin().await?;
+        let inserted = sqlx::query!(
+            r#"INSERT INTO hostname_conflicts
+                (hostname, client_id, existing_client_id, policy, assigned, created_at)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(hostname, client_id) DO NOTHING"#,
+            conflict.hostname,
+            conflict.id,
+            conflict.existing_id,
+            policy,
+            conflict.assigned,
+            created_at
+        )
+        .execute(&mut conn)
+        .await?
+        .rows_affected()
+            == 1;
+        if !inserted {
+            sqlx::query!(
+                r#"UPDATE hostname_conflicts
+                SET existing_client_id = ?3, policy = ?4, assigned = ?5
+                WHERE hostname = ?1 AND client_id = ?2"#,
+                conflict.hostname,
+                conflict.id,
+                conflict.existing_id,
+                policy,
+                conflict.assigned
+            )
+            .execute(&mut conn)
+            .await?;
+        }
+        conn.commit().await?;
+        Ok(inserted)
+    }
 
-    use super::*;
-    pub fn systime_epoch(time: SystemTime) -> i64 {
-        // / get secs as i64 (for use in sqlite) from epoch to `time`
-        time.duration_since(SystemTime::UNIX_EPOCH)
-            .expect("failed to get system time")
-            .as_secs() as i64
+    async fn hostname_conflicts(&self, limit: u32) -> Result<Vec<HostnameConflict>, Self::Error> {
+        Ok(sqlx::query!(
+            r#"SELECT hostname, client_id, existing_client_id, policy, assigned, created_at
+            FROM hostname_conflicts ORDER BY id DESC LIMIT ?1"#,
+            limit
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(|row| HostnameConflict {
+            hostname: row.hostname,
+            id: row.client_id,
+            existing_id: row.existing_client_id,
+            policy: util::parse_policy(&row.policy),
+            assigned: row.assigned,
+            created_at: util::to_systime(row.created_at),
+        })
+        .collect())
     }
 
-    pub fn to_systime(time: i64) -> SystemTime {
-        SystemTime::UNIX_EPOCH + Duration::from_secs(time as u64)
+    async fn expired_between(
+        &self,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Result<Vec<ClientInfo>, Self::Error> {
+        let (start, end) = (util::systime_epoch(start), util::systime_epoch(end));
+        Ok(sqlx::query_as!(
+            util::Entry,
+            r#"SELECT ip, client_id, expires_at, network, state FROM leases
+            WHERE state IN ('active', 'expired') AND leased = 1
+                AND expires_at > ?1 AND expires_at <= ?2"#,
+            start,
+            end
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .iter()
+        .map(util::client_info)
+        .collect())
     }
 
-    pub async fn delete<'a, E>(conn: E, ip: i64) -> Result<(), sqlx::Error>
-    where
-        E: sqlx::Executor<'a, Database = Sqlite>,
-    {
-        sqlx::query!("DELETE FROM leases WHERE ip = ?1", ip)
-            .execute(conn)
+    async fn expired_offers_between(
+        &self,
+        start: SystemTime,
+        end: SystemTime,
+    ) -> Result<Vec<ClientInfo>, Self::Error> {
+        let (start, end) = (util::systime_epoch(start), util::systime_epoch(end));
+        Ok(sqlx::query_as!(
+            util::Entry,
+            r#"SELECT ip, client_id, expires_at, network, state FROM leases
+            WHERE state IN ('offered', 'expired') AND leased = 0 AND probation = 0
+                AND expires_at > ?1 AND expires_at <= ?2"#,
+            start,
+            end
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .iter()
+        .map(util::client_info)
+        .collect())
+    }
+
+    async fn expire_leases(&self, now: SystemTime) -> Result<u64, Self::Error> {
+        let now = util::systime_epoch(now);
+        let mut trans = self.inner.begin().await?;
+        // the entries past their expiry & the state they're moved to
+        let expired = sqlx::query!(
+            r#"SELECT ip, client_id, state, expires_at,
+                CASE WHEN state IN ('offered', 'active') THEN 'expired' ELSE 'reclaimed' END
+                    AS "to_state!: String"
+            FROM leases
+            WHERE state IN ('offered', 'active', 'declined', 'reserved') AND expires_at <= ?1"#,
+            now
+        )
+        .fetch_all(&mut trans)
+        .await?;
+        let count = expired.len() as u64;
+        for row in expired {
+            let to = util::parse_state(row.to_state)?;
+            let to_state = to.as_str();
+            sqlx::query!(
+                "UPDATE leases SET state = ?2 WHERE ip = ?1",
+                row.ip,
+                to_state
+            )
+            .execute(&mut trans)
             .await?;
-        Ok(())
+            util::log_transition(
+                &mut trans,
+                row.ip,
+                row.client_id.as_deref(),
+                Some(util::parse_state(row.state)?),
+                to,
+                row.expires_at,
+            )
+            .await?;
+        }
+        trans.commit().await?;
+        Ok(count)
     }
 
-    pub async fn release_ip(
-        conn: &SqlitePool,
-        ip: i64,
-        id: &[u8],
-    ) -> Result<Option<ClientInfo>, sqlx::Error> {
-        let mut trans = conn.begin().await?;
-        let cur = sqlx::query!(
-            "SELECT * FROM leases WHERE ip = ?1 AND client_id = ?2",
+    async fn lease_transitions(
+        &self,
+        ip: Option<IpAddr>,
+        limit: u32,
+    ) -> Result<Vec<LeaseTransition>, Self::Error> {
+        let ip = ip.map(util::ip_int);
+        sqlx::query!(
+            r#"SELECT id, ip, client_id, from_state, to_state, at
+            FROM lease_transitions WHERE ?1 IS NULL OR ip = ?1
+            ORDER BY id DESC LIMIT ?2"#,
             ip,
-            id
+            limit
         )
-        .fetch_optional(&mut trans)
+        .fetch_all(&self.inner)
         .await?
-        .map(|cur| ClientInfo {
-            ip: IpAddr::V4(Ipv4Addr::from(cur.ip as u32)),
-            id: cur.client_id.map(|v| v.to_vec()),
-            network: IpAddr::V4(Ipv4Addr::from(cur.network as u32)),
-            expires_at: to_systime(cur.expires_at),
-        });
-        util::delete(&mut trans, ip).await?;
+        .into_iter()
+        .map(|row| {
+            Ok(LeaseTransition {
+                id: row.id as u64,
+                ip: IpAddr::V4(Ipv4Addr::from(row.ip as u32)),
+                client_id: row.client_id,
+                from: row.from_state.map(util::parse_state).transpose()?,
+                to: util::parse_state(row.to_state)?,
+                at: util::to_systime(row.at),
+            })
+        })
+        .collect()
+    }
 
-        trans.commit().await?;
-        // instead of deleting:
-        // sqlx::query!(
-        //     "UPDATE leases SET leased = false WHERE ip = ?1 AND client_id = ?2",
-        //     ip,
-        //     id
-        // )
-        // .fetch_optional(conn)
-        // .await?;
-        Ok(cur)
-    }
-
-    /// Inserts ip/network/client_id/expires_at into db.
-    /// If state is Some, we will insert the leased/probation state too.
-    /// if None then we use the default column type
-    pub async fn insert<'a, E>(
-        conn: E,
-        ip: i64,
-        network: i64,
-        client_id: &[u8],
-        expires_at: i64,
-        state: Option<(bool, bool)>,
-    ) -> Result<(), sqlx::Error>
-    where
-        E: sqlx::Executor<'a, Database = Sqlite>,
-    {
-        match state {
-            Some((leased, probation)) => {
-                sqlx::query!(
-                    r#"INSERT INTO leases
-                    (ip, client_id, expires_at, network, leased, probation)
-                VALUES
-                    (?1, ?2, ?3, ?4, ?5, ?6)"#,
-                    ip,
-                    client_id,
-                    expires_at,
-                    network,
-                    leased,
-                    probation
-                )
-                .execute(conn)
-                .await?;
+    async fn delete_lease_transitions(&self, before: SystemTime) -> Result<u64, Self::Error> {
+        let before = util::systime_epoch(before);
+        Ok(
+            sqlx::query!("DELETE FROM lease_transitions WHERE at < ?1", before)
+                .execute(&self.inner)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn range_counts(
+        &self,
+        range: RangeInclusive<IpAddr>,
+    ) -> Result<RangeCounts, Self::Error> {
+        let (start, end) = match (*range.start(), *range.end()) {
+            (IpAddr::V4(start), IpAddr::V4(end)) => {
+                (u32::from(start) as i64, u32::from(end) as i64)
             }
-            None => {
-                sqlx::query!(
-                "INSERT INTO leases (ip, client_id, expires_at, network) VALUES (?1, ?2, ?3, ?4)",
-                ip,
-                client_id,
-                expires_at,
+            _ => panic!("ipv6 not yet implemented"),
+        };
+        let now = util::systime_epoch(self.now());
+        let row = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(leased = 1 AND probation = 0), 0) AS "leased!: i64",
+                COALESCE(SUM(leased = 0 AND probation = 0), 0) AS "reserved!: i64",
+                COALESCE(SUM(probation = 1), 0) AS "probated!: i64"
+            FROM leases WHERE ip BETWEEN ?1 AND ?2 AND expires_at > ?3"#,
+            start,
+            end,
+            now
+        )
+        .fetch_one(&self.inner)
+        .await?;
+        Ok(RangeCounts {
+            leased: row.leased as u64,
+            reserved: row.reserved as u64,
+            probated: row.probated as u64,
+        })
+    }
+
+    async fn insert_pool_samples(&self, samples: &[PoolSample]) -> Result<(), Self::Error> {
+        let mut trans = self.inner.begin().await?;
+        for sample in samples {
+            let network = util::ip_int(sample.network);
+            let range_start = util::ip_int(*sample.range.start());
+            let range_end = util::ip_int(*sample.range.end());
+            let total = sample.total as i64;
+            let leased = sample.counts.leased as i64;
+            let reserved = sample.counts.reserved as i64;
+            let probated = sample.counts.probated as i64;
+            let sampled_at = util::systime_epoch(sample.sampled_at);
+            sqlx::query!(
+                r#"INSERT INTO pool_stats
+                    (network, range_start, range_end, total, leased, reserved, probated, sampled_at)
+                VALUES
+                    (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"#,
                 network,
+                range_start,
+                range_end,
+                total,
+                leased,
+                reserved,
+                probated,
+                sampled_at
             )
-                .execute(conn)
-                .await?;
-            }
+            .execute(&mut trans)
+            .await?;
         }
-        Ok(())
+        trans.commit().await
     }
 
-    pub async fn find(pool: &SqlitePool, ip: i64) -> Result<Option<State>, sqlx::Error> {
-        Ok(sqlx::query!("SELECT * FROM leases WHERE ip = ?1", ip)
-            .fetch_optional(pool)
-            .await?
-            .map(|cur| {
-                let info = ClientInfo {
-                    ip: IpAddr::V4(Ipv4Addr::from(cur.ip as u32)),
-                    id: cur.client_id.map(|v| v.to_vec()),
-                    network: IpAddr::V4(Ipv4Addr::from(cur.network as u32)),
-                    expires_at: to_systime(cur.expires_at),
-                };
-                into_clientinfo(info, cur.leased, cur.probation)
-            }))
+    async fn pool_samples(&self, since: SystemTime) -> Result<Vec<PoolSample>, Self::Error> {
+        let since = util::systime_epoch(since);
+        let ip = |ip: i64| IpAddr::V4(Ipv4Addr::from(ip as u32));
+        Ok(sqlx::query!(
+            r#"SELECT network, range_start, range_end, total, leased, reserved, probated, sampled_at
+            FROM pool_stats WHERE sampled_at >= ?1 ORDER BY sampled_at, range_start"#,
+            since
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(|row| PoolSample {
+            network: ip(row.network),
+            range: ip(row.range_start)..=ip(row.range_end),
+            total: row.total as u64,
+            counts: RangeCounts {
+                leased: row.leased as u64,
+                reserved: row.reserved as u64,
+                probated: row.probated as u64,
+            },
+            sampled_at: util::to_systime(row.sampled_at),
+        })
+        .collect())
     }
 
-    /// return a count of all rows where leased & probation & un-expired
-    pub async fn count(
-        pool: &SqlitePool,
-        leased: bool,
-        probation: bool,
-        expires_at: i64,
-    ) -> Result<usize, sqlx::Error> {
-        Ok(sqlx::query_scalar!(
-            "SELECT COUNT(ip) as count_ip FROM leases WHERE leased = ?1 AND probation = ?2 AND expires_at > ?3",
-            leased,
-            probation,
-            expires_at
+    async fn rollup_pool_samples(&self, before: SystemTime) -> Result<u64, Self::Error> {
+        let before = util::systime_epoch(before);
+        let mut trans = self.inner.begin().await?;
+        // a day already rolled up (ex. the retention shrank) is merged into
+        // the existing row
+        sqlx::query!(
+            r#"INSERT INTO pool_stats_daily
+                (network, range_start, range_end, day, samples, total,
+                 leased_avg, leased_max, used_avg, used_max)
+            SELECT network, range_start, range_end, sampled_at - sampled_at % 86400 AS day,
+                COUNT(*), MAX(total), AVG(leased), MAX(leased),
+                AVG(leased + reserved + probated), MAX(leased + reserved + probated)
+            FROM pool_stats WHERE sampled_at < ?1
+            GROUP BY network, range_start, range_end, day
+            ON CONFLICT(network, range_start, range_end, day) DO UPDATE SET
+                samples = samples + excluded.samples,
+                total = MAX(total, excluded.total),
+                leased_avg = (leased_avg * samples + excluded.leased_avg * excluded.samples)
+                    / (samples + excluded.samples),
+                leased_max = MAX(leased_max, excluded.leased_max),
+                used_avg = (used_avg * samples + excluded.used_avg * excluded.samples)
+                    / (samples + excluded.samples),
+                used_max = MAX(used_max, excluded.used_max)"#,
+            before
         )
-        .fetch_one(pool)
-        .await? as usize)
+        .execute(&mut trans)
+        .await?;
+        let rolled_up = sqlx::query!("DELETE FROM pool_stats WHERE sampled_at < ?1", before)
+            .execute(&mut trans)
+            .await?
+            .rows_affected();
+        trans.commit().await?;
+        Ok(rolled_up)
     }
 
-    /// return the info for this client_id and if it's un-expired
-    pub async fn find_by_id(
-        pool: &SqlitePool,
-        id: &[u8],
-        now: i64,
-    ) -> Result<Option<IpAddr>, sqlx::Error> {
+    async fn pool_rollups(&self, since: SystemTime) -> Result<Vec<DailyPoolStats>, Self::Error> {
+        let since = util::systime_epoch(since);
+        let ip = |ip: i64| IpAddr::V4(Ipv4Addr::from(ip as u32));
         Ok(sqlx::query!(
-            "SELECT ip 
-            FROM 
-                leases 
-            WHERE 
-                client_id = ?1 AND expires_at > ?2 
-            LIMIT 1",
-            id,
-            now
+            r#"SELECT network, range_start, range_end, day, samples, total,
+                leased_avg AS "leased_avg: f64", leased_max, used_avg AS "used_avg: f64", used_max
+            FROM pool_stats_daily WHERE day >= ?1 ORDER BY day, range_start"#,
+            since
         )
-        .fetch_optional(pool)
+        .fetch_all(&self.inner)
         .await?
-        .map(|cur| IpAddr::V4(Ipv4Addr::from(cur.ip as u32))))
+        .into_iter()
+        .map(|row| DailyPoolStats {
+            network: ip(row.network),
+            range: ip(row.range_start)..=ip(row.range_end),
+            day: util::to_systime(row.day),
+            samples: row.samples as u64,
+            total: row.total as u64,
+            leased_avg: row.leased_avg,
+            leased_max: row.leased_max as u64,
+            used_avg: row.used_avg,
+            used_max: row.used_max as u64,
+        })
+        .collect())
     }
 
-    /// returns the first expired IP in a range, or where the id matches
-    /// expires_at can refer to IPs under probation
-    pub async fn update_next_expired<'a, E>(
-        conn: E,
-        // select
-        now: i64,
-        id: &[u8],
-        start_ip: i64,
-        end_ip: i64,
-        // update
-        expires_at: i64,
-        leased: bool,
-    ) -> Result<Option<IpAddr>, sqlx::Error>
-    where
-        E: sqlx::Executor<'a, Database = Sqlite>,
-    {
-        // leased = false -> we got a discover but not yet ACK'd
-        // leased = true -> we have ACK'd
-        Ok(sqlx::query!(
-            r#"
-            UPDATE leases
-            SET
-                client_id = ?4, leased = ?5, expires_at = ?6, probation = FALSE
-            WHERE ip in
-               (
-                   SELECT ip
-                    FROM leases
-                    WHERE
-                        ((expires_at < ?1) AND (ip >= ?2 AND ip <= ?3)) OR (client_id = ?4)
-                    ORDER BY ip LIMIT 1
-                )
-            RETURNING ip
-            "#,
-            now,
-            start_ip,
-            end_ip,
-            id,
-            leased,
-            expires_at,
+    async fn delete_pool_rollups(&self, before: SystemTime) -> Result<u64, Self::Error> {
+        let before = util::systime_epoch(before);
+        Ok(
+            sqlx::query!("DELETE FROM pool_stats_daily WHERE day < ?1", before)
+                .execute(&self.inner)
+                .await?
+                .rows_affected(),
         )
-        .fetch_optional(conn)
-        .await?
-        .map(|cur| IpAddr::V4(Ipv4Addr::from(cur.ip as u32))))
     }
 
-    /// updates an entry if the ip & id match and not expired
-    pub async fn update_unexpired<'a, E>(
-        conn: E,
+    async fn delete_hostname_conflicts(&self, before: SystemTime) -> Result<u64, Self::Error> {
+        let before = util::systime_epoch(before);
+        Ok(sqlx::query!(
+            "DELETE FROM hostname_conflicts WHERE created_at < ?1",
+            before
+        )
+        .execute(&self.inner)
+        .await?
+        .rows_affected())
+    }
+
+    async fn save_network_counts(&self, counts: &[NetworkCount]) -> Result<(), Self::Error> {
+        let mut trans = self.inner.begin().await?;
+        for count in counts {
+            let network = util::ip_int(count.network);
+            let total = count.count as i64;
+            sqlx::query!(
+                r#"INSERT INTO network_counts (network, msg_type, count) VALUES (?1, ?2, ?3)
+                ON CONFLICT(network, msg_type) DO UPDATE SET count = excluded.count"#,
+                network,
+                count.msg_type,
+                total
+            )
+            .execute(&mut trans)
+            .await?;
+        }
+        trans.commit().await
+    }
+
+    async fn network_counts(&self) -> Result<Vec<NetworkCount>, Self::Error> {
+        Ok(sqlx::query!(
+            "SELECT network, msg_type, count FROM network_counts ORDER BY network, msg_type"
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(|row| NetworkCount {
+            network: IpAddr::V4(Ipv4Addr::from(row.network as u32)),
+            msg_type: row.msg_type,
+            count: row.count as u64,
+        })
+        .collect())
+    }
+
+    async fn active_leases(&self) -> Result<Vec<ActiveLease>, Self::Error> {
+        let now = util::systime_epoch(self.now());
+        // ACTIVE_LEASES, the joined columns are NULL without a match. `+` keeps
+        // the hostnames join off idx_hostnames_client, sqlx can't describe a
+        // LEFT JOIN through that index
+        let leases = sqlx::query_as!(
+            util::LeaseRow,
+            r#"SELECT l.ip, l.client_id, l.network, l.state, l.expires_at,
+                h.hostname AS "hostname?", r.data AS "relay_info?",
+                f.fingerprint AS "fingerprint?", f.vendor_class AS "vendor_class?",
+                f.category AS "category?", f.device AS "device?"
+            FROM leases l LEFT JOIN hostnames h
+                ON h.ip = l.ip AND +h.client_id = l.client_id AND h.expires_at > ?1
+            LEFT JOIN relay_info r ON r.ip = l.ip AND r.client_id = l.client_id
+            LEFT JOIN fingerprints f ON f.ip = l.ip AND f.client_id = l.client_id
+            WHERE l.expires_at > ?1
+            ORDER BY l.ip"#,
+            now
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(util::active_lease)
+        .collect::<Result<_, _>>()?;
+        util::with_notes(&self.inner, leases).await
+    }
+
+    async fn search_leases(&self, query: &LeaseQuery) -> Result<Vec<ActiveLease>, Self::Error> {
+        let (filter, binds) = search::to_sql(query, 2);
+        let sql = format!("{ACTIVE_LEASES}{filter}");
+        let mut q = sqlx::query_as::<_, util::LeaseRow>(&sql).bind(util::systime_epoch(self.now()));
+        for bind in binds {
+            q = match bind {
+                search::Bind::Int(n) => q.bind(n),
+                search::Bind::Text(s) => q.bind(s),
+            };
+        }
+        let leases = q
+            .fetch_all(&self.inner)
+            .await?
+            .into_iter()
+            .map(util::active_lease)
+            .collect::<Result<_, _>>()?;
+        util::with_notes(&self.inner, leases).await
+    }
+
+    async fn set_forcerenew_nonce(&self, nonce: &ForceRenewNonce) -> Result<(), Self::Error> {
+        let ip = util::ip_int(nonce.ip);
+        let network = util::ip_int(nonce.network);
+        let server_id = u32::from(nonce.server_id) as i64;
+        let classes = nonce.classes.join(",");
+        sqlx::query!(
+            r#"INSERT OR REPLACE INTO forcerenew_nonces
+                (ip, client_id, chaddr, network, server_id, classes, nonce)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7)"#,
+            ip,
+            nonce.id,
+            nonce.chaddr,
+            network,
+            server_id,
+            classes,
+            nonce.nonce
+        )
+        .execute(&self.inner)
+        .await?;
+        Ok(())
+    }
+
+    async fn forcerenew_nonces(&self) -> Result<Vec<ForceRenewNonce>, Self::Error> {
+        let now = util::systime_epoch(self.now());
+        let ip = |ip: i64| Ipv4Addr::from(ip as u32);
+        Ok(sqlx::query!(
+            r#"SELECT f.ip, f.client_id, f.chaddr, f.network, f.server_id, f.classes, f.nonce
+            FROM forcerenew_nonces f JOIN leases l
+                ON l.ip = f.ip AND l.client_id = f.client_id
+            WHERE l.leased = 1 AND l.expires_at > ?1 ORDER BY f.ip"#,
+            now
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(|row| ForceRenewNonce {
+            ip: ip(row.ip).into(),
+            id: row.client_id,
+            chaddr: row.chaddr,
+            network: ip(row.network).into(),
+            server_id: ip(row.server_id),
+            classes: row
+                .classes
+                .split(',')
+                .filter(|class| !class.is_empty())
+                .map(|class| class.to_owned())
+                .collect(),
+            nonce: row.nonce,
+        })
+        .collect())
+    }
+
+    async fn renew_sticky(
+        &self,
+        ip: IpAddr,
+        network: IpAddr,
+        id: &[u8],
+        after_renewals: Option<u32>,
+        hold_until: SystemTime,
+    ) -> Result<Option<SystemTime>, Self::Error> {
+        let ip = util::ip_int(ip);
+        let network = util::ip_int(network);
+        let hold_until = util::systime_epoch(hold_until);
+        // column references in SET are the values before the update
+        let row = sqlx::query!(
+            r#"INSERT INTO sticky_leases (ip, client_id, network, renewals, expires_at)
+            VALUES (?1, ?2, ?3, 0, CASE WHEN ?4 = 0 THEN ?5 END)
+            ON CONFLICT(ip) DO UPDATE SET
+                renewals = CASE WHEN client_id = excluded.client_id THEN renewals + 1 ELSE 0 END,
+                expires_at = CASE
+                    WHEN client_id != excluded.client_id THEN excluded.expires_at
+                    WHEN pinned = 1 THEN expires_at
+                    WHEN expires_at IS NOT NULL OR renewals + 1 >= ?4 THEN ?5
+                END,
+                pinned = pinned AND client_id = excluded.client_id,
+                client_id = excluded.client_id,
+                network = excluded.network
+            RETURNING expires_at"#,
+            ip,
+            id,
+            network,
+            after_renewals,
+            hold_until
+        )
+        .fetch_one(&self.inner)
+        .await?;
+        Ok(row.expires_at.map(util::to_systime))
+    }
+
+    async fn set_sticky(
+        &self,
+        ip: IpAddr,
+        network: IpAddr,
+        id: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<(), Self::Error> {
+        let ip = util::ip_int(ip);
+        let network = util::ip_int(network);
+        let expires_at = util::systime_epoch(expires_at);
+        sqlx::query!(
+            r#"INSERT INTO sticky_leases (ip, client_id, network, renewals, expires_at)
+            VALUES (?1, ?2, ?3, 0, ?4)
+            ON CONFLICT(ip) DO UPDATE SET
+                renewals = CASE WHEN client_id = excluded.client_id THEN renewals ELSE 0 END,
+                expires_at = excluded.expires_at,
+                pinned = 0,
+                client_id = excluded.client_id,
+                network = excluded.network"#,
+            ip,
+            id,
+            network,
+            expires_at
+        )
+        .execute(&self.inner)
+        .await?;
+        Ok(())
+    }
+
+    async fn set_pin(
+        &self,
+        ip: IpAddr,
+        network: IpAddr,
+        id: &[u8],
+        expires_at: SystemTime,
+    ) -> Result<(), Self::Error> {
+        let ip = util::ip_int(ip);
+        let network = util::ip_int(network);
+        let expires_at = util::systime_epoch(expires_at);
+        sqlx::query!(
+            r#"INSERT INTO sticky_leases (ip, client_id, network, renewals, expires_at, pinned)
+            VALUES (?1, ?2, ?3, 0, ?4, 1)
+            ON CONFLICT(ip) DO UPDATE SET
+                renewals = 0,
+                expires_at = excluded.expires_at,
+                pinned = 1,
+                client_id = excluded.client_id,
+                network = excluded.network"#,
+            ip,
+            id,
+            network,
+            expires_at
+        )
+        .execute(&self.inner)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_expired_pins(&self) -> Result<u64, Self::Error> {
+        let now = util::systime_epoch(self.now());
+        Ok(sqlx::query!(
+            "DELETE FROM sticky_leases WHERE pinned = 1 AND expires_at <= ?1",
+            now
+        )
+        .execute(&self.inner)
+        .await?
+        .rows_affected())
+    }
+
+    async fn get_sticky(
+        &self,
+        id: &[u8],
+        network: IpAddr,
+    ) -> Result<Option<StickyLease>, Self::Error> {
+        util::sticky_leases(
+            &self.inner,
+            Some(id),
+            Some(util::ip_int(network)),
+            None,
+            util::systime_epoch(self.now()),
+        )
+        .await
+        .map(|leases| leases.into_iter().next())
+    }
+
+    async fn get_sticky_ip(&self, ip: IpAddr) -> Result<Option<StickyLease>, Self::Error> {
+        util::sticky_leases(
+            &self.inner,
+            None,
+            None,
+            Some(util::ip_int(ip)),
+            util::systime_epoch(self.now()),
+        )
+        .await
+        .map(|leases| leases.into_iter().next())
+    }
+
+    async fn delete_sticky(&self, ip: IpAddr) -> Result<bool, Self::Error> {
+        // the renewal count goes too, so the lease isn't promoted again right away
+        let ip = util::ip_int(ip);
+        let expires_at = sqlx::query!(
+            "DELETE FROM sticky_leases WHERE ip = ?1 RETURNING expires_at",
+            ip
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .and_then(|row| row.expires_at);
+        Ok(matches!(expires_at, Some(t) if t > util::systime_epoch(self.now())))
+    }
+
+    async fn sticky_leases(&self) -> Result<Vec<StickyLease>, Self::Error> {
+        util::sticky_leases(
+            &self.inner,
+            None,
+            None,
+            None,
+            util::systime_epoch(self.now()),
+        )
+        .await
+    }
+
+    async fn set_relay_info(
+        &self,
+        ip: IpAddr,
+        id: &[u8],
+        info: Option<&[u8]>,
+    ) -> Result<(), Self::Error> {
+        let ip = util::ip_int(ip);
+        match info {
+            Some(data) => {
+                sqlx::query!(
+                    r#"INSERT OR REPLACE INTO relay_info (ip, client_id, data)
+                    VALUES (?1, ?2, ?3)"#,
+                    ip,
+                    id,
+                    data
+                )
+                .execute(&self.inner)
+                .await?;
+            }
+            None => {
+                sqlx::query!("DELETE FROM relay_info WHERE ip = ?1", ip)
+                    .execute(&self.inner)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_fingerprint(
+        &self,
+        ip: IpAddr,
+        id: &[u8],
+        fingerprint: Option<&LeaseFingerprint>,
+    ) -> Result<(), Self::Error> {
+        let ip = util::ip_int(ip);
+        match fingerprint {
+            Some(fp) => {
+                sqlx::query!(
+                    r#"INSERT OR REPLACE INTO fingerprints
+                    (ip, client_id, fingerprint, vendor_class, category, device)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)"#,
+                    ip,
+                    id,
+                    fp.fingerprint,
+                    fp.vendor_class,
+                    fp.category,
+                    fp.device
+                )
+                .execute(&self.inner)
+                .await?;
+            }
+            None => {
+                sqlx::query!("DELETE FROM fingerprints WHERE ip = ?1", ip)
+                    .execute(&self.inner)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_client_note(
+        &self,
+        id: &[u8],
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        match value {
+            Some(value) => {
+                sqlx::query!(
+                    r#"INSERT OR REPLACE INTO client_notes (client_id, key, value)
+                    VALUES (?1, ?2, ?3)"#,
+                    id,
+                    key,
+                    value
+                )
+                .execute(&self.inner)
+                .await?;
+            }
+            None => {
+                sqlx::query!(
+                    "DELETE FROM client_notes WHERE client_id = ?1 AND key = ?2",
+                    id,
+                    key
+                )
+                .execute(&self.inner)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn client_notes(&self, id: &[u8]) -> Result<BTreeMap<String, String>, Self::Error> {
+        Ok(sqlx::query!(
+            "SELECT key, value FROM client_notes WHERE client_id = ?1",
+            id
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(|row| (row.key, row.value))
+        .collect())
+    }
+
+    async fn integrity_check(&self) -> Result<Vec<String>, Self::Error> {
+        sqlx::query("PRAGMA quick_check")
+            .fetch_all(&self.inner)
+            .await?
+            .into_iter()
+            .map(|row| row.try_get::<String, _>(0))
+            .filter(|res| !matches!(res, Ok(msg) if msg == "ok"))
+            .collect()
+    }
+
+    async fn delete_orphans(&self) -> Result<u64, Self::Error> {
+        let mut trans = self.inner.begin().await?;
+        let nonces = sqlx::query!(
+            r#"DELETE FROM forcerenew_nonces WHERE NOT EXISTS
+            (SELECT 1 FROM leases l
+                WHERE l.ip = forcerenew_nonces.ip AND l.client_id = forcerenew_nonces.client_id)"#
+        )
+        .execute(&mut trans)
+        .await?;
+        let relay_info = sqlx::query!(
+            r#"DELETE FROM relay_info WHERE NOT EXISTS
+            (SELECT 1 FROM leases l
+                WHERE l.ip = relay_info.ip AND l.client_id = relay_info.client_id)"#
+        )
+        .execute(&mut trans)
+        .await?;
+        let fingerprints = sqlx::query!(
+            r#"DELETE FROM fingerprints WHERE NOT EXISTS
+            (SELECT 1 FROM leases l
+                WHERE l.ip = fingerprints.ip AND l.client_id = fingerprints.client_id)"#
+        )
+        .execute(&mut trans)
+        .await?;
+        trans.commit().await?;
+        Ok(nonces.rows_affected() + relay_info.rows_affected() + fingerprints.rows_affected())
+    }
+
+    async fn expiry_checkpoint(&self) -> Result<Option<SystemTime>, Self::Error> {
+        Ok(sqlx::query_scalar!(
+            "SELECT value FROM server_state WHERE key = ?1",
+            EXPIRY_CHECKPOINT
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(util::to_systime))
+    }
+
+    async fn set_expiry_checkpoint(&self, at: SystemTime) -> Result<(), Self::Error> {
+        let at = util::systime_epoch(at);
+        sqlx::query!(
+            "INSERT OR REPLACE INTO server_state (key, value) VALUES (?1, ?2)",
+            EXPIRY_CHECKPOINT,
+            at
+        )
+        .execute(&self.inner)
+        .await?;
+        Ok(())
+    }
+
+    async fn checkpoint(&self) -> Result<(), Self::Error> {
+        let Some(behind) = &self.behind else {
+            sqlx::query("PRAGMA wal_checkpoint(PASSIVE)")
+                .execute(&self.inner)
+                .await?;
+            return Ok(());
+        };
+        let _lock = behind.lock.lock().await;
+        // write a snapshot next to the file & swap it in, so a crash part way
+        // leaves the previous checkpoint intact
+        let tmp = PathBuf::from(format!("{}.tmp", behind.path.display()));
+        if let Err(err) = tokio::fs::remove_file(&tmp).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                return Err(err.into());
+            }
+        }
+        sqlx::query("VACUUM INTO ?1")
+            .bind(tmp.to_string_lossy().into_owned())
+            .execute(&self.inner)
+            .await?;
+        tokio::fs::File::open(&tmp).await?.sync_all().await?;
+        tokio::fs::rename(&tmp, &behind.path).await?;
+        Ok(())
+    }
+
+    async fn maintain(&self, max_pages: u32) -> Result<Maintenance, Self::Error> {
+        /// `auto_vacuum` value of incremental vacuum
+        const INCREMENTAL: u64 = 2;
+        let started_at = SystemTime::now();
+        let start = std::time::Instant::now();
+        // pragmas apply to the connection, run everything on one
+        let mut conn = self.inner.acquire().await?;
+        let pages = pragma(&mut conn, "page_count").await?;
+        // a write-behind copy is in memory, checkpoints already write a compact file
+        let full_vacuum =
+            self.behind.is_none() && pragma(&mut conn, "auto_vacuum").await? != INCREMENTAL;
+        if full_vacuum {
+            // auto_vacuum only changes on an empty database or with a full VACUUM
+            sqlx::query("PRAGMA auto_vacuum = INCREMENTAL")
+                .execute(&mut *conn)
+                .await?;
+            sqlx::query("VACUUM").execute(&mut *conn).await?;
+        } else if self.behind.is_none() {
+            // 0 frees every page
+            sqlx::query(&format!("PRAGMA incremental_vacuum({max_pages})"))
+                .execute(&mut *conn)
+                .await?;
+        }
+        sqlx::query("ANALYZE").execute(&mut *conn).await?;
+        let page_count = pragma(&mut conn, "page_count").await?;
+        let run = Maintenance {
+            started_at,
+            elapsed: start.elapsed(),
+            reclaimed_pages: pages.saturating_sub(page_count),
+            page_count,
+            free_pages: pragma(&mut conn, "freelist_count").await?,
+            full_vacuum,
+        };
+        let started_at = util::systime_epoch(run.started_at);
+        let elapsed_ms = run.elapsed.as_millis() as i64;
+        let reclaimed_pages = run.reclaimed_pages as i64;
+        let page_count = run.page_count as i64;
+        let free_pages = run.free_pages as i64;
+        sqlx::query!(
+            r#"INSERT OR REPLACE INTO maintenance
+            (id, started_at, elapsed_ms, reclaimed_pages, page_count, free_pages, full_vacuum)
+            VALUES (0, ?1, ?2, ?3, ?4, ?5, ?6)"#,
+            started_at,
+            elapsed_ms,
+            reclaimed_pages,
+            page_count,
+            free_pages,
+            run.full_vacuum
+        )
+        .execute(&mut *conn)
+        .await?;
+        Ok(run)
+    }
+
+    async fn last_maintenance(&self) -> Result<Option<Maintenance>, Self::Error> {
+        Ok(sqlx::query!(
+            r#"SELECT started_at, elapsed_ms, reclaimed_pages, page_count, free_pages, full_vacuum
+            FROM maintenance WHERE id = 0"#
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(|row| Maintenance {
+            started_at: util::to_systime(row.started_at),
+            elapsed: Duration::from_millis(row.elapsed_ms as u64),
+            reclaimed_pages: row.reclaimed_pages as u64,
+            page_count: row.page_count as u64,
+            free_pages: row.free_pages as u64,
+            full_vacuum: row.full_vacuum,
+        }))
+    }
+
+    async fn push_config(
+        &self,
+        config: &str,
+        note: Option<&str>,
+    ) -> Result<ConfigVersion, Self::Error> {
+        let now = util::systime_epoch(self.now());
+        let row = sqlx::query!(
+            r#"INSERT INTO config_versions (config, created_at, note)
+            VALUES (?1, ?2, ?3)
+            RETURNING version, created_at, note"#,
+            config,
+            now,
+            note
+        )
+        .fetch_one(&self.inner)
+        .await?;
+        Ok(util::config_version(row.version, row.created_at, row.note))
+    }
+
+    async fn config_versions(&self) -> Result<Vec<ConfigVersion>, Self::Error> {
+        Ok(sqlx::query!(
+            "SELECT version, created_at, note FROM config_versions ORDER BY version DESC"
+        )
+        .fetch_all(&self.inner)
+        .await?
+        .into_iter()
+        .map(|row| util::config_version(row.version, row.created_at, row.note))
+        .collect())
+    }
+
+    async fn config_version(
+        &self,
+        version: u64,
+    ) -> Result<Option<(ConfigVersion, String)>, Self::Error> {
+        let version = version as i64;
+        Ok(sqlx::query!(
+            "SELECT version, created_at, note, config FROM config_versions WHERE version = ?1",
+            version
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(|row| {
+            (
+                util::config_version(row.version, row.created_at, row.note),
+                row.config,
+            )
+        }))
+    }
+
+    async fn latest_config(&self) -> Result<Option<(ConfigVersion, String)>, Self::Error> {
+        Ok(sqlx::query!(
+            "SELECT version, created_at, note, config FROM config_versions ORDER BY version DESC LIMIT 1"
+        )
+        .fetch_optional(&self.inner)
+        .await?
+        .map(|row| {
+            (
+                util::config_version(row.version, row.created_at, row.note),
+                row.config,
+            )
+        }))
+    }
+}
+
+mod util {
+    use std::net::Ipv4Addr;
+
+    use crate::State;
+
+    use super::*;
+    pub fn systime_epoch(time: SystemTime) -> i64 {
+        // / get secs as i64 (for use in sqlite) from epoch to `time`
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .expect("failed to get system time")
+            .as_secs() as i64
+    }
+
+    pub fn ip_int(ip: IpAddr) -> i64 {
+        match ip {
+            IpAddr::V4(ip) => u32::from(ip) as i64,
+            IpAddr::V6(_ip) => panic!("ipv6 not yet implemented"),
+        }
+    }
+    pub fn to_systime(time: i64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(time as u64)
+    }
+
+    /// a row of `ACTIVE_LEASES`
+    #[derive(sqlx::FromRow)]
+    pub struct LeaseRow {
+        pub ip: i64,
+        pub client_id: Option<Vec<u8>>,
+        pub network: i64,
+        pub state: String,
+        pub expires_at: i64,
+        pub hostname: Option<String>,
+        pub relay_info: Option<Vec<u8>>,
+        pub fingerprint: Option<String>,
+        pub vendor_class: Option<String>,
+        pub category: Option<String>,
+        pub device: Option<String>,
+    }
+
+    /// an [`ActiveLease`] from a row of `ACTIVE_LEASES`
+    pub fn active_lease(row: LeaseRow) -> Result<ActiveLease, sqlx::Error> {
+        let lease_state = parse_state(row.state)?;
+        Ok(ActiveLease {
+            ip: IpAddr::V4(Ipv4Addr::from(row.ip as u32)),
+            id: row.client_id,
+            network: IpAddr::V4(Ipv4Addr::from(row.network as u32)),
+            state: lease_state.into(),
+            lease_state,
+            expires_at: to_systime(row.expires_at),
+            hostname: row.hostname,
+            relay_info: row.relay_info,
+            fingerprint: row.fingerprint.map(|fingerprint| LeaseFingerprint {
+                fingerprint,
+                vendor_class: row.vendor_class,
+                category: row.category,
+                device: row.device,
+            }),
+            notes: BTreeMap::new(),
+        })
+    }
+
+    /// `leases` with the notes on their clients
+    pub async fn with_notes(
+        pool: &SqlitePool,
+        mut leases: Vec<ActiveLease>,
+    ) -> Result<Vec<ActiveLease>, sqlx::Error> {
+        /// client ids per query, under sqlite's bound parameter limit
+        const CHUNK: usize = 500;
+        let ids = leases
+            .iter()
+            .filter_map(|lease| lease.id.as_deref())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let mut notes = HashMap::<Vec<u8>, BTreeMap<String, String>>::new();
+        for chunk in ids.chunks(CHUNK) {
+            let sql = format!(
+                "SELECT client_id, key, value FROM client_notes WHERE client_id IN ({})",
+                vec!["?"; chunk.len()].join(", ")
+            );
+            let mut q = sqlx::query(&sql);
+            for id in chunk {
+                q = q.bind(*id);
+            }
+            for row in q.fetch_all(pool).await? {
+                notes
+                    .entry(row.try_get("client_id")?)
+                    .or_default()
+                    .insert(row.try_get("key")?, row.try_get("value")?);
+            }
+        }
+        for lease in &mut leases {
+            if let Some(client_notes) = lease.id.as_ref().and_then(|id| notes.get(id)) {
+                lease.notes = client_notes.clone();
+            }
+        }
+        Ok(leases)
+    }
+
+    pub fn config_version(version: i64, created_at: i64, note: Option<String>) -> ConfigVersion {
+        ConfigVersion {
+            version: version as u64,
+            created_at: to_systime(created_at),
+            note,
+        }
+    }
+
+    pub fn policy_str(policy: HostnamePolicy) -> &'static str {
+        match policy {
+            HostnamePolicy::Reject => "reject",
+            HostnamePolicy::Suffix => "suffix",
+            HostnamePolicy::Replace => "replace",
+        }
+    }
+
+    pub fn parse_policy(policy: &str) -> HostnamePolicy {
+        match policy {
+            "suffix" => HostnamePolicy::Suffix,
+            "replace" => HostnamePolicy::Replace,
+            _ => HostnamePolicy::Reject,
+        }
+    }
+
+    pub async fn delete<'a, E>(conn: E, ip: i64) -> Result<(), sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = Sqlite>,
+    {
+        sqlx::query!("DELETE FROM leases WHERE ip = ?1", ip)
+            .execute(conn)
+            .await?;
+        Ok(())
+    }
+
+    /// mark the un-expired offer or lease of `client_id` for `ip` released,
+    /// returning it as it was
+    pub async fn release_ip(
+        conn: &mut SqliteConnection,
         ip: i64,
         client_id: &[u8],
+        now: i64,
+    ) -> Result<Option<ClientInfo>, sqlx::Error> {
+        let Some(entry) = sqlx::query_as!(
+            Entry,
+            "SELECT ip, client_id, expires_at, network, state FROM leases WHERE ip = ?1 AND client_id = ?2",
+            ip,
+            client_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let info = client_info(&entry);
+        let released = transition(
+            conn,
+            &entry,
+            LeaseState::Released,
+            Some(client_id),
+            now,
+            now,
+        )
+        .await?;
+        Ok(released.then_some(info))
+    }
+
+    /// Inserts ip/network/client_id/expires_at into db in `state`, logging it
+    pub async fn insert(
+        conn: &mut SqliteConnection,
+        ip: i64,
+        network: i64,
+        client_id: &[u8],
         expires_at: i64,
         now: i64,
+        state: LeaseState,
+    ) -> Result<(), sqlx::Error> {
+        if !LeaseState::can_transition(None, state) {
+            return Err(sqlx::Error::Protocol(format!(
+                "a new lease entry can't be {state}"
+            )));
+        }
+        let (leased, probation) = state.into();
+        let state_name = state.as_str();
+        sqlx::query!(
+            r#"INSERT INTO leases
+                (ip, client_id, expires_at, network, leased, probation, state)
+            VALUES
+                (?1, ?2, ?3, ?4, ?5, ?6, ?7)"#,
+            ip,
+            client_id,
+            expires_at,
+            network,
+            leased,
+            probation,
+            state_name
+        )
+        .execute(&mut *conn)
+        .await?;
+        log_transition(conn, ip, Some(client_id), None, state, now).await
+    }
+
+    pub async fn find(pool: &SqlitePool, ip: i64) -> Result<Option<State>, sqlx::Error> {
+        Ok(sqlx::query!("SELECT * FROM leases WHERE ip = ?1", ip)
+            .fetch_optional(pool)
+            .await?
+            .map(|cur| {
+                let info = ClientInfo {
+                    ip: IpAddr::V4(Ipv4Addr::from(cur.ip as u32)),
+                    id: cur.client_id.map(|v| v.to_vec()),
+                    network: IpAddr::V4(Ipv4Addr::from(cur.network as u32)),
+                    expires_at: to_systime(cur.expires_at),
+                };
+                into_clientinfo(info, cur.leased, cur.probation)
+            }))
+    }
+
+    /// return a count of all rows where leased & probation & un-expired
+    pub async fn count(
+        pool: &SqlitePool,
         leased: bool,
         probation: bool,
-        new_id: Option<&[u8]>,
-    ) -> Result<Option<IpAddr>, sqlx::Error>
-    where
-        E: sqlx::Executor<'a, Database = Sqlite>,
-    {
+        expires_at: i64,
+    ) -> Result<usize, sqlx::Error> {
+        Ok(sqlx::query_scalar!(
+            "SELECT COUNT(ip) as count_ip FROM leases WHERE leased = ?1 AND probation = ?2 AND expires_at > ?3",
+            leased,
+            probation,
+            expires_at
+        )
+        .fetch_one(pool)
+        .await? as usize)
+    }
+
+    /// return the info for this client_id and if it's un-expired
+    pub async fn find_by_id(
+        pool: &SqlitePool,
+        id: &[u8],
+        now: i64,
+    ) -> Result<Option<IpAddr>, sqlx::Error> {
         Ok(sqlx::query!(
+            "SELECT ip 
+            FROM 
+                leases 
+            WHERE 
+                client_id = ?1 AND expires_at > ?2 
+            LIMIT 1",
+            id,
+            now
+        )
+        .fetch_optional(pool)
+        .await?
+        .map(|cur| IpAddr::V4(Ipv4Addr::from(cur.ip as u32))))
+    }
+
+    /// offers the first expired IP in a range, or where the id matches,
+    /// returning it. expires_at can refer to IPs under probation. Addresses
+    /// held as sticky leases for other clients are skipped
+    pub async fn update_next_expired(
+        conn: &mut SqliteConnection,
+        // select
+        now: i64,
+        id: &[u8],
+        start_ip: i64,
+        end_ip: i64,
+        // update
+        expires_at: i64,
+    ) -> Result<Option<IpAddr>, sqlx::Error> {
+        // the client's own entry is skipped while it's on probation
+        let Some(entry) = sqlx::query_as!(
+            Entry,
             r#"
-            UPDATE leases
-            SET
-                leased = ?4, expires_at = ?5, probation = ?6, client_id = ?7
-            WHERE ip in
-               (
-                    SELECT ip
-                    FROM leases
-                    WHERE
-                        ((expires_at > ?1) AND (client_id = ?2) AND (ip = ?3))
-                    ORDER BY ip LIMIT 1
-                )
-            RETURNING ip
+            SELECT ip, client_id, expires_at, network, state
+            FROM leases
+            WHERE
+                ((expires_at < ?1) AND (ip >= ?2 AND ip <= ?3)
+                    AND ip NOT IN (
+                        SELECT ip FROM sticky_leases
+                        WHERE expires_at > ?1 AND client_id != ?4
+                    ))
+                OR (client_id = ?4
+                    AND (state NOT IN ('declined', 'reserved') OR expires_at < ?1))
+            ORDER BY ip LIMIT 1
             "#,
             now,
+            start_ip,
+            end_ip,
+            id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let ip = client_info(&entry).ip;
+        Ok(
+            transition(conn, &entry, LeaseState::Offered, Some(id), expires_at, now)
+                .await?
+                .then_some(ip),
+        )
+    }
+
+    /// updates an entry if the ip & id match and not expired
+    pub async fn update_unexpired(
+        conn: &mut SqliteConnection,
+        ip: i64,
+        client_id: &[u8],
+        expires_at: i64,
+        now: i64,
+        state: LeaseState,
+        new_id: Option<&[u8]>,
+    ) -> Result<Option<IpAddr>, sqlx::Error> {
+        let Some(entry) = sqlx::query_as!(
+            Entry,
+            "SELECT ip, client_id, expires_at, network, state FROM leases WHERE expires_at > ?1 AND client_id = ?2 AND ip = ?3",
+            now,
             client_id,
-            ip,
-            leased,
-            expires_at,
-            probation,
-            new_id
+            ip
         )
-        .fetch_optional(conn)
+        .fetch_optional(&mut *conn)
         .await?
-        .map(|cur| IpAddr::V4(Ipv4Addr::from(cur.ip as u32))))
+        else {
+            return Ok(None);
+        };
+        Ok(transition(conn, &entry, state, new_id, expires_at, now)
+            .await?
+            .then_some(IpAddr::V4(Ipv4Addr::from(ip as u32))))
     }
 
     /// updates an entry if the ip & id match
     /// or if the entry is expired and the ip matches
-    pub async fn update_expired<'a, E>(
-        conn: E,
+    pub async fn update_expired(
+        conn: &mut SqliteConnection,
         ip: i64,
         client_id: &[u8],
         expires_at: i64,
         now: i64,
-        leased: bool,
-        probation: bool,
-    ) -> Result<Option<IpAddr>, sqlx::Error>
-    where
-        E: sqlx::Executor<'a, Database = Sqlite>,
-    {
-        Ok(sqlx::query!(
-            r#"
-            UPDATE leases
-            SET
-                client_id = ?2, leased = ?4, expires_at = ?5, probation = ?6
-            WHERE ip in
-               (
-                    SELECT ip
-                    FROM leases
-                    WHERE
-                        ((client_id = ?2 AND ip = ?3) 
-                            OR (expires_at < ?1 AND ip = ?3))
-                    ORDER BY ip LIMIT 1
-                )
-            RETURNING ip
-            "#,
+        state: LeaseState,
+    ) -> Result<Option<IpAddr>, sqlx::Error> {
+        let Some(entry) = sqlx::query_as!(
+            Entry,
+            "SELECT ip, client_id, expires_at, network, state FROM leases WHERE ip = ?3 AND (client_id = ?2 OR expires_at < ?1)",
             now,
             client_id,
-            ip,
-            leased,
-            expires_at,
-            probation
+            ip
         )
-        .fetch_optional(conn)
+        .fetch_optional(&mut *conn)
         .await?
-        .map(|cur| IpAddr::V4(Ipv4Addr::from(cur.ip as u32))))
+        else {
+            return Ok(None);
+        };
+        Ok(
+            transition(conn, &entry, state, Some(client_id), expires_at, now)
+                .await?
+                .then_some(IpAddr::V4(Ipv4Addr::from(ip as u32))),
+        )
     }
 
     /// get the max IP in a given range
@@ -637,12 +1977,80 @@ mod util {
         }))
     }
 
+    /// un-expired sticky leases, filtered by client id, network & ip if set
+    pub async fn sticky_leases<'a, E>(
+        conn: E,
+        id: Option<&[u8]>,
+        network: Option<i64>,
+        ip: Option<i64>,
+        now: i64,
+    ) -> Result<Vec<StickyLease>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = Sqlite>,
+    {
+        let to_ip = |ip: i64| IpAddr::V4(Ipv4Addr::from(ip as u32));
+        Ok(sqlx::query!(
+            r#"SELECT ip, client_id, network, renewals,
+                expires_at AS "expires_at!", pinned AS "pinned: bool"
+            FROM sticky_leases
+            WHERE expires_at > ?1
+                AND (?2 IS NULL OR client_id = ?2)
+                AND (?3 IS NULL OR network = ?3)
+                AND (?4 IS NULL OR ip = ?4)
+            ORDER BY ip"#,
+            now,
+            id,
+            network,
+            ip
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|row| StickyLease {
+            ip: to_ip(row.ip),
+            id: row.client_id,
+            network: to_ip(row.network),
+            renewals: row.renewals as u32,
+            expires_at: to_systime(row.expires_at),
+            pinned: row.pinned,
+        })
+        .collect())
+    }
+
+    /// addresses between start & end held as un-expired sticky leases for a
+    /// client other than `id`
+    pub async fn sticky_held<'a, E>(
+        conn: E,
+        id: &[u8],
+        start_ip: i64,
+        end_ip: i64,
+        now: i64,
+    ) -> Result<HashSet<Ipv4Addr>, sqlx::Error>
+    where
+        E: sqlx::Executor<'a, Database = Sqlite>,
+    {
+        Ok(sqlx::query_scalar!(
+            r#"SELECT ip FROM sticky_leases
+            WHERE expires_at > ?1 AND client_id != ?2 AND ip >= ?3 AND ip <= ?4"#,
+            now,
+            id,
+            start_ip,
+            end_ip
+        )
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|ip| Ipv4Addr::from(ip as u32))
+        .collect())
+    }
+
     /// get the next IP between start and end, skipping any exclusions
-    pub fn inc_ip(start: IpAddr, end: IpAddr, exclusions: &HashSet<Ipv4Addr>) -> Option<IpAddr> {
+    pub fn inc_ip(start: IpAddr, end: IpAddr, exclusions: &Exclusions) -> Option<IpAddr> {
         match (start, end) {
             (IpAddr::V4(ip), IpAddr::V4(end)) => {
-                NetRangeIter::new(ipnet::Ipv4AddrRange::new(ip, end), exclusions)
-                    .nth(1)
+                let next = Ipv4Addr::from(u32::from(ip).checked_add(1)?);
+                NetRangeIter::new(next..=end, exclusions)
+                    .next()
                     .map(|ip| ip.into())
             }
             (IpAddr::V6(ip), IpAddr::V6(end)) => {
@@ -662,42 +2070,134 @@ mod util {
         }
     }
 
-    pub async fn update_ip<'a, E>(
-        conn: E,
+    /// updates the entry for `ip` whatever client it's for
+    pub async fn update_ip(
+        conn: &mut SqliteConnection,
         ip: i64,
         client_id: Option<&[u8]>,
         expires_at: i64,
-        leased: bool,
-        probation: bool,
-    ) -> Result<Option<State>, sqlx::Error>
-    where
-        E: sqlx::Executor<'a, Database = Sqlite>,
-    {
-        Ok(sqlx::query!(
-            r#"
-            UPDATE leases
-            SET
-                client_id = ?2, expires_at = ?3, leased = ?4, probation = ?5
-            WHERE 
-                ip = ?1
-            RETURNING *
-            "#,
+        now: i64,
+        state: LeaseState,
+    ) -> Result<Option<State>, sqlx::Error> {
+        let Some(entry) = sqlx::query_as!(
+            Entry,
+            "SELECT ip, client_id, expires_at, network, state FROM leases WHERE ip = ?1",
+            ip
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        else {
+            return Ok(None);
+        };
+        if !transition(conn, &entry, state, client_id, expires_at, now).await? {
+            return Ok(None);
+        }
+        let info = ClientInfo {
+            id: client_id.map(<[u8]>::to_vec),
+            expires_at: to_systime(expires_at),
+            ..client_info(&entry)
+        };
+        let (leased, probation) = state.into();
+        Ok(Some(into_clientinfo(info, leased, probation)))
+    }
+
+    /// the [`LeaseState`] named `state`
+    pub fn parse_state(state: String) -> Result<LeaseState, sqlx::Error> {
+        state
+            .parse()
+            .map_err(|err| sqlx::Error::Decode(Box::new(err)))
+    }
+
+    /// the columns of a `leases` entry its transitions need
+    pub struct Entry {
+        pub ip: i64,
+        pub client_id: Option<Vec<u8>>,
+        pub expires_at: i64,
+        pub network: i64,
+        pub state: String,
+    }
+
+    /// a [`ClientInfo`] from an entry of `leases`
+    pub fn client_info(entry: &Entry) -> ClientInfo {
+        ClientInfo {
+            ip: IpAddr::V4(Ipv4Addr::from(entry.ip as u32)),
+            id: entry.client_id.clone(),
+            network: IpAddr::V4(Ipv4Addr::from(entry.network as u32)),
+            expires_at: to_systime(entry.expires_at),
+        }
+    }
+
+    /// move `entry` to `to` with a new client & expiry, logging the
+    /// transition. An entry read as expired or reclaimed that wasn't moved
+    /// there yet has that transition logged first, at its expiry. Returns
+    /// `false` & changes nothing if `to` can't follow the entry's state
+    pub async fn transition(
+        conn: &mut SqliteConnection,
+        entry: &Entry,
+        to: LeaseState,
+        client_id: Option<&[u8]>,
+        expires_at: i64,
+        now: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let ip = entry.ip;
+        let stored = parse_state(entry.state.clone())?;
+        let prev_expiry = entry.expires_at;
+        let from = stored.at(to_systime(prev_expiry), to_systime(now));
+        if !LeaseState::can_transition(Some(from), to) {
+            debug!(ip = ?Ipv4Addr::from(ip as u32), %from, %to, "illegal lease transition");
+            return Ok(false);
+        }
+        if from != stored {
+            log_transition(
+                conn,
+                ip,
+                entry.client_id.as_deref(),
+                Some(stored),
+                from,
+                prev_expiry,
+            )
+            .await?;
+        }
+        let (leased, probation) = to.into();
+        let state = to.as_str();
+        sqlx::query!(
+            r#"UPDATE leases
+            SET client_id = ?2, expires_at = ?3, leased = ?4, probation = ?5, state = ?6
+            WHERE ip = ?1"#,
             ip,
             client_id,
             expires_at,
             leased,
-            probation
+            probation,
+            state
         )
-        .fetch_optional(conn)
-        .await?
-        .map(|cur| {
-            let info = ClientInfo {
-                ip: IpAddr::V4(Ipv4Addr::from(cur.ip as u32)),
-                id: cur.client_id.map(|v| v.to_vec()),
-                network: IpAddr::V4(Ipv4Addr::from(cur.network as u32)),
-                expires_at: to_systime(cur.expires_at),
-            };
-            into_clientinfo(info, cur.leased, cur.probation)
-        }))
+        .execute(&mut *conn)
+        .await?;
+        log_transition(conn, ip, client_id, Some(from), to, now).await?;
+        Ok(true)
+    }
+
+    /// append a transition of the entry for `ip` to the log
+    pub async fn log_transition(
+        conn: &mut SqliteConnection,
+        ip: i64,
+        client_id: Option<&[u8]>,
+        from: Option<LeaseState>,
+        to: LeaseState,
+        at: i64,
+    ) -> Result<(), sqlx::Error> {
+        let (from, to) = (from.map(|state| state.as_str()), to.as_str());
+        sqlx::query!(
+            r#"INSERT INTO lease_transitions (ip, client_id, from_state, to_state, at)
+            VALUES (?1, ?2, ?3, ?4, ?5)"#,
+            ip,
+            client_id,
+            from,
+            to,
+            at
+        )
+        .execute(conn)
+        .await?;
+        Ok(())
     }
 }
@@ -0,0 +1,157 @@
+//! group commits: lease writes from many clients share one transaction, so
+//! they share the sync to disk too
+use std::{net::IpAddr, time::Duration};
+
+use dora_core::metrics;
+use sqlx::{sqlite::SqliteConnection, SqlitePool};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::{self, Instant},
+};
+use tracing::{error, trace};
+
+use super::util;
+use crate::LeaseState;
+
+/// how many lease writes are committed together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchLimits {
+    /// most writes in one transaction
+    pub size: usize,
+    /// longest the first write of a batch waits for others to join it. With
+    /// no wait only writes already queued join, which adds no latency
+    pub wait: Duration,
+}
+
+/// a lease write, see the `util` fn of the same name
+#[derive(Debug)]
+pub(super) enum Op {
+    UpdateUnexpired {
+        ip: i64,
+        id: Vec<u8>,
+        expires_at: i64,
+        now: i64,
+        state: LeaseState,
+        new_id: Option<Vec<u8>>,
+    },
+    Insert {
+        ip: i64,
+        network: i64,
+        id: Vec<u8>,
+        expires_at: i64,
+        now: i64,
+        state: LeaseState,
+    },
+}
+
+impl Op {
+    async fn run(&self, conn: &mut SqliteConnection) -> Result<Option<IpAddr>, sqlx::Error> {
+        match self {
+            Op::UpdateUnexpired {
+                ip,
+                id,
+                expires_at,
+                now,
+                state,
+                new_id,
+            } => {
+                util::update_unexpired(conn, *ip, id, *expires_at, *now, *state, new_id.as_deref())
+                    .await
+            }
+            Op::Insert {
+                ip,
+                network,
+                id,
+                expires_at,
+                now,
+                state,
+            } => util::insert(conn, *ip, *network, id, *expires_at, *now, *state)
+                .await
+                .map(|_| None),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Write {
+    op: Op,
+    done: oneshot::Sender<Result<Option<IpAddr>, sqlx::Error>>,
+}
+
+/// queues writes for the task committing them
+#[derive(Debug, Clone)]
+pub(super) struct Batcher {
+    tx: mpsc::Sender<Write>,
+}
+
+impl Batcher {
+    pub(super) fn spawn(pool: SqlitePool, limits: BatchLimits) -> Self {
+        let (tx, rx) = mpsc::channel(limits.size.max(1) * 4);
+        tokio::spawn(run(pool, rx, limits));
+        Self { tx }
+    }
+
+    /// run `op`, returning once the batch it joined has committed
+    pub(super) async fn write(&self, op: Op) -> Result<Option<IpAddr>, sqlx::Error> {
+        let (done, rx) = oneshot::channel();
+        self.tx
+            .send(Write { op, done })
+            .await
+            .map_err(|_| sqlx::Error::PoolClosed)?;
+        rx.await.map_err(|_| sqlx::Error::PoolClosed)?
+    }
+}
+
+async fn run(pool: SqlitePool, mut rx: mpsc::Receiver<Write>, limits: BatchLimits) {
+    while let Some(first) = rx.recv().await {
+        let deadline = Instant::now() + limits.wait;
+        let mut batch = vec![first];
+        while batch.len() < limits.size {
+            let next = match rx.try_recv() {
+                Ok(write) => Some(write),
+                Err(_) if limits.wait.is_zero() => None,
+                Err(_) => time::timeout_at(deadline, rx.recv()).await.ok().flatten(),
+            };
+            let Some(write) = next else {
+                break;
+            };
+            batch.push(write);
+        }
+        commit(&pool, batch).await;
+    }
+}
+
+/// run every write of `batch` in one transaction, replying to each once it commits
+async fn commit(pool: &SqlitePool, batch: Vec<Write>) {
+    let start = std::time::Instant::now();
+    let res = async {
+        let mut trans = pool.begin().await?;
+        let mut results = Vec::with_capacity(batch.len());
+        for write in &batch {
+            // a failed statement is rolled back on its own, the rest still commit
+            results.push(write.op.run(&mut trans).await);
+        }
+        trans.commit().await?;
+        Ok::<_, sqlx::Error>(results)
+    }
+    .await;
+    metrics::DB_BATCH_SIZE.observe(batch.len() as f64);
+    metrics::DB_BATCH_COMMIT.observe(start.elapsed().as_secs_f64());
+    trace!(writes = batch.len(), elapsed = ?start.elapsed(), "committed lease batch");
+    match res {
+        Ok(results) => {
+            for (write, res) in batch.into_iter().zip(results) {
+                // the client may have given up waiting
+                let _ = write.done.send(res);
+            }
+        }
+        Err(err) => {
+            error!(?err, writes = batch.len(), "failed to commit lease batch");
+            for write in batch {
+                let _ = write.done.send(Err(sqlx::Error::Protocol(format!(
+                    "lease batch failed to commit: {err}"
+                ))));
+            }
+        }
+    }
+}
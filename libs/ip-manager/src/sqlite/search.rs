@@ -0,0 +1,119 @@
+//! lease searches as SQL: the conditions of a [`LeaseQuery`] are appended to
+//! `ACTIVE_LEASES`, so only the requested page of leases is read
+use crate::query::{Cond, IpCol, LeaseQuery, SortKey, StateFilter};
+
+/// a value bound to a query placeholder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Bind {
+    Int(i64),
+    Text(String),
+}
+
+/// `WHERE` conditions, `ORDER BY` & `LIMIT` for `query`, with the values to
+/// bind. Placeholders start at `?{first}`
+pub(super) fn to_sql(query: &LeaseQuery, first: usize) -> (String, Vec<Bind>) {
+    let mut sql = String::new();
+    let mut binds = Vec::new();
+    for filter in query.filters() {
+        let mut bind = |value: Bind| {
+            binds.push(value);
+            format!("?{}", first + binds.len() - 1)
+        };
+        let cond = match filter.cond() {
+            Cond::Block { col, start, end } => {
+                let col = column(*col);
+                format!(
+                    "{col} BETWEEN {} AND {}",
+                    bind(Bind::Int(u32::from(*start) as i64)),
+                    bind(Bind::Int(u32::from(*end) as i64))
+                )
+            }
+            Cond::State(state) => state_sql(*state).to_owned(),
+            Cond::ClientId { hex, contains } => {
+                let placeholder = bind(Bind::Text(hex.clone()));
+                if *contains {
+                    format!("instr(lower(hex(l.client_id)), {placeholder}) > 0")
+                } else {
+                    format!("lower(hex(l.client_id)) = {placeholder}")
+                }
+            }
+            Cond::Hostname { name, contains } => {
+                let placeholder = bind(Bind::Text(name.to_lowercase()));
+                if *contains {
+                    format!("instr(lower(h.hostname), {placeholder}) > 0")
+                } else {
+                    format!("lower(h.hostname) = {placeholder}")
+                }
+            }
+        };
+        // missing client ids & hostnames are NULL, which doesn't match either way
+        if filter.negated() {
+            sql.push_str(&format!(" AND ({cond}) IS NOT NULL AND NOT ({cond})"));
+        } else {
+            sql.push_str(&format!(" AND IFNULL(({cond}), 0)"));
+        }
+    }
+    let order = if query.descending() { "DESC" } else { "ASC" };
+    let sort = match query.sort_key() {
+        SortKey::Ip => "l.ip",
+        SortKey::Network => "l.network",
+        SortKey::ExpiresAt => "l.expires_at",
+        SortKey::Hostname => "h.hostname",
+    };
+    sql.push_str(&format!(" ORDER BY {sort} {order}, l.ip {order}"));
+    binds.push(Bind::Int(query.limit() as i64));
+    binds.push(Bind::Int(query.offset() as i64));
+    sql.push_str(&format!(
+        " LIMIT ?{} OFFSET ?{}",
+        first + binds.len() - 2,
+        first + binds.len() - 1
+    ));
+    (sql, binds)
+}
+
+fn column(col: IpCol) -> &'static str {
+    match col {
+        IpCol::Ip => "l.ip",
+        IpCol::Network => "l.network",
+    }
+}
+
+fn state_sql(state: StateFilter) -> &'static str {
+    match state {
+        StateFilter::Leased => "l.state = 'active'",
+        StateFilter::Offered => "l.state = 'offered'",
+        StateFilter::Probated => "l.state IN ('declined', 'reserved')",
+        StateFilter::Declined => "l.state = 'declined'",
+        StateFilter::Reserved => "l.state = 'reserved'",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_sql() {
+        let query = LeaseQuery::new("ip=10.0.0.0/30 AND hostname!=printer")
+            .unwrap()
+            .sort(SortKey::ExpiresAt, true)
+            .page(10, 20);
+        let (sql, binds) = to_sql(&query, 2);
+        assert_eq!(
+            sql,
+            " AND IFNULL((l.ip BETWEEN ?2 AND ?3), 0) \
+             AND (lower(h.hostname) = ?4) IS NOT NULL AND NOT (lower(h.hostname) = ?4) \
+             ORDER BY l.expires_at DESC, l.ip DESC LIMIT ?5 OFFSET ?6"
+        );
+        assert_eq!(
+            binds,
+            [
+                Bind::Int(0x0a00_0000),
+                Bind::Int(0x0a00_0003),
+                Bind::Text("printer".to_owned()),
+                Bind::Int(10),
+                Bind::Int(20),
+            ]
+        );
+    }
+}
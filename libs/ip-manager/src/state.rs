@@ -0,0 +1,236 @@
+//! explicit lease states
+//!
+//! Every entry in the lease table carries a [`LeaseState`]. Writes only move an
+//! entry along the transitions [`LeaseState::can_transition`] allows, anything
+//! else is treated like a missing entry, and every change is appended to a
+//! transition log (see [`Storage::lease_transitions`]).
+//!
+//! ```text
+//! (new)     -> offered | active | declined | reserved
+//! offered   -> offered | active | released | declined | expired
+//! active    -> offered | active | released | declined | expired
+//! declined  -> declined | reserved | reclaimed
+//! reserved  -> declined | reserved | reclaimed
+//! expired   -> offered | active | declined | reserved
+//! released  -> offered | active | declined | reserved
+//! reclaimed -> offered | active | declined | reserved
+//! ```
+//!
+//! An offer or lease past its expiry is `expired`, a probation past its end is
+//! `reclaimed`. [`IpManager::watch_expiry`] moves entries there as it polls,
+//! until it does the state of an entry is read as of its expiry.
+//!
+//! The `leased` & `probation` columns the state replaces are kept in step with
+//! the last offer, lease or probation written for the address.
+//!
+//! [`Storage::lease_transitions`]: crate::Storage::lease_transitions
+//! [`IpManager::watch_expiry`]: crate::IpManager::watch_expiry
+use std::{fmt, net::IpAddr, str::FromStr, time::SystemTime};
+
+use thiserror::Error;
+
+use crate::IpState;
+
+/// the state of an entry in the lease table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LeaseState {
+    /// held for a client after DISCOVER, waiting for REQUEST
+    Offered,
+    /// leased after REQUEST
+    Active,
+    /// the offer or lease ran out without being renewed
+    Expired,
+    /// the client released the address
+    Released,
+    /// on probation after DECLINE, or found in use by a ping
+    Declined,
+    /// on probation because it's assigned statically outside of dora
+    Reserved,
+    /// a probation ended & the address is free again
+    Reclaimed,
+}
+
+/// a state name that isn't a [`LeaseState`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("unknown lease state {0:?}")]
+pub struct UnknownState(pub String);
+
+impl LeaseState {
+    pub const ALL: [LeaseState; 7] = [
+        LeaseState::Offered,
+        LeaseState::Active,
+        LeaseState::Expired,
+        LeaseState::Released,
+        LeaseState::Declined,
+        LeaseState::Reserved,
+        LeaseState::Reclaimed,
+    ];
+
+    /// state name, as stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeaseState::Offered => "offered",
+            LeaseState::Active => "active",
+            LeaseState::Expired => "expired",
+            LeaseState::Released => "released",
+            LeaseState::Declined => "declined",
+            LeaseState::Reserved => "reserved",
+            LeaseState::Reclaimed => "reclaimed",
+        }
+    }
+
+    /// can an entry in `from` be moved to `to`? `None` is an address with no entry
+    pub fn can_transition(from: Option<LeaseState>, to: LeaseState) -> bool {
+        use LeaseState::*;
+        matches!(
+            (from, to),
+            (None, Offered | Active | Declined | Reserved)
+                | (
+                    Some(Offered | Active),
+                    Offered | Active | Released | Declined | Expired
+                )
+                | (Some(Declined | Reserved), Declined | Reserved | Reclaimed)
+                | (
+                    Some(Expired | Released | Reclaimed),
+                    Offered | Active | Declined | Reserved
+                )
+        )
+    }
+
+    /// the state of an entry in `self` that expires at `expires_at`, as of `now`
+    pub fn at(self, expires_at: SystemTime, now: SystemTime) -> LeaseState {
+        match self {
+            _ if expires_at > now => self,
+            LeaseState::Offered | LeaseState::Active => LeaseState::Expired,
+            LeaseState::Declined | LeaseState::Reserved => LeaseState::Reclaimed,
+            _ => self,
+        }
+    }
+
+    /// is the address free to be given out?
+    pub fn is_free(&self) -> bool {
+        matches!(
+            self,
+            LeaseState::Expired | LeaseState::Released | LeaseState::Reclaimed
+        )
+    }
+}
+
+impl fmt::Display for LeaseState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for LeaseState {
+    type Err = UnknownState;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LeaseState::ALL
+            .into_iter()
+            .find(|state| state.as_str() == s)
+            .ok_or_else(|| UnknownState(s.to_owned()))
+    }
+}
+
+/// the `IpState` an entry in this state is listed with
+impl From<LeaseState> for IpState {
+    fn from(state: LeaseState) -> Self {
+        match state {
+            LeaseState::Active => IpState::Lease,
+            LeaseState::Declined | LeaseState::Reserved => IpState::Probate,
+            _ => IpState::Clear,
+        }
+    }
+}
+
+/// the `leased` & `probation` columns written with this state
+impl From<LeaseState> for (bool, bool) {
+    fn from(state: LeaseState) -> Self {
+        IpState::from(state).into()
+    }
+}
+
+/// an entry of the transition log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseTransition {
+    /// position in the log, increases with every transition
+    pub id: u64,
+    pub ip: IpAddr,
+    /// client the entry is for after the transition
+    pub client_id: Option<Vec<u8>>,
+    /// `None` for a new entry
+    pub from: Option<LeaseState>,
+    pub to: LeaseState,
+    pub at: SystemTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use LeaseState::*;
+
+    #[test]
+    fn test_can_transition() {
+        // the table in the module docs
+        let allowed: [(Option<LeaseState>, &[LeaseState]); 8] = [
+            (None, &[Offered, Active, Declined, Reserved]),
+            (
+                Some(Offered),
+                &[Offered, Active, Released, Declined, Expired],
+            ),
+            (
+                Some(Active),
+                &[Offered, Active, Released, Declined, Expired],
+            ),
+            (Some(Declined), &[Declined, Reserved, Reclaimed]),
+            (Some(Reserved), &[Declined, Reserved, Reclaimed]),
+            (Some(Expired), &[Offered, Active, Declined, Reserved]),
+            (Some(Released), &[Offered, Active, Declined, Reserved]),
+            (Some(Reclaimed), &[Offered, Active, Declined, Reserved]),
+        ];
+        for (from, to) in allowed {
+            for state in LeaseState::ALL {
+                assert_eq!(
+                    LeaseState::can_transition(from, state),
+                    to.contains(&state),
+                    "{from:?} -> {state}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_at() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let later = now + Duration::from_secs(1);
+        for state in LeaseState::ALL {
+            assert_eq!(state.at(later, now), state, "{state} before expiry");
+        }
+        for (state, expired) in [
+            (Offered, Expired),
+            (Active, Expired),
+            (Declined, Reclaimed),
+            (Reserved, Reclaimed),
+            (Expired, Expired),
+            (Released, Released),
+            (Reclaimed, Reclaimed),
+        ] {
+            // an entry expiring now has expired
+            assert_eq!(state.at(now, now), expired, "{state} at expiry");
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        for state in LeaseState::ALL {
+            assert_eq!(state.as_str().parse(), Ok(state));
+        }
+        assert_eq!(
+            "leased".parse::<LeaseState>(),
+            Err(UnknownState("leased".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! pool utilization samples
+//!
+//! [`IpManager::watch_pool_stats`] periodically counts the addresses in use in
+//! each range & stores a [`PoolSample`], so utilization trends can be served
+//! without an external time series database.
+//!
+//! [`IpManager::watch_retention`] compacts the database on a schedule: samples
+//! past their retention are rolled up into one [`DailyPoolStats`] per range per
+//! day, and rollups & hostname conflicts past theirs are deleted. SQLite reuses
+//! the freed pages, so the file stops growing once retention is reached.
+//!
+//! [`IpManager::watch_network_counts`] saves the cumulative per network message
+//! counts, which [`IpManager::restore_network_counts`] adds back to the metrics
+//! on start so dashboards don't drop to zero when dora restarts.
+use std::{
+    net::IpAddr,
+    ops::RangeInclusive,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use config::DhcpConfig;
+use dora_core::metrics;
+use tracing::{debug, error};
+
+use crate::{IpManager, Storage};
+
+/// how often [`IpManager::watch_retention`] compacts the database
+pub const COMPACT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const DAY: u64 = 24 * 60 * 60;
+
+/// addresses in a range by state, only un-expired entries are counted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RangeCounts {
+    pub leased: u64,
+    /// offered & waiting for REQUEST
+    pub reserved: u64,
+    pub probated: u64,
+}
+
+/// utilization of a range at `sampled_at`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolSample {
+    /// subnet of the network the range belongs to
+    pub network: IpAddr,
+    pub range: RangeInclusive<IpAddr>,
+    /// allocatable addresses in the range, exclusions are not counted
+    pub total: u64,
+    pub counts: RangeCounts,
+    pub sampled_at: SystemTime,
+}
+
+/// the utilization of a range over a UTC day, rolled up from its samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyPoolStats {
+    /// subnet of the network the range belongs to
+    pub network: IpAddr,
+    pub range: RangeInclusive<IpAddr>,
+    /// start of the day
+    pub day: SystemTime,
+    /// number of samples rolled up
+    pub samples: u64,
+    /// largest `total` seen that day
+    pub total: u64,
+    pub leased_avg: f64,
+    pub leased_max: u64,
+    /// leased, reserved & probated addresses
+    pub used_avg: f64,
+    pub used_max: u64,
+}
+
+/// a cumulative count of messages of `msg_type` on a network, ex. `ack`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkCount {
+    /// subnet of the network
+    pub network: IpAddr,
+    pub msg_type: String,
+    pub count: u64,
+}
+
+/// how long compacted data is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// pool samples, older samples are rolled up. Rollups are for whole days, a
+    /// sample is kept until the end of the day it falls out of retention
+    pub samples: Duration,
+    /// daily pool rollups
+    pub rollups: Duration,
+    /// hostname conflicts & lease transitions
+    pub audit: Duration,
+}
+
+impl<T> IpManager<T>
+where
+    T: Storage,
+{
+    /// sample the utilization of every v4 range in `cfg` each `interval`.
+    /// Never returns
+    pub async fn watch_pool_stats(self, cfg: Arc<DhcpConfig>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.sample_pools(&cfg).await {
+                error!(?err, "failed to sample pool utilization");
+            }
+        }
+    }
+
+    /// compact the database every [`COMPACT_INTERVAL`]. Never returns
+    pub async fn watch_retention(self, retention: Retention) {
+        let mut ticker = tokio::time::interval(COMPACT_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.compact(retention).await {
+                error!(?err, "failed to compact lease database");
+            }
+        }
+    }
+
+    /// save the message counts of `instance` each `interval`, `instance` is empty
+    /// when not running with `instances`. Never returns
+    pub async fn watch_network_counts(self, instance: String, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        // the first tick completes immediately, there's nothing new to save
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(err) = self.save_network_counts(&instance).await {
+                error!(?err, "failed to save network message counts");
+            }
+        }
+    }
+
+    /// save the current message counts of `instance`
+    pub async fn save_network_counts(&self, instance: &str) -> Result<(), T::Error> {
+        let counts = metrics::network_counts(instance)
+            .into_iter()
+            .filter_map(|(network, msg_type, count)| {
+                Some(NetworkCount {
+                    network: network.parse().ok()?,
+                    msg_type,
+                    count,
+                })
+            })
+            .collect::<Vec<_>>();
+        self.store.save_network_counts(&counts).await?;
+        debug!(counts = counts.len(), "saved network message counts");
+        Ok(())
+    }
+
+    /// add the saved message counts to the metrics of `instance`, should be
+    /// called once before the server starts
+    pub async fn restore_network_counts(&self, instance: &str) -> Result<(), T::Error> {
+        let counts = self.store.network_counts().await?;
+        for count in &counts {
+            metrics::NETWORK_COUNT_VEC
+                .with_label_values(&[instance, &count.network.to_string(), &count.msg_type])
+                .inc_by(count.count);
+        }
+        debug!(counts = counts.len(), "restored network message counts");
+        Ok(())
+    }
+
+    /// roll up pool samples & delete data older than `retention`
+    pub async fn compact(&self, retention: Retention) -> Result<(), T::Error> {
+        let now = self.now();
+        let cutoff = |keep: Duration| now.checked_sub(keep).unwrap_or(SystemTime::UNIX_EPOCH);
+        let rolled_up = self
+            .store
+            .rollup_pool_samples(day_start(cutoff(retention.samples)))
+            .await?;
+        let rollups = self
+            .store
+            .delete_pool_rollups(cutoff(retention.rollups))
+            .await?;
+        let conflicts = self
+            .store
+            .delete_hostname_conflicts(cutoff(retention.audit))
+            .await?;
+        let transitions = self
+            .store
+            .delete_lease_transitions(cutoff(retention.audit))
+            .await?;
+        debug!(
+            rolled_up,
+            rollups, conflicts, transitions, "compacted lease database"
+        );
+        Ok(())
+    }
+
+    async fn sample_pools(&self, cfg: &DhcpConfig) -> Result<(), T::Error> {
+        let now = self.now();
+        let mut samples = Vec::new();
+        for network in cfg.v4().networks().values() {
+            for range in network.ranges() {
+                let range_ips = IpAddr::from(range.start())..=IpAddr::from(range.end());
+                samples.push(PoolSample {
+                    network: network.subnet().into(),
+                    counts: self.store.range_counts(range_ips.clone()).await?,
+                    range: range_ips,
+                    total: range.total_addrs() as u64,
+                    sampled_at: now,
+                });
+            }
+        }
+        self.store.insert_pool_samples(&samples).await?;
+        debug!(samples = samples.len(), "sampled pool utilization");
+        Ok(())
+    }
+}
+
+/// midnight UTC at the start of the day `time` falls in
+fn day_start(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    SystemTime::UNIX_EPOCH + Duration::from_secs(secs - secs % DAY)
+}
@@ -0,0 +1,22 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use ip_manager::{conformance, sqlite::SqliteDb, FakeClock};
+
+#[tokio::test]
+async fn test_sqlite_conformance() -> Result<(), sqlx::Error> {
+    conformance::run(SqliteDb::memory).await
+}
+
+#[tokio::test]
+async fn test_sqlite_fake_clock_conformance() -> Result<(), sqlx::Error> {
+    // years behind the system clock, every expiry is measured against it
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    conformance::run(|| async move {
+        let clock = Arc::new(FakeClock::frozen(start));
+        Ok(SqliteDb::memory().await?.with_clock(clock))
+    })
+    .await
+}
@@ -0,0 +1,439 @@
+//! # ipam-sync
+//!
+//! Keep addresses that an IPAM records as statically assigned from being handed
+//! out. IPAMs are enabled with cargo features: `netbox` and `phpipam`. The IPAM
+//! is chosen from the url scheme, `netbox+https://netbox.example.com` or
+//! `phpipam+https://ipam.example.com/api/<app id>`.
+//!
+//! Every sync, the statically assigned v4 addresses that fall in one of the
+//! configured ranges are put on probation for [`HOLD_SYNCS`] sync intervals, so
+//! an address stays excluded while the IPAM is briefly unreachable, and is
+//! released soon after it's unassigned in the IPAM. Addresses already leased to
+//! a client are left alone & logged, the collision has to be fixed by hand.
+//!
+//! Leases can be exported the other way with [`run_export`]. Leases that are new
+//! or changed since the last export are pushed to the IPAM as DHCP addresses
+//! with their hostname & MAC, in batches with a pause between them, and the
+//! records of leases that ended are deleted. Exported records are never read
+//! back as static addresses. Only changes seen by a running dora are exported,
+//! a record whose lease ended while dora was stopped is left in the IPAM.
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    unreachable_pub,
+    non_snake_case,
+    non_upper_case_globals
+)]
+#![allow(clippy::cognitive_complexity)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use config::DhcpConfig;
+use ip_manager::{ActiveLease, IpManager, IpState, Storage};
+use ipnet::Ipv4Net;
+use tracing::{debug, error, info};
+
+#[cfg(feature = "netbox")]
+mod netbox;
+#[cfg(feature = "phpipam")]
+mod phpipam;
+
+/// sync intervals an address is held for after it was last seen in the IPAM
+pub const HOLD_SYNCS: u32 = 3;
+
+/// a lease as it's recorded in an IPAM
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedLease {
+    /// leased address
+    pub ip: Ipv4Addr,
+    /// the network the lease is in, with its prefix length
+    pub network: Ipv4Net,
+    /// hostname registered by the client
+    pub hostname: Option<String>,
+    /// MAC address of the client, if its client id contains one
+    pub mac: Option<String>,
+}
+
+/// a connection to an IPAM
+#[async_trait]
+pub trait Ipam: Send + Sync + std::fmt::Debug {
+    /// every v4 address recorded as statically assigned
+    async fn static_addrs(&self) -> Result<Vec<Ipv4Addr>>;
+    /// create or update the DHCP address record of each lease
+    async fn export(&self, leases: &[ExportedLease]) -> Result<()>;
+    /// delete the DHCP address records of `ips`, records of other kinds are
+    /// left alone
+    async fn remove(&self, ips: &[Ipv4Addr]) -> Result<()>;
+}
+
+/// create a client for the IPAM at `url`, authenticating with `token`
+#[cfg_attr(
+    not(any(feature = "netbox", feature = "phpipam")),
+    allow(unused_variables)
+)]
+pub fn connect(url: &str, token: Option<&str>) -> Result<Arc<dyn Ipam>> {
+    let (scheme, base) = url.split_once('+').ok_or_else(|| {
+        anyhow::anyhow!("ipam url must be netbox+https://.. or phpipam+https://..")
+    })?;
+    match scheme {
+        #[cfg(feature = "netbox")]
+        "netbox" => Ok(Arc::new(netbox::NetBox::new(base, token)?)),
+        #[cfg(feature = "phpipam")]
+        "phpipam" => Ok(Arc::new(phpipam::PhpIpam::new(base, token)?)),
+        #[cfg(not(feature = "netbox"))]
+        "netbox" => {
+            anyhow::bail!("dora was built without the `netbox` feature, can't sync from {base}")
+        }
+        #[cfg(not(feature = "phpipam"))]
+        "phpipam" => {
+            anyhow::bail!("dora was built without the `phpipam` feature, can't sync from {base}")
+        }
+        _ => anyhow::bail!("unsupported ipam {scheme}"),
+    }
+}
+
+/// sync from `ipam` every `interval`. Sync errors are logged & the
+/// addresses probated earlier are kept until their hold runs out. Never returns
+pub async fn run<T: Storage>(
+    ipam: Arc<dyn Ipam>,
+    ip_mgr: IpManager<T>,
+    cfg: Arc<DhcpConfig>,
+    interval: Duration,
+) {
+    info!(?ipam, ?interval, "syncing static addresses from ipam");
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match sync(&*ipam, &ip_mgr, &cfg, interval * HOLD_SYNCS).await {
+            Ok(probated) => debug!(probated, "synced static addresses from ipam"),
+            Err(err) => error!(?err, "failed to sync static addresses from ipam"),
+        }
+    }
+}
+
+/// probate the static addresses in `ipam` for `hold`, returning the number
+/// that weren't on probation already
+pub async fn sync<T: Storage>(
+    ipam: &dyn Ipam,
+    ip_mgr: &IpManager<T>,
+    cfg: &DhcpConfig,
+    hold: Duration,
+) -> Result<usize> {
+    let addrs = ipam.static_addrs().await?;
+    let until = ip_mgr.now() + hold;
+    let mut probated = 0;
+    for (ip, network) in in_ranges(cfg, addrs) {
+        match ip_mgr
+            .probate_static(IpAddr::V4(ip), IpAddr::V4(network), until)
+            .await
+        {
+            Ok(true) => probated += 1,
+            Ok(false) => {}
+            Err(err) => error!(?err, ?ip, "failed to probate static address"),
+        }
+    }
+    Ok(probated)
+}
+
+/// how leases are exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportLimits {
+    /// time between exports
+    pub interval: Duration,
+    /// leases per batch
+    pub batch: usize,
+    /// pause between batches
+    pub delay: Duration,
+}
+
+/// export the leases in `store` to `ipam` every `limits.interval`. Export
+/// errors are logged & the failed batches retried on the next export. Never
+/// returns
+pub async fn run_export<T: Storage>(
+    ipam: Arc<dyn Ipam>,
+    store: T,
+    cfg: Arc<DhcpConfig>,
+    limits: ExportLimits,
+) {
+    info!(?ipam, ?limits, "exporting leases to ipam");
+    let mut ticker = tokio::time::interval(limits.interval);
+    // what the ipam holds for each address exported so far
+    let mut exported = HashMap::new();
+    loop {
+        ticker.tick().await;
+        let leases = match store.active_leases().await {
+            Ok(leases) => exported_leases(&cfg, leases),
+            Err(err) => {
+                error!(?err, "failed to list leases for ipam export");
+                continue;
+            }
+        };
+        export(&*ipam, &mut exported, leases, limits).await;
+    }
+}
+
+/// push the changes between `exported` & `leases` to `ipam`, updating
+/// `exported` with the batches that succeeded
+async fn export(
+    ipam: &dyn Ipam,
+    exported: &mut HashMap<Ipv4Addr, ExportedLease>,
+    leases: HashMap<Ipv4Addr, ExportedLease>,
+    limits: ExportLimits,
+) {
+    let changed = leases
+        .values()
+        .filter(|lease| exported.get(&lease.ip) != Some(lease))
+        .cloned()
+        .collect::<Vec<_>>();
+    let ended = exported
+        .keys()
+        .filter(|ip| !leases.contains_key(ip))
+        .copied()
+        .collect::<Vec<_>>();
+    let batch = limits.batch.max(1);
+    // no pause before the first request
+    let mut sent = 0;
+    for leases in changed.chunks(batch) {
+        if sent > 0 {
+            tokio::time::sleep(limits.delay).await;
+        }
+        sent += 1;
+        match ipam.export(leases).await {
+            Ok(()) => {
+                for lease in leases {
+                    exported.insert(lease.ip, lease.clone());
+                }
+            }
+            Err(err) => error!(?err, leases = leases.len(), "failed to export leases"),
+        }
+    }
+    for ips in ended.chunks(batch) {
+        if sent > 0 {
+            tokio::time::sleep(limits.delay).await;
+        }
+        sent += 1;
+        match ipam.remove(ips).await {
+            Ok(()) => {
+                for ip in ips {
+                    exported.remove(ip);
+                }
+            }
+            Err(err) => error!(?err, leases = ips.len(), "failed to remove ended leases"),
+        }
+    }
+    debug!(
+        changed = changed.len(),
+        ended = ended.len(),
+        "exported leases to ipam"
+    );
+}
+
+/// the v4 leases (not offers or probated addresses) in a configured network
+fn exported_leases(cfg: &DhcpConfig, leases: Vec<ActiveLease>) -> HashMap<Ipv4Addr, ExportedLease> {
+    leases
+        .into_iter()
+        .filter(|lease| lease.state == IpState::Lease)
+        .filter_map(|lease| {
+            let IpAddr::V4(ip) = lease.ip else {
+                return None;
+            };
+            let network = *cfg
+                .v4()
+                .networks()
+                .keys()
+                .find(|subnet| subnet.contains(&ip))?;
+            let mac = lease.id.as_deref().and_then(oui::client_mac).map(|mac| {
+                mac.iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect::<Vec<_>>()
+                    .join(":")
+            });
+            Some((
+                ip,
+                ExportedLease {
+                    ip,
+                    network,
+                    hostname: lease.hostname,
+                    mac,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// the addresses in a configured v4 range, with the subnet of their network
+fn in_ranges(cfg: &DhcpConfig, addrs: Vec<Ipv4Addr>) -> Vec<(Ipv4Addr, Ipv4Addr)> {
+    addrs
+        .into_iter()
+        .filter_map(|ip| {
+            cfg.v4()
+                .networks()
+                .values()
+                .find(|net| net.ranges().iter().any(|range| range.contains(&ip)))
+                .map(|net| (ip, net.subnet()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use ip_manager::LeaseState;
+
+    use super::*;
+
+    static CONFIG: &str = "
+networks:
+    192.168.0.0/24:
+        ranges:
+            -
+                start: 192.168.0.100
+                end: 192.168.0.200
+                config:
+                    lease_time:
+                        default: 3600
+                except:
+                    - 192.168.0.150
+";
+
+    #[test]
+    fn test_in_ranges() {
+        let cfg = DhcpConfig::parse_str(CONFIG).unwrap();
+        let addrs = [
+            "192.168.0.10",
+            "192.168.0.120",
+            "192.168.0.150",
+            "10.0.0.120",
+        ]
+        .into_iter()
+        .map(|ip| ip.parse().unwrap())
+        .collect();
+        // outside of the ranges, or excluded already
+        assert_eq!(
+            in_ranges(&cfg, addrs),
+            [(
+                Ipv4Addr::new(192, 168, 0, 120),
+                Ipv4Addr::new(192, 168, 0, 0)
+            )]
+        );
+    }
+
+    fn lease(ip: [u8; 4], state: IpState, id: &[u8]) -> ActiveLease {
+        ActiveLease {
+            ip: IpAddr::V4(ip.into()),
+            id: Some(id.to_vec()),
+            network: IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)),
+            state,
+            lease_state: match state {
+                IpState::Lease => LeaseState::Active,
+                IpState::Probate => LeaseState::Declined,
+                IpState::Clear => LeaseState::Offered,
+            },
+            expires_at: SystemTime::now(),
+            hostname: None,
+            relay_info: None,
+            fingerprint: None,
+            notes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_exported_leases() {
+        let cfg = DhcpConfig::parse_str(CONFIG).unwrap();
+        let leases = exported_leases(
+            &cfg,
+            vec![
+                lease([192, 168, 0, 120], IpState::Lease, &[1, 0, 1, 2, 3, 4, 5]),
+                lease([192, 168, 0, 121], IpState::Lease, b"not a mac"),
+                lease([192, 168, 0, 122], IpState::Clear, &[1, 0, 1, 2, 3, 4, 6]),
+                lease([10, 0, 0, 120], IpState::Lease, &[1, 0, 1, 2, 3, 4, 7]),
+            ],
+        );
+        assert_eq!(leases.len(), 2);
+        let exported = &leases[&Ipv4Addr::new(192, 168, 0, 120)];
+        assert_eq!(exported.network, "192.168.0.0/24".parse().unwrap());
+        assert_eq!(exported.mac.as_deref(), Some("00:01:02:03:04:05"));
+        assert_eq!(leases[&Ipv4Addr::new(192, 168, 0, 121)].mac, None);
+    }
+
+    /// records the requests it was sent
+    #[derive(Debug, Default)]
+    struct Recorder {
+        exported: std::sync::Mutex<Vec<Vec<Ipv4Addr>>>,
+        removed: std::sync::Mutex<Vec<Vec<Ipv4Addr>>>,
+    }
+
+    #[async_trait]
+    impl Ipam for Recorder {
+        async fn static_addrs(&self) -> Result<Vec<Ipv4Addr>> {
+            Ok(Vec::new())
+        }
+
+        async fn export(&self, leases: &[ExportedLease]) -> Result<()> {
+            let mut ips = leases.iter().map(|lease| lease.ip).collect::<Vec<_>>();
+            ips.sort();
+            self.exported.lock().unwrap().push(ips);
+            Ok(())
+        }
+
+        async fn remove(&self, ips: &[Ipv4Addr]) -> Result<()> {
+            self.removed.lock().unwrap().push(ips.to_vec());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export() {
+        let cfg = DhcpConfig::parse_str(CONFIG).unwrap();
+        let limits = ExportLimits {
+            interval: Duration::from_secs(1),
+            batch: 3,
+            delay: Duration::ZERO,
+        };
+        let ipam = Recorder::default();
+        let mut exported = HashMap::new();
+        let leases = (100..104)
+            .map(|i| lease([192, 168, 0, i], IpState::Lease, &[1, 0, 1, 2, 3, 4, i]))
+            .collect::<Vec<_>>();
+        export(
+            &ipam,
+            &mut exported,
+            exported_leases(&cfg, leases.clone()),
+            limits,
+        )
+        .await;
+        let batches = ipam.exported.lock().unwrap().drain(..).collect::<Vec<_>>();
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), [3, 1]);
+        assert_eq!(exported.len(), 4);
+
+        // only the changed lease is sent again, the ended one is removed
+        let mut leases = leases[1..].to_vec();
+        leases[0].hostname = Some("printer".to_owned());
+        export(&ipam, &mut exported, exported_leases(&cfg, leases), limits).await;
+        assert_eq!(
+            *ipam.exported.lock().unwrap(),
+            [[Ipv4Addr::new(192, 168, 0, 101)]]
+        );
+        assert_eq!(
+            *ipam.removed.lock().unwrap(),
+            [[Ipv4Addr::new(192, 168, 0, 100)]]
+        );
+        assert_eq!(exported.len(), 3);
+    }
+
+    #[test]
+    fn test_connect() {
+        assert!(connect("https://netbox.example.com", None).is_err());
+        assert!(connect("infoblox+https://ipam.example.com", None).is_err());
+    }
+}
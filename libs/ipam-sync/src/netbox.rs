@@ -0,0 +1,249 @@
+//! NetBox client, reads & writes the addresses in `/api/ipam/ip-addresses/`
+//!
+//! Leases are exported as addresses with the `dhcp` status, which are skipped
+//! when reading static addresses. Only `dhcp` addresses are updated or deleted.
+use std::{collections::HashMap, net::Ipv4Addr};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{header, Client, Method};
+use serde_json::{json, Value};
+
+use crate::{ExportedLease, Ipam};
+
+/// addresses fetched per request
+const PAGE_SIZE: u32 = 1000;
+/// statuses of statically assigned addresses, `dhcp` & `slaac` addresses are
+/// handed out by a server & `deprecated` ones are no longer in use
+const STATIC_STATUSES: [&str; 2] = ["active", "reserved"];
+/// status of exported leases
+const LEASE_STATUS: &str = "dhcp";
+
+#[derive(Debug)]
+pub(crate) struct NetBox {
+    client: Client,
+    base: String,
+}
+
+impl NetBox {
+    pub(crate) fn new(base: &str, token: Option<&str>) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        if let Some(token) = token {
+            let mut value = header::HeaderValue::from_str(&format!("Token {token}"))
+                .context("invalid netbox token")?;
+            value.set_sensitive(true);
+            headers.insert(header::AUTHORIZATION, value);
+        }
+        Ok(Self {
+            client: Client::builder().default_headers(headers).build()?,
+            base: base.trim_end_matches('/').to_owned(),
+        })
+    }
+}
+
+impl NetBox {
+    fn addresses_url(&self) -> String {
+        format!("{}/api/ipam/ip-addresses/", self.base)
+    }
+
+    /// ids of the `dhcp` addresses of `ips`
+    async fn lease_ids(&self, ips: &[Ipv4Addr]) -> Result<HashMap<Ipv4Addr, u64>> {
+        let filter = ips
+            .iter()
+            .map(|ip| format!("&address={ip}"))
+            .collect::<String>();
+        let mut next = Some(format!(
+            "{}?family=4&limit={PAGE_SIZE}&status={LEASE_STATUS}{filter}",
+            self.addresses_url()
+        ));
+        let mut ids = HashMap::new();
+        while let Some(url) = next {
+            let page = self
+                .client
+                .get(&url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await?;
+            next = parse_ids(&page, &mut ids)
+                .with_context(|| format!("unexpected response from {url}"))?;
+        }
+        Ok(ids)
+    }
+
+    /// send a bulk request of `records` to the addresses endpoint
+    async fn bulk(&self, method: Method, records: Vec<Value>) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        self.client
+            .request(method, self.addresses_url())
+            .json(&records)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Ipam for NetBox {
+    async fn static_addrs(&self) -> Result<Vec<Ipv4Addr>> {
+        let status = STATIC_STATUSES
+            .map(|status| format!("&status={status}"))
+            .concat();
+        let mut next = Some(format!(
+            "{}/api/ipam/ip-addresses/?family=4&limit={PAGE_SIZE}{status}",
+            self.base
+        ));
+        let mut addrs = Vec::new();
+        while let Some(url) = next {
+            let page = self
+                .client
+                .get(&url)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Value>()
+                .await?;
+            next = parse_page(&page, &mut addrs)
+                .with_context(|| format!("unexpected response from {url}"))?;
+        }
+        Ok(addrs)
+    }
+
+    async fn export(&self, leases: &[ExportedLease]) -> Result<()> {
+        let ips = leases.iter().map(|lease| lease.ip).collect::<Vec<_>>();
+        let ids = self.lease_ids(&ips).await?;
+        let (mut create, mut update) = (Vec::new(), Vec::new());
+        for lease in leases {
+            let mut record = record(lease);
+            match ids.get(&lease.ip) {
+                Some(id) => {
+                    record["id"] = json!(id);
+                    update.push(record);
+                }
+                None => create.push(record),
+            }
+        }
+        self.bulk(Method::POST, create).await?;
+        self.bulk(Method::PATCH, update).await
+    }
+
+    async fn remove(&self, ips: &[Ipv4Addr]) -> Result<()> {
+        let ids = self.lease_ids(ips).await?;
+        self.bulk(
+            Method::DELETE,
+            ids.values().map(|id| json!({ "id": id })).collect(),
+        )
+        .await
+    }
+}
+
+/// the address record of a lease
+fn record(lease: &ExportedLease) -> Value {
+    let description = match &lease.mac {
+        Some(mac) => format!("dora lease, mac {mac}"),
+        None => "dora lease".to_owned(),
+    };
+    json!({
+        "address": format!("{}/{}", lease.ip, lease.network.prefix_len()),
+        "status": LEASE_STATUS,
+        "dns_name": lease.hostname.as_deref().unwrap_or_default(),
+        "description": description,
+    })
+}
+
+/// add the ids of the addresses in a page of results to `ids`, returns the url
+/// of the next page
+fn parse_ids(page: &Value, ids: &mut HashMap<Ipv4Addr, u64>) -> Result<Option<String>> {
+    for result in page["results"].as_array().context("missing results")? {
+        let address = result["address"].as_str().context("missing address")?;
+        let id = result["id"].as_u64().context("missing id")?;
+        let ip = address.split('/').next().unwrap_or(address);
+        if let Ok(ip) = ip.parse() {
+            ids.insert(ip, id);
+        }
+    }
+    Ok(page["next"].as_str().map(str::to_owned))
+}
+
+/// add the addresses in a page of results to `addrs`, returns the url of the
+/// next page
+fn parse_page(page: &Value, addrs: &mut Vec<Ipv4Addr>) -> Result<Option<String>> {
+    for result in page["results"].as_array().context("missing results")? {
+        let address = result["address"].as_str().context("missing address")?;
+        // in CIDR notation, ex. 192.168.0.10/24
+        let ip = address.split('/').next().unwrap_or(address);
+        if let Ok(ip) = ip.parse() {
+            addrs.push(ip);
+        }
+    }
+    Ok(page["next"].as_str().map(str::to_owned))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_page() {
+        let next = "https://netbox.example.com/api/ipam/ip-addresses/?limit=1000&offset=1000";
+        let page = serde_json::json!({
+            "count": 1002,
+            "next": next,
+            "results": [
+                { "address": "192.168.0.10/24", "status": { "value": "active" } },
+                { "address": "2001:db8::10/64", "status": { "value": "active" } },
+            ],
+        });
+        let mut addrs = Vec::new();
+        assert_eq!(
+            parse_page(&page, &mut addrs).unwrap().as_deref(),
+            Some(next)
+        );
+        assert_eq!(addrs, [Ipv4Addr::new(192, 168, 0, 10)]);
+
+        let last = serde_json::json!({ "count": 1002, "next": null, "results": [] });
+        assert_eq!(parse_page(&last, &mut addrs).unwrap(), None);
+        let denied = serde_json::json!({ "detail": "Invalid token" });
+        assert!(parse_page(&denied, &mut addrs).is_err());
+    }
+
+    #[test]
+    fn test_record() {
+        let mut lease = ExportedLease {
+            ip: Ipv4Addr::new(192, 168, 0, 120),
+            network: "192.168.0.0/24".parse().unwrap(),
+            hostname: Some("printer".to_owned()),
+            mac: Some("00:01:02:03:04:05".to_owned()),
+        };
+        assert_eq!(
+            record(&lease),
+            json!({
+                "address": "192.168.0.120/24",
+                "status": "dhcp",
+                "dns_name": "printer",
+                "description": "dora lease, mac 00:01:02:03:04:05",
+            })
+        );
+        // a hostname that was dropped is cleared
+        lease.hostname = None;
+        lease.mac = None;
+        assert_eq!(record(&lease)["dns_name"], "");
+        assert_eq!(record(&lease)["description"], "dora lease");
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        let page = serde_json::json!({
+            "count": 1,
+            "next": null,
+            "results": [{ "id": 42, "address": "192.168.0.120/24", "status": { "value": "dhcp" } }],
+        });
+        let mut ids = HashMap::new();
+        assert_eq!(parse_ids(&page, &mut ids).unwrap(), None);
+        assert_eq!(ids, HashMap::from([(Ipv4Addr::new(192, 168, 0, 120), 42)]));
+    }
+}
@@ -0,0 +1,248 @@
+//! phpIPAM client, reads & writes the addresses in `/addresses/` of an API app
+//!
+//! Leases are exported as addresses tagged `dhcp` in the subnet holding them,
+//! which has to exist in phpIPAM already. phpIPAM has no bulk requests, each
+//! lease is looked up & written on its own. Only `dhcp` addresses are updated
+//! or deleted.
+use std::{collections::HashMap, net::Ipv4Addr};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ipnet::Ipv4Net;
+use reqwest::{header, Client, Method};
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::{ExportedLease, Ipam};
+
+/// tags of statically assigned addresses, `used` & `reserved`. `offline` (1) &
+/// `dhcp` (4) addresses are skipped
+const STATIC_TAGS: [u64; 2] = [2, 3];
+/// tag of exported leases
+const LEASE_TAG: u64 = 4;
+
+#[derive(Debug)]
+pub(crate) struct PhpIpam {
+    client: Client,
+    base: String,
+}
+
+impl PhpIpam {
+    pub(crate) fn new(base: &str, token: Option<&str>) -> Result<Self> {
+        let mut headers = header::HeaderMap::new();
+        if let Some(token) = token {
+            let mut value =
+                header::HeaderValue::from_str(token).context("invalid phpipam token")?;
+            value.set_sensitive(true);
+            headers.insert("token", value);
+        }
+        Ok(Self {
+            client: Client::builder().default_headers(headers).build()?,
+            base: base.trim_end_matches('/').to_owned(),
+        })
+    }
+}
+
+impl PhpIpam {
+    /// send a request to `path` under the app, returns the `data` of the
+    /// response
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value> {
+        let url = format!("{}/{path}", self.base);
+        let mut req = self.client.request(method, &url);
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+        // the status is repeated in the body, which is read for errors too
+        let body = req.send().await?.json::<Value>().await?;
+        data(body).with_context(|| format!("unexpected response from {url}"))
+    }
+
+    /// the addresses recorded for `ip`
+    async fn search(&self, ip: Ipv4Addr) -> Result<Vec<Value>> {
+        let found = self
+            .request(Method::GET, &format!("addresses/search/{ip}/"), None)
+            .await?;
+        Ok(found.as_array().cloned().unwrap_or_default())
+    }
+
+    /// id of the subnet `net`
+    async fn subnet_id(&self, net: Ipv4Net) -> Result<u64> {
+        let path = format!("subnets/cidr/{}/{}/", net.network(), net.prefix_len());
+        let found = self.request(Method::GET, &path, None).await?;
+        found
+            .as_array()
+            .and_then(|subnets| subnets.first())
+            .and_then(|subnet| number(&subnet["id"]))
+            .with_context(|| format!("no subnet {net} in phpipam"))
+    }
+}
+
+#[async_trait]
+impl Ipam for PhpIpam {
+    async fn static_addrs(&self) -> Result<Vec<Ipv4Addr>> {
+        let addrs = self.request(Method::GET, "addresses/", None).await?;
+        parse_addrs(&addrs)
+    }
+
+    async fn export(&self, leases: &[ExportedLease]) -> Result<()> {
+        let mut subnets = HashMap::new();
+        for lease in leases {
+            let found = self.search(lease.ip).await?;
+            if let Some(addr) = found.iter().find(|addr| tag(addr) == Some(LEASE_TAG)) {
+                let id = number(&addr["id"]).context("address without id")?;
+                let path = format!("addresses/{id}/");
+                self.request(Method::PATCH, &path, Some(record(lease, None)))
+                    .await?;
+            } else if !found.is_empty() {
+                warn!(ip = ?lease.ip, "leased address is static in phpipam, not exporting");
+            } else {
+                let subnet = match subnets.get(&lease.network) {
+                    Some(&subnet) => subnet,
+                    None => {
+                        let subnet = self.subnet_id(lease.network).await?;
+                        subnets.insert(lease.network, subnet);
+                        subnet
+                    }
+                };
+                self.request(
+                    Method::POST,
+                    "addresses/",
+                    Some(record(lease, Some(subnet))),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn remove(&self, ips: &[Ipv4Addr]) -> Result<()> {
+        for &ip in ips {
+            for addr in self.search(ip).await? {
+                if tag(&addr) != Some(LEASE_TAG) {
+                    continue;
+                }
+                let id = number(&addr["id"]).context("address without id")?;
+                self.request(Method::DELETE, &format!("addresses/{id}/"), None)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the `data` of a response, `null` if phpIPAM found nothing
+fn data(body: Value) -> Result<Value> {
+    if body["success"] != true {
+        // a search without results is a 404
+        if body["code"] == 404 {
+            return Ok(Value::Null);
+        }
+        anyhow::bail!(
+            "phpipam error {}: {}",
+            body["code"],
+            body["message"].as_str().unwrap_or_default()
+        );
+    }
+    Ok(body["data"].clone())
+}
+
+/// the fields of a lease's address. The subnet & address can only be set when
+/// the address is created
+fn record(lease: &ExportedLease, subnet: Option<u64>) -> Value {
+    let mut record = json!({
+        "hostname": lease.hostname.as_deref().unwrap_or_default(),
+        "mac": lease.mac.as_deref().unwrap_or_default(),
+        "description": "dora lease",
+    });
+    if let Some(subnet) = subnet {
+        record["subnetId"] = json!(subnet);
+        record["ip"] = json!(lease.ip.to_string());
+        record["tag"] = json!(LEASE_TAG);
+    }
+    record
+}
+
+/// numbers are strings in older versions
+fn number(value: &Value) -> Option<u64> {
+    match value {
+        Value::String(n) => n.parse().ok(),
+        n => n.as_u64(),
+    }
+}
+
+fn tag(addr: &Value) -> Option<u64> {
+    number(&addr["tag"])
+}
+
+/// the statically assigned v4 addresses in the `data` of a response
+fn parse_addrs(data: &Value) -> Result<Vec<Ipv4Addr>> {
+    // an app without addresses
+    if data.is_null() {
+        return Ok(Vec::new());
+    }
+    let mut addrs = Vec::new();
+    for addr in data.as_array().context("missing data")? {
+        if !matches!(tag(addr), Some(tag) if STATIC_TAGS.contains(&tag)) {
+            continue;
+        }
+        if let Some(Ok(ip)) = addr["ip"].as_str().map(str::parse) {
+            addrs.push(ip);
+        }
+    }
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_addrs() {
+        let body = serde_json::json!({
+            "code": 200,
+            "success": true,
+            "data": [
+                { "ip": "192.168.0.10", "tag": "2" },
+                { "ip": "192.168.0.11", "tag": 3 },
+                { "ip": "192.168.0.12", "tag": "4" },
+                { "ip": "192.168.0.13", "tag": "1" },
+                { "ip": "2001:db8::10", "tag": "2" },
+            ],
+        });
+        assert_eq!(
+            parse_addrs(&data(body).unwrap()).unwrap(),
+            [
+                Ipv4Addr::new(192, 168, 0, 10),
+                Ipv4Addr::new(192, 168, 0, 11)
+            ]
+        );
+        let empty =
+            serde_json::json!({ "code": 404, "success": false, "message": "No addresses found" });
+        assert!(parse_addrs(&data(empty).unwrap()).unwrap().is_empty());
+        let denied =
+            serde_json::json!({ "code": 401, "success": false, "message": "Invalid token" });
+        assert!(data(denied).is_err());
+    }
+
+    #[test]
+    fn test_record() {
+        let lease = ExportedLease {
+            ip: Ipv4Addr::new(192, 168, 0, 120),
+            network: "192.168.0.0/24".parse().unwrap(),
+            hostname: Some("printer".to_owned()),
+            mac: None,
+        };
+        assert_eq!(
+            record(&lease, Some(7)),
+            json!({
+                "subnetId": 7,
+                "ip": "192.168.0.120",
+                "tag": 4,
+                "hostname": "printer",
+                "mac": "",
+                "description": "dora lease",
+            })
+        );
+        assert!(record(&lease, None).get("ip").is_none());
+    }
+}
@@ -0,0 +1,178 @@
+//! # oui
+//!
+//! Look up the hardware vendor of a MAC address by its OUI, the first 3 bytes.
+//! The database is loaded from a copy of the IEEE MA-L registry, in either the
+//! csv (<https://standards-oui.ieee.org/oui/oui.csv>) or txt
+//! (<https://standards-oui.ieee.org/oui/oui.txt>) format. Lines that aren't
+//! assignments are skipped.
+//!
+//! [`client_mac`] & [`duid_mac`] find the MAC address in a v4 client id or a v6
+//! DUID, so the same host can be recognized across both protocols.
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    unreachable_pub,
+    non_snake_case,
+    non_upper_case_globals
+)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+
+/// vendor names by OUI
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OuiDb {
+    vendors: HashMap<[u8; 3], String>,
+}
+
+impl OuiDb {
+    /// load the registry at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let db = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read OUI database at {}", path.display()))?;
+        Ok(Self::parse(&db))
+    }
+
+    /// parse a registry in csv or txt format
+    pub fn parse(db: &str) -> Self {
+        Self {
+            vendors: db.lines().filter_map(parse_line).collect(),
+        }
+    }
+
+    /// number of OUIs in the database
+    pub fn len(&self) -> usize {
+        self.vendors.len()
+    }
+
+    /// is the database empty
+    pub fn is_empty(&self) -> bool {
+        self.vendors.is_empty()
+    }
+
+    /// vendor of `mac`
+    pub fn lookup(&self, mac: &[u8]) -> Option<&str> {
+        let oui: [u8; 3] = mac.get(..3)?.try_into().ok()?;
+        self.vendors.get(&oui).map(|vendor| vendor.as_str())
+    }
+
+    /// vendor of a DHCP client id, if it contains a MAC address. See [`client_mac`]
+    pub fn lookup_client_id(&self, id: &[u8]) -> Option<&str> {
+        self.lookup(&client_mac(id)?)
+    }
+}
+
+/// MAC address of a v4 client id. Either the 6 byte chaddr, an ethernet client
+/// identifier (`[1][mac]`) or an RFC 4361 identifier (`[255][iaid][duid]`) with
+/// a link-layer DUID
+pub fn client_mac(id: &[u8]) -> Option<[u8; 6]> {
+    match id {
+        [_, _, _, _, _, _] => id.try_into().ok(),
+        [1, mac @ ..] => mac.try_into().ok(),
+        [255, _, _, _, _, duid @ ..] => duid_mac(duid),
+        _ => None,
+    }
+}
+
+/// MAC address of an ethernet DUID-LLT or DUID-LL (RFC 8415 11.2, 11.4), other
+/// DUID types don't contain one
+pub fn duid_mac(duid: &[u8]) -> Option<[u8; 6]> {
+    // [duid type][hw type = 1 ethernet]
+    match duid {
+        [0, 1, 0, 1, _, _, _, _, mac @ ..] | [0, 3, 0, 1, mac @ ..] => mac.try_into().ok(),
+        _ => None,
+    }
+}
+
+/// `MA-L,0050C2,IEEE Registration Authority,...` or
+/// `00-50-C2   (hex)`, tabs, then `IEEE Registration Authority`
+fn parse_line(line: &str) -> Option<([u8; 3], String)> {
+    let (oui, vendor) = match line.split_once("(hex)") {
+        Some((oui, vendor)) => (oui.trim().replace('-', ""), vendor.trim()),
+        None => {
+            let mut fields = line.splitn(3, ',');
+            let _registry = fields.next()?;
+            let oui = fields.next()?.trim().to_owned();
+            let rest = fields.next()?;
+            // names with a comma are quoted
+            let vendor = match rest.strip_prefix('"') {
+                Some(quoted) => quoted.split('"').next()?,
+                None => rest.split(',').next()?,
+            };
+            (oui, vendor.trim())
+        }
+    };
+    if oui.len() != 6 || vendor.is_empty() {
+        return None;
+    }
+    let mut bytes = [0; 3];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(oui.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some((bytes, vendor.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let db = OuiDb::parse(
+            "Registry,Assignment,Organization Name,Organization Address\n\
+             MA-L,002272,American Micro-Fuel Device Corp.,2181 Buchanan Loop Ferndale WA US 98248\n\
+             MA-L,08EA40,\"SHENZHEN BILIAN ELECTRONIC CO., LTD\",NO 268 Shenzhen CN 518108\n",
+        );
+        assert_eq!(db.len(), 2);
+        assert_eq!(
+            db.lookup(&[0x00, 0x22, 0x72, 0x01, 0x02, 0x03]),
+            Some("American Micro-Fuel Device Corp.")
+        );
+        assert_eq!(
+            db.lookup(&[0x08, 0xea, 0x40, 0, 0, 0]),
+            Some("SHENZHEN BILIAN ELECTRONIC CO., LTD")
+        );
+        assert_eq!(db.lookup(&[0xff, 0xff, 0xff, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_parse_txt() {
+        let db = OuiDb::parse(
+            "OUI/MA-L                                                    Organization\n\
+             company_id                                                  Organization\n\
+             00-22-72   (hex)\t\tAmerican Micro-Fuel Device Corp.\n\
+             002272     (base 16)\t\tAmerican Micro-Fuel Device Corp.\n",
+        );
+        assert_eq!(db.len(), 1);
+        assert_eq!(
+            db.lookup_client_id(&[1, 0x00, 0x22, 0x72, 0x01, 0x02, 0x03]),
+            Some("American Micro-Fuel Device Corp.")
+        );
+        assert_eq!(db.lookup_client_id(&[0x00, 0x22, 0x72]), None);
+    }
+
+    #[test]
+    fn test_client_mac() {
+        let mac = [0x00, 0x22, 0x72, 0x01, 0x02, 0x03];
+        assert_eq!(client_mac(&mac), Some(mac));
+        assert_eq!(
+            client_mac(&[1, 0x00, 0x22, 0x72, 0x01, 0x02, 0x03]),
+            Some(mac)
+        );
+        // DUID-LL
+        let ll = [0, 3, 0, 1, 0x00, 0x22, 0x72, 0x01, 0x02, 0x03];
+        assert_eq!(duid_mac(&ll), Some(mac));
+        // RFC 4361 id, IAID then DUID-LLT
+        let id = [
+            255, 0, 0, 0, 1, 0, 1, 0, 1, 0x2c, 0x5d, 0x6e, 0x7f, 0x00, 0x22, 0x72, 0x01, 0x02, 0x03,
+        ];
+        assert_eq!(client_mac(&id), Some(mac));
+        // DUID-EN has no MAC
+        assert_eq!(duid_mac(&[0, 2, 0, 0, 0x01, 0x37, 0xab, 0xcd]), None);
+        assert_eq!(client_mac(&[1, 0x00, 0x22]), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! # relay-agent
+//!
+//! dora's `relay` mode, a DHCP relay agent for branch routers that forwards
+//! client messages to a central dora (or any DHCP server) instead of leasing
+//! addresses itself.
+//!
+//! v4 requests from clients get the receiving interface's address as giaddr &
+//! relay agent information (opt 82, RFC 3046) with the interface name as the
+//! circuit id & the configured remote id. They're sent to every upstream v4
+//! server. Replies are stripped of opt 82 & sent to the client on the interface
+//! giaddr belongs to. Requests already relayed by another agent are forwarded
+//! as they are, with `hops` incremented.
+//!
+//! v6 messages are encapsulated in Relay-Forward (RFC 8415 section 19) with the
+//! Interface-ID & Remote-ID (RFC 4649) options, Relay-Reply messages are
+//! unwrapped & sent on to the client, or to the next relay down.
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    unreachable_pub,
+    non_snake_case,
+    non_upper_case_globals
+)]
+#![allow(clippy::cognitive_complexity)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+use std::{future::Future, net::SocketAddr};
+
+use config::relay;
+use dora_core::{
+    anyhow::Result,
+    config::cli,
+    dhcproto::{v4 as dhcpv4, v6 as dhcpv6},
+    tokio::{self, sync::oneshot},
+    tracing::info,
+};
+
+mod v4;
+mod v6;
+
+/// a DHCP relay agent, see the crate docs
+#[derive(Debug)]
+pub struct RelayAgent {
+    cfg: relay::Config,
+    v4_addr: SocketAddr,
+    v6_addr: SocketAddr,
+    /// destination port of v4 replies to clients
+    v4_client_port: u16,
+    /// destination port of v6 replies to clients
+    v6_client_port: u16,
+    /// completed once the relayed families' sockets are bound
+    bound: Option<oneshot::Sender<()>>,
+}
+
+impl RelayAgent {
+    /// relay on the listen addresses of `cli` with `cfg`
+    pub fn new(cli: &cli::Config, cfg: relay::Config) -> Self {
+        Self {
+            cfg,
+            v4_addr: cli.v4_listen_addr(),
+            v6_addr: cli.v6_listen_addr(),
+            v4_client_port: cli.v4_client_port.unwrap_or(dhcpv4::CLIENT_PORT),
+            v6_client_port: cli.v6_client_port.unwrap_or(dhcpv6::CLIENT_PORT),
+            bound: None,
+        }
+    }
+
+    /// send on `bound` once the sockets are bound, ex. to drop privileges only
+    /// after binding
+    pub fn notify_bound(&mut self, bound: oneshot::Sender<()>) -> &mut Self {
+        self.bound = Some(bound);
+        self
+    }
+
+    /// relay v4 & v6 messages until `shutdown` completes. A family is only
+    /// relayed if it has upstream servers
+    pub async fn run<F>(self, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = Result<()>>,
+    {
+        let v4 = match self.cfg.v4_servers() {
+            [] => None,
+            _ => Some(v4::bind(self.v4_addr).await?),
+        };
+        let v6 = match self.cfg.v6_servers() {
+            [] => None,
+            _ => Some(v6::bind(&self.cfg, self.v6_addr)?),
+        };
+        if let Some(bound) = self.bound {
+            // nobody may be waiting
+            let _ = bound.send(());
+        }
+        let v4 = async {
+            match v4 {
+                Some(soc) => v4::run(&self.cfg, soc, self.v4_client_port).await,
+                None => std::future::pending().await,
+            }
+        };
+        let v6 = async {
+            match v6 {
+                Some(soc) => v6::run(&self.cfg, soc, self.v6_client_port).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            res = v4 => res,
+            res = v6 => res,
+            res = shutdown => {
+                info!("caught shutdown signal handler");
+                res
+            }
+        }
+    }
+}
@@ -0,0 +1,383 @@
+//! v4 relaying, RFC 1542 & RFC 3046
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use config::relay;
+use dora_core::{
+    anyhow::{bail, Context, Result},
+    dhcproto::{
+        v4::{
+            relay::{RelayAgentInformation, RelayInfo},
+            DhcpOption, Message, Opcode, OptionCode,
+        },
+        Decodable, Decoder, Encodable,
+    },
+    pnet::{datalink::NetworkInterface, ipnetwork::IpNetwork},
+    server::ioctl,
+    tokio_stream::StreamExt,
+    tracing::{debug, error, info, trace, warn},
+};
+use tokio_util::codec::BytesCodec;
+use unix_udp_sock::{framed::UdpFramed, RecvMeta, Source, Transmit, UdpSocket, UdpState};
+
+/// requests relayed this many times are dropped, RFC 1542 section 4.1.1
+const MAX_HOPS: u8 = 16;
+
+struct Relay<'a> {
+    cfg: &'a relay::Config,
+    soc: Arc<UdpSocket>,
+    state: UdpState,
+    client_port: u16,
+}
+
+/// a broadcast capable socket on `addr`
+pub(crate) async fn bind(addr: SocketAddr) -> Result<UdpSocket> {
+    let soc = UdpSocket::bind(addr)
+        .await
+        .context("failed to bind v4 relay socket")?;
+    soc.set_broadcast(true).context("failed to set_broadcast")?;
+    Ok(soc)
+}
+
+/// relay v4 messages received on `soc`, never returns unless the socket fails
+pub(crate) async fn run(cfg: &relay::Config, soc: UdpSocket, client_port: u16) -> Result<()> {
+    let soc = Arc::new(soc);
+    let addr = soc.local_addr()?;
+    info!(?addr, servers = ?cfg.v4_servers(), "relaying v4");
+    let relay = Relay {
+        cfg,
+        soc: soc.clone(),
+        state: UdpState::new(),
+        client_port,
+    };
+    let mut stream = UdpFramed::new(soc, BytesCodec::new());
+    while let Some(res) = stream.next().await {
+        let (buf, meta) = match res {
+            Ok(recv) => recv,
+            Err(err) => {
+                error!(?err, "failed to receive v4 message");
+                continue;
+            }
+        };
+        if let Err(err) = relay.handle(&buf, meta).await {
+            debug!(?err, src = ?meta.addr, "dropped v4 message");
+        }
+    }
+    Ok(())
+}
+
+impl Relay<'_> {
+    async fn handle(&self, buf: &[u8], meta: RecvMeta) -> Result<()> {
+        let msg = Message::decode(&mut Decoder::new(buf))?;
+        trace!(?meta, %msg, "received v4 message");
+        match msg.opcode() {
+            Opcode::BootRequest => self.forward(msg, meta.ifindex).await,
+            Opcode::BootReply => self.reply(msg).await,
+            opcode => bail!("unknown opcode {opcode:?}"),
+        }
+    }
+
+    /// relay a client's request received on `ifindex` to the upstream servers
+    async fn forward(&self, mut msg: Message, ifindex: u32) -> Result<()> {
+        let interface = self
+            .cfg
+            .v4_interfaces()
+            .iter()
+            .find(|int| int.index == ifindex)
+            .with_context(|| format!("received on interface {ifindex}, which isn't relayed"))?;
+        if msg.hops() >= MAX_HOPS {
+            bail!("request was relayed {} times already", msg.hops());
+        }
+        msg.set_hops(msg.hops() + 1);
+        // a non-zero giaddr was set by another relay, forward it as is
+        if msg.giaddr().is_unspecified() {
+            let giaddr = v4_addr(interface)
+                .with_context(|| format!("interface {} has no v4 address", interface.name))?;
+            msg.set_giaddr(giaddr);
+            if msg.opts().get(OptionCode::RelayAgentInformation).is_none() {
+                if let Some(info) = self.agent_info(interface) {
+                    msg.opts_mut()
+                        .insert(DhcpOption::RelayAgentInformation(info));
+                }
+            }
+        }
+        let bytes = msg.to_vec()?;
+        for server in self.cfg.v4_servers() {
+            debug!(%server, giaddr = %msg.giaddr(), interface = %interface.name, "relaying v4 request");
+            if let Err(err) = self.soc.send_to(&bytes, *server).await {
+                error!(?err, %server, "failed to relay v4 request");
+            }
+        }
+        Ok(())
+    }
+
+    /// opt 82 sub-options added to requests from `interface`, `None` if there are none
+    fn agent_info(&self, interface: &NetworkInterface) -> Option<RelayAgentInformation> {
+        let mut info = RelayAgentInformation::default();
+        let mut empty = true;
+        if self.cfg.interface_id() {
+            info.insert(RelayInfo::AgentCircuitId(
+                interface.name.as_bytes().to_vec(),
+            ));
+            empty = false;
+        }
+        if let Some(id) = self.cfg.remote_id() {
+            info.insert(RelayInfo::AgentRemoteId(id.to_vec()));
+            empty = false;
+        }
+        (!empty).then_some(info)
+    }
+
+    /// send a server's reply to the client, on the interface its giaddr belongs to
+    async fn reply(&self, mut msg: Message) -> Result<()> {
+        let giaddr = IpAddr::V4(msg.giaddr());
+        let interface = self
+            .cfg
+            .v4_interfaces()
+            .iter()
+            .find(|int| int.ips.iter().any(|net| net.ip() == giaddr))
+            .with_context(|| format!("reply for giaddr {giaddr}, which isn't relayed by us"))?;
+        msg.opts_mut().remove(OptionCode::RelayAgentInformation);
+        let dst = self.client_addr(&msg);
+        debug!(%dst, interface = %interface.name, "relaying v4 reply");
+        let transmit = Transmit::new(dst, Bytes::from(msg.to_vec()?))
+            .src_ip(Source::Interface(interface.index));
+        self.soc.send_msg(&self.state, transmit).await?;
+        Ok(())
+    }
+
+    /// where a reply goes, RFC 2131 section 4.1 with the relay's client port
+    fn client_addr(&self, msg: &Message) -> SocketAddr {
+        let (ciaddr, yiaddr) = (msg.ciaddr(), msg.yiaddr());
+        if !ciaddr.is_unspecified() {
+            return (ciaddr, self.client_port).into();
+        }
+        if !msg.flags().broadcast() && !yiaddr.is_unspecified() {
+            // the client has no address yet, unicast needs an ARP entry for it
+            match ioctl::arp_set(
+                socket2::SockRef::from(&*self.soc),
+                yiaddr,
+                msg.htype(),
+                msg.chaddr(),
+            ) {
+                Ok(()) => return (yiaddr, self.client_port).into(),
+                Err(err) => warn!(?err, %yiaddr, "failed to set ARP entry, broadcasting reply"),
+            }
+        }
+        (Ipv4Addr::BROADCAST, self.client_port).into()
+    }
+}
+
+/// the first v4 address of `interface`
+fn v4_addr(interface: &NetworkInterface) -> Option<Ipv4Addr> {
+    interface.ips.iter().find_map(|net| match net {
+        IpNetwork::V4(net) => Some(net.ip()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use config::wire;
+    use dora_core::dhcproto::v4::{Flags, MessageType};
+    use tokio::{net::UdpSocket as TestSocket, time};
+
+    use super::*;
+
+    const LOCALHOST: Ipv4Addr = Ipv4Addr::LOCALHOST;
+
+    /// relay config for `lo`, forwarding to `server`
+    fn cfg(server: SocketAddr) -> relay::Config {
+        let relay = wire::Relay {
+            servers: vec![server.to_string()],
+            interface_id: true,
+            remote_id: Some("branch-1".to_owned()),
+            enterprise_number: 0,
+        };
+        relay::Config::new(relay, Some(vec!["lo".to_owned()])).unwrap()
+    }
+
+    async fn relay(cfg: &relay::Config, client_port: u16) -> Relay<'_> {
+        Relay {
+            cfg,
+            soc: Arc::new(bind((LOCALHOST, 0).into()).await.unwrap()),
+            state: UdpState::new(),
+            client_port,
+        }
+    }
+
+    fn request(giaddr: Ipv4Addr, hops: u8) -> Message {
+        let mut msg = Message::new(
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::UNSPECIFIED,
+            giaddr,
+            &[1, 2, 3, 4, 5, 6],
+        );
+        msg.set_hops(hops);
+        msg.opts_mut()
+            .insert(DhcpOption::MessageType(MessageType::Discover));
+        msg
+    }
+
+    fn reply(ciaddr: Ipv4Addr, flags: Flags) -> Message {
+        let mut msg = Message::new(
+            ciaddr,
+            Ipv4Addr::new(192, 168, 0, 10),
+            Ipv4Addr::UNSPECIFIED,
+            LOCALHOST,
+            &[1, 2, 3, 4, 5, 6],
+        );
+        msg.set_opcode(Opcode::BootReply).set_flags(flags);
+        msg.opts_mut()
+            .insert(DhcpOption::MessageType(MessageType::Offer));
+        let mut info = RelayAgentInformation::default();
+        info.insert(RelayInfo::AgentCircuitId(b"lo".to_vec()));
+        msg.opts_mut()
+            .insert(DhcpOption::RelayAgentInformation(info));
+        msg
+    }
+
+    /// the next message received on `soc`, `None` if nothing arrives
+    async fn recv_raw(soc: &TestSocket) -> Option<Vec<u8>> {
+        let mut buf = [0; 1500];
+        let (len, _) = time::timeout(Duration::from_millis(200), soc.recv_from(&mut buf))
+            .await
+            .ok()?
+            .unwrap();
+        Some(buf[..len].to_vec())
+    }
+
+    async fn recv(soc: &TestSocket) -> Option<Message> {
+        let buf = recv_raw(soc).await?;
+        Some(Message::decode(&mut Decoder::new(&buf)).unwrap())
+    }
+
+    /// the sub-options of opt 82 in an encoded message. dhcproto decodes the
+    /// remote id as a circuit id, so they're checked as they were sent
+    fn agent_info(buf: &[u8]) -> Option<&[u8]> {
+        // options follow the fixed header & magic cookie
+        let mut opts = &buf[240..];
+        while let [code, len, rest @ ..] = opts {
+            let (data, rest) = rest.split_at(*len as usize);
+            if *code == u8::from(OptionCode::RelayAgentInformation) {
+                return Some(data);
+            }
+            opts = rest;
+        }
+        None
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let server = TestSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let cfg = cfg(server.local_addr().unwrap());
+        let relay = relay(&cfg, 0).await;
+        let lo = cfg.v4_interfaces()[0].index;
+
+        relay
+            .forward(request(Ipv4Addr::UNSPECIFIED, 0), lo)
+            .await
+            .unwrap();
+        let buf = recv_raw(&server).await.unwrap();
+        let msg = Message::decode(&mut Decoder::new(&buf)).unwrap();
+        assert_eq!(msg.hops(), 1);
+        assert_eq!(msg.giaddr(), LOCALHOST);
+        let info = agent_info(&buf).unwrap();
+        assert!(info.windows(4).any(|w| w == b"\x01\x02lo"));
+        assert!(info.windows(10).any(|w| w == b"\x02\x08branch-1"));
+    }
+
+    #[tokio::test]
+    async fn test_forward_relayed() {
+        let server = TestSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let cfg = cfg(server.local_addr().unwrap());
+        let relay = relay(&cfg, 0).await;
+        let lo = cfg.v4_interfaces()[0].index;
+
+        // another relay set giaddr, it's forwarded as is
+        let giaddr = Ipv4Addr::new(10, 0, 0, 1);
+        relay.forward(request(giaddr, 3), lo).await.unwrap();
+        let msg = recv(&server).await.unwrap();
+        assert_eq!(msg.hops(), 4);
+        assert_eq!(msg.giaddr(), giaddr);
+        assert!(msg.opts().get(OptionCode::RelayAgentInformation).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hop_limit() {
+        let server = TestSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let cfg = cfg(server.local_addr().unwrap());
+        let relay = relay(&cfg, 0).await;
+        let lo = cfg.v4_interfaces()[0].index;
+
+        relay
+            .forward(request(Ipv4Addr::UNSPECIFIED, MAX_HOPS - 1), lo)
+            .await
+            .unwrap();
+        assert_eq!(recv(&server).await.unwrap().hops(), MAX_HOPS);
+        assert!(relay
+            .forward(request(Ipv4Addr::UNSPECIFIED, MAX_HOPS), lo)
+            .await
+            .is_err());
+        assert!(recv(&server).await.is_none());
+        // not received on a relayed interface
+        assert!(relay
+            .forward(request(Ipv4Addr::UNSPECIFIED, 0), u32::MAX)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reply() {
+        let client = TestSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let cfg = cfg((LOCALHOST, 67).into());
+        let relay = relay(&cfg, client.local_addr().unwrap().port()).await;
+
+        // a renewing client has an address, the reply is unicast to it
+        relay
+            .reply(reply(LOCALHOST, Flags::default()))
+            .await
+            .unwrap();
+        let msg = recv(&client).await.unwrap();
+        assert_eq!(msg.opcode(), Opcode::BootReply);
+        assert!(msg.opts().get(OptionCode::RelayAgentInformation).is_none());
+
+        // giaddr isn't one of ours
+        let mut msg = reply(LOCALHOST, Flags::default());
+        msg.set_giaddr([10, 0, 0, 1]);
+        assert!(relay.reply(msg).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_addr() {
+        let cfg = cfg((LOCALHOST, 67).into());
+        let relay = relay(&cfg, 68).await;
+
+        assert_eq!(
+            relay.client_addr(&reply(LOCALHOST, Flags::default())),
+            (LOCALHOST, 68).into()
+        );
+        // ciaddr wins over the broadcast flag
+        assert_eq!(
+            relay.client_addr(&reply(LOCALHOST, Flags::default().set_broadcast())),
+            (LOCALHOST, 68).into()
+        );
+        assert_eq!(
+            relay.client_addr(&reply(
+                Ipv4Addr::UNSPECIFIED,
+                Flags::default().set_broadcast()
+            )),
+            (Ipv4Addr::BROADCAST, 68).into()
+        );
+        // no address to unicast to
+        let mut msg = reply(Ipv4Addr::UNSPECIFIED, Flags::default());
+        msg.set_yiaddr(Ipv4Addr::UNSPECIFIED);
+        assert_eq!(relay.client_addr(&msg), (Ipv4Addr::BROADCAST, 68).into());
+    }
+}
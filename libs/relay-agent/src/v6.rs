@@ -0,0 +1,346 @@
+//! v6 relaying, RFC 8415 section 19
+use std::{
+    net::{Ipv6Addr, SocketAddr, SocketAddrV6},
+    os::unix::prelude::{FromRawFd, IntoRawFd},
+};
+
+use config::relay;
+use dora_core::{
+    anyhow::{bail, Context, Result},
+    config::cli::ALL_DHCP_RELAY_AGENTS_AND_SERVERS,
+    dhcproto::v6::SERVER_PORT,
+    pnet::{datalink::NetworkInterface, ipnetwork::IpNetwork},
+    server::relay::{
+        RelayLayer, HOP_COUNT_LIMIT, OPTION_INTERFACE_ID, OPTION_REMOTE_ID, RELAY_FORW, RELAY_REPL,
+    },
+    tokio_stream::StreamExt,
+    tracing::{debug, error, info, trace},
+};
+use tokio_util::codec::BytesCodec;
+use unix_udp_sock::{framed::UdpFramed, RecvMeta, UdpSocket};
+
+/// ADVERTISE, REPLY & RECONFIGURE are only sent by servers
+const SERVER_MSGS: [u8; 3] = [2, 7, 10];
+
+struct Relay<'a> {
+    cfg: &'a relay::Config,
+    soc: &'a UdpSocket,
+    client_port: u16,
+}
+
+/// relay v6 messages received on `soc`, never returns unless the socket fails
+pub(crate) async fn run(cfg: &relay::Config, soc: UdpSocket, client_port: u16) -> Result<()> {
+    let addr = soc.local_addr()?;
+    info!(?addr, servers = ?cfg.v6_servers(), "relaying v6");
+    let relay = Relay {
+        cfg,
+        soc: &soc,
+        client_port,
+    };
+    let mut stream = UdpFramed::new(&soc, BytesCodec::new());
+    while let Some(res) = stream.next().await {
+        let (buf, meta) = match res {
+            Ok(recv) => recv,
+            Err(err) => {
+                error!(?err, "failed to receive v6 message");
+                continue;
+            }
+        };
+        if let Err(err) = relay.handle(&buf, meta).await {
+            debug!(?err, src = ?meta.addr, "dropped v6 message");
+        }
+    }
+    Ok(())
+}
+
+/// a socket on `addr` that has joined All_DHCP_Relay_Agents_and_Servers on the
+/// relayed interfaces
+pub(crate) fn bind(cfg: &relay::Config, addr: SocketAddr) -> Result<UdpSocket> {
+    let socket = socket2::Socket::new(socket2::Domain::IPV6, socket2::Type::DGRAM, None)
+        .context("failed to bind v6 relay socket")?;
+    socket.set_only_v6(true).context("only ipv6")?;
+    socket
+        .set_reuse_address(true)
+        .context("failed to set_reuse_address")?;
+    socket
+        .set_nonblocking(true)
+        .context("failed to set nonblocking mode on socket")?;
+    socket
+        .bind(&addr.into())
+        .context("failed to bind v6 relay socket")?;
+    for int in cfg.v6_interfaces() {
+        socket
+            .join_multicast_v6(&ALL_DHCP_RELAY_AGENTS_AND_SERVERS, int.index)
+            .with_context(|| format!("failed to join v6 multicast on {}", int.name))?;
+    }
+    Ok(UdpSocket::from_std(unsafe {
+        std::net::UdpSocket::from_raw_fd(socket.into_raw_fd())
+    })?)
+}
+
+impl Relay<'_> {
+    async fn handle(&self, buf: &[u8], meta: RecvMeta) -> Result<()> {
+        trace!(?meta, len = buf.len(), "received v6 message");
+        match buf.first() {
+            Some(&RELAY_REPL) => self.reply(buf).await,
+            Some(msg_type) if SERVER_MSGS.contains(msg_type) => {
+                bail!("message type {msg_type} is only sent by servers")
+            }
+            Some(_) => self.forward(buf, meta).await,
+            None => bail!("empty message"),
+        }
+    }
+
+    /// encapsulate a client message, or another relay's Relay-Forward, & send
+    /// it to the upstream servers
+    async fn forward(&self, buf: &[u8], meta: RecvMeta) -> Result<()> {
+        let interface = self
+            .cfg
+            .v6_interfaces()
+            .iter()
+            .find(|int| int.index == meta.ifindex)
+            .with_context(|| {
+                format!(
+                    "received on interface {}, which isn't relayed",
+                    meta.ifindex
+                )
+            })?;
+        let SocketAddr::V6(src) = meta.addr else {
+            bail!("v6 message from v4 address {}", meta.addr);
+        };
+        let hop_count = match buf {
+            [RELAY_FORW, hops, ..] if *hops as usize >= HOP_COUNT_LIMIT => {
+                bail!("message was relayed {hops} times already")
+            }
+            [RELAY_FORW, hops, ..] => hops + 1,
+            _ => 0,
+        };
+        let link_addr = global_addr(interface).unwrap_or(Ipv6Addr::UNSPECIFIED);
+        let mut opts = Vec::new();
+        // the server can't tell where the client is without a link-address,
+        // the Interface-ID is how its reply finds its way back
+        if self.cfg.interface_id() || link_addr.is_unspecified() {
+            opts.push((OPTION_INTERFACE_ID, interface.name.as_bytes().to_vec()));
+        }
+        if let Some(id) = self.cfg.remote_id() {
+            let mut data = self.cfg.enterprise_number().to_be_bytes().to_vec();
+            data.extend_from_slice(id);
+            opts.push((OPTION_REMOTE_ID, data));
+        }
+        let layer = RelayLayer {
+            hop_count,
+            link_addr,
+            peer_addr: *src.ip(),
+            opts,
+        };
+        let bytes = layer.forward(buf);
+        for server in self.cfg.v6_servers() {
+            debug!(%server, %link_addr, interface = %interface.name, "relaying v6 message");
+            if let Err(err) = self.soc.send_to(&bytes, *server).await {
+                error!(?err, %server, "failed to relay v6 message");
+            }
+        }
+        Ok(())
+    }
+
+    /// unwrap a Relay-Reply & send the message it carries to the peer it was
+    /// relayed for, a client or the next relay
+    async fn reply(&self, buf: &[u8]) -> Result<()> {
+        let (layer, msg) = RelayLayer::unwrap_reply(buf)?.context("not a relay-reply")?;
+        let interface = match layer.get(OPTION_INTERFACE_ID) {
+            Some(name) => self
+                .cfg
+                .v6_interfaces()
+                .iter()
+                .find(|int| int.name.as_bytes() == name),
+            None => self
+                .cfg
+                .v6_interfaces()
+                .iter()
+                .find(|int| int.ips.iter().any(|net| net.ip() == layer.link_addr)),
+        }
+        .with_context(|| format!("can't find the interface for link {}", layer.link_addr))?;
+        let port = match msg.first() {
+            Some(&RELAY_REPL) => SERVER_PORT,
+            _ => self.client_port,
+        };
+        let dst = SocketAddrV6::new(layer.peer_addr, port, 0, interface.index);
+        debug!(%dst, interface = %interface.name, "relaying v6 reply");
+        self.soc.send_to(msg, dst.into()).await?;
+        Ok(())
+    }
+}
+
+/// the first address of `interface` that isn't link-local
+fn global_addr(interface: &NetworkInterface) -> Option<Ipv6Addr> {
+    interface.ips.iter().find_map(|net| match net {
+        // fe80::/10
+        IpNetwork::V6(net) if net.ip().segments()[0] & 0xffc0 != 0xfe80 => Some(net.ip()),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use config::wire;
+    use dora_core::{dhcproto::v6::CLIENT_PORT, server::relay::RelayInfo};
+    use tokio::{net::UdpSocket as TestSocket, time};
+
+    use super::*;
+
+    const LOCALHOST: Ipv6Addr = Ipv6Addr::LOCALHOST;
+    /// SOLICIT with a transaction id
+    const SOLICIT: [u8; 4] = [1, 0xaa, 0xbb, 0xcc];
+
+    /// relay config for `lo`, forwarding to `server`
+    fn cfg(server: SocketAddr) -> relay::Config {
+        let relay = wire::Relay {
+            servers: vec![server.to_string()],
+            interface_id: true,
+            remote_id: Some("branch-1".to_owned()),
+            enterprise_number: 4491,
+        };
+        relay::Config::new(relay, Some(vec!["lo".to_owned()])).unwrap()
+    }
+
+    async fn socket() -> UdpSocket {
+        UdpSocket::bind((LOCALHOST, 0)).await.unwrap()
+    }
+
+    fn meta(cfg: &relay::Config, peer: Ipv6Addr) -> RecvMeta {
+        RecvMeta {
+            addr: (peer, CLIENT_PORT).into(),
+            ifindex: cfg.v6_interfaces()[0].index,
+            ..RecvMeta::default()
+        }
+    }
+
+    /// the next message received on `soc`, `None` if nothing arrives
+    async fn recv(soc: &TestSocket) -> Option<Vec<u8>> {
+        let mut buf = [0; 1500];
+        let (len, _) = time::timeout(Duration::from_millis(200), soc.recv_from(&mut buf))
+            .await
+            .ok()?
+            .unwrap();
+        Some(buf[..len].to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_forward() {
+        let server = TestSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let cfg = cfg(server.local_addr().unwrap());
+        let soc = socket().await;
+        let relay = Relay {
+            cfg: &cfg,
+            soc: &soc,
+            client_port: CLIENT_PORT,
+        };
+        let client: Ipv6Addr = "fe80::1".parse().unwrap();
+
+        relay.handle(&SOLICIT, meta(&cfg, client)).await.unwrap();
+        let buf = recv(&server).await.unwrap();
+        let (info, msg) = RelayInfo::unwrap(&buf).unwrap().unwrap();
+        assert_eq!(msg, SOLICIT);
+        let [layer] = info.layers() else {
+            panic!("expected a single relay layer");
+        };
+        assert_eq!(layer.hop_count, 0);
+        assert_eq!(layer.link_addr, LOCALHOST);
+        assert_eq!(layer.peer_addr, client);
+        assert_eq!(layer.get(OPTION_INTERFACE_ID), Some(&b"lo"[..]));
+        assert_eq!(
+            layer.get(OPTION_REMOTE_ID),
+            Some(&b"\x00\x00\x11\x8bbranch-1"[..])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hop_limit() {
+        let server = TestSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let cfg = cfg(server.local_addr().unwrap());
+        let soc = socket().await;
+        let relay = Relay {
+            cfg: &cfg,
+            soc: &soc,
+            client_port: CLIENT_PORT,
+        };
+        let relayed = |hop_count| {
+            RelayLayer {
+                hop_count,
+                link_addr: Ipv6Addr::UNSPECIFIED,
+                peer_addr: "fe80::1".parse().unwrap(),
+                opts: Vec::new(),
+            }
+            .forward(&SOLICIT)
+        };
+        let peer: Ipv6Addr = "fe80::2".parse().unwrap();
+
+        // another relay's Relay-Forward is encapsulated with the next hop count
+        let limit = HOP_COUNT_LIMIT as u8;
+        relay
+            .handle(&relayed(limit - 1), meta(&cfg, peer))
+            .await
+            .unwrap();
+        let buf = recv(&server).await.unwrap();
+        let (info, msg) = RelayInfo::unwrap(&buf).unwrap().unwrap();
+        assert_eq!(msg, SOLICIT);
+        assert_eq!(info.layers().len(), 2);
+        assert_eq!(info.layers()[0].hop_count, limit);
+        assert_eq!(info.layers()[0].peer_addr, peer);
+
+        assert!(relay
+            .handle(&relayed(limit), meta(&cfg, peer))
+            .await
+            .is_err());
+        assert!(recv(&server).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_reply() {
+        let client = TestSocket::bind((LOCALHOST, 0)).await.unwrap();
+        let cfg = cfg((LOCALHOST, SERVER_PORT).into());
+        let soc = socket().await;
+        let relay = Relay {
+            cfg: &cfg,
+            soc: &soc,
+            client_port: client.local_addr().unwrap().port(),
+        };
+        // ADVERTISE
+        let advertise = [2, 0xaa, 0xbb, 0xcc];
+        let layer = RelayLayer {
+            hop_count: 0,
+            link_addr: LOCALHOST,
+            peer_addr: LOCALHOST,
+            opts: vec![(OPTION_INTERFACE_ID, b"lo".to_vec())],
+        };
+        let (info, _) = RelayInfo::unwrap(&layer.forward(&SOLICIT))
+            .unwrap()
+            .unwrap();
+
+        relay
+            .handle(&info.wrap(&advertise), meta(&cfg, LOCALHOST))
+            .await
+            .unwrap();
+        assert_eq!(recv(&client).await.unwrap(), advertise);
+
+        // server messages that aren't relayed are dropped
+        assert!(relay
+            .handle(&advertise, meta(&cfg, LOCALHOST))
+            .await
+            .is_err());
+        // the Interface-ID isn't one we relay
+        let layer = RelayLayer {
+            opts: vec![(OPTION_INTERFACE_ID, b"eth9".to_vec())],
+            ..layer
+        };
+        let (info, _) = RelayInfo::unwrap(&layer.forward(&SOLICIT))
+            .unwrap()
+            .unwrap();
+        assert!(relay
+            .handle(&info.wrap(&advertise), meta(&cfg, LOCALHOST))
+            .await
+            .is_err());
+    }
+}
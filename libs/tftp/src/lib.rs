@@ -0,0 +1,445 @@
+//! # tftp
+//!
+//! A read-only TFTP server (RFC 1350), so a small PXE deployment can serve its
+//! boot files from dora. Files are served from a root directory, requests for
+//! paths outside of it & write requests are refused.
+//!
+//! The `blksize` (RFC 2348), `tsize` & `timeout` (RFC 2349) options are
+//! negotiated, PXE firmware relies on them. `netascii` transfers are sent as
+//! is, without converting line endings. Each transfer gets its own socket &
+//! task, a block is sent up to [`RETRIES`] more times before the transfer is
+//! given up. At most [`DEFAULT_MAX_TRANSFERS`] transfers run at once unless
+//! set with [`Server::max_transfers`], requests beyond that are refused.
+#![warn(
+    missing_debug_implementations,
+    missing_docs,
+    rust_2018_idioms,
+    unreachable_pub,
+    non_snake_case,
+    non_upper_case_globals
+)]
+#![allow(clippy::cognitive_complexity)]
+#![deny(rustdoc::broken_intra_doc_links)]
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use tokio::{
+    fs::File,
+    io::AsyncReadExt,
+    net::UdpSocket,
+    sync::Semaphore,
+    time::{self, Instant},
+};
+use tracing::{debug, error, info, warn};
+
+mod packet;
+
+use packet::{Reply, Request};
+
+/// times a block is resent when it isn't acknowledged
+pub const RETRIES: u32 = 5;
+/// transfers running at once without [`Server::max_transfers`]
+pub const DEFAULT_MAX_TRANSFERS: usize = 64;
+/// block size without a `blksize` option
+const DEFAULT_BLKSIZE: usize = 512;
+/// largest `blksize`, RFC 2348
+const MAX_BLKSIZE: usize = 65_464;
+/// retransmission timeout without a `timeout` option
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// largest request, a file name & a handful of options
+const MAX_REQUEST: usize = 1_500;
+
+/// a TFTP server for the files in a directory
+#[derive(Debug)]
+pub struct Server {
+    soc: UdpSocket,
+    root: Arc<Path>,
+    /// a permit is held for each running transfer
+    transfers: Arc<Semaphore>,
+}
+
+impl Server {
+    /// serve `root` on `addr`
+    pub async fn bind(addr: SocketAddr, root: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            soc: UdpSocket::bind(addr).await?,
+            root: root.into(),
+            transfers: Arc::new(Semaphore::new(DEFAULT_MAX_TRANSFERS)),
+        })
+    }
+
+    /// serve `root` on a socket that's bound already, i.e. by systemd
+    pub fn from_std(soc: std::net::UdpSocket, root: PathBuf) -> io::Result<Self> {
+        soc.set_nonblocking(true)?;
+        Ok(Self {
+            soc: UdpSocket::from_std(soc)?,
+            root: root.into(),
+            transfers: Arc::new(Semaphore::new(DEFAULT_MAX_TRANSFERS)),
+        })
+    }
+
+    /// run at most `max` transfers at once, read requests beyond that are
+    /// answered with an error
+    pub fn max_transfers(mut self, max: usize) -> Self {
+        self.transfers = Arc::new(Semaphore::new(max));
+        self
+    }
+
+    /// address requests are received on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.soc.local_addr()
+    }
+
+    /// answer requests, each transfer is sent from its own socket. Never returns
+    pub async fn run(self) {
+        info!(root = %self.root.display(), addr = ?self.soc.local_addr(), "serving tftp");
+        // transfers are sent from the address requests are received on
+        let local = self
+            .soc
+            .local_addr()
+            .map(|addr| addr.ip())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let mut buf = vec![0; MAX_REQUEST];
+        loop {
+            let (len, peer) = match self.soc.recv_from(&mut buf).await {
+                Ok(recv) => recv,
+                Err(err) => {
+                    error!(?err, "failed to receive tftp request");
+                    continue;
+                }
+            };
+            match packet::parse_request(&buf[..len]) {
+                Some(Request::Read { file, mode, opts }) => {
+                    debug!(?peer, ?file, ?mode, ?opts, "tftp read request");
+                    let Ok(permit) = self.transfers.clone().try_acquire_owned() else {
+                        warn!(?peer, ?file, "too many tftp transfers, refusing request");
+                        let reply = packet::error(packet::NOT_DEFINED, "server busy");
+                        let _ = self.soc.send_to(&reply, peer).await;
+                        continue;
+                    };
+                    let root = self.root.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = send_file(&root, local, peer, &file, &opts).await {
+                            warn!(?err, ?peer, ?file, "tftp transfer failed");
+                        }
+                        drop(permit);
+                    });
+                }
+                Some(Request::Write) => {
+                    let reply = packet::error(packet::ACCESS_VIOLATION, "read only server");
+                    let _ = self.soc.send_to(&reply, peer).await;
+                }
+                None => debug!(?peer, "invalid tftp request"),
+            }
+        }
+    }
+}
+
+/// the path of `file` under `root`, `None` if it would leave `root`. Leading
+/// slashes are dropped & backslashes read as separators, some PXE firmware
+/// sends them
+pub fn resolve(root: &Path, file: &str) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for part in file.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => return None,
+            part => path.push(part),
+        }
+    }
+    (path != root).then_some(path)
+}
+
+/// the names in `files` that aren't a file under `root`. Names with a url
+/// scheme, i.e. iPXE's `http://..`, aren't fetched over TFTP & are skipped
+pub fn missing<'a>(root: &Path, files: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+    files
+        .into_iter()
+        .filter(|file| !file.contains("://"))
+        .filter(|file| !resolve(root, file).map_or(false, |path| path.is_file()))
+        .collect()
+}
+
+/// options accepted for a transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Negotiated {
+    blksize: usize,
+    timeout: Duration,
+    /// sent back to the client in an OACK, empty if none were accepted
+    acked: Vec<(String, String)>,
+}
+
+/// accept the options in a request for a file of `size` bytes, unknown options
+/// & invalid values are ignored
+fn negotiate(opts: &[(String, String)], size: u64) -> Negotiated {
+    let mut negotiated = Negotiated {
+        blksize: DEFAULT_BLKSIZE,
+        timeout: DEFAULT_TIMEOUT,
+        acked: Vec::new(),
+    };
+    for (name, value) in opts {
+        match (name.as_str(), value.parse::<u64>()) {
+            ("blksize", Ok(blksize)) if blksize >= 8 => {
+                // a smaller size is acked when the client asks for too much
+                negotiated.blksize = (blksize as usize).min(MAX_BLKSIZE);
+                let acked = negotiated.blksize.to_string();
+                negotiated.acked.push((name.clone(), acked));
+            }
+            ("timeout", Ok(secs @ 1..=255)) => {
+                negotiated.timeout = Duration::from_secs(secs);
+                negotiated.acked.push((name.clone(), value.clone()));
+            }
+            ("tsize", Ok(_)) => negotiated.acked.push((name.clone(), size.to_string())),
+            _ => {}
+        }
+    }
+    negotiated
+}
+
+/// send `file` to `peer` from a new socket on `local`
+async fn send_file(
+    root: &Path,
+    local: IpAddr,
+    peer: SocketAddr,
+    file: &str,
+    opts: &[(String, String)],
+) -> Result<()> {
+    let soc = UdpSocket::bind((local, 0)).await?;
+    soc.connect(peer).await?;
+    let Some(path) = resolve(root, file) else {
+        soc.send(&packet::error(packet::ACCESS_VIOLATION, "path outside of root"))
+            .await?;
+        bail!("path outside of root");
+    };
+    let not_found = packet::error(packet::FILE_NOT_FOUND, "file not found");
+    let mut f = match File::open(&path).await {
+        Ok(f) => f,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            soc.send(&not_found).await?;
+            debug!(?peer, ?file, "tftp file not found");
+            return Ok(());
+        }
+        Err(err) => {
+            soc.send(&packet::error(packet::ACCESS_VIOLATION, "can't read file"))
+                .await?;
+            return Err(err.into());
+        }
+    };
+    let meta = f.metadata().await?;
+    // directories open fine on unix
+    if !meta.is_file() {
+        soc.send(&not_found).await?;
+        debug!(?peer, ?file, "tftp path isn't a file");
+        return Ok(());
+    }
+    let size = meta.len();
+    let opts = negotiate(opts, size);
+    if !opts.acked.is_empty()
+        && !send_until_ack(&soc, &packet::oack(&opts.acked), 0, opts.timeout).await?
+    {
+        // PXE firmware asks for the size only, then aborts & asks again
+        return Ok(());
+    }
+    let mut buf = vec![0; opts.blksize];
+    let mut block: u16 = 0;
+    loop {
+        let len = read_block(&mut f, &mut buf).await?;
+        // block numbers roll over on files larger than 65535 blocks
+        block = block.wrapping_add(1);
+        if !send_until_ack(&soc, &packet::data(block, &buf[..len]), block, opts.timeout).await? {
+            bail!("client aborted the transfer");
+        }
+        // a short block ends the transfer
+        if len < opts.blksize {
+            debug!(?peer, ?file, size, "tftp transfer done");
+            return Ok(());
+        }
+    }
+}
+
+/// send `msg` until `block` is acknowledged, resending on `timeout`.
+/// Returns `false` if the client sent an error instead
+async fn send_until_ack(
+    soc: &UdpSocket,
+    msg: &[u8],
+    block: u16,
+    timeout: Duration,
+) -> Result<bool> {
+    let mut buf = vec![0; MAX_REQUEST];
+    for _ in 0..=RETRIES {
+        soc.send(msg).await?;
+        let deadline = Instant::now() + timeout;
+        while let Ok(len) = time::timeout_at(deadline, soc.recv(&mut buf)).await {
+            match packet::parse_reply(&buf[..len?]) {
+                Reply::Ack(acked) if acked == block => return Ok(true),
+                Reply::Error(code, msg) => {
+                    debug!(code, ?msg, "tftp client sent error");
+                    return Ok(false);
+                }
+                // duplicate acks of earlier blocks aren't answered, RFC 1123 4.2.3.1
+                Reply::Ack(_) | Reply::Other => continue,
+            }
+        }
+    }
+    let _ = soc
+        .send(&packet::error(packet::NOT_DEFINED, "timed out"))
+        .await;
+    bail!("block {block} not acknowledged after {RETRIES} retries")
+}
+
+/// fill `buf` from `f`, returns less than its length at the end of the file
+async fn read_block(f: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match f.read(&mut buf[len..]).await? {
+            0 => break,
+            n => len += n,
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("dora-tftp-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("efi")).unwrap();
+        root
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = Path::new("/srv/tftp");
+        assert_eq!(
+            resolve(root, "/pxelinux.0"),
+            Some(PathBuf::from("/srv/tftp/pxelinux.0"))
+        );
+        assert_eq!(
+            resolve(root, "efi\\grubx64.efi"),
+            Some(PathBuf::from("/srv/tftp/efi/grubx64.efi"))
+        );
+        assert_eq!(resolve(root, "../etc/passwd"), None);
+        assert_eq!(resolve(root, "efi/../../etc/passwd"), None);
+        assert_eq!(resolve(root, "/"), None);
+    }
+
+    #[test]
+    fn test_missing() {
+        let root = temp_root("missing");
+        std::fs::write(root.join("pxelinux.0"), b"boot").unwrap();
+        let files = [
+            "pxelinux.0",
+            "efi",
+            "ipxe.efi",
+            "http://boot.example.com/boot.ipxe",
+        ];
+        assert_eq!(missing(&root, files), ["efi", "ipxe.efi"]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let opts = |opts: &[(&str, &str)]| {
+            let opts = opts
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>();
+            negotiate(&opts, 1_000)
+        };
+        let negotiated = opts(&[("tsize", "0"), ("blksize", "1468"), ("multicast", "")]);
+        assert_eq!(negotiated.blksize, 1468);
+        assert_eq!(
+            negotiated.acked,
+            [
+                ("tsize".to_owned(), "1000".to_owned()),
+                ("blksize".to_owned(), "1468".to_owned())
+            ]
+        );
+        assert_eq!(opts(&[("blksize", "100000")]).blksize, MAX_BLKSIZE);
+        // out of range values are ignored
+        let negotiated = opts(&[("blksize", "4"), ("timeout", "0")]);
+        assert_eq!(negotiated.blksize, DEFAULT_BLKSIZE);
+        assert_eq!(negotiated.timeout, DEFAULT_TIMEOUT);
+        assert!(negotiated.acked.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_transfer() {
+        let root = temp_root("transfer");
+        let contents = (0..1_300).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(root.join("efi/boot.efi"), &contents).unwrap();
+        let srv = Server::bind("127.0.0.1:0".parse().unwrap(), root.clone())
+            .await
+            .unwrap();
+        let addr = srv.local_addr().unwrap();
+        tokio::spawn(srv.run());
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client
+            .send_to(b"\x00\x01/efi/boot.efi\x00octet\x00", addr)
+            .await
+            .unwrap();
+        let mut received = Vec::<u8>::new();
+        let mut buf = vec![0; 1_024];
+        loop {
+            let (len, from) = client.recv_from(&mut buf).await.unwrap();
+            assert_eq!(&buf[..2], [0, 3]);
+            received.extend(&buf[4..len]);
+            client.send_to(&[0, 4, buf[2], buf[3]], from).await.unwrap();
+            if len - 4 < DEFAULT_BLKSIZE {
+                break;
+            }
+        }
+        assert_eq!(received, contents);
+
+        client
+            .send_to(b"\x00\x01nope.efi\x00octet\x00", addr)
+            .await
+            .unwrap();
+        let len = client.recv(&mut buf).await.unwrap();
+        assert_eq!(
+            packet::parse_reply(&buf[..len]),
+            Reply::Error(packet::FILE_NOT_FOUND, "file not found".to_owned())
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_max_transfers() {
+        let root = temp_root("max-transfers");
+        std::fs::write(root.join("boot.efi"), vec![0; 2_000]).unwrap();
+        let srv = Server::bind("127.0.0.1:0".parse().unwrap(), root.clone())
+            .await
+            .unwrap()
+            .max_transfers(1);
+        let addr = srv.local_addr().unwrap();
+        tokio::spawn(srv.run());
+
+        let request = b"\x00\x01boot.efi\x00octet\x00";
+        let mut buf = vec![0; 1_024];
+        // the first transfer is started & left waiting for an ack
+        let first = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        first.send_to(request, addr).await.unwrap();
+        let len = first.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf[..4], [0, 3, 0, 1]);
+        assert_eq!(len, 4 + DEFAULT_BLKSIZE);
+
+        let second = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        second.send_to(request, addr).await.unwrap();
+        let len = second.recv(&mut buf).await.unwrap();
+        assert_eq!(
+            packet::parse_reply(&buf[..len]),
+            Reply::Error(packet::NOT_DEFINED, "server busy".to_owned())
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
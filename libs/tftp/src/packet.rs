@@ -0,0 +1,141 @@
+//! TFTP packets, RFC 1350 with the option extension of RFC 2347
+use std::str;
+
+const RRQ: u16 = 1;
+const WRQ: u16 = 2;
+const DATA: u16 = 3;
+const ACK: u16 = 4;
+const ERROR: u16 = 5;
+const OACK: u16 = 6;
+
+/// error codes sent to clients
+pub(crate) const NOT_DEFINED: u16 = 0;
+pub(crate) const FILE_NOT_FOUND: u16 = 1;
+pub(crate) const ACCESS_VIOLATION: u16 = 2;
+
+/// a request sent to the server port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Request {
+    Read {
+        file: String,
+        /// lowercased, `octet` or `netascii`
+        mode: String,
+        /// lowercased option names & their values, in the order sent
+        opts: Vec<(String, String)>,
+    },
+    Write,
+}
+
+/// a packet sent by the client during a transfer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Reply {
+    Ack(u16),
+    /// the client gave up on the transfer
+    Error(u16, String),
+    Other,
+}
+
+pub(crate) fn parse_request(buf: &[u8]) -> Option<Request> {
+    match opcode(buf)? {
+        WRQ => Some(Request::Write),
+        RRQ => {
+            // every field is NUL terminated
+            let fields = buf[2..].strip_suffix(&[0])?;
+            let mut fields = fields
+                .split(|b| *b == 0)
+                .map(|field| str::from_utf8(field).ok());
+            let file = fields.next()??.to_owned();
+            let mode = fields.next()??.to_ascii_lowercase();
+            let mut opts = Vec::new();
+            while let Some(name) = fields.next() {
+                let value = fields.next()??;
+                opts.push((name?.to_ascii_lowercase(), value.to_owned()));
+            }
+            Some(Request::Read { file, mode, opts })
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_reply(buf: &[u8]) -> Reply {
+    let block = || Some(u16::from_be_bytes([*buf.get(2)?, *buf.get(3)?]));
+    match (opcode(buf), block()) {
+        (Some(ACK), Some(block)) => Reply::Ack(block),
+        (Some(ERROR), Some(code)) => {
+            let msg = buf[4..].split(|b| *b == 0).next().unwrap_or_default();
+            Reply::Error(code, String::from_utf8_lossy(msg).into_owned())
+        }
+        _ => Reply::Other,
+    }
+}
+
+pub(crate) fn data(block: u16, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(4 + payload.len());
+    buf.extend(DATA.to_be_bytes());
+    buf.extend(block.to_be_bytes());
+    buf.extend(payload);
+    buf
+}
+
+pub(crate) fn oack(opts: &[(String, String)]) -> Vec<u8> {
+    let mut buf = OACK.to_be_bytes().to_vec();
+    for (name, value) in opts {
+        buf.extend(name.as_bytes());
+        buf.push(0);
+        buf.extend(value.as_bytes());
+        buf.push(0);
+    }
+    buf
+}
+
+pub(crate) fn error(code: u16, msg: &str) -> Vec<u8> {
+    let mut buf = ERROR.to_be_bytes().to_vec();
+    buf.extend(code.to_be_bytes());
+    buf.extend(msg.as_bytes());
+    buf.push(0);
+    buf
+}
+
+fn opcode(buf: &[u8]) -> Option<u16> {
+    Some(u16::from_be_bytes([*buf.first()?, *buf.get(1)?]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request() {
+        let rrq = b"\x00\x01pxelinux.0\x00OCTET\x00tsize\x000\x00BLKSIZE\x001468\x00";
+        assert_eq!(
+            parse_request(rrq),
+            Some(Request::Read {
+                file: "pxelinux.0".to_owned(),
+                mode: "octet".to_owned(),
+                opts: vec![
+                    ("tsize".to_owned(), "0".to_owned()),
+                    ("blksize".to_owned(), "1468".to_owned())
+                ],
+            })
+        );
+        assert_eq!(
+            parse_request(b"\x00\x02a\x00octet\x00"),
+            Some(Request::Write)
+        );
+        // not NUL terminated, option without a value
+        assert_eq!(parse_request(b"\x00\x01a\x00octet"), None);
+        assert_eq!(parse_request(b"\x00\x01a\x00octet\x00tsize\x00"), None);
+        assert_eq!(parse_request(b"\x00\x03\x00\x01"), None);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply(b"\x00\x04\x01\x02"), Reply::Ack(0x0102));
+        assert_eq!(
+            parse_reply(&error(8, "tsize only")),
+            Reply::Error(8, "tsize only".to_owned())
+        );
+        assert_eq!(parse_reply(&data(1, b"abc")), Reply::Other);
+        assert_eq!(parse_reply(b"\x00"), Reply::Other);
+    }
+}
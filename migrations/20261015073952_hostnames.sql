@@ -0,0 +1,21 @@
+-- hostnames claimed by clients, used to enforce hostname uniqueness
+CREATE TABLE IF NOT EXISTS hostnames(
+    hostname TEXT NOT NULL,
+    client_id BLOB NOT NULL,
+    ip INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    PRIMARY KEY(hostname)
+);
+CREATE INDEX idx_hostnames_client on hostnames (client_id);
+-- log of hostname conflicts and how they were resolved
+CREATE TABLE IF NOT EXISTS hostname_conflicts(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    hostname TEXT NOT NULL,
+    client_id BLOB NOT NULL,
+    existing_client_id BLOB NOT NULL,
+    policy TEXT NOT NULL,
+    assigned TEXT,
+    created_at INTEGER NOT NULL
+);
+-- a conflict is recorded once per hostname & client, repeats update it
+CREATE UNIQUE INDEX idx_hostname_conflicts_key on hostname_conflicts (hostname, client_id);
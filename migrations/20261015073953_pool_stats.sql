@@ -0,0 +1,13 @@
+-- periodic utilization samples per range, old samples are deleted after the
+-- retention period
+CREATE TABLE IF NOT EXISTS pool_stats(
+    network INTEGER NOT NULL,
+    range_start INTEGER NOT NULL,
+    range_end INTEGER NOT NULL,
+    total INTEGER NOT NULL,
+    leased INTEGER NOT NULL,
+    reserved INTEGER NOT NULL,
+    probated INTEGER NOT NULL,
+    sampled_at INTEGER NOT NULL
+);
+CREATE INDEX idx_pool_stats_sampled on pool_stats (sampled_at);
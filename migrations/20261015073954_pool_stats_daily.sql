@@ -0,0 +1,17 @@
+-- daily rollups of pool_stats, samples past their retention are compacted into
+-- one row per range per day
+CREATE TABLE IF NOT EXISTS pool_stats_daily(
+    network INTEGER NOT NULL,
+    range_start INTEGER NOT NULL,
+    range_end INTEGER NOT NULL,
+    day INTEGER NOT NULL,
+    samples INTEGER NOT NULL,
+    total INTEGER NOT NULL,
+    leased_avg REAL NOT NULL,
+    leased_max INTEGER NOT NULL,
+    used_avg REAL NOT NULL,
+    used_max INTEGER NOT NULL,
+    PRIMARY KEY(network, range_start, range_end, day)
+);
+CREATE INDEX idx_pool_stats_daily_day on pool_stats_daily (day);
+CREATE INDEX idx_hostname_conflicts_created on hostname_conflicts (created_at);
@@ -0,0 +1,12 @@
+-- FORCERENEW nonces (RFC 6704) sent to clients in their last ACK, one per
+-- leased address
+CREATE TABLE IF NOT EXISTS forcerenew_nonces(
+    ip INTEGER NOT NULL,
+    client_id BLOB NOT NULL,
+    chaddr BLOB NOT NULL,
+    network INTEGER NOT NULL,
+    server_id INTEGER NOT NULL,
+    classes TEXT NOT NULL,
+    nonce BLOB NOT NULL,
+    PRIMARY KEY(ip)
+);
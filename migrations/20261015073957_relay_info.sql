@@ -0,0 +1,8 @@
+-- relay agent information (option 82) a lease was last made with, one per
+-- leased address
+CREATE TABLE IF NOT EXISTS relay_info(
+    ip INTEGER NOT NULL,
+    client_id BLOB NOT NULL,
+    data BLOB NOT NULL,
+    PRIMARY KEY(ip)
+);
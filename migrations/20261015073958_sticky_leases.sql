@@ -0,0 +1,11 @@
+-- renewals of an address by the same client. A lease promoted to a sticky lease
+-- holds the address for its client until expires_at, which is NULL until then
+CREATE TABLE IF NOT EXISTS sticky_leases(
+    ip INTEGER NOT NULL,
+    client_id BLOB NOT NULL,
+    network INTEGER NOT NULL,
+    renewals INTEGER NOT NULL DEFAULT 0,
+    expires_at INTEGER,
+    PRIMARY KEY(ip)
+);
+CREATE INDEX idx_sticky_client on sticky_leases (client_id, network);
@@ -0,0 +1,7 @@
+-- values dora keeps across restarts, ex. how far expired leases have been
+-- published
+CREATE TABLE IF NOT EXISTS server_state(
+    key TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    PRIMARY KEY(key)
+);
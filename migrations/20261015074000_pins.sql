@@ -0,0 +1,3 @@
+-- sticky leases created through the api for a fixed time. A pin isn't
+-- extended by renewals & is deleted once it expires
+ALTER TABLE sticky_leases ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+-- cumulative message counts per network, saved periodically so they survive
+-- restarts
+CREATE TABLE IF NOT EXISTS network_counts(
+    network INTEGER NOT NULL,
+    msg_type TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY(network, msg_type)
+);
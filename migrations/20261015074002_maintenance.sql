@@ -0,0 +1,11 @@
+-- the last lease database maintenance run, one row
+CREATE TABLE IF NOT EXISTS maintenance(
+    id INTEGER NOT NULL CHECK (id = 0),
+    started_at INTEGER NOT NULL,
+    elapsed_ms INTEGER NOT NULL,
+    reclaimed_pages INTEGER NOT NULL,
+    page_count INTEGER NOT NULL,
+    free_pages INTEGER NOT NULL,
+    full_vacuum BOOLEAN NOT NULL,
+    PRIMARY KEY(id)
+);
@@ -0,0 +1,11 @@
+-- DHCP fingerprint of the client a lease was last made for & the device it
+-- was identified as, one per leased address
+CREATE TABLE IF NOT EXISTS fingerprints(
+    ip INTEGER NOT NULL,
+    client_id BLOB NOT NULL,
+    fingerprint TEXT NOT NULL,
+    vendor_class TEXT,
+    category TEXT,
+    device TEXT,
+    PRIMARY KEY(ip)
+);
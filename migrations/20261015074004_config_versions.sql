@@ -0,0 +1,7 @@
+-- configs pushed through the api, every push is a new version
+CREATE TABLE IF NOT EXISTS config_versions(
+    version INTEGER PRIMARY KEY AUTOINCREMENT,
+    config TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    note TEXT
+);
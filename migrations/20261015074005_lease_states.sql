@@ -0,0 +1,23 @@
+-- the state of each entry, see `ip_manager::LeaseState`. Offers & leases past
+-- their expiry are expired, probations past their end reclaimed. leased &
+-- probation are kept in step with the last offer, lease or probation
+ALTER TABLE leases ADD COLUMN state TEXT NOT NULL DEFAULT 'offered';
+UPDATE leases SET state = CASE
+    WHEN expires_at <= CAST(strftime('%s', 'now') AS INTEGER) AND probation = 1 THEN 'reclaimed'
+    WHEN expires_at <= CAST(strftime('%s', 'now') AS INTEGER) THEN 'expired'
+    WHEN probation = 1 THEN 'declined'
+    WHEN leased = 1 THEN 'active'
+    ELSE 'offered'
+END;
+CREATE INDEX idx_state_expires on leases (state, expires_at);
+-- every change of state of an entry, from_state is NULL for a new entry
+CREATE TABLE IF NOT EXISTS lease_transitions(
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    ip INTEGER NOT NULL,
+    client_id BLOB,
+    from_state TEXT,
+    to_state TEXT NOT NULL,
+    at INTEGER NOT NULL
+);
+CREATE INDEX idx_transitions_ip on lease_transitions (ip, id);
+CREATE INDEX idx_transitions_at on lease_transitions (at);
@@ -0,0 +1,7 @@
+-- key/value notes attached to a client through the API, kept across leases
+CREATE TABLE IF NOT EXISTS client_notes(
+    client_id BLOB NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY(client_id, key)
+);
@@ -12,24 +12,30 @@
 use std::{
     fmt,
     net::{IpAddr, Ipv4Addr},
-    time::{Duration, SystemTime},
+    time::SystemTime,
 };
 
 use dora_core::{
     anyhow::anyhow,
     chrono::{DateTime, SecondsFormat, Utc},
-    dhcproto::v4::{DhcpOption, Message, MessageType, OptionCode},
+    dhcproto::{
+        v4::{DhcpOption, Message, MessageType, OptionCode},
+        Encodable,
+    },
     prelude::*,
+    server::client_state::ClientState,
+    tracing::warn,
 };
 use message_type::MatchedClasses;
 use register_derive::Register;
 use static_addr::StaticAddr;
 
 use config::{
-    v4::{NetRange, Network},
+    v4::{HintPolicy, NetRange, Network},
     DhcpConfig,
 };
-use ip_manager::{IpError, IpManager, Storage};
+use ip_manager::{ForceRenewNonce, IpError, IpManager, LeaseFingerprint, Storage};
+use moka::future::{Cache, CacheBuilder};
 
 #[derive(Register)]
 #[register(msg(Message))]
@@ -40,6 +46,19 @@ where
 {
     cfg: Arc<DhcpConfig>,
     ip_mgr: IpManager<S>,
+    /// OFFERs by client id & xid, `None` if `offer_dedup_window` is 0
+    offers: Option<Cache<(Vec<u8>, u32), SentOffer>>,
+}
+
+/// max number of OFFERs kept for retransmitted DISCOVERs
+const OFFER_CACHE_SIZE: u64 = 10_000;
+
+/// an OFFER sent to a client, re-sent if it retransmits its DISCOVER
+#[derive(Debug, Clone)]
+struct SentOffer {
+    resp: Message,
+    /// end of the offer hold
+    expires_at: SystemTime,
 }
 
 impl<S> fmt::Debug for Leases<S>
@@ -56,7 +75,17 @@ where
     S: Storage,
 {
     pub fn new(cfg: Arc<DhcpConfig>, ip_mgr: IpManager<S>) -> Self {
-        Self { cfg, ip_mgr }
+        let window = cfg.v4().offer_dedup_window();
+        let offers = (!window.is_zero()).then(|| {
+            CacheBuilder::new(OFFER_CACHE_SIZE)
+                .time_to_live(window)
+                .build()
+        });
+        Self {
+            cfg,
+            ip_mgr,
+            offers,
+        }
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -70,7 +99,10 @@ where
         classes: Option<&[String]>,
         ctx: &mut MsgContext<Message>,
     ) -> Result<()> {
-        let (lease, t1, t2) = range.lease().determine_lease(ctx.requested_lease_time());
+        let now = DateTime::<Utc>::from(self.ip_mgr.now());
+        let (lease, t1, t2) = range
+            .lease_at(now)
+            .determine_lease(ctx.requested_lease_time());
         debug!(
             ?ip,
             ?client_id,
@@ -79,11 +111,17 @@ where
             subnet = ?network.subnet(),
             "reserved requested ip"
         );
-        ctx.decoded_resp_msg_mut()
-            .context("response message must be set before leases is run")?
-            .set_yiaddr(ip);
+        let boot = self
+            .cfg
+            .v4()
+            .boot(range.boot(), classes, ctx.decoded_msg().opts());
+        let resp = ctx
+            .decoded_resp_msg_mut()
+            .context("response message must be set before leases is run")?;
+        resp.set_yiaddr(ip);
+        boot.apply(resp);
         ctx.populate_opts_lease(
-            &self.cfg.v4().collect_opts(range.opts(), classes),
+            &self.cfg.v4().collect_opts(range.opts_at(now), classes),
             lease,
             t1,
             t2,
@@ -91,6 +129,272 @@ where
         ctx.set_local(ExpiresAt(expires_at));
         Ok(())
     }
+
+    /// register the client's hostname, if hostname tracking is enabled for `network`.
+    /// If a different hostname is assigned because of a conflict, it is returned
+    /// to the client in option 12. Clients without a hostname are registered with
+    /// one from the network's template, if it has one
+    async fn claim_hostname(
+        &self,
+        ctx: &mut MsgContext<Message>,
+        network: &Network,
+        ip: Ipv4Addr,
+        client_id: &[u8],
+        expires_at: SystemTime,
+    ) {
+        let hostname = match client_hostname(ctx.decoded_msg()) {
+            Some(hostname) => hostname,
+            None => {
+                if let Some(template) = network.hostname_template() {
+                    let hostname = template.render(ip, ctx.decoded_msg().chaddr());
+                    self.claim_generated_hostname(&hostname, ip, client_id, expires_at)
+                        .await;
+                }
+                return;
+            }
+        };
+        let policy = match network.hostname_conflict() {
+            Some(policy) => policy,
+            None => return,
+        };
+        match self
+            .ip_mgr
+            .claim_hostname(&hostname, ip.into(), client_id, expires_at, policy)
+            .await
+        {
+            Ok(Some(assigned)) if assigned != hostname => {
+                debug!(?hostname, ?assigned, "assigned different hostname");
+                if let Some(resp) = ctx.decoded_resp_msg_mut() {
+                    resp.opts_mut().insert(DhcpOption::Hostname(assigned));
+                }
+            }
+            Ok(_) => {}
+            Err(err) => {
+                error!(?err, ?hostname, "failed to claim hostname");
+            }
+        }
+    }
+
+    /// register a hostname generated for the client
+    async fn claim_generated_hostname(
+        &self,
+        hostname: &str,
+        ip: Ipv4Addr,
+        client_id: &[u8],
+        expires_at: SystemTime,
+    ) {
+        match self
+            .ip_mgr
+            .claim_generated_hostname(hostname, ip.into(), client_id, expires_at)
+            .await
+        {
+            Ok(Some(assigned)) => debug!(?assigned, "registered generated hostname"),
+            Ok(None) => warn!(?hostname, "every numbered generated hostname is taken"),
+            Err(err) => error!(?err, ?hostname, "failed to claim generated hostname"),
+        }
+    }
+}
+
+impl<S> Leases<S>
+where
+    S: Storage,
+{
+    /// send a FORCERENEW nonce (RFC 6704) in the ACK if the network has it enabled
+    /// & the client supports it. The nonce is stored with the lease so the
+    /// external api can authenticate a FORCERENEW to the client later
+    async fn send_forcerenew_nonce(
+        &self,
+        ctx: &mut MsgContext<Message>,
+        network: &Network,
+        ip: Ipv4Addr,
+        client_id: &[u8],
+        classes: Option<&[String]>,
+    ) {
+        if !network.forcerenew_nonce() || !forcerenew::capable(ctx.decoded_msg()) {
+            return;
+        }
+        let chaddr = ctx.decoded_msg().chaddr().to_vec();
+        let resp = match ctx.decoded_resp_msg_mut() {
+            Some(resp) => resp,
+            None => return,
+        };
+        let server_id = match resp.opts().get(OptionCode::ServerIdentifier) {
+            Some(DhcpOption::ServerIdentifier(id)) => *id,
+            _ => {
+                debug!("no server identifier in response, not sending forcerenew nonce");
+                return;
+            }
+        };
+        let nonce = forcerenew::new_nonce();
+        let entry = ForceRenewNonce {
+            ip: ip.into(),
+            id: client_id.to_vec(),
+            chaddr,
+            network: network.subnet().into(),
+            server_id,
+            classes: classes.map(|c| c.to_vec()).unwrap_or_default(),
+            nonce: nonce.to_vec(),
+        };
+        // without a stored nonce the client couldn't be sent a FORCERENEW
+        match self.ip_mgr.set_forcerenew_nonce(&entry).await {
+            Ok(()) => {
+                resp.opts_mut().insert(forcerenew::nonce_option(&nonce));
+            }
+            Err(err) => {
+                error!(?err, "failed to store forcerenew nonce");
+            }
+        }
+    }
+}
+
+impl<S> Leases<S>
+where
+    S: Storage,
+{
+    /// store the relay agent information (option 82) the request was relayed
+    /// with alongside the lease, so all of its sub-options show up in lease records
+    async fn record_relay_info(&self, ctx: &MsgContext<Message>, ip: Ipv4Addr, client_id: &[u8]) {
+        let info = ctx
+            .decoded_msg()
+            .opts()
+            .get(OptionCode::RelayAgentInformation)
+            .and_then(|opt| opt.to_vec().ok())
+            // [code][len][sub-options]
+            .map(|buf| buf.get(2..).unwrap_or_default().to_vec());
+        if let Err(err) = self
+            .ip_mgr
+            .set_relay_info(ip.into(), client_id, info.as_deref())
+            .await
+        {
+            error!(?err, "failed to store relay agent information");
+        }
+    }
+
+    /// store the client's DHCP fingerprint & the device it was identified as
+    /// alongside the lease
+    async fn record_fingerprint(&self, ctx: &MsgContext<Message>, ip: Ipv4Addr, client_id: &[u8]) {
+        let fingerprint = self.cfg.v4().fingerprint(ctx.decoded_msg());
+        let fingerprint = fingerprint.map(|(fingerprint, device)| LeaseFingerprint {
+            fingerprint: fingerprint.prl,
+            vendor_class: fingerprint.vendor_class,
+            category: device.map(|d| d.category.clone()),
+            device: device.map(|d| d.name.clone()),
+        });
+        if let Some(fp) = &fingerprint {
+            debug!(
+                fingerprint = %fp.fingerprint,
+                category = ?fp.category,
+                device = ?fp.device,
+                "client fingerprint"
+            );
+        }
+        if let Err(err) = self
+            .ip_mgr
+            .set_fingerprint(ip.into(), client_id, fingerprint.as_ref())
+            .await
+        {
+            error!(?err, "failed to store client fingerprint");
+        }
+    }
+}
+
+impl<S> Leases<S>
+where
+    S: Storage,
+{
+    /// the address held for the client as a sticky lease on `network`
+    async fn sticky_ip(&self, client_id: &[u8], network: &Network) -> Option<Ipv4Addr> {
+        match self.ip_mgr.sticky(client_id, network.subnet().into()).await {
+            Ok(Some(sticky)) => match sticky.ip {
+                IpAddr::V4(ip) if network.contains(ip) => Some(ip),
+                _ => None,
+            },
+            Ok(None) => None,
+            Err(err) => {
+                error!(?err, "failed to look up sticky lease");
+                None
+            }
+        }
+    }
+
+    /// count the lease towards making it sticky, if the network has sticky leases
+    async fn renew_sticky(&self, network: &Network, ip: Ipv4Addr, client_id: &[u8]) {
+        match self
+            .ip_mgr
+            .renew_sticky(ip.into(), client_id, network)
+            .await
+        {
+            Ok(Some(expires_at)) => {
+                debug!(
+                    ?ip,
+                    expires_at = %DateTime::<Utc>::from(expires_at).to_rfc3339_opts(SecondsFormat::Secs, true),
+                    "sticky lease held"
+                );
+            }
+            Ok(None) => {}
+            Err(err) => {
+                error!(?err, "failed to renew sticky lease");
+            }
+        }
+    }
+}
+
+/// the range a sticky lease gets its lease time & options from. If the ranges
+/// changed so none contain `ip`, the first range the client can use
+fn sticky_range<'a>(
+    network: &'a Network,
+    ip: Ipv4Addr,
+    classes: Option<&'a [String]>,
+) -> Option<&'a NetRange> {
+    network
+        .range(ip, classes)
+        .or_else(|| network.ranges_with_class(classes).next())
+}
+
+/// turn the response into a NAK, with the network's message if configured
+fn nak(ctx: &mut MsgContext<Message>, network: &Network) -> Result<Action> {
+    ctx.update_resp_msg(MessageType::Nak)
+        .context("failed to set msg type")?;
+    if let Some(msg) = &network.nak().message {
+        ctx.decoded_resp_msg_mut()
+            .context("response message must be set before leases is run")?
+            .opts_mut()
+            .insert(DhcpOption::Message(msg.clone()));
+    }
+    Ok(Action::Respond)
+}
+
+/// get the client's hostname from option 81 (client FQDN), falling back to
+/// option 12. Only the first label is used, lowercased
+fn client_hostname(msg: &Message) -> Option<String> {
+    let name = msg
+        .opts()
+        .get(OptionCode::from(81))
+        .and_then(|opt| opt.to_vec().ok())
+        .and_then(|buf| fqdn_label(buf.get(2..)?))
+        .or_else(|| match msg.opts().get(OptionCode::Hostname) {
+            Some(DhcpOption::Hostname(name)) => name.split('.').next().map(|s| s.to_owned()),
+            _ => None,
+        })?;
+    let name = name.trim().to_ascii_lowercase();
+    (!name.is_empty()).then_some(name)
+}
+
+/// parse the first label from the client FQDN option data
+/// <https://www.rfc-editor.org/rfc/rfc4702#section-2>
+fn fqdn_label(data: &[u8]) -> Option<String> {
+    let (flags, name) = (data.first()?, data.get(3..)?);
+    // E bit: canonical wire format
+    if flags & 0x04 != 0 {
+        let len = *name.first()? as usize;
+        String::from_utf8(name.get(1..1 + len)?.to_vec()).ok()
+    } else {
+        std::str::from_utf8(name)
+            .ok()?
+            .split('.')
+            .next()
+            .map(|s| s.to_owned())
+    }
 }
 
 #[async_trait]
@@ -120,9 +424,22 @@ where
             }
             // giaddr has matched one of our configured subnets
             (MessageType::Discover, Some(net)) => {
-                self.discover(ctx, &client_id, net, classes).await
+                if self.resend_offer(ctx, &client_id) {
+                    return Ok(Action::Continue);
+                }
+                let action = self.discover(ctx, &client_id, net, classes).await?;
+                if action == Action::Continue {
+                    self.cache_offer(ctx, &client_id).await;
+                }
+                Ok(action)
+            }
+            (MessageType::Request, Some(net)) => {
+                if let Some(offers) = &self.offers {
+                    // the OFFER was answered, a DISCOVER with its xid starts over
+                    offers.invalidate(&(client_id.clone(), req.xid())).await;
+                }
+                self.request(ctx, &client_id, net, classes).await
             }
-            (MessageType::Request, Some(net)) => self.request(ctx, &client_id, net, classes).await,
             (MessageType::Release, _) => self.release(ctx, &client_id).await,
             (MessageType::Decline, Some(net)) => self.decline(ctx, &client_id, net).await,
             _ => {
@@ -138,6 +455,51 @@ impl<S> Leases<S>
 where
     S: Storage,
 {
+    /// answer a retransmitted DISCOVER with the OFFER already sent for its xid,
+    /// without allocating or ping checking again. False if there is none or its
+    /// hold has run out
+    fn resend_offer(&self, ctx: &mut MsgContext<Message>, client_id: &[u8]) -> bool {
+        let Some(offers) = &self.offers else {
+            return false;
+        };
+        let req = ctx.decoded_msg();
+        let sent = match offers.get(&(client_id.to_vec(), req.xid())) {
+            Some(sent) if sent.expires_at > self.ip_mgr.now() => sent,
+            _ => return false,
+        };
+        let mut resp = sent.resp;
+        // clients may ask for a broadcast reply on retransmission
+        resp.set_flags(req.flags());
+        debug!(ip = ?resp.yiaddr(), "re-sending OFFER for retransmitted DISCOVER");
+        ctx.set_decoded_resp_msg(resp);
+        ctx.set_local(ExpiresAt(sent.expires_at));
+        dora_core::metrics::OFFER_RESENT.inc();
+        true
+    }
+
+    /// keep the OFFER made for the DISCOVER in `ctx`, for retransmissions
+    async fn cache_offer(&self, ctx: &MsgContext<Message>, client_id: &[u8]) {
+        let Some(offers) = &self.offers else {
+            return;
+        };
+        let (Some(resp), Some(ExpiresAt(expires_at))) =
+            (ctx.decoded_resp_msg(), ctx.get_local::<ExpiresAt>())
+        else {
+            return;
+        };
+        if resp.yiaddr().is_unspecified() {
+            return;
+        }
+        let sent = SentOffer {
+            resp: resp.clone(),
+            expires_at: *expires_at,
+        };
+        offers
+            .insert((client_id.to_vec(), ctx.decoded_msg().xid()), sent)
+            .await;
+    }
+
+    #[instrument(name = "allocate", level = "debug", skip_all)]
     async fn discover(
         &self,
         ctx: &mut MsgContext<Message>,
@@ -145,53 +507,83 @@ where
         network: &Network,
         classes: Option<Vec<String>>,
     ) -> Result<Action> {
-        let req = ctx.decoded_msg();
-        // give 60 seconds between discover & request, TODO: configurable?
-        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        if self.cfg.v4().release_on_move() {
+            // not fatal, the old leases will expire on their own
+            if let Err(err) = self
+                .ip_mgr
+                .release_elsewhere(client_id, network.subnet().into())
+                .await
+            {
+                error!(?err, "failed to release leases on other networks");
+            }
+        }
+        // hold the address until the client sends REQUEST
+        let expires_at = self.ip_mgr.now() + network.offer_hold();
         let classes = classes.as_deref();
-        // requested ip included in message, try to reserve
-        if let Some(DhcpOption::RequestedIpAddress(ip)) =
-            req.opts().get(OptionCode::RequestedIpAddress)
-        {
-            let ip = *ip;
-            // within our range. `range` makes sure IP is not in exclude list
-            if let Some(range) = network.range(ip, classes) {
-                match self
-                    .ip_mgr
-                    .try_ip(
-                        ip.into(),
-                        network.subnet().into(),
-                        client_id,
-                        expires_at,
-                        network,
-                    )
-                    .await
-                {
-                    Ok(_) => {
-                        self.set_response(network, ip, range, client_id, expires_at, classes, ctx)
-                            .await?;
-                        return Ok(Action::Continue);
-                    }
-                    // address in use from ping or cannot reserve this ip
-                    // try to assign an IP
-                    Err(err) => {
-                        debug!(
-                            ?err,
-                            "could not assign requested IP, attempting to get new one"
-                        );
-                    }
+        // the address requested in DISCOVER, if its range honors hints. `range` makes
+        // sure the IP is not in the exclude list
+        let hint = match ctx.decoded_msg().opts().get(OptionCode::RequestedIpAddress) {
+            Some(DhcpOption::RequestedIpAddress(ip)) => network
+                .range(*ip, classes)
+                .filter(|range| {
+                    !range.is_closed_at(self.ip_mgr.now().into())
+                        && range.hints() != HintPolicy::Never
+                })
+                .map(|range| (*ip, range)),
+            _ => None,
+        };
+        let sticky = match self.sticky_ip(client_id, network).await {
+            Some(ip) => sticky_range(network, ip, classes).map(|range| (ip, range)),
+            None => None,
+        };
+        // a sticky lease is offered before anything else, unless the hint's range says
+        // to always honor it
+        let candidates = match hint {
+            Some((_, range)) if range.hints() == HintPolicy::Always => [hint, sticky],
+            _ => [sticky, hint],
+        };
+        for (ip, range) in candidates.into_iter().flatten() {
+            match self
+                .ip_mgr
+                .try_ip(
+                    ip.into(),
+                    network.subnet().into(),
+                    client_id,
+                    expires_at,
+                    range.ping(),
+                )
+                .await
+            {
+                Ok(_) => {
+                    self.set_response(network, ip, range, client_id, expires_at, classes, ctx)
+                        .await?;
+                    return Ok(Action::Continue);
+                }
+                // address in use from ping or held by another client, try the next one
+                Err(err) => {
+                    debug!(?err, ?ip, "could not offer sticky or requested IP");
                 }
             }
         }
-        // no requested IP, so find the next available
-        for range in network.ranges_with_class(classes) {
+        // nothing requested or it can't be had, so find the next available
+        let now = DateTime::<Utc>::from(self.ip_mgr.now());
+        // closed ranges don't give out new addresses
+        for range in network
+            .ranges_with_class(classes)
+            .filter(|range| !range.is_closed_at(now))
+        {
             match self
                 .ip_mgr
                 .reserve_first(range, network, client_id, expires_at)
                 .await
             {
                 Ok(IpAddr::V4(ip)) => {
-                    debug!(?ip, ?client_id, "got IP for client-- sending offer");
+                    debug!(
+                        ?ip,
+                        ?client_id,
+                        vendor = ?self.ip_mgr.vendor(client_id),
+                        "got IP for client-- sending offer"
+                    );
                     self.set_response(network, ip, range, client_id, expires_at, classes, ctx)
                         .await?;
                     return Ok(Action::Continue);
@@ -209,6 +601,7 @@ where
         Ok(Action::NoResponse)
     }
 
+    #[instrument(name = "allocate", level = "debug", skip_all)]
     async fn request(
         &self,
         ctx: &mut MsgContext<Message>,
@@ -216,14 +609,18 @@ where
         network: &Network,
         classes: Option<Vec<String>>,
     ) -> Result<Action> {
-        // requested ip comes from opts or ciaddr
-        let ip = match ctx.requested_ip() {
+        // requested ip comes from opts or ciaddr, depending on the client state
+        let state = ctx.get_local::<ClientState>().copied();
+        debug!(?state);
+        let requested = match state {
+            Some(state) => state.requested_ip(),
+            None => ctx.requested_ip(),
+        };
+        let ip = match requested {
             Some(ip) => ip,
-            None if network.authoritative() => {
+            None if network.authoritative() && network.nak().unknown_client => {
                 debug!("no requested IP and we are authoritative, so NAK");
-                ctx.update_resp_msg(MessageType::Nak)
-                    .context("failed to set msg type")?;
-                return Ok(Action::Respond);
+                return nak(ctx, network);
             }
             None => {
                 debug!("couldn't get requested IP, No response");
@@ -233,12 +630,19 @@ where
 
         let classes = classes.as_deref();
         // within our range
-        let range = network.range(ip, classes);
+        let mut range = network.range(ip, classes);
+        if range.is_none() && self.sticky_ip(client_id, network).await == Some(ip) {
+            // sticky leases are kept after the ranges change
+            range = sticky_range(network, ip, classes);
+        }
         debug!(?ip, range = ?range.map(|r| r.addrs()), "is IP in range?");
         if let Some(range) = range {
             // calculate the lease time
-            let (lease, t1, t2) = range.lease().determine_lease(ctx.requested_lease_time());
-            let expires_at = SystemTime::now() + lease;
+            let now = self.ip_mgr.now();
+            let (lease, t1, t2) = range
+                .lease_at(now.into())
+                .determine_lease(ctx.requested_lease_time());
+            let expires_at = now + lease;
             match self
                 .ip_mgr
                 .try_lease(ip.into(), client_id, expires_at, network)
@@ -248,27 +652,52 @@ where
                     ctx.decoded_resp_msg_mut()
                         .context("response message must be set before leases is run")?
                         .set_yiaddr(ip);
+                    self.claim_hostname(ctx, network, ip, client_id, expires_at)
+                        .await;
                     debug!(
                         ?ip,
                         ?client_id,
+                        vendor = ?self.ip_mgr.vendor(client_id),
                         expires_at = %DateTime::<Utc>::from(expires_at).to_rfc3339_opts(SecondsFormat::Secs, true),
                         "leased requested ip"
                     );
                     ctx.populate_opts_lease(
-                        &self.cfg.v4().collect_opts(range.opts(), classes),
+                        &self
+                            .cfg
+                            .v4()
+                            .collect_opts(range.opts_at(now.into()), classes),
                         lease,
                         t1,
                         t2,
                     );
+                    self.send_forcerenew_nonce(ctx, network, ip, client_id, classes)
+                        .await;
+                    self.record_relay_info(ctx, ip, client_id).await;
+                    self.record_fingerprint(ctx, ip, client_id).await;
+                    self.renew_sticky(network, ip, client_id).await;
                     ctx.set_local(ExpiresAt(expires_at));
                     return Ok(Action::Continue);
                 }
                 // ip not reserved or chaddr doesn't match
                 Err(err) if network.authoritative() => {
-                    debug!(?err, "can't give out lease");
-                    ctx.update_resp_msg(MessageType::Nak)
-                        .context("failed to set msg type")?;
-                    return Ok(Action::Respond);
+                    let expired = match self.ip_mgr.lease_expired(ip.into(), client_id).await {
+                        Ok(expired) => expired,
+                        Err(err) => {
+                            error!(?err, "failed to look up lease");
+                            false
+                        }
+                    };
+                    let policy = network.nak();
+                    if (expired && policy.expired_lease) || (!expired && policy.unknown_client) {
+                        debug!(?err, ?expired, "can't give out lease");
+                        return nak(ctx, network);
+                    }
+                    debug!(
+                        ?err,
+                        ?expired,
+                        "can't give out lease & nak policy is silent"
+                    );
+                    ctx.decoded_resp_msg_mut().take();
                 }
                 Err(err) => {
                     debug!(?err, "can't give out lease & not authoritative");
@@ -276,6 +705,9 @@ where
                 }
             }
             Ok(Action::Continue)
+        } else if network.authoritative() && network.nak().wrong_network && !network.contains(ip) {
+            debug!(?ip, "requested IP is not on this network, so NAK");
+            nak(ctx, network)
         } else {
             Ok(Action::Continue)
         }
@@ -305,7 +737,7 @@ where
         } else {
             Err(anyhow!("decline has no option 50 (requested IP)"))
         }?;
-        let expires_at = SystemTime::now() + network.probation_period();
+        let expires_at = self.ip_mgr.now() + network.probation_period();
         self.ip_mgr
             .probate_ip((*declined_ip).into(), client_id, expires_at)
             .await?;
@@ -10,17 +10,20 @@
 #![allow(clippy::cognitive_complexity)]
 
 use dora_core::{
+    chrono::Utc,
     dhcproto::{
         v4::{DhcpOption, Message, MessageType, Opcode, OptionCode},
         v6,
     },
+    pnet::util::MacAddr,
     prelude::*,
-    tracing::warn,
+    tracing::{debug_span, warn},
 };
 use register_derive::Register;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr};
 
-use config::DhcpConfig;
+use config::{v6::StatusPolicy, DhcpConfig};
+use dora_core::{garbage::Detector, server::relay::RelayInfo};
 
 #[derive(Debug, Register)]
 #[register(msg(Message))]
@@ -28,11 +31,14 @@ use config::DhcpConfig;
 #[register(plugin())]
 pub struct MsgType {
     cfg: Arc<DhcpConfig>,
+    /// `None` unless `garbage_clients` is set
+    garbage: Option<Detector>,
 }
 
 impl MsgType {
     pub fn new(cfg: Arc<DhcpConfig>) -> Result<Self> {
-        Ok(Self { cfg })
+        let garbage = cfg.v4().garbage_clients().map(Detector::new);
+        Ok(Self { cfg, garbage })
     }
 }
 
@@ -49,6 +55,18 @@ impl Plugin<Message> for MsgType {
             .get_interface(meta.ifindex)
             .context("interface message was received on does not exist?")?;
         ctx.set_interface(interface);
+        // clients directly attached to a secondary subnet of the interface are
+        // found by option 118 or the address they have/request
+        if ctx.decoded_msg().giaddr().is_unspecified() {
+            let addr = ctx.relay_subnet().ok().or_else(|| ctx.requested_ip());
+            if let Some(secondary) = addr
+                .and_then(|addr| self.cfg.v4().attached_interface(meta.ifindex, addr))
+                .filter(|int| *int != interface)
+            {
+                debug!(%secondary, "client is on a secondary subnet of the interface");
+                ctx.set_interface(secondary);
+            }
+        }
 
         let req = ctx.decoded_msg();
         let msg_type = req.opts().msg_type();
@@ -69,11 +87,70 @@ impl Plugin<Message> for MsgType {
             .context("cannot find server_id")?;
         // look up which network the message belongs to
         let network = self.cfg.v4().network(subnet);
+        // a relay can only act for networks it is allowed on
+        let giaddr = req.giaddr();
+        if !giaddr.is_unspecified() && matches!(network, Some(net) if !net.relay_allowed(giaddr)) {
+            debug!(?giaddr, "relay not in allowed_relays for network, dropping");
+            dora_core::metrics::RELAY_DROPPED.inc();
+            return Ok(Action::NoResponse);
+        }
+        if let Some(finding) = self.garbage.as_ref().and_then(|garbage| garbage.check(req)) {
+            warn!(
+                kind = %finding.kind,
+                chaddr = %finding.chaddr,
+                chaddrs = finding.chaddrs,
+                xid = req.xid(),
+                ?giaddr,
+                blocked = finding.blocked,
+                "garbage client"
+            );
+            if finding.blocked {
+                return Ok(Action::NoResponse);
+            }
+        }
+        // ACKs, NAKs & DECLINEs are counted per network
+        if let Some(net) = network {
+            ctx.set_local(dora_core::metrics::MatchedNetwork(net.subnet()));
+        }
+        let req = ctx.decoded_msg();
+        let siaddr = network
+            .and_then(|net| net.next_server())
+            .unwrap_or(server_id);
         let sname = network.and_then(|net| net.server_name());
-        let fname = network.and_then(|net| net.file_name());
+        // clients that chainloaded iPXE are sent its script, not the ROM's file again
+        let fname = match network {
+            Some(net) if config::v4::is_ipxe(req.opts()) => {
+                net.ipxe_file_name().or_else(|| net.file_name())
+            }
+            _ => network.and_then(|net| net.file_name()),
+        };
+        // used by the server when the reply is encoded
+        let encoding = network.map(|net| net.reply_encoding());
+        // used by the response builder to pick which configured options are sent
+        let reply_opts = network.map(|net| net.reply_options().clone());
+        // used by the server to strip or rewrite options before the reply is encoded
+        let reply_rules = network.map(|net| net.reply_rules().clone());
         // message that will be returned
-        let mut resp = util::new_msg(req, server_id, sname, fname);
+        let mut resp = util::new_msg(req, siaddr, sname, fname);
+        // https://datatracker.ietf.org/doc/html/rfc6842#section-3
+        // copy client id, NAKs keep it when the response is cleared
+        if self.cfg.v4().echo_client_id() {
+            if let Some(id) = req.opts().get(OptionCode::ClientIdentifier) {
+                resp.opts_mut().insert(id.clone());
+            }
+        }
 
+        // https://www.rfc-editor.org/rfc/rfc2131#section-4.3.2
+        let state = ctx.client_state();
+        if let Some(state) = state {
+            state.inc_metric();
+            // the client declined our OFFER, nothing is allocated for it
+            if state.selected_other(server_id) {
+                debug!(?state, ?server_id, "client selected another server");
+                dora_core::metrics::REQUEST_OTHER_SERVER.inc();
+                return Ok(Action::NoResponse);
+            }
+        }
         // if there is a server identifier it must match ours
         if matches!(req.opts().get(OptionCode::ServerIdentifier), Some(DhcpOption::ServerIdentifier(id)) if *id != server_id && !id.is_unspecified())
         {
@@ -88,7 +165,7 @@ impl Plugin<Message> for MsgType {
         resp.opts_mut()
             .insert(DhcpOption::ServerIdentifier(server_id));
         // evaluate client classes
-        let matched = util::client_classes(self.cfg.v4(), req);
+        let matched = debug_span!("classify").in_scope(|| util::client_classes(self.cfg.v4(), req));
         let addr = {
             let ciaddr = ctx.decoded_msg().ciaddr();
             if !ciaddr.is_unspecified() {
@@ -119,11 +196,31 @@ impl Plugin<Message> for MsgType {
                 resp.opts_mut()
                     .insert(DhcpOption::MessageType(MessageType::Ack));
 
-                if let Some(range) = self.cfg.v4().range(addr, addr, matched.as_deref()) {
+                // manually addressed hosts are answered with their own options
+                let host = network.and_then(|net| {
+                    let [a, b, c, d, e, f] = <[u8; 6]>::try_from(req.chaddr()).ok()?;
+                    net.static_host(addr, MacAddr::new(a, b, c, d, e, f))
+                });
+                let opts = match host {
+                    Some(host) => Some(host.opts()),
+                    None => self
+                        .cfg
+                        .v4()
+                        .range(addr, addr, matched.as_deref())
+                        .map(|range| range.opts_at(Utc::now())),
+                };
+                if let Some(opts) = opts {
+                    if let Some(encoding) = encoding {
+                        ctx.set_local(encoding);
+                    }
+                    if let Some(reply_opts) = reply_opts {
+                        ctx.set_local(reply_opts);
+                    }
+                    if let Some(reply_rules) = reply_rules {
+                        ctx.set_local(reply_rules);
+                    }
                     ctx.set_decoded_resp_msg(resp);
-                    ctx.populate_opts(
-                        &self.cfg.v4().collect_opts(range.opts(), matched.as_deref()),
-                    );
+                    ctx.populate_opts(&self.cfg.v4().collect_opts(opts, matched.as_deref()));
                     return Ok(Action::Respond);
                 }
                 warn!(msg_type = ?MessageType::Inform, "couldn't match appropriate range with INFORM message");
@@ -142,6 +239,15 @@ impl Plugin<Message> for MsgType {
             }
             None if req.opcode() == Opcode::BootRequest && self.cfg.v4().bootp_enabled() => {
                 // No message type but BOOTREQUEST, this is a BOOTP message
+                if let Some(encoding) = encoding {
+                    ctx.set_local(encoding);
+                }
+                if let Some(reply_opts) = reply_opts {
+                    ctx.set_local(reply_opts);
+                }
+                if let Some(reply_rules) = reply_rules {
+                    ctx.set_local(reply_rules);
+                }
                 ctx.set_decoded_resp_msg(resp);
                 return Ok(Action::Continue);
             }
@@ -154,6 +260,18 @@ impl Plugin<Message> for MsgType {
         if let Some(classes) = matched {
             ctx.set_local(MatchedClasses(classes));
         }
+        if let Some(state) = state {
+            ctx.set_local(state);
+        }
+        if let Some(encoding) = encoding {
+            ctx.set_local(encoding);
+        }
+        if let Some(reply_opts) = reply_opts {
+            ctx.set_local(reply_opts);
+        }
+        if let Some(reply_rules) = reply_rules {
+            ctx.set_local(reply_rules);
+        }
         ctx.set_decoded_resp_msg(resp);
         Ok(Action::Continue)
     }
@@ -238,13 +356,23 @@ impl Plugin<v6::Message> for MsgType {
             req = %ctx.decoded_msg(),
         );
 
-        // let network = self.cfg.v6().get_network(meta.ifindex);
+        let link_addr = ctx
+            .get_local::<RelayInfo>()
+            .and_then(|relay| relay.innermost())
+            .map(|layer| layer.link_addr);
+        let network = self.cfg.v6().get_network_by_link(meta.ifindex, link_addr);
+        let options_only = matches!(network, Some(net) if net.options_only());
+        let default_policy = StatusPolicy::default();
+        let policy = network.map_or(&default_policy, |net| net.status());
+        // relays always unicast to us, only direct messages must be multicast
+        let unicast = ctx.get_local::<RelayInfo>().is_none()
+            && matches!(meta.dst_local_ip, Some(IpAddr::V6(ip)) if !ip.is_multicast());
 
         // create initial response with reply type
         let mut resp = v6::Message::new_with_id(Reply, req.xid());
 
         let server_id = self.cfg.v6().server_id();
-        // TODO RelayForw type
+        // RelayForw is unwrapped by the server, `req` is always the client msg
         // TODO: make sure we handle client ids as specified - https://www.rfc-editor.org/rfc/rfc8415#section-16.1
         let req_sid = req.opts().get(v6::OptionCode::ServerId);
         // if the request includes a server id, it must match our server id
@@ -255,15 +383,87 @@ impl Plugin<v6::Message> for MsgType {
         // add server id to response
         resp.opts_mut()
             .insert(v6::DhcpOption::ServerId(server_id.to_vec()));
+        if let Some(client_id) = req.opts().get(v6::OptionCode::ClientId) {
+            resp.opts_mut().insert(client_id.clone());
+        }
 
         match msg_type {
+            // stateless service only, https://www.rfc-editor.org/rfc/rfc8415#section-6.1
+            Solicit | Request | Confirm | Renew | Rebind | Release | Decline if options_only => {
+                debug!(?msg_type, "options_only network, ignoring");
+                return Ok(Action::NoResponse);
+            }
             // discard if it has these types but NO server id
             // https://www.rfc-editor.org/rfc/rfc8415#section-16.6
             Request | Renew | Decline | Release if req_sid.is_none() => {
                 return Ok(Action::NoResponse);
             }
+            // https://www.rfc-editor.org/rfc/rfc8415#section-18.4
+            Request | Renew | Decline | Release if unicast && policy.use_multicast => {
+                debug!(?msg_type, "received by unicast, replying UseMulticast");
+                resp.opts_mut()
+                    .insert(status::code(v6::Status::UseMulticast, policy));
+                ctx.set_decoded_resp_msg(resp);
+                return Ok(Action::Respond);
+            }
+            // https://www.rfc-editor.org/rfc/rfc8415#section-18.3.3
+            Confirm => {
+                let addrs = status::ia_addrs(req);
+                if addrs.is_empty() {
+                    return Ok(Action::NoResponse);
+                }
+                let on_link =
+                    matches!(network, Some(net) if addrs.iter().all(|ip| net.contains(ip)));
+                let code = if on_link {
+                    v6::Status::Success
+                } else if policy.not_on_link {
+                    v6::Status::NotOnLink
+                } else {
+                    return Ok(Action::NoResponse);
+                };
+                resp.opts_mut().insert(status::code(code, policy));
+                ctx.set_decoded_resp_msg(resp);
+                return Ok(Action::Respond);
+            }
+            // v6 allocation isn't implemented, there is nothing to offer or bind
+            // https://www.rfc-editor.org/rfc/rfc8415#section-18.3.1
+            Solicit | Request => {
+                if msg_type == Solicit {
+                    resp.set_msg_type(Advertise);
+                }
+                let not_on_link = |ia: &v6::IANA| {
+                    msg_type == Request
+                        && policy.not_on_link
+                        && status::addrs(&ia.opts)
+                            .any(|ip| !matches!(network, Some(net) if net.contains(&ip)))
+                };
+                let ias = status::iana(req, |ia| {
+                    if not_on_link(ia) {
+                        v6::Status::NotOnLink
+                    } else {
+                        v6::Status::NoAddrsAvail
+                    }
+                });
+                status::reply_ias(&mut resp, ias, v6::Status::NoAddrsAvail, policy);
+                ctx.set_decoded_resp_msg(resp);
+                return Ok(Action::Respond);
+            }
+            // https://www.rfc-editor.org/rfc/rfc8415#section-18.3.4
+            Renew | Rebind | Release | Decline => {
+                let ias = status::iana(req, |_| v6::Status::NoBinding);
+                if matches!(msg_type, Release | Decline) {
+                    resp.opts_mut()
+                        .insert(status::code(v6::Status::Success, policy));
+                }
+                status::reply_ias(&mut resp, ias, v6::Status::NoBinding, policy);
+                ctx.set_decoded_resp_msg(resp);
+                return Ok(Action::Respond);
+            }
             InformationRequest => {
-                if let Some(opts) = self.cfg.v6().get_opts(meta.ifindex) {
+                let opts = network
+                    .map(|net| net.opts())
+                    .or_else(|| self.cfg.v6().get_opts(meta.ifindex));
+                if let Some(opts) = opts {
                     ctx.set_decoded_resp_msg(resp);
                     ctx.populate_opts(opts);
                     return Ok(Action::Respond);
@@ -285,6 +485,99 @@ impl Plugin<v6::Message> for MsgType {
     }
 }
 
+/// RFC 8415 status codes, see [`StatusPolicy`]
+mod status {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    /// a status code option for `status`, with the network's message
+    pub(crate) fn code(status: v6::Status, policy: &StatusPolicy) -> v6::DhcpOption {
+        let messages = &policy.messages;
+        let (label, msg) = match status {
+            v6::Status::Success => ("success", None),
+            v6::Status::NoAddrsAvail => ("no_addrs_avail", messages.no_addrs_avail.as_deref()),
+            v6::Status::NoBinding => ("no_binding", messages.no_binding.as_deref()),
+            v6::Status::NotOnLink => ("not_on_link", messages.not_on_link.as_deref()),
+            v6::Status::UseMulticast => ("use_multicast", messages.use_multicast.as_deref()),
+            _ => ("other", None),
+        };
+        dora_core::metrics::V6_STATUS_COUNT_VEC
+            .with_label_values(&[label])
+            .inc();
+        let msg = msg.map(ToOwned::to_owned).unwrap_or_else(|| {
+            match status {
+                v6::Status::Success => "success",
+                v6::Status::NoAddrsAvail => "no addresses available",
+                v6::Status::NoBinding => "no binding for IA",
+                v6::Status::NotOnLink => "address not on link",
+                v6::Status::UseMulticast => "use multicast",
+                _ => "",
+            }
+            .to_owned()
+        });
+        v6::DhcpOption::StatusCode(v6::StatusCode { status, msg })
+    }
+
+    /// the addresses in IA_NA options of `opts`
+    pub(crate) fn addrs(opts: &v6::DhcpOptions) -> impl Iterator<Item = Ipv6Addr> + '_ {
+        opts.iter().filter_map(|opt| match opt {
+            v6::DhcpOption::IAAddr(addr) => Some(addr.addr),
+            _ => None,
+        })
+    }
+
+    /// all addresses in all of the IA_NAs of `msg`
+    pub(crate) fn ia_addrs(msg: &v6::Message) -> Vec<Ipv6Addr> {
+        msg.opts()
+            .iter()
+            .filter_map(|opt| match opt {
+                v6::DhcpOption::IANA(ia) => Some(addrs(&ia.opts).collect::<Vec<_>>()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// each IA_NA of `msg` & the status it will be returned with
+    pub(crate) fn iana(
+        msg: &v6::Message,
+        status: impl Fn(&v6::IANA) -> v6::Status,
+    ) -> Vec<(u32, v6::Status)> {
+        msg.opts()
+            .iter()
+            .filter_map(|opt| match opt {
+                v6::DhcpOption::IANA(ia) => Some((ia.id, status(ia))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// return each IA with its status & no addresses. With no IA_NAs in the
+    /// request `fallback` is sent as the top-level status instead
+    pub(crate) fn reply_ias(
+        resp: &mut v6::Message,
+        ias: Vec<(u32, v6::Status)>,
+        fallback: v6::Status,
+        policy: &StatusPolicy,
+    ) {
+        if ias.is_empty() {
+            resp.opts_mut().insert(code(fallback, policy));
+            return;
+        }
+        for (id, status) in ias {
+            let mut opts = v6::DhcpOptions::new();
+            opts.insert(code(status, policy));
+            resp.opts_mut().insert(v6::DhcpOption::IANA(v6::IANA {
+                id,
+                t1: 0,
+                t2: 0,
+                opts,
+            }));
+        }
+    }
+}
+
 /// a list of matching client classes for this message
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MatchedClasses(pub Vec<String>);
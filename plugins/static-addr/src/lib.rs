@@ -91,9 +91,15 @@ impl StaticAddr {
         let static_ip = res.ip();
         let (lease, t1, t2) = res.lease().determine_lease(ctx.requested_lease_time());
         debug!(?static_ip, ?chaddr, "use static requested ip");
-        ctx.decoded_resp_msg_mut()
-            .context("response message must be set before static is run")?
-            .set_yiaddr(static_ip);
+        let boot = self
+            .cfg
+            .v4()
+            .boot(res.boot(), classes, ctx.decoded_msg().opts());
+        let resp = ctx
+            .decoded_resp_msg_mut()
+            .context("response message must be set before static is run")?;
+        resp.set_yiaddr(static_ip);
+        boot.apply(resp);
         ctx.populate_opts_lease(
             &self.cfg.v4().collect_opts(res.opts(), classes),
             lease,
@@ -114,9 +120,15 @@ impl StaticAddr {
     ) -> Result<Action> {
         let static_ip = res.ip();
         debug!(?static_ip, ?chaddr, "BOOTREPLY using static ip");
-        ctx.decoded_resp_msg_mut()
-            .context("response message must be set before static is run")?
-            .set_yiaddr(static_ip);
+        let boot = self
+            .cfg
+            .v4()
+            .boot(res.boot(), classes, ctx.decoded_msg().opts());
+        let resp = ctx
+            .decoded_resp_msg_mut()
+            .context("response message must be set before static is run")?;
+        resp.set_yiaddr(static_ip);
+        boot.apply(resp);
         // populate opts with no lease time info
         ctx.populate_opts(&self.cfg.v4().collect_opts(res.opts(), classes));
         // remove options that aren't allowed in a BOOTP response
@@ -154,9 +166,15 @@ impl StaticAddr {
         }
 
         let (lease, t1, t2) = res.lease().determine_lease(ctx.requested_lease_time());
-        ctx.decoded_resp_msg_mut()
-            .context("response message must be set before static plugin is run")?
-            .set_yiaddr(ip);
+        let boot = self
+            .cfg
+            .v4()
+            .boot(res.boot(), classes, ctx.decoded_msg().opts());
+        let resp = ctx
+            .decoded_resp_msg_mut()
+            .context("response message must be set before static plugin is run")?;
+        resp.set_yiaddr(ip);
+        boot.apply(resp);
         ctx.populate_opts_lease(
             &self.cfg.v4().collect_opts(res.opts(), classes),
             lease,